@@ -0,0 +1,126 @@
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput};
+
+use openprod_core::{field_value::FieldValue, identity::ActorIdentity, vector_clock::VectorClock};
+use openprod_bench::{bundles_since, scales, Workspace, FACET};
+use openprod_storage::{SqliteStorage, Storage};
+
+/// Entities edited concurrently on both sides in the conflict benchmark, and
+/// inside the overlay in the overlay benchmark.
+const EDITED: usize = 1_000;
+
+fn append_bundle(c: &mut Criterion, workspace: &Workspace, ops: usize) {
+    let mut group = c.benchmark_group("append_bundle");
+    group.sample_size(10);
+    let bundles = bundles_since(&workspace.open_as_author().unwrap(), &VectorClock::new()).unwrap();
+    group.throughput(Throughput::Elements(ops as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(ops), &bundles, |b, bundles| {
+        b.iter_batched(
+            || {
+                let dir = tempfile::tempdir().unwrap();
+                let storage = SqliteStorage::open(dir.path().join("empty.db").to_str().unwrap()).unwrap();
+                (dir, storage)
+            },
+            |(_dir, mut storage)| {
+                for (bundle, operations) in bundles {
+                    storage.append_bundle(bundle, operations).unwrap();
+                }
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn ingest_with_conflicts(c: &mut Criterion, base: &Workspace, ops: usize) {
+    let mut group = c.benchmark_group("ingest_with_conflicts");
+    group.sample_size(10);
+    let edited = &base.entities[..EDITED.min(base.entities.len())];
+
+    // The author edits some fields; another device edits the same fields
+    // concurrently, so ingesting the author's bundles there conflicts
+    let remote = base.copy().unwrap();
+    let mut author = remote.open_as_author().unwrap();
+    let before = author.get_vector_clock().unwrap();
+    for &entity_id in edited {
+        author.set_field(entity_id, "label", FieldValue::Text("remote".into())).unwrap();
+    }
+    let incoming = bundles_since(&author, &before).unwrap();
+
+    let local = base.copy().unwrap();
+    let mut device = local.open(ActorIdentity::generate()).unwrap();
+    for &entity_id in edited {
+        device.set_field(entity_id, "label", FieldValue::Text("local".into())).unwrap();
+    }
+    drop(device);
+
+    group.throughput(Throughput::Elements(incoming.len() as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(ops), &incoming, |b, incoming| {
+        b.iter_batched(
+            || {
+                let workspace = local.copy().unwrap();
+                let engine = workspace.open(ActorIdentity::generate()).unwrap();
+                (workspace, engine)
+            },
+            |(_workspace, mut engine)| {
+                let conflicts = engine.ingest_bundles(incoming).unwrap().conflicts;
+                assert_eq!(conflicts.len(), edited.len());
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn rebuild_from_oplog(c: &mut Criterion, workspace: &Workspace, ops: usize) {
+    let mut group = c.benchmark_group("rebuild_from_oplog");
+    group.sample_size(10);
+    let workspace = workspace.copy().unwrap();
+    let mut engine = workspace.open_as_author().unwrap();
+    group.throughput(Throughput::Elements(ops as u64));
+    group.bench_function(BenchmarkId::from_parameter(ops), |b| b.iter(|| engine.rebuild_state().unwrap()));
+    group.finish();
+}
+
+fn query_by_facet(c: &mut Criterion, workspace: &Workspace, ops: usize) {
+    let mut group = c.benchmark_group("query_by_facet");
+    let engine = workspace.open_as_author().unwrap();
+    group.bench_function(BenchmarkId::from_parameter(ops), |b| {
+        b.iter(|| engine.get_entities_by_facet(FACET).unwrap())
+    });
+    group.finish();
+}
+
+fn overlay_read(c: &mut Criterion, workspace: &Workspace, ops: usize) {
+    let mut group = c.benchmark_group("overlay_read");
+    let workspace = workspace.copy().unwrap();
+    let mut engine = workspace.open_as_author().unwrap();
+    let entity_id = workspace.entities[0];
+
+    group.bench_function(BenchmarkId::new("canonical", ops), |b| {
+        b.iter(|| engine.get_fields(entity_id).unwrap())
+    });
+
+    engine.create_overlay("bench").unwrap();
+    for &edited in &workspace.entities[..EDITED.min(workspace.entities.len())] {
+        engine.set_field(edited, "label", FieldValue::Text("draft".into())).unwrap();
+    }
+    group.bench_function(BenchmarkId::new("overlay", ops), |b| {
+        b.iter(|| engine.get_fields(entity_id).unwrap())
+    });
+    group.finish();
+}
+
+// Building a workspace dwarfs any single benchmark, so each scale is built
+// once and shared; benchmarks that write work on a copy
+fn main() {
+    let mut c = Criterion::default().configure_from_args();
+    for ops in scales() {
+        let workspace = Workspace::build(ops).unwrap();
+        append_bundle(&mut c, &workspace, ops);
+        ingest_with_conflicts(&mut c, &workspace, ops);
+        rebuild_from_oplog(&mut c, &workspace, ops);
+        query_by_facet(&mut c, &workspace, ops);
+        overlay_read(&mut c, &workspace, ops);
+    }
+    c.final_summary();
+}
@@ -0,0 +1,97 @@
+//! Fixtures for the criterion benchmarks in `benches/`.
+//!
+//! Workspaces are built once per scale and copied for benchmarks that write,
+//! so setup stays out of the measurement. Run with `cargo bench -p
+//! openprod-bench`; building the 1M-op scale takes minutes, so it only runs
+//! when `OPENPROD_BENCH_MAX_OPS` is raised to include it.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use openprod_core::{
+    field_value::FieldValue,
+    identity::ActorIdentity,
+    ids::EntityId,
+    operations::{Bundle, Operation},
+    sync_scope::SyncScope,
+    vector_clock::VectorClock,
+};
+use openprod_engine::Engine;
+use openprod_storage::SqliteStorage;
+use tempfile::TempDir;
+
+/// Oplog sizes every benchmark is run at.
+pub const SCALES: [usize; 3] = [10_000, 100_000, 1_000_000];
+
+/// Largest scale run unless `OPENPROD_BENCH_MAX_OPS` says otherwise.
+const DEFAULT_MAX_OPS: usize = 100_000;
+
+/// Facet every fixture entity carries.
+pub const FACET: &str = "Cue";
+
+/// Fields per fixture entity; with its create op, each entity is one bundle
+/// of `FIELDS.len() + 1` ops.
+pub const FIELDS: [&str; 8] = ["label", "number", "page", "duration", "fade", "delay", "notes", "status"];
+
+pub type BundleWithOps = (Bundle, Vec<Operation>);
+
+/// The entries of `SCALES` allowed by `OPENPROD_BENCH_MAX_OPS`.
+pub fn scales() -> Vec<usize> {
+    let max = std::env::var("OPENPROD_BENCH_MAX_OPS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_OPS);
+    SCALES.into_iter().filter(|&ops| ops <= max).collect()
+}
+
+/// A workspace database on disk, written by one actor.
+pub struct Workspace {
+    _dir: TempDir,
+    path: PathBuf,
+    author: [u8; 32],
+    pub entities: Vec<EntityId>,
+}
+
+impl Workspace {
+    /// At least `ops` operations: `FACET` entities with every field in `FIELDS` set.
+    pub fn build(ops: usize) -> Result<Self, Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("workspace.db");
+        let author = ActorIdentity::generate();
+        let mut engine = Engine::new(ActorIdentity::from_secret_bytes(&author.secret_bytes()), open_storage(&path)?)?;
+
+        let mut entities = Vec::new();
+        for i in 0..ops.div_ceil(FIELDS.len() + 1) {
+            let fields = FIELDS.iter().map(|&key| (key, FieldValue::Integer(i as i64))).collect();
+            entities.push(engine.create_entity_with_fields(FACET, fields)?.0);
+        }
+        Ok(Self { _dir: dir, path, author: author.secret_bytes(), entities })
+    }
+
+    /// A private copy of the database, for benchmarks that write.
+    pub fn copy(&self) -> Result<Self, Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("workspace.db");
+        open_storage(&self.path)?.backup_to(&path)?;
+        Ok(Self { _dir: dir, path, author: self.author, entities: self.entities.clone() })
+    }
+
+    /// Open the workspace as the actor that built it.
+    pub fn open_as_author(&self) -> Result<Engine<SqliteStorage>, Box<dyn Error>> {
+        self.open(ActorIdentity::from_secret_bytes(&self.author))
+    }
+
+    /// Open the workspace as another device.
+    pub fn open(&self, identity: ActorIdentity) -> Result<Engine<SqliteStorage>, Box<dyn Error>> {
+        Ok(Engine::new(identity, open_storage(&self.path)?)?)
+    }
+}
+
+/// Every bundle `engine` has that `known` doesn't cover, with its ops, in HLC order.
+pub fn bundles_since(engine: &Engine<SqliteStorage>, known: &VectorClock) -> Result<Vec<BundleWithOps>, Box<dyn Error>> {
+    Ok(engine.bundles_since(known, &SyncScope::All, None)?)
+}
+
+fn open_storage(path: &Path) -> Result<SqliteStorage, Box<dyn Error>> {
+    Ok(SqliteStorage::open(path.to_str().ok_or("temp path is not valid UTF-8")?)?)
+}
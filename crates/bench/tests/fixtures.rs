@@ -0,0 +1,17 @@
+use openprod_bench::{bundles_since, Workspace, FACET, FIELDS};
+use openprod_core::{field_value::FieldValue, vector_clock::VectorClock};
+
+#[test]
+fn workspace_has_requested_ops_and_copies_independently() -> Result<(), Box<dyn std::error::Error>> {
+    let workspace = Workspace::build(100)?;
+    let engine = workspace.open_as_author()?;
+    assert!(engine.op_count()? >= 100);
+    assert_eq!(engine.op_count()? as usize, workspace.entities.len() * (FIELDS.len() + 1));
+    assert_eq!(engine.get_entities_by_facet(FACET)?.len(), workspace.entities.len());
+    assert_eq!(bundles_since(&engine, &VectorClock::new())?.len(), workspace.entities.len());
+
+    let copy = workspace.copy()?;
+    copy.open_as_author()?.set_field(copy.entities[0], "label", FieldValue::Text("edited".into()))?;
+    assert_eq!(engine.get_field(workspace.entities[0], "label")?, Some(FieldValue::Integer(0)));
+    Ok(())
+}
@@ -0,0 +1,378 @@
+//! `openprod` — inspect and repair workspace databases from the shell.
+//!
+//! Read-only commands open the database without migrating it, so they are
+//! safe to point at a production file or a backup. Commands that write sign
+//! their bundles with `--key` (a 32-byte secret key file) or, without one, a
+//! throwaway identity that is printed so the edit can be traced later.
+
+mod shell;
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use openprod_core::field_value::{FieldValue, ValueType};
+use openprod_core::identity::ActorIdentity;
+use openprod_core::ids::{ConflictId, EntityId, OverlayId};
+use openprod_engine::{Engine, EntityEvent, TimelineFilter};
+use openprod_storage::{ConflictRecord, SqliteStorage};
+use uuid::Uuid;
+
+type Error = Box<dyn std::error::Error>;
+
+#[derive(Parser)]
+#[command(name = "openprod", about = "Inspect and manipulate openprod workspaces")]
+struct Cli {
+    /// File holding the 32-byte secret key to sign edits with
+    #[arg(long, global = true)]
+    key: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Summarize a workspace database
+    Inspect {
+        db: PathBuf,
+        /// Also verify signatures, references and replayed state
+        #[arg(long)]
+        verify: bool,
+    },
+    /// Look for orphaned rows and materialized state that differs from the oplog
+    Check {
+        db: PathBuf,
+        /// Re-materialize divergent rows and delete orphans
+        #[arg(long)]
+        repair: bool,
+    },
+    /// List bundles, or the ops that touched one entity
+    Ops {
+        db: PathBuf,
+        #[arg(long)]
+        entity: Option<String>,
+    },
+    /// List or resolve field conflicts
+    #[command(subcommand)]
+    Conflicts(ConflictsCommand),
+    /// List or commit overlays
+    #[command(subcommand)]
+    Overlay(OverlayCommand),
+    /// Write every bundle to an archive file
+    Export { db: PathBuf, archive: PathBuf },
+    /// Ingest the bundles in an archive file
+    Import { db: PathBuf, archive: PathBuf },
+    /// Interactive prompt for queries, history and test edits
+    Shell { db: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum ConflictsCommand {
+    /// Open conflicts, oldest first
+    List {
+        db: PathBuf,
+        #[arg(long)]
+        entity: Option<String>,
+    },
+    /// Resolve a conflict with one of its values, a new value, or a clear
+    Resolve {
+        db: PathBuf,
+        conflict: String,
+        /// Index of the competing value to keep, as shown by `conflicts list`
+        #[arg(long, conflicts_with_all = ["value", "clear"])]
+        pick: Option<usize>,
+        /// New value as TYPE:VALUE (text, int, float, bool, ts, ref, or any value type name) or `null`
+        #[arg(long, conflicts_with = "clear")]
+        value: Option<String>,
+        #[arg(long)]
+        clear: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum OverlayCommand {
+    /// Active and stashed overlays
+    List { db: PathBuf },
+    /// Commit an overlay's ops to canonical state
+    Commit { db: PathBuf, overlay: String },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Error> {
+    let key = cli.key.as_deref();
+    match cli.command {
+        Command::Inspect { db, verify } => inspect(&db, verify, key),
+        Command::Check { db, repair } => check(&db, repair, key),
+        Command::Ops { db, entity } => ops(&db, entity.as_deref()),
+        Command::Conflicts(ConflictsCommand::List { db, entity }) => {
+            let engine = open_read_only(&db)?;
+            let conflicts = match entity {
+                Some(entity) => engine.get_open_conflicts_for_entity(EntityId::from_uuid(parse_uuid(&entity)?))?,
+                None => engine.get_open_conflicts()?,
+            };
+            for conflict in &conflicts {
+                print_conflict(conflict, &mut io::stdout())?;
+            }
+            Ok(())
+        }
+        Command::Conflicts(ConflictsCommand::Resolve { db, conflict, pick, value, clear }) => {
+            let mut engine = open_writable(&db, key)?;
+            let conflict_id = ConflictId::from_uuid(parse_uuid(&conflict)?);
+            let chosen = match (pick, value) {
+                (Some(index), _) => {
+                    let record = engine.get_conflict(conflict_id)?.ok_or("no such conflict")?;
+                    let picked = record.values.get(index).ok_or("--pick is out of range")?;
+                    picked.value.as_deref().map(FieldValue::from_msgpack).transpose()?
+                }
+                (None, Some(value)) => Some(parse_value(&value)?),
+                (None, None) if clear => None,
+                (None, None) => return Err("pass one of --pick, --value or --clear".into()),
+            };
+            let bundle_id = engine.resolve_conflict(conflict_id, chosen)?;
+            println!("resolved in bundle {bundle_id}");
+            Ok(())
+        }
+        Command::Overlay(OverlayCommand::List { db }) => {
+            let engine = open_read_only(&db)?;
+            if let Some(active) = engine.active_overlay() {
+                println!("{active}  active");
+            }
+            for (overlay_id, name) in engine.stashed_overlays()? {
+                println!("{overlay_id}  stashed  {name}");
+            }
+            Ok(())
+        }
+        Command::Overlay(OverlayCommand::Commit { db, overlay }) => {
+            let mut engine = open_writable(&db, key)?;
+            let bundle_id = engine.commit_overlay(OverlayId::from_uuid(parse_uuid(&overlay)?))?;
+            println!("committed as bundle {bundle_id}");
+            Ok(())
+        }
+        Command::Export { db, archive } => {
+            let engine = open_read_only(&db)?;
+            let count = engine.export_archive(&archive, &Default::default())?;
+            println!("exported {count} bundles to {}", archive.display());
+            Ok(())
+        }
+        Command::Import { db, archive } => {
+            let mut engine = open_writable(&db, key)?;
+            let report = engine.import_archive(&archive)?;
+            println!(
+                "imported {} bundles ({} already present, {} new conflicts)",
+                report.imported,
+                report.already_present,
+                report.conflicts.len()
+            );
+            Ok(())
+        }
+        Command::Shell { db } => {
+            let mut engine = open_writable(&db, key)?;
+            shell::run(&mut engine, io::stdin().lock(), &mut io::stdout())?;
+            Ok(())
+        }
+    }
+}
+
+fn inspect(db: &Path, verify: bool, key: Option<&Path>) -> Result<(), Error> {
+    let engine = open_read_only(db)?;
+    println!("schema version  {}", engine.storage().schema_version()?);
+    println!("ops             {}", engine.op_count()?);
+    println!("bundles         {}", engine.timeline(&TimelineFilter::new())?.len());
+    println!("pending         {}", engine.pending_count()?);
+    println!("open conflicts  {}", engine.get_open_conflicts()?.len());
+    println!(
+        "overlays        {} stashed{}",
+        engine.stashed_overlays()?.len(),
+        if engine.active_overlay().is_some() { ", 1 active" } else { "" }
+    );
+    let actors = engine.list_actors()?;
+    println!("actors          {}", actors.len());
+    for actor in actors {
+        println!(
+            "  {}  {:>8} ops  {}",
+            actor.actor_id,
+            actor.op_count,
+            actor.display_name.as_deref().unwrap_or("-")
+        );
+    }
+
+    if verify {
+        // Replaying the oplog needs a writable connection
+        drop(engine);
+        let report = open_writable(db, key)?.verify_integrity()?;
+        println!("verified        {} bundles, {} ops", report.bundles_checked, report.ops_checked);
+        for (bundle_id, reason) in &report.bundle_errors {
+            println!("  bundle {bundle_id}: {reason}");
+        }
+        for problem in report.reference_errors.iter().chain(&report.storage_errors) {
+            println!("  {problem}");
+        }
+        if !report.replay_matches {
+            println!("  replaying the oplog does not reproduce the materialized state");
+        }
+        if !report.is_ok() {
+            return Err("integrity check failed".into());
+        }
+    }
+    Ok(())
+}
+
+fn check(db: &Path, repair: bool, key: Option<&Path>) -> Result<(), Error> {
+    // Replaying the oplog needs a writable connection
+    let mut engine = open_writable(db, key)?;
+    let report = if repair {
+        let repaired = engine.repair()?;
+        println!("repaired        {} rows, {} orphans removed", repaired.rematerialized, repaired.orphans_removed);
+        repaired.remaining
+    } else {
+        engine.check_consistency()?
+    };
+    for orphans in &report.orphans {
+        println!("  {} {} rows without {}", orphans.count, orphans.table, orphans.parent);
+    }
+    for row in &report.divergent {
+        println!("  {} ({}): {:?}", row.table, row.key, row.divergence);
+    }
+    if let Some(reason) = &report.replay_error {
+        println!("  the oplog cannot be replayed: {reason}");
+    }
+    for problem in &report.dangling {
+        println!("  {problem}");
+    }
+    if !report.is_ok() {
+        return Err("consistency check failed".into());
+    }
+    println!("consistent");
+    Ok(())
+}
+
+fn ops(db: &Path, entity: Option<&str>) -> Result<(), Error> {
+    let engine = open_read_only(db)?;
+    match entity {
+        Some(entity) => {
+            for entry in engine.entity_history(EntityId::from_uuid(parse_uuid(entity)?))? {
+                println!(
+                    "{}.{:<4} {}  {}  {}",
+                    entry.hlc.wall_ms(),
+                    entry.hlc.counter(),
+                    entry.actor_id,
+                    entry.bundle_id,
+                    describe(&entry.event)
+                );
+            }
+        }
+        None => {
+            for entry in engine.timeline(&TimelineFilter::new())? {
+                println!(
+                    "{}.{:<4} {}  {}  {:?} ({} ops){}",
+                    entry.hlc.wall_ms(),
+                    entry.hlc.counter(),
+                    entry.actor_id,
+                    entry.bundle_id,
+                    entry.bundle_type,
+                    entry.op_count,
+                    entry.label.map(|label| format!("  {label}")).unwrap_or_default()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn open_read_only(db: &Path) -> Result<Engine<SqliteStorage>, Error> {
+    let storage = SqliteStorage::open_read_only(db)?;
+    Ok(Engine::new(ActorIdentity::generate(), storage)?)
+}
+
+fn open_writable(db: &Path, key: Option<&Path>) -> Result<Engine<SqliteStorage>, Error> {
+    let identity = match key {
+        Some(path) => {
+            let bytes: [u8; 32] = std::fs::read(path)?
+                .try_into()
+                .map_err(|_| format!("{} is not a 32-byte secret key", path.display()))?;
+            ActorIdentity::from_secret_bytes(&bytes)
+        }
+        None => {
+            let identity = ActorIdentity::generate();
+            eprintln!("signing as ephemeral actor {}", identity.actor_id());
+            identity
+        }
+    };
+    let path = db.to_str().ok_or("database path is not valid UTF-8")?;
+    if !db.exists() {
+        return Err(format!("{} does not exist", db.display()).into());
+    }
+    Ok(Engine::new(identity, SqliteStorage::open(path)?)?)
+}
+
+fn parse_uuid(s: &str) -> Result<Uuid, Error> {
+    Uuid::parse_str(s).map_err(|e| format!("invalid id {s:?}: {e}").into())
+}
+
+/// Parse `TYPE:VALUE`, e.g. `text:Q1`, `int:3`, `ts:2024-05-01T19:30:00Z`,
+/// `ref:<uuid>`, or `null`. TYPE is a short alias or a `ValueType` name.
+fn parse_value(s: &str) -> Result<FieldValue, Error> {
+    if s == "null" {
+        return Ok(FieldValue::Null);
+    }
+    let (kind, value) = s.split_once(':').ok_or("expected TYPE:VALUE")?;
+    let value_type = match kind {
+        "int" => ValueType::Integer,
+        "bool" => ValueType::Boolean,
+        "ts" => ValueType::Timestamp,
+        "ref" => ValueType::EntityRef,
+        _ => kind.parse()?,
+    };
+    Ok(FieldValue::parse(value_type, value)?)
+}
+
+fn show(value: &FieldValue) -> String {
+    match value {
+        FieldValue::Text(s) => format!("{s:?}"),
+        FieldValue::EntityRef(id) => format!("ref:{id}"),
+        FieldValue::Bytes(bytes) => format!("<{} bytes>", bytes.len()),
+        other => other.to_string(),
+    }
+}
+
+fn describe(event: &EntityEvent) -> String {
+    match event {
+        EntityEvent::Created { initial_table: Some(table) } => format!("created in {table}"),
+        EntityEvent::Created { initial_table: None } => "created".into(),
+        EntityEvent::FieldSet { field_key, value } => format!("set {field_key} = {}", show(value)),
+        EntityEvent::FieldCleared { field_key } => format!("cleared {field_key}"),
+        EntityEvent::ConflictResolved { conflict_id, field_key, chosen_value } => format!(
+            "resolved {conflict_id} on {field_key} = {}",
+            chosen_value.as_ref().map(show).unwrap_or_else(|| "(cleared)".into())
+        ),
+        other => format!("{other:?}"),
+    }
+}
+
+fn print_conflict(conflict: &ConflictRecord, out: &mut impl Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "{}  {:?}  entity {}  field {}",
+        conflict.conflict_id, conflict.status, conflict.entity_id, conflict.field_key
+    )?;
+    for (index, candidate) in conflict.values.iter().enumerate() {
+        let value = match candidate.value.as_deref().map(FieldValue::from_msgpack) {
+            Some(Ok(value)) => show(&value),
+            Some(Err(_)) => "<undecodable>".into(),
+            None => "(cleared)".into(),
+        };
+        writeln!(out, "  [{index}] {value}  by {}", candidate.actor_id)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! `openprod shell`: a line-oriented prompt over one open workspace.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use openprod_core::ids::EntityId;
+use openprod_engine::{Engine, EntityEvent, UndoResult};
+use openprod_storage::SqliteStorage;
+
+use crate::{describe, parse_uuid, parse_value, print_conflict, show, Error};
+
+const HELP: &str = "\
+find <facet>                       live entities with a facet
+show <entity>                      facets and fields, with who last wrote each
+history <entity> [field]           ops that touched an entity (or one field)
+create <facet> [key=TYPE:VALUE]..  create an entity
+set <entity> <key> <TYPE:VALUE>    set a field (value may contain spaces)
+clear <entity> <key>               clear a field
+delete <entity>                    delete an entity
+undo | redo                        step the local undo stack
+conflicts                          open conflicts
+rebuild                            rebuild materialized state from the oplog
+quit                               leave the shell";
+
+/// Read commands from `input` until EOF or `quit`. Errors from individual
+/// commands are reported and the shell carries on.
+pub(crate) fn run(engine: &mut Engine<SqliteStorage>, input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+    let interactive = io::stdin().is_terminal();
+    let mut lines = input.lines();
+    loop {
+        if interactive {
+            write!(out, "openprod> ")?;
+            out.flush()?;
+        }
+        let Some(line) = lines.next().transpose()? else { break };
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "quit" | "exit" => break,
+            "help" => writeln!(out, "{HELP}")?,
+            _ => {
+                if let Err(e) = execute(engine, line, out) {
+                    writeln!(out, "error: {e}")?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn execute(engine: &mut Engine<SqliteStorage>, line: &str, out: &mut impl Write) -> Result<(), Error> {
+    let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let args: Vec<&str> = rest.split_whitespace().collect();
+    match (command, args.as_slice()) {
+        ("find", [facet]) => {
+            for entity_id in engine.get_entities_by_facet(facet)? {
+                if engine.get_entity(entity_id)?.is_some_and(|e| !e.deleted) {
+                    writeln!(out, "{entity_id}")?;
+                }
+            }
+        }
+        ("show", [entity]) => {
+            let entity_id = entity_arg(entity)?;
+            let record = engine.get_entity(entity_id)?.ok_or("no such entity")?;
+            writeln!(out, "{entity_id}{}", if record.deleted { "  (deleted)" } else { "" })?;
+            let facets: Vec<_> = engine.get_facets(entity_id)?.into_iter().filter(|f| !f.detached).map(|f| f.facet_type).collect();
+            writeln!(out, "  facets: {}", facets.join(", "))?;
+            let blame = engine.field_blame(entity_id)?;
+            for (key, value) in engine.get_fields(entity_id)? {
+                match blame.iter().find(|b| b.field_key == key) {
+                    Some(b) => writeln!(out, "  {key} = {}  ({})", show(&value), b.actor_id)?,
+                    None => writeln!(out, "  {key} = {}", show(&value))?,
+                }
+            }
+        }
+        ("history", [entity, field @ ..]) if field.len() <= 1 => {
+            for entry in engine.entity_history(entity_arg(entity)?)? {
+                if field.first().is_some_and(|f| event_field(&entry.event) != Some(*f)) {
+                    continue;
+                }
+                writeln!(out, "{}.{:<4} {}  {}", entry.hlc.wall_ms(), entry.hlc.counter(), entry.actor_id, describe(&entry.event))?;
+            }
+        }
+        ("create", [facet, fields @ ..]) => {
+            let mut values = Vec::new();
+            for field in fields {
+                let (key, value) = field.split_once('=').ok_or("fields are key=TYPE:VALUE")?;
+                values.push((key, parse_value(value)?));
+            }
+            let (entity_id, _) = engine.create_entity_with_fields(facet, values)?;
+            writeln!(out, "{entity_id}")?;
+        }
+        ("set", [entity, key, _, ..]) => {
+            // The value is everything after the key, so text may contain spaces
+            let value = rest.trim_start()[entity.len()..].trim_start()[key.len()..].trim();
+            engine.set_field(entity_arg(entity)?, key, parse_value(value)?)?;
+        }
+        ("clear", [entity, key]) => {
+            engine.clear_field(entity_arg(entity)?, key)?;
+        }
+        ("delete", [entity]) => {
+            engine.delete_entity(entity_arg(entity)?)?;
+        }
+        ("undo" | "redo", []) => {
+            let result = if command == "undo" { engine.undo()? } else { engine.redo()? };
+            match result {
+                UndoResult::Applied(_) => {}
+                UndoResult::Skipped { conflicts } => {
+                    writeln!(out, "skipped: {} fields were since edited by others", conflicts.len())?
+                }
+                UndoResult::Empty => writeln!(out, "nothing to {command}")?,
+            }
+        }
+        ("conflicts", []) => {
+            for conflict in engine.get_open_conflicts()? {
+                print_conflict(&conflict, out)?;
+            }
+        }
+        ("rebuild", []) => {
+            let replayed = engine.rebuild_state()?;
+            writeln!(out, "replayed {replayed} ops")?;
+        }
+        _ => return Err(format!("unrecognized command {line:?} (try `help`)").into()),
+    }
+    Ok(())
+}
+
+fn entity_arg(s: &str) -> Result<EntityId, Error> {
+    Ok(EntityId::from_uuid(parse_uuid(s)?))
+}
+
+/// The field an event touched, if it was a field-level change.
+fn event_field(event: &EntityEvent) -> Option<&str> {
+    match event {
+        EntityEvent::FieldSet { field_key, .. }
+        | EntityEvent::FieldCleared { field_key }
+        | EntityEvent::FieldMerged { field_key }
+        | EntityEvent::ConflictResolved { field_key, .. } => Some(field_key),
+        _ => None,
+    }
+}
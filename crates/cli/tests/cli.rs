@@ -0,0 +1,140 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+use openprod_core::{field_value::FieldValue, identity::ActorIdentity};
+use openprod_engine::Engine;
+use openprod_storage::{SqliteStorage, Storage};
+
+fn openprod(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_openprod")).args(args).output().expect("run openprod")
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn open(path: &Path) -> Result<Engine<SqliteStorage>, Box<dyn std::error::Error>> {
+    Ok(Engine::new(ActorIdentity::generate(), SqliteStorage::open(path.to_str().unwrap())?)?)
+}
+
+fn sync(from: &Engine<SqliteStorage>, to: &mut Engine<SqliteStorage>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut batch = Vec::new();
+    for bundle_id in from.bundles_missing_from(&to.get_vector_clock()?)? {
+        let bundle = from.storage().get_bundle(bundle_id)?.ok_or("missing")?;
+        batch.push((bundle, from.get_ops_by_bundle(bundle_id)?));
+    }
+    to.ingest_bundles(&batch)?;
+    Ok(())
+}
+
+#[test]
+fn inspect_and_ops_read_without_writing() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("show.db");
+    let cue = {
+        let mut engine = open(&db)?;
+        let (cue, _) = engine.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+        engine.set_field(cue, "label", FieldValue::Text("Q2".into()))?;
+        cue
+    };
+    let db = db.to_str().unwrap();
+
+    let summary = stdout(&openprod(&["inspect", db, "--verify"]));
+    assert!(summary.contains("open conflicts  0"), "{summary}");
+    assert!(summary.contains("actors          1"), "{summary}");
+    assert!(summary.contains("verified        2 bundles"), "{summary}");
+    assert!(stdout(&openprod(&["check", db])).contains("consistent"));
+
+    let history = stdout(&openprod(&["ops", db, "--entity", &cue.to_string()]));
+    assert!(history.contains("set label = \"Q2\""), "{history}");
+    assert_eq!(stdout(&openprod(&["ops", db])).lines().count(), 2);
+
+    let missing = openprod(&["ops", db, "--entity", "not-a-uuid"]);
+    assert!(!missing.status.success());
+    Ok(())
+}
+
+#[test]
+fn conflicts_can_be_listed_and_resolved() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("show.db");
+    let (cue, conflict_id) = {
+        let mut local = open(&db)?;
+        let mut peer = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+        let (cue, _) = peer.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+        sync(&peer, &mut local)?;
+        local.set_field(cue, "label", FieldValue::Text("Local".into()))?;
+        peer.set_field(cue, "label", FieldValue::Text("Peer".into()))?;
+        sync(&peer, &mut local)?;
+        (cue, local.get_open_conflicts()?[0].conflict_id)
+    };
+    let db_arg = db.to_str().unwrap();
+
+    let listed = stdout(&openprod(&["conflicts", "list", db_arg]));
+    assert!(listed.contains(&conflict_id.to_string()), "{listed}");
+    assert!(listed.contains("\"Local\"") && listed.contains("\"Peer\""), "{listed}");
+
+    let resolve = openprod(&["conflicts", "resolve", db_arg, &conflict_id.to_string(), "--value", "text:Both"]);
+    stdout(&resolve);
+    assert!(String::from_utf8_lossy(&resolve.stderr).contains("ephemeral actor"));
+    assert!(stdout(&openprod(&["conflicts", "list", db_arg])).is_empty());
+    assert_eq!(open(&db)?.get_field(cue, "label")?, Some(FieldValue::Text("Both".into())));
+    Ok(())
+}
+
+#[test]
+fn export_then_import_into_another_database() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let source = dir.path().join("source.db");
+    let target = dir.path().join("target.db");
+    let archive = dir.path().join("show.opa");
+    let key = dir.path().join("key");
+    let identity = ActorIdentity::generate();
+    std::fs::write(&key, identity.secret_bytes())?;
+    let cue = open(&source)?.create_entity(Some("Cue"))?.0;
+    drop(open(&target)?);
+
+    let [source, target, archive, key] = [&source, &target, &archive, &key].map(|p| p.to_str().unwrap());
+    assert!(stdout(&openprod(&["export", source, archive])).contains("exported 1 bundles"));
+    let imported = stdout(&openprod(&["--key", key, "import", target, archive]));
+    assert!(imported.contains("imported 1 bundles"), "{imported}");
+    assert!(stdout(&openprod(&["import", target, archive])).contains("1 already present"));
+    assert!(open(Path::new(target))?.get_entity(cue)?.is_some());
+
+    let bad_key = openprod(&["--key", archive, "import", target, archive]);
+    assert!(String::from_utf8_lossy(&bad_key.stderr).contains("not a 32-byte secret key"));
+    Ok(())
+}
+
+#[test]
+fn shell_runs_piped_commands() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("show.db");
+    let cue = open(&db)?.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?.0;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_openprod"))
+        .args(["shell", db.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let script = format!(
+        "find Cue\nset {cue} label text:Opening look\nhistory {cue} label\nshow {cue}\nbogus\nundo\nrebuild\nquit\nfind Cue\n"
+    );
+    child.stdin.take().ok_or("stdin")?.write_all(script.as_bytes())?;
+    let output = stdout(&child.wait_with_output()?);
+
+    assert!(output.starts_with(&cue.to_string()), "{output}");
+    assert!(output.contains("set label = \"Opening look\""), "{output}");
+    assert!(output.contains("  label = \"Opening look\""), "{output}");
+    assert!(output.contains("error: unrecognized command \"bogus\""), "{output}");
+    assert!(output.contains("replayed 4 ops"), "{output}");
+    // Nothing after `quit` runs
+    assert_eq!(output.matches(&cue.to_string()).count(), 2, "{output}");
+    assert_eq!(open(&db)?.get_field(cue, "label")?, Some(FieldValue::Text("Q1".into())));
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CoreError;
+use crate::operations::{CrdtType, OperationPayload};
+
+/// Version of the op/bundle wire encoding. Peers with different format versions
+/// cannot sync at all; bump only on incompatible encoding changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// What a peer can materialize, exchanged at the start of sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub format_version: u32,
+    /// Payload variants (by `op_type_name`) the peer materializes.
+    pub payload_types: BTreeSet<String>,
+    /// CRDT types the peer can merge for `ApplyCrdt`.
+    pub crdt_types: BTreeSet<CrdtType>,
+}
+
+impl Capabilities {
+    pub fn new(
+        payload_types: impl IntoIterator<Item = impl Into<String>>,
+        crdt_types: impl IntoIterator<Item = CrdtType>,
+    ) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            payload_types: payload_types.into_iter().map(Into::into).collect(),
+            crdt_types: crdt_types.into_iter().collect(),
+        }
+    }
+
+    /// Whether a peer with these capabilities can materialize `payload`.
+    pub fn supports(&self, payload: &OperationPayload) -> bool {
+        if !self.payload_types.contains(payload.op_type_name()) {
+            return false;
+        }
+        match payload {
+            OperationPayload::ApplyCrdt { crdt_type, .. } => self.crdt_types.contains(crdt_type),
+            _ => true,
+        }
+    }
+
+    /// Compare our capabilities (`self`) against a remote peer's.
+    pub fn compatibility_with(&self, remote: &Capabilities) -> CompatibilityReport {
+        CompatibilityReport {
+            local_format_version: self.format_version,
+            remote_format_version: remote.format_version,
+            payload_types_missing_remotely: self.payload_types.difference(&remote.payload_types).cloned().collect(),
+            payload_types_missing_locally: remote.payload_types.difference(&self.payload_types).cloned().collect(),
+            crdt_types_missing_remotely: self.crdt_types.difference(&remote.crdt_types).copied().collect(),
+            crdt_types_missing_locally: remote.crdt_types.difference(&self.crdt_types).copied().collect(),
+        }
+    }
+
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, CoreError> {
+        rmp_serde::to_vec_named(self).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, CoreError> {
+        rmp_serde::from_slice(bytes).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+}
+
+/// Outcome of comparing two peers' capabilities.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatibilityReport {
+    pub local_format_version: u32,
+    pub remote_format_version: u32,
+    /// Payload types we materialize but the remote doesn't (ops using them are parked).
+    pub payload_types_missing_remotely: Vec<String>,
+    /// Payload types the remote materializes but we don't.
+    pub payload_types_missing_locally: Vec<String>,
+    pub crdt_types_missing_remotely: Vec<CrdtType>,
+    pub crdt_types_missing_locally: Vec<CrdtType>,
+}
+
+impl CompatibilityReport {
+    /// Peers can sync at all (some ops may still be parked).
+    pub fn can_sync(&self) -> bool {
+        self.local_format_version == self.remote_format_version
+    }
+
+    /// Peers can exchange every op without parking.
+    pub fn is_fully_compatible(&self) -> bool {
+        self.can_sync()
+            && self.payload_types_missing_remotely.is_empty()
+            && self.payload_types_missing_locally.is_empty()
+            && self.crdt_types_missing_remotely.is_empty()
+            && self.crdt_types_missing_locally.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::EntityId;
+
+    #[test]
+    fn supports_checks_payload_and_crdt_type() {
+        let caps = Capabilities::new(["SetField", "ApplyCrdt"], [CrdtType::Text]);
+        let entity_id = EntityId::new();
+        let set = OperationPayload::SetField {
+            entity_id,
+            field_key: "a".into(),
+            value: crate::field_value::FieldValue::Integer(1),
+        };
+        let crdt = |crdt_type| OperationPayload::ApplyCrdt {
+            entity_id,
+            field_key: "a".into(),
+            crdt_type,
+            delta: Vec::new(),
+        };
+        assert!(caps.supports(&set));
+        assert!(caps.supports(&crdt(CrdtType::Text)));
+        assert!(!caps.supports(&crdt(CrdtType::List)));
+        assert!(!caps.supports(&OperationPayload::DeleteEdge { edge_id: crate::ids::EdgeId::new() }));
+    }
+
+    #[test]
+    fn compatibility_report_lists_differences() {
+        let newer = Capabilities::new(["SetField", "ApplyCrdt"], [CrdtType::Text]);
+        let older = Capabilities::new(["SetField"], []);
+        let report = newer.compatibility_with(&older);
+        assert!(report.can_sync());
+        assert!(!report.is_fully_compatible());
+        assert_eq!(report.payload_types_missing_remotely, vec!["ApplyCrdt".to_string()]);
+        assert!(report.payload_types_missing_locally.is_empty());
+        assert_eq!(report.crdt_types_missing_remotely, vec![CrdtType::Text]);
+
+        let mut future = older.clone();
+        future.format_version = FORMAT_VERSION + 1;
+        assert!(!older.compatibility_with(&future).can_sync());
+    }
+
+    #[test]
+    fn capabilities_msgpack_roundtrip() {
+        let caps = Capabilities::new(["SetField"], [CrdtType::List]);
+        let bytes = caps.to_msgpack().unwrap();
+        assert_eq!(Capabilities::from_msgpack(&bytes).unwrap(), caps);
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::CoreError;
+use crate::hlc::Hlc;
+use crate::ids::BundleId;
+
+/// Half-open range of bundle HLCs: `start <= hlc < end` (`end = None` is unbounded).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HlcRange {
+    pub start: Hlc,
+    pub end: Option<Hlc>,
+}
+
+impl HlcRange {
+    /// The range covering every bundle.
+    pub fn full() -> Self {
+        Self { start: Hlc::new(0, 0), end: None }
+    }
+
+    pub fn contains(&self, hlc: Hlc) -> bool {
+        hlc >= self.start && self.end.is_none_or(|end| hlc < end)
+    }
+}
+
+/// Order-independent summary of the bundles in an HLC range.
+///
+/// The fingerprint is the XOR of `blake3(bundle_id)` over the range, so two
+/// peers holding the same bundles agree regardless of how they store them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RangeDigest {
+    pub range: HlcRange,
+    pub count: u64,
+    pub fingerprint: [u8; 32],
+}
+
+impl RangeDigest {
+    pub fn new(range: HlcRange, bundle_ids: impl IntoIterator<Item = BundleId>) -> Self {
+        let mut count = 0;
+        let mut fingerprint = [0u8; 32];
+        for bundle_id in bundle_ids {
+            let hash = blake3::hash(bundle_id.as_bytes());
+            for (acc, byte) in fingerprint.iter_mut().zip(hash.as_bytes()) {
+                *acc ^= byte;
+            }
+            count += 1;
+        }
+        Self { range, count, fingerprint }
+    }
+
+    /// True if both sides hold the same bundles in this range (with overwhelming probability).
+    pub fn matches(&self, other: &RangeDigest) -> bool {
+        self.range == other.range && self.count == other.count && self.fingerprint == other.fingerprint
+    }
+}
+
+/// One entry of a range-reconciliation round.
+///
+/// Peers exchange digests and recursively split mismatched ranges until they are
+/// small enough to send as explicit bundle id lists, so missing bundles are found
+/// in O(log n) round-trips.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RangeMessage {
+    /// "This is what I hold in `range`"; the receiver compares and recurses.
+    Digest(RangeDigest),
+    /// Every bundle id the sender holds in `range`; ends recursion for the range.
+    Ids { range: HlcRange, bundle_ids: Vec<BundleId> },
+}
+
+impl RangeMessage {
+    pub fn to_msgpack(messages: &[RangeMessage]) -> Result<Vec<u8>, CoreError> {
+        rmp_serde::to_vec_named(messages).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Vec<RangeMessage>, CoreError> {
+        rmp_serde::from_slice(bytes).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_is_order_independent() {
+        let ids: Vec<BundleId> = (0..5).map(|_| BundleId::new()).collect();
+        let forward = RangeDigest::new(HlcRange::full(), ids.iter().copied());
+        let backward = RangeDigest::new(HlcRange::full(), ids.iter().rev().copied());
+        assert!(forward.matches(&backward));
+
+        let fewer = RangeDigest::new(HlcRange::full(), ids[1..].iter().copied());
+        assert!(!forward.matches(&fewer));
+    }
+
+    #[test]
+    fn range_bounds_are_half_open() {
+        let range = HlcRange { start: Hlc::new(10, 0), end: Some(Hlc::new(20, 0)) };
+        assert!(range.contains(Hlc::new(10, 0)));
+        assert!(range.contains(Hlc::new(19, 5)));
+        assert!(!range.contains(Hlc::new(20, 0)));
+        assert!(HlcRange::full().contains(Hlc::new(u64::MAX, 0)));
+    }
+
+    #[test]
+    fn messages_roundtrip_msgpack() {
+        let messages = vec![
+            RangeMessage::Digest(RangeDigest::new(HlcRange::full(), [BundleId::new()])),
+            RangeMessage::Ids { range: HlcRange::full(), bundle_ids: vec![BundleId::new()] },
+        ];
+        let bytes = RangeMessage::to_msgpack(&messages).unwrap();
+        assert_eq!(RangeMessage::from_msgpack(&bytes).unwrap(), messages);
+    }
+}
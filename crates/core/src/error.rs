@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::ids::BundleId;
+
 #[derive(Debug, Error)]
 pub enum CoreError {
     #[error("serialization error: {0}")]
@@ -17,6 +19,21 @@ pub enum CoreError {
     #[error("HLC counter overflow")]
     HlcCounterOverflow,
 
+    #[error("HLC is {ahead_ms}ms ahead of physical time (max {max_ms}ms)")]
+    HlcClockAhead { ahead_ms: u64, max_ms: u64 },
+
+    #[error("clock regression: physical time is {behind_ms}ms behind the last issued HLC (max {max_ms}ms)")]
+    ClockRegression { behind_ms: u64, max_ms: u64 },
+
+    #[error("unsupported wire format version {version} (max supported {max})")]
+    UnsupportedWireVersion { version: u8, max: u8 },
+
+    #[error("decryption failed: {0}")]
+    Decryption(String),
+
     #[error("invalid data: {0}")]
     InvalidData(String),
+
+    #[error("bundle {bundle_id}: {reason}")]
+    InvalidBundle { bundle_id: BundleId, reason: String },
 }
@@ -1,6 +1,12 @@
+use std::fmt;
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
+use crate::error::CoreError;
 use crate::ids::{BlobHash, EntityId};
+use crate::json::{parse_hex, to_hex};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum FieldValue {
@@ -34,6 +40,88 @@ impl PartialEq for FieldValue {
 
 impl Eq for FieldValue {}
 
+/// The type of a `FieldValue`, named as in export documents (`text`,
+/// `entity_ref`, ...). The hint `FieldValue::parse` needs to read text back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ValueType {
+    Null,
+    Text,
+    Integer,
+    Float,
+    Boolean,
+    Timestamp,
+    EntityRef,
+    BlobRef,
+    Bytes,
+}
+
+impl ValueType {
+    pub const ALL: [ValueType; 9] = [
+        Self::Null,
+        Self::Text,
+        Self::Integer,
+        Self::Float,
+        Self::Boolean,
+        Self::Timestamp,
+        Self::EntityRef,
+        Self::BlobRef,
+        Self::Bytes,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Text => "text",
+            Self::Integer => "integer",
+            Self::Float => "float",
+            Self::Boolean => "boolean",
+            Self::Timestamp => "timestamp",
+            Self::EntityRef => "entity_ref",
+            Self::BlobRef => "blob_ref",
+            Self::Bytes => "bytes",
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ValueType {
+    type Err = CoreError;
+
+    fn from_str(s: &str) -> Result<Self, CoreError> {
+        Self::ALL
+            .into_iter()
+            .find(|t| t.as_str() == s)
+            .ok_or_else(|| CoreError::InvalidData(format!("unknown value type {s:?}")))
+    }
+}
+
+/// Canonical text, the same in every locale: `null`; text as-is; integers
+/// in decimal; floats in Rust's shortest round-trip form (`1.0`, `2.5e-8`,
+/// `NaN`, `inf`); `true`/`false`; timestamps in RFC 3339 UTC with
+/// milliseconds (`2023-11-14T22:13:20.000Z`), or as epoch ms outside years
+/// 0000-9999; entity refs as hyphenated UUIDs; blob refs and bytes as
+/// lowercase hex. `FieldValue::parse` reads all of it back.
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Text(text) => f.write_str(text),
+            Self::Integer(n) => write!(f, "{n}"),
+            Self::Float(x) => write!(f, "{x:?}"),
+            Self::Boolean(b) => write!(f, "{b}"),
+            Self::Timestamp(ms) => write_timestamp(f, *ms),
+            Self::EntityRef(id) => write!(f, "{id}"),
+            Self::BlobRef(hash) => f.write_str(&to_hex(hash.as_bytes())),
+            Self::Bytes(bytes) => f.write_str(&to_hex(bytes)),
+        }
+    }
+}
+
 impl FieldValue {
     pub fn is_null(&self) -> bool {
         matches!(self, FieldValue::Null)
@@ -60,6 +148,46 @@ impl FieldValue {
         }
     }
 
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            FieldValue::Null => ValueType::Null,
+            FieldValue::Text(_) => ValueType::Text,
+            FieldValue::Integer(_) => ValueType::Integer,
+            FieldValue::Float(_) => ValueType::Float,
+            FieldValue::Boolean(_) => ValueType::Boolean,
+            FieldValue::Timestamp(_) => ValueType::Timestamp,
+            FieldValue::EntityRef(_) => ValueType::EntityRef,
+            FieldValue::BlobRef(_) => ValueType::BlobRef,
+            FieldValue::Bytes(_) => ValueType::Bytes,
+        }
+    }
+
+    /// Read a value of type `type_hint` from text, as `Display` writes it.
+    /// Also accepted: booleans in any case, timestamps as epoch ms, as a bare
+    /// date (midnight UTC), or with a `+HH:MM`/`-HH:MM` offset and any number
+    /// of fractional digits (truncated to ms). Surrounding whitespace is not
+    /// trimmed.
+    pub fn parse(type_hint: ValueType, s: &str) -> Result<Self, CoreError> {
+        let invalid = || CoreError::InvalidData(format!("invalid {type_hint} {s:?}"));
+        Ok(match type_hint {
+            ValueType::Null if s == "null" => Self::Null,
+            ValueType::Null => return Err(invalid()),
+            ValueType::Text => Self::Text(s.to_string()),
+            ValueType::Integer => Self::Integer(s.parse().map_err(|_| invalid())?),
+            ValueType::Float => Self::Float(s.parse().map_err(|_| invalid())?),
+            ValueType::Boolean if s.eq_ignore_ascii_case("true") => Self::Boolean(true),
+            ValueType::Boolean if s.eq_ignore_ascii_case("false") => Self::Boolean(false),
+            ValueType::Boolean => return Err(invalid()),
+            ValueType::Timestamp => Self::Timestamp(parse_timestamp(s).ok_or_else(invalid)?),
+            ValueType::EntityRef => Self::EntityRef(EntityId::from_uuid(Uuid::parse_str(s).map_err(|_| invalid())?)),
+            ValueType::BlobRef => {
+                let bytes = parse_hex(s).and_then(|bytes| bytes.try_into().ok()).ok_or_else(invalid)?;
+                Self::BlobRef(BlobHash::from_bytes(bytes))
+            }
+            ValueType::Bytes => Self::Bytes(parse_hex(s).ok_or_else(invalid)?),
+        })
+    }
+
     pub fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
         rmp_serde::to_vec(self)
     }
@@ -67,4 +195,162 @@ impl FieldValue {
     pub fn from_msgpack(bytes: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
         rmp_serde::from_slice(bytes)
     }
+
+    /// JSON, e.g. `{"Text":"Q1"}`; see `json`.
+    pub fn to_json(&self) -> Result<String, CoreError> {
+        crate::json::to_json(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, CoreError> {
+        crate::json::from_json(json)
+    }
+}
+
+const MS_PER_DAY: i64 = 86_400_000;
+
+fn write_timestamp(f: &mut fmt::Formatter<'_>, ms: i64) -> fmt::Result {
+    let (year, month, day) = civil_from_days(ms.div_euclid(MS_PER_DAY));
+    if !(0..=9999).contains(&year) {
+        return write!(f, "{ms}");
+    }
+    let time = ms.rem_euclid(MS_PER_DAY);
+    write!(
+        f,
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        time / 3_600_000,
+        time / 60_000 % 60,
+        time / 1_000 % 60,
+        time % 1_000
+    )
+}
+
+fn parse_timestamp(s: &str) -> Option<i64> {
+    if let Ok(ms) = s.parse::<i64>() {
+        return Some(ms);
+    }
+    // Fixed-width runs of ASCII digits only, so no signs or spaces slip in
+    let number = |digits: &str, width: usize| {
+        (digits.len() == width && digits.bytes().all(|b| b.is_ascii_digit())).then(|| digits.parse::<i64>().ok())?
+    };
+    let (date, time) = match s.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let mut parts = date.splitn(3, '-');
+    let year = number(parts.next()?, 4)?;
+    let month = number(parts.next()?, 2)?;
+    let day = number(parts.next()?, 2)?;
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    let Some(time) = time else {
+        return Some(days * MS_PER_DAY);
+    };
+
+    // HH:MM:SS[.fff...] then Z or an offset
+    let (clock, offset_ms) = match time.strip_suffix(['Z', 'z']) {
+        Some(clock) => (clock, 0),
+        None => {
+            let (clock, offset) = time.split_at(time.rfind(['+', '-'])?);
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let offset_ms = (number(hours, 2)? * 60 + number(minutes, 2)?) * 60_000;
+            (clock, if offset.starts_with('-') { -offset_ms } else { offset_ms })
+        }
+    };
+    let (clock, millis) = match clock.split_once('.') {
+        Some((clock, fraction)) if !fraction.is_empty() && fraction.bytes().all(|b| b.is_ascii_digit()) => {
+            (clock, number(&format!("{fraction:0<3}")[..3], 3)?)
+        }
+        Some(_) => return None,
+        None => (clock, 0),
+    };
+    let mut parts = clock.splitn(3, ':');
+    let hour = number(parts.next()?, 2)?;
+    let minute = number(parts.next()?, 2)?;
+    let second = number(parts.next()?, 2)?;
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    Some(days * MS_PER_DAY + ((hour * 60 + minute) * 60 + second) * 1_000 + millis - offset_ms)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The (year, month, day) `days` after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_round_trips_through_parse() {
+        let values = [
+            FieldValue::Null,
+            FieldValue::Text("Act 1, \"Scene\" 2".into()),
+            FieldValue::Text(String::new()),
+            FieldValue::Integer(-42),
+            FieldValue::Float(1.0),
+            FieldValue::Float(2.5e-8),
+            FieldValue::Float(f64::NEG_INFINITY),
+            FieldValue::Float(f64::NAN),
+            FieldValue::Boolean(true),
+            FieldValue::Timestamp(1_700_000_000_123),
+            FieldValue::Timestamp(-1),
+            FieldValue::Timestamp(i64::MAX),
+            FieldValue::EntityRef(EntityId::new()),
+            FieldValue::BlobRef(BlobHash::from_bytes([0xab; 32])),
+            FieldValue::Bytes(vec![0, 1, 254, 255]),
+        ];
+        for value in values {
+            let text = value.to_string();
+            assert_eq!(FieldValue::parse(value.value_type(), &text).unwrap(), value, "{text}");
+        }
+        for value_type in ValueType::ALL {
+            assert_eq!(value_type.as_str().parse::<ValueType>().unwrap(), value_type);
+        }
+    }
+
+    #[test]
+    fn canonical_formats() {
+        assert_eq!(FieldValue::Timestamp(1_700_000_000_123).to_string(), "2023-11-14T22:13:20.123Z");
+        assert_eq!(FieldValue::Timestamp(-1).to_string(), "1969-12-31T23:59:59.999Z");
+        assert_eq!(FieldValue::Float(3.0).to_string(), "3.0");
+        assert_eq!(FieldValue::Bytes(vec![0x0f, 0xa0]).to_string(), "0fa0");
+
+        let ts = |s| FieldValue::parse(ValueType::Timestamp, s).ok();
+        assert_eq!(ts("2023-11-14T22:13:20.123Z"), Some(FieldValue::Timestamp(1_700_000_000_123)));
+        assert_eq!(ts("2023-11-14T23:13:20.1234+01:00"), Some(FieldValue::Timestamp(1_700_000_000_123)));
+        assert_eq!(ts("2023-11-14T22:13:20Z"), Some(FieldValue::Timestamp(1_700_000_000_000)));
+        assert_eq!(ts("1970-01-02"), Some(FieldValue::Timestamp(86_400_000)));
+        assert_eq!(ts("1700000000123"), Some(FieldValue::Timestamp(1_700_000_000_123)));
+        for bad in ["2023-02-29", "2023-11-14T24:00:00Z", "2023-11-14T22:13:20", "2023-11-14T22:13:20.Z", "2023-1-14", "+2023-11-14"] {
+            assert_eq!(ts(bad), None, "{bad}");
+        }
+
+        assert_eq!(FieldValue::parse(ValueType::Boolean, "FALSE").unwrap(), FieldValue::Boolean(false));
+        assert!(FieldValue::parse(ValueType::Integer, " 7").is_err());
+        assert!(FieldValue::parse(ValueType::BlobRef, "abcd").is_err());
+        assert!(FieldValue::parse(ValueType::Null, "").is_err());
+        assert!("int".parse::<ValueType>().is_err());
+    }
 }
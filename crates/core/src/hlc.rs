@@ -1,4 +1,7 @@
 use std::cmp::Ordering;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
@@ -8,6 +11,7 @@ use crate::CoreError;
 pub const MAX_DRIFT_MS: u64 = 300_000; // 5 minutes
 
 /// Returns the current wall-clock time as milliseconds since Unix epoch.
+#[cfg(not(target_arch = "wasm32"))]
 pub fn physical_now() -> Result<u64, CoreError> {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -15,6 +19,66 @@ pub fn physical_now() -> Result<u64, CoreError> {
         .map_err(|_| CoreError::InvalidData("system clock before epoch".into()))
 }
 
+/// Returns the current wall-clock time as milliseconds since Unix epoch.
+/// `SystemTime` panics on wasm32-unknown-unknown, so the browser clock is used.
+#[cfg(target_arch = "wasm32")]
+pub fn physical_now() -> Result<u64, CoreError> {
+    let now = js_sys::Date::now();
+    if now < 0.0 {
+        return Err(CoreError::InvalidData("system clock before epoch".into()));
+    }
+    Ok(now as u64)
+}
+
+/// Where an [`HlcClock`] reads physical time from. The default is
+/// [`SystemClock`]; tests and simulations use [`ManualClock`], and embedders
+/// can plug in a monotonic or NTP-disciplined source.
+pub trait ClockSource: Send + Sync {
+    /// Current time in milliseconds since Unix epoch.
+    fn now_ms(&self) -> Result<u64, CoreError>;
+}
+
+/// Wall-clock time via [`physical_now`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now_ms(&self) -> Result<u64, CoreError> {
+        physical_now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so a
+/// test can keep one handle and pass another to the engine.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now_ms: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new(now_ms: u64) -> Self {
+        Self { now_ms: Arc::new(AtomicU64::new(now_ms)) }
+    }
+
+    pub fn set(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, AtomicOrdering::SeqCst);
+    }
+
+    pub fn advance(&self, ms: u64) {
+        self.now_ms.fetch_add(ms, AtomicOrdering::SeqCst);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.now_ms.load(AtomicOrdering::SeqCst)
+    }
+}
+
+impl ClockSource for ManualClock {
+    fn now_ms(&self) -> Result<u64, CoreError> {
+        Ok(self.get())
+    }
+}
+
 /// A 12-byte Hybrid Logical Clock timestamp: 8 bytes wall_ms (big-endian u64)
 /// followed by 4 bytes counter (big-endian u32).
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
@@ -62,14 +126,23 @@ impl PartialOrd for Hlc {
     }
 }
 
+/// Raw bytes, or `"<wall_ms>:<counter>"` in human-readable formats.
 impl Serialize for Hlc {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(&format!("{}:{}", self.wall_ms, self.counter));
+        }
         serializer.serialize_bytes(&self.to_bytes())
     }
 }
 
 impl<'de> Deserialize<'de> for Hlc {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let text = String::deserialize(deserializer)?;
+            let parsed = text.split_once(':').and_then(|(wall_ms, counter)| Some(Hlc::new(wall_ms.parse().ok()?, counter.parse().ok()?)));
+            return parsed.ok_or_else(|| serde::de::Error::custom(format!("invalid HLC {text:?}")));
+        }
         let bytes: Vec<u8> = Deserialize::deserialize(deserializer)?;
         let arr: [u8; 12] = bytes
             .try_into()
@@ -82,25 +155,109 @@ impl<'de> Deserialize<'de> for Hlc {
 pub struct HlcClock {
     wall_ms: u64,
     counter: u32,
+    source: Arc<dyn ClockSource>,
+    max_drift_ms: u64,
+    clamp_ahead: bool,
+    max_counter: u32,
 }
 
 impl HlcClock {
     pub fn new() -> Self {
+        Self::with_source(Arc::new(SystemClock))
+    }
+
+    /// A clock reading physical time from `source` instead of the system clock.
+    pub fn with_source(source: Arc<dyn ClockSource>) -> Self {
         Self {
             wall_ms: 0,
             counter: 0,
+            source,
+            max_drift_ms: MAX_DRIFT_MS,
+            clamp_ahead: false,
+            max_counter: u32::MAX,
+        }
+    }
+
+    /// How far ahead of physical time a remote timestamp may be before
+    /// `receive` rejects it. Defaults to [`MAX_DRIFT_MS`].
+    pub fn set_max_drift_ms(&mut self, max_drift_ms: u64) {
+        self.max_drift_ms = max_drift_ms;
+    }
+
+    pub fn max_drift_ms(&self) -> u64 {
+        self.max_drift_ms
+    }
+
+    /// When enabled, `tick` fails with `HlcClockAhead` instead of issuing a
+    /// timestamp more than `max_drift_ms` ahead of physical time, e.g. after
+    /// the system clock was stepped back by hours. Off by default.
+    pub fn set_clamp_ahead(&mut self, enabled: bool) {
+        self.clamp_ahead = enabled;
+    }
+
+    pub fn clamp_ahead(&self) -> bool {
+        self.clamp_ahead
+    }
+
+    /// Highest logical counter to issue within one millisecond; past it,
+    /// `tick` and `receive` fail with `HlcCounterOverflow`. Lower it when
+    /// HLCs must fit a narrower encoding. Defaults to `u32::MAX`.
+    pub fn set_max_counter(&mut self, max_counter: u32) {
+        self.max_counter = max_counter;
+    }
+
+    pub fn max_counter(&self) -> u32 {
+        self.max_counter
+    }
+
+    /// The latest timestamp issued or merged, the one to persist so a
+    /// restarted clock can `restore` it.
+    pub fn last(&self) -> Hlc {
+        Hlc::new(self.wall_ms, self.counter)
+    }
+
+    /// Resume from a timestamp persisted by an earlier run, so the clock
+    /// never issues one at or below it, even if the system clock was set back
+    /// meanwhile. The clock is restored either way; the error reports that
+    /// physical time is more than `max_drift_ms` behind it, meaning every
+    /// timestamp until it catches up comes from the logical counter.
+    pub fn restore(&mut self, last: Hlc) -> Result<(), CoreError> {
+        if last > self.last() {
+            self.wall_ms = last.wall_ms;
+            self.counter = last.counter;
+        }
+        let behind_ms = last.wall_ms.saturating_sub(self.now_ms()?);
+        if behind_ms > self.max_drift_ms {
+            return Err(CoreError::ClockRegression { behind_ms, max_ms: self.max_drift_ms });
         }
+        Ok(())
+    }
+
+    /// `counter + 1`, unless that passes `max_counter`.
+    fn next_counter(&self, counter: u32) -> Result<u32, CoreError> {
+        counter.checked_add(1).filter(|c| *c <= self.max_counter).ok_or(CoreError::HlcCounterOverflow)
+    }
+
+    /// Physical time as seen by this clock's source.
+    pub fn now_ms(&self) -> Result<u64, CoreError> {
+        self.source.now_ms()
     }
 
     /// Generate the next monotonically increasing timestamp.
     pub fn tick(&mut self) -> Result<Hlc, CoreError> {
-        let now = physical_now()?;
+        let now = self.now_ms()?;
+
+        if self.clamp_ahead && self.wall_ms > now.saturating_add(self.max_drift_ms) {
+            return Err(CoreError::HlcClockAhead {
+                ahead_ms: self.wall_ms - now,
+                max_ms: self.max_drift_ms,
+            });
+        }
 
         let hlc = if now > self.wall_ms {
             Hlc::new(now, 0)
         } else {
-            let c = self.counter.checked_add(1).ok_or(CoreError::HlcCounterOverflow)?;
-            Hlc::new(self.wall_ms, c)
+            Hlc::new(self.wall_ms, self.next_counter(self.counter)?)
         };
 
         self.wall_ms = hlc.wall_ms;
@@ -110,13 +267,13 @@ impl HlcClock {
 
     /// Merge with a remote timestamp, producing a timestamp greater than both.
     pub fn receive(&mut self, remote: &Hlc) -> Result<Hlc, CoreError> {
-        let now = physical_now()?;
+        let now = self.now_ms()?;
 
         // Reject remote timestamps too far in the future
-        if remote.wall_ms > now + MAX_DRIFT_MS {
+        if remote.wall_ms > now.saturating_add(self.max_drift_ms) {
             return Err(CoreError::HlcDriftTooLarge {
                 delta_ms: remote.wall_ms - now,
-                max_ms: MAX_DRIFT_MS,
+                max_ms: self.max_drift_ms,
             });
         }
 
@@ -125,18 +282,15 @@ impl HlcClock {
             Hlc::new(now, 0)
         } else if self.wall_ms == remote.wall_ms && self.wall_ms == now {
             // All three equal
-            let c = self.counter.max(remote.counter).checked_add(1)
-                .ok_or(CoreError::HlcCounterOverflow)?;
+            let c = self.next_counter(self.counter.max(remote.counter))?;
             Hlc::new(self.wall_ms, c)
         } else if self.wall_ms == remote.wall_ms {
             // Local and remote tied, both ahead of physical
-            let c = self.counter.max(remote.counter).checked_add(1)
-                .ok_or(CoreError::HlcCounterOverflow)?;
+            let c = self.next_counter(self.counter.max(remote.counter))?;
             Hlc::new(self.wall_ms, c)
         } else if self.wall_ms > remote.wall_ms {
             // Local is greatest
-            let c = self.counter.checked_add(1)
-                .ok_or(CoreError::HlcCounterOverflow)?;
+            let c = self.next_counter(self.counter)?;
             if self.wall_ms == now {
                 Hlc::new(now, c)
             } else {
@@ -144,8 +298,7 @@ impl HlcClock {
             }
         } else {
             // Remote is greatest
-            let c = remote.counter.checked_add(1)
-                .ok_or(CoreError::HlcCounterOverflow)?;
+            let c = self.next_counter(remote.counter)?;
             if remote.wall_ms == now {
                 Hlc::new(now, c)
             } else {
@@ -299,6 +452,107 @@ mod tests {
         }
     }
 
+    #[test]
+    fn manual_clock_drives_ticks() {
+        let source = ManualClock::new(1_000);
+        let mut clock = HlcClock::with_source(Arc::new(source.clone()));
+        assert_eq!(clock.tick().unwrap(), Hlc::new(1_000, 0));
+        assert_eq!(clock.tick().unwrap(), Hlc::new(1_000, 1));
+
+        source.advance(5);
+        assert_eq!(clock.tick().unwrap(), Hlc::new(1_005, 0));
+
+        // A source stepping backwards doesn't move the HLC backwards
+        source.set(900);
+        assert_eq!(clock.tick().unwrap(), Hlc::new(1_005, 1));
+    }
+
+    #[test]
+    fn drift_is_measured_against_the_source() {
+        let source = ManualClock::new(1_000);
+        let mut clock = HlcClock::with_source(Arc::new(source.clone()));
+        let remote = Hlc::new(1_000 + MAX_DRIFT_MS + 1, 0);
+        assert!(matches!(
+            clock.receive(&remote),
+            Err(CoreError::HlcDriftTooLarge { delta_ms, .. }) if delta_ms == MAX_DRIFT_MS + 1
+        ));
+
+        source.advance(1);
+        assert_eq!(clock.receive(&remote).unwrap(), Hlc::new(remote.wall_ms(), 1));
+    }
+
+    #[test]
+    fn clamp_ahead_refuses_ticks_far_ahead_of_physical_time() {
+        let source = ManualClock::new(10_000_000);
+        let mut clock = HlcClock::with_source(Arc::new(source.clone()));
+        clock.set_max_drift_ms(1_000);
+        clock.tick().unwrap();
+
+        // System clock stepped back by an hour: unclamped ticks keep counting
+        source.set(10_000_000 - 3_600_000);
+        assert_eq!(clock.tick().unwrap(), Hlc::new(10_000_000, 1));
+
+        clock.set_clamp_ahead(true);
+        match clock.tick().unwrap_err() {
+            CoreError::HlcClockAhead { ahead_ms, max_ms } => {
+                assert_eq!(ahead_ms, 3_600_000);
+                assert_eq!(max_ms, 1_000);
+            }
+            other => panic!("expected HlcClockAhead, got {other:?}"),
+        }
+
+        // Within the limit again once physical time catches up
+        source.set(10_000_000 - 500);
+        assert_eq!(clock.tick().unwrap(), Hlc::new(10_000_000, 2));
+    }
+
+    #[test]
+    fn unbounded_drift_accepts_any_timestamp() {
+        let source = ManualClock::new(1_000);
+        let mut clock = HlcClock::with_source(Arc::new(source));
+        clock.set_max_drift_ms(u64::MAX);
+        clock.set_clamp_ahead(true);
+        let remote = Hlc::new(u64::MAX / 2, 0);
+        assert_eq!(clock.receive(&remote).unwrap(), Hlc::new(remote.wall_ms(), 1));
+        assert_eq!(clock.tick().unwrap(), Hlc::new(remote.wall_ms(), 2));
+    }
+
+    #[test]
+    fn max_counter_bounds_ticks_within_one_millisecond() {
+        let source = ManualClock::new(1_000);
+        let mut clock = HlcClock::with_source(Arc::new(source.clone()));
+        clock.set_max_counter(2);
+        for counter in 0..=2 {
+            assert_eq!(clock.tick().unwrap(), Hlc::new(1_000, counter));
+        }
+        assert!(matches!(clock.tick(), Err(CoreError::HlcCounterOverflow)));
+        assert!(matches!(clock.receive(&Hlc::new(1_000, 2)), Err(CoreError::HlcCounterOverflow)));
+
+        source.advance(1);
+        assert_eq!(clock.tick().unwrap(), Hlc::new(1_001, 0));
+    }
+
+    #[test]
+    fn restore_resumes_above_the_persisted_timestamp() {
+        let source = ManualClock::new(10_000_000);
+        let mut clock = HlcClock::with_source(Arc::new(source.clone()));
+        clock.set_max_drift_ms(1_000);
+
+        // Restarted after the system clock was set back an hour
+        let persisted = Hlc::new(10_000_000 + 3_600_000, 7);
+        match clock.restore(persisted).unwrap_err() {
+            CoreError::ClockRegression { behind_ms, max_ms } => assert_eq!((behind_ms, max_ms), (3_600_000, 1_000)),
+            other => panic!("expected ClockRegression, got {other:?}"),
+        }
+        assert_eq!(clock.tick().unwrap(), Hlc::new(persisted.wall_ms(), 8));
+
+        // An older timestamp doesn't move the clock back
+        clock.restore(Hlc::new(10_000_000, 0)).unwrap();
+        assert_eq!(clock.last(), Hlc::new(persisted.wall_ms(), 8));
+        source.set(persisted.wall_ms() + 1);
+        assert_eq!(clock.tick().unwrap(), Hlc::new(persisted.wall_ms() + 1, 0));
+    }
+
     #[test]
     fn concurrent_timestamp_merging() {
         let mut clock = HlcClock::new();
@@ -1,4 +1,5 @@
 use ed25519_dalek::{Signer, Verifier};
+use serde::{Deserialize, Serialize};
 
 use crate::error::CoreError;
 use crate::ids::{ActorId, Signature};
@@ -15,6 +16,14 @@ impl ActorIdentity {
         }
     }
 
+    /// Deterministic identity derived from a seed, so test runs reproduce exactly
+    /// (actor ordering, LWW tiebreaks). **Test-only**: the key is trivially guessable.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn from_seed(seed: u64) -> Self {
+        let secret = blake3::derive_key("openprod test identity v1", &seed.to_le_bytes());
+        Self::from_secret_bytes(&secret)
+    }
+
     pub fn from_secret_bytes(bytes: &[u8; 32]) -> Self {
         Self {
             signing_key: ed25519_dalek::SigningKey::from_bytes(bytes),
@@ -34,6 +43,53 @@ impl ActorIdentity {
         let sig = self.signing_key.sign(message);
         Signature::from_bytes(sig.to_bytes())
     }
+
+    /// Generate a successor key and a proof linking this key to it.
+    pub fn rotate(&self) -> (ActorIdentity, KeyRotation) {
+        let next = ActorIdentity::generate();
+        let message = KeyRotation::signing_bytes(&self.actor_id(), &next.actor_id());
+        let rotation = KeyRotation {
+            old_actor: self.actor_id(),
+            new_actor: next.actor_id(),
+            old_signature: self.sign(&message),
+            new_signature: next.sign(&message),
+        };
+        (next, rotation)
+    }
+}
+
+/// Continuity proof that `new_actor` succeeds `old_actor` as the same logical
+/// actor. Signed by both keys: the old one authorizes the hand-over, the new one
+/// proves possession.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    pub old_actor: ActorId,
+    pub new_actor: ActorId,
+    pub old_signature: Signature,
+    pub new_signature: Signature,
+}
+
+impl KeyRotation {
+    fn signing_bytes(old_actor: &ActorId, new_actor: &ActorId) -> Vec<u8> {
+        let mut bytes = b"openprod key rotation v1".to_vec();
+        bytes.extend_from_slice(old_actor.as_bytes());
+        bytes.extend_from_slice(new_actor.as_bytes());
+        bytes
+    }
+
+    pub fn verify(&self) -> Result<(), CoreError> {
+        let message = Self::signing_bytes(&self.old_actor, &self.new_actor);
+        verify_signature(&self.old_actor, &message, &self.old_signature)?;
+        verify_signature(&self.new_actor, &message, &self.new_signature)
+    }
+
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, CoreError> {
+        rmp_serde::to_vec_named(self).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, CoreError> {
+        rmp_serde::from_slice(bytes).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
 }
 
 pub fn verify_signature(
@@ -84,4 +140,22 @@ mod tests {
         let restored = ActorIdentity::from_secret_bytes(&bytes);
         assert_eq!(identity.actor_id(), restored.actor_id());
     }
+
+    #[test]
+    fn rotation_proof_verifies_only_unmodified() {
+        let identity = ActorIdentity::generate();
+        let (next, rotation) = identity.rotate();
+        assert_eq!(rotation.new_actor, next.actor_id());
+        assert!(rotation.verify().is_ok());
+
+        let mut hijacked = rotation.clone();
+        hijacked.new_actor = ActorIdentity::generate().actor_id();
+        assert!(hijacked.verify().is_err());
+    }
+
+    #[test]
+    fn from_seed_is_deterministic() {
+        assert_eq!(ActorIdentity::from_seed(7).actor_id(), ActorIdentity::from_seed(7).actor_id());
+        assert_ne!(ActorIdentity::from_seed(7).actor_id(), ActorIdentity::from_seed(8).actor_id());
+    }
 }
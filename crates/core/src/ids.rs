@@ -52,9 +52,28 @@ uuid_id!(TableId);
 uuid_id!(RuleId);
 uuid_id!(ConflictId);
 uuid_id!(OverlayId);
+uuid_id!(SessionId);
+uuid_id!(WebhookId);
+uuid_id!(ListItemId);
+
+impl OpId {
+    /// An id derived from the op itself rather than drawn at random: a hash
+    /// of its actor, bundle, position in the bundle and payload, shaped as a
+    /// version 8 UUID. The same op re-sent always carries the same id.
+    pub fn derive(actor_id: &ActorId, bundle_id: &BundleId, position: u32, payload_bytes: &[u8]) -> Self {
+        let mut hasher = blake3::Hasher::new_derive_key("openprod op id v1");
+        hasher.update(actor_id.as_bytes());
+        hasher.update(bundle_id.as_bytes());
+        hasher.update(&position.to_le_bytes());
+        hasher.update(payload_bytes);
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&hasher.finalize().as_bytes()[..16]);
+        Self(uuid::Builder::from_custom_bytes(bytes).into_uuid())
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
-pub struct ActorId([u8; 32]);
+pub struct ActorId(#[serde(with = "crate::json::hex_bytes")] [u8; 32]);
 
 impl ActorId {
     pub fn from_bytes(bytes: [u8; 32]) -> Self {
@@ -100,13 +119,21 @@ impl Signature {
 
 impl Serialize for Signature {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(&crate::json::to_hex(&self.0));
+        }
         serializer.serialize_bytes(&self.0)
     }
 }
 
 impl<'de> Deserialize<'de> for Signature {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        let bytes: Vec<u8> = Deserialize::deserialize(deserializer)?;
+        let bytes: Vec<u8> = if deserializer.is_human_readable() {
+            let hex = String::deserialize(deserializer)?;
+            crate::json::parse_hex(&hex).ok_or_else(|| serde::de::Error::custom(format!("invalid hex {hex:?}")))?
+        } else {
+            Deserialize::deserialize(deserializer)?
+        };
         let arr: [u8; 64] = bytes
             .try_into()
             .map_err(|v: Vec<u8>| serde::de::Error::invalid_length(v.len(), &"64 bytes"))?;
@@ -121,7 +148,7 @@ impl fmt::Debug for Signature {
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
-pub struct BlobHash([u8; 32]);
+pub struct BlobHash(#[serde(with = "crate::json::hex_bytes")] [u8; 32]);
 
 impl BlobHash {
     pub fn from_bytes(bytes: [u8; 32]) -> Self {
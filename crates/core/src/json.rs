@@ -0,0 +1,85 @@
+//! JSON forms of the core types, for logs, debugging tools and HTTP APIs.
+//!
+//! JSON goes through the same serde impls as msgpack; the types whose
+//! msgpack form is raw bytes switch to readable text when the format is
+//! human-readable: actor ids, hashes, signatures and opaque byte fields as
+//! lowercase hex, HLCs as `"<wall_ms>:<counter>"`, and uuid ids hyphenated.
+//! The msgpack encoding (and so every signature) is unchanged. Non-finite
+//! floats have no JSON form and fail to read back.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::CoreError;
+
+pub(crate) fn to_json<T: Serialize>(value: &T) -> Result<String, CoreError> {
+    serde_json::to_string(value).map_err(|e| CoreError::Serialization(e.to_string()))
+}
+
+pub(crate) fn from_json<T: DeserializeOwned>(json: &str) -> Result<T, CoreError> {
+    serde_json::from_str(json).map_err(|e| CoreError::Serialization(e.to_string()))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.is_ascii() || !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok()).collect()
+}
+
+/// `#[serde(with)]` for byte fields: hex in human-readable formats, as the
+/// field's own serde impl otherwise.
+pub(crate) mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<[u8]> + Serialize,
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&super::to_hex(bytes.as_ref()))
+        } else {
+            bytes.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: TryFrom<Vec<u8>> + Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        if !deserializer.is_human_readable() {
+            return T::deserialize(deserializer);
+        }
+        let hex = String::deserialize(deserializer)?;
+        let bytes = super::parse_hex(&hex).ok_or_else(|| D::Error::custom(format!("invalid hex {hex:?}")))?;
+        let len = bytes.len();
+        T::try_from(bytes).map_err(|_| D::Error::custom(format!("unexpected length {len}")))
+    }
+}
+
+/// `hex_bytes` for optional byte fields.
+pub(crate) mod hex_bytes_opt {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            bytes.as_deref().map(super::to_hex).serialize(serializer)
+        } else {
+            bytes.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        if !deserializer.is_human_readable() {
+            return Option::deserialize(deserializer);
+        }
+        Option::<String>::deserialize(deserializer)?
+            .map(|hex| super::parse_hex(&hex).ok_or_else(|| D::Error::custom(format!("invalid hex {hex:?}"))))
+            .transpose()
+    }
+}
@@ -1,12 +1,21 @@
+pub mod capabilities;
+pub mod digest;
 pub mod error;
 pub mod field_value;
 pub mod hlc;
 pub mod identity;
 pub mod ids;
+pub mod json;
+pub mod list;
 pub mod operations;
+pub mod presence;
+pub mod proto;
+pub mod sealed;
+pub mod sync_scope;
 pub mod vector_clock;
+pub mod wire;
 
 pub use error::CoreError;
-pub use field_value::FieldValue;
+pub use field_value::{FieldValue, ValueType};
 pub use hlc::Hlc;
 pub use ids::*;
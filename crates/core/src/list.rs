@@ -0,0 +1,121 @@
+//! Ordered list fields: the deltas carried by `ApplyCrdt` ops of type `List`.
+//!
+//! Each item sits at a position key; the list reads in key order (item id
+//! breaks ties between keys picked concurrently). Keys are strings of base-62
+//! digits that compare as fractions, so one can always be made between two
+//! others without renumbering the rest. A move rewrites one key, latest op
+//! wins; a removal is final.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CoreError;
+use crate::field_value::FieldValue;
+use crate::ids::ListItemId;
+
+/// Position-key digits, in ascending byte order.
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ListOp {
+    Insert { item_id: ListItemId, position: String, value: FieldValue },
+    Move { item_id: ListItemId, position: String },
+    Remove { item_id: ListItemId },
+}
+
+impl ListOp {
+    pub fn item_id(&self) -> ListItemId {
+        match self {
+            Self::Insert { item_id, .. } | Self::Move { item_id, .. } | Self::Remove { item_id } => *item_id,
+        }
+    }
+
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, CoreError> {
+        rmp_serde::to_vec_named(self).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, CoreError> {
+        rmp_serde::from_slice(bytes).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+}
+
+/// A position key strictly between `before` and `after` (None for either
+/// end of the list). Fails unless `before < after` and both are valid keys.
+pub fn position_between(before: Option<&str>, after: Option<&str>) -> Result<String, CoreError> {
+    for key in [before, after].into_iter().flatten() {
+        if !is_valid_position(key) {
+            return Err(CoreError::InvalidData(format!("invalid list position {key:?}")));
+        }
+    }
+    let before = before.unwrap_or("");
+    if let Some(after) = after
+        && before >= after
+    {
+        return Err(CoreError::InvalidData(format!("list position {before:?} is not before {after:?}")));
+    }
+    Ok(midpoint(before.as_bytes(), after.map(str::as_bytes)))
+}
+
+/// Non-empty base-62 digits, not ending in the lowest digit (which would
+/// leave no room below it).
+pub fn is_valid_position(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|b| DIGITS.contains(&b)) && !key.ends_with(DIGITS[0] as char)
+}
+
+fn midpoint(before: &[u8], after: Option<&[u8]>) -> String {
+    if let Some(after) = after {
+        // Share the common prefix, reading a missing digit of `before` as zero
+        let shared = (0..after.len()).take_while(|&i| before.get(i).copied().unwrap_or(DIGITS[0]) == after[i]).count();
+        if shared > 0 {
+            let rest = midpoint(before.get(shared..).unwrap_or(&[]), Some(&after[shared..]));
+            return String::from_utf8_lossy(&after[..shared]).into_owned() + &rest;
+        }
+    }
+    let low = before.first().map_or(0, |&b| digit(b));
+    let high = after.map_or(DIGITS.len(), |after| digit(after[0]));
+    if high - low > 1 {
+        return (DIGITS[(low + high).div_ceil(2)] as char).to_string();
+    }
+    match after {
+        Some(after) if after.len() > 1 => (after[0] as char).to_string(),
+        _ => (DIGITS[low] as char).to_string() + &midpoint(before.get(1..).unwrap_or(&[]), None),
+    }
+}
+
+fn digit(byte: u8) -> usize {
+    DIGITS.iter().position(|&d| d == byte).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_fit_between_any_two_keys() {
+        let first = position_between(None, None).unwrap();
+        let mut keys = vec![first.clone(), position_between(Some(&first), None).unwrap()];
+        // Keep inserting at the front, the back, and just after the first key
+        for _ in 0..200 {
+            let front = position_between(None, Some(&keys[0])).unwrap();
+            let back = position_between(Some(keys.last().unwrap()), None).unwrap();
+            let next = keys.iter().find(|k| **k > first).unwrap().clone();
+            let after_first = position_between(Some(&first), Some(&next)).unwrap();
+            assert!(front < keys[0] && back > *keys.last().unwrap() && first < after_first && after_first < next);
+            keys.extend([front, back, after_first]);
+            keys.sort();
+            assert!(keys.iter().all(|k| is_valid_position(k)));
+        }
+        keys.dedup();
+        assert_eq!(keys.len(), 602);
+
+        assert!(position_between(Some(&first), Some(&first)).is_err());
+        assert!(position_between(Some("V0"), None).is_err());
+        assert!(position_between(Some("-"), None).is_err());
+    }
+
+    #[test]
+    fn list_op_msgpack_roundtrip() {
+        let op = ListOp::Insert { item_id: ListItemId::new(), position: "V".into(), value: FieldValue::Text("milk".into()) };
+        let bytes = op.to_msgpack().unwrap();
+        assert_eq!(ListOp::from_msgpack(&bytes).unwrap(), op);
+    }
+}
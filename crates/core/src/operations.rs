@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use crate::error::CoreError;
 use crate::field_value::FieldValue;
@@ -8,10 +8,12 @@ use crate::identity::{verify_signature, ActorIdentity};
 use crate::ids::*;
 use crate::vector_clock::VectorClock;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum CrdtType {
     Text,
     List,
+    /// Automerge changes to a rich-text document.
+    RichText,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -50,6 +52,7 @@ pub enum OperationPayload {
         entity_id: EntityId,
         field_key: String,
         crdt_type: CrdtType,
+        #[serde(with = "crate::json::hex_bytes")]
         delta: Vec<u8>,
     },
     ClearAndAdd {
@@ -130,6 +133,7 @@ pub enum OperationPayload {
         name: String,
         when_clause: String,
         action_type: String,
+        #[serde(with = "crate::json::hex_bytes")]
         action_params: Vec<u8>,
         auto_accept: bool,
     },
@@ -181,6 +185,35 @@ impl OperationPayload {
         }
     }
 
+    /// Edges this op creates, changes or deletes, including those a
+    /// `DeleteEntity` cascades to.
+    pub fn edge_ids(&self) -> Vec<EdgeId> {
+        match self {
+            Self::CreateEdge { edge_id, .. }
+            | Self::DeleteEdge { edge_id }
+            | Self::SetEdgeProperty { edge_id, .. }
+            | Self::ClearEdgeProperty { edge_id, .. }
+            | Self::CreateOrderedEdge { edge_id, .. }
+            | Self::MoveOrderedEdge { edge_id, .. }
+            | Self::RestoreEdge { edge_id } => vec![*edge_id],
+            Self::DeleteEntity { cascade_edges, .. } => cascade_edges.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Edge whose properties this op writes: set or cleared, or given
+    /// initial values when the edge is created. Indexed by storage so an
+    /// edge's property history can be read without scanning the oplog.
+    pub fn property_edge_id(&self) -> Option<EdgeId> {
+        match self {
+            Self::CreateEdge { edge_id, .. }
+            | Self::CreateOrderedEdge { edge_id, .. }
+            | Self::SetEdgeProperty { edge_id, .. }
+            | Self::ClearEdgeProperty { edge_id, .. } => Some(*edge_id),
+            _ => None,
+        }
+    }
+
     /// String name of the operation type for storage/indexing.
     pub fn op_type_name(&self) -> &'static str {
         match self {
@@ -220,6 +253,16 @@ impl OperationPayload {
     pub fn from_msgpack(bytes: &[u8]) -> Result<Self, CoreError> {
         rmp_serde::from_slice(bytes).map_err(|e| CoreError::Serialization(e.to_string()))
     }
+
+    /// JSON, e.g. `{"SetField":{"entity_id":"...","field_key":"title",...}}`;
+    /// see `json`.
+    pub fn to_json(&self) -> Result<String, CoreError> {
+        crate::json::to_json(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, CoreError> {
+        crate::json::from_json(json)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -259,11 +302,37 @@ impl Operation {
         module_versions: BTreeMap<String, String>,
         payload: OperationPayload,
     ) -> Result<Self, CoreError> {
-        let op_id = OpId::new();
-        let actor_id = identity.actor_id();
         let payload_bytes = payload.to_msgpack()?;
+        Self::sign(OpId::new(), identity, hlc, bundle_id, module_versions, payload, &payload_bytes)
+    }
+
+    /// `new_signed`, with the id derived by `OpId::derive` from the op's
+    /// `position` in its bundle instead of drawn at random.
+    pub fn new_signed_derived(
+        identity: &ActorIdentity,
+        hlc: Hlc,
+        bundle_id: BundleId,
+        position: u32,
+        module_versions: BTreeMap<String, String>,
+        payload: OperationPayload,
+    ) -> Result<Self, CoreError> {
+        let payload_bytes = payload.to_msgpack()?;
+        let op_id = OpId::derive(&identity.actor_id(), &bundle_id, position, &payload_bytes);
+        Self::sign(op_id, identity, hlc, bundle_id, module_versions, payload, &payload_bytes)
+    }
+
+    fn sign(
+        op_id: OpId,
+        identity: &ActorIdentity,
+        hlc: Hlc,
+        bundle_id: BundleId,
+        module_versions: BTreeMap<String, String>,
+        payload: OperationPayload,
+        payload_bytes: &[u8],
+    ) -> Result<Self, CoreError> {
+        let actor_id = identity.actor_id();
         let signing_bytes =
-            Self::signing_bytes(&op_id, &actor_id, &hlc, &module_versions, &payload_bytes)?;
+            Self::signing_bytes(&op_id, &actor_id, &hlc, &module_versions, payload_bytes)?;
         let signature = identity.sign(&signing_bytes);
 
         Ok(Self {
@@ -277,6 +346,8 @@ impl Operation {
         })
     }
 
+    /// Verify the signature against `actor_id`, whether it was made over the
+    /// msgpack signing bytes or, by a non-Rust client, over `proto`'s.
     pub fn verify_signature(&self) -> Result<(), CoreError> {
         let payload_bytes = self.payload.to_msgpack()?;
         let signing_bytes = Self::signing_bytes(
@@ -286,7 +357,19 @@ impl Operation {
             &self.module_versions,
             &payload_bytes,
         )?;
-        verify_signature(&self.actor_id, &signing_bytes, &self.signature)
+        verify_signature(&self.actor_id, &signing_bytes, &self.signature).or_else(|_| {
+            verify_signature(&self.actor_id, &crate::proto::operation_signing_bytes(self), &self.signature)
+        })
+    }
+
+    /// JSON, with the payload as `OperationPayload::to_json` writes it.
+    /// Reading it back doesn't verify the signature.
+    pub fn to_json(&self) -> Result<String, CoreError> {
+        crate::json::to_json(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, CoreError> {
+        crate::json::from_json(json)
     }
 }
 
@@ -319,15 +402,39 @@ pub struct Bundle {
     pub hlc: Hlc,
     pub bundle_type: BundleType,
     pub op_count: u32,
+    #[serde(with = "crate::json::hex_bytes")]
     pub checksum: [u8; 32],
     pub creates: Vec<EntityId>,
     pub deletes: Vec<EntityId>,
+    #[serde(with = "crate::json::hex_bytes_opt")]
     pub meta: Option<Vec<u8>>,
     pub signature: Signature,
     pub creator_vc: Option<VectorClock>,
 }
 
 impl Bundle {
+    fn signing_bytes(
+        bundle_id: &BundleId,
+        actor_id: &ActorId,
+        hlc: &Hlc,
+        bundle_type: BundleType,
+        op_count: u32,
+        checksum: &[u8; 32],
+        creator_vc: &Option<VectorClock>,
+    ) -> Result<Vec<u8>, CoreError> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(bundle_id.as_bytes());
+        bytes.extend_from_slice(actor_id.as_bytes());
+        bytes.extend_from_slice(&hlc.to_bytes());
+        bytes.push(bundle_type as u8);
+        bytes.extend_from_slice(&op_count.to_be_bytes());
+        bytes.extend_from_slice(checksum);
+        let vc_bytes = rmp_serde::to_vec(creator_vc)
+            .map_err(|e| CoreError::Serialization(e.to_string()))?;
+        bytes.extend_from_slice(&vc_bytes);
+        Ok(bytes)
+    }
+
     pub fn new_signed(
         bundle_id: BundleId,
         identity: &ActorIdentity,
@@ -339,12 +446,7 @@ impl Bundle {
         let actor_id = identity.actor_id();
         let op_count = operations.len() as u32;
 
-        let mut hasher = blake3::Hasher::new();
-        for op in operations {
-            let bytes = op.payload.to_msgpack()?;
-            hasher.update(&bytes);
-        }
-        let checksum = *hasher.finalize().as_bytes();
+        let checksum = Self::msgpack_checksum(operations)?;
 
         let mut creates = Vec::new();
         let mut deletes = Vec::new();
@@ -356,16 +458,15 @@ impl Bundle {
             }
         }
 
-        let mut sign_bytes = Vec::new();
-        sign_bytes.extend_from_slice(bundle_id.as_bytes());
-        sign_bytes.extend_from_slice(actor_id.as_bytes());
-        sign_bytes.extend_from_slice(&hlc.to_bytes());
-        sign_bytes.push(bundle_type as u8);
-        sign_bytes.extend_from_slice(&op_count.to_be_bytes());
-        sign_bytes.extend_from_slice(&checksum);
-        let vc_bytes = rmp_serde::to_vec(&creator_vc)
-            .map_err(|e| CoreError::Serialization(e.to_string()))?;
-        sign_bytes.extend_from_slice(&vc_bytes);
+        let sign_bytes = Self::signing_bytes(
+            &bundle_id,
+            &actor_id,
+            &hlc,
+            bundle_type,
+            op_count,
+            &checksum,
+            &creator_vc,
+        )?;
         let signature = identity.sign(&sign_bytes);
 
         Ok(Self {
@@ -382,4 +483,215 @@ impl Bundle {
             creator_vc,
         })
     }
+
+    fn msgpack_checksum(operations: &[Operation]) -> Result<[u8; 32], CoreError> {
+        let mut hasher = blake3::Hasher::new();
+        for op in operations {
+            let bytes = op.payload.to_msgpack()?;
+            hasher.update(&bytes);
+        }
+        Ok(*hasher.finalize().as_bytes())
+    }
+
+    /// Verify the bundle header signature against `actor_id`, made over the
+    /// msgpack signing bytes or `proto`'s. Does not check the operations
+    /// themselves; see `Operation::verify_signature`.
+    pub fn verify_signature(&self) -> Result<(), CoreError> {
+        let signing_bytes = Self::signing_bytes(
+            &self.bundle_id,
+            &self.actor_id,
+            &self.hlc,
+            self.bundle_type,
+            self.op_count,
+            &self.checksum,
+            &self.creator_vc,
+        )?;
+        verify_signature(&self.actor_id, &signing_bytes, &self.signature).or_else(|_| {
+            verify_signature(&self.actor_id, &crate::proto::bundle_signing_bytes(self), &self.signature)
+        })
+    }
+
+    /// Whether `checksum` covers these operations' payloads, in order, hashed
+    /// as msgpack or (for a bundle built from `proto` messages) as protobuf.
+    pub fn checksum_matches(&self, operations: &[Operation]) -> Result<bool, CoreError> {
+        Ok(Self::msgpack_checksum(operations)? == self.checksum
+            || crate::proto::payload_checksum(operations) == self.checksum)
+    }
+
+    /// Check the bundle against its operations: the header signature,
+    /// `op_count`, that each operation belongs to the bundle and is signed,
+    /// and the checksum over their payloads. Fails with `InvalidBundle` saying
+    /// which check failed, so tampering or corruption anywhere in the bundle
+    /// is caught.
+    pub fn verify(&self, operations: &[Operation]) -> Result<(), CoreError> {
+        let invalid = |reason: String| CoreError::InvalidBundle { bundle_id: self.bundle_id, reason };
+
+        self.verify_signature().map_err(|_| invalid("bad signature".into()))?;
+        if operations.len() != self.op_count as usize {
+            return Err(invalid(format!("expected {} ops, found {}", self.op_count, operations.len())));
+        }
+        self.verify_operations(operations)?;
+        if !self.checksum_matches(operations)? {
+            return Err(invalid("checksum mismatch".into()));
+        }
+        Ok(())
+    }
+
+    /// `verify` for a bundle trimmed to a `SyncScope` by its sender: the
+    /// header and every operation present must verify, but some may have been
+    /// left out, so `op_count` is only an upper bound and the checksum (over
+    /// all of them) can't be checked.
+    pub fn verify_partial(&self, operations: &[Operation]) -> Result<(), CoreError> {
+        let invalid = |reason: String| CoreError::InvalidBundle { bundle_id: self.bundle_id, reason };
+
+        self.verify_signature().map_err(|_| invalid("bad signature".into()))?;
+        if operations.len() > self.op_count as usize {
+            return Err(invalid(format!("expected at most {} ops, found {}", self.op_count, operations.len())));
+        }
+        self.verify_operations(operations)
+    }
+
+    /// Each operation belongs to the bundle and is signed.
+    fn verify_operations(&self, operations: &[Operation]) -> Result<(), CoreError> {
+        let invalid = |reason: String| CoreError::InvalidBundle { bundle_id: self.bundle_id, reason };
+        for op in operations {
+            if op.bundle_id != self.bundle_id || op.actor_id != self.actor_id {
+                return Err(invalid(format!("operation {} belongs to another bundle", op.op_id)));
+            }
+            op.verify_signature().map_err(|_| invalid(format!("operation {}: bad signature", op.op_id)))?;
+        }
+        Ok(())
+    }
+
+    /// Decode `meta` as structured `BundleMeta` (None if the bundle carries no meta).
+    pub fn decode_meta(&self) -> Result<Option<BundleMeta>, CoreError> {
+        self.meta.as_deref().map(BundleMeta::from_msgpack).transpose()
+    }
+    /// JSON, with `meta` left as its msgpack bytes in hex. Reading it back
+    /// doesn't verify the signature.
+    pub fn to_json(&self) -> Result<String, CoreError> {
+        crate::json::to_json(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, CoreError> {
+        crate::json::from_json(json)
+    }
+}
+
+/// A bundle as it travels between peers: the msgpack-encoded header and each
+/// operation encoded on its own, so one operation that can't be decoded (e.g.
+/// a payload type from a newer build) doesn't make the rest of a sync unreadable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawBundle {
+    pub bundle: Vec<u8>,
+    pub operations: Vec<Vec<u8>>,
+}
+
+impl RawBundle {
+    pub fn encode(bundle: &Bundle, operations: &[Operation]) -> Result<Self, CoreError> {
+        let error = |e: rmp_serde::encode::Error| CoreError::Serialization(e.to_string());
+        Ok(Self {
+            bundle: rmp_serde::to_vec(bundle).map_err(error)?,
+            operations: operations.iter().map(|op| rmp_serde::to_vec(op).map_err(error)).collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Decode just the header, e.g. to identify a bundle whose operations don't decode.
+    pub fn decode_header(&self) -> Result<Bundle, CoreError> {
+        rmp_serde::from_slice(&self.bundle).map_err(|e| CoreError::Serialization(format!("bundle header: {e}")))
+    }
+
+    /// Decode the header and every operation. Does not verify signatures.
+    pub fn decode(&self) -> Result<(Bundle, Vec<Operation>), CoreError> {
+        let bundle = self.decode_header()?;
+        let operations = self
+            .operations
+            .iter()
+            .enumerate()
+            .map(|(i, bytes)| {
+                rmp_serde::from_slice(bytes).map_err(|e| CoreError::Serialization(format!("operation {i}: {e}")))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((bundle, operations))
+    }
+
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, CoreError> {
+        rmp_serde::to_vec(self).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, CoreError> {
+        rmp_serde::from_slice(bytes).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+}
+
+/// Structured bundle metadata, carried msgpack-encoded in `Bundle.meta`.
+///
+/// Meta is informational and not covered by the bundle signature. Encoded with
+/// named fields so new fields can be added without breaking older meta.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BundleMeta {
+    /// Human-facing label for history views (e.g. "Import").
+    pub label: Option<String>,
+    /// Longer explanation of the edit (e.g. "Imported 40 rows from cues.csv").
+    pub description: Option<String>,
+    /// The device the edit was made on, as the app names it.
+    pub origin_device: Option<String>,
+    /// Version of the app that made the edit.
+    pub app_version: Option<String>,
+    /// Indexed by storage so history views can find edits by kind
+    /// (e.g. "import", "bulk-rename").
+    pub tags: BTreeSet<String>,
+    /// Editing session the bundle was written in (see `Engine::begin_session`);
+    /// indexed by storage so a session's bundles can be listed together.
+    pub session: Option<SessionId>,
+    /// Key the client issued the command under (see `Engine::idempotent`);
+    /// indexed by storage with the bundle's author so a retried command finds
+    /// the bundle it already wrote.
+    pub idempotency_key: Option<String>,
+    /// Free-form annotations, e.g. audit data added by an engine interceptor.
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl BundleMeta {
+    pub fn with_label(label: impl Into<String>) -> Self {
+        Self {
+            label: Some(label.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.insert(tag.into());
+        self
+    }
+
+    /// Fill whatever this meta leaves unset from `defaults`; tags and
+    /// attributes are merged, with this meta's attributes winning.
+    pub fn or_defaults(mut self, defaults: &BundleMeta) -> Self {
+        self.label = self.label.or_else(|| defaults.label.clone());
+        self.description = self.description.or_else(|| defaults.description.clone());
+        self.origin_device = self.origin_device.or_else(|| defaults.origin_device.clone());
+        self.app_version = self.app_version.or_else(|| defaults.app_version.clone());
+        self.session = self.session.or(defaults.session);
+        self.idempotency_key = self.idempotency_key.or_else(|| defaults.idempotency_key.clone());
+        self.tags.extend(defaults.tags.iter().cloned());
+        for (key, value) in &defaults.attributes {
+            self.attributes.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        self
+    }
+
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, CoreError> {
+        rmp_serde::to_vec_named(self).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, CoreError> {
+        rmp_serde::from_slice(bytes).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
 }
@@ -0,0 +1,80 @@
+//! Awareness messages: who is online, which entity they have selected, and
+//! where their cursor is. Ephemeral by design: never signed, stored or put in
+//! the oplog, so a transport can send them as often as it likes.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CoreError;
+use crate::ids::{ActorId, EntityId};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OnlineStatus {
+    #[default]
+    Online,
+    /// Connected but idle.
+    Away,
+    /// Signing off; receivers forget the actor straight away.
+    Offline,
+}
+
+/// A text cursor inside one field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CursorPosition {
+    pub entity_id: EntityId,
+    pub field_key: String,
+    /// Character offset of the caret.
+    pub offset: u32,
+    /// Other end of the selection, if text is selected.
+    pub anchor: Option<u32>,
+}
+
+/// Everything one actor shares about itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceState {
+    pub status: OnlineStatus,
+    pub selected_entity: Option<EntityId>,
+    pub cursor: Option<CursorPosition>,
+}
+
+/// An actor's current `PresenceState`, as sent between peers. Each message
+/// replaces the last, so there are no partial updates to merge.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceMessage {
+    pub actor_id: ActorId,
+    /// Increases with every message the actor sends; receivers drop any no
+    /// newer than the last they applied, so relayed copies can't loop.
+    pub seq: u64,
+    pub state: PresenceState,
+}
+
+impl PresenceMessage {
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, CoreError> {
+        rmp_serde::to_vec_named(self).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, CoreError> {
+        rmp_serde::from_slice(bytes).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presence_message_msgpack_roundtrip() {
+        let entity_id = EntityId::new();
+        let message = PresenceMessage {
+            actor_id: ActorId::from_bytes([7; 32]),
+            seq: 3,
+            state: PresenceState {
+                status: OnlineStatus::Away,
+                selected_entity: Some(entity_id),
+                cursor: Some(CursorPosition { entity_id, field_key: "notes".into(), offset: 4, anchor: Some(9) }),
+            },
+        };
+        let bytes = message.to_msgpack().unwrap();
+        assert_eq!(PresenceMessage::from_msgpack(&bytes).unwrap(), message);
+        assert!(PresenceMessage::from_msgpack(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
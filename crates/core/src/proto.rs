@@ -0,0 +1,1176 @@
+//! Messages for `proto/wire.proto` (package `openprod.wire.v1`): bundles and
+//! operations in a form non-Rust clients can produce with any protobuf
+//! library, and converters to and from the core types. Field tags here must
+//! match the `.proto` file.
+//!
+//! A bundle built this way is signed over the protobuf encoding rather than
+//! msgpack (see the `.proto` file for the exact bytes);
+//! `Operation::verify_signature`, `Bundle::verify_signature` and
+//! `Bundle::checksum_matches` accept either, so once decoded it ingests and
+//! syncs like any other.
+
+use std::collections::BTreeMap;
+
+use prost::{Enumeration, Message, Oneof};
+
+use crate::error::CoreError;
+use crate::field_value::FieldValue;
+use crate::ids::*;
+use crate::operations::{self, OperationPayload};
+use crate::{hlc, vector_clock};
+
+/// Signed ahead of an encoded `OperationSigningInput`.
+pub const OPERATION_SIGNING_CONTEXT: &[u8] = b"openprod.wire.v1.OperationSigningInput";
+
+/// Signed ahead of an encoded `BundleSigningInput`.
+pub const BUNDLE_SIGNING_CONTEXT: &[u8] = b"openprod.wire.v1.BundleSigningInput";
+
+/// Encode a bundle and its operations as a `SignedBundle`.
+pub fn encode_bundle(bundle: &operations::Bundle, operations: &[operations::Operation]) -> Vec<u8> {
+    SignedBundle { bundle: Some(bundle.into()), operations: operations.iter().map(Operation::from).collect() }
+        .encode_to_vec()
+}
+
+/// Decode a `SignedBundle`, e.g. one a non-Rust client built, for
+/// `Engine::ingest_bundle`. Doesn't verify signatures.
+pub fn decode_bundle(bytes: &[u8]) -> Result<(operations::Bundle, Vec<operations::Operation>), CoreError> {
+    let message = SignedBundle::decode(bytes).map_err(|e| CoreError::Serialization(e.to_string()))?;
+    let bundle = required(message.bundle, "bundle")?.try_into()?;
+    let operations = message
+        .operations
+        .into_iter()
+        .enumerate()
+        .map(|(i, op)| op.try_into().map_err(|e: CoreError| invalid(format!("operation {i}: {e}"))))
+        .collect::<Result<_, _>>()?;
+    Ok((bundle, operations))
+}
+
+/// What an operation signed over the protobuf encoding is signed over.
+pub(crate) fn operation_signing_bytes(op: &operations::Operation) -> Vec<u8> {
+    let input = OperationSigningInput {
+        op_id: op.op_id.as_bytes().to_vec(),
+        actor_id: op.actor_id.as_bytes().to_vec(),
+        hlc: Some(op.hlc.into()),
+        module_versions: module_versions(&op.module_versions),
+        payload: Some((&op.payload).into()),
+    };
+    [OPERATION_SIGNING_CONTEXT, &input.encode_to_vec()].concat()
+}
+
+/// What a bundle signed over the protobuf encoding is signed over.
+pub(crate) fn bundle_signing_bytes(bundle: &operations::Bundle) -> Vec<u8> {
+    let input = BundleSigningInput {
+        bundle_id: bundle.bundle_id.as_bytes().to_vec(),
+        actor_id: bundle.actor_id.as_bytes().to_vec(),
+        hlc: Some(bundle.hlc.into()),
+        bundle_type: BundleType::from(bundle.bundle_type) as i32,
+        op_count: bundle.op_count,
+        checksum: bundle.checksum.to_vec(),
+        creator_vc: bundle.creator_vc.as_ref().map(VectorClock::from),
+    };
+    [BUNDLE_SIGNING_CONTEXT, &input.encode_to_vec()].concat()
+}
+
+/// Bundle checksum over the protobuf encoding: each payload length-delimited.
+pub(crate) fn payload_checksum(operations: &[operations::Operation]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    for op in operations {
+        hasher.update(&Payload::from(&op.payload).encode_length_delimited_to_vec());
+    }
+    *hasher.finalize().as_bytes()
+}
+
+// ============================================================================
+// Messages
+// ============================================================================
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SignedBundle {
+    #[prost(message, optional, tag = "1")]
+    pub bundle: Option<Bundle>,
+    #[prost(message, repeated, tag = "2")]
+    pub operations: Vec<Operation>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Bundle {
+    #[prost(bytes, tag = "1")]
+    pub bundle_id: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub actor_id: Vec<u8>,
+    #[prost(message, optional, tag = "3")]
+    pub hlc: Option<Hlc>,
+    #[prost(enumeration = "BundleType", tag = "4")]
+    pub bundle_type: i32,
+    #[prost(uint32, tag = "5")]
+    pub op_count: u32,
+    #[prost(bytes, tag = "6")]
+    pub checksum: Vec<u8>,
+    #[prost(bytes, repeated, tag = "7")]
+    pub creates: Vec<Vec<u8>>,
+    #[prost(bytes, repeated, tag = "8")]
+    pub deletes: Vec<Vec<u8>>,
+    #[prost(bytes, optional, tag = "9")]
+    pub meta: Option<Vec<u8>>,
+    #[prost(bytes, tag = "10")]
+    pub signature: Vec<u8>,
+    #[prost(message, optional, tag = "11")]
+    pub creator_vc: Option<VectorClock>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Operation {
+    #[prost(bytes, tag = "1")]
+    pub op_id: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub actor_id: Vec<u8>,
+    #[prost(message, optional, tag = "3")]
+    pub hlc: Option<Hlc>,
+    #[prost(bytes, tag = "4")]
+    pub bundle_id: Vec<u8>,
+    #[prost(message, repeated, tag = "5")]
+    pub module_versions: Vec<ModuleVersion>,
+    #[prost(message, optional, tag = "6")]
+    pub payload: Option<Payload>,
+    #[prost(bytes, tag = "7")]
+    pub signature: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct OperationSigningInput {
+    #[prost(bytes, tag = "1")]
+    pub op_id: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub actor_id: Vec<u8>,
+    #[prost(message, optional, tag = "3")]
+    pub hlc: Option<Hlc>,
+    #[prost(message, repeated, tag = "4")]
+    pub module_versions: Vec<ModuleVersion>,
+    #[prost(message, optional, tag = "5")]
+    pub payload: Option<Payload>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BundleSigningInput {
+    #[prost(bytes, tag = "1")]
+    pub bundle_id: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub actor_id: Vec<u8>,
+    #[prost(message, optional, tag = "3")]
+    pub hlc: Option<Hlc>,
+    #[prost(enumeration = "BundleType", tag = "4")]
+    pub bundle_type: i32,
+    #[prost(uint32, tag = "5")]
+    pub op_count: u32,
+    #[prost(bytes, tag = "6")]
+    pub checksum: Vec<u8>,
+    #[prost(message, optional, tag = "7")]
+    pub creator_vc: Option<VectorClock>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Hlc {
+    #[prost(uint64, tag = "1")]
+    pub wall_ms: u64,
+    #[prost(uint32, tag = "2")]
+    pub counter: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ModuleVersion {
+    #[prost(string, tag = "1")]
+    pub module: String,
+    #[prost(string, tag = "2")]
+    pub version: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct VectorClock {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<VectorClockEntry>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct VectorClockEntry {
+    #[prost(bytes, tag = "1")]
+    pub actor_id: Vec<u8>,
+    #[prost(message, optional, tag = "2")]
+    pub hlc: Option<Hlc>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Field {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CreateEntity {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, optional, tag = "2")]
+    pub initial_table: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct DeleteEntity {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(bytes, repeated, tag = "2")]
+    pub cascade_edges: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AttachFacet {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub facet_type: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct DetachFacet {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub facet_type: String,
+    #[prost(bool, tag = "3")]
+    pub preserve_values: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RestoreFacet {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub facet_type: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SetField {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub field_key: String,
+    #[prost(message, optional, tag = "3")]
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ClearField {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub field_key: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ApplyCrdt {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub field_key: String,
+    #[prost(enumeration = "CrdtType", tag = "3")]
+    pub crdt_type: i32,
+    #[prost(bytes, tag = "4")]
+    pub delta: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ClearAndAdd {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub field_key: String,
+    #[prost(message, repeated, tag = "3")]
+    pub values: Vec<Value>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CreateEdge {
+    #[prost(bytes, tag = "1")]
+    pub edge_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub edge_type: String,
+    #[prost(bytes, tag = "3")]
+    pub source_id: Vec<u8>,
+    #[prost(bytes, tag = "4")]
+    pub target_id: Vec<u8>,
+    #[prost(message, repeated, tag = "5")]
+    pub properties: Vec<Field>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct DeleteEdge {
+    #[prost(bytes, tag = "1")]
+    pub edge_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SetEdgeProperty {
+    #[prost(bytes, tag = "1")]
+    pub edge_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub property_key: String,
+    #[prost(message, optional, tag = "3")]
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ClearEdgeProperty {
+    #[prost(bytes, tag = "1")]
+    pub edge_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub property_key: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CreateOrderedEdge {
+    #[prost(bytes, tag = "1")]
+    pub edge_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub edge_type: String,
+    #[prost(bytes, tag = "3")]
+    pub source_id: Vec<u8>,
+    #[prost(bytes, tag = "4")]
+    pub target_id: Vec<u8>,
+    #[prost(bytes, optional, tag = "5")]
+    pub after: Option<Vec<u8>>,
+    #[prost(bytes, optional, tag = "6")]
+    pub before: Option<Vec<u8>>,
+    #[prost(message, repeated, tag = "7")]
+    pub properties: Vec<Field>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MoveOrderedEdge {
+    #[prost(bytes, tag = "1")]
+    pub edge_id: Vec<u8>,
+    #[prost(bytes, optional, tag = "2")]
+    pub after: Option<Vec<u8>>,
+    #[prost(bytes, optional, tag = "3")]
+    pub before: Option<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LinkTables {
+    #[prost(bytes, tag = "1")]
+    pub source_table: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub target_table: Vec<u8>,
+    #[prost(message, repeated, tag = "3")]
+    pub field_mappings: Vec<FieldMapping>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FieldMapping {
+    #[prost(string, tag = "1")]
+    pub source_field: String,
+    #[prost(string, tag = "2")]
+    pub target_field: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct UnlinkTables {
+    #[prost(bytes, tag = "1")]
+    pub source_table: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub target_table: Vec<u8>,
+    #[prost(string, tag = "3")]
+    pub data_handling: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AddToTable {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub table: String,
+    #[prost(message, repeated, tag = "3")]
+    pub defaults: Vec<Field>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RemoveFromTable {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub table: String,
+    #[prost(string, tag = "3")]
+    pub data_handling: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ConfirmFieldMapping {
+    #[prost(bytes, tag = "1")]
+    pub source_table: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub target_table: Vec<u8>,
+    #[prost(string, tag = "3")]
+    pub source_field: String,
+    #[prost(string, tag = "4")]
+    pub target_field: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MergeEntities {
+    #[prost(bytes, tag = "1")]
+    pub survivor: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub absorbed: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SplitEntity {
+    #[prost(bytes, tag = "1")]
+    pub source: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub new_entity: Vec<u8>,
+    #[prost(string, repeated, tag = "3")]
+    pub facets: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CreateRule {
+    #[prost(bytes, tag = "1")]
+    pub rule_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, tag = "3")]
+    pub when_clause: String,
+    #[prost(string, tag = "4")]
+    pub action_type: String,
+    #[prost(bytes, tag = "5")]
+    pub action_params: Vec<u8>,
+    #[prost(bool, tag = "6")]
+    pub auto_accept: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RestoreEntity {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RestoreEdge {
+    #[prost(bytes, tag = "1")]
+    pub edge_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ResolveConflict {
+    #[prost(bytes, tag = "1")]
+    pub conflict_id: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "3")]
+    pub field_key: String,
+    #[prost(message, optional, tag = "4")]
+    pub chosen_value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Value {
+    #[prost(oneof = "value::Kind", tags = "1, 2, 3, 4, 5, 6, 7, 8, 9")]
+    pub kind: Option<value::Kind>,
+}
+
+pub mod value {
+    use super::Oneof;
+
+    #[derive(Clone, PartialEq, Oneof)]
+    pub enum Kind {
+        #[prost(bool, tag = "1")]
+        Null(bool),
+        #[prost(string, tag = "2")]
+        Text(String),
+        #[prost(int64, tag = "3")]
+        Integer(i64),
+        #[prost(double, tag = "4")]
+        Float(f64),
+        #[prost(bool, tag = "5")]
+        Boolean(bool),
+        #[prost(int64, tag = "6")]
+        Timestamp(i64),
+        #[prost(bytes, tag = "7")]
+        EntityRef(Vec<u8>),
+        #[prost(bytes, tag = "8")]
+        BlobRef(Vec<u8>),
+        #[prost(bytes, tag = "9")]
+        Bytes(Vec<u8>),
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Payload {
+    #[prost(
+        oneof = "payload::Kind",
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26"
+    )]
+    pub kind: Option<payload::Kind>,
+}
+
+pub mod payload {
+    use super::Oneof;
+
+    #[derive(Clone, PartialEq, Oneof)]
+    pub enum Kind {
+        #[prost(message, tag = "1")]
+        CreateEntity(super::CreateEntity),
+        #[prost(message, tag = "2")]
+        DeleteEntity(super::DeleteEntity),
+        #[prost(message, tag = "3")]
+        AttachFacet(super::AttachFacet),
+        #[prost(message, tag = "4")]
+        DetachFacet(super::DetachFacet),
+        #[prost(message, tag = "5")]
+        RestoreFacet(super::RestoreFacet),
+        #[prost(message, tag = "6")]
+        SetField(super::SetField),
+        #[prost(message, tag = "7")]
+        ClearField(super::ClearField),
+        #[prost(message, tag = "8")]
+        ApplyCrdt(super::ApplyCrdt),
+        #[prost(message, tag = "9")]
+        ClearAndAdd(super::ClearAndAdd),
+        #[prost(message, tag = "10")]
+        CreateEdge(super::CreateEdge),
+        #[prost(message, tag = "11")]
+        DeleteEdge(super::DeleteEdge),
+        #[prost(message, tag = "12")]
+        SetEdgeProperty(super::SetEdgeProperty),
+        #[prost(message, tag = "13")]
+        ClearEdgeProperty(super::ClearEdgeProperty),
+        #[prost(message, tag = "14")]
+        CreateOrderedEdge(super::CreateOrderedEdge),
+        #[prost(message, tag = "15")]
+        MoveOrderedEdge(super::MoveOrderedEdge),
+        #[prost(message, tag = "16")]
+        LinkTables(super::LinkTables),
+        #[prost(message, tag = "17")]
+        UnlinkTables(super::UnlinkTables),
+        #[prost(message, tag = "18")]
+        AddToTable(super::AddToTable),
+        #[prost(message, tag = "19")]
+        RemoveFromTable(super::RemoveFromTable),
+        #[prost(message, tag = "20")]
+        ConfirmFieldMapping(super::ConfirmFieldMapping),
+        #[prost(message, tag = "21")]
+        MergeEntities(super::MergeEntities),
+        #[prost(message, tag = "22")]
+        SplitEntity(super::SplitEntity),
+        #[prost(message, tag = "23")]
+        CreateRule(super::CreateRule),
+        #[prost(message, tag = "24")]
+        RestoreEntity(super::RestoreEntity),
+        #[prost(message, tag = "25")]
+        RestoreEdge(super::RestoreEdge),
+        #[prost(message, tag = "26")]
+        ResolveConflict(super::ResolveConflict),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Enumeration)]
+#[repr(i32)]
+pub enum BundleType {
+    Unspecified = 0,
+    UserEdit = 1,
+    ScriptOutput = 2,
+    Import = 3,
+    System = 4,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Enumeration)]
+#[repr(i32)]
+pub enum CrdtType {
+    Text = 0,
+    List = 1,
+    RichText = 2,
+}
+
+// ============================================================================
+// Conversions
+// ============================================================================
+
+fn invalid(message: String) -> CoreError {
+    CoreError::InvalidData(message)
+}
+
+fn required<T>(value: Option<T>, field: &str) -> Result<T, CoreError> {
+    value.ok_or_else(|| invalid(format!("missing {field}")))
+}
+
+fn fixed<const N: usize>(bytes: &[u8], field: &str) -> Result<[u8; N], CoreError> {
+    bytes.try_into().map_err(|_| invalid(format!("{field}: expected {N} bytes, got {}", bytes.len())))
+}
+
+/// A 16-byte id, e.g. `id(&bytes, "entity_id", EntityId::from_bytes)`.
+fn id<T>(bytes: &[u8], field: &str, from_bytes: fn([u8; 16]) -> T) -> Result<T, CoreError> {
+    Ok(from_bytes(fixed(bytes, field)?))
+}
+
+fn optional_id<T>(bytes: Option<Vec<u8>>, field: &str, from_bytes: fn([u8; 16]) -> T) -> Result<Option<T>, CoreError> {
+    bytes.map(|bytes| id(&bytes, field, from_bytes)).transpose()
+}
+
+fn module_versions(versions: &BTreeMap<String, String>) -> Vec<ModuleVersion> {
+    versions
+        .iter()
+        .map(|(module, version)| ModuleVersion { module: module.clone(), version: version.clone() })
+        .collect()
+}
+
+fn fields(fields: &[(String, FieldValue)]) -> Vec<Field> {
+    fields.iter().map(|(key, value)| Field { key: key.clone(), value: Some(value.into()) }).collect()
+}
+
+fn from_fields(fields: Vec<Field>) -> Result<Vec<(String, FieldValue)>, CoreError> {
+    fields.into_iter().map(|field| Ok((field.key, required(field.value, "field value")?.try_into()?))).collect()
+}
+
+impl From<hlc::Hlc> for Hlc {
+    fn from(hlc: hlc::Hlc) -> Self {
+        Self { wall_ms: hlc.wall_ms(), counter: hlc.counter() }
+    }
+}
+
+impl From<Hlc> for hlc::Hlc {
+    fn from(hlc: Hlc) -> Self {
+        Self::new(hlc.wall_ms, hlc.counter)
+    }
+}
+
+impl From<&vector_clock::VectorClock> for VectorClock {
+    fn from(clock: &vector_clock::VectorClock) -> Self {
+        let entries = clock
+            .entries()
+            .iter()
+            .map(|(actor_id, hlc)| VectorClockEntry { actor_id: actor_id.as_bytes().to_vec(), hlc: Some((*hlc).into()) })
+            .collect();
+        Self { entries }
+    }
+}
+
+impl TryFrom<VectorClock> for vector_clock::VectorClock {
+    type Error = CoreError;
+
+    fn try_from(clock: VectorClock) -> Result<Self, CoreError> {
+        let mut result = Self::new();
+        for entry in clock.entries {
+            result.update(ActorId::from_bytes(fixed(&entry.actor_id, "actor_id")?), required(entry.hlc, "hlc")?.into());
+        }
+        Ok(result)
+    }
+}
+
+impl From<&FieldValue> for Value {
+    fn from(value: &FieldValue) -> Self {
+        use value::Kind;
+        let kind = match value {
+            FieldValue::Null => Kind::Null(true),
+            FieldValue::Text(s) => Kind::Text(s.clone()),
+            FieldValue::Integer(n) => Kind::Integer(*n),
+            FieldValue::Float(f) => Kind::Float(*f),
+            FieldValue::Boolean(b) => Kind::Boolean(*b),
+            FieldValue::Timestamp(t) => Kind::Timestamp(*t),
+            FieldValue::EntityRef(id) => Kind::EntityRef(id.as_bytes().to_vec()),
+            FieldValue::BlobRef(hash) => Kind::BlobRef(hash.as_bytes().to_vec()),
+            FieldValue::Bytes(bytes) => Kind::Bytes(bytes.clone()),
+        };
+        Self { kind: Some(kind) }
+    }
+}
+
+impl TryFrom<Value> for FieldValue {
+    type Error = CoreError;
+
+    fn try_from(value: Value) -> Result<Self, CoreError> {
+        use value::Kind;
+        Ok(match required(value.kind, "value")? {
+            Kind::Null(_) => Self::Null,
+            Kind::Text(s) => Self::Text(s),
+            Kind::Integer(n) => Self::Integer(n),
+            Kind::Float(f) => Self::Float(f),
+            Kind::Boolean(b) => Self::Boolean(b),
+            Kind::Timestamp(t) => Self::Timestamp(t),
+            Kind::EntityRef(bytes) => Self::EntityRef(id(&bytes, "entity_ref", EntityId::from_bytes)?),
+            Kind::BlobRef(bytes) => Self::BlobRef(BlobHash::from_bytes(fixed(&bytes, "blob_ref")?)),
+            Kind::Bytes(bytes) => Self::Bytes(bytes),
+        })
+    }
+}
+
+impl From<operations::BundleType> for BundleType {
+    fn from(bundle_type: operations::BundleType) -> Self {
+        match bundle_type {
+            operations::BundleType::UserEdit => Self::UserEdit,
+            operations::BundleType::ScriptOutput => Self::ScriptOutput,
+            operations::BundleType::Import => Self::Import,
+            operations::BundleType::System => Self::System,
+        }
+    }
+}
+
+impl TryFrom<BundleType> for operations::BundleType {
+    type Error = CoreError;
+
+    fn try_from(bundle_type: BundleType) -> Result<Self, CoreError> {
+        match bundle_type {
+            BundleType::Unspecified => Err(invalid("bundle_type unspecified".into())),
+            BundleType::UserEdit => Ok(Self::UserEdit),
+            BundleType::ScriptOutput => Ok(Self::ScriptOutput),
+            BundleType::Import => Ok(Self::Import),
+            BundleType::System => Ok(Self::System),
+        }
+    }
+}
+
+impl From<operations::CrdtType> for CrdtType {
+    fn from(crdt_type: operations::CrdtType) -> Self {
+        match crdt_type {
+            operations::CrdtType::Text => Self::Text,
+            operations::CrdtType::List => Self::List,
+            operations::CrdtType::RichText => Self::RichText,
+        }
+    }
+}
+
+impl From<CrdtType> for operations::CrdtType {
+    fn from(crdt_type: CrdtType) -> Self {
+        match crdt_type {
+            CrdtType::Text => Self::Text,
+            CrdtType::List => Self::List,
+            CrdtType::RichText => Self::RichText,
+        }
+    }
+}
+
+impl From<&OperationPayload> for Payload {
+    fn from(payload: &OperationPayload) -> Self {
+        use payload::Kind;
+        let kind = match payload {
+            OperationPayload::CreateEntity { entity_id, initial_table } => Kind::CreateEntity(CreateEntity {
+                entity_id: entity_id.as_bytes().to_vec(),
+                initial_table: initial_table.clone(),
+            }),
+            OperationPayload::DeleteEntity { entity_id, cascade_edges } => Kind::DeleteEntity(DeleteEntity {
+                entity_id: entity_id.as_bytes().to_vec(),
+                cascade_edges: cascade_edges.iter().map(|id| id.as_bytes().to_vec()).collect(),
+            }),
+            OperationPayload::AttachFacet { entity_id, facet_type } => Kind::AttachFacet(AttachFacet {
+                entity_id: entity_id.as_bytes().to_vec(),
+                facet_type: facet_type.clone(),
+            }),
+            OperationPayload::DetachFacet { entity_id, facet_type, preserve_values } => Kind::DetachFacet(DetachFacet {
+                entity_id: entity_id.as_bytes().to_vec(),
+                facet_type: facet_type.clone(),
+                preserve_values: *preserve_values,
+            }),
+            OperationPayload::RestoreFacet { entity_id, facet_type } => Kind::RestoreFacet(RestoreFacet {
+                entity_id: entity_id.as_bytes().to_vec(),
+                facet_type: facet_type.clone(),
+            }),
+            OperationPayload::SetField { entity_id, field_key, value } => Kind::SetField(SetField {
+                entity_id: entity_id.as_bytes().to_vec(),
+                field_key: field_key.clone(),
+                value: Some(value.into()),
+            }),
+            OperationPayload::ClearField { entity_id, field_key } => Kind::ClearField(ClearField {
+                entity_id: entity_id.as_bytes().to_vec(),
+                field_key: field_key.clone(),
+            }),
+            OperationPayload::ApplyCrdt { entity_id, field_key, crdt_type, delta } => Kind::ApplyCrdt(ApplyCrdt {
+                entity_id: entity_id.as_bytes().to_vec(),
+                field_key: field_key.clone(),
+                crdt_type: CrdtType::from(*crdt_type) as i32,
+                delta: delta.clone(),
+            }),
+            OperationPayload::ClearAndAdd { entity_id, field_key, values } => Kind::ClearAndAdd(ClearAndAdd {
+                entity_id: entity_id.as_bytes().to_vec(),
+                field_key: field_key.clone(),
+                values: values.iter().map(Value::from).collect(),
+            }),
+            OperationPayload::CreateEdge { edge_id, edge_type, source_id, target_id, properties } => {
+                Kind::CreateEdge(CreateEdge {
+                    edge_id: edge_id.as_bytes().to_vec(),
+                    edge_type: edge_type.clone(),
+                    source_id: source_id.as_bytes().to_vec(),
+                    target_id: target_id.as_bytes().to_vec(),
+                    properties: fields(properties),
+                })
+            }
+            OperationPayload::DeleteEdge { edge_id } => Kind::DeleteEdge(DeleteEdge { edge_id: edge_id.as_bytes().to_vec() }),
+            OperationPayload::SetEdgeProperty { edge_id, property_key, value } => Kind::SetEdgeProperty(SetEdgeProperty {
+                edge_id: edge_id.as_bytes().to_vec(),
+                property_key: property_key.clone(),
+                value: Some(value.into()),
+            }),
+            OperationPayload::ClearEdgeProperty { edge_id, property_key } => {
+                Kind::ClearEdgeProperty(ClearEdgeProperty {
+                    edge_id: edge_id.as_bytes().to_vec(),
+                    property_key: property_key.clone(),
+                })
+            }
+            OperationPayload::CreateOrderedEdge { edge_id, edge_type, source_id, target_id, after, before, properties } => {
+                Kind::CreateOrderedEdge(CreateOrderedEdge {
+                    edge_id: edge_id.as_bytes().to_vec(),
+                    edge_type: edge_type.clone(),
+                    source_id: source_id.as_bytes().to_vec(),
+                    target_id: target_id.as_bytes().to_vec(),
+                    after: after.map(|id| id.as_bytes().to_vec()),
+                    before: before.map(|id| id.as_bytes().to_vec()),
+                    properties: fields(properties),
+                })
+            }
+            OperationPayload::MoveOrderedEdge { edge_id, after, before } => Kind::MoveOrderedEdge(MoveOrderedEdge {
+                edge_id: edge_id.as_bytes().to_vec(),
+                after: after.map(|id| id.as_bytes().to_vec()),
+                before: before.map(|id| id.as_bytes().to_vec()),
+            }),
+            OperationPayload::LinkTables { source_table, target_table, field_mappings } => Kind::LinkTables(LinkTables {
+                source_table: source_table.as_bytes().to_vec(),
+                target_table: target_table.as_bytes().to_vec(),
+                field_mappings: field_mappings
+                    .iter()
+                    .map(|(source, target)| FieldMapping { source_field: source.clone(), target_field: target.clone() })
+                    .collect(),
+            }),
+            OperationPayload::UnlinkTables { source_table, target_table, data_handling } => {
+                Kind::UnlinkTables(UnlinkTables {
+                    source_table: source_table.as_bytes().to_vec(),
+                    target_table: target_table.as_bytes().to_vec(),
+                    data_handling: data_handling.clone(),
+                })
+            }
+            OperationPayload::AddToTable { entity_id, table, defaults } => Kind::AddToTable(AddToTable {
+                entity_id: entity_id.as_bytes().to_vec(),
+                table: table.clone(),
+                defaults: fields(defaults),
+            }),
+            OperationPayload::RemoveFromTable { entity_id, table, data_handling } => {
+                Kind::RemoveFromTable(RemoveFromTable {
+                    entity_id: entity_id.as_bytes().to_vec(),
+                    table: table.clone(),
+                    data_handling: data_handling.clone(),
+                })
+            }
+            OperationPayload::ConfirmFieldMapping { source_table, target_table, source_field, target_field } => {
+                Kind::ConfirmFieldMapping(ConfirmFieldMapping {
+                    source_table: source_table.as_bytes().to_vec(),
+                    target_table: target_table.as_bytes().to_vec(),
+                    source_field: source_field.clone(),
+                    target_field: target_field.clone(),
+                })
+            }
+            OperationPayload::MergeEntities { survivor, absorbed } => Kind::MergeEntities(MergeEntities {
+                survivor: survivor.as_bytes().to_vec(),
+                absorbed: absorbed.as_bytes().to_vec(),
+            }),
+            OperationPayload::SplitEntity { source, new_entity, facets } => Kind::SplitEntity(SplitEntity {
+                source: source.as_bytes().to_vec(),
+                new_entity: new_entity.as_bytes().to_vec(),
+                facets: facets.clone(),
+            }),
+            OperationPayload::CreateRule { rule_id, name, when_clause, action_type, action_params, auto_accept } => {
+                Kind::CreateRule(CreateRule {
+                    rule_id: rule_id.as_bytes().to_vec(),
+                    name: name.clone(),
+                    when_clause: when_clause.clone(),
+                    action_type: action_type.clone(),
+                    action_params: action_params.clone(),
+                    auto_accept: *auto_accept,
+                })
+            }
+            OperationPayload::RestoreEntity { entity_id } => {
+                Kind::RestoreEntity(RestoreEntity { entity_id: entity_id.as_bytes().to_vec() })
+            }
+            OperationPayload::RestoreEdge { edge_id } => Kind::RestoreEdge(RestoreEdge { edge_id: edge_id.as_bytes().to_vec() }),
+            OperationPayload::ResolveConflict { conflict_id, entity_id, field_key, chosen_value } => {
+                Kind::ResolveConflict(ResolveConflict {
+                    conflict_id: conflict_id.as_bytes().to_vec(),
+                    entity_id: entity_id.as_bytes().to_vec(),
+                    field_key: field_key.clone(),
+                    chosen_value: chosen_value.as_ref().map(Value::from),
+                })
+            }
+        };
+        Self { kind: Some(kind) }
+    }
+}
+
+impl TryFrom<Payload> for OperationPayload {
+    type Error = CoreError;
+
+    fn try_from(payload: Payload) -> Result<Self, CoreError> {
+        use payload::Kind;
+        Ok(match required(payload.kind, "payload")? {
+            Kind::CreateEntity(m) => Self::CreateEntity {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                initial_table: m.initial_table,
+            },
+            Kind::DeleteEntity(m) => Self::DeleteEntity {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                cascade_edges: m
+                    .cascade_edges
+                    .iter()
+                    .map(|bytes| id(bytes, "cascade_edges", EdgeId::from_bytes))
+                    .collect::<Result<_, _>>()?,
+            },
+            Kind::AttachFacet(m) => Self::AttachFacet {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                facet_type: m.facet_type,
+            },
+            Kind::DetachFacet(m) => Self::DetachFacet {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                facet_type: m.facet_type,
+                preserve_values: m.preserve_values,
+            },
+            Kind::RestoreFacet(m) => Self::RestoreFacet {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                facet_type: m.facet_type,
+            },
+            Kind::SetField(m) => Self::SetField {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                field_key: m.field_key,
+                value: required(m.value, "value")?.try_into()?,
+            },
+            Kind::ClearField(m) => Self::ClearField {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                field_key: m.field_key,
+            },
+            Kind::ApplyCrdt(m) => Self::ApplyCrdt {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                field_key: m.field_key,
+                crdt_type: CrdtType::try_from(m.crdt_type)
+                    .map_err(|_| invalid(format!("unknown crdt_type {}", m.crdt_type)))?
+                    .into(),
+                delta: m.delta,
+            },
+            Kind::ClearAndAdd(m) => Self::ClearAndAdd {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                field_key: m.field_key,
+                values: m.values.into_iter().map(FieldValue::try_from).collect::<Result<_, _>>()?,
+            },
+            Kind::CreateEdge(m) => Self::CreateEdge {
+                edge_id: id(&m.edge_id, "edge_id", EdgeId::from_bytes)?,
+                edge_type: m.edge_type,
+                source_id: id(&m.source_id, "source_id", EntityId::from_bytes)?,
+                target_id: id(&m.target_id, "target_id", EntityId::from_bytes)?,
+                properties: from_fields(m.properties)?,
+            },
+            Kind::DeleteEdge(m) => Self::DeleteEdge { edge_id: id(&m.edge_id, "edge_id", EdgeId::from_bytes)? },
+            Kind::SetEdgeProperty(m) => Self::SetEdgeProperty {
+                edge_id: id(&m.edge_id, "edge_id", EdgeId::from_bytes)?,
+                property_key: m.property_key,
+                value: required(m.value, "value")?.try_into()?,
+            },
+            Kind::ClearEdgeProperty(m) => Self::ClearEdgeProperty {
+                edge_id: id(&m.edge_id, "edge_id", EdgeId::from_bytes)?,
+                property_key: m.property_key,
+            },
+            Kind::CreateOrderedEdge(m) => Self::CreateOrderedEdge {
+                edge_id: id(&m.edge_id, "edge_id", EdgeId::from_bytes)?,
+                edge_type: m.edge_type,
+                source_id: id(&m.source_id, "source_id", EntityId::from_bytes)?,
+                target_id: id(&m.target_id, "target_id", EntityId::from_bytes)?,
+                after: optional_id(m.after, "after", EdgeId::from_bytes)?,
+                before: optional_id(m.before, "before", EdgeId::from_bytes)?,
+                properties: from_fields(m.properties)?,
+            },
+            Kind::MoveOrderedEdge(m) => Self::MoveOrderedEdge {
+                edge_id: id(&m.edge_id, "edge_id", EdgeId::from_bytes)?,
+                after: optional_id(m.after, "after", EdgeId::from_bytes)?,
+                before: optional_id(m.before, "before", EdgeId::from_bytes)?,
+            },
+            Kind::LinkTables(m) => Self::LinkTables {
+                source_table: id(&m.source_table, "source_table", TableId::from_bytes)?,
+                target_table: id(&m.target_table, "target_table", TableId::from_bytes)?,
+                field_mappings: m.field_mappings.into_iter().map(|m| (m.source_field, m.target_field)).collect(),
+            },
+            Kind::UnlinkTables(m) => Self::UnlinkTables {
+                source_table: id(&m.source_table, "source_table", TableId::from_bytes)?,
+                target_table: id(&m.target_table, "target_table", TableId::from_bytes)?,
+                data_handling: m.data_handling,
+            },
+            Kind::AddToTable(m) => Self::AddToTable {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                table: m.table,
+                defaults: from_fields(m.defaults)?,
+            },
+            Kind::RemoveFromTable(m) => Self::RemoveFromTable {
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                table: m.table,
+                data_handling: m.data_handling,
+            },
+            Kind::ConfirmFieldMapping(m) => Self::ConfirmFieldMapping {
+                source_table: id(&m.source_table, "source_table", TableId::from_bytes)?,
+                target_table: id(&m.target_table, "target_table", TableId::from_bytes)?,
+                source_field: m.source_field,
+                target_field: m.target_field,
+            },
+            Kind::MergeEntities(m) => Self::MergeEntities {
+                survivor: id(&m.survivor, "survivor", EntityId::from_bytes)?,
+                absorbed: id(&m.absorbed, "absorbed", EntityId::from_bytes)?,
+            },
+            Kind::SplitEntity(m) => Self::SplitEntity {
+                source: id(&m.source, "source", EntityId::from_bytes)?,
+                new_entity: id(&m.new_entity, "new_entity", EntityId::from_bytes)?,
+                facets: m.facets,
+            },
+            Kind::CreateRule(m) => Self::CreateRule {
+                rule_id: id(&m.rule_id, "rule_id", RuleId::from_bytes)?,
+                name: m.name,
+                when_clause: m.when_clause,
+                action_type: m.action_type,
+                action_params: m.action_params,
+                auto_accept: m.auto_accept,
+            },
+            Kind::RestoreEntity(m) => Self::RestoreEntity { entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)? },
+            Kind::RestoreEdge(m) => Self::RestoreEdge { edge_id: id(&m.edge_id, "edge_id", EdgeId::from_bytes)? },
+            Kind::ResolveConflict(m) => Self::ResolveConflict {
+                conflict_id: id(&m.conflict_id, "conflict_id", ConflictId::from_bytes)?,
+                entity_id: id(&m.entity_id, "entity_id", EntityId::from_bytes)?,
+                field_key: m.field_key,
+                chosen_value: m.chosen_value.map(FieldValue::try_from).transpose()?,
+            },
+        })
+    }
+}
+
+impl From<&operations::Operation> for Operation {
+    fn from(op: &operations::Operation) -> Self {
+        Self {
+            op_id: op.op_id.as_bytes().to_vec(),
+            actor_id: op.actor_id.as_bytes().to_vec(),
+            hlc: Some(op.hlc.into()),
+            bundle_id: op.bundle_id.as_bytes().to_vec(),
+            module_versions: module_versions(&op.module_versions),
+            payload: Some((&op.payload).into()),
+            signature: op.signature.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<Operation> for operations::Operation {
+    type Error = CoreError;
+
+    fn try_from(op: Operation) -> Result<Self, CoreError> {
+        Ok(Self {
+            op_id: id(&op.op_id, "op_id", OpId::from_bytes)?,
+            actor_id: ActorId::from_bytes(fixed(&op.actor_id, "actor_id")?),
+            hlc: required(op.hlc, "hlc")?.into(),
+            bundle_id: id(&op.bundle_id, "bundle_id", BundleId::from_bytes)?,
+            module_versions: op.module_versions.into_iter().map(|m| (m.module, m.version)).collect(),
+            payload: required(op.payload, "payload")?.try_into()?,
+            signature: Signature::from_bytes(fixed(&op.signature, "signature")?),
+        })
+    }
+}
+
+impl From<&operations::Bundle> for Bundle {
+    fn from(bundle: &operations::Bundle) -> Self {
+        Self {
+            bundle_id: bundle.bundle_id.as_bytes().to_vec(),
+            actor_id: bundle.actor_id.as_bytes().to_vec(),
+            hlc: Some(bundle.hlc.into()),
+            bundle_type: BundleType::from(bundle.bundle_type) as i32,
+            op_count: bundle.op_count,
+            checksum: bundle.checksum.to_vec(),
+            creates: bundle.creates.iter().map(|id| id.as_bytes().to_vec()).collect(),
+            deletes: bundle.deletes.iter().map(|id| id.as_bytes().to_vec()).collect(),
+            meta: bundle.meta.clone(),
+            signature: bundle.signature.as_bytes().to_vec(),
+            creator_vc: bundle.creator_vc.as_ref().map(VectorClock::from),
+        }
+    }
+}
+
+impl TryFrom<Bundle> for operations::Bundle {
+    type Error = CoreError;
+
+    fn try_from(bundle: Bundle) -> Result<Self, CoreError> {
+        let entity_ids = |ids: &[Vec<u8>], field: &str| -> Result<Vec<EntityId>, CoreError> {
+            ids.iter().map(|bytes| id(bytes, field, EntityId::from_bytes)).collect()
+        };
+        Ok(Self {
+            bundle_id: id(&bundle.bundle_id, "bundle_id", BundleId::from_bytes)?,
+            actor_id: ActorId::from_bytes(fixed(&bundle.actor_id, "actor_id")?),
+            hlc: required(bundle.hlc, "hlc")?.into(),
+            bundle_type: BundleType::try_from(bundle.bundle_type)
+                .map_err(|_| invalid(format!("unknown bundle_type {}", bundle.bundle_type)))?
+                .try_into()?,
+            op_count: bundle.op_count,
+            checksum: fixed(&bundle.checksum, "checksum")?,
+            creates: entity_ids(&bundle.creates, "creates")?,
+            deletes: entity_ids(&bundle.deletes, "deletes")?,
+            meta: bundle.meta,
+            signature: Signature::from_bytes(fixed(&bundle.signature, "signature")?),
+            creator_vc: bundle.creator_vc.map(vector_clock::VectorClock::try_from).transpose()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::ActorIdentity;
+    use crate::operations::BundleMeta;
+
+    #[test]
+    fn bundle_roundtrips_through_protobuf() {
+        let identity = ActorIdentity::generate();
+        let bundle_id = BundleId::new();
+        let entity_id = EntityId::new();
+        let payloads = [
+            OperationPayload::CreateEntity { entity_id, initial_table: Some("Task".into()) },
+            OperationPayload::SetField { entity_id, field_key: "done".into(), value: FieldValue::Null },
+            OperationPayload::CreateOrderedEdge {
+                edge_id: EdgeId::new(),
+                edge_type: "step".into(),
+                source_id: entity_id,
+                target_id: EntityId::new(),
+                after: Some(EdgeId::new()),
+                before: None,
+                properties: vec![("weight".into(), FieldValue::Float(-0.5))],
+            },
+            OperationPayload::ResolveConflict {
+                conflict_id: ConflictId::new(),
+                entity_id,
+                field_key: "title".into(),
+                chosen_value: Some(FieldValue::Null),
+            },
+        ];
+        let operations: Vec<_> = payloads
+            .into_iter()
+            .enumerate()
+            .map(|(i, payload)| {
+                let versions = BTreeMap::from([("core".to_string(), "1".to_string())]);
+                operations::Operation::new_signed(&identity, hlc::Hlc::new(1_000, i as u32), bundle_id, versions, payload)
+            })
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let mut clock = vector_clock::VectorClock::new();
+        clock.update(identity.actor_id(), hlc::Hlc::new(900, 2));
+        let mut bundle = operations::Bundle::new_signed(
+            bundle_id,
+            &identity,
+            hlc::Hlc::new(1_000, 3),
+            operations::BundleType::Import,
+            &operations,
+            Some(clock),
+        )
+        .unwrap();
+        bundle.meta = Some(BundleMeta::with_label("Import").to_msgpack().unwrap());
+
+        let (decoded, decoded_ops) = decode_bundle(&encode_bundle(&bundle, &operations)).unwrap();
+        assert_eq!(decoded_ops, operations);
+        assert_eq!(decoded.creates, vec![entity_id]);
+        assert_eq!((&decoded.meta, &decoded.creator_vc), (&bundle.meta, &bundle.creator_vc));
+        decoded.verify_signature().unwrap();
+        assert!(decoded.checksum_matches(&decoded_ops).unwrap());
+        assert!(decode_bundle(&[0xff]).is_err());
+    }
+}
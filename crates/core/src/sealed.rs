@@ -0,0 +1,198 @@
+//! End-to-end encrypted bundles for untrusted relays.
+//!
+//! The bundle header (ids, HLC, actor, signature) stays readable so relays can
+//! route, dedupe and order bundles; the operations and bundle meta are encrypted
+//! with XChaCha20-Poly1305 under a key shared by the workspace's members.
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::error::CoreError;
+use crate::operations::{Bundle, Operation, RawBundle};
+
+/// Prefix of the single "operation" a sealed `RawBundle` carries. Encoded
+/// operations are msgpack maps or arrays, so they never start with it.
+const SEALED_TAG: &[u8] = b"openprod-sealed-v1:";
+
+/// Symmetric key shared by every member of a workspace.
+#[derive(Clone, PartialEq, Eq)]
+pub struct WorkspaceKey([u8; 32]);
+
+impl WorkspaceKey {
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Short public identifier, so a receiver can tell which key a bundle needs
+    /// without trying every key it holds.
+    pub fn key_id(&self) -> [u8; 8] {
+        let hash = blake3::derive_key("openprod workspace key id v1", &self.0);
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&hash[..8]);
+        id
+    }
+}
+
+impl std::fmt::Debug for WorkspaceKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WorkspaceKey({:02x?})", self.key_id())
+    }
+}
+
+/// A bundle whose body is encrypted to a workspace key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedBundle {
+    /// Plaintext header; `meta` is always None here (it travels in the ciphertext).
+    pub bundle: Bundle,
+    pub key_id: [u8; 8],
+    pub nonce: [u8; 24],
+    /// Encrypted msgpack `(meta, operations)`, authenticated against the bundle id.
+    pub ciphertext: Vec<u8>,
+}
+
+impl SealedBundle {
+    pub fn seal(bundle: &Bundle, operations: &[Operation], key: &WorkspaceKey) -> Result<Self, CoreError> {
+        let plaintext = rmp_serde::to_vec_named(&(&bundle.meta, operations))
+            .map_err(|e| CoreError::Serialization(e.to_string()))?;
+        let mut nonce = [0u8; 24];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let cipher = XChaCha20Poly1305::new(key.as_bytes().into());
+        let payload = Payload { msg: &plaintext, aad: bundle.bundle_id.as_bytes() };
+        let ciphertext = cipher
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .map_err(|_| CoreError::Decryption("encryption failed".into()))?;
+
+        let mut header = bundle.clone();
+        header.meta = None;
+        Ok(Self { bundle: header, key_id: key.key_id(), nonce, ciphertext })
+    }
+
+    /// Decrypt back to the original bundle and operations. Fails if `key` is not
+    /// the key this bundle was sealed with, or the ciphertext was altered.
+    pub fn open(&self, key: &WorkspaceKey) -> Result<(Bundle, Vec<Operation>), CoreError> {
+        if key.key_id() != self.key_id {
+            return Err(CoreError::Decryption("sealed with a different workspace key".into()));
+        }
+        let cipher = XChaCha20Poly1305::new(key.as_bytes().into());
+        let payload = Payload { msg: &self.ciphertext, aad: self.bundle.bundle_id.as_bytes() };
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(&self.nonce), payload)
+            .map_err(|_| CoreError::Decryption("ciphertext failed authentication".into()))?;
+
+        let (meta, operations): (Option<Vec<u8>>, Vec<Operation>) =
+            rmp_serde::from_slice(&plaintext).map_err(|e| CoreError::Serialization(e.to_string()))?;
+        let mut bundle = self.bundle.clone();
+        bundle.meta = meta;
+        Ok((bundle, operations))
+    }
+
+    /// Encode for transports and stores that carry `RawBundle`s: the plaintext
+    /// header as usual, and the envelope as the only, tagged, operation.
+    pub fn to_raw(&self) -> Result<RawBundle, CoreError> {
+        let error = |e: rmp_serde::encode::Error| CoreError::Serialization(e.to_string());
+        let mut envelope = SEALED_TAG.to_vec();
+        envelope.extend(rmp_serde::to_vec(&(&self.key_id, &self.nonce, &self.ciphertext)).map_err(error)?);
+        Ok(RawBundle { bundle: rmp_serde::to_vec(&self.bundle).map_err(error)?, operations: vec![envelope] })
+    }
+
+    /// The sealed bundle a `RawBundle` carries, or None if it is a plain one.
+    pub fn from_raw(raw: &RawBundle) -> Result<Option<Self>, CoreError> {
+        let [envelope] = raw.operations.as_slice() else {
+            return Ok(None);
+        };
+        let Some(envelope) = envelope.strip_prefix(SEALED_TAG) else {
+            return Ok(None);
+        };
+        let (key_id, nonce, ciphertext) =
+            rmp_serde::from_slice(envelope).map_err(|e| CoreError::Serialization(format!("sealed envelope: {e}")))?;
+        Ok(Some(Self { bundle: raw.decode_header()?, key_id, nonce, ciphertext }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field_value::FieldValue;
+    use crate::hlc::Hlc;
+    use crate::identity::ActorIdentity;
+    use crate::ids::{BundleId, EntityId};
+    use crate::operations::{BundleMeta, BundleType, OperationPayload};
+    use std::collections::BTreeMap;
+
+    fn sample() -> (Bundle, Vec<Operation>) {
+        let identity = ActorIdentity::generate();
+        let bundle_id = BundleId::new();
+        let hlc = Hlc::new(1_000, 0);
+        let payload = OperationPayload::SetField {
+            entity_id: EntityId::new(),
+            field_key: "salary".into(),
+            value: FieldValue::Integer(100_000),
+        };
+        let ops = vec![Operation::new_signed(&identity, hlc, bundle_id, BTreeMap::new(), payload).unwrap()];
+        let mut bundle = Bundle::new_signed(bundle_id, &identity, hlc, BundleType::UserEdit, &ops, None).unwrap();
+        bundle.meta = Some(BundleMeta::with_label("Payroll").to_msgpack().unwrap());
+        (bundle, ops)
+    }
+
+    #[test]
+    fn seal_open_roundtrip_keeps_header_readable() {
+        let (bundle, ops) = sample();
+        let key = WorkspaceKey::generate();
+        let sealed = SealedBundle::seal(&bundle, &ops, &key).unwrap();
+
+        assert_eq!(sealed.bundle.bundle_id, bundle.bundle_id);
+        assert_eq!(sealed.bundle.meta, None);
+        sealed.bundle.verify_signature().unwrap();
+
+        let (opened, opened_ops) = sealed.open(&key).unwrap();
+        assert_eq!(opened.meta, bundle.meta);
+        assert_eq!(opened_ops, ops);
+    }
+
+    #[test]
+    fn wrong_key_or_tampering_is_refused() {
+        let (bundle, ops) = sample();
+        let key = WorkspaceKey::generate();
+        let sealed = SealedBundle::seal(&bundle, &ops, &key).unwrap();
+
+        assert!(matches!(sealed.open(&WorkspaceKey::generate()), Err(CoreError::Decryption(_))));
+
+        let mut tampered = sealed.clone();
+        tampered.ciphertext[0] ^= 1;
+        assert!(matches!(tampered.open(&key), Err(CoreError::Decryption(_))));
+
+        // Ciphertext is bound to its bundle id
+        let mut moved = sealed;
+        moved.bundle.bundle_id = BundleId::new();
+        assert!(matches!(moved.open(&key), Err(CoreError::Decryption(_))));
+    }
+
+    #[test]
+    fn raw_encoding_is_told_apart_from_plain_bundles() {
+        let (bundle, ops) = sample();
+        let key = WorkspaceKey::generate();
+        let sealed = SealedBundle::seal(&bundle, &ops, &key).unwrap();
+
+        let raw = sealed.to_raw().unwrap();
+        assert_eq!(raw.decode_header().unwrap().bundle_id, bundle.bundle_id);
+        let (opened, opened_ops) = SealedBundle::from_raw(&raw).unwrap().unwrap().open(&key).unwrap();
+        assert_eq!(opened.meta, bundle.meta);
+        assert_eq!(opened_ops, ops);
+
+        let plain = RawBundle::encode(&bundle, &ops).unwrap();
+        assert!(SealedBundle::from_raw(&plain).unwrap().is_none());
+    }
+}
@@ -0,0 +1,61 @@
+//! What part of a workspace a peer replicates, declared when it pulls so the
+//! sender can leave out the rest (e.g. a phone that only syncs tasks and
+//! notes).
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CoreError;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyncScope {
+    /// Every op.
+    #[default]
+    All,
+    /// Ops on entities that have (or had) one of these facets, edges with
+    /// such an entity at either end, and workspace-wide ops (table links,
+    /// field mappings, rules).
+    Facets(BTreeSet<String>),
+}
+
+impl SyncScope {
+    pub fn facets(facet_types: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self::Facets(facet_types.into_iter().map(Into::into).collect())
+    }
+
+    pub fn is_all(&self) -> bool {
+        matches!(self, Self::All)
+    }
+
+    /// Whether entities with `facet_type` are replicated.
+    pub fn covers_facet(&self, facet_type: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Facets(facet_types) => facet_types.contains(facet_type),
+        }
+    }
+
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, CoreError> {
+        rmp_serde::to_vec_named(self).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, CoreError> {
+        rmp_serde::from_slice(bytes).map_err(|e| CoreError::Serialization(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_covers_listed_facets_only() {
+        let scope = SyncScope::facets(["Task", "Note"]);
+        assert!(scope.covers_facet("Task") && scope.covers_facet("Note"));
+        assert!(!scope.covers_facet("Project"));
+        assert!(!scope.is_all());
+        assert!(SyncScope::All.covers_facet("Project"));
+        assert_eq!(SyncScope::from_msgpack(&scope.to_msgpack().unwrap()).unwrap(), scope);
+    }
+}
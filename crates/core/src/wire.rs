@@ -0,0 +1,197 @@
+//! Stable envelope for shipping a bundle and its operations between peers.
+//!
+//! Layout: `MAGIC` (4 bytes), format version (1 byte), flags (1 byte), then the
+//! msgpack-encoded `(Bundle, Vec<Operation>)`, zstd-compressed if
+//! `FLAG_ZSTD` is set. Msgpack uses named fields, so newer versions can add
+//! fields without breaking older readers; the version byte is bumped only for
+//! incompatible layout changes.
+
+use crate::error::CoreError;
+use crate::operations::{Bundle, Operation};
+
+pub const MAGIC: [u8; 4] = *b"OPBW";
+pub const WIRE_VERSION: u8 = 1;
+/// Largest body a compressed envelope may inflate to. Envelopes come from
+/// untrusted peers and archives, so decompression stops here rather than
+/// trusting the frame.
+pub const MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+
+const HEADER_LEN: usize = MAGIC.len() + 2;
+const FLAG_ZSTD: u8 = 0b0000_0001;
+#[cfg(not(target_arch = "wasm32"))]
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Zstd,
+}
+
+pub fn encode_bundle_wire(
+    bundle: &Bundle,
+    operations: &[Operation],
+    compression: Compression,
+) -> Result<Vec<u8>, CoreError> {
+    let body = rmp_serde::to_vec_named(&(bundle, operations))
+        .map_err(|e| CoreError::Serialization(e.to_string()))?;
+
+    let mut out = Vec::with_capacity(HEADER_LEN + body.len());
+    out.extend_from_slice(&MAGIC);
+    out.push(WIRE_VERSION);
+    match compression {
+        Compression::None => {
+            out.push(0);
+            out.extend_from_slice(&body);
+        }
+        Compression::Zstd => {
+            out.push(FLAG_ZSTD);
+            out.extend_from_slice(&zstd_compress(&body)?);
+        }
+    }
+    Ok(out)
+}
+
+pub fn decode_bundle_wire(bytes: &[u8]) -> Result<(Bundle, Vec<Operation>), CoreError> {
+    if bytes.len() < HEADER_LEN || bytes[..MAGIC.len()] != MAGIC {
+        return Err(CoreError::InvalidData("not a bundle wire envelope".into()));
+    }
+    let version = bytes[MAGIC.len()];
+    if version == 0 {
+        return Err(CoreError::InvalidData("wire version 0".into()));
+    }
+    if version > WIRE_VERSION {
+        return Err(CoreError::UnsupportedWireVersion { version, max: WIRE_VERSION });
+    }
+    let flags = bytes[MAGIC.len() + 1];
+    if flags & !FLAG_ZSTD != 0 {
+        return Err(CoreError::InvalidData(format!("unknown wire flags {flags:#04x}")));
+    }
+
+    let body = &bytes[HEADER_LEN..];
+    let decompressed;
+    let body = if flags & FLAG_ZSTD != 0 {
+        decompressed = zstd_decompress(body)?;
+        decompressed.as_slice()
+    } else {
+        body
+    };
+    rmp_serde::from_slice(body).map_err(|e| CoreError::Serialization(e.to_string()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn zstd_compress(body: &[u8]) -> Result<Vec<u8>, CoreError> {
+    zstd::encode_all(body, ZSTD_LEVEL).map_err(|e| CoreError::Serialization(e.to_string()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn zstd_decompress(body: &[u8]) -> Result<Vec<u8>, CoreError> {
+    let decoder = zstd::stream::read::Decoder::new(body).map_err(|e| CoreError::Serialization(e.to_string()))?;
+    read_capped(decoder)
+}
+
+/// Read all of `reader`, failing once it yields more than `MAX_DECOMPRESSED_LEN`.
+fn read_capped(reader: impl std::io::Read) -> Result<Vec<u8>, CoreError> {
+    use std::io::Read;
+    let mut out = Vec::new();
+    reader
+        .take(MAX_DECOMPRESSED_LEN as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| CoreError::Serialization(e.to_string()))?;
+    if out.len() > MAX_DECOMPRESSED_LEN {
+        return Err(CoreError::InvalidData(format!(
+            "envelope inflates past {MAX_DECOMPRESSED_LEN} bytes"
+        )));
+    }
+    Ok(out)
+}
+
+// Pure-Rust codec for browser builds; frames are interchangeable with libzstd's.
+#[cfg(target_arch = "wasm32")]
+fn zstd_compress(body: &[u8]) -> Result<Vec<u8>, CoreError> {
+    Ok(ruzstd::encoding::compress_to_vec(body, ruzstd::encoding::CompressionLevel::Fastest))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn zstd_decompress(body: &[u8]) -> Result<Vec<u8>, CoreError> {
+    let decoder =
+        ruzstd::decoding::StreamingDecoder::new(body).map_err(|e| CoreError::Serialization(e.to_string()))?;
+    read_capped(decoder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field_value::FieldValue;
+    use crate::hlc::Hlc;
+    use crate::identity::ActorIdentity;
+    use crate::ids::{BundleId, EntityId};
+    use crate::operations::{BundleType, OperationPayload};
+    use std::collections::BTreeMap;
+
+    fn sample(fields: usize) -> (Bundle, Vec<Operation>) {
+        let identity = ActorIdentity::generate();
+        let bundle_id = BundleId::new();
+        let hlc = Hlc::new(1_000, 0);
+        let entity_id = EntityId::new();
+        let ops: Vec<Operation> = (0..fields)
+            .map(|i| {
+                let payload = OperationPayload::SetField {
+                    entity_id,
+                    field_key: format!("field_{i}"),
+                    value: FieldValue::Text("the same long text value repeated".into()),
+                };
+                Operation::new_signed(&identity, hlc, bundle_id, BTreeMap::new(), payload).unwrap()
+            })
+            .collect();
+        let bundle = Bundle::new_signed(bundle_id, &identity, hlc, BundleType::UserEdit, &ops, None).unwrap();
+        (bundle, ops)
+    }
+
+    #[test]
+    fn roundtrips_with_and_without_compression() {
+        let (bundle, ops) = sample(50);
+        for compression in [Compression::None, Compression::Zstd] {
+            let bytes = encode_bundle_wire(&bundle, &ops, compression).unwrap();
+            let (decoded, decoded_ops) = decode_bundle_wire(&bytes).unwrap();
+            assert_eq!(decoded.bundle_id, bundle.bundle_id);
+            assert_eq!(decoded_ops, ops);
+            decoded.verify_signature().unwrap();
+        }
+
+        let plain = encode_bundle_wire(&bundle, &ops, Compression::None).unwrap();
+        let compressed = encode_bundle_wire(&bundle, &ops, Compression::Zstd).unwrap();
+        assert!(compressed.len() < plain.len());
+    }
+
+    #[test]
+    fn rejects_foreign_and_future_envelopes() {
+        let (bundle, ops) = sample(1);
+        let bytes = encode_bundle_wire(&bundle, &ops, Compression::None).unwrap();
+
+        assert!(matches!(decode_bundle_wire(b"{}"), Err(CoreError::InvalidData(_))));
+
+        let mut unversioned = bytes.clone();
+        unversioned[MAGIC.len()] = 0;
+        assert!(matches!(decode_bundle_wire(&unversioned), Err(CoreError::InvalidData(_))));
+
+        let mut future = bytes.clone();
+        future[MAGIC.len()] = WIRE_VERSION + 1;
+        assert!(matches!(
+            decode_bundle_wire(&future),
+            Err(CoreError::UnsupportedWireVersion { version, .. }) if version == WIRE_VERSION + 1
+        ));
+
+        let mut unknown_flag = bytes;
+        unknown_flag[MAGIC.len() + 1] = 0b1000_0000;
+        assert!(matches!(decode_bundle_wire(&unknown_flag), Err(CoreError::InvalidData(_))));
+    }
+
+    #[test]
+    fn refuses_envelopes_that_inflate_past_the_cap() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[WIRE_VERSION, FLAG_ZSTD]);
+        bytes.extend_from_slice(&zstd_compress(&vec![0; MAX_DECOMPRESSED_LEN + 1]).unwrap());
+        assert!(bytes.len() < 64 * 1024);
+        assert!(matches!(decode_bundle_wire(&bytes), Err(CoreError::InvalidData(_))));
+    }
+}
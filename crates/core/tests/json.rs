@@ -0,0 +1,125 @@
+//! Golden-file tests for the JSON forms of the core types. Run with
+//! `OPENPROD_BLESS=1` to rewrite the files after an intended format change.
+
+use std::path::PathBuf;
+
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::Hlc,
+    identity::ActorIdentity,
+    ids::{BlobHash, BundleId, EntityId},
+    json::to_hex,
+    operations::*,
+    vector_clock::VectorClock,
+};
+
+const T0: u64 = 1_700_000_000_000;
+
+fn fixture() -> Result<(Bundle, Vec<Operation>), Box<dyn std::error::Error>> {
+    let identity = ActorIdentity::from_secret_bytes(&[1; 32]);
+    let bundle_id = BundleId::from_bytes([0x11; 16]);
+    let entity_id = EntityId::from_bytes([0x22; 16]);
+    let payloads = [
+        OperationPayload::CreateEntity { entity_id, initial_table: Some("Cue".into()) },
+        OperationPayload::SetField { entity_id, field_key: "label".into(), value: FieldValue::Text("Opening look".into()) },
+        OperationPayload::ApplyCrdt { entity_id, field_key: "notes".into(), crdt_type: CrdtType::RichText, delta: vec![0x85, 0x6f, 0x4a] },
+    ];
+    let hlc = Hlc::new(T0, 3);
+    let operations = (0u32..)
+        .zip(payloads)
+        .map(|(position, payload)| Operation::new_signed_derived(&identity, hlc, bundle_id, position, Default::default(), payload))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut creator_vc = VectorClock::new();
+    creator_vc.update(ActorIdentity::from_secret_bytes(&[2; 32]).actor_id(), Hlc::new(T0 - 1, 0));
+    let mut bundle = Bundle::new_signed(bundle_id, &identity, hlc, BundleType::UserEdit, &operations, Some(creator_vc))?;
+    bundle.meta = Some(BundleMeta::with_label("Patch").to_msgpack()?);
+    Ok((bundle, operations))
+}
+
+fn field_values() -> Vec<FieldValue> {
+    vec![
+        FieldValue::Null,
+        FieldValue::Text("Act 1".into()),
+        FieldValue::Integer(-7),
+        FieldValue::Float(0.25),
+        FieldValue::Boolean(true),
+        FieldValue::Timestamp(T0 as i64),
+        FieldValue::EntityRef(EntityId::from_bytes([0x33; 16])),
+        FieldValue::BlobRef(BlobHash::from_bytes([0xab; 32])),
+        FieldValue::Bytes(vec![1, 2, 255]),
+    ]
+}
+
+/// Compare `actual` with the golden file `name`, as JSON values so layout
+/// doesn't matter, or rewrite the file when blessing.
+fn check_golden(name: &str, actual: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name);
+    let actual: serde_json::Value = serde_json::from_str(actual)?;
+    if std::env::var_os("OPENPROD_BLESS").is_some() {
+        std::fs::write(&path, serde_json::to_string_pretty(&actual)? + "\n")?;
+        return Ok(());
+    }
+    let expected: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+    assert_eq!(actual, expected, "{name} differs from its golden file:\n{}", serde_json::to_string_pretty(&actual)?);
+    Ok(())
+}
+
+fn golden(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(std::fs::read_to_string(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name))?)
+}
+
+#[test]
+fn operations_match_golden_json() -> Result<(), Box<dyn std::error::Error>> {
+    let (_, operations) = fixture()?;
+    let json = format!("[{}]", operations.iter().map(Operation::to_json).collect::<Result<Vec<_>, _>>()?.join(","));
+    check_golden("operations.json", &json)?;
+
+    let values: Vec<serde_json::Value> = serde_json::from_str(&golden("operations.json")?)?;
+    for (value, op) in values.iter().zip(&operations) {
+        let decoded = Operation::from_json(&value.to_string())?;
+        assert_eq!(&decoded, op);
+        decoded.verify_signature()?;
+        let payload = serde_json::to_string(&value["payload"])?;
+        assert_eq!(OperationPayload::from_json(&payload)?, op.payload);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&op.payload.to_json()?)?, value["payload"]);
+    }
+    Ok(())
+}
+
+#[test]
+fn bundle_matches_golden_json() -> Result<(), Box<dyn std::error::Error>> {
+    let (bundle, operations) = fixture()?;
+    check_golden("bundle.json", &bundle.to_json()?)?;
+
+    let decoded = Bundle::from_json(&golden("bundle.json")?)?;
+    decoded.verify_signature()?;
+    assert_eq!(decoded.decode_meta()?.and_then(|meta| meta.label), Some("Patch".into()));
+    // Reads back to the exact msgpack the bundle was signed as
+    assert_eq!(RawBundle::encode(&decoded, &operations)?, RawBundle::encode(&bundle, &operations)?);
+    Ok(())
+}
+
+#[test]
+fn field_values_match_golden_json() -> Result<(), Box<dyn std::error::Error>> {
+    let values = field_values();
+    let json = format!("[{}]", values.iter().map(FieldValue::to_json).collect::<Result<Vec<_>, _>>()?.join(","));
+    check_golden("field_values.json", &json)?;
+
+    let decoded: Vec<serde_json::Value> = serde_json::from_str(&golden("field_values.json")?)?;
+    for (value, expected) in decoded.iter().zip(&values) {
+        assert_eq!(&FieldValue::from_json(&value.to_string())?, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn msgpack_encoding_is_unchanged() -> Result<(), Box<dyn std::error::Error>> {
+    let (bundle, operations) = fixture()?;
+    let raw = RawBundle::encode(&bundle, &operations)?;
+    let json = serde_json::json!({
+        "bundle": to_hex(&raw.bundle),
+        "operations": raw.operations.iter().map(|op| to_hex(op)).collect::<Vec<_>>(),
+        "field_values": field_values().iter().map(|v| Ok(to_hex(&v.to_msgpack()?))).collect::<Result<Vec<_>, rmp_serde::encode::Error>>()?,
+    });
+    check_golden("msgpack.json", &json.to_string())
+}
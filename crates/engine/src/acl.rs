@@ -0,0 +1,266 @@
+//! Workspace access control.
+//!
+//! Roles live in the fields of system entities carrying the `ACL_FACET` facet,
+//! one field per actor (`role.<actor hex>`), so they sync and merge like any
+//! other data (LWW per actor). Normally there is a single ACL entity; if two
+//! peers bootstrap concurrently, the latest write for an actor across all ACL
+//! entities wins. A workspace without any ACL entity is open: every actor may
+//! write.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::Hlc,
+    ids::{ActorId, BundleId, EntityId, OpId},
+    operations::{BundleType, Operation, OperationPayload},
+    vector_clock::VectorClock,
+};
+use openprod_storage::EngineStorage;
+
+use crate::Engine;
+use crate::error::EngineError;
+
+/// Facet marking the system entities that hold the workspace ACL.
+pub const ACL_FACET: &str = "openprod.acl";
+
+const ROLE_PREFIX: &str = "role.";
+
+/// What an actor may do. Ordered: each role includes the ones below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// May sync and read, but not write.
+    Reader,
+    /// May write workspace data.
+    Writer,
+    /// May also grant and revoke roles.
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Reader => "reader",
+            Role::Writer => "writer",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub(crate) fn to_field_value(self) -> FieldValue {
+        FieldValue::Text(self.as_str().to_string())
+    }
+
+    /// `None` for revoked entries (stored as `Null`) and unknown role names.
+    pub(crate) fn from_field_value(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Text(s) if s == "reader" => Some(Role::Reader),
+            FieldValue::Text(s) if s == "writer" => Some(Role::Writer),
+            FieldValue::Text(s) if s == "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn role_key(actor_id: ActorId) -> String {
+    let mut key = String::from(ROLE_PREFIX);
+    for byte in actor_id.as_bytes() {
+        key.push_str(&format!("{byte:02x}"));
+    }
+    key
+}
+
+pub(crate) fn actor_from_role_key(key: &str) -> Option<ActorId> {
+    let hex = key.strip_prefix(ROLE_PREFIX)?;
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(ActorId::from_bytes(bytes))
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Give `actor_id` a role. The first grant in an open workspace creates the
+    /// ACL and also makes the caller an admin; after that only admins may grant.
+    pub fn grant_role(&mut self, actor_id: ActorId, role: Role) -> Result<BundleId, EngineError> {
+        let mut payloads = Vec::new();
+        let acl_entity = match self.acl_entity()? {
+            Some(entity_id) => entity_id,
+            None => {
+                let entity_id = EntityId::new();
+                payloads.push(OperationPayload::CreateEntity {
+                    entity_id,
+                    initial_table: Some(ACL_FACET.to_string()),
+                });
+                if actor_id != self.actor_id() {
+                    payloads.push(OperationPayload::SetField {
+                        entity_id,
+                        field_key: role_key(self.actor_id()),
+                        value: Role::Admin.to_field_value(),
+                    });
+                }
+                entity_id
+            }
+        };
+        payloads.push(OperationPayload::SetField {
+            entity_id: acl_entity,
+            field_key: role_key(actor_id),
+            value: role.to_field_value(),
+        });
+        let (bundle_id, _) = self.execute_internal(BundleType::System, payloads, false)?;
+        Ok(bundle_id)
+    }
+
+    /// Remove any role from `actor_id`. Admin only.
+    pub fn revoke_role(&mut self, actor_id: ActorId) -> Result<BundleId, EngineError> {
+        let acl_entity = self
+            .acl_entity()?
+            .ok_or_else(|| EngineError::PermissionDenied("workspace has no ACL".into()))?;
+        let payloads = vec![OperationPayload::SetField {
+            entity_id: acl_entity,
+            field_key: role_key(actor_id),
+            value: FieldValue::Null,
+        }];
+        let (bundle_id, _) = self.execute_internal(BundleType::System, payloads, false)?;
+        Ok(bundle_id)
+    }
+
+    /// True once an ACL exists; before that every actor may write.
+    pub fn acl_enabled(&self) -> Result<bool, EngineError> {
+        Ok(self.acl_entity()?.is_some())
+    }
+
+    /// Current role of `actor_id` (None if it has none or was revoked).
+    pub fn role_of(&self, actor_id: ActorId) -> Result<Option<Role>, EngineError> {
+        // A rotated key inherits its predecessor's role until granted its own
+        for actor_id in self.actor_chain(actor_id)? {
+            if let Some(role) = self.recorded_role(actor_id)? {
+                return Ok(role);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Latest ACL entry written for exactly `actor_id`: None if never written,
+    /// Some(None) if revoked.
+    fn recorded_role(&self, actor_id: ActorId) -> Result<Option<Option<Role>>, EngineError> {
+        let key = role_key(actor_id);
+        let mut latest: Option<(Hlc, Option<Role>)> = None;
+        for entity_id in self.storage.get_entities_by_facet(ACL_FACET)? {
+            let Some((_, hlc)) = self.storage.get_field_metadata(entity_id, &key)? else {
+                continue;
+            };
+            if latest.is_none_or(|(latest_hlc, _)| hlc > latest_hlc) {
+                let role = self.storage.get_field(entity_id, &key)?.and_then(|v| Role::from_field_value(&v));
+                latest = Some((hlc, role));
+            }
+        }
+        Ok(latest.map(|(_, role)| role))
+    }
+
+    /// Every actor holding a role, in actor order.
+    pub fn acl_entries(&self) -> Result<Vec<(ActorId, Role)>, EngineError> {
+        let mut actors = BTreeSet::new();
+        for entity_id in self.storage.get_entities_by_facet(ACL_FACET)? {
+            for (key, _) in self.storage.get_fields(entity_id)? {
+                actors.extend(actor_from_role_key(&key));
+            }
+        }
+        let mut entries = Vec::new();
+        for actor_id in actors {
+            if let Some(role) = self.role_of(actor_id)? {
+                entries.push((actor_id, role));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The ACL entity grants are written to: the lowest id, so every peer
+    /// picks the same one once concurrent bootstraps have synced.
+    fn acl_entity(&self) -> Result<Option<EntityId>, EngineError> {
+        Ok(self.storage.get_entities_by_facet(ACL_FACET)?.into_iter().min())
+    }
+
+    /// Roles as the holder of `context` saw them: each actor's latest role
+    /// write among the ACL ops the clock covers (None if revoked). None if it
+    /// covers no ACL entity yet, i.e. the workspace was still open. Every peer
+    /// applying a bundle holds the ops its `creator_vc` covers, so all of them
+    /// reach the same verdict however their own ACL has moved on since.
+    fn roles_as_of(&self, context: &VectorClock) -> Result<Option<BTreeMap<ActorId, Option<Role>>>, EngineError> {
+        let covered = |op: &Operation| context.get(&op.actor_id).is_some_and(|hlc| *hlc >= op.hlc);
+        let mut enabled = false;
+        let mut latest: BTreeMap<ActorId, (Hlc, OpId, Option<Role>)> = BTreeMap::new();
+        for entity_id in self.storage.get_entities_by_facet(ACL_FACET)? {
+            for op in self.storage.get_ops_by_entity(entity_id)?.into_iter().filter(|op| covered(op)) {
+                let (field_key, role) = match &op.payload {
+                    OperationPayload::CreateEntity { initial_table, .. } => {
+                        enabled |= initial_table.as_deref() == Some(ACL_FACET);
+                        continue;
+                    }
+                    OperationPayload::AttachFacet { facet_type, .. } => {
+                        enabled |= facet_type == ACL_FACET;
+                        continue;
+                    }
+                    OperationPayload::SetField { field_key, value, .. } => (field_key, Role::from_field_value(value)),
+                    OperationPayload::ClearField { field_key, .. } => (field_key, None),
+                    _ => continue,
+                };
+                if let Some(actor_id) = actor_from_role_key(field_key)
+                    && latest.get(&actor_id).is_none_or(|(hlc, op_id, _)| (op.hlc, op.op_id) > (*hlc, *op_id))
+                {
+                    latest.insert(actor_id, (op.hlc, op.op_id, role));
+                }
+            }
+        }
+        Ok(enabled.then(|| latest.into_iter().map(|(actor_id, (_, _, role))| (actor_id, role)).collect()))
+    }
+
+    /// Fail with `PermissionDenied` unless `actor_id` may apply `payloads`:
+    /// writers may change data, only admins may touch the ACL itself. Judged
+    /// against the ACL as of `context` (an ingested bundle's `creator_vc`),
+    /// or the current one for local writes and bundles without a clock.
+    pub(crate) fn check_permission<'a>(
+        &self,
+        actor_id: ActorId,
+        context: Option<&VectorClock>,
+        payloads: impl Iterator<Item = &'a OperationPayload>,
+    ) -> Result<(), EngineError> {
+        let roles = match context {
+            Some(context) => match self.roles_as_of(context)? {
+                Some(roles) => Some(roles),
+                None => return Ok(()),
+            },
+            None if !self.acl_enabled()? => return Ok(()),
+            None => None,
+        };
+        let mut required = Role::Writer;
+        for payload in payloads {
+            let touches_acl = match payload {
+                OperationPayload::CreateEntity { initial_table, .. } => initial_table.as_deref() == Some(ACL_FACET),
+                OperationPayload::AttachFacet { facet_type, .. } => facet_type == ACL_FACET,
+                OperationPayload::AddToTable { table, .. } => table == ACL_FACET,
+                _ => false,
+            } || match payload.entity_id() {
+                Some(entity_id) => self.storage.get_facets(entity_id)?.iter().any(|f| f.facet_type == ACL_FACET),
+                None => false,
+            };
+            if touches_acl {
+                required = Role::Admin;
+                break;
+            }
+        }
+        let role = match &roles {
+            Some(roles) => self.actor_chain(actor_id)?.iter().find_map(|a| roles.get(a).copied()).flatten(),
+            None => self.role_of(actor_id)?,
+        };
+        match role {
+            Some(role) if role >= required => Ok(()),
+            _ => Err(EngineError::PermissionDenied(format!(
+                "actor {actor_id} needs the {} role",
+                required.as_str()
+            ))),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Single-file oplog archives for offline ("sneakernet") sync.
+//!
+//! Layout: `ARCHIVE_MAGIC` (4 bytes), format version (1 byte), blake3 checksum
+//! of the body (32 bytes), then the body: a sequence of entries, each a
+//! big-endian `u32` length followed by a zstd-compressed bundle wire envelope.
+
+use std::path::Path;
+
+use openprod_core::{
+    operations::{Bundle, Operation},
+    vector_clock::VectorClock,
+    wire::{decode_bundle_wire, encode_bundle_wire, Compression},
+};
+use openprod_storage::{ConflictRecord, EngineStorage};
+
+use crate::Engine;
+use crate::error::EngineError;
+
+pub const ARCHIVE_MAGIC: [u8; 4] = *b"OPAR";
+pub const ARCHIVE_VERSION: u8 = 1;
+
+const HEADER_LEN: usize = ARCHIVE_MAGIC.len() + 1 + 32;
+
+/// Outcome of `Engine::import_archive`.
+#[derive(Debug, Default)]
+pub struct ArchiveImport {
+    /// Bundles in the archive that were new to this engine.
+    pub imported: usize,
+    /// Bundles already present (importing the same archive twice is a no-op).
+    pub already_present: usize,
+    pub conflicts: Vec<ConflictRecord>,
+}
+
+pub(crate) fn encode_archive<'a>(
+    bundles: impl IntoIterator<Item = (&'a Bundle, &'a [Operation])>,
+) -> Result<Vec<u8>, EngineError> {
+    let mut body = Vec::new();
+    for (bundle, operations) in bundles {
+        let entry = encode_bundle_wire(bundle, operations, Compression::Zstd)?;
+        let len = u32::try_from(entry.len())
+            .map_err(|_| EngineError::InvalidArchive("bundle too large".into()))?;
+        body.extend_from_slice(&len.to_be_bytes());
+        body.extend_from_slice(&entry);
+    }
+
+    let mut out = Vec::with_capacity(HEADER_LEN + body.len());
+    out.extend_from_slice(&ARCHIVE_MAGIC);
+    out.push(ARCHIVE_VERSION);
+    out.extend_from_slice(blake3::hash(&body).as_bytes());
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+/// Decode an archive, checking its checksum and every bundle's integrity.
+pub(crate) fn decode_archive(bytes: &[u8]) -> Result<Vec<(Bundle, Vec<Operation>)>, EngineError> {
+    if bytes.len() < HEADER_LEN || bytes[..ARCHIVE_MAGIC.len()] != ARCHIVE_MAGIC {
+        return Err(EngineError::InvalidArchive("not an oplog archive".into()));
+    }
+    let version = bytes[ARCHIVE_MAGIC.len()];
+    if version > ARCHIVE_VERSION {
+        return Err(EngineError::InvalidArchive(format!("unsupported archive version {version}")));
+    }
+    let checksum = &bytes[ARCHIVE_MAGIC.len() + 1..HEADER_LEN];
+    let mut body = &bytes[HEADER_LEN..];
+    if blake3::hash(body).as_bytes() != checksum {
+        return Err(EngineError::InvalidArchive("checksum mismatch".into()));
+    }
+
+    let mut bundles = Vec::new();
+    while !body.is_empty() {
+        let Some((len, rest)) = body.split_first_chunk::<4>() else {
+            return Err(EngineError::InvalidArchive("truncated entry header".into()));
+        };
+        let len = u32::from_be_bytes(*len) as usize;
+        if rest.len() < len {
+            return Err(EngineError::InvalidArchive("truncated entry".into()));
+        }
+        let (bundle, operations) = decode_bundle_wire(&rest[..len])?;
+        crate::ingest::verify_bundle_integrity(&bundle, &operations).map_err(EngineError::InvalidArchive)?;
+        bundles.push((bundle, operations));
+        body = &rest[len..];
+    }
+    Ok(bundles)
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Write every bundle not covered by `since` to a single checksummed archive
+    /// file at `path`. Pass an empty clock to export the whole oplog. Returns the
+    /// number of bundles written.
+    pub fn export_archive(&self, path: impl AsRef<Path>, since: &VectorClock) -> Result<usize, EngineError> {
+        let mut bundles = Vec::new();
+        for bundle_id in self.bundles_missing_from(since)? {
+            let bundle = self
+                .storage
+                .get_bundle(bundle_id)?
+                .ok_or_else(|| EngineError::BundleNotFound(bundle_id.to_string()))?;
+            let operations = self.storage.get_ops_by_bundle(bundle_id)?;
+            bundles.push((bundle, operations));
+        }
+        let bytes = encode_archive(bundles.iter().map(|(b, ops)| (b, ops.as_slice())))?;
+        std::fs::write(path, bytes)?;
+        Ok(bundles.len())
+    }
+
+    /// Verify and ingest an archive written by `export_archive`. The whole file
+    /// is checked (checksum and signatures) before anything is ingested, and
+    /// bundles already present are skipped, so re-importing is harmless.
+    pub fn import_archive(&mut self, path: impl AsRef<Path>) -> Result<ArchiveImport, EngineError> {
+        let bundles = decode_archive(&std::fs::read(path)?)?;
+
+        let mut report = ArchiveImport::default();
+        let mut fresh = Vec::new();
+        for (bundle, operations) in bundles {
+            if self.storage.get_bundle(bundle.bundle_id)?.is_some() {
+                report.already_present += 1;
+            } else {
+                fresh.push((bundle, operations));
+            }
+        }
+        report.imported = fresh.len();
+        report.conflicts = self.ingest_bundles(&fresh)?.conflicts;
+        Ok(report)
+    }
+}
@@ -0,0 +1,323 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use openprod_core::{
+    hlc::{ClockSource, HlcClock, SystemClock, MAX_DRIFT_MS},
+    identity::ActorIdentity,
+    operations::BundleMeta,
+    sealed::WorkspaceKey,
+    sync_scope::SyncScope,
+};
+use openprod_storage::EngineStorage;
+#[cfg(feature = "sqlite")]
+use openprod_storage::SqliteStorage;
+
+use crate::error::EngineError;
+use crate::modules::ModuleRegistry;
+use crate::overlay::OverlayManager;
+use crate::presence::PresenceTable;
+use crate::trust::TrustPolicy;
+use crate::undo::UndoManager;
+use crate::watch::ChangeSet;
+use crate::Engine;
+
+/// Undo steps kept when no depth is configured.
+pub const DEFAULT_UNDO_DEPTH: usize = 100;
+
+/// Configures an [`Engine`] before it starts. `Engine::new` is
+/// `EngineBuilder::new().build(identity, storage)`.
+///
+/// ```no_run
+/// # use openprod_core::identity::ActorIdentity;
+/// # use openprod_engine::{EngineBuilder, TrustPolicy};
+/// let engine = EngineBuilder::new()
+///     .undo_depth(500)
+///     .trust_policy(TrustPolicy::QuarantineUnknown)
+///     .verify_signatures(true)
+///     .open(ActorIdentity::generate(), "show.db")?;
+/// # Ok::<(), openprod_engine::EngineError>(())
+/// ```
+// Not `Debug`: it may hold the workspace key
+#[derive(Clone)]
+pub struct EngineBuilder {
+    undo_depth: usize,
+    trust_policy: TrustPolicy,
+    group_user_devices: bool,
+    verify_signatures: bool,
+    verify_on_append: bool,
+    scan_drift_on_ingest: bool,
+    derive_op_ids: bool,
+    sync_scope: SyncScope,
+    modules: ModuleRegistry,
+    workspace_key: Option<WorkspaceKey>,
+    clock_source: Arc<dyn ClockSource>,
+    clock_skew_tolerance: Duration,
+    clamp_clock_ahead: bool,
+    max_clock_counter: u32,
+    unique_edge_types: Vec<String>,
+    facet_fields: Vec<(String, Vec<String>)>,
+    default_meta: Option<BundleMeta>,
+    #[cfg(feature = "sqlite")]
+    pragmas: Vec<(String, String)>,
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        Self {
+            undo_depth: DEFAULT_UNDO_DEPTH,
+            trust_policy: TrustPolicy::default(),
+            group_user_devices: true,
+            verify_signatures: false,
+            verify_on_append: false,
+            scan_drift_on_ingest: true,
+            derive_op_ids: false,
+            sync_scope: SyncScope::All,
+            modules: ModuleRegistry::default(),
+            workspace_key: None,
+            clock_source: Arc::new(SystemClock),
+            clock_skew_tolerance: Duration::from_millis(MAX_DRIFT_MS),
+            clamp_clock_ahead: false,
+            max_clock_counter: u32::MAX,
+            unique_edge_types: Vec::new(),
+            facet_fields: Vec::new(),
+            default_meta: None,
+            #[cfg(feature = "sqlite")]
+            pragmas: Vec::new(),
+        }
+    }
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Undo steps kept per undo scope before the scope's oldest is dropped.
+    pub fn undo_depth(mut self, depth: usize) -> Self {
+        self.undo_depth = depth;
+        self
+    }
+
+    /// How bundles from actors with no trust decision are treated.
+    pub fn trust_policy(mut self, policy: TrustPolicy) -> Self {
+        self.trust_policy = policy;
+        self
+    }
+
+    /// Whether devices linked to the same user count as one writer for
+    /// conflict detection (on by default), so they overwrite rather than
+    /// conflict with each other.
+    pub fn group_user_devices(mut self, enabled: bool) -> Self {
+        self.group_user_devices = enabled;
+        self
+    }
+
+    /// Check every new bundle's signatures and checksum in `ingest_bundle(s)`,
+    /// failing the ingest with `InvalidBundle` if one doesn't verify. Off by
+    /// default: transports that go through `ingest_raw_bundles` are already
+    /// verified there.
+    pub fn verify_signatures(mut self, enabled: bool) -> Self {
+        self.verify_signatures = enabled;
+        self
+    }
+
+    /// Check every bundle with `Bundle::verify` right before it's written,
+    /// local ones included, failing with `InvalidBundle` instead of storing
+    /// one that was corrupted or tampered with on its way to storage. Off by
+    /// default: it re-hashes and re-verifies every write.
+    pub fn verify_on_append(mut self, enabled: bool) -> Self {
+        self.verify_on_append = enabled;
+        self
+    }
+
+    /// Flag overlay edits as drifted when ingest changes the canonical value
+    /// under them (on by default). Turning it off saves a write per ingested
+    /// field on relays and servers that never hold overlays; overlays on such
+    /// an engine won't see remote changes as drift.
+    pub fn scan_drift_on_ingest(mut self, enabled: bool) -> Self {
+        self.scan_drift_on_ingest = enabled;
+        self
+    }
+
+    /// Replicate only part of the workspace, e.g. `SyncScope::facets(["Task",
+    /// "Note"])` on a phone. Peers send such an engine bundles trimmed to the
+    /// scope (`Engine::bundles_since`), so it verifies them without their
+    /// checksums, doesn't wait for causal dependencies it may never get, and
+    /// keeps entities it only knows by reference as placeholders. Since the
+    /// bundles it received may be partial, it only ever serves its own.
+    /// `SyncScope::All` by default.
+    pub fn sync_scope(mut self, scope: SyncScope) -> Self {
+        self.sync_scope = scope;
+        self
+    }
+
+    /// Derive the ids of local ops from their actor, bundle, position and
+    /// payload (`OpId::derive`) instead of drawing them at random, so any
+    /// peer can recompute an op's id and an identical op re-sent keeps it.
+    /// Off by default.
+    pub fn derive_op_ids(mut self, enabled: bool) -> Self {
+        self.derive_op_ids = enabled;
+        self
+    }
+
+    /// Module versions to stamp on local ops and check on ingest.
+    pub fn modules(mut self, modules: ModuleRegistry) -> Self {
+        self.modules = modules;
+        self
+    }
+
+    /// Meta stamped on every local bundle, e.g. the origin device and app
+    /// version. Per-write meta takes precedence for the fields it sets.
+    pub fn default_bundle_meta(mut self, meta: BundleMeta) -> Self {
+        self.default_meta = Some(meta);
+        self
+    }
+
+    /// Key for sealing outgoing bundles and opening incoming sealed ones.
+    pub fn workspace_key(mut self, key: WorkspaceKey) -> Self {
+        self.workspace_key = Some(key);
+        self
+    }
+
+    /// Where HLC timestamps read physical time from (the system clock by
+    /// default). Pass a `ManualClock` to drive time by hand in tests.
+    pub fn clock_source(mut self, source: impl ClockSource + 'static) -> Self {
+        self.clock_source = Arc::new(source);
+        self
+    }
+
+    /// How far ahead of local time an ingested bundle's HLC may be before it
+    /// is reported as clock skew (five minutes by default).
+    pub fn clock_skew_tolerance(mut self, tolerance: Duration) -> Self {
+        self.clock_skew_tolerance = tolerance;
+        self
+    }
+
+    /// Fail local writes with `HlcClockAhead` rather than stamp them beyond
+    /// the skew tolerance ahead of physical time (off by default).
+    pub fn clamp_clock_ahead(mut self, enabled: bool) -> Self {
+        self.clamp_clock_ahead = enabled;
+        self
+    }
+
+    /// Highest HLC logical counter to issue within one millisecond
+    /// (`u32::MAX` by default).
+    pub fn max_clock_counter(mut self, max_counter: u32) -> Self {
+        self.max_clock_counter = max_counter;
+        self
+    }
+
+    /// Declare `edge_type` unique per (source, target) pair when the engine
+    /// is built (see `Engine::declare_unique_edge_type`). Repeat for more types.
+    pub fn unique_edge_type(mut self, edge_type: impl Into<String>) -> Self {
+        self.unique_edge_types.push(edge_type.into());
+        self
+    }
+
+    /// Declare the fields `facet_type` owns when the engine is built (see
+    /// `Engine::set_facet_fields`). Repeat for more facets.
+    pub fn facet_fields<I, T>(mut self, facet_type: impl Into<String>, field_keys: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.facet_fields.push((facet_type.into(), field_keys.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// A SQLite `PRAGMA` to set after the default ones (e.g. `synchronous`,
+    /// `cache_size`). Applied by `open` and `open_in_memory`; `build` takes
+    /// storage that is already open and leaves it as it is.
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite_pragma(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.pragmas.push((name.into(), value.into()));
+        self
+    }
+
+    /// Create the engine over `storage`, restoring the active overlay (if any)
+    /// so a restart doesn't silently drop back to canonical.
+    pub fn build<S: EngineStorage>(self, identity: ActorIdentity, storage: S) -> Result<Engine<S>, EngineError> {
+        let mut engine = Engine {
+            identity,
+            clock: HlcClock::with_source(self.clock_source),
+            storage,
+            undo_manager: UndoManager::new(self.undo_depth),
+            overlay_manager: OverlayManager::new(),
+            bundle_listeners: Vec::new(),
+            entity_watchers: Vec::new(),
+            query_watchers: Vec::new(),
+            conflict_watchers: Vec::new(),
+            conflict_events: Vec::new(),
+            changes: ChangeSet::default(),
+            workspace_key: self.workspace_key,
+            trust_policy: self.trust_policy,
+            modules: self.modules,
+            interceptors: Vec::new(),
+            group_user_devices: self.group_user_devices,
+            verify_signatures: self.verify_signatures,
+            verify_on_append: self.verify_on_append,
+            scan_drift_on_ingest: self.scan_drift_on_ingest,
+            derive_op_ids: self.derive_op_ids,
+            sync_scope: self.sync_scope,
+            default_meta: self.default_meta,
+            scoped_meta: None,
+            session_meta: None,
+            webhook_transport: None,
+            webhooks_due: false,
+            presence: PresenceTable::default(),
+            presence_listeners: Vec::new(),
+            clock_regression: None,
+            key_rotations: OnceLock::new(),
+        };
+        engine.set_clock_skew_tolerance(self.clock_skew_tolerance);
+        engine.set_clamp_clock_ahead(self.clamp_clock_ahead);
+        engine.set_max_clock_counter(self.max_clock_counter);
+        engine.restore_clock()?;
+        for edge_type in &self.unique_edge_types {
+            engine.declare_unique_edge_type(edge_type)?;
+        }
+        for (facet_type, field_keys) in &self.facet_fields {
+            let field_keys: Vec<&str> = field_keys.iter().map(String::as_str).collect();
+            engine.set_facet_fields(facet_type, &field_keys)?;
+        }
+        engine.restore_active_overlay()?;
+        Ok(engine)
+    }
+
+    /// Open (or create) the SQLite database at `path`, apply the configured
+    /// pragmas, and build the engine over it.
+    #[cfg(feature = "sqlite")]
+    pub fn open(self, identity: ActorIdentity, path: &str) -> Result<Engine<SqliteStorage>, EngineError> {
+        let storage = SqliteStorage::open(path)?;
+        self.build_sqlite(identity, storage)
+    }
+
+    /// `open` scoped to one workspace of the file: its oplog, entities, clock,
+    /// peers and settings are separate from every other workspace's, so many
+    /// small projects can share one database. `open` uses `DEFAULT_WORKSPACE`.
+    #[cfg(feature = "sqlite")]
+    pub fn open_workspace(
+        self,
+        identity: ActorIdentity,
+        path: &str,
+        workspace: &str,
+    ) -> Result<Engine<SqliteStorage>, EngineError> {
+        let storage = SqliteStorage::open_workspace(path, workspace)?;
+        self.build_sqlite(identity, storage)
+    }
+
+    /// `open` for a fresh in-memory database.
+    #[cfg(feature = "sqlite")]
+    pub fn open_in_memory(self, identity: ActorIdentity) -> Result<Engine<SqliteStorage>, EngineError> {
+        let storage = SqliteStorage::open_in_memory()?;
+        self.build_sqlite(identity, storage)
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn build_sqlite(self, identity: ActorIdentity, storage: SqliteStorage) -> Result<Engine<SqliteStorage>, EngineError> {
+        for (name, value) in &self.pragmas {
+            storage.set_pragma(name, value)?;
+        }
+        self.build(identity, storage)
+    }
+}
@@ -0,0 +1,26 @@
+use std::fmt;
+
+use crate::EngineError;
+
+/// Why one item of a bulk command was rejected. `index` is the item's
+/// position in the input, so callers can flag the offending row.
+#[derive(Debug)]
+pub struct BulkItemError {
+    pub index: usize,
+    pub error: EngineError,
+}
+
+impl fmt::Display for BulkItemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "item {}: {}", self.index, self.error)
+    }
+}
+
+/// Summarize per-item errors for `EngineError::BulkValidation`'s message.
+pub(crate) fn describe(errors: &[BulkItemError]) -> String {
+    let first = errors.first().map(ToString::to_string).unwrap_or_default();
+    match errors.len() {
+        1 => first,
+        n => format!("{first} (and {} more)", n - 1),
+    }
+}
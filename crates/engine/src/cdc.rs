@@ -0,0 +1,221 @@
+//! Change data capture: the oplog as a stream of normalized change events
+//! (`Engine::cdc_events`) for search indexers, analytics and other systems
+//! that mirror a workspace.
+//!
+//! The cursor is the local sequence number of the op an event came from, in
+//! the order this replica stored ops (local writes and ingested bundles
+//! alike), so it only grows and survives restarts. Events carry the state
+//! as of the call rather than as of the op: applying them in order as
+//! upserts leaves a mirror matching the workspace, even when a synced op
+//! lost to a newer write.
+
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::Hlc,
+    ids::{ActorId, BundleId, ConflictId, EdgeId, EntityId, OpId},
+    operations::{Operation, OperationPayload},
+};
+use openprod_storage::{ConflictStatus, EngineStorage};
+
+use crate::Engine;
+use crate::error::EngineError;
+
+/// One change, and the op it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CdcEvent {
+    /// Resume with this to get the events after it. Events from the same op
+    /// share a cursor and are always returned together.
+    pub cursor: u64,
+    pub op_id: OpId,
+    pub bundle_id: BundleId,
+    pub actor_id: ActorId,
+    pub hlc: Hlc,
+    pub change: CdcChange,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CdcChange {
+    /// Created, restored, or its facets changed. `facets` are the ones
+    /// attached now, sorted.
+    EntityUpserted { entity_id: EntityId, facets: Vec<String> },
+    EntityDeleted { entity_id: EntityId },
+    /// `value` is the field's current value, `None` once cleared.
+    FieldChanged { entity_id: EntityId, field_key: String, value: Option<FieldValue> },
+    /// Created, deleted, restored, or a property changed.
+    EdgeChanged { edge_id: EdgeId, edge_type: String, source_id: EntityId, target_id: EntityId, deleted: bool },
+    /// The op is one of a conflict's competing writes, reopened it or
+    /// resolved it; `status` is where the conflict stands now.
+    Conflict { conflict_id: ConflictId, entity_id: EntityId, field_key: String, status: ConflictStatus },
+}
+
+impl CdcChange {
+    /// The event type as webhooks name it, e.g. `"field_changed"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::EntityUpserted { .. } => "entity_upserted",
+            Self::EntityDeleted { .. } => "entity_deleted",
+            Self::FieldChanged { .. } => "field_changed",
+            Self::EdgeChanged { .. } => "edge_changed",
+            Self::Conflict { .. } => "conflict",
+        }
+    }
+}
+
+/// Returned by `Engine::cdc_events`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CdcBatch {
+    pub events: Vec<CdcEvent>,
+    /// Pass this as `after_cursor` next time. It also moves past ops that
+    /// yield no events (e.g. ops only kept in the oplog). An empty batch
+    /// means the stream is caught up.
+    pub cursor: u64,
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Entities whose canonical state changed in ops stored after `since`,
+    /// in id order, so a polling client re-renders those rather than diffing
+    /// everything. Pass the `changes_watermark` from the previous poll (0 for
+    /// the whole history).
+    ///
+    /// `since` is a position in this replica's oplog, the same cursor as
+    /// `cdc_events`, so ops synced in count from when they arrive, however old
+    /// their HLC. Overlay edits aren't included.
+    pub fn entities_changed_since(&self, since: u64) -> Result<Vec<EntityId>, EngineError> {
+        Ok(self.storage.get_entities_changed_since(since)?)
+    }
+
+    /// Edges created, changed or deleted in ops stored after `since`, in id
+    /// order. Same caveats as `entities_changed_since`.
+    pub fn edges_changed_since(&self, since: u64) -> Result<Vec<EdgeId>, EngineError> {
+        Ok(self.storage.get_edges_changed_since(since)?)
+    }
+
+    /// The position of the newest stored op: the `since` for the next poll.
+    /// 0 while the oplog is empty.
+    pub fn changes_watermark(&self) -> Result<u64, EngineError> {
+        self.cdc_tail()
+    }
+
+    /// Change events for ops stored after `after_cursor` (0 for the whole
+    /// history), up to about `limit` of them; see the `cdc` module docs.
+    /// An op's events are never split across batches.
+    pub fn cdc_events(&self, after_cursor: u64, limit: usize) -> Result<CdcBatch, EngineError> {
+        let limit = limit.max(1);
+        let mut batch = CdcBatch { events: Vec::new(), cursor: after_cursor };
+        loop {
+            let page = self.storage.get_ops_after_seq(batch.cursor, limit)?;
+            let exhausted = page.len() < limit;
+            for (seq, op) in page {
+                let changes = self.cdc_changes(&op)?;
+                if !batch.events.is_empty() && batch.events.len() + changes.len() > limit {
+                    return Ok(batch);
+                }
+                batch.events.extend(changes.into_iter().map(|change| CdcEvent {
+                    cursor: seq,
+                    op_id: op.op_id,
+                    bundle_id: op.bundle_id,
+                    actor_id: op.actor_id,
+                    hlc: op.hlc,
+                    change,
+                }));
+                batch.cursor = seq;
+            }
+            if exhausted || batch.events.len() >= limit {
+                return Ok(batch);
+            }
+        }
+    }
+
+    /// The cursor of the newest op: `cdc_events` from here returns only
+    /// changes made after this call.
+    pub fn cdc_tail(&self) -> Result<u64, EngineError> {
+        // Sequence numbers are at least the op count; backends may leave gaps
+        let mut tail = self.storage.op_count()?;
+        loop {
+            let page = self.storage.get_ops_after_seq(tail, 1000)?;
+            match page.last() {
+                Some((seq, _)) => tail = *seq,
+                None => return Ok(tail),
+            }
+        }
+    }
+
+    /// What `op` changed, as of now.
+    fn cdc_changes(&self, op: &Operation) -> Result<Vec<CdcChange>, EngineError> {
+        let mut changes = Vec::new();
+        match &op.payload {
+            OperationPayload::CreateEntity { entity_id, .. }
+            | OperationPayload::DeleteEntity { entity_id, .. }
+            | OperationPayload::RestoreEntity { entity_id }
+            | OperationPayload::AttachFacet { entity_id, .. }
+            | OperationPayload::DetachFacet { entity_id, .. }
+            | OperationPayload::RestoreFacet { entity_id, .. } => {
+                let entity_id = *entity_id;
+                let live = self.storage.get_entity(entity_id)?.is_some_and(|e| !e.deleted);
+                changes.push(if live {
+                    let mut facets: Vec<String> = self
+                        .storage
+                        .get_facets(entity_id)?
+                        .into_iter()
+                        .filter(|f| !f.detached)
+                        .map(|f| f.facet_type)
+                        .collect();
+                    facets.sort();
+                    CdcChange::EntityUpserted { entity_id, facets }
+                } else {
+                    CdcChange::EntityDeleted { entity_id }
+                });
+            }
+            OperationPayload::SetField { entity_id, field_key, .. }
+            | OperationPayload::ClearField { entity_id, field_key } => {
+                changes.push(CdcChange::FieldChanged {
+                    entity_id: *entity_id,
+                    field_key: field_key.clone(),
+                    value: self.storage.get_field(*entity_id, field_key)?,
+                });
+                if let Some(conflict) = self.storage.get_latest_conflict_for_field(*entity_id, field_key)?
+                    && (conflict.values.iter().any(|v| v.op_id == op.op_id) || conflict.reopened_by_op == Some(op.op_id))
+                {
+                    changes.push(CdcChange::Conflict {
+                        conflict_id: conflict.conflict_id,
+                        entity_id: conflict.entity_id,
+                        field_key: conflict.field_key,
+                        status: conflict.status,
+                    });
+                }
+            }
+            OperationPayload::ResolveConflict { conflict_id, entity_id, field_key, .. } => {
+                changes.push(CdcChange::FieldChanged {
+                    entity_id: *entity_id,
+                    field_key: field_key.clone(),
+                    value: self.storage.get_field(*entity_id, field_key)?,
+                });
+                if let Some(conflict) = self.storage.get_conflict(*conflict_id)? {
+                    changes.push(CdcChange::Conflict {
+                        conflict_id: conflict.conflict_id,
+                        entity_id: conflict.entity_id,
+                        field_key: conflict.field_key,
+                        status: conflict.status,
+                    });
+                }
+            }
+            OperationPayload::CreateEdge { edge_id, .. }
+            | OperationPayload::DeleteEdge { edge_id }
+            | OperationPayload::RestoreEdge { edge_id }
+            | OperationPayload::SetEdgeProperty { edge_id, .. }
+            | OperationPayload::ClearEdgeProperty { edge_id, .. } => {
+                if let Some(edge) = self.storage.get_edge(*edge_id)? {
+                    changes.push(CdcChange::EdgeChanged {
+                        edge_id: edge.edge_id,
+                        edge_type: edge.edge_type,
+                        source_id: edge.source_id,
+                        target_id: edge.target_id,
+                        deleted: edge.deleted,
+                    });
+                }
+            }
+            _ => {}
+        }
+        Ok(changes)
+    }
+}
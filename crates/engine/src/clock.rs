@@ -0,0 +1,117 @@
+//! Hybrid logical clock upkeep: restoring it on open, ticking it for local
+//! writes, and guarding it against skewed remote clocks.
+
+use std::time::Duration;
+
+use openprod_core::{hlc::Hlc, operations::Bundle};
+use openprod_storage::EngineStorage;
+
+use crate::Engine;
+use crate::error::EngineError;
+use crate::ingest::{ClockSkew, IngestReport};
+
+impl<S: EngineStorage> Engine<S> {
+    /// How far ahead of local physical time an ingested bundle's HLC may be.
+    /// Bundles within it advance the local clock so later local edits order
+    /// after them; bundles beyond it are still ingested but reported in
+    /// `IngestReport::clock_skew` and don't drag the local clock forward.
+    /// Defaults to five minutes.
+    pub fn set_clock_skew_tolerance(&mut self, tolerance: Duration) {
+        self.clock.set_max_drift_ms(tolerance.as_millis().try_into().unwrap_or(u64::MAX));
+    }
+
+    pub fn clock_skew_tolerance(&self) -> Duration {
+        Duration::from_millis(self.clock.max_drift_ms())
+    }
+
+    /// When enabled, local writes fail with `HlcClockAhead` rather than be
+    /// stamped more than the skew tolerance ahead of physical time (e.g. after
+    /// the system clock was stepped back). Off by default.
+    pub fn set_clamp_clock_ahead(&mut self, enabled: bool) {
+        self.clock.set_clamp_ahead(enabled);
+    }
+
+    pub fn clamp_clock_ahead(&self) -> bool {
+        self.clock.clamp_ahead()
+    }
+
+    /// Highest HLC logical counter to issue within one millisecond; local
+    /// writes past it fail with `HlcCounterOverflow` until the clock moves on.
+    /// Defaults to `u32::MAX`.
+    pub fn set_max_clock_counter(&mut self, max_counter: u32) {
+        self.clock.set_max_counter(max_counter);
+    }
+
+    pub fn max_clock_counter(&self) -> u32 {
+        self.clock.max_counter()
+    }
+
+    /// If physical time was more than the skew tolerance behind the last HLC
+    /// issued before the engine was opened (the system clock was set back
+    /// while it was down), by how much. Timestamps stay monotonic regardless;
+    /// until physical time catches up they advance only the logical counter.
+    pub fn clock_regression(&self) -> Option<Duration> {
+        self.clock_regression
+    }
+
+    /// Resume the clock from the last HLC this replica issued: the one saved
+    /// by `tick`, or its latest op for storage written before that was saved.
+    /// A regression beyond the skew tolerance is recorded, or with
+    /// `clamp_clock_ahead` fails with `ClockRegression`, since every local
+    /// write would fail with `HlcClockAhead` anyway.
+    pub(crate) fn restore_clock(&mut self) -> Result<(), EngineError> {
+        let own_id = self.identity.actor_id();
+        let horizon = self.clock.now_ms()?.saturating_add(self.clock.max_drift_ms());
+        // Remote timestamps the clock merged before the restart; ones beyond
+        // the tolerance were never merged
+        let seen = self
+            .storage
+            .get_vector_clock()?
+            .entries()
+            .iter()
+            .filter(|(actor_id, hlc)| **actor_id == own_id || hlc.wall_ms() <= horizon)
+            .map(|(_, hlc)| *hlc)
+            .max();
+        let Some(last) = self.storage.get_last_hlc()?.max(seen) else {
+            return Ok(());
+        };
+        match self.clock.restore(last) {
+            Ok(()) => Ok(()),
+            Err(openprod_core::CoreError::ClockRegression { behind_ms, .. }) if !self.clock.clamp_ahead() => {
+                self.clock_regression = Some(Duration::from_millis(behind_ms));
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Issue the HLC for a local write, saving it so a restart resumes above it.
+    pub(crate) fn tick(&mut self) -> Result<Hlc, EngineError> {
+        let hlc = self.clock.tick()?;
+        self.storage.set_last_hlc(hlc)?;
+        Ok(hlc)
+    }
+
+    /// Merge a new bundle's HLC into the local clock, or report it as skewed
+    /// if it is beyond the tolerance.
+    pub(crate) fn observe_remote_clock(&mut self, bundle: &Bundle, report: &mut IngestReport) -> Result<(), EngineError> {
+        let now = self.clock.now_ms()?;
+        let ahead_ms = bundle.hlc.wall_ms().saturating_sub(now);
+        if ahead_ms > self.clock.max_drift_ms() {
+            report.clock_skew.push(ClockSkew {
+                bundle_id: bundle.bundle_id,
+                actor_id: bundle.actor_id,
+                hlc: bundle.hlc,
+                ahead: Duration::from_millis(ahead_ms),
+            });
+            return Ok(());
+        }
+        // Only a remote counter at its maximum can fail the merge; the bundle is
+        // still valid, so keep the local clock as it is
+        if let Ok(hlc) = self.clock.receive(&bundle.hlc) {
+            // Saved like a tick, so a restart doesn't resume below what it followed
+            self.storage.set_last_hlc(hlc)?;
+        }
+        Ok(())
+    }
+}
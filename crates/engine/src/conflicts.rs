@@ -0,0 +1,239 @@
+//! Conflict resolution and the conflict inbox.
+
+use std::time::Duration;
+
+use openprod_core::{
+    field_value::FieldValue,
+    ids::{BundleId, ConflictId, EntityId, OpId, OverlayId},
+    operations::{BundleType, OperationPayload},
+};
+use openprod_storage::{ConflictRecord, ConflictStatus, ConflictTriage, EngineStorage};
+
+use crate::Engine;
+use crate::error::EngineError;
+use crate::merge::{merge_text, TextMerge};
+
+impl<S: EngineStorage> Engine<S> {
+    // ========================================================================
+    // Conflict Resolution
+    // ========================================================================
+
+    /// Resolve a conflict by choosing a value.
+    /// `chosen_value: None` means resolve to cleared (tombstone).
+    /// Resolution is NOT undoable per spec.
+    pub fn resolve_conflict(
+        &mut self,
+        conflict_id: ConflictId,
+        chosen_value: Option<FieldValue>,
+    ) -> Result<BundleId, EngineError> {
+        // Load conflict
+        let conflict = self.storage.get_conflict(conflict_id)?
+            .ok_or_else(|| EngineError::ConflictNotFound(conflict_id.to_string()))?;
+
+        if conflict.status != ConflictStatus::Open {
+            return Err(EngineError::ConflictAlreadyResolved(conflict_id.to_string()));
+        }
+
+        self.storage.begin_transaction()?;
+
+        let result = (|| -> Result<BundleId, EngineError> {
+            // Create ResolveConflict operation payload
+            let payloads = vec![OperationPayload::ResolveConflict {
+                conflict_id,
+                entity_id: conflict.entity_id,
+                field_key: conflict.field_key.clone(),
+                chosen_value: chosen_value.clone(),
+            }];
+
+            // Execute as non-undoable
+            let (bundle_id, hlc) = self.execute_internal(BundleType::UserEdit, payloads, false)?;
+
+            // Update conflict record to resolved
+            let resolved_value_bytes = match &chosen_value {
+                Some(v) => Some(v.to_msgpack()
+                    .map_err(|e| EngineError::Core(openprod_core::CoreError::Serialization(e.to_string())))?),
+                None => None,
+            };
+            // Get the op_id from the bundle we just created
+            let ops = self.storage.get_ops_by_bundle(bundle_id)?;
+            let resolve_op_id = ops.first().map(|o| o.op_id)
+                .ok_or_else(|| EngineError::ConflictNotFound("no ops in resolve bundle".into()))?;
+
+            self.storage.update_conflict_resolved(
+                conflict_id,
+                hlc,
+                self.identity.actor_id(),
+                resolve_op_id,
+                resolved_value_bytes,
+            )?;
+
+            Ok(bundle_id)
+        })();
+
+        match result {
+            Ok(bundle_id) => {
+                self.storage.commit_transaction()?;
+                Ok(bundle_id)
+            }
+            Err(e) => {
+                let _ = self.rollback_transaction();
+                Err(e)
+            }
+        }
+    }
+
+    /// Three-way merge of a text conflict's branch tips against the value
+    /// they last had in common, line by line. A clean merge can be passed
+    /// straight to `resolve_conflict`; otherwise the hunks show where the
+    /// branches disagree. Fails if any tip is cleared or not `Text`.
+    pub fn suggest_text_merge(&self, conflict_id: ConflictId) -> Result<TextMerge, EngineError> {
+        let conflict = self.storage.get_conflict(conflict_id)?
+            .ok_or_else(|| EngineError::ConflictNotFound(conflict_id.to_string()))?;
+        let mut branches = Vec::new();
+        for tip in &conflict.values {
+            let value = tip.value.as_deref().map(FieldValue::from_msgpack).transpose()
+                .map_err(|e| EngineError::Core(openprod_core::CoreError::Serialization(e.to_string())))?;
+            match value {
+                Some(FieldValue::Text(text)) => branches.push(text),
+                _ => return Err(EngineError::NotTextConflict(conflict_id.to_string())),
+            }
+        }
+        let base = match self.conflict_base(&conflict)? {
+            Some(FieldValue::Text(text)) => text,
+            _ => String::new(),
+        };
+        let branches: Vec<&str> = branches.iter().map(String::as_str).collect();
+        Ok(merge_text(&base, &branches))
+    }
+
+    /// Open a draft overlay showing one side of a conflict: the field set to
+    /// `chosen_tip`'s value (a tip `op_id` from the conflict's `values`),
+    /// plus the other fields that tip's bundle wrote, so the branch can be
+    /// explored in context before resolving. The overlay becomes active like
+    /// one from `create_overlay`; discarding it leaves the conflict as it was.
+    pub fn overlay_from_conflict(&mut self, conflict_id: ConflictId, chosen_tip: OpId) -> Result<OverlayId, EngineError> {
+        let conflict = self.storage.get_conflict(conflict_id)?
+            .ok_or_else(|| EngineError::ConflictNotFound(conflict_id.to_string()))?;
+        if conflict.status != ConflictStatus::Open {
+            return Err(EngineError::ConflictAlreadyResolved(conflict_id.to_string()));
+        }
+        let tip = conflict.values.iter().find(|tip| tip.op_id == chosen_tip)
+            .ok_or_else(|| EngineError::NotConflictTip(format!("{chosen_tip} in conflict {conflict_id}")))?;
+
+        let value = tip.value.as_deref().map(FieldValue::from_msgpack).transpose()
+            .map_err(|e| EngineError::Core(openprod_core::CoreError::Serialization(e.to_string())))?;
+        let mut payloads = vec![match value {
+            Some(value) => OperationPayload::SetField { entity_id: conflict.entity_id, field_key: conflict.field_key.clone(), value },
+            None => OperationPayload::ClearField { entity_id: conflict.entity_id, field_key: conflict.field_key.clone() },
+        }];
+        // The rest of the edit the tip was part of, where its entity still exists
+        let tip_op = self.storage.get_ops_by_entity(conflict.entity_id)?.into_iter().find(|op| op.op_id == chosen_tip);
+        if let Some(tip_op) = tip_op {
+            for op in self.storage.get_ops_by_bundle(tip_op.bundle_id)? {
+                let (OperationPayload::SetField { entity_id, field_key, .. }
+                | OperationPayload::ClearField { entity_id, field_key }) = &op.payload
+                else {
+                    continue;
+                };
+                let same_field = *entity_id == conflict.entity_id && *field_key == conflict.field_key;
+                if !same_field && self.require_live_entity(*entity_id).is_ok() {
+                    payloads.push(op.payload);
+                }
+            }
+        }
+
+        let overlay_id = self.create_overlay(&format!("{} from {}", conflict.field_key, tip.actor_id))?;
+        if let Err(e) = self.execute_internal(BundleType::UserEdit, payloads, true) {
+            let _ = self.discard_overlay(overlay_id);
+            return Err(e);
+        }
+        Ok(overlay_id)
+    }
+
+    /// The conflicted field's value as of the newest write every branch
+    /// tip's writer had seen, by the tip bundles' vector clocks. `None` if
+    /// there is no such write or it cleared the field.
+    fn conflict_base(&self, conflict: &ConflictRecord) -> Result<Option<FieldValue>, EngineError> {
+        let ops = self.storage.get_ops_by_entity(conflict.entity_id)?;
+        let mut tips = Vec::new();
+        for tip in &conflict.values {
+            let Some(op) = ops.iter().find(|op| op.op_id == tip.op_id) else { continue };
+            tips.push((tip, self.storage.get_bundle_vector_clock(op.bundle_id)?));
+        }
+        let mut base = None;
+        for op in &ops {
+            let value = match &op.payload {
+                OperationPayload::SetField { field_key, value, .. } if *field_key == conflict.field_key => {
+                    Some(value.clone())
+                }
+                OperationPayload::ClearField { field_key, .. } if *field_key == conflict.field_key => None,
+                OperationPayload::ResolveConflict { field_key, chosen_value, .. } if *field_key == conflict.field_key => {
+                    chosen_value.clone()
+                }
+                _ => continue,
+            };
+            let seen_by_all = tips.iter().all(|(tip, vc)| {
+                (op.actor_id == tip.actor_id && op.hlc < tip.hlc)
+                    || vc.as_ref().and_then(|vc| vc.get(&op.actor_id)).is_some_and(|known| *known >= op.hlc)
+            });
+            if seen_by_all && !conflict.values.iter().any(|tip| tip.op_id == op.op_id) {
+                base = value;
+            }
+        }
+        Ok(base)
+    }
+
+    // ========================================================================
+    // Conflict Queries
+    // ========================================================================
+
+    pub fn get_open_conflicts_for_entity(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<Vec<ConflictRecord>, EngineError> {
+        Ok(self.storage.get_open_conflicts_for_entity(entity_id)?)
+    }
+
+    /// Every open conflict in the workspace, oldest first.
+    pub fn get_open_conflicts(&self) -> Result<Vec<ConflictRecord>, EngineError> {
+        Ok(self.storage.get_open_conflicts()?)
+    }
+
+    pub fn get_conflict(
+        &self,
+        conflict_id: ConflictId,
+    ) -> Result<Option<ConflictRecord>, EngineError> {
+        Ok(self.storage.get_conflict(conflict_id)?)
+    }
+
+    /// This replica's triage of a conflict (seen, snoozed).
+    pub fn conflict_triage(&self, conflict_id: ConflictId) -> Result<ConflictTriage, EngineError> {
+        self.storage
+            .get_conflict_triage(conflict_id)?
+            .ok_or_else(|| EngineError::ConflictNotFound(conflict_id.to_string()))
+    }
+
+    /// Mark a conflict seen (or unseen again with `seen: false`), e.g. once
+    /// the user has opened it. Local only; reopening or extending the
+    /// conflict marks it unseen.
+    pub fn mark_conflict_seen(&mut self, conflict_id: ConflictId, seen: bool) -> Result<(), EngineError> {
+        let mut triage = self.conflict_triage(conflict_id)?;
+        triage.seen_at = if seen { Some(self.clock.now_ms()? as i64) } else { None };
+        Ok(self.storage.set_conflict_triage(conflict_id, triage)?)
+    }
+
+    /// Leave a conflict out of `unseen_conflict_count` for `duration`. Local
+    /// only; reopening or extending the conflict ends the snooze.
+    pub fn snooze_conflict(&mut self, conflict_id: ConflictId, duration: Duration) -> Result<(), EngineError> {
+        let mut triage = self.conflict_triage(conflict_id)?;
+        let duration_ms = i64::try_from(duration.as_millis()).unwrap_or(i64::MAX);
+        triage.snoozed_until = Some((self.clock.now_ms()? as i64).saturating_add(duration_ms));
+        Ok(self.storage.set_conflict_triage(conflict_id, triage)?)
+    }
+
+    /// Open conflicts neither seen nor currently snoozed: the number for a
+    /// conflict badge.
+    pub fn unseen_conflict_count(&self) -> Result<u64, EngineError> {
+        Ok(self.storage.count_unseen_conflicts(self.clock.now_ms()? as i64)?)
+    }
+}
@@ -0,0 +1,231 @@
+//! Multi-device user grouping.
+//!
+//! A user is a name claimed by several actor keys (one per device). Each link
+//! is a system entity with the `USER_DEVICE_FACET` facet holding the `user`
+//! name and the linked `actor`. A device belongs to a user once it has linked
+//! itself (its own claim) and an existing member has linked it too. The first
+//! self-claim for a name founds the user; an actor belongs to at most one user.
+//!
+//! Rotated keys are resolved to their original key before grouping, so a
+//! device keeps its membership across rotations.
+
+use std::collections::{HashMap, HashSet};
+
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::Hlc,
+    ids::{ActorId, EntityId},
+    operations::{BundleType, OperationPayload},
+};
+use openprod_storage::EngineStorage;
+
+use crate::Engine;
+use crate::error::EngineError;
+use crate::rotation::MAX_CHAIN;
+
+/// Facet marking user/device link records.
+pub const USER_DEVICE_FACET: &str = "openprod.user_device";
+
+pub(crate) const USER_FIELD: &str = "user";
+pub(crate) const ACTOR_FIELD: &str = "actor";
+
+/// A link record as read from storage.
+pub(crate) struct DeviceLink {
+    pub entity_id: EntityId,
+    pub created_at: Hlc,
+    pub created_by: ActorId,
+    pub user: String,
+    pub actor: ActorId,
+}
+
+/// Resolves actors to logical actors (through key rotations) and users.
+pub(crate) struct ActorGroups {
+    /// new key -> key it replaced
+    rotations: HashMap<ActorId, ActorId>,
+    /// logical actor -> user
+    users: HashMap<ActorId, String>,
+    group_devices: bool,
+}
+
+impl ActorGroups {
+    pub(crate) fn new(
+        rotations: impl IntoIterator<Item = (ActorId, ActorId)>,
+        mut links: Vec<DeviceLink>,
+        group_devices: bool,
+    ) -> Self {
+        let mut groups = Self {
+            rotations: rotations.into_iter().collect(),
+            users: HashMap::new(),
+            group_devices,
+        };
+        for link in &mut links {
+            link.actor = groups.logical(link.actor);
+            link.created_by = groups.logical(link.created_by);
+        }
+        links.sort_by_key(|l| (l.created_at, l.entity_id));
+
+        // Users are resolved in founding order, so an earlier user keeps a
+        // device that a later one also claims.
+        let mut seen = HashSet::new();
+        let founders: Vec<(String, ActorId)> = links
+            .iter()
+            .filter(|l| l.created_by == l.actor && seen.insert(l.user.clone()))
+            .map(|l| (l.user.clone(), l.actor))
+            .collect();
+        for (user, founder) in founders {
+            if groups.users.contains_key(&founder) {
+                continue;
+            }
+            let mut members = vec![founder];
+            loop {
+                let joined: Vec<ActorId> = links
+                    .iter()
+                    .filter(|l| {
+                        l.user == user
+                            && l.created_by == l.actor
+                            && !members.contains(&l.actor)
+                            && !groups.users.contains_key(&l.actor)
+                            && links.iter().any(|a| {
+                                a.user == user && a.actor == l.actor && members.contains(&a.created_by)
+                            })
+                    })
+                    .map(|l| l.actor)
+                    .collect();
+                if joined.is_empty() {
+                    break;
+                }
+                for actor in joined {
+                    if !members.contains(&actor) {
+                        members.push(actor);
+                    }
+                }
+            }
+            for member in members {
+                groups.users.insert(member, user.clone());
+            }
+        }
+        groups
+    }
+
+    /// The original key of `actor_id`'s rotation chain.
+    pub(crate) fn logical(&self, actor_id: ActorId) -> ActorId {
+        let mut current = actor_id;
+        for _ in 0..MAX_CHAIN {
+            match self.rotations.get(&current) {
+                Some(&old) if old != actor_id => current = old,
+                _ => break,
+            }
+        }
+        current
+    }
+
+    pub(crate) fn user_of(&self, actor_id: ActorId) -> Option<&str> {
+        self.users.get(&self.logical(actor_id)).map(String::as_str)
+    }
+
+    /// Logical actors that are members of `user`.
+    pub(crate) fn members(&self, user: &str) -> Vec<ActorId> {
+        let mut members: Vec<ActorId> =
+            self.users.iter().filter(|(_, u)| *u == user).map(|(a, _)| *a).collect();
+        members.sort();
+        members
+    }
+
+    /// Whether writes by `a` and `b` should never conflict with each other:
+    /// they are the same logical actor, or (when grouping is enabled) devices
+    /// of the same user.
+    pub(crate) fn same(&self, a: ActorId, b: ActorId) -> bool {
+        if a == b {
+            return true;
+        }
+        let (a, b) = (self.logical(a), self.logical(b));
+        a == b
+            || (self.group_devices
+                && matches!((self.users.get(&a), self.users.get(&b)), (Some(x), Some(y)) if x == y))
+    }
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Claim `user_id` for this device. The first claim founds the user; later
+    /// devices also need an existing member to `add_user_device` them.
+    pub fn join_user(&mut self, user_id: &str) -> Result<(), EngineError> {
+        self.write_device_link(user_id, self.actor_id())
+    }
+
+    /// Vouch for `actor_id` as another device of `user_id`. Only effective
+    /// when this device is a member and `actor_id` has joined the user too.
+    pub fn add_user_device(&mut self, user_id: &str, actor_id: ActorId) -> Result<(), EngineError> {
+        if self.user_of(self.actor_id())?.as_deref() != Some(user_id) {
+            return Err(EngineError::PermissionDenied(format!(
+                "{} is not a device of user {user_id}",
+                self.actor_id()
+            )));
+        }
+        self.write_device_link(user_id, actor_id)
+    }
+
+    /// The user `actor_id` is a device of, if any.
+    pub fn user_of(&self, actor_id: ActorId) -> Result<Option<String>, EngineError> {
+        Ok(self.actor_groups()?.user_of(actor_id).map(str::to_string))
+    }
+
+    /// Devices of `user_id`, as logical (original-key) actor ids.
+    pub fn user_devices(&self, user_id: &str) -> Result<Vec<ActorId>, EngineError> {
+        Ok(self.actor_groups()?.members(user_id))
+    }
+
+    /// Whether devices of the same user are treated as one actor by conflict
+    /// detection and undo. Enabled by default.
+    pub fn set_group_user_devices(&mut self, enabled: bool) {
+        self.group_user_devices = enabled;
+    }
+
+    pub fn group_user_devices(&self) -> bool {
+        self.group_user_devices
+    }
+
+    fn write_device_link(&mut self, user_id: &str, actor_id: ActorId) -> Result<(), EngineError> {
+        let entity_id = EntityId::new();
+        let payloads = vec![
+            OperationPayload::CreateEntity {
+                entity_id,
+                initial_table: Some(USER_DEVICE_FACET.to_string()),
+            },
+            OperationPayload::SetField {
+                entity_id,
+                field_key: USER_FIELD.to_string(),
+                value: FieldValue::Text(user_id.to_string()),
+            },
+            OperationPayload::SetField {
+                entity_id,
+                field_key: ACTOR_FIELD.to_string(),
+                value: FieldValue::Bytes(actor_id.as_bytes().to_vec()),
+            },
+        ];
+        self.execute_internal(BundleType::System, payloads, false)?;
+        Ok(())
+    }
+
+    pub(crate) fn actor_groups(&self) -> Result<ActorGroups, EngineError> {
+        let rotations = self.cached_key_rotations()?.iter().map(|r| (r.new_actor, r.old_actor));
+        let mut links = Vec::new();
+        for entity_id in self.storage.get_entities_by_facet(USER_DEVICE_FACET)? {
+            let Some(entity) = self.storage.get_entity(entity_id)? else {
+                continue;
+            };
+            if let Some(FieldValue::Text(user)) = self.storage.get_field(entity_id, USER_FIELD)?
+                && let Some(FieldValue::Bytes(bytes)) = self.storage.get_field(entity_id, ACTOR_FIELD)?
+                && let Ok(actor) = <[u8; 32]>::try_from(bytes.as_slice())
+            {
+                links.push(DeviceLink {
+                    entity_id,
+                    created_at: entity.created_at,
+                    created_by: entity.created_by,
+                    user,
+                    actor: ActorId::from_bytes(actor),
+                });
+            }
+        }
+        Ok(ActorGroups::new(rotations, links, self.group_user_devices))
+    }
+}
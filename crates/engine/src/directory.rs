@@ -0,0 +1,86 @@
+//! Actor directory.
+//!
+//! Display names are published as system entities with the
+//! `ACTOR_PROFILE_FACET` facet, one per actor, holding its `display_name`.
+//! Only the actor itself can name itself: a profile counts only if the actor
+//! created it and wrote the name. Resolved names are cached in the `actors`
+//! table after every local write or ingest.
+
+use std::collections::BTreeMap;
+
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::Hlc,
+    ids::{ActorId, EntityId},
+    operations::{BundleType, OperationPayload},
+};
+use openprod_storage::{ActorRecord, EngineStorage};
+
+use crate::Engine;
+use crate::error::EngineError;
+
+/// Facet marking actor profile records.
+pub const ACTOR_PROFILE_FACET: &str = "openprod.actor_profile";
+
+pub(crate) const DISPLAY_NAME_FIELD: &str = "display_name";
+
+impl<S: EngineStorage> Engine<S> {
+    /// Publish this actor's display name to every peer.
+    pub fn set_actor_display_name(&mut self, name: &str) -> Result<(), EngineError> {
+        let mut existing = None;
+        for entity_id in self.storage.get_entities_by_facet(ACTOR_PROFILE_FACET)? {
+            if let Some(entity) = self.storage.get_entity(entity_id)?
+                && entity.created_by == self.actor_id()
+                && !entity.deleted
+            {
+                existing = Some(entity_id);
+                break;
+            }
+        }
+
+        let mut payloads = Vec::new();
+        let entity_id = existing.unwrap_or_else(|| {
+            let entity_id = EntityId::new();
+            payloads.push(OperationPayload::CreateEntity {
+                entity_id,
+                initial_table: Some(ACTOR_PROFILE_FACET.to_string()),
+            });
+            entity_id
+        });
+        payloads.push(OperationPayload::SetField {
+            entity_id,
+            field_key: DISPLAY_NAME_FIELD.to_string(),
+            value: FieldValue::Text(name.to_string()),
+        });
+        self.execute_internal(BundleType::System, payloads, false)?;
+        self.refresh_actor_names()
+    }
+
+    /// Every actor that has authored ops, with its published display name
+    /// and activity stats, in first-seen order.
+    pub fn list_actors(&self) -> Result<Vec<ActorRecord>, EngineError> {
+        Ok(self.storage.list_actors()?)
+    }
+
+    /// Re-resolve published display names into the `actors` table cache.
+    pub(crate) fn refresh_actor_names(&mut self) -> Result<(), EngineError> {
+        let mut names: BTreeMap<ActorId, (Hlc, String)> = BTreeMap::new();
+        for entity_id in self.storage.get_entities_by_facet(ACTOR_PROFILE_FACET)? {
+            let Some(entity) = self.storage.get_entity(entity_id)? else {
+                continue;
+            };
+            if let Some(FieldValue::Text(name)) = self.storage.get_field(entity_id, DISPLAY_NAME_FIELD)?
+                && let Some((writer, hlc)) =
+                    self.storage.get_field_metadata(entity_id, DISPLAY_NAME_FIELD)?
+                && writer == entity.created_by
+                && names.get(&writer).is_none_or(|(latest, _)| hlc > *latest)
+            {
+                names.insert(writer, (hlc, name));
+            }
+        }
+        for (actor_id, (_, name)) in names {
+            self.storage.set_actor_display_name(actor_id, Some(&name))?;
+        }
+        Ok(())
+    }
+}
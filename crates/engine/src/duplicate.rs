@@ -0,0 +1,57 @@
+/// What `Engine::duplicate_entity` copies. The default copies every attached
+/// facet and every field, but no edges.
+///
+/// ```ignore
+/// let options = DuplicateOptions::new().fields(["title", "status"]).outgoing_edges(true);
+/// let (copy, _) = engine.duplicate_entity(task, &options)?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DuplicateOptions {
+    facets: Option<Vec<String>>,
+    fields: Option<Vec<String>>,
+    outgoing_edges: bool,
+}
+
+impl DuplicateOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy only these facets (those the source has attached).
+    pub fn facets<I, T>(mut self, facet_types: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.facets = Some(facet_types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Copy only these fields (those the source has a value for).
+    pub fn fields<I, T>(mut self, field_keys: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.fields = Some(field_keys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Also copy live outgoing edges, with their properties (off by default).
+    pub fn outgoing_edges(mut self, enabled: bool) -> Self {
+        self.outgoing_edges = enabled;
+        self
+    }
+
+    pub fn copies_facet(&self, facet_type: &str) -> bool {
+        self.facets.as_ref().is_none_or(|facets| facets.iter().any(|f| f == facet_type))
+    }
+
+    pub fn copies_field(&self, field_key: &str) -> bool {
+        self.fields.as_ref().is_none_or(|fields| fields.iter().any(|f| f == field_key))
+    }
+
+    pub fn copies_outgoing_edges(&self) -> bool {
+        self.outgoing_edges
+    }
+}
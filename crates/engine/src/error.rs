@@ -2,6 +2,8 @@ use openprod_core::CoreError;
 use openprod_storage::StorageError;
 use thiserror::Error;
 
+use crate::bulk::{self, BulkItemError};
+
 #[derive(Debug, Error)]
 pub enum EngineError {
     #[error("storage error: {0}")]
@@ -16,9 +18,30 @@ pub enum EngineError {
     #[error("entity already deleted: {0}")]
     EntityAlreadyDeleted(String),
 
+    #[error("entity not deleted: {0}")]
+    EntityNotDeleted(String),
+
+    #[error("edge not found: {0}")]
+    EdgeNotFound(String),
+
+    #[error("edge not deleted: {0}")]
+    EdgeNotDeleted(String),
+
+    #[error("list item not found: {0}")]
+    ListItemNotFound(String),
+
+    #[error("bundle not found: {0}")]
+    BundleNotFound(String),
+
     #[error("conflict not found: {0}")]
     ConflictNotFound(String),
 
+    #[error("conflict is not between text values: {0}")]
+    NotTextConflict(String),
+
+    #[error("not a branch tip of the conflict: {0}")]
+    NotConflictTip(String),
+
     #[error("conflict already resolved: {0}")]
     ConflictAlreadyResolved(String),
 
@@ -33,4 +56,46 @@ pub enum EngineError {
 
     #[error("unresolved drift on overlay: {0}")]
     UnresolvedDrift(String),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid archive: {0}")]
+    InvalidArchive(String),
+
+    #[error("invalid backup: {0}")]
+    InvalidBackup(String),
+
+    #[error("invalid export document: {0}")]
+    InvalidDocument(String),
+
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+
+    #[error("untrusted actor: {0}")]
+    UntrustedActor(String),
+
+    #[error("no workspace key configured")]
+    NoWorkspaceKey,
+
+    #[error("incompatible peer: {0}")]
+    IncompatiblePeer(String),
+
+    #[error("invalid bundle: {0}")]
+    InvalidBundle(String),
+
+    #[error("quarantined bundle not found: {0}")]
+    QuarantineNotFound(String),
+
+    #[error("unsupported module version: {0}")]
+    UnsupportedModule(String),
+
+    #[error("write rejected: {0}")]
+    Rejected(String),
+
+    #[error("reserved system field: {0}")]
+    ReservedField(String),
+
+    #[error("bulk command rejected: {}", bulk::describe(.0))]
+    BulkValidation(Vec<BulkItemError>),
 }
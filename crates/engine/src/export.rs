@@ -0,0 +1,209 @@
+//! JSON export of entities and the edges between them (`Engine::export_json`),
+//! read back by `Engine::import_json`.
+//!
+//! ```json
+//! {
+//!   "format": "openprod.export",
+//!   "version": 1,
+//!   "entities": [
+//!     {
+//!       "id": "01923f4e-7b1c-7d2a-9c1e-5b7f0a3e2d10",
+//!       "facets": ["Task"],
+//!       "fields": {
+//!         "title": { "type": "text", "value": "Hang plot" },
+//!         "due": { "type": "timestamp", "value": 1767225600000 }
+//!       }
+//!     }
+//!   ],
+//!   "edges": [
+//!     {
+//!       "id": "01923f4e-8a2d-7e3b-8d2f-6c8e1b4f3e21",
+//!       "type": "depends_on",
+//!       "source": "01923f4e-7b1c-7d2a-9c1e-5b7f0a3e2d10",
+//!       "target": "01923f4e-7c3e-7f4c-ae30-7d9f2c5a4f32",
+//!       "properties": { "lag": { "type": "integer", "value": 2 } }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Values carry a type tag: `null`, `text`, `integer`, `float`, `boolean`,
+//! `timestamp`, `entity_ref` (an entity id), `blob_ref` (hex hash) or `bytes`
+//! (hex). Entities and edges are listed in id order, fields and properties
+//! by key, so exporting unchanged data gives the same document.
+
+use std::collections::BTreeMap;
+
+use openprod_core::{
+    field_value::FieldValue,
+    ids::{BlobHash, EdgeId, EntityId},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::EngineError;
+
+/// `format` of every export document.
+pub const EXPORT_FORMAT: &str = "openprod.export";
+
+/// Newest document version this build writes and reads.
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Which entities `Engine::export_json` includes. The default exports every
+/// live entity and the live edges between exported entities.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportFilter {
+    facets: Option<Vec<String>>,
+    entities: Option<Vec<EntityId>>,
+    skip_edges: bool,
+}
+
+impl ExportFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only entities with at least one of these facets attached.
+    pub fn facets<I, T>(mut self, facet_types: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.facets = Some(facet_types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Only these entities (those that are live).
+    pub fn entities(mut self, entity_ids: impl IntoIterator<Item = EntityId>) -> Self {
+        self.entities = Some(entity_ids.into_iter().collect());
+        self
+    }
+
+    /// Include edges between exported entities (on by default).
+    pub fn edges(mut self, enabled: bool) -> Self {
+        self.skip_edges = !enabled;
+        self
+    }
+
+    pub fn exports_entity(&self, entity_id: EntityId, facet_types: &[String]) -> bool {
+        self.entities.as_ref().is_none_or(|ids| ids.contains(&entity_id))
+            && self.facets.as_ref().is_none_or(|facets| facet_types.iter().any(|f| facets.contains(f)))
+    }
+
+    pub fn exports_edges(&self) -> bool {
+        !self.skip_edges
+    }
+}
+
+/// The export document; see the module docs for its JSON shape.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportDocument {
+    pub format: String,
+    pub version: u32,
+    pub entities: Vec<ExportedEntity>,
+    #[serde(default)]
+    pub edges: Vec<ExportedEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedEntity {
+    pub id: EntityId,
+    #[serde(default)]
+    pub facets: Vec<String>,
+    #[serde(default)]
+    pub fields: BTreeMap<String, TypedValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedEdge {
+    pub id: EdgeId,
+    #[serde(rename = "type")]
+    pub edge_type: String,
+    pub source: EntityId,
+    pub target: EntityId,
+    #[serde(default)]
+    pub properties: BTreeMap<String, TypedValue>,
+}
+
+/// A `FieldValue` with its type spelled out, as `{"type": ..., "value": ...}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum TypedValue {
+    Null,
+    Text(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Timestamp(i64),
+    EntityRef(EntityId),
+    BlobRef(String),
+    Bytes(String),
+}
+
+impl From<&FieldValue> for TypedValue {
+    fn from(value: &FieldValue) -> Self {
+        match value {
+            FieldValue::Null => Self::Null,
+            FieldValue::Text(text) => Self::Text(text.clone()),
+            FieldValue::Integer(n) => Self::Integer(*n),
+            FieldValue::Float(x) => Self::Float(*x),
+            FieldValue::Boolean(b) => Self::Boolean(*b),
+            FieldValue::Timestamp(ms) => Self::Timestamp(*ms),
+            FieldValue::EntityRef(id) => Self::EntityRef(*id),
+            FieldValue::BlobRef(hash) => Self::BlobRef(to_hex(hash.as_bytes())),
+            FieldValue::Bytes(bytes) => Self::Bytes(to_hex(bytes)),
+        }
+    }
+}
+
+impl TryFrom<TypedValue> for FieldValue {
+    type Error = EngineError;
+
+    fn try_from(value: TypedValue) -> Result<Self, EngineError> {
+        Ok(match value {
+            TypedValue::Null => Self::Null,
+            TypedValue::Text(text) => Self::Text(text),
+            TypedValue::Integer(n) => Self::Integer(n),
+            TypedValue::Float(x) => Self::Float(x),
+            TypedValue::Boolean(b) => Self::Boolean(b),
+            TypedValue::Timestamp(ms) => Self::Timestamp(ms),
+            TypedValue::EntityRef(id) => Self::EntityRef(id),
+            TypedValue::BlobRef(hex) => {
+                let bytes = from_hex(&hex)?
+                    .try_into()
+                    .map_err(|_| EngineError::InvalidDocument(format!("blob hash {hex:?} is not 32 bytes")))?;
+                Self::BlobRef(BlobHash::from_bytes(bytes))
+            }
+            TypedValue::Bytes(hex) => Self::Bytes(from_hex(&hex)?),
+        })
+    }
+}
+
+impl ExportDocument {
+    pub fn to_json(&self) -> Result<String, EngineError> {
+        serde_json::to_string_pretty(self).map_err(|e| EngineError::InvalidDocument(e.to_string()))
+    }
+
+    /// Parse a document, refusing other formats and newer versions.
+    pub fn from_json(json: &str) -> Result<Self, EngineError> {
+        let document: Self = serde_json::from_str(json).map_err(|e| EngineError::InvalidDocument(e.to_string()))?;
+        if document.format != EXPORT_FORMAT {
+            return Err(EngineError::InvalidDocument(format!("not an export document: {:?}", document.format)));
+        }
+        if document.version > EXPORT_VERSION {
+            return Err(EngineError::InvalidDocument(format!("unsupported export version {}", document.version)));
+        }
+        Ok(document)
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, EngineError> {
+    let invalid = || EngineError::InvalidDocument(format!("invalid hex {hex:?}"));
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid())).collect()
+}
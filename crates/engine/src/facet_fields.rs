@@ -0,0 +1,135 @@
+//! Facet field declarations.
+//!
+//! The fields a facet owns are declared by a system entity with the
+//! `FACET_FIELDS_FACET` facet, one per facet type, holding the `facet_type`
+//! name and a `field.<key>` flag per owned field (`true`, or `Null` once
+//! dropped), so declarations sync and merge like any other data (LWW per
+//! field). If concurrent first declarations leave several entities for one
+//! facet, the latest write for a field across them wins. Resolved
+//! declarations are cached in storage after every local declaration or ingest.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::Hlc,
+    ids::EntityId,
+    operations::{BundleType, OperationPayload},
+};
+use openprod_storage::EngineStorage;
+
+use crate::Engine;
+use crate::error::EngineError;
+
+/// Facet marking facet field declarations.
+pub const FACET_FIELDS_FACET: &str = "openprod.facet_fields";
+
+pub(crate) const FACET_TYPE_FIELD: &str = "facet_type";
+
+const FIELD_PREFIX: &str = "field.";
+
+pub(crate) fn declared_key(field_key: &str) -> String {
+    format!("{FIELD_PREFIX}{field_key}")
+}
+
+pub(crate) fn field_from_declared_key(key: &str) -> Option<&str> {
+    key.strip_prefix(FIELD_PREFIX)
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Declare the fields `facet_type` owns, replacing any earlier declaration
+    /// (an empty list removes it), on every peer: the declaration syncs like
+    /// other data. A declared facet's detach touches only its own fields: a
+    /// preserving detach gives back just those, and a plain one clears them.
+    /// Undeclared facets give back every field and clear none.
+    pub fn set_facet_fields(&mut self, facet_type: &str, field_keys: &[&str]) -> Result<(), EngineError> {
+        let current = self.facet_field_keys(facet_type)?;
+        let mut payloads = Vec::new();
+        let entity_id = match self.facet_fields_entity(facet_type)? {
+            Some(entity_id) => entity_id,
+            None if field_keys.is_empty() => return Ok(()),
+            None => {
+                let entity_id = EntityId::new();
+                payloads.push(OperationPayload::CreateEntity {
+                    entity_id,
+                    initial_table: Some(FACET_FIELDS_FACET.to_string()),
+                });
+                payloads.push(OperationPayload::SetField {
+                    entity_id,
+                    field_key: FACET_TYPE_FIELD.to_string(),
+                    value: FieldValue::Text(facet_type.to_string()),
+                });
+                entity_id
+            }
+        };
+        let wanted: BTreeSet<&str> = field_keys.iter().copied().collect();
+        let added = wanted.iter().filter(|key| !current.iter().any(|c| c == *key)).map(|key| (*key, FieldValue::Boolean(true)));
+        let dropped = current.iter().filter(|key| !wanted.contains(key.as_str())).map(|key| (key.as_str(), FieldValue::Null));
+        for (field_key, value) in added.chain(dropped) {
+            payloads.push(OperationPayload::SetField {
+                entity_id,
+                field_key: declared_key(field_key),
+                value,
+            });
+        }
+        if payloads.is_empty() {
+            return Ok(());
+        }
+        self.execute_internal(BundleType::System, payloads, false)?;
+        self.refresh_facet_fields()
+    }
+
+    /// The entity declarations for `facet_type` are written to: the lowest
+    /// id, so every peer picks the same one once concurrent ones have synced.
+    fn facet_fields_entity(&self, facet_type: &str) -> Result<Option<EntityId>, EngineError> {
+        let mut found = None;
+        for entity_id in self.storage.get_entities_by_facet(FACET_FIELDS_FACET)? {
+            if self.storage.get_field(entity_id, FACET_TYPE_FIELD)?.as_ref().and_then(FieldValue::as_text)
+                == Some(facet_type)
+                && found.is_none_or(|found| entity_id < found)
+            {
+                found = Some(entity_id);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Re-resolve facet field declarations into storage's cache: for each
+    /// facet and field, the latest write across declaration entities wins.
+    pub(crate) fn refresh_facet_fields(&mut self) -> Result<(), EngineError> {
+        let mut latest: BTreeMap<(String, String), (Hlc, bool)> = BTreeMap::new();
+        let mut facet_types = BTreeSet::new();
+        for entity_id in self.storage.get_entities_by_facet(FACET_FIELDS_FACET)? {
+            let Some(FieldValue::Text(facet_type)) = self.storage.get_field(entity_id, FACET_TYPE_FIELD)? else {
+                continue;
+            };
+            for (key, value) in self.storage.get_fields(entity_id)? {
+                let Some(field_key) = field_from_declared_key(&key) else {
+                    continue;
+                };
+                let Some((_, hlc)) = self.storage.get_field_metadata(entity_id, &key)? else {
+                    continue;
+                };
+                let slot = (facet_type.clone(), field_key.to_string());
+                if latest.get(&slot).is_none_or(|(at, _)| hlc > *at) {
+                    latest.insert(slot, (hlc, value == FieldValue::Boolean(true)));
+                }
+            }
+            facet_types.insert(facet_type);
+        }
+        for facet_type in facet_types {
+            let owned: Vec<&str> = latest
+                .iter()
+                .filter(|((t, _), (_, declared))| *t == facet_type && *declared)
+                .map(|((_, field_key), _)| field_key.as_str())
+                .collect();
+            self.storage.set_facet_fields(&facet_type, &owned)?;
+        }
+        Ok(())
+    }
+
+    /// Fields declared for `facet_type`, in key order.
+    pub fn facet_field_keys(&self, facet_type: &str) -> Result<Vec<String>, EngineError> {
+        Ok(self.storage.get_facet_fields(facet_type)?)
+    }
+}
@@ -0,0 +1,333 @@
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::Hlc,
+    ids::*,
+    operations::{Bundle, BundleType, OperationPayload},
+};
+use openprod_storage::EngineStorage;
+
+use crate::Engine;
+use crate::error::EngineError;
+
+/// One bundle in the local history timeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineEntry {
+    pub bundle_id: BundleId,
+    pub actor_id: ActorId,
+    pub hlc: Hlc,
+    pub bundle_type: BundleType,
+    pub op_count: u32,
+    /// Effective label: the local relabel if set, otherwise the bundle's meta label.
+    pub label: Option<String>,
+}
+
+/// Filter for `Engine::timeline`. All set criteria must match.
+///
+/// ```ignore
+/// engine.timeline(&TimelineFilter::new().label("Import"))?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TimelineFilter {
+    label: Option<String>,
+    actor_id: Option<ActorId>,
+    bundle_type: Option<BundleType>,
+}
+
+impl TimelineFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only bundles whose effective label equals `label`.
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Only bundles authored by `actor_id`.
+    pub fn actor(mut self, actor_id: ActorId) -> Self {
+        self.actor_id = Some(actor_id);
+        self
+    }
+
+    /// Only bundles of the given type.
+    pub fn bundle_type(mut self, bundle_type: BundleType) -> Self {
+        self.bundle_type = Some(bundle_type);
+        self
+    }
+
+    pub fn matches(&self, entry: &TimelineEntry) -> bool {
+        self.label.as_ref().is_none_or(|l| entry.label.as_ref() == Some(l))
+            && self.actor_id.is_none_or(|a| entry.actor_id == a)
+            && self.bundle_type.is_none_or(|t| entry.bundle_type == t)
+    }
+}
+
+/// One op in an entity's history, as returned by `Engine::entity_history`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityHistoryEntry {
+    pub op_id: OpId,
+    pub bundle_id: BundleId,
+    pub actor_id: ActorId,
+    pub hlc: Hlc,
+    pub event: EntityEvent,
+}
+
+/// What an op did to an entity.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityEvent {
+    Created { initial_table: Option<String> },
+    Deleted,
+    Restored,
+    FacetAttached { facet_type: String },
+    FacetDetached { facet_type: String },
+    FacetRestored { facet_type: String },
+    AddedToTable { table: String },
+    RemovedFromTable { table: String },
+    FieldSet { field_key: String, value: FieldValue },
+    FieldCleared { field_key: String },
+    /// A CRDT delta or a `ClearAndAdd` on a collection field.
+    FieldMerged { field_key: String },
+    ConflictResolved { conflict_id: ConflictId, field_key: String, chosen_value: Option<FieldValue> },
+    EdgeCreated { edge_id: EdgeId, edge_type: String, source_id: EntityId, target_id: EntityId },
+    EdgeDeleted { edge_id: EdgeId },
+    EdgeRestored { edge_id: EdgeId },
+    EdgeMoved { edge_id: EdgeId },
+    EdgePropertySet { edge_id: EdgeId, property_key: String, value: FieldValue },
+    EdgePropertyCleared { edge_id: EdgeId, property_key: String },
+    Merged { survivor: EntityId, absorbed: EntityId },
+    Split { source: EntityId, new_entity: EntityId },
+}
+
+impl EntityEvent {
+    /// The event `payload` represents for `entity_id`, given the ids of every
+    /// edge touching it. None if the op doesn't affect the entity.
+    pub(crate) fn from_payload(
+        payload: &OperationPayload,
+        entity_id: EntityId,
+        edge_ids: &[EdgeId],
+    ) -> Option<Self> {
+        let touches_edge = |edge_id: &EdgeId| edge_ids.contains(edge_id);
+        let event = match payload {
+            OperationPayload::CreateEntity { initial_table, .. } => {
+                Self::Created { initial_table: initial_table.clone() }
+            }
+            OperationPayload::DeleteEntity { .. } => Self::Deleted,
+            OperationPayload::RestoreEntity { .. } => Self::Restored,
+            OperationPayload::AttachFacet { facet_type, .. } => {
+                Self::FacetAttached { facet_type: facet_type.clone() }
+            }
+            OperationPayload::DetachFacet { facet_type, .. } => {
+                Self::FacetDetached { facet_type: facet_type.clone() }
+            }
+            OperationPayload::RestoreFacet { facet_type, .. } => {
+                Self::FacetRestored { facet_type: facet_type.clone() }
+            }
+            OperationPayload::AddToTable { table, .. } => Self::AddedToTable { table: table.clone() },
+            OperationPayload::RemoveFromTable { table, .. } => {
+                Self::RemovedFromTable { table: table.clone() }
+            }
+            OperationPayload::SetField { field_key, value, .. } => {
+                Self::FieldSet { field_key: field_key.clone(), value: value.clone() }
+            }
+            OperationPayload::ClearField { field_key, .. } => {
+                Self::FieldCleared { field_key: field_key.clone() }
+            }
+            OperationPayload::ApplyCrdt { field_key, .. } | OperationPayload::ClearAndAdd { field_key, .. } => {
+                Self::FieldMerged { field_key: field_key.clone() }
+            }
+            OperationPayload::ResolveConflict { conflict_id, field_key, chosen_value, .. } => {
+                Self::ConflictResolved {
+                    conflict_id: *conflict_id,
+                    field_key: field_key.clone(),
+                    chosen_value: chosen_value.clone(),
+                }
+            }
+            OperationPayload::CreateEdge { edge_id, edge_type, source_id, target_id, .. }
+            | OperationPayload::CreateOrderedEdge { edge_id, edge_type, source_id, target_id, .. } => {
+                if *source_id != entity_id && *target_id != entity_id {
+                    return None;
+                }
+                Self::EdgeCreated {
+                    edge_id: *edge_id,
+                    edge_type: edge_type.clone(),
+                    source_id: *source_id,
+                    target_id: *target_id,
+                }
+            }
+            OperationPayload::DeleteEdge { edge_id } if touches_edge(edge_id) => {
+                Self::EdgeDeleted { edge_id: *edge_id }
+            }
+            OperationPayload::RestoreEdge { edge_id } if touches_edge(edge_id) => {
+                Self::EdgeRestored { edge_id: *edge_id }
+            }
+            OperationPayload::MoveOrderedEdge { edge_id, .. } if touches_edge(edge_id) => {
+                Self::EdgeMoved { edge_id: *edge_id }
+            }
+            OperationPayload::SetEdgeProperty { edge_id, property_key, value } if touches_edge(edge_id) => {
+                Self::EdgePropertySet {
+                    edge_id: *edge_id,
+                    property_key: property_key.clone(),
+                    value: value.clone(),
+                }
+            }
+            OperationPayload::ClearEdgeProperty { edge_id, property_key } if touches_edge(edge_id) => {
+                Self::EdgePropertyCleared { edge_id: *edge_id, property_key: property_key.clone() }
+            }
+            OperationPayload::MergeEntities { survivor, absorbed }
+                if *survivor == entity_id || *absorbed == entity_id =>
+            {
+                Self::Merged { survivor: *survivor, absorbed: *absorbed }
+            }
+            OperationPayload::SplitEntity { source, new_entity, .. }
+                if *source == entity_id || *new_entity == entity_id =>
+            {
+                Self::Split { source: *source, new_entity: *new_entity }
+            }
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+/// Last writer of one field, as returned by `Engine::field_blame`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldBlame {
+    pub field_key: String,
+    pub actor_id: ActorId,
+    pub hlc: Hlc,
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Bundles in HLC order, filtered by `filter`. Labels come from a local
+    /// relabel if present, otherwise from the bundle's `BundleMeta`; meta a
+    /// peer sent that doesn't decode counts as no label.
+    pub fn timeline(&self, filter: &TimelineFilter) -> Result<Vec<TimelineEntry>, EngineError> {
+        let mut entries = Vec::new();
+        for (bundle, local_label) in self.storage.list_bundles_with_labels()? {
+            let label = match local_label {
+                Some(label) => Some(label),
+                None => bundle.decode_meta().ok().flatten().and_then(|m| m.label),
+            };
+            let entry = TimelineEntry {
+                bundle_id: bundle.bundle_id,
+                actor_id: bundle.actor_id,
+                hlc: bundle.hlc,
+                bundle_type: bundle.bundle_type,
+                op_count: bundle.op_count,
+                label,
+            };
+            if filter.matches(&entry) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Bundles tagged `tag` in their meta, in HLC order, labelled as in `timeline`.
+    pub fn find_bundles_by_tag(&self, tag: &str) -> Result<Vec<TimelineEntry>, EngineError> {
+        self.timeline_entries(self.storage.get_bundles_by_tag(tag)?)
+    }
+
+    /// Bundles written during an editing session (see `begin_session`), in
+    /// HLC order, labelled as in `timeline`. Includes bundles synced from the
+    /// peer that ran the session.
+    pub fn get_session_bundles(&self, session_id: SessionId) -> Result<Vec<TimelineEntry>, EngineError> {
+        self.timeline_entries(self.storage.get_bundles_by_session(session_id)?)
+    }
+
+    fn timeline_entries(&self, bundles: Vec<Bundle>) -> Result<Vec<TimelineEntry>, EngineError> {
+        bundles
+            .into_iter()
+            .map(|bundle| {
+                Ok(TimelineEntry {
+                    bundle_id: bundle.bundle_id,
+                    actor_id: bundle.actor_id,
+                    hlc: bundle.hlc,
+                    bundle_type: bundle.bundle_type,
+                    op_count: bundle.op_count,
+                    label: self.bundle_label(bundle.bundle_id)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Every op affecting `entity_id` in canonical order, as typed events:
+    /// its own field, facet and lifecycle ops, edges to or from it, merges and
+    /// splits it took part in, and conflict resolutions on its fields.
+    pub fn entity_history(&self, entity_id: EntityId) -> Result<Vec<EntityHistoryEntry>, EngineError> {
+        let mut ops = self.storage.get_ops_by_entity(entity_id)?;
+        ops.extend(self.storage.get_ops_by_type(&[
+            "CreateEdge",
+            "CreateOrderedEdge",
+            "DeleteEdge",
+            "RestoreEdge",
+            "MoveOrderedEdge",
+            "SetEdgeProperty",
+            "ClearEdgeProperty",
+            "MergeEntities",
+            "SplitEntity",
+        ])?);
+        ops.sort_by_key(|op| (op.hlc, op.op_id));
+        ops.dedup_by_key(|op| op.op_id);
+
+        let edge_ids: Vec<EdgeId> = ops
+            .iter()
+            .filter_map(|op| match &op.payload {
+                OperationPayload::CreateEdge { edge_id, source_id, target_id, .. }
+                | OperationPayload::CreateOrderedEdge { edge_id, source_id, target_id, .. }
+                    if *source_id == entity_id || *target_id == entity_id =>
+                {
+                    Some(*edge_id)
+                }
+                _ => None,
+            })
+            .collect();
+
+        Ok(ops
+            .into_iter()
+            .filter_map(|op| {
+                EntityEvent::from_payload(&op.payload, entity_id, &edge_ids).map(|event| EntityHistoryEntry {
+                    op_id: op.op_id,
+                    bundle_id: op.bundle_id,
+                    actor_id: op.actor_id,
+                    hlc: op.hlc,
+                    event,
+                })
+            })
+            .collect())
+    }
+
+    /// The last writer of each current field of `entity_id`, in field key order.
+    pub fn field_blame(&self, entity_id: EntityId) -> Result<Vec<FieldBlame>, EngineError> {
+        let mut blame = Vec::new();
+        for (field_key, _) in self.storage.get_fields(entity_id)? {
+            if let Some((actor_id, hlc)) = self.storage.get_field_metadata(entity_id, &field_key)? {
+                blame.push(FieldBlame { field_key, actor_id, hlc });
+            }
+        }
+        blame.sort_by(|a, b| a.field_key.cmp(&b.field_key));
+        Ok(blame)
+    }
+
+    /// Effective label of a bundle: the local relabel if set, otherwise its meta label.
+    pub(crate) fn bundle_label(&self, bundle_id: BundleId) -> Result<Option<String>, EngineError> {
+        if let Some(label) = self.storage.get_bundle_label(bundle_id)? {
+            return Ok(Some(label));
+        }
+        Ok(match self.storage.get_bundle(bundle_id)? {
+            Some(bundle) => bundle.decode_meta().ok().flatten().and_then(|m| m.label),
+            None => None,
+        })
+    }
+
+    /// Relabel a bundle in the local history. Stored locally only: creates no
+    /// ops and overrides the bundle's meta label for this peer.
+    pub fn relabel_bundle(&mut self, bundle_id: BundleId, label: &str) -> Result<(), EngineError> {
+        if self.storage.get_bundle(bundle_id)?.is_none() {
+            return Err(EngineError::BundleNotFound(bundle_id.to_string()));
+        }
+        self.storage.set_bundle_label(bundle_id, label)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! Typed commands that are safe to retry, for `Engine::idempotent`.
+
+use openprod_core::{
+    field_value::FieldValue,
+    ids::*,
+    operations::{Bundle, BundleMeta, BundleType, OperationPayload},
+};
+use openprod_storage::EngineStorage;
+
+use crate::error::EngineError;
+use crate::Engine;
+
+/// One typed command issued under an idempotency key. If this actor already
+/// wrote a bundle under the key, the command is not run again: it returns
+/// what the first call did, read back from that bundle. Otherwise it runs as
+/// usual, with the key stored in the bundle's meta.
+///
+/// Writes staged in an active overlay aren't bundles yet, so they aren't
+/// recorded; a command that writes nothing (e.g. reusing a unique edge) has
+/// nothing to record either and simply runs again.
+pub struct Idempotent<'a, S: EngineStorage> {
+    engine: &'a mut Engine<S>,
+    key: String,
+}
+
+impl<'a, S: EngineStorage> Idempotent<'a, S> {
+    pub(crate) fn new(engine: &'a mut Engine<S>, key: String) -> Self {
+        Self { engine, key }
+    }
+
+    /// The bundle an earlier call under this key wrote, if any.
+    pub fn original(&self) -> Result<Option<Bundle>, EngineError> {
+        Ok(self.engine.storage().get_bundle_by_idempotency_key(self.engine.actor_id(), &self.key)?)
+    }
+
+    pub fn create_entity_with_fields(
+        self,
+        facet_type: &str,
+        fields: Vec<(&str, FieldValue)>,
+    ) -> Result<(EntityId, BundleId), EngineError> {
+        if let Some(bundle) = self.original()? {
+            let entity_id = *bundle.creates.first().ok_or_else(|| self.reused(&bundle))?;
+            return Ok((entity_id, bundle.bundle_id));
+        }
+        self.run(|engine| engine.create_entity_with_fields(facet_type, fields))
+    }
+
+    pub fn set_field(self, entity_id: EntityId, field_key: &str, value: FieldValue) -> Result<BundleId, EngineError> {
+        if let Some(bundle) = self.original()? {
+            return Ok(bundle.bundle_id);
+        }
+        self.run(|engine| engine.set_field(entity_id, field_key, value))
+    }
+
+    pub fn clear_field(self, entity_id: EntityId, field_key: &str) -> Result<BundleId, EngineError> {
+        if let Some(bundle) = self.original()? {
+            return Ok(bundle.bundle_id);
+        }
+        self.run(|engine| engine.clear_field(entity_id, field_key))
+    }
+
+    pub fn delete_entity(self, entity_id: EntityId) -> Result<BundleId, EngineError> {
+        if let Some(bundle) = self.original()? {
+            return Ok(bundle.bundle_id);
+        }
+        self.run(|engine| engine.delete_entity(entity_id))
+    }
+
+    pub fn create_edge_with_properties(
+        self,
+        edge_type: &str,
+        source_id: EntityId,
+        target_id: EntityId,
+        properties: Vec<(&str, FieldValue)>,
+    ) -> Result<(EdgeId, BundleId), EngineError> {
+        if let Some(bundle) = self.original()? {
+            // A unique edge that was reused shows up restored or with properties set
+            let edge_id = self.engine.get_ops_by_bundle(bundle.bundle_id)?.into_iter().find_map(|op| match op.payload {
+                OperationPayload::CreateEdge { edge_id, .. }
+                | OperationPayload::RestoreEdge { edge_id }
+                | OperationPayload::SetEdgeProperty { edge_id, .. } => Some(edge_id),
+                _ => None,
+            });
+            return Ok((edge_id.ok_or_else(|| self.reused(&bundle))?, bundle.bundle_id));
+        }
+        self.run(|engine| engine.create_edge_with_properties(edge_type, source_id, target_id, properties))
+    }
+
+    pub fn create_edge(self, edge_type: &str, source_id: EntityId, target_id: EntityId) -> Result<(EdgeId, BundleId), EngineError> {
+        self.create_edge_with_properties(edge_type, source_id, target_id, Vec::new())
+    }
+
+    /// `Engine::execute` under the key.
+    pub fn execute(self, bundle_type: BundleType, payloads: Vec<OperationPayload>) -> Result<BundleId, EngineError> {
+        if let Some(bundle) = self.original()? {
+            return Ok(bundle.bundle_id);
+        }
+        self.run(|engine| engine.execute(bundle_type, payloads))
+    }
+
+    /// Run the command with the key added to whatever meta is in scope.
+    fn run<T>(self, f: impl FnOnce(&mut Engine<S>) -> Result<T, EngineError>) -> Result<T, EngineError> {
+        let meta = BundleMeta { idempotency_key: Some(self.key), ..Default::default() };
+        let meta = match &self.engine.scoped_meta {
+            Some(outer) => meta.or_defaults(outer),
+            None => meta,
+        };
+        self.engine.with_bundle_meta(meta, f)
+    }
+
+    /// The key was first used for a command whose result can't be read back
+    /// as this one's.
+    fn reused(&self, bundle: &Bundle) -> EngineError {
+        EngineError::Rejected(format!(
+            "idempotency key {} was used for a different command (bundle {})",
+            self.key, bundle.bundle_id
+        ))
+    }
+}
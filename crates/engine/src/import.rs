@@ -0,0 +1,398 @@
+//! Record imports from external files: CSV, and export documents (see
+//! `export`) as JSON.
+//!
+//! A CSV import creates one entity per row with the target facet attached and
+//! the mapped columns as fields, written as `BundleType::Import` bundles of
+//! at most `chunk_size` rows. The column→field mapping is recorded with
+//! `ConfirmFieldMapping` ops in the first bundle, so later imports from the
+//! same source (on any peer) can reuse it.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use openprod_core::{
+    field_value::{FieldValue, ValueType},
+    ids::{BundleId, EdgeId, EntityId, TableId},
+    operations::{BundleType, OperationPayload},
+};
+use openprod_storage::{EdgeFilter, EngineStorage};
+
+use crate::Engine;
+use crate::error::EngineError;
+use crate::export::{
+    EXPORT_FORMAT, EXPORT_VERSION, ExportDocument, ExportFilter, ExportedEdge, ExportedEntity,
+};
+use crate::system::check_user_field;
+
+pub const DEFAULT_IMPORT_CHUNK_SIZE: usize = 500;
+
+/// What `Engine::import_csv` creates and how it reads the input.
+///
+/// ```ignore
+/// let options = ImportOptions::new("sm.Contacts", source, contacts_table)
+///     .map("Name", "name")
+///     .map("E-mail", "email");
+/// let report = engine.import_csv(&text, &options)?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportOptions {
+    facet_type: String,
+    source_table: TableId,
+    target_table: TableId,
+    columns: Vec<(String, String)>,
+    value_types: BTreeMap<String, ValueType>,
+    chunk_size: usize,
+}
+
+impl ImportOptions {
+    /// Import rows as `facet_type` records. `source_table` names the external
+    /// source (reuse it for repeat imports of the same file), `target_table`
+    /// the table backed by `facet_type`.
+    pub fn new(facet_type: impl Into<String>, source_table: TableId, target_table: TableId) -> Self {
+        Self {
+            facet_type: facet_type.into(),
+            source_table,
+            target_table,
+            columns: Vec::new(),
+            value_types: BTreeMap::new(),
+            chunk_size: DEFAULT_IMPORT_CHUNK_SIZE,
+        }
+    }
+
+    /// Import the column headed `column` into `field_key`. Unmapped columns
+    /// are skipped; with no columns mapped, the confirmed mappings are used.
+    pub fn map(mut self, column: impl Into<String>, field_key: impl Into<String>) -> Self {
+        self.columns.push((column.into(), field_key.into()));
+        self
+    }
+
+    /// Read `field_key`'s cells as `value_type` (`FieldValue::parse`, after
+    /// trimming) rather than as text. A cell that doesn't parse skips its row.
+    pub fn value_type(mut self, field_key: impl Into<String>, value_type: ValueType) -> Self {
+        self.value_types.insert(field_key.into(), value_type);
+        self
+    }
+
+    /// Rows per bundle (at least 1).
+    pub fn chunk_size(mut self, rows: usize) -> Self {
+        self.chunk_size = rows.max(1);
+        self
+    }
+
+    pub fn facet_type(&self) -> &str {
+        &self.facet_type
+    }
+
+    pub fn source_table(&self) -> TableId {
+        self.source_table
+    }
+
+    pub fn target_table(&self) -> TableId {
+        self.target_table
+    }
+
+    /// `(column, field_key)` pairs in the order they were mapped.
+    pub fn columns(&self) -> &[(String, String)] {
+        &self.columns
+    }
+
+    /// The type `field_key` is imported as.
+    pub fn type_of(&self, field_key: &str) -> ValueType {
+        self.value_types.get(field_key).copied().unwrap_or(ValueType::Text)
+    }
+
+    pub fn rows_per_bundle(&self) -> usize {
+        self.chunk_size
+    }
+}
+
+/// Outcome of `Engine::import_csv`.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// One `Import` bundle per chunk, in order.
+    pub bundle_ids: Vec<BundleId>,
+    /// The created entities, in row order.
+    pub entity_ids: Vec<EntityId>,
+    /// Rows that were skipped, and why.
+    pub errors: Vec<ImportRowError>,
+}
+
+/// Outcome of `Engine::import_json`.
+#[derive(Debug, Default)]
+pub struct JsonImport {
+    /// `Import` bundles written, in order.
+    pub bundle_ids: Vec<BundleId>,
+    /// Entity ids in the document, mapped to the entities created for them.
+    pub entity_ids: BTreeMap<EntityId, EntityId>,
+    /// Edge ids in the document, mapped to the edges created for them.
+    pub edge_ids: BTreeMap<EdgeId, EdgeId>,
+}
+
+/// A row `Engine::import_csv` skipped. `row` counts data rows from 1 (the
+/// header is not counted).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportRowError {
+    pub row: usize,
+    pub message: String,
+}
+
+/// Split CSV text (RFC 4180: comma-separated, `"`-quoted with `""` escapes,
+/// quoted fields may span lines) into records. Blank lines are skipped; an
+/// unterminated quote fails the record it starts.
+pub(crate) fn parse_csv(input: &str) -> Vec<Result<Vec<String>, String>> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let mut records = Vec::new();
+    let mut chars = input.chars().peekable();
+    while chars.peek().is_some() {
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut unterminated = false;
+        loop {
+            match chars.next() {
+                None => {
+                    unterminated = quoted;
+                    break;
+                }
+                Some('"') if quoted => {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        quoted = false;
+                    }
+                }
+                Some('"') if field.is_empty() => quoted = true,
+                Some(c) if quoted => field.push(c),
+                Some(',') => record.push(std::mem::take(&mut field)),
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') => break,
+                Some(c) => field.push(c),
+            }
+        }
+        record.push(field);
+        if unterminated {
+            records.push(Err("unterminated quoted field".to_string()));
+        } else if record.len() > 1 || !record[0].is_empty() {
+            records.push(Ok(record));
+        }
+    }
+    records
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Import CSV rows as `options.facet_type()` records: one entity per row,
+    /// mapped columns as text fields (empty cells are left unset), written
+    /// in `Import` bundles of `options.rows_per_bundle()` rows. The first
+    /// bundle also confirms any column mapping not already confirmed, with
+    /// `ConfirmFieldMapping` ops. Options without mapped columns reuse the
+    /// mappings confirmed between the source and target tables.
+    ///
+    /// The first row is the header. A missing mapped column or a reserved
+    /// field key rejects the whole import; malformed rows are skipped and
+    /// reported in `errors`. Bundles written before a failed chunk stay.
+    pub fn import_csv(&mut self, input: &str, options: &ImportOptions) -> Result<ImportReport, EngineError> {
+        let confirmed: Vec<(String, String)> = self
+            .storage
+            .get_field_mappings(options.source_table())?
+            .into_iter()
+            .filter(|m| m.target_table == options.target_table())
+            .map(|m| (m.source_field, m.target_field))
+            .collect();
+        let columns = if options.columns().is_empty() { &confirmed[..] } else { options.columns() };
+        if columns.is_empty() {
+            return Err(EngineError::Rejected("import maps no columns".into()));
+        }
+        for (_, field_key) in columns {
+            check_user_field(field_key)?;
+        }
+        let mut records = parse_csv(input).into_iter();
+        let header = match records.next() {
+            Some(Ok(header)) => header,
+            Some(Err(reason)) => return Err(EngineError::Rejected(format!("CSV header: {reason}"))),
+            None => return Err(EngineError::Rejected("CSV input has no header row".into())),
+        };
+        let mut mapped = Vec::with_capacity(columns.len());
+        for (column, field_key) in columns {
+            let index = header
+                .iter()
+                .position(|h| h.trim() == column)
+                .ok_or_else(|| EngineError::Rejected(format!("CSV has no column {column:?}")))?;
+            mapped.push((index, field_key));
+        }
+
+        let mut report = ImportReport::default();
+        let mut payloads: Vec<OperationPayload> = columns
+            .iter()
+            .filter(|mapping| !confirmed.contains(mapping))
+            .map(|(column, field_key)| OperationPayload::ConfirmFieldMapping {
+                source_table: options.source_table(),
+                target_table: options.target_table(),
+                source_field: column.clone(),
+                target_field: field_key.clone(),
+            })
+            .collect();
+        let mut rows_in_chunk = 0;
+        for (i, record) in records.enumerate() {
+            let row = i + 1;
+            let cells = match record {
+                Ok(cells) if cells.len() == header.len() => cells,
+                Ok(cells) => {
+                    let message = format!("expected {} fields, found {}", header.len(), cells.len());
+                    report.errors.push(ImportRowError { row, message });
+                    continue;
+                }
+                Err(message) => {
+                    report.errors.push(ImportRowError { row, message });
+                    continue;
+                }
+            };
+            let entity_id = EntityId::new();
+            let fields: Result<Vec<OperationPayload>, String> = mapped
+                .iter()
+                .filter(|(index, _)| !cells[*index].is_empty())
+                .map(|(index, field_key)| {
+                    let value = match options.type_of(field_key) {
+                        ValueType::Text => FieldValue::Text(cells[*index].clone()),
+                        value_type => FieldValue::parse(value_type, cells[*index].trim()).map_err(|e| format!("{field_key}: {e}"))?,
+                    };
+                    Ok(OperationPayload::SetField { entity_id, field_key: field_key.to_string(), value })
+                })
+                .collect();
+            let mut fields = match fields {
+                Ok(fields) => fields,
+                Err(message) => {
+                    report.errors.push(ImportRowError { row, message });
+                    continue;
+                }
+            };
+            payloads.push(OperationPayload::CreateEntity {
+                entity_id,
+                initial_table: Some(options.facet_type().to_string()),
+            });
+            payloads.append(&mut fields);
+            report.entity_ids.push(entity_id);
+            rows_in_chunk += 1;
+            if rows_in_chunk == options.rows_per_bundle() {
+                let (bundle_id, _) =
+                    self.execute_internal(BundleType::Import, std::mem::take(&mut payloads), false)?;
+                report.bundle_ids.push(bundle_id);
+                rows_in_chunk = 0;
+            }
+        }
+        if !payloads.is_empty() {
+            let (bundle_id, _) = self.execute_internal(BundleType::Import, payloads, false)?;
+            report.bundle_ids.push(bundle_id);
+        }
+        Ok(report)
+    }
+
+    /// Canonical state (the active overlay is not applied) as an export
+    /// document: live entities matching `filter`, with their attached facets
+    /// and fields, and the live edges between them. See `export` for the format.
+    pub fn export_json(&self, filter: &ExportFilter) -> Result<String, EngineError> {
+        let mut document =
+            ExportDocument { format: EXPORT_FORMAT.into(), version: EXPORT_VERSION, entities: Vec::new(), edges: Vec::new() };
+        for entity_id in self.storage.get_entities_changed_since(0)? {
+            if self.storage.get_entity(entity_id)?.is_none_or(|e| e.deleted) {
+                continue;
+            }
+            let mut facets: Vec<String> =
+                self.storage.get_facets(entity_id)?.into_iter().filter(|f| !f.detached).map(|f| f.facet_type).collect();
+            facets.sort();
+            if !filter.exports_entity(entity_id, &facets) {
+                continue;
+            }
+            let fields = self.storage.get_fields(entity_id)?.iter().map(|(k, v)| (k.clone(), v.into())).collect();
+            document.entities.push(ExportedEntity { id: entity_id, facets, fields });
+        }
+
+        if filter.exports_edges() {
+            let exported: BTreeSet<EntityId> = document.entities.iter().map(|e| e.id).collect();
+            for entity_id in &exported {
+                for edge in self.storage.get_edges_from(*entity_id, &EdgeFilter::live())? {
+                    if !exported.contains(&edge.target_id) {
+                        continue;
+                    }
+                    let properties =
+                        self.storage.get_edge_properties(edge.edge_id)?.iter().map(|(k, v)| (k.clone(), v.into())).collect();
+                    document.edges.push(ExportedEdge {
+                        id: edge.edge_id,
+                        edge_type: edge.edge_type,
+                        source: edge.source_id,
+                        target: edge.target_id,
+                        properties,
+                    });
+                }
+            }
+            document.edges.sort_by_key(|e| e.id);
+        }
+        document.to_json()
+    }
+
+    /// Recreate an export document's entities and edges as new ones, in
+    /// `Import` bundles. Ids are fresh (so a document can be imported next to
+    /// the data it came from); edges and `EntityRef` values pointing into the
+    /// document are rewired to the new ids. Edges may also point at live
+    /// entities outside the document. Nothing is written unless the whole
+    /// document is valid.
+    pub fn import_json(&mut self, json: &str) -> Result<JsonImport, EngineError> {
+        let document = ExportDocument::from_json(json)?;
+        let mut report = JsonImport::default();
+        for entity in &document.entities {
+            if report.entity_ids.insert(entity.id, EntityId::new()).is_some() {
+                return Err(EngineError::InvalidDocument(format!("entity {} is listed twice", entity.id)));
+            }
+        }
+        let rewire = |id: EntityId| report.entity_ids.get(&id).copied();
+
+        // One item per entity or edge; items are never split across bundles
+        let mut items: Vec<Vec<OperationPayload>> = Vec::new();
+        for entity in document.entities {
+            let entity_id = report.entity_ids[&entity.id];
+            let mut facets = entity.facets.into_iter();
+            let mut payloads = vec![OperationPayload::CreateEntity { entity_id, initial_table: facets.next() }];
+            payloads.extend(facets.map(|facet_type| OperationPayload::AttachFacet { entity_id, facet_type }));
+            for (field_key, value) in entity.fields {
+                let value = match FieldValue::try_from(value)? {
+                    FieldValue::EntityRef(target) => FieldValue::EntityRef(rewire(target).unwrap_or(target)),
+                    value => value,
+                };
+                payloads.push(OperationPayload::SetField { entity_id, field_key, value });
+            }
+            items.push(payloads);
+        }
+        for edge in document.edges {
+            let endpoint = |id: EntityId| -> Result<EntityId, EngineError> {
+                match rewire(id) {
+                    Some(new_id) => Ok(new_id),
+                    None if self.storage.get_entity(id)?.is_some_and(|e| !e.deleted) => Ok(id),
+                    None => Err(EngineError::InvalidDocument(format!("edge {} points at unknown entity {id}", edge.id))),
+                }
+            };
+            let (source_id, target_id) = (endpoint(edge.source)?, endpoint(edge.target)?);
+            let edge_id = EdgeId::new();
+            if report.edge_ids.insert(edge.id, edge_id).is_some() {
+                return Err(EngineError::InvalidDocument(format!("edge {} is listed twice", edge.id)));
+            }
+            let properties = edge
+                .properties
+                .into_iter()
+                .map(|(key, value)| Ok((key, FieldValue::try_from(value)?)))
+                .collect::<Result<_, EngineError>>()?;
+            items.push(vec![OperationPayload::CreateEdge {
+                edge_id,
+                edge_type: edge.edge_type,
+                source_id,
+                target_id,
+                properties,
+            }]);
+        }
+
+        for chunk in items.chunks(DEFAULT_IMPORT_CHUNK_SIZE) {
+            let payloads = chunk.iter().flatten().cloned().collect();
+            let (bundle_id, _) = self.execute_internal(BundleType::Import, payloads, false)?;
+            report.bundle_ids.push(bundle_id);
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,1148 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::time::Duration;
+
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::{physical_now, Hlc},
+    ids::*,
+    operations::{Bundle, Operation, OperationPayload, RawBundle},
+    sealed::SealedBundle,
+    vector_clock::VectorClock,
+};
+use openprod_storage::{
+    check_bundle_ops, ConflictRecord, ConflictStatus, ConflictValue, EngineStorage,
+    PendingBundleRecord, QuarantinedBundle, Storage, StorageError,
+};
+
+use crate::Engine;
+use crate::error::EngineError;
+use crate::modules::{ModuleMismatch, ModulePolicy};
+use crate::overlay::{DriftRecord, OverlayStatus};
+use crate::rotation::may_change_rotations;
+use crate::trust::TrustDecision;
+use crate::watch::ConflictEvent;
+
+/// A field whose materialized value would change if a bundle were ingested.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub entity_id: EntityId,
+    pub field_key: String,
+    /// Value before ingest (None = absent or cleared).
+    pub old_value: Option<FieldValue>,
+    /// Value after ingest (None = cleared).
+    pub new_value: Option<FieldValue>,
+}
+
+/// Result of a dry-run ingest: what `ingest_bundle` would do, without writing anything.
+#[derive(Debug, Clone, Default)]
+pub struct IngestPreview {
+    /// Fields whose materialized value would change (LWW losers are omitted).
+    pub field_changes: Vec<FieldChange>,
+    /// Entities that would come into existence.
+    pub entities_created: Vec<EntityId>,
+    /// Live entities that would be soft-deleted.
+    pub entities_deleted: Vec<EntityId>,
+    /// Conflicts that would be opened, extended, or reopened.
+    pub conflicts: Vec<ConflictRecord>,
+    /// Overlay fields that would newly drift from canonical (active and stashed overlays).
+    pub drift: Vec<OverlayDrift>,
+    /// Causal dependencies we lack. When non-empty the bundle would be buffered,
+    /// not applied, and every other field is empty.
+    pub missing_dependencies: Vec<MissingDependency>,
+    /// Set when ingest would quarantine or hold the bundle instead of
+    /// applying it; the change fields are then empty.
+    pub set_aside: Option<SetAside>,
+    /// Modules the bundle needs at versions this engine lacks.
+    pub module_mismatches: Vec<ModuleMismatch>,
+}
+
+/// Why ingest would set a bundle aside rather than apply it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetAside {
+    /// Its actor awaits approval (`IngestReport::bundles_untrusted`).
+    Untrusted,
+    /// It needs module versions we lack under `ModulePolicy::Hold`
+    /// (`IngestReport::bundles_held`).
+    Held(String),
+    /// Its author lacked the role for it (`IngestReport::bundles_denied`).
+    Denied(String),
+}
+
+/// A bundle whose HLC was further ahead of local physical time than
+/// `Engine::clock_skew_tolerance`, usually because its author's wall clock is
+/// wrong. Its timestamps would win last-writer-wins against every local edit
+/// until real time caught up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockSkew {
+    pub bundle_id: BundleId,
+    pub actor_id: ActorId,
+    pub hlc: Hlc,
+    /// How far `hlc` was ahead of the local clock when ingested.
+    pub ahead: Duration,
+}
+
+/// What an ingest call actually did, for sync UIs and logs.
+///
+/// Bundle counts cover everything the call touched, including pending bundles
+/// it released. Op counts cover new bundles only: re-ingesting a stored bundle
+/// is a no-op and counts as `bundles_already_present`.
+#[derive(Debug, Clone, Default)]
+pub struct IngestReport {
+    /// Bundles applied, including previously pending ones released by this call.
+    pub bundles_applied: usize,
+    /// Bundles that were already in the oplog.
+    pub bundles_already_present: usize,
+    /// Bundles from this call left waiting for causal dependencies.
+    pub bundles_buffered: usize,
+    /// Bundles held back because their actor is not trusted.
+    pub bundles_untrusted: usize,
+    /// Bundles that failed to decode or verify, or buffered bundles that
+    /// could not be applied once their dependencies arrived.
+    pub bundles_quarantined: usize,
+    /// Bundles quarantined by `ModulePolicy::Hold` for needing module versions
+    /// this engine doesn't support.
+    pub bundles_held: usize,
+    /// Bundles quarantined because their author lacked the role for them
+    /// under the ACL as of the bundle's `creator_vc`.
+    pub bundles_denied: usize,
+    /// New bundles stamped further ahead of the local clock than the skew
+    /// tolerance. They are ingested, but don't advance the local clock.
+    pub clock_skew: Vec<ClockSkew>,
+    /// Unsupported module versions seen on new bundles (`ModulePolicy::Warn`
+    /// and `Hold`).
+    pub module_mismatches: Vec<ModuleMismatch>,
+    /// Operations that took effect.
+    pub ops_applied: usize,
+    /// SetField/ClearField operations that lost last-writer-wins to the value
+    /// already materialized.
+    pub ops_skipped_lww: usize,
+    /// Operations skipped because their bundle was already stored (resent, or
+    /// relayed along another path).
+    pub ops_duplicate: usize,
+    /// Entities that came into existence.
+    pub entities_created: usize,
+    /// Overlay ops newly flagged as drifted from canonical.
+    pub drift_flagged: u64,
+    pub conflicts_opened: usize,
+    /// Open conflicts that gained another branch tip.
+    pub conflicts_extended: usize,
+    /// Resolved conflicts reopened by a late concurrent edit.
+    pub conflicts_reopened: usize,
+    /// Every conflict opened, extended, or reopened, in detection order.
+    pub conflicts: Vec<ConflictRecord>,
+    /// Wall-clock time spent, at millisecond resolution.
+    pub duration: Duration,
+}
+
+/// What the admission checks shared by ingest and its preview decided for a
+/// new bundle.
+pub(crate) enum Admission {
+    /// Apply it, reporting these module mismatches (`ModulePolicy::Warn`).
+    Apply(Vec<ModuleMismatch>),
+    /// Its actor isn't trusted yet.
+    Untrusted,
+    /// Hold it for these module mismatches (`ModulePolicy::Hold`).
+    Held(Vec<ModuleMismatch>),
+}
+
+/// How ingest changed a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConflictChange {
+    Opened,
+    Extended,
+    Reopened,
+}
+
+/// A drift record the previewed bundle would cause, tagged with its overlay.
+#[derive(Debug, Clone)]
+pub struct OverlayDrift {
+    pub overlay_id: OverlayId,
+    pub drift: DriftRecord,
+}
+
+impl IngestPreview {
+    /// True if ingesting would leave materialized state unchanged, open no
+    /// conflicts, and cause no overlay drift.
+    pub fn is_empty(&self) -> bool {
+        self.field_changes.is_empty()
+            && self.entities_created.is_empty()
+            && self.entities_deleted.is_empty()
+            && self.conflicts.is_empty()
+            && self.drift.is_empty()
+    }
+
+    /// True if the bundle would be held in the pending area rather than applied.
+    pub fn would_buffer(&self) -> bool {
+        !self.missing_dependencies.is_empty()
+    }
+
+    /// True if the bundle would be quarantined or held rather than applied.
+    pub fn would_set_aside(&self) -> bool {
+        self.set_aside.is_some()
+    }
+}
+
+/// A causal dependency of a buffered bundle that has not been ingested yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingDependency {
+    pub actor_id: ActorId,
+    /// Latest HLC from this actor that the bundle's creator had seen.
+    pub required: Hlc,
+    /// Latest HLC from this actor that we have (None = nothing yet).
+    pub have: Option<Hlc>,
+}
+
+/// A bundle held in the pending area until its `creator_vc` is covered locally.
+#[derive(Debug, Clone)]
+pub struct PendingBundle {
+    pub bundle_id: BundleId,
+    pub actor_id: ActorId,
+    pub hlc: Hlc,
+    /// Local receive time in milliseconds since the Unix epoch.
+    pub received_at: i64,
+    pub missing: Vec<MissingDependency>,
+}
+
+/// Pending bundles being released during an ingest. Each waits on the first
+/// dependency it still misses, so applying a bundle only re-checks the
+/// bundles that waited on its actor's clock.
+#[derive(Default)]
+pub(crate) struct PendingQueue {
+    ready: VecDeque<PendingBundleRecord>,
+    waiting: BTreeMap<(ActorId, Hlc), Vec<PendingBundleRecord>>,
+}
+
+impl PendingQueue {
+    /// Queue a bundle: ready to apply if nothing is `missing`, otherwise
+    /// waiting on the first missing dependency.
+    pub(crate) fn push(&mut self, pending: PendingBundleRecord, missing: &[MissingDependency]) {
+        match missing.first() {
+            None => self.ready.push_back(pending),
+            Some(dep) => self.waiting.entry((dep.actor_id, dep.required)).or_default().push(pending),
+        }
+    }
+
+    pub(crate) fn pop_ready(&mut self) -> Option<PendingBundleRecord> {
+        self.ready.pop_front()
+    }
+
+    /// Take the bundles waiting on `actor_id` at or below `have`. They may
+    /// still miss other actors, so the caller re-checks and pushes them back.
+    pub(crate) fn wake(&mut self, actor_id: ActorId, have: Hlc) -> Vec<PendingBundleRecord> {
+        let keys: Vec<(ActorId, Hlc)> =
+            self.waiting.range((actor_id, Hlc::new(0, 0))..=(actor_id, have)).map(|(key, _)| *key).collect();
+        keys.into_iter().flat_map(|key| self.waiting.remove(&key).unwrap_or_default()).collect()
+    }
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Ingest a foreign bundle and its operations into this engine's storage.
+    /// Used for sync and testing — does NOT push to undo stack.
+    /// Detects field-level conflicts via vector clock comparison.
+    /// Returns a report of what was applied and any detected conflicts.
+    ///
+    /// If the bundle's `creator_vc` references ops we have not seen yet, it is
+    /// buffered in the pending area instead (reporting no conflicts) so that
+    /// conflict detection never runs against an incomplete causal history. After
+    /// every successful ingest, pending bundles that became ready are flushed and
+    /// counted in the report.
+    pub fn ingest_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<IngestReport, EngineError> {
+        if !self.missing_dependencies(bundle)?.is_empty() {
+            self.check_signatures(bundle, operations)?;
+            self.check_pending(bundle, operations)?;
+            self.storage.insert_pending_bundle(bundle, operations)?;
+            return Ok(IngestReport { bundles_buffered: 1, ..Default::default() });
+        }
+        self.ingest_batch(&[(bundle, operations)])
+    }
+
+    /// Ingest many bundles in one transaction (e.g. catching up a long-offline
+    /// peer). Equivalent to calling `ingest_bundle` for each in order, but field
+    /// metadata is read from storage at most once per field and overlay drift is
+    /// scanned once for the whole batch. Returns one report for the whole batch.
+    pub fn ingest_bundles(
+        &mut self,
+        batch: &[(Bundle, Vec<Operation>)],
+    ) -> Result<IngestReport, EngineError> {
+        let batch: Vec<(&Bundle, &[Operation])> =
+            batch.iter().map(|(bundle, ops)| (bundle, ops.as_slice())).collect();
+        self.ingest_batch(&batch)
+    }
+
+    /// Ingest every pending bundle whose causal dependencies are now satisfied,
+    /// repeating until no more progress is made.
+    pub fn flush_pending(&mut self) -> Result<IngestReport, EngineError> {
+        self.ingest_batch(&[])
+    }
+
+    /// Bundles waiting for causal dependencies, in HLC order, with what each is missing.
+    pub fn pending_bundles(&self) -> Result<Vec<PendingBundle>, EngineError> {
+        self.storage
+            .list_pending_bundles()?
+            .into_iter()
+            .map(|pending| {
+                Ok(PendingBundle {
+                    bundle_id: pending.bundle.bundle_id,
+                    actor_id: pending.bundle.actor_id,
+                    hlc: pending.bundle.hlc,
+                    received_at: pending.received_at,
+                    missing: self.missing_dependencies(&pending.bundle)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Number of bundles waiting for causal dependencies.
+    pub fn pending_count(&self) -> Result<u64, EngineError> {
+        Ok(self.storage.count_pending_bundles()?)
+    }
+
+    /// Drop a stuck bundle from the pending area without ingesting it.
+    /// Returns false if the bundle was not pending.
+    pub fn discard_pending(&mut self, bundle_id: BundleId) -> Result<bool, EngineError> {
+        Ok(self.storage.delete_pending_bundle(bundle_id)?)
+    }
+
+    /// Ingest bundles as received off the wire. Bundles that don't decode, or
+    /// fail signature or checksum verification, are quarantined with the
+    /// reason instead of failing the batch; the rest are ingested as by
+    /// `ingest_bundles`, and counted in the report alongside them. Sealed
+    /// bundles (`SealedBundle::to_raw`) are opened with the workspace key;
+    /// without one they are quarantined until `retry_quarantined` can open them.
+    pub fn ingest_raw_bundles(&mut self, batch: &[RawBundle]) -> Result<IngestReport, EngineError> {
+        let started = physical_now()?;
+        let mut quarantined = 0;
+        let mut decoded = Vec::with_capacity(batch.len());
+        for raw in batch {
+            match self.decode_verified(raw) {
+                Ok(bundle) => decoded.push(bundle),
+                Err(reason) => {
+                    let header = raw.decode_header().ok();
+                    self.storage.insert_quarantined_bundle(
+                        raw,
+                        header.as_ref().map(|b| b.bundle_id),
+                        header.as_ref().map(|b| b.actor_id),
+                        &reason,
+                    )?;
+                    quarantined += 1;
+                }
+            }
+        }
+        let mut report = self.ingest_bundles(&decoded)?;
+        report.bundles_quarantined += quarantined;
+        report.duration = elapsed_since(started)?;
+        Ok(report)
+    }
+
+    /// Bundles quarantined by `ingest_raw_bundles`, oldest first.
+    pub fn list_quarantined(&self) -> Result<Vec<QuarantinedBundle>, EngineError> {
+        Ok(self.storage.list_quarantined_bundles()?)
+    }
+
+    /// Decode, verify and ingest a quarantined bundle again (e.g. after an
+    /// upgrade taught this build its payload types or module versions, or a
+    /// workspace key was set). On success it leaves the quarantine and the
+    /// ingest report is returned; otherwise it stays, with the new reason, and
+    /// `InvalidBundle` (or `UnsupportedModule` while held by
+    /// `ModulePolicy::Hold`, `PermissionDenied` while its author lacks the
+    /// role, or `NoWorkspaceKey` while a sealed bundle can't be opened) is
+    /// returned.
+    pub fn retry_quarantined(&mut self, quarantine_id: i64) -> Result<IngestReport, EngineError> {
+        let entry = self
+            .storage
+            .get_quarantined_bundle(quarantine_id)?
+            .ok_or_else(|| EngineError::QuarantineNotFound(quarantine_id.to_string()))?;
+        match self.decode_verified(&entry.raw) {
+            Ok((bundle, operations)) => {
+                let mismatches = self.modules.check(bundle.bundle_id, &operations);
+                if self.modules.policy() == ModulePolicy::Hold && !mismatches.is_empty() {
+                    self.storage.update_quarantine_reason(quarantine_id, &module_hold_reason(&mismatches))?;
+                    return Err(EngineError::UnsupportedModule(module_mismatch_details(&mismatches)));
+                }
+                let report = self.ingest_bundles(&[(bundle, operations)])?;
+                if report.bundles_denied > 0 {
+                    // Still denied: ingesting it again refreshed the entry
+                    let reason = self.storage.get_quarantined_bundle(quarantine_id)?.map(|q| q.reason);
+                    return Err(EngineError::PermissionDenied(reason.unwrap_or_default()));
+                }
+                self.storage.delete_quarantined_bundle(quarantine_id)?;
+                Ok(report)
+            }
+            Err(reason) => {
+                self.storage.update_quarantine_reason(quarantine_id, &reason)?;
+                if self.workspace_key.is_none() && matches!(SealedBundle::from_raw(&entry.raw), Ok(Some(_))) {
+                    return Err(EngineError::NoWorkspaceKey);
+                }
+                Err(EngineError::InvalidBundle(reason))
+            }
+        }
+    }
+
+    /// Drop a quarantined bundle without ingesting it.
+    /// Returns false if it was not quarantined.
+    pub fn discard_quarantined(&mut self, quarantine_id: i64) -> Result<bool, EngineError> {
+        Ok(self.storage.delete_quarantined_bundle(quarantine_id)?)
+    }
+
+    /// With `EngineBuilder::verify_signatures`, fail with `InvalidBundle` unless
+    /// the bundle and its operations verify.
+    fn check_signatures(&self, bundle: &Bundle, operations: &[Operation]) -> Result<(), EngineError> {
+        if !self.verify_signatures {
+            return Ok(());
+        }
+        self.verify_bundle(bundle, operations).map_err(EngineError::InvalidBundle)
+    }
+
+    /// The checks `append_bundle` would make, run before a bundle is buffered
+    /// so the pending area only holds bundles that can apply once released.
+    fn check_pending(&self, bundle: &Bundle, operations: &[Operation]) -> Result<(), EngineError> {
+        check_bundle_ops(bundle, operations)?;
+        if self.verify_on_append {
+            self.verify_bundle(bundle, operations).map_err(EngineError::InvalidBundle)?;
+        }
+        Ok(())
+    }
+
+    /// `Storage::append_bundle`, checking the bundle first with
+    /// `EngineBuilder::verify_on_append`.
+    pub(crate) fn append_bundle(&mut self, bundle: &Bundle, operations: &[Operation]) -> Result<Vec<OpId>, EngineError> {
+        if self.verify_on_append {
+            self.verify_bundle(bundle, operations).map_err(EngineError::InvalidBundle)?;
+        }
+        if operations.iter().any(|op| may_change_rotations(&op.payload)) {
+            self.key_rotations.take();
+        }
+        Ok(self.storage.append_bundle(bundle, operations)?)
+    }
+
+    /// `Storage::rollback_transaction`, dropping caches built from the
+    /// writes being rolled back.
+    pub(crate) fn rollback_transaction(&mut self) -> Result<(), EngineError> {
+        self.key_rotations.take();
+        Ok(self.storage.rollback_transaction()?)
+    }
+
+    /// `Bundle::verify`, or `Bundle::verify_partial` with a `SyncScope`,
+    /// since peers leave out the ops outside it.
+    pub(crate) fn verify_bundle(&self, bundle: &Bundle, operations: &[Operation]) -> Result<(), String> {
+        if self.sync_scope.is_all() {
+            verify_bundle_integrity(bundle, operations)
+        } else {
+            bundle.verify_partial(operations).map_err(|e| e.to_string())
+        }
+    }
+
+    /// Decode a raw bundle and verify it, or say why it can't be ingested.
+    fn decode_verified(&self, raw: &RawBundle) -> Result<(Bundle, Vec<Operation>), String> {
+        let (bundle, operations) = match SealedBundle::from_raw(raw).map_err(|e| e.to_string())? {
+            Some(sealed) => {
+                let key = self.workspace_key.as_ref().ok_or_else(|| EngineError::NoWorkspaceKey.to_string())?;
+                sealed.open(key).map_err(|e| e.to_string())?
+            }
+            None => raw.decode().map_err(|e| e.to_string())?,
+        };
+        self.verify_bundle(&bundle, &operations)?;
+        Ok((bundle, operations))
+    }
+
+    /// Entries of the bundle's `creator_vc` that our vector clock does not cover.
+    /// None with a `SyncScope`: bundles out of scope never arrive, so the
+    /// clock may never cover them.
+    fn missing_dependencies(&self, bundle: &Bundle) -> Result<Vec<MissingDependency>, EngineError> {
+        let Some(creator_vc) = bundle.creator_vc.as_ref().filter(|_| self.sync_scope.is_all()) else {
+            return Ok(Vec::new());
+        };
+        let local_vc = self.storage.get_vector_clock()?;
+        Ok(local_vc
+            .diff(creator_vc)
+            .into_iter()
+            .filter_map(|(actor_id, have)| {
+                creator_vc.get(&actor_id).map(|required| MissingDependency {
+                    actor_id,
+                    required: *required,
+                    have,
+                })
+            })
+            .collect())
+    }
+
+    /// Transaction shared by all ingest entry points: apply (or buffer) each
+    /// bundle, release pending bundles that became ready, then scan for drift.
+    pub(crate) fn ingest_batch(
+        &mut self,
+        batch: &[(&Bundle, &[Operation])],
+    ) -> Result<IngestReport, EngineError> {
+        let started = physical_now()?;
+        self.storage.begin_transaction()?;
+
+        let result = (|| -> Result<(IngestReport, Vec<BundleId>), EngineError> {
+            let mut cache = FieldSourceCache::new();
+            let mut report = IngestReport::default();
+            let mut buffered: BTreeSet<BundleId> = BTreeSet::new();
+            let mut modified_fields: Vec<(EntityId, String)> = Vec::new();
+            let mut new_bundles: Vec<BundleId> = Vec::new();
+
+            for (bundle, operations) in batch {
+                if self.storage.get_bundle(bundle.bundle_id)?.is_none() {
+                    match self.admit(bundle, operations)? {
+                        Admission::Apply(mismatches) => report.module_mismatches.extend(mismatches),
+                        Admission::Untrusted => {
+                            self.storage.insert_untrusted_bundle(bundle, operations)?;
+                            report.bundles_untrusted += 1;
+                            continue;
+                        }
+                        Admission::Held(mismatches) => {
+                            self.quarantine_bundle(bundle, operations, &module_hold_reason(&mismatches))?;
+                            report.bundles_held += 1;
+                            report.module_mismatches.extend(mismatches);
+                            continue;
+                        }
+                    }
+                }
+                if self.missing_dependencies(bundle)?.is_empty() {
+                    let is_new = self.storage.get_bundle(bundle.bundle_id)?.is_none();
+                    match self.apply_ready_bundle(bundle, operations, &mut cache, &mut report) {
+                        Ok(()) => {}
+                        Err(EngineError::PermissionDenied(reason)) => {
+                            self.quarantine_denied(bundle, operations, &reason, &mut report)?;
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    }
+                    if is_new {
+                        new_bundles.push(bundle.bundle_id);
+                    }
+                    self.record_changes(operations.iter().map(|op| &op.payload));
+                    modified_fields.extend(modified_fields_of(operations));
+                } else {
+                    self.check_pending(bundle, operations)?;
+                    self.storage.insert_pending_bundle(bundle, operations)?;
+                    buffered.insert(bundle.bundle_id);
+                }
+            }
+
+            // Release pending bundles, re-checking only those whose awaited
+            // actor clock a released bundle advanced
+            let mut queue = PendingQueue::default();
+            for pending in self.storage.list_pending_bundles()? {
+                let missing = self.missing_dependencies(&pending.bundle)?;
+                queue.push(pending, &missing);
+            }
+            while let Some(pending) = queue.pop_ready() {
+                let is_new = self.storage.get_bundle(pending.bundle.bundle_id)?.is_none();
+                let result = self.check_pending(&pending.bundle, &pending.operations).and_then(|()| {
+                    self.apply_ready_bundle(&pending.bundle, &pending.operations, &mut cache, &mut report)
+                });
+                buffered.remove(&pending.bundle.bundle_id);
+                match result {
+                    Ok(()) => {}
+                    Err(EngineError::PermissionDenied(reason)) => {
+                        self.storage.delete_pending_bundle(pending.bundle.bundle_id)?;
+                        self.quarantine_denied(&pending.bundle, &pending.operations, &reason, &mut report)?;
+                        continue;
+                    }
+                    // Rejected before anything was written: one bad
+                    // buffered bundle must not wedge every later ingest
+                    Err(
+                        e @ (EngineError::InvalidBundle(_)
+                        | EngineError::Storage(
+                            StorageError::ConstraintViolation(_) | StorageError::EntityCollision { .. },
+                        )),
+                    ) => {
+                        self.storage.delete_pending_bundle(pending.bundle.bundle_id)?;
+                        self.quarantine_bundle(&pending.bundle, &pending.operations, &e.to_string())?;
+                        report.bundles_quarantined += 1;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+                if is_new {
+                    new_bundles.push(pending.bundle.bundle_id);
+                }
+                self.record_changes(pending.operations.iter().map(|op| &op.payload));
+                modified_fields.extend(modified_fields_of(&pending.operations));
+                let actor_id = pending.bundle.actor_id;
+                if let Some(have) = self.storage.get_vector_clock()?.get(&actor_id).copied() {
+                    for woken in queue.wake(actor_id, have) {
+                        let missing = self.missing_dependencies(&woken.bundle)?;
+                        queue.push(woken, &missing);
+                    }
+                }
+            }
+
+            if !new_bundles.is_empty() {
+                self.refresh_unique_edge_types()?;
+                self.refresh_facet_fields()?;
+            }
+
+            // Scan for overlay drift once per modified field
+            modified_fields.sort();
+            modified_fields.dedup();
+            if self.scan_drift_on_ingest {
+                report.drift_flagged = self.scan_overlay_drift(&modified_fields)?;
+            }
+            report.bundles_buffered = buffered.len();
+
+            Ok((report, new_bundles))
+        })();
+
+        match result {
+            Ok((mut report, new_bundles)) => {
+                self.storage.commit_transaction()?;
+                if !new_bundles.is_empty() {
+                    self.refresh_actor_names()?;
+                }
+                for bundle_id in new_bundles {
+                    self.notify_bundle(bundle_id);
+                }
+                self.notify_watchers();
+                report.duration = elapsed_since(started)?;
+                Ok(report)
+            }
+            Err(e) => {
+                let _ = self.rollback_transaction();
+                self.conflict_events.clear();
+                Err(e)
+            }
+        }
+    }
+
+    /// Checks a new bundle must pass before it is applied, shared with
+    /// `preview_ingest`: signatures, the actor's trust and module versions.
+    /// Fails on a bad signature or a rejected actor, as ingest does.
+    fn admit(&mut self, bundle: &Bundle, operations: &[Operation]) -> Result<Admission, EngineError> {
+        self.check_signatures(bundle, operations)?;
+        match self.trust_decision(bundle.actor_id)? {
+            TrustDecision::Accept => {}
+            TrustDecision::Quarantine => return Ok(Admission::Untrusted),
+            TrustDecision::Reject => return Err(EngineError::UntrustedActor(bundle.actor_id.to_string())),
+        }
+        let mismatches = self.modules.check(bundle.bundle_id, operations);
+        Ok(match self.modules.policy() {
+            _ if mismatches.is_empty() => Admission::Apply(mismatches),
+            ModulePolicy::Accept => Admission::Apply(Vec::new()),
+            ModulePolicy::Warn => Admission::Apply(mismatches),
+            ModulePolicy::Hold => Admission::Held(mismatches),
+        })
+    }
+
+    /// Quarantine a bundle whose author lacked the role for it, so it can be
+    /// inspected or discarded instead of failing the batch or vanishing.
+    fn quarantine_denied(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+        reason: &str,
+        report: &mut IngestReport,
+    ) -> Result<(), EngineError> {
+        self.quarantine_bundle(bundle, operations, &format!("permission denied: {reason}"))?;
+        report.bundles_denied += 1;
+        Ok(())
+    }
+
+    /// Store a decoded bundle in quarantine with the reason it was set aside.
+    fn quarantine_bundle(&mut self, bundle: &Bundle, operations: &[Operation], reason: &str) -> Result<(), EngineError> {
+        let raw = RawBundle::encode(bundle, operations)?;
+        self.storage.insert_quarantined_bundle(&raw, Some(bundle.bundle_id), Some(bundle.actor_id), reason)?;
+        Ok(())
+    }
+
+    /// Apply a bundle whose causal dependencies are satisfied, tallying what it
+    /// did into `report`. Must run inside a transaction.
+    fn apply_ready_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+        cache: &mut FieldSourceCache,
+        report: &mut IngestReport,
+    ) -> Result<(), EngineError> {
+        // Re-ingesting a stored bundle is a no-op, so only new bundles are checked
+        let is_new = self.storage.get_bundle(bundle.bundle_id)?.is_none();
+        if is_new {
+            self.check_permission(bundle.actor_id, bundle.creator_vc.as_ref(), operations.iter().map(|op| &op.payload))?;
+        }
+
+        // 1. Snapshot field metadata for all SetField/ClearField ops BEFORE materialization
+        let pre_snapshots = self.snapshot_field_metadata_cached(operations, cache)?;
+
+        if is_new {
+            report.bundles_applied += 1;
+            self.observe_remote_clock(bundle, report)?;
+            let mut created = BTreeSet::new();
+            for op in operations {
+                if let OperationPayload::CreateEntity { entity_id, .. } = &op.payload
+                    && self.storage.get_entity(*entity_id)?.is_none()
+                    && created.insert(*entity_id)
+                {
+                    report.entities_created += 1;
+                }
+            }
+        } else {
+            report.bundles_already_present += 1;
+        }
+
+        // 2. Append bundle (materializes ops via SAVEPOINT, nests correctly)
+        //    and release it from the pending area if it was buffered. A
+        //    bundle already stored is skipped whole.
+        report.ops_duplicate += self.append_bundle(bundle, operations)?.len();
+        self.storage.delete_pending_bundle(bundle.bundle_id)?;
+        cache.advance(bundle, operations);
+
+        if is_new {
+            let lost = pre_snapshots
+                .iter()
+                .filter(|snap| {
+                    let Some(op) = operations.iter().find(|op| op.op_id == snap.ingested_op_id) else {
+                        return false;
+                    };
+                    snap.current_hlc.zip(snap.current_op_id).is_some_and(|current| current > (op.hlc, op.op_id))
+                })
+                .count();
+            report.ops_skipped_lww += lost;
+            report.ops_applied += operations.len() - lost;
+        }
+
+        // 3. Detect conflicts using pre-materialization snapshots
+        for (change, conflict) in self.detect_conflicts(bundle, operations, &pre_snapshots)? {
+            match change {
+                ConflictChange::Opened => report.conflicts_opened += 1,
+                ConflictChange::Extended => report.conflicts_extended += 1,
+                ConflictChange::Reopened => report.conflicts_reopened += 1,
+            }
+            if !self.conflict_watchers.is_empty() {
+                self.conflict_events.push(match change {
+                    ConflictChange::Opened => ConflictEvent::Opened(conflict.clone()),
+                    ConflictChange::Extended => ConflictEvent::Extended(conflict.clone()),
+                    ConflictChange::Reopened => ConflictEvent::Reopened(conflict.clone()),
+                });
+            }
+            report.conflicts.push(conflict);
+        }
+        Ok(())
+    }
+
+    /// Dry-run ingest: report what `ingest_bundle` would change without writing.
+    ///
+    /// Runs the same admission checks as ingest (signatures, trust, module
+    /// versions and the author's role), then the full pipeline
+    /// (materialization, conflict detection and overlay drift scanning) inside a
+    /// transaction that is always rolled back, so the preview matches real
+    /// ingest, including LWW outcomes and N-way conflict extension. A bundle
+    /// ingest would set aside only reports why; one that would be buffered for
+    /// missing causal dependencies only reports those dependencies. Watchers
+    /// are not told about the discarded writes.
+    pub fn preview_ingest(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<IngestPreview, EngineError> {
+        let missing_dependencies = self.missing_dependencies(bundle)?;
+        if !missing_dependencies.is_empty() {
+            self.check_signatures(bundle, operations)?;
+            self.check_pending(bundle, operations)?;
+            return Ok(IngestPreview { missing_dependencies, ..Default::default() });
+        }
+
+        self.storage.begin_transaction()?;
+        let changes = std::mem::take(&mut self.changes);
+
+        let result = (|| -> Result<IngestPreview, EngineError> {
+            let mut module_mismatches = Vec::new();
+            if self.storage.get_bundle(bundle.bundle_id)?.is_none() {
+                let payloads = operations.iter().map(|op| &op.payload);
+                let set_aside = match self.admit(bundle, operations)? {
+                    Admission::Apply(mismatches) => {
+                        module_mismatches = mismatches;
+                        match self.check_permission(bundle.actor_id, bundle.creator_vc.as_ref(), payloads) {
+                            Ok(()) => None,
+                            Err(EngineError::PermissionDenied(reason)) => Some(SetAside::Denied(reason)),
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    Admission::Untrusted => Some(SetAside::Untrusted),
+                    Admission::Held(mismatches) => {
+                        let reason = module_hold_reason(&mismatches);
+                        module_mismatches = mismatches;
+                        Some(SetAside::Held(reason))
+                    }
+                };
+                if set_aside.is_some() {
+                    return Ok(IngestPreview { set_aside, module_mismatches, ..Default::default() });
+                }
+            }
+
+            let mut touched_fields: Vec<(EntityId, String)> = Vec::new();
+            let mut touched_entities: Vec<EntityId> = Vec::new();
+            for op in operations {
+                match &op.payload {
+                    OperationPayload::SetField { entity_id, field_key, .. }
+                    | OperationPayload::ClearField { entity_id, field_key }
+                    | OperationPayload::ResolveConflict { entity_id, field_key, .. }
+                        if !touched_fields.iter().any(|(e, k)| e == entity_id && k == field_key) =>
+                    {
+                        touched_fields.push((*entity_id, field_key.clone()));
+                    }
+                    OperationPayload::CreateEntity { entity_id, .. }
+                    | OperationPayload::DeleteEntity { entity_id, .. }
+                        if !touched_entities.contains(entity_id) =>
+                    {
+                        touched_entities.push(*entity_id);
+                    }
+                    _ => {}
+                }
+            }
+
+            // Capture state before materialization
+            let mut fields_before = Vec::with_capacity(touched_fields.len());
+            for (entity_id, field_key) in &touched_fields {
+                fields_before.push(self.storage.get_field(*entity_id, field_key)?);
+            }
+            let mut entities_before = Vec::with_capacity(touched_entities.len());
+            for entity_id in &touched_entities {
+                entities_before.push(self.storage.get_entity(*entity_id)?);
+            }
+
+            let mut overlays = self.storage.list_overlays_by_status(OverlayStatus::Active.as_str())?;
+            overlays.extend(self.storage.list_overlays_by_status(OverlayStatus::Stashed.as_str())?);
+            let mut drift_before = Vec::with_capacity(overlays.len());
+            for (overlay_id, ..) in &overlays {
+                drift_before.push(self.check_drift(*overlay_id)?);
+            }
+
+            // Run the real pipeline
+            let pre_snapshots = self.snapshot_field_metadata(operations)?;
+            self.append_bundle(bundle, operations)?;
+            let conflicts = self.detect_conflicts(bundle, operations, &pre_snapshots)?;
+            let conflicts = conflicts.into_iter().map(|(_, conflict)| conflict).collect();
+            self.scan_overlay_drift(&modified_fields_of(operations))?;
+
+            // Diff against post-materialization state
+            let mut preview = IngestPreview { conflicts, module_mismatches, ..Default::default() };
+            for ((overlay_id, ..), before) in overlays.iter().zip(drift_before) {
+                for drift in self.check_drift(*overlay_id)? {
+                    let is_new = !before.iter().any(|d| d.entity_id == drift.entity_id && d.field_key == drift.field_key);
+                    let seen = preview.drift.iter().any(|d| {
+                        d.overlay_id == *overlay_id
+                            && d.drift.entity_id == drift.entity_id
+                            && d.drift.field_key == drift.field_key
+                    });
+                    if is_new && !seen {
+                        preview.drift.push(OverlayDrift { overlay_id: *overlay_id, drift });
+                    }
+                }
+            }
+            for ((entity_id, field_key), old_value) in touched_fields.into_iter().zip(fields_before) {
+                let new_value = self.storage.get_field(entity_id, &field_key)?;
+                if new_value != old_value {
+                    preview.field_changes.push(FieldChange {
+                        entity_id,
+                        field_key,
+                        old_value,
+                        new_value,
+                    });
+                }
+            }
+            for (entity_id, before) in touched_entities.into_iter().zip(entities_before) {
+                let after = self.storage.get_entity(entity_id)?;
+                let live_before = before.as_ref().is_some_and(|e| !e.deleted);
+                let live_after = after.as_ref().is_some_and(|e| !e.deleted);
+                if before.is_none() && after.is_some() {
+                    preview.entities_created.push(entity_id);
+                } else if live_before && !live_after {
+                    preview.entities_deleted.push(entity_id);
+                }
+            }
+
+            Ok(preview)
+        })();
+
+        // Always discard: a preview must never leave a trace
+        self.changes = changes;
+        self.rollback_transaction()?;
+        result
+    }
+
+    /// Pre-materialization snapshot of field metadata for conflict detection.
+    fn snapshot_field_metadata(
+        &self,
+        operations: &[Operation],
+    ) -> Result<Vec<FieldMetadataSnapshot>, EngineError> {
+        self.snapshot_field_metadata_cached(operations, &mut FieldSourceCache::new())
+    }
+
+    /// `snapshot_field_metadata`, reading each field from storage only on a cache miss.
+    fn snapshot_field_metadata_cached(
+        &self,
+        operations: &[Operation],
+        cache: &mut FieldSourceCache,
+    ) -> Result<Vec<FieldMetadataSnapshot>, EngineError> {
+        let mut snapshots = Vec::new();
+        for op in operations {
+            match &op.payload {
+                OperationPayload::SetField { entity_id, field_key, value } => {
+                    let current = cache.get(&self.storage, *entity_id, field_key)?;
+                    let value_bytes = value.to_msgpack()
+                        .map_err(|e| EngineError::Core(openprod_core::CoreError::Serialization(e.to_string())))?;
+                    snapshots.push(FieldMetadataSnapshot {
+                        entity_id: *entity_id,
+                        field_key: field_key.clone(),
+                        current_actor: current.as_ref().map(|(a, _, _, _)| *a),
+                        current_hlc: current.as_ref().map(|(_, h, _, _)| *h),
+                        current_op_id: current.as_ref().map(|(_, _, o, _)| *o),
+                        current_bundle_vc: current.and_then(|(_, _, _, vc)| vc),
+                        ingested_op_id: op.op_id,
+                        ingested_value: Some(value_bytes),
+                    });
+                }
+                OperationPayload::ClearField { entity_id, field_key } => {
+                    let current = cache.get(&self.storage, *entity_id, field_key)?;
+                    snapshots.push(FieldMetadataSnapshot {
+                        entity_id: *entity_id,
+                        field_key: field_key.clone(),
+                        current_actor: current.as_ref().map(|(a, _, _, _)| *a),
+                        current_hlc: current.as_ref().map(|(_, h, _, _)| *h),
+                        current_op_id: current.as_ref().map(|(_, _, o, _)| *o),
+                        current_bundle_vc: current.and_then(|(_, _, _, vc)| vc),
+                        ingested_op_id: op.op_id,
+                        ingested_value: None,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(snapshots)
+    }
+
+    /// Detect field-level conflicts by comparing the ingested bundle's vector clock
+    /// against the pre-materialization field state.
+    fn detect_conflicts(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+        pre_snapshots: &[FieldMetadataSnapshot],
+    ) -> Result<Vec<(ConflictChange, ConflictRecord)>, EngineError> {
+        let ingested_actor = bundle.actor_id;
+        let ingested_vc = bundle.creator_vc.as_ref();
+        let groups = self.actor_groups()?;
+
+        let mut conflicts = Vec::new();
+
+        for snap in pre_snapshots {
+            // 1. No prior value → no conflict
+            let current_actor = match snap.current_actor {
+                Some(a) => a,
+                None => continue,
+            };
+            let current_hlc = snap.current_hlc.unwrap(); // safe: actor implies hlc
+            let current_op_id = snap.current_op_id.unwrap();
+
+            // 2. Same logical actor (or same user's device) → no conflict
+            if groups.same(current_actor, ingested_actor) {
+                continue;
+            }
+
+            // Find the ingested op's HLC
+            let ingested_op = operations.iter().find(|o| o.op_id == snap.ingested_op_id);
+            let ingested_hlc = match ingested_op {
+                Some(op) => op.hlc,
+                None => continue,
+            };
+
+            // 3. Did ingested actor know about the current value?
+            //    creator_vc.get(current_actor) >= current_hlc?
+            if let Some(vc) = ingested_vc
+                && let Some(known_hlc) = vc.get(&current_actor)
+                && *known_hlc >= current_hlc
+            {
+                continue; // ingested saw the current value → not concurrent
+            }
+
+            // 4. Did the current writer know about the ingested actor?
+            //    current_bundle_vc.get(ingested_actor) >= ingested_hlc?
+            if let Some(ref current_vc) = snap.current_bundle_vc
+                && let Some(known_hlc) = current_vc.get(&ingested_actor)
+                && *known_hlc >= ingested_hlc
+            {
+                continue; // current writer saw the ingested actor → not concurrent
+            }
+
+            // Both didn't see each other → CONFLICT
+            // Check for existing conflict on this (entity, field) — open or resolved
+            let existing = self.storage.get_latest_conflict_for_field(snap.entity_id, &snap.field_key)?;
+
+            // Get the current field's value bytes for the conflict record
+            let current_value_bytes: Option<Vec<u8>> = {
+                self.get_field_value_from_oplog(current_op_id)?
+            };
+
+            let incoming_tip = ConflictValue {
+                value: snap.ingested_value.clone(),
+                actor_id: ingested_actor,
+                hlc: ingested_hlc,
+                op_id: snap.ingested_op_id,
+            };
+
+            if let Some(existing) = existing {
+                if existing.status == ConflictStatus::Resolved {
+                    // Resolved conflict being reopened by a new concurrent edit.
+                    // Build fresh branch tips from resolution + late-arriving edit.
+                    let resolution_tip = ConflictValue {
+                        value: existing.resolved_value.clone(),
+                        actor_id: existing.resolved_by.unwrap(),
+                        hlc: existing.resolved_at.unwrap(),
+                        op_id: existing.resolved_op_id.unwrap(),
+                    };
+                    self.storage.reopen_conflict(
+                        existing.conflict_id,
+                        ingested_hlc,
+                        snap.ingested_op_id,
+                        &[resolution_tip, incoming_tip],
+                    )?;
+                    conflicts.push((ConflictChange::Reopened, self.storage.get_conflict(existing.conflict_id)?.unwrap()));
+                } else {
+                    // Already open — extend to N-way by adding the new branch tip
+                    self.storage.add_conflict_value(existing.conflict_id, &incoming_tip)?;
+                    conflicts.push((ConflictChange::Extended, self.storage.get_conflict(existing.conflict_id)?.unwrap()));
+                }
+                continue;
+            }
+
+            // Create new conflict
+            let conflict_id = ConflictId::new();
+            let record = ConflictRecord {
+                conflict_id,
+                entity_id: snap.entity_id,
+                field_key: snap.field_key.clone(),
+                status: ConflictStatus::Open,
+                values: vec![
+                    ConflictValue {
+                        value: current_value_bytes,
+                        actor_id: current_actor,
+                        hlc: current_hlc,
+                        op_id: current_op_id,
+                    },
+                    incoming_tip,
+                ],
+                detected_at: ingested_hlc,
+                detected_in_bundle: bundle.bundle_id,
+                resolved_at: None,
+                resolved_by: None,
+                resolved_op_id: None,
+                resolved_value: None,
+                reopened_at: None,
+                reopened_by_op: None,
+            };
+            self.storage.insert_conflict(&record)?;
+            conflicts.push((ConflictChange::Opened, record));
+        }
+
+        Ok(conflicts)
+    }
+
+    /// Extract a field value from an oplog operation by op_id.
+    fn get_field_value_from_oplog(&self, op_id: OpId) -> Result<Option<Vec<u8>>, EngineError> {
+        Ok(self.storage.get_op_field_value(op_id)?)
+    }
+}
+
+/// Source of a field's current value: (actor, HLC, op, creator VC of its bundle).
+type FieldSource = (ActorId, Hlc, OpId, Option<VectorClock>);
+
+/// Field sources read during a batch ingest, kept current in memory as bundles
+/// are applied so each field is read from storage at most once.
+struct FieldSourceCache {
+    entries: BTreeMap<(EntityId, String), Option<FieldSource>>,
+}
+
+impl FieldSourceCache {
+    fn new() -> Self {
+        Self { entries: BTreeMap::new() }
+    }
+
+    fn get(
+        &mut self,
+        storage: &impl Storage,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<FieldSource>, EngineError> {
+        let key = (entity_id, field_key.to_string());
+        if let Some(source) = self.entries.get(&key) {
+            return Ok(source.clone());
+        }
+        let source = storage.get_field_source_bundle_vc(entity_id, field_key)?;
+        self.entries.insert(key, source.clone());
+        Ok(source)
+    }
+
+    /// Mirror materialization of an applied bundle: SetField/ClearField follow
+    /// the same LWW rule as storage; any other op on an entity evicts that
+    /// entity's fields so they are re-read.
+    fn advance(&mut self, bundle: &Bundle, operations: &[Operation]) {
+        for op in operations {
+            match &op.payload {
+                OperationPayload::SetField { entity_id, field_key, .. }
+                | OperationPayload::ClearField { entity_id, field_key } => {
+                    if let Some(source) = self.entries.get_mut(&(*entity_id, field_key.clone())) {
+                        let wins = source.as_ref().is_none_or(|(_, hlc, op_id, _)| (op.hlc, op.op_id) > (*hlc, *op_id));
+                        if wins {
+                            *source = Some((op.actor_id, op.hlc, op.op_id, bundle.creator_vc.clone()));
+                        }
+                    }
+                }
+                payload => {
+                    if let Some(entity_id) = payload.entity_id() {
+                        self.entries.retain(|(e, _), _| *e != entity_id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Pre-materialization snapshot of a field's metadata for conflict detection.
+struct FieldMetadataSnapshot {
+    entity_id: EntityId,
+    field_key: String,
+    current_actor: Option<ActorId>,
+    current_hlc: Option<Hlc>,
+    current_op_id: Option<OpId>,
+    current_bundle_vc: Option<VectorClock>,
+    ingested_op_id: OpId,
+    ingested_value: Option<Vec<u8>>,
+}
+
+/// `Bundle::verify`, with the failure as a reason for reports and errors.
+pub(crate) fn verify_bundle_integrity(bundle: &Bundle, operations: &[Operation]) -> Result<(), String> {
+    bundle.verify(operations).map_err(|e| e.to_string())
+}
+
+/// Quarantine reason for a bundle held by `ModulePolicy::Hold`.
+fn module_hold_reason(mismatches: &[ModuleMismatch]) -> String {
+    format!("unsupported module version: {}", module_mismatch_details(mismatches))
+}
+
+fn module_mismatch_details(mismatches: &[ModuleMismatch]) -> String {
+    mismatches.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+}
+
+/// Time since `started` (from `physical_now`), clamped at zero if the clock stepped back.
+fn elapsed_since(started: u64) -> Result<Duration, EngineError> {
+    Ok(Duration::from_millis(physical_now()?.saturating_sub(started)))
+}
+
+/// (entity, field) pairs written by SetField/ClearField ops.
+fn modified_fields_of(operations: &[Operation]) -> Vec<(EntityId, String)> {
+    operations.iter().filter_map(|op| {
+        match &op.payload {
+            OperationPayload::SetField { entity_id, field_key, .. }
+            | OperationPayload::ClearField { entity_id, field_key } => {
+                Some((*entity_id, field_key.clone()))
+            }
+            _ => None,
+        }
+    }).collect()
+}
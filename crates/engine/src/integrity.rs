@@ -0,0 +1,183 @@
+use openprod_core::{hlc::Hlc, ids::BundleId};
+use openprod_storage::{EngineStorage, MaterializeProgress};
+#[cfg(feature = "sqlite")]
+use openprod_core::digest::HlcRange;
+#[cfg(feature = "sqlite")]
+use openprod_storage::{DivergentRow, MaterializationStore, OrphanedRows, SqliteStorage, Storage};
+
+use crate::Engine;
+use crate::error::EngineError;
+
+/// Result of `Engine::verify_integrity`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IntegrityReport {
+    pub bundles_checked: usize,
+    pub ops_checked: usize,
+    /// Bundles failing signature, checksum or op membership checks, with the reason.
+    pub bundle_errors: Vec<(BundleId, String)>,
+    /// Broken references between the oplog, bundles and materialized tables.
+    pub reference_errors: Vec<String>,
+    /// Problems reported by SQLite's `quick_check`.
+    pub storage_errors: Vec<String>,
+    /// Whether replaying the oplog reproduces the materialized state.
+    pub replay_matches: bool,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.bundle_errors.is_empty()
+            && self.reference_errors.is_empty()
+            && self.storage_errors.is_empty()
+            && self.replay_matches
+    }
+}
+
+/// Result of `Engine::check_consistency`.
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConsistencyReport {
+    /// Rows whose parent row is missing, per table.
+    pub orphans: Vec<OrphanedRows>,
+    /// Materialized rows that differ from a replay of the oplog.
+    pub divergent: Vec<DivergentRow>,
+    /// Set when the oplog could not be replayed, so `divergent` is unknown.
+    pub replay_error: Option<String>,
+    /// Other foreign keys pointing at missing rows.
+    pub dangling: Vec<String>,
+}
+
+#[cfg(feature = "sqlite")]
+impl ConsistencyReport {
+    pub fn is_ok(&self) -> bool {
+        self.orphans.is_empty() && self.divergent.is_empty() && self.replay_error.is_none() && self.dangling.is_empty()
+    }
+}
+
+/// Result of `Engine::repair`.
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RepairReport {
+    /// Materialized rows rewritten or deleted to match the oplog.
+    pub rematerialized: u64,
+    /// Orphaned rows deleted.
+    pub orphans_removed: u64,
+    /// What a check finds after the repair.
+    pub remaining: ConsistencyReport,
+}
+
+impl<S: EngineStorage> Engine<S> {
+    // ========================================================================
+    // State Rebuild
+    // ========================================================================
+
+    /// Rebuild materialized state from the oplog. Returns the number of operations replayed.
+    pub fn rebuild_state(&mut self) -> Result<u64, EngineError> {
+        let count = self.storage.rebuild_from_oplog()?;
+        self.key_rotations.take();
+        self.refresh_actor_names()?;
+        Ok(count)
+    }
+
+    /// Re-derive only the materialized state written since the last
+    /// checkpoint (a rebuild, recovery, or clean `verify_integrity`), reporting
+    /// progress as ops replay. Without a checkpoint this replays everything.
+    /// Returns the number of operations replayed.
+    pub fn recover_state(&mut self, progress: &mut dyn FnMut(MaterializeProgress)) -> Result<u64, EngineError> {
+        let from = match self.storage.materialization_watermark()? {
+            // Ops stored since the checkpoint can be older than ones it covered
+            // (offline edits synced in late), so replay from the earliest
+            Some(mut seq) => {
+                let mut earliest: Option<Hlc> = None;
+                loop {
+                    let page = self.storage.get_ops_after_seq(seq, 1000)?;
+                    let Some((last, _)) = page.last() else { break };
+                    seq = *last;
+                    earliest = page.iter().map(|(_, op)| op.hlc).chain(earliest).min();
+                }
+                match earliest {
+                    Some(hlc) => hlc,
+                    None => return Ok(0),
+                }
+            }
+            None => Hlc::new(0, 0),
+        };
+        let count = self.storage.materialize_from(from, progress)?;
+        self.key_rotations.take();
+        self.refresh_actor_names()?;
+        Ok(count)
+    }
+}
+
+/// Checks that rely on SQLite specifics (`PRAGMA` checks).
+#[cfg(feature = "sqlite")]
+impl Engine<SqliteStorage> {
+    // ========================================================================
+    // Integrity Verification
+    // ========================================================================
+
+    /// Re-check every stored bundle (signatures, op membership, checksum),
+    /// references between the oplog, bundles and materialized tables, and
+    /// that replaying the oplog reproduces the materialized state. The replay
+    /// runs in a rolled-back savepoint; a clean report checkpoints the
+    /// materialization watermark so `recover_state` can start from here.
+    pub fn verify_integrity(&mut self) -> Result<IntegrityReport, EngineError> {
+        let mut report = IntegrityReport {
+            storage_errors: self.storage.quick_check()?,
+            ..Default::default()
+        };
+        for (_, bundle_id) in self.storage.get_bundle_ids_in_range(&HlcRange::full())? {
+            let bundle = self
+                .storage
+                .get_bundle(bundle_id)?
+                .ok_or_else(|| EngineError::BundleNotFound(bundle_id.to_string()))?;
+            let operations = self.storage.get_ops_by_bundle(bundle_id)?;
+            report.bundles_checked += 1;
+            report.ops_checked += operations.len();
+            if let Err(reason) = self.verify_bundle(&bundle, &operations) {
+                report.bundle_errors.push((bundle_id, reason));
+            }
+        }
+        report.reference_errors = self.storage.dangling_references()?;
+        report.replay_matches = self.storage.replay_matches()?;
+        if report.is_ok() {
+            self.storage.checkpoint_materialization()?;
+        }
+        Ok(report)
+    }
+
+    /// Look for orphaned rows (fields of missing entities, conflict values
+    /// without a conflict, overlay ops of missing overlays, ...), materialized
+    /// rows that differ from a replay of the oplog, and other dangling foreign
+    /// keys. Read-only; the replay is rolled back.
+    pub fn check_consistency(&mut self) -> Result<ConsistencyReport, EngineError> {
+        let (divergent, replay_error) = match self.storage.divergent_rows()? {
+            Ok(divergent) => (divergent, None),
+            Err(reason) => (Vec::new(), Some(reason)),
+        };
+        Ok(ConsistencyReport {
+            orphans: self.storage.orphaned_rows()?,
+            divergent,
+            replay_error,
+            dangling: self.storage.dangling_foreign_keys()?,
+        })
+    }
+
+    /// Fix what `check_consistency` finds where the oplog allows: divergent
+    /// rows are re-materialized from a replay (other rows are left alone),
+    /// then rows still orphaned are deleted. If the oplog cannot be replayed
+    /// only the orphans are removed. Dangling references into the oplog and
+    /// bundles are left for `verify_integrity` to report.
+    pub fn repair(&mut self) -> Result<RepairReport, EngineError> {
+        let rematerialized = match self.storage.divergent_rows()? {
+            Ok(divergent) if !divergent.is_empty() => self.storage.repair_materialized_rows()?,
+            _ => 0,
+        };
+        let orphans_removed = self.storage.delete_orphaned_rows()?;
+        if rematerialized + orphans_removed > 0 {
+            self.key_rotations.take();
+            self.changes.mark_all();
+            self.notify_watchers();
+        }
+        Ok(RepairReport { rematerialized, orphans_removed, remaining: self.check_consistency()? })
+    }
+}
@@ -0,0 +1,56 @@
+use openprod_core::{
+    hlc::Hlc,
+    ids::{ActorId, BundleId, OverlayId},
+    operations::{BundleMeta, BundleType, OperationPayload},
+};
+
+use crate::error::EngineError;
+
+/// App-level hook around every local write (`execute`, typed commands, undo,
+/// redo, overlay commits). Register with `Engine::add_interceptor`; hooks run
+/// in registration order. Bundles ingested from peers don't pass through.
+pub trait Interceptor: Send {
+    /// Runs before the payloads are checked against the ACL, signed and written.
+    /// May validate them (returning an error aborts the write, usually
+    /// `EngineError::Rejected`), rewrite them, or fill in `meta`.
+    fn before_execute(&mut self, write: &mut PendingWrite) -> Result<(), EngineError> {
+        let _ = write;
+        Ok(())
+    }
+
+    /// Runs once the write is stored, e.g. to update an index or notify the UI.
+    fn after_execute(&mut self, write: &CompletedWrite) {
+        let _ = write;
+    }
+}
+
+/// A local write about to be executed, as seen by `Interceptor::before_execute`.
+#[derive(Debug, Clone)]
+pub struct PendingWrite {
+    pub actor_id: ActorId,
+    pub bundle_type: BundleType,
+    pub payloads: Vec<OperationPayload>,
+    /// Attached to the bundle. Ignored for overlay writes, which create no bundle.
+    pub meta: Option<BundleMeta>,
+    /// The overlay the write goes to instead of canonical state, if one is active.
+    pub overlay_id: Option<OverlayId>,
+}
+
+impl PendingWrite {
+    /// Meta to annotate, created empty if the write has none yet.
+    pub fn meta_mut(&mut self) -> &mut BundleMeta {
+        self.meta.get_or_insert_with(BundleMeta::default)
+    }
+}
+
+/// A local write that has been stored, as seen by `Interceptor::after_execute`.
+#[derive(Debug, Clone)]
+pub struct CompletedWrite {
+    /// The canonical bundle, or a synthetic id for overlay writes.
+    pub bundle_id: BundleId,
+    pub hlc: Hlc,
+    pub bundle_type: BundleType,
+    pub payloads: Vec<OperationPayload>,
+    pub meta: Option<BundleMeta>,
+    pub overlay_id: Option<OverlayId>,
+}
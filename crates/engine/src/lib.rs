@@ -1,28 +1,111 @@
+pub mod acl;
+pub mod archive;
+pub mod builder;
+pub mod bulk;
+pub mod cdc;
+pub mod devices;
+pub mod directory;
+pub mod duplicate;
 pub mod error;
+pub mod export;
+pub mod facet_fields;
+pub mod history;
+pub mod idempotency;
+pub mod import;
+pub mod ingest;
+pub mod integrity;
+pub mod interceptor;
+pub mod list;
+pub mod merge;
+pub mod modules;
 pub mod overlay;
+pub mod presence;
+pub mod query;
+pub mod reconcile;
+pub mod rich_text;
+pub mod rotation;
+pub mod sandbox;
+pub mod system;
+pub mod transaction;
+pub mod trust;
 pub mod undo;
-
+pub mod unique_edges;
+pub mod watch;
+pub mod webhook;
+
+mod clock;
+mod conflicts;
+mod sync;
+#[cfg(feature = "sqlite")]
+mod workspace;
+
+pub use acl::{Role, ACL_FACET};
+pub use archive::ArchiveImport;
+pub use builder::{EngineBuilder, DEFAULT_UNDO_DEPTH};
+pub use bulk::BulkItemError;
+pub use cdc::{CdcBatch, CdcChange, CdcEvent};
+pub use devices::USER_DEVICE_FACET;
+pub use directory::ACTOR_PROFILE_FACET;
+pub use duplicate::DuplicateOptions;
 pub use error::EngineError;
-pub use overlay::{DriftRecord, OverlayManager, OverlayOpRecord, OverlayRecord, OverlaySource, OverlayStatus};
+pub use export::{ExportDocument, ExportFilter, ExportedEdge, ExportedEntity, TypedValue, EXPORT_FORMAT, EXPORT_VERSION};
+pub use facet_fields::FACET_FIELDS_FACET;
+pub use history::{EntityEvent, EntityHistoryEntry, FieldBlame, TimelineEntry, TimelineFilter};
+pub use idempotency::Idempotent;
+pub use import::{ImportOptions, ImportReport, ImportRowError, JsonImport, DEFAULT_IMPORT_CHUNK_SIZE};
+pub use ingest::{ClockSkew, FieldChange, IngestPreview, IngestReport, MissingDependency, OverlayDrift, PendingBundle, SetAside};
+pub use integrity::IntegrityReport;
+#[cfg(feature = "sqlite")]
+pub use integrity::{ConsistencyReport, RepairReport};
+pub use interceptor::{CompletedWrite, Interceptor, PendingWrite};
+pub use list::ListItem;
+pub use merge::{merge_text, MergeHunk, TextMerge};
+pub use modules::{ModuleMismatch, ModulePolicy, ModuleRegistry};
+pub use overlay::{DriftPolicy, DriftRecord, OverlayManager, OverlayOpRecord, OverlayRecord, OverlaySource, OverlayStatus};
+pub use presence::{EditorPresence, PresenceHint, PresenceTracker, DEFAULT_PRESENCE_TIMEOUT};
+pub use reconcile::{ReconcileRound, RECONCILE_FANOUT, RECONCILE_MAX_IDS};
+pub use rich_text::{TextDocument, TextMark, TEXT_DOCUMENT_KEY};
+pub use rotation::KEY_ROTATION_FACET;
+pub use sandbox::{SandboxDiff, SandboxHandle};
+pub use system::{is_system_field, ARCHIVED_FIELD, ICON_FIELD, SYSTEM_FIELD_PREFIX, TITLE_FIELD};
+pub use transaction::Transaction;
+pub use trust::TrustPolicy;
+pub use undo::{UndoScope, UndoSummary};
+pub use unique_edges::UNIQUE_EDGE_TYPE_FACET;
+pub use query::{EntityQuery, FieldCondition};
+pub use watch::{ConflictEvent, EntityChange, EntityView, EntityWatch, QueryUpdate, QueryWatch};
+/// The Automerge version rich-text updates are written with.
+pub use automerge;
+pub use webhook::{
+    WebhookFailure, WebhookFilter, WebhookReport, WebhookTransport, WEBHOOK_BATCH_SIZE, WEBHOOK_RETRY_INITIAL,
+    WEBHOOK_RETRY_MAX,
+};
 
-use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
 
 use openprod_core::{
     field_value::FieldValue,
     hlc::{Hlc, HlcClock},
-    identity::ActorIdentity,
+    identity::{ActorIdentity, KeyRotation},
     ids::*,
-    operations::{Bundle, BundleType, Operation, OperationPayload},
+    operations::{Bundle, BundleMeta, BundleType, Operation, OperationPayload},
+    sealed::WorkspaceKey,
+    sync_scope::SyncScope,
     vector_clock::VectorClock,
 };
 use openprod_storage::{
-    ConflictRecord, ConflictStatus, ConflictValue,
-    EdgeRecord, EntityRecord, FacetRecord, SqliteStorage, Storage,
+    Aggregate, Deletion, EdgeFilter, EdgeRecord, EngineStorage, EntityFields, EntityOrder,
+    EntityPage, EntityPageCursor, EntityRecord, FacetRecord, FieldMappingRecord, OpCursor,
 };
+#[cfg(feature = "sqlite")]
+use openprod_storage::SqliteStorage;
 
+use crate::presence::PresenceTable;
 use crate::undo::UndoManager;
-
-const DEFAULT_UNDO_DEPTH: usize = 100;
+use crate::watch::{ChangeSet, EntityWatcher, QueryWatcher};
 
 #[derive(Debug)]
 pub enum UndoResult {
@@ -38,23 +121,96 @@ pub struct UndoConflict {
     pub modified_by: ActorId,
 }
 
-pub struct Engine {
+/// One page of `Engine::bundles_since_page`.
+#[derive(Debug)]
+pub struct SyncPage {
+    pub bundles: Vec<(Bundle, Vec<Operation>)>,
+    /// Clock to ask with for the next page; None if this was the last one.
+    pub next: Option<VectorClock>,
+}
+
+/// An edge property `Engine::revert_bundle` left alone because it was
+/// written after the reverted bundle.
+#[derive(Debug)]
+pub struct SkippedEdgeProperty {
+    pub edge_id: EdgeId,
+    pub property_key: String,
+    pub modified_by: ActorId,
+}
+
+/// Outcome of `Engine::revert_bundle`.
+#[derive(Debug)]
+pub struct RevertResult {
+    /// The compensating bundle, or None if nothing was left to revert.
+    pub bundle_id: Option<BundleId>,
+    /// Fields left untouched because they were written after the reverted bundle.
+    pub skipped: Vec<UndoConflict>,
+    /// Edge properties left untouched for the same reason.
+    pub skipped_edge_properties: Vec<SkippedEdgeProperty>,
+}
+
+/// Backend `Engine` uses when none is named: SQLite, or the in-memory store
+/// in builds without the `sqlite` feature (e.g. wasm32).
+#[cfg(feature = "sqlite")]
+pub type DefaultStorage = SqliteStorage;
+#[cfg(not(feature = "sqlite"))]
+pub type DefaultStorage = openprod_storage::MemoryStorage;
+
+pub struct Engine<S = DefaultStorage> {
     identity: ActorIdentity,
     clock: HlcClock,
-    storage: SqliteStorage,
+    storage: S,
     undo_manager: UndoManager,
     overlay_manager: OverlayManager,
+    /// Change-notification hook: receivers of `subscribe_bundles`.
+    bundle_listeners: Vec<Sender<BundleId>>,
+    /// Open `watch_entity` subscriptions.
+    entity_watchers: Vec<EntityWatcher>,
+    /// Open `watch_query` subscriptions.
+    query_watchers: Vec<QueryWatcher>,
+    /// What writes touched since watchers were last notified.
+    changes: ChangeSet,
+    /// Open `watch_conflicts` subscriptions.
+    conflict_watchers: Vec<Sender<ConflictEvent>>,
+    /// Conflict events from the current ingest, sent once it commits.
+    conflict_events: Vec<ConflictEvent>,
+    workspace_key: Option<WorkspaceKey>,
+    trust_policy: TrustPolicy,
+    modules: ModuleRegistry,
+    interceptors: Vec<Box<dyn Interceptor>>,
+    group_user_devices: bool,
+    verify_signatures: bool,
+    verify_on_append: bool,
+    scan_drift_on_ingest: bool,
+    derive_op_ids: bool,
+    sync_scope: SyncScope,
+    /// Merged into the meta of every local bundle.
+    default_meta: Option<BundleMeta>,
+    /// Meta for writes inside `with_bundle_meta`.
+    scoped_meta: Option<BundleMeta>,
+    /// Meta stamped on every local bundle while a `begin_session` session is open.
+    session_meta: Option<BundleMeta>,
+    /// Sends `dispatch_webhooks` requests; webhooks stay queued without one.
+    webhook_transport: Option<Box<dyn WebhookTransport>>,
+    /// A bundle was stored since webhooks were last dispatched.
+    webhooks_due: bool,
+    /// Who is editing what, from `announce_editing` here and on peers.
+    presence: PresenceTable,
+    /// Receivers of `subscribe_presence`.
+    presence_listeners: Vec<Sender<PresenceHint>>,
+    /// How far physical time was behind the restored clock when opened.
+    clock_regression: Option<Duration>,
+    /// Valid key rotations, verified once and kept until a write that may
+    /// change them (or a rollback or restore).
+    key_rotations: OnceLock<Vec<KeyRotation>>,
 }
 
-impl Engine {
-    pub fn new(identity: ActorIdentity, storage: SqliteStorage) -> Self {
-        Self {
-            identity,
-            clock: HlcClock::new(),
-            storage,
-            undo_manager: UndoManager::new(DEFAULT_UNDO_DEPTH),
-            overlay_manager: OverlayManager::new(),
-        }
+impl<S: EngineStorage> Engine<S> {
+    /// Create an engine over existing storage, restoring the active overlay (if any)
+    /// so a restart doesn't silently drop back to canonical.
+    /// Use `EngineBuilder` to configure anything beyond the defaults.
+    pub fn new(identity: ActorIdentity, storage: S) -> Result<Self, EngineError> {
+        EngineBuilder::new().build(identity, storage)
     }
 
     pub fn actor_id(&self) -> ActorId {
@@ -65,18 +221,202 @@ impl Engine {
         &self.identity
     }
 
-    pub fn storage(&self) -> &SqliteStorage {
+    pub fn storage(&self) -> &S {
         &self.storage
     }
 
-    pub fn storage_mut(&mut self) -> &mut SqliteStorage {
+    pub fn storage_mut(&mut self) -> &mut S {
         &mut self.storage
     }
 
-    /// Execute a batch SQL statement on the underlying connection, mapping errors.
-    fn exec_batch(&self, sql: &str) -> Result<(), EngineError> {
-        self.storage.conn().execute_batch(sql)
-            .map_err(|e| EngineError::Storage(openprod_storage::StorageError::Sqlite(e)))
+    /// Change-notification hook: the returned receiver gets the id of every
+    /// canonical bundle created locally or newly ingested from a peer. Dropped
+    /// receivers are pruned on the next notification.
+    pub fn subscribe_bundles(&mut self) -> Receiver<BundleId> {
+        let (sender, receiver) = mpsc::channel();
+        self.bundle_listeners.push(sender);
+        receiver
+    }
+
+    fn notify_bundle(&mut self, bundle_id: BundleId) {
+        self.bundle_listeners.retain(|listener| listener.send(bundle_id).is_ok());
+        self.webhooks_due = true;
+    }
+
+    /// Keep a detail view live: returns the entity as it reads now (through
+    /// the active overlay), and a receiver that gets every later change to its
+    /// fields, facets, edges or deletion state, whether from local writes,
+    /// ingest, or switching overlays. Dropped receivers are pruned on the next
+    /// change.
+    pub fn watch_entity(&mut self, entity_id: EntityId) -> Result<EntityWatch, EngineError> {
+        let view = self
+            .entity_view(entity_id)?
+            .ok_or_else(|| EngineError::EntityNotFound(entity_id.to_string()))?;
+        let (sender, updates) = mpsc::channel();
+        self.entity_watchers.push(EntityWatcher { last: view.clone(), sender });
+        Ok(EntityWatch { view, updates })
+    }
+
+    /// Get a `ConflictEvent` for every conflict an ingest opens, reopens or
+    /// extends, e.g. to refresh a conflict badge (see `unseen_conflict_count`).
+    /// Dropped receivers are pruned on the next event.
+    pub fn watch_conflicts(&mut self) -> Receiver<ConflictEvent> {
+        let (sender, events) = mpsc::channel();
+        self.conflict_watchers.push(sender);
+        events
+    }
+
+    /// The entity as `watch_entity` reports it (None if it doesn't exist).
+    pub fn entity_view(&self, entity_id: EntityId) -> Result<Option<EntityView>, EngineError> {
+        let Some(entity) = self.storage.get_entity(entity_id)? else {
+            return Ok(None);
+        };
+        let facets = self.storage.get_facets(entity_id)?;
+        let mut edges = self.storage.get_edges_from(entity_id, &EdgeFilter::live())?;
+        edges.extend(self.storage.get_edges_to(entity_id, &EdgeFilter::live())?);
+        Ok(Some(EntityView {
+            entity_id,
+            deleted: entity.deleted,
+            fields: self.get_fields(entity_id)?.into_iter().collect(),
+            facets: facets.into_iter().filter(|f| !f.detached).map(|f| f.facet_type).collect(),
+            edges: edges.into_iter().map(|e| (e.edge_id, e)).collect(),
+        }))
+    }
+
+    /// Keep a list view live: returns the entities matching `query` now, and
+    /// a receiver that gets the entities added to, removed from, or changed
+    /// within the results after each write, ingest or overlay switch that
+    /// affects them. Only entities a write touched are re-checked, and only
+    /// when it involved the query's facet or condition fields. Dropped
+    /// receivers are pruned on the next update.
+    pub fn watch_query(&mut self, query: EntityQuery) -> Result<QueryWatch, EngineError> {
+        let results = self.query_entities(&query)?;
+        let (sender, updates) = mpsc::channel();
+        self.query_watchers.push(QueryWatcher { query, results: results.iter().copied().collect(), sender });
+        Ok(QueryWatch { results, updates })
+    }
+
+    /// Entities matching `query` now, in id order.
+    pub fn query_entities(&self, query: &EntityQuery) -> Result<Vec<EntityId>, EngineError> {
+        let mut candidates = self.storage.get_entities_by_facet(query.facet_type())?;
+        candidates.sort();
+        candidates.dedup();
+        let mut results = Vec::new();
+        for entity_id in candidates {
+            if self.matches_query(query, entity_id)? {
+                results.push(entity_id);
+            }
+        }
+        Ok(results)
+    }
+
+    fn matches_query(&self, query: &EntityQuery, entity_id: EntityId) -> Result<bool, EngineError> {
+        if self.storage.get_entity(entity_id)?.is_none_or(|e| e.deleted) {
+            return Ok(false);
+        }
+        let facets = self.storage.get_facets(entity_id)?;
+        if !facets.iter().any(|f| f.facet_type == query.facet_type() && !f.detached) {
+            return Ok(false);
+        }
+        for condition in query.conditions() {
+            if !condition.matches(self.get_field(entity_id, condition.field_key())?.as_ref()) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Track what `payloads` touch for the next `notify_watchers`.
+    fn record_changes<'a>(&mut self, payloads: impl IntoIterator<Item = &'a OperationPayload>) {
+        if !self.query_watchers.is_empty() {
+            for payload in payloads {
+                self.changes.record(payload);
+            }
+        }
+    }
+
+    /// Send watchers what changed since they were last notified.
+    fn notify_watchers(&mut self) {
+        self.notify_entity_watchers();
+        for event in std::mem::take(&mut self.conflict_events) {
+            self.conflict_watchers.retain(|watcher| watcher.send(event.clone()).is_ok());
+        }
+        let changes = std::mem::take(&mut self.changes);
+        if self.query_watchers.is_empty() {
+            return;
+        }
+        let mut watchers = std::mem::take(&mut self.query_watchers);
+        watchers.retain_mut(|watcher| {
+            // On a read error the watcher keeps its last results and catches up next time
+            let Ok(update) = self.query_update(watcher, &changes) else {
+                return true;
+            };
+            update.is_empty() || watcher.sender.send(update).is_ok()
+        });
+        self.query_watchers = watchers;
+    }
+
+    /// Diff a live query against its last results, updating them.
+    fn query_update(&self, watcher: &mut QueryWatcher, changes: &ChangeSet) -> Result<QueryUpdate, EngineError> {
+        let mut update = QueryUpdate::default();
+        if changes.is_all() {
+            let results: BTreeSet<EntityId> = self.query_entities(&watcher.query)?.into_iter().collect();
+            update.added = results.difference(&watcher.results).copied().collect();
+            update.removed = watcher.results.difference(&results).copied().collect();
+            update.changed = results.intersection(&watcher.results).filter(|id| changes.entities.contains(id)).copied().collect();
+            watcher.results = results;
+            return Ok(update);
+        }
+
+        let recheck = changes.affects(&watcher.query);
+        for &entity_id in &changes.entities {
+            let was = watcher.results.contains(&entity_id);
+            let is = if recheck { self.matches_query(&watcher.query, entity_id)? } else { was };
+            match (was, is) {
+                (false, true) => update.added.push(entity_id),
+                (true, false) => update.removed.push(entity_id),
+                (true, true) => update.changed.push(entity_id),
+                (false, false) => {}
+            }
+        }
+        for entity_id in &update.added {
+            watcher.results.insert(*entity_id);
+        }
+        for entity_id in &update.removed {
+            watcher.results.remove(entity_id);
+        }
+        Ok(update)
+    }
+
+    /// Send each entity watcher what changed since its last view. Edge ops
+    /// don't name their endpoints, so every watched entity is re-read rather
+    /// than only those a write touched; watchers are few (one per open detail view).
+    fn notify_entity_watchers(&mut self) {
+        if self.entity_watchers.is_empty() {
+            return;
+        }
+        let mut watchers = std::mem::take(&mut self.entity_watchers);
+        watchers.retain_mut(|watcher| {
+            // An unreadable or vanished entity (e.g. after a restore) keeps its last view
+            let Ok(Some(view)) = self.entity_view(watcher.last.entity_id) else {
+                return true;
+            };
+            let changes = watcher.last.changes_to(&view);
+            watcher.last = view;
+            changes.into_iter().all(|change| watcher.sender.send(change).is_ok())
+        });
+        self.entity_watchers = watchers;
+    }
+
+    /// Run `interceptor` around every subsequent local write, after any
+    /// registered before it.
+    pub fn add_interceptor(&mut self, interceptor: impl Interceptor + 'static) {
+        self.interceptors.push(Box::new(interceptor));
+    }
+
+    /// Remove every registered interceptor.
+    pub fn clear_interceptors(&mut self) {
+        self.interceptors.clear();
     }
 
     /// Core internal method for executing a bundle of operations.
@@ -89,40 +429,117 @@ impl Engine {
         payloads: Vec<OperationPayload>,
         is_undoable: bool,
     ) -> Result<(BundleId, Hlc), EngineError> {
+        self.execute_internal_with_meta(bundle_type, payloads, is_undoable, None)
+    }
+
+    /// `execute_internal` with optional structured meta attached to the bundle.
+    /// Meta is dropped for overlay writes (no bundle is created).
+    /// Registered interceptors run around the write.
+    pub(crate) fn execute_internal_with_meta(
+        &mut self,
+        bundle_type: BundleType,
+        payloads: Vec<OperationPayload>,
+        is_undoable: bool,
+        meta: Option<BundleMeta>,
+    ) -> Result<(BundleId, Hlc), EngineError> {
+        let mut meta = meta.or_else(|| self.scoped_meta.clone());
+        for defaults in [&self.session_meta, &self.default_meta].into_iter().flatten() {
+            meta = Some(match meta {
+                Some(meta) => meta.or_defaults(defaults),
+                None => defaults.clone(),
+            });
+        }
+        let result = if self.interceptors.is_empty() {
+            self.write_internal(bundle_type, payloads, is_undoable, meta)
+        } else {
+            // Hooks can't reach the engine, so nothing registers while they're out
+            let mut interceptors = std::mem::take(&mut self.interceptors);
+            let result = self.write_intercepted(&mut interceptors, bundle_type, payloads, is_undoable, meta);
+            self.interceptors = interceptors;
+            result
+        };
+        if result.is_ok() {
+            self.notify_watchers();
+        }
+        result
+    }
+
+    fn write_intercepted(
+        &mut self,
+        interceptors: &mut [Box<dyn Interceptor>],
+        bundle_type: BundleType,
+        payloads: Vec<OperationPayload>,
+        is_undoable: bool,
+        meta: Option<BundleMeta>,
+    ) -> Result<(BundleId, Hlc), EngineError> {
+        let overlay_id = self.overlay_manager.active_overlay_id();
+        let mut write = PendingWrite { actor_id: self.actor_id(), bundle_type, payloads, meta, overlay_id };
+        for interceptor in interceptors.iter_mut() {
+            interceptor.before_execute(&mut write)?;
+        }
+
+        let (bundle_id, hlc) = self.write_internal(bundle_type, write.payloads.clone(), is_undoable, write.meta.clone())?;
+        let completed = CompletedWrite {
+            bundle_id,
+            hlc,
+            bundle_type,
+            payloads: write.payloads,
+            meta: if overlay_id.is_some() { None } else { write.meta },
+            overlay_id,
+        };
+        for interceptor in interceptors.iter_mut() {
+            interceptor.after_execute(&completed);
+        }
+        Ok((bundle_id, hlc))
+    }
+
+    /// Check permissions, then sign and store the bundle (or route it to the
+    /// active overlay). The body of `execute_internal_with_meta`, minus hooks.
+    fn write_internal(
+        &mut self,
+        bundle_type: BundleType,
+        payloads: Vec<OperationPayload>,
+        is_undoable: bool,
+        meta: Option<BundleMeta>,
+    ) -> Result<(BundleId, Hlc), EngineError> {
+        self.check_permission(self.actor_id(), None, payloads.iter())?;
+        self.record_changes(&payloads);
+
         // Check for active overlay — if present, route to overlay storage
         if let Some(overlay_id) = self.overlay_manager.active_overlay_id() {
             return self.execute_overlay(overlay_id, payloads);
         }
 
         let bundle_id = BundleId::new();
-        let hlc = self.clock.tick()?;
-        let module_versions = BTreeMap::new();
+        let hlc = self.tick()?;
+        let module_versions = self.modules.versions().clone();
 
-        // Capture pre-execution snapshot if undoable
+        // Capture pre-execution snapshot and scopes if undoable
         let snapshot = if is_undoable {
-            Some(self.undo_manager.capture_snapshot(&self.storage, &payloads)?)
+            Some((
+                self.undo_manager.capture_snapshot(&self.storage, &payloads)?,
+                self.undo_scopes(&payloads)?,
+            ))
         } else {
             None
         };
 
         // Create signed operations
         let mut operations = Vec::new();
-        for payload in &payloads {
-            let op = Operation::new_signed(
-                &self.identity,
-                hlc,
-                bundle_id,
-                module_versions.clone(),
-                payload.clone(),
-            )?;
+        for (position, payload) in (0u32..).zip(&payloads) {
+            let op = if self.derive_op_ids {
+                Operation::new_signed_derived(&self.identity, hlc, bundle_id, position, module_versions.clone(), payload.clone())?
+            } else {
+                Operation::new_signed(&self.identity, hlc, bundle_id, module_versions.clone(), payload.clone())?
+            };
             operations.push(op);
         }
 
         // Get current vector clock for causal tracking
         let creator_vc = Some(self.storage.get_vector_clock()?);
 
-        // Create and sign bundle
-        let bundle = Bundle::new_signed(
+        // Create and sign bundle (meta is not covered by the signature)
+        let mut bundle = Bundle::new_signed(
             bundle_id,
             &self.identity,
             hlc,
@@ -130,14 +547,16 @@ impl Engine {
             &operations,
             creator_vc,
         )?;
+        bundle.meta = meta.map(|m| m.to_msgpack()).transpose()?;
 
         // Append to storage
-        self.storage.append_bundle(&bundle, &operations)?;
+        self.append_bundle(&bundle, &operations)?;
+        self.notify_bundle(bundle_id);
 
         // Push to undo stack if undoable
-        if let Some(snapshot) = snapshot {
-            self.undo_manager.push_undo(bundle_id, hlc, payloads.clone(), snapshot);
-            self.undo_manager.clear_redo();
+        if let Some((snapshot, scopes)) = snapshot {
+            self.undo_manager.clear_redo(&scopes);
+            self.undo_manager.push_undo(bundle_id, hlc, payloads.clone(), snapshot, scopes);
         }
 
         Ok((bundle_id, hlc))
@@ -150,13 +569,17 @@ impl Engine {
         overlay_id: OverlayId,
         payloads: Vec<OperationPayload>,
     ) -> Result<(BundleId, Hlc), EngineError> {
-        let hlc = self.clock.tick()?;
+        let hlc = self.tick()?;
         // Use a synthetic BundleId for tracking (not a real bundle)
         let synthetic_bundle_id = BundleId::new();
 
-        for payload in &payloads {
-            let op_id = OpId::new();
+        for (position, payload) in (0u32..).zip(&payloads) {
             let payload_bytes = payload.to_msgpack()?;
+            let op_id = if self.derive_op_ids {
+                OpId::derive(&self.actor_id(), &synthetic_bundle_id, position, &payload_bytes)
+            } else {
+                OpId::new()
+            };
             let entity_id = payload.entity_id();
             let op_type = payload.op_type_name();
 
@@ -239,6 +662,9 @@ impl Engine {
         facet_type: &str,
         fields: Vec<(&str, FieldValue)>,
     ) -> Result<(EntityId, BundleId), EngineError> {
+        for (key, _) in &fields {
+            system::check_user_field(key)?;
+        }
         let entity_id = EntityId::new();
         let mut payloads = vec![OperationPayload::CreateEntity {
             entity_id,
@@ -255,13 +681,15 @@ impl Engine {
         Ok((entity_id, bundle_id))
     }
 
-    /// Set a field value on an entity.
+    /// Set a field value on an entity. System fields (`sys:` keys) are
+    /// refused; see `set_system_field`.
     pub fn set_field(
         &mut self,
         entity_id: EntityId,
         field_key: &str,
         value: FieldValue,
     ) -> Result<BundleId, EngineError> {
+        system::check_user_field(field_key)?;
         self.require_live_entity(entity_id)?;
         let payloads = vec![OperationPayload::SetField {
             entity_id,
@@ -272,12 +700,13 @@ impl Engine {
         Ok(bundle_id)
     }
 
-    /// Clear a field on an entity.
+    /// Clear a field on an entity. System fields are refused, as in `set_field`.
     pub fn clear_field(
         &mut self,
         entity_id: EntityId,
         field_key: &str,
     ) -> Result<BundleId, EngineError> {
+        system::check_user_field(field_key)?;
         self.require_live_entity(entity_id)?;
         let payloads = vec![OperationPayload::ClearField {
             entity_id,
@@ -294,14 +723,9 @@ impl Engine {
     ) -> Result<BundleId, EngineError> {
         self.require_live_entity(entity_id)?;
         // Compute cascade edges
-        let edges_from = self.storage.get_edges_from(entity_id)?;
-        let edges_to = self.storage.get_edges_to(entity_id)?;
-        let cascade_edges: Vec<EdgeId> = edges_from
-            .iter()
-            .chain(edges_to.iter())
-            .filter(|e| !e.deleted)
-            .map(|e| e.edge_id)
-            .collect();
+        let edges_from = self.storage.get_edges_from(entity_id, &EdgeFilter::live())?;
+        let edges_to = self.storage.get_edges_to(entity_id, &EdgeFilter::live())?;
+        let cascade_edges: Vec<EdgeId> = edges_from.iter().chain(edges_to.iter()).map(|e| e.edge_id).collect();
 
         let payloads = vec![OperationPayload::DeleteEntity {
             entity_id,
@@ -311,6 +735,188 @@ impl Engine {
         Ok(bundle_id)
     }
 
+    /// Delete many entities in one bundle (one undo entry), cascading to
+    /// their connected edges. Every id is checked first; if any is missing
+    /// or already deleted, nothing is written and the error lists each
+    /// rejected item. Repeated ids are deleted once.
+    pub fn delete_entities(
+        &mut self,
+        entity_ids: &[EntityId],
+    ) -> Result<BundleId, EngineError> {
+        if entity_ids.is_empty() {
+            return Err(EngineError::Rejected("bulk delete has no entities".into()));
+        }
+        let errors: Vec<BulkItemError> = entity_ids
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &entity_id)| {
+                self.require_live_entity(entity_id).err().map(|error| BulkItemError { index, error })
+            })
+            .collect();
+        if !errors.is_empty() {
+            return Err(EngineError::BulkValidation(errors));
+        }
+
+        let mut seen_entities = BTreeSet::new();
+        let mut seen_edges = BTreeSet::new();
+        let mut payloads = Vec::new();
+        for &entity_id in entity_ids {
+            if !seen_entities.insert(entity_id) {
+                continue;
+            }
+            let edges_from = self.storage.get_edges_from(entity_id, &EdgeFilter::live())?;
+            let edges_to = self.storage.get_edges_to(entity_id, &EdgeFilter::live())?;
+            let cascade_edges: Vec<EdgeId> = edges_from
+                .iter()
+                .chain(edges_to.iter())
+                .map(|e| e.edge_id)
+                .filter(|edge_id| seen_edges.insert(*edge_id))
+                .collect();
+            payloads.push(OperationPayload::DeleteEntity { entity_id, cascade_edges });
+        }
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
+        Ok(bundle_id)
+    }
+
+    /// Delete every live entity with `facet_type` attached, in one bundle.
+    /// Returns `None` when there is nothing to delete.
+    pub fn delete_by_facet(
+        &mut self,
+        facet_type: &str,
+    ) -> Result<Option<BundleId>, EngineError> {
+        let mut entity_ids = Vec::new();
+        for entity_id in self.storage.get_entities_by_facet(facet_type)? {
+            if self.storage.get_entity(entity_id)?.is_some_and(|e| !e.deleted) {
+                entity_ids.push(entity_id);
+            }
+        }
+        if entity_ids.is_empty() {
+            return Ok(None);
+        }
+        self.delete_entities(&entity_ids).map(Some)
+    }
+
+    /// Set many fields, possibly across many entities, in one bundle (one
+    /// undo entry). Every target is checked first; if any entity is missing
+    /// or deleted, nothing is written and the error lists each rejected item.
+    pub fn set_field_bulk(
+        &mut self,
+        changes: Vec<(EntityId, &str, FieldValue)>,
+    ) -> Result<BundleId, EngineError> {
+        if changes.is_empty() {
+            return Err(EngineError::Rejected("bulk field update has no changes".into()));
+        }
+        let errors: Vec<BulkItemError> = changes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (entity_id, field_key, _))| {
+                system::check_user_field(field_key)
+                    .and_then(|()| self.require_live_entity(*entity_id))
+                    .err()
+                    .map(|error| BulkItemError { index, error })
+            })
+            .collect();
+        if !errors.is_empty() {
+            return Err(EngineError::BulkValidation(errors));
+        }
+
+        let payloads = changes
+            .into_iter()
+            .map(|(entity_id, field_key, value)| OperationPayload::SetField {
+                entity_id,
+                field_key: field_key.to_string(),
+                value,
+            })
+            .collect();
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
+        Ok(bundle_id)
+    }
+
+    /// Restore a deleted entity along with the edges its latest delete
+    /// cascaded to. Edges whose other end is still deleted stay deleted.
+    pub fn restore_entity(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Result<BundleId, EngineError> {
+        match self.storage.get_entity(entity_id)? {
+            None => return Err(EngineError::EntityNotFound(entity_id.to_string())),
+            Some(e) if !e.deleted => return Err(EngineError::EntityNotDeleted(entity_id.to_string())),
+            Some(_) => {}
+        }
+        let mut payloads = vec![OperationPayload::RestoreEntity { entity_id }];
+        for edge_id in self.cascaded_edges(entity_id)? {
+            let Some(edge) = self.storage.get_edge(edge_id)? else { continue };
+            let other = if edge.source_id == entity_id { edge.target_id } else { edge.source_id };
+            let other_live = other == entity_id || self.storage.get_entity(other)?.is_some_and(|e| !e.deleted);
+            if edge.deleted && other_live {
+                payloads.push(OperationPayload::RestoreEdge { edge_id });
+            }
+        }
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
+        Ok(bundle_id)
+    }
+
+    /// Copy an entity's facets and fields (and optionally its outgoing edges)
+    /// onto a new entity in a single bundle. Fields are read through the
+    /// active overlay. References back to the source, whether in fields, edge
+    /// properties, or as an edge's target, point at the copy instead; all
+    /// other references are copied unchanged.
+    pub fn duplicate_entity(
+        &mut self,
+        entity_id: EntityId,
+        options: &DuplicateOptions,
+    ) -> Result<(EntityId, BundleId), EngineError> {
+        self.require_live_entity(entity_id)?;
+        let copy_id = EntityId::new();
+        let remap = |value: FieldValue| match value {
+            FieldValue::EntityRef(id) if id == entity_id => FieldValue::EntityRef(copy_id),
+            other => other,
+        };
+
+        let mut payloads = vec![OperationPayload::CreateEntity { entity_id: copy_id, initial_table: None }];
+        for facet in self.storage.get_facets(entity_id)? {
+            if !facet.detached && options.copies_facet(&facet.facet_type) {
+                payloads.push(OperationPayload::AttachFacet { entity_id: copy_id, facet_type: facet.facet_type });
+            }
+        }
+        let mut fields = self.get_fields(entity_id)?;
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        for (field_key, value) in fields {
+            if options.copies_field(&field_key) {
+                payloads.push(OperationPayload::SetField { entity_id: copy_id, field_key, value: remap(value) });
+            }
+        }
+        if options.copies_outgoing_edges() {
+            for edge in self.storage.get_edges_from(entity_id, &EdgeFilter::live())? {
+                let mut properties = self.storage.get_edge_properties(edge.edge_id)?;
+                properties.sort_by(|a, b| a.0.cmp(&b.0));
+                payloads.push(OperationPayload::CreateEdge {
+                    edge_id: EdgeId::new(),
+                    edge_type: edge.edge_type,
+                    source_id: copy_id,
+                    target_id: if edge.target_id == entity_id { copy_id } else { edge.target_id },
+                    properties: properties.into_iter().map(|(key, value)| (key, remap(value))).collect(),
+                });
+            }
+        }
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
+        Ok((copy_id, bundle_id))
+    }
+
+    /// Edges cascaded by the latest `DeleteEntity` of `entity_id` in the oplog.
+    fn cascaded_edges(&self, entity_id: EntityId) -> Result<Vec<EdgeId>, EngineError> {
+        let latest_delete = self
+            .storage
+            .get_ops_by_entity(entity_id)?
+            .into_iter()
+            .filter(|op| matches!(op.payload, OperationPayload::DeleteEntity { .. }))
+            .max_by_key(|op| (op.hlc, op.op_id));
+        Ok(match latest_delete.map(|op| op.payload) {
+            Some(OperationPayload::DeleteEntity { cascade_edges, .. }) => cascade_edges,
+            _ => Vec::new(),
+        })
+    }
+
     /// Attach a facet to an entity.
     pub fn attach_facet(
         &mut self,
@@ -326,7 +932,9 @@ impl Engine {
         Ok(bundle_id)
     }
 
-    /// Detach a facet from an entity.
+    /// Detach a facet from an entity. Without `preserve_values`, the fields
+    /// declared for the facet (see `set_facet_fields`) are cleared in the same
+    /// bundle; fields of undeclared facets are left alone.
     pub fn detach_facet(
         &mut self,
         entity_id: EntityId,
@@ -334,37 +942,63 @@ impl Engine {
         preserve_values: bool,
     ) -> Result<BundleId, EngineError> {
         self.require_live_entity(entity_id)?;
-        let payloads = vec![OperationPayload::DetachFacet {
+        let mut payloads = vec![OperationPayload::DetachFacet {
             entity_id,
             facet_type: facet_type.to_string(),
             preserve_values,
         }];
+        if !preserve_values {
+            for (field_key, _) in self.get_facet_fields(entity_id, facet_type)? {
+                payloads.push(OperationPayload::ClearField { entity_id, field_key });
+            }
+        }
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
+        Ok(bundle_id)
+    }
+
+    /// Reattach a detached facet. With `restore_values`, the fields a
+    /// preserving detach kept (see `get_preserved_facet_values`) are written
+    /// back in the same bundle, skipping any field that has a value again.
+    pub fn restore_facet(
+        &mut self,
+        entity_id: EntityId,
+        facet_type: &str,
+        restore_values: bool,
+    ) -> Result<BundleId, EngineError> {
+        self.require_live_entity(entity_id)?;
+        let detached = self.storage.get_facets(entity_id)?.iter().any(|f| f.facet_type == facet_type && f.detached);
+        if !detached {
+            return Err(EngineError::Rejected(format!("facet {facet_type} is not detached from {entity_id}")));
+        }
+        let mut payloads = vec![OperationPayload::RestoreFacet { entity_id, facet_type: facet_type.to_string() }];
+        if restore_values {
+            for (field_key, value) in self.get_preserved_facet_values(entity_id, facet_type)?.unwrap_or_default() {
+                if self.get_field(entity_id, &field_key)?.is_none() {
+                    payloads.push(OperationPayload::SetField { entity_id, field_key, value });
+                }
+            }
+        }
         let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
         Ok(bundle_id)
     }
 
-    /// Create an edge between two entities.
+    /// Create an edge between two entities. Reuses the existing edge for a
+    /// unique edge type, as `create_edge_with_properties` does.
     pub fn create_edge(
         &mut self,
         edge_type: &str,
         source_id: EntityId,
         target_id: EntityId,
     ) -> Result<(EdgeId, BundleId), EngineError> {
-        self.require_live_entity(source_id)?;
-        self.require_live_entity(target_id)?;
-        let edge_id = EdgeId::new();
-        let payloads = vec![OperationPayload::CreateEdge {
-            edge_id,
-            edge_type: edge_type.to_string(),
-            source_id,
-            target_id,
-            properties: Vec::new(),
-        }];
-        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
-        Ok((edge_id, bundle_id))
+        self.create_edge_with_properties(edge_type, source_id, target_id, Vec::new())
     }
 
     /// Create an edge between two entities with initial properties.
+    ///
+    /// For a unique edge type (see `declare_unique_edge_type`) an existing edge
+    /// between the pair is reused instead: it is restored if deleted and the
+    /// properties are set on it. If that writes nothing, the bundle that
+    /// created the edge is returned.
     pub fn create_edge_with_properties(
         &mut self,
         edge_type: &str,
@@ -374,6 +1008,23 @@ impl Engine {
     ) -> Result<(EdgeId, BundleId), EngineError> {
         self.require_live_entity(source_id)?;
         self.require_live_entity(target_id)?;
+        if let Some(existing) = self.canonical_unique_edge(edge_type, source_id, target_id)? {
+            let edge_id = existing.edge_id;
+            let restore = existing.deleted.then_some(OperationPayload::RestoreEdge { edge_id });
+            let payloads: Vec<OperationPayload> = restore
+                .into_iter()
+                .chain(properties.into_iter().map(|(key, value)| OperationPayload::SetEdgeProperty {
+                    edge_id,
+                    property_key: key.to_string(),
+                    value,
+                }))
+                .collect();
+            if payloads.is_empty() {
+                return Ok((edge_id, existing.created_in_bundle));
+            }
+            let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
+            return Ok((edge_id, bundle_id));
+        }
         let edge_id = EdgeId::new();
         let payloads = vec![OperationPayload::CreateEdge {
             edge_id,
@@ -416,6 +1067,33 @@ impl Engine {
         Ok(bundle_id)
     }
 
+    /// Restore a deleted edge. Both ends must be live, and of the edges of a
+    /// unique type joining the pair only the one kept by merging (the
+    /// earliest created) can come back.
+    pub fn restore_edge(
+        &mut self,
+        edge_id: EdgeId,
+    ) -> Result<BundleId, EngineError> {
+        let edge = match self.storage.get_edge(edge_id)? {
+            None => return Err(EngineError::EdgeNotFound(edge_id.to_string())),
+            Some(e) if !e.deleted => return Err(EngineError::EdgeNotDeleted(edge_id.to_string())),
+            Some(e) => e,
+        };
+        self.require_live_entity(edge.source_id)?;
+        self.require_live_entity(edge.target_id)?;
+        if let Some(kept) = self.canonical_unique_edge(&edge.edge_type, edge.source_id, edge.target_id)?
+            && kept.edge_id != edge_id
+        {
+            return Err(EngineError::Rejected(format!(
+                "unique {} edges between {} and {} merge into {}; restore that one instead",
+                edge.edge_type, edge.source_id, edge.target_id, kept.edge_id
+            )));
+        }
+        let payloads = vec![OperationPayload::RestoreEdge { edge_id }];
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
+        Ok(bundle_id)
+    }
+
     /// Delete an edge.
     pub fn delete_edge(
         &mut self,
@@ -438,166 +1116,112 @@ impl Engine {
         Ok(bundle_id)
     }
 
-    // ========================================================================
-    // Undo / Redo
-    // ========================================================================
-
-    /// Undo the most recent undoable command.
-    /// Returns `Applied(bundle_id)` if undo was successful.
-    /// Returns `Skipped { conflicts }` if another actor modified the same fields (skip-and-advance).
-    /// Returns `Empty` if there's nothing to undo.
-    pub fn undo(&mut self) -> Result<UndoResult, EngineError> {
-        let entry = match self.undo_manager.pop_undo() {
-            Some(entry) => entry,
-            None => return Ok(UndoResult::Empty),
-        };
-
-        // Check for conflicts: for each field in the snapshot, see if another actor
-        // modified it after the original bundle was executed
-        let my_actor = self.actor_id();
-        let mut conflicts = Vec::new();
-
-        for field_snap in &entry.snapshot.field_states {
-            if let Some((actor, hlc)) = self.storage.get_field_metadata(
-                field_snap.entity_id,
-                &field_snap.field_key,
-            )?
-                && actor != my_actor && hlc > entry.bundle_hlc
-            {
-                conflicts.push(UndoConflict {
-                    entity_id: field_snap.entity_id,
-                    field_key: field_snap.field_key.clone(),
-                    modified_by: actor,
-                });
-            }
-        }
-
-        // Also check entity-level conflicts: if undoing a CreateEntity,
-        // check if any other actor wrote fields to it
-        for entity_snap in &entry.snapshot.entity_states {
-            // If entity didn't exist before (we're undoing a create), check if others wrote to it
-            if entity_snap.existed.is_none() {
-                let fields = self.storage.get_fields(entity_snap.entity_id)?;
-                for (field_key, _) in &fields {
-                    if let Some((actor, _)) = self.storage.get_field_metadata(
-                        entity_snap.entity_id,
-                        field_key,
-                    )?
-                        && actor != my_actor
-                    {
-                        conflicts.push(UndoConflict {
-                            entity_id: entity_snap.entity_id,
-                            field_key: field_key.clone(),
-                            modified_by: actor,
-                        });
-                    }
-                }
-            }
-        }
-
-        // If conflicts, skip and advance (entry is consumed)
-        if !conflicts.is_empty() {
-            return Ok(UndoResult::Skipped { conflicts });
-        }
+    /// `execute` with structured meta (e.g. a history label) attached to the bundle.
+    pub fn execute_with_meta(
+        &mut self,
+        bundle_type: BundleType,
+        payloads: Vec<OperationPayload>,
+        meta: BundleMeta,
+    ) -> Result<BundleId, EngineError> {
+        let is_undoable = matches!(bundle_type, BundleType::UserEdit);
+        let (bundle_id, _) =
+            self.execute_internal_with_meta(bundle_type, payloads, is_undoable, Some(meta))?;
+        Ok(bundle_id)
+    }
 
-        // Compute inverse operations
-        let mut inverse = self.undo_manager.compute_inverse(&entry);
-
-        // For CreateEntity undo -> DeleteEntity, compute fresh cascade_edges from storage
-        for payload in &mut inverse {
-            if let OperationPayload::DeleteEntity { entity_id, cascade_edges } = payload {
-                let edges_from = self.storage.get_edges_from(*entity_id)?;
-                let edges_to = self.storage.get_edges_to(*entity_id)?;
-                *cascade_edges = edges_from
-                    .iter()
-                    .chain(edges_to.iter())
-                    .filter(|e| !e.deleted)
-                    .map(|e| e.edge_id)
-                    .collect();
-            }
+    /// Run a multi-step edit as one bundle. The closure's commands are
+    /// buffered and checked as they are issued; if it returns `Ok` they are
+    /// written as a single undoable bundle, and if it fails nothing is
+    /// written. Steps run through `Transaction::savepoint` can fail on their
+    /// own without abandoning the rest:
+    ///
+    /// ```ignore
+    /// let (task, _) = engine.transaction(|tx| {
+    ///     let task = tx.create_entity_with_fields("Task", vec![("title", FieldValue::Text("Hang lights".into()))])?;
+    ///     let _ = tx.savepoint(|tx| tx.create_edge("assigned_to", task, maybe_gone));
+    ///     Ok(task)
+    /// })?;
+    /// ```
+    ///
+    /// The bundle id is `None` if the closure issued no commands.
+    pub fn transaction<T>(
+        &mut self,
+        f: impl FnOnce(&mut Transaction<'_, S>) -> Result<T, EngineError>,
+    ) -> Result<(T, Option<BundleId>), EngineError> {
+        let mut tx = Transaction::new(self);
+        let value = f(&mut tx)?;
+        let payloads = tx.into_payloads();
+        if payloads.is_empty() {
+            return Ok((value, None));
         }
-
-        // Execute inverse as non-undoable
-        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, inverse, false)?;
-
-        // Push original entry to redo stack
-        self.undo_manager.push_redo(entry);
-
-        Ok(UndoResult::Applied(bundle_id))
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
+        Ok((value, Some(bundle_id)))
     }
 
-    /// Redo the most recently undone command.
-    /// Returns `Applied(bundle_id)` if redo was successful.
-    /// Returns `Empty` if there's nothing to redo.
-    pub fn redo(&mut self) -> Result<UndoResult, EngineError> {
-        let entry = match self.undo_manager.pop_redo() {
-            Some(entry) => entry,
-            None => return Ok(UndoResult::Empty),
-        };
+    /// Run `f` with `meta` attached to every bundle it writes, so typed
+    /// commands can be labelled and tagged:
+    ///
+    /// ```ignore
+    /// engine.with_bundle_meta(BundleMeta::with_label("Bulk rename").tag("bulk-rename"), |engine| {
+    ///     engine.set_field_bulk(changes)
+    /// })?;
+    /// ```
+    ///
+    /// Explicit meta (`execute_with_meta`) still wins. Scopes nest; the
+    /// outer meta is back in effect once `f` returns.
+    pub fn with_bundle_meta<T>(
+        &mut self,
+        meta: BundleMeta,
+        f: impl FnOnce(&mut Self) -> Result<T, EngineError>,
+    ) -> Result<T, EngineError> {
+        let outer = self.scoped_meta.replace(meta);
+        let result = f(self);
+        self.scoped_meta = outer;
+        result
+    }
 
-        // Fix up payloads for current DB state (soft-deleted entities/edges
-        // need RestoreEntity/RestoreEdge instead of CreateEntity/CreateEdge)
-        let mut fixed_payloads = Vec::new();
-        for payload in &entry.payloads {
-            match payload {
-                OperationPayload::CreateEntity { entity_id, initial_table } => {
-                    // Check if entity exists and is soft-deleted
-                    if let Some(entity) = self.storage.get_entity(*entity_id)?
-                        && entity.deleted
-                    {
-                        // Entity exists but is soft-deleted — restore it
-                        fixed_payloads.push(OperationPayload::RestoreEntity {
-                            entity_id: *entity_id,
-                        });
-                        // Re-attach facet if there was an initial_table
-                        if let Some(facet_type) = initial_table {
-                            // Check if the facet is detached and needs reattaching
-                            let facets = self.storage.get_facets(*entity_id)?;
-                            let facet_exists = facets.iter().any(|f| f.facet_type == *facet_type);
-                            if !facet_exists {
-                                fixed_payloads.push(OperationPayload::AttachFacet {
-                                    entity_id: *entity_id,
-                                    facet_type: facet_type.clone(),
-                                });
-                            }
-                        }
-                        continue;
-                    }
-                    // Entity doesn't exist or isn't deleted — use original payload
-                    fixed_payloads.push(payload.clone());
-                }
+    /// Issue the next typed command under an idempotency key, so retrying it
+    /// (e.g. after the app crashed before seeing the result) returns the
+    /// original bundle instead of writing again:
+    ///
+    /// ```ignore
+    /// let (cue, _) = engine.idempotent(request_id).create_entity_with_fields("Cue", fields)?;
+    /// ```
+    ///
+    /// The key is stored in the bundle's meta, in the same write as the
+    /// bundle. Keys are per actor and never expire.
+    pub fn idempotent(&mut self, key: impl Into<String>) -> Idempotent<'_, S> {
+        Idempotent::new(self, key.into())
+    }
 
-                OperationPayload::CreateEdge { edge_id, .. } => {
-                    // Check if edge exists and is soft-deleted
-                    if let Some(edge) = self.storage.get_edge(*edge_id)?
-                        && edge.deleted
-                    {
-                        // Edge exists but is soft-deleted — restore it
-                        fixed_payloads.push(OperationPayload::RestoreEdge {
-                            edge_id: *edge_id,
-                        });
-                        continue;
-                    }
-                    // Edge doesn't exist or isn't deleted — use original payload
-                    fixed_payloads.push(payload.clone());
-                }
+    // ========================================================================
+    // Editing Sessions
+    // ========================================================================
 
-                _ => {
-                    fixed_payloads.push(payload.clone());
-                }
-            }
+    /// Open an editing session: until `end_session`, every local bundle is
+    /// stamped with the returned id in its meta (and `label`, unless it has
+    /// its own), so the session's edits can be listed with
+    /// `get_session_bundles` or reverted together with `revert_session`.
+    /// Sessions don't nest and live only as long as this engine.
+    pub fn begin_session(&mut self, label: impl Into<String>) -> Result<SessionId, EngineError> {
+        if let Some(open) = self.active_session() {
+            return Err(EngineError::Rejected(format!("session {open} is already open")));
         }
+        let session_id = SessionId::new();
+        self.session_meta = Some(BundleMeta {
+            session: Some(session_id),
+            ..BundleMeta::with_label(label)
+        });
+        Ok(session_id)
+    }
 
-        // Capture snapshot for the fixed payloads (so this redo can be undone)
-        let snapshot = self.undo_manager.capture_snapshot(&self.storage, &fixed_payloads)?;
-
-        // Execute the fixed payloads (not self-undoable — we manage stack manually)
-        let (bundle_id, hlc) = self.execute_internal(BundleType::UserEdit, fixed_payloads.clone(), false)?;
-
-        // Push new undo entry so this redo can be undone
-        self.undo_manager.push_undo(bundle_id, hlc, fixed_payloads, snapshot);
+    /// Close the open session, returning its id (None if none was open).
+    pub fn end_session(&mut self) -> Option<SessionId> {
+        self.session_meta.take().and_then(|meta| meta.session)
+    }
 
-        Ok(UndoResult::Applied(bundle_id))
+    pub fn active_session(&self) -> Option<SessionId> {
+        self.session_meta.as_ref().and_then(|meta| meta.session)
     }
 
     // ========================================================================
@@ -661,18 +1285,162 @@ impl Engine {
         Ok(self.storage.get_entities_by_facet(facet_type)?)
     }
 
-    pub fn get_edges_from(&self, entity_id: EntityId) -> Result<Vec<EdgeRecord>, EngineError> {
-        Ok(self.storage.get_edges_from(entity_id)?)
+    /// Every live entity with `facet_type`, in id order, with its values for
+    /// `field_keys` (in that order, missing ones left out), e.g. the columns
+    /// of a list view. Read in one storage query plus one pass over the
+    /// active overlay, whose edits win as in `get_fields`.
+    pub fn get_entities_with_fields(
+        &self,
+        facet_type: &str,
+        field_keys: &[&str],
+    ) -> Result<Vec<EntityFields>, EngineError> {
+        let mut entities = self.storage.get_entities_with_fields(facet_type, field_keys)?;
+        let Some(overlay_id) = self.overlay_manager.active_overlay_id() else {
+            return Ok(entities);
+        };
+        for (_rowid, _op_id, _hlc, payload_bytes, eid, _op_type, _canon, _drifted, _field_key) in
+            &self.storage.get_overlay_ops(overlay_id)?
+        {
+            let Some(entity_id) = eid.as_ref().and_then(|b| <[u8; 16]>::try_from(b.as_slice()).ok().map(EntityId::from_bytes))
+            else {
+                continue;
+            };
+            let Ok(index) = entities.binary_search_by_key(&entity_id, |(id, _)| *id) else { continue };
+            let fields = &mut entities[index].1;
+            match OperationPayload::from_msgpack(payload_bytes) {
+                Ok(OperationPayload::SetField { field_key, value, .. }) if field_keys.contains(&field_key.as_str()) => {
+                    fields.retain(|(k, _)| *k != field_key);
+                    fields.push((field_key, value));
+                }
+                Ok(OperationPayload::ClearField { field_key, .. }) => fields.retain(|(k, _)| *k != field_key),
+                _ => {}
+            }
+        }
+        for (_, fields) in &mut entities {
+            fields.sort_by_key(|(key, _)| field_keys.iter().position(|k| k == key));
+        }
+        Ok(entities)
+    }
+
+    /// A page of up to `limit` live entities with `facet_type`, in `order`,
+    /// for listing large facets incrementally, e.g. in a UI table. Start with
+    /// `after: None` and pass each page's `next` to get the following one.
+    /// Sorted by storage over canonical values; an active overlay's edits are
+    /// not included.
+    pub fn get_entities_page(
+        &self,
+        facet_type: &str,
+        order: &EntityOrder,
+        after: Option<&EntityPageCursor>,
+        limit: usize,
+    ) -> Result<EntityPage, EngineError> {
+        Ok(self.storage.get_entities_page(facet_type, order, after, limit)?)
     }
 
-    pub fn get_edges_to(&self, entity_id: EntityId) -> Result<Vec<EdgeRecord>, EngineError> {
-        Ok(self.storage.get_edges_to(entity_id)?)
+    /// Live entities per attached facet, by facet type, counted by storage
+    /// without loading the entities.
+    pub fn count_entities_by_facet(&self) -> Result<Vec<(String, u64)>, EngineError> {
+        Ok(self.storage.count_entities_by_facet()?)
+    }
+
+    /// Sum, average, minimum or maximum of the numeric values of `field_key`
+    /// across live entities with `facet_type`, e.g. a budget total for a
+    /// dashboard. Non-numeric values are skipped and `None` means there were
+    /// none. Computed by storage over canonical values; an active overlay's
+    /// edits are not included.
+    pub fn aggregate_field(
+        &self,
+        facet_type: &str,
+        field_key: &str,
+        aggregate: Aggregate,
+    ) -> Result<Option<FieldValue>, EngineError> {
+        Ok(self.storage.aggregate_field(facet_type, field_key, aggregate)?)
+    }
+
+    /// Field mappings confirmed (by any peer) out of `source_table`.
+    pub fn get_field_mappings(&self, source_table: TableId) -> Result<Vec<FieldMappingRecord>, EngineError> {
+        Ok(self.storage.get_field_mappings(source_table)?)
+    }
+
+    /// The entity's fields declared for `facet_type`, read through the active
+    /// overlay and sorted by key. Empty for an undeclared facet.
+    pub fn get_facet_fields(
+        &self,
+        entity_id: EntityId,
+        facet_type: &str,
+    ) -> Result<Vec<(String, FieldValue)>, EngineError> {
+        let owned = self.storage.get_facet_fields(facet_type)?;
+        let mut fields = self.get_fields(entity_id)?;
+        fields.retain(|(key, _)| owned.contains(key));
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(fields)
+    }
+
+    /// Field values kept by a preserving detach of `facet_type`, sorted by
+    /// key and narrowed to the facet's declared fields, if it has any; `None`
+    /// unless the facet is detached with a snapshot.
+    pub fn get_preserved_facet_values(
+        &self,
+        entity_id: EntityId,
+        facet_type: &str,
+    ) -> Result<Option<Vec<(String, FieldValue)>>, EngineError> {
+        let Some(mut values) = self.storage.get_preserved_values(entity_id, facet_type)? else {
+            return Ok(None);
+        };
+        let owned = self.storage.get_facet_fields(facet_type)?;
+        if !owned.is_empty() {
+            values.retain(|(key, _)| owned.contains(key));
+        }
+        Ok(Some(values))
+    }
+
+    /// Edges out of `entity_id`; `EdgeFilter::all()` includes deleted ones.
+    /// With a `SyncScope`, edges to entities not synced yet are left out
+    /// here and in the other edge listings (`get_edge` still finds them).
+    pub fn get_edges_from(&self, entity_id: EntityId, filter: &EdgeFilter) -> Result<Vec<EdgeRecord>, EngineError> {
+        self.without_deferred_edges(self.storage.get_edges_from(entity_id, filter)?)
+    }
+
+    /// Edges into `entity_id`; `EdgeFilter::all()` includes deleted ones.
+    pub fn get_edges_to(&self, entity_id: EntityId, filter: &EdgeFilter) -> Result<Vec<EdgeRecord>, EngineError> {
+        self.without_deferred_edges(self.storage.get_edges_to(entity_id, filter)?)
+    }
+
+    /// Edges from `source_id` to `target_id`, in that direction only.
+    pub fn get_edges_between(
+        &self,
+        source_id: EntityId,
+        target_id: EntityId,
+        filter: &EdgeFilter,
+    ) -> Result<Vec<EdgeRecord>, EngineError> {
+        self.without_deferred_edges(self.storage.get_edges_between(source_id, target_id, filter)?)
+    }
+
+    /// Live edges of `edge_type` across the workspace.
+    pub fn get_edges_by_type(&self, edge_type: &str) -> Result<Vec<EdgeRecord>, EngineError> {
+        self.without_deferred_edges(self.storage.get_edges_by_type(edge_type)?)
     }
 
     pub fn get_edge(&self, edge_id: EdgeId) -> Result<Option<EdgeRecord>, EngineError> {
         Ok(self.storage.get_edge(edge_id)?)
     }
 
+    /// Deleted entities with who deleted them and when, most recent first,
+    /// for a "recently deleted" view. `facet_type` keeps only entities with
+    /// that facet; `since` only deletions after that HLC.
+    pub fn list_deleted_entities(
+        &self,
+        facet_type: Option<&str>,
+        since: Option<Hlc>,
+    ) -> Result<Vec<(EntityRecord, Deletion)>, EngineError> {
+        Ok(self.storage.get_deleted_entities(facet_type, since)?)
+    }
+
+    /// Deleted edges with who deleted them and when, most recent first.
+    pub fn list_deleted_edges(&self) -> Result<Vec<(EdgeRecord, Deletion)>, EngineError> {
+        Ok(self.storage.get_deleted_edges()?)
+    }
+
     pub fn get_edge_properties(
         &self,
         edge_id: EdgeId,
@@ -704,6 +1472,17 @@ impl Engine {
         Ok(self.storage.get_ops_canonical()?)
     }
 
+    /// One page of the oplog in canonical order; see `Storage::get_ops_page`.
+    pub fn get_ops_page(&self, after: Option<(Hlc, OpId)>, limit: usize) -> Result<Vec<Operation>, EngineError> {
+        Ok(self.storage.get_ops_page(after, limit)?)
+    }
+
+    /// The whole oplog in canonical order, fetched `page_size` ops at a time
+    /// instead of all at once like `get_ops_canonical`.
+    pub fn ops_canonical_paged(&self, page_size: usize) -> impl Iterator<Item = Result<Operation, EngineError>> + '_ {
+        OpCursor::new(&self.storage, page_size).map(|op| op.map_err(EngineError::from))
+    }
+
     pub fn get_ops_by_bundle(&self, bundle_id: BundleId) -> Result<Vec<Operation>, EngineError> {
         Ok(self.storage.get_ops_by_bundle(bundle_id)?)
     }
@@ -720,6 +1499,11 @@ impl Engine {
         Ok(self.storage.op_count()?)
     }
 
+    /// Refresh the storage's query planner statistics, e.g. after an initial sync.
+    pub fn analyze(&mut self) -> Result<(), EngineError> {
+        Ok(self.storage.analyze()?)
+    }
+
     pub fn get_field_metadata(
         &self,
         entity_id: EntityId,
@@ -727,619 +1511,4 @@ impl Engine {
     ) -> Result<Option<(ActorId, Hlc)>, EngineError> {
         Ok(self.storage.get_field_metadata(entity_id, field_key)?)
     }
-
-    // ========================================================================
-    // Ingest (Sync / Testing)
-    // ========================================================================
-
-    /// Ingest a foreign bundle and its operations into this engine's storage.
-    /// Used for sync and testing — does NOT push to undo stack.
-    /// Detects field-level conflicts via vector clock comparison.
-    /// Returns any detected conflicts.
-    pub fn ingest_bundle(
-        &mut self,
-        bundle: &Bundle,
-        operations: &[Operation],
-    ) -> Result<Vec<ConflictRecord>, EngineError> {
-        self.exec_batch("BEGIN IMMEDIATE")?;
-
-        let result = (|| -> Result<Vec<ConflictRecord>, EngineError> {
-            // 1. Snapshot field metadata for all SetField/ClearField ops BEFORE materialization
-            let pre_snapshots = self.snapshot_field_metadata(operations)?;
-
-            // 2. Append bundle (materializes ops via SAVEPOINT, nests correctly)
-            self.storage.append_bundle(bundle, operations)?;
-
-            // 3. Detect conflicts using pre-materialization snapshots
-            let conflicts = self.detect_conflicts(bundle, operations, &pre_snapshots)?;
-
-            // 4. Scan for overlay drift on modified fields
-            let modified_fields: Vec<(EntityId, String)> = operations.iter().filter_map(|op| {
-                match &op.payload {
-                    OperationPayload::SetField { entity_id, field_key, .. }
-                    | OperationPayload::ClearField { entity_id, field_key } => {
-                        Some((*entity_id, field_key.clone()))
-                    }
-                    _ => None,
-                }
-            }).collect();
-            self.scan_overlay_drift(&modified_fields)?;
-
-            Ok(conflicts)
-        })();
-
-        match result {
-            Ok(conflicts) => {
-                self.exec_batch("COMMIT")?;
-                Ok(conflicts)
-            }
-            Err(e) => {
-                let _ = self.exec_batch("ROLLBACK");
-                Err(e)
-            }
-        }
-    }
-
-    /// Pre-materialization snapshot of field metadata for conflict detection.
-    fn snapshot_field_metadata(
-        &self,
-        operations: &[Operation],
-    ) -> Result<Vec<FieldMetadataSnapshot>, EngineError> {
-        let mut snapshots = Vec::new();
-        for op in operations {
-            match &op.payload {
-                OperationPayload::SetField { entity_id, field_key, value } => {
-                    let current = self.storage.get_field_source_bundle_vc(*entity_id, field_key)?;
-                    let value_bytes = value.to_msgpack()
-                        .map_err(|e| EngineError::Core(openprod_core::CoreError::Serialization(e.to_string())))?;
-                    snapshots.push(FieldMetadataSnapshot {
-                        entity_id: *entity_id,
-                        field_key: field_key.clone(),
-                        current_actor: current.as_ref().map(|(a, _, _, _)| *a),
-                        current_hlc: current.as_ref().map(|(_, h, _, _)| *h),
-                        current_op_id: current.as_ref().map(|(_, _, o, _)| *o),
-                        current_bundle_vc: current.and_then(|(_, _, _, vc)| vc),
-                        ingested_op_id: op.op_id,
-                        ingested_value: Some(value_bytes),
-                    });
-                }
-                OperationPayload::ClearField { entity_id, field_key } => {
-                    let current = self.storage.get_field_source_bundle_vc(*entity_id, field_key)?;
-                    snapshots.push(FieldMetadataSnapshot {
-                        entity_id: *entity_id,
-                        field_key: field_key.clone(),
-                        current_actor: current.as_ref().map(|(a, _, _, _)| *a),
-                        current_hlc: current.as_ref().map(|(_, h, _, _)| *h),
-                        current_op_id: current.as_ref().map(|(_, _, o, _)| *o),
-                        current_bundle_vc: current.and_then(|(_, _, _, vc)| vc),
-                        ingested_op_id: op.op_id,
-                        ingested_value: None,
-                    });
-                }
-                _ => {}
-            }
-        }
-        Ok(snapshots)
-    }
-
-    /// Detect field-level conflicts by comparing the ingested bundle's vector clock
-    /// against the pre-materialization field state.
-    fn detect_conflicts(
-        &mut self,
-        bundle: &Bundle,
-        operations: &[Operation],
-        pre_snapshots: &[FieldMetadataSnapshot],
-    ) -> Result<Vec<ConflictRecord>, EngineError> {
-        let ingested_actor = bundle.actor_id;
-        let ingested_vc = bundle.creator_vc.as_ref();
-
-        let mut conflicts = Vec::new();
-
-        for snap in pre_snapshots {
-            // 1. No prior value → no conflict
-            let current_actor = match snap.current_actor {
-                Some(a) => a,
-                None => continue,
-            };
-            let current_hlc = snap.current_hlc.unwrap(); // safe: actor implies hlc
-            let current_op_id = snap.current_op_id.unwrap();
-
-            // 2. Same actor → no conflict
-            if current_actor == ingested_actor {
-                continue;
-            }
-
-            // Find the ingested op's HLC
-            let ingested_op = operations.iter().find(|o| o.op_id == snap.ingested_op_id);
-            let ingested_hlc = match ingested_op {
-                Some(op) => op.hlc,
-                None => continue,
-            };
-
-            // 3. Did ingested actor know about the current value?
-            //    creator_vc.get(current_actor) >= current_hlc?
-            if let Some(vc) = ingested_vc
-                && let Some(known_hlc) = vc.get(&current_actor)
-                && *known_hlc >= current_hlc
-            {
-                continue; // ingested saw the current value → not concurrent
-            }
-
-            // 4. Did the current writer know about the ingested actor?
-            //    current_bundle_vc.get(ingested_actor) >= ingested_hlc?
-            if let Some(ref current_vc) = snap.current_bundle_vc
-                && let Some(known_hlc) = current_vc.get(&ingested_actor)
-                && *known_hlc >= ingested_hlc
-            {
-                continue; // current writer saw the ingested actor → not concurrent
-            }
-
-            // Both didn't see each other → CONFLICT
-            // Check for existing conflict on this (entity, field) — open or resolved
-            let existing = self.storage.get_latest_conflict_for_field(snap.entity_id, &snap.field_key)?;
-
-            // Get the current field's value bytes for the conflict record
-            let current_value_bytes: Option<Vec<u8>> = {
-                self.get_field_value_from_oplog(current_op_id)?
-            };
-
-            let incoming_tip = ConflictValue {
-                value: snap.ingested_value.clone(),
-                actor_id: ingested_actor,
-                hlc: ingested_hlc,
-                op_id: snap.ingested_op_id,
-            };
-
-            if let Some(existing) = existing {
-                if existing.status == ConflictStatus::Resolved {
-                    // Resolved conflict being reopened by a new concurrent edit.
-                    // Build fresh branch tips from resolution + late-arriving edit.
-                    let resolution_tip = ConflictValue {
-                        value: existing.resolved_value.clone(),
-                        actor_id: existing.resolved_by.unwrap(),
-                        hlc: existing.resolved_at.unwrap(),
-                        op_id: existing.resolved_op_id.unwrap(),
-                    };
-                    self.storage.reopen_conflict(
-                        existing.conflict_id,
-                        ingested_hlc,
-                        snap.ingested_op_id,
-                        &[resolution_tip, incoming_tip],
-                    )?;
-                    conflicts.push(self.storage.get_conflict(existing.conflict_id)?.unwrap());
-                } else {
-                    // Already open — extend to N-way by adding the new branch tip
-                    self.storage.add_conflict_value(existing.conflict_id, &incoming_tip)?;
-                    conflicts.push(self.storage.get_conflict(existing.conflict_id)?.unwrap());
-                }
-                continue;
-            }
-
-            // Create new conflict
-            let conflict_id = ConflictId::new();
-            let record = ConflictRecord {
-                conflict_id,
-                entity_id: snap.entity_id,
-                field_key: snap.field_key.clone(),
-                status: ConflictStatus::Open,
-                values: vec![
-                    ConflictValue {
-                        value: current_value_bytes,
-                        actor_id: current_actor,
-                        hlc: current_hlc,
-                        op_id: current_op_id,
-                    },
-                    incoming_tip,
-                ],
-                detected_at: ingested_hlc,
-                detected_in_bundle: bundle.bundle_id,
-                resolved_at: None,
-                resolved_by: None,
-                resolved_op_id: None,
-                resolved_value: None,
-                reopened_at: None,
-                reopened_by_op: None,
-            };
-            self.storage.insert_conflict(&record)?;
-            conflicts.push(record);
-        }
-
-        Ok(conflicts)
-    }
-
-    /// Extract a field value from an oplog operation by op_id.
-    fn get_field_value_from_oplog(&self, op_id: OpId) -> Result<Option<Vec<u8>>, EngineError> {
-        Ok(self.storage.get_op_field_value(op_id)?)
-    }
-
-    // ========================================================================
-    // Conflict Resolution
-    // ========================================================================
-
-    /// Resolve a conflict by choosing a value.
-    /// `chosen_value: None` means resolve to cleared (tombstone).
-    /// Resolution is NOT undoable per spec.
-    pub fn resolve_conflict(
-        &mut self,
-        conflict_id: ConflictId,
-        chosen_value: Option<FieldValue>,
-    ) -> Result<BundleId, EngineError> {
-        // Load conflict
-        let conflict = self.storage.get_conflict(conflict_id)?
-            .ok_or_else(|| EngineError::ConflictNotFound(conflict_id.to_string()))?;
-
-        if conflict.status != ConflictStatus::Open {
-            return Err(EngineError::ConflictAlreadyResolved(conflict_id.to_string()));
-        }
-
-        self.exec_batch("BEGIN IMMEDIATE")?;
-
-        let result = (|| -> Result<BundleId, EngineError> {
-            // Create ResolveConflict operation payload
-            let payloads = vec![OperationPayload::ResolveConflict {
-                conflict_id,
-                entity_id: conflict.entity_id,
-                field_key: conflict.field_key.clone(),
-                chosen_value: chosen_value.clone(),
-            }];
-
-            // Execute as non-undoable
-            let (bundle_id, hlc) = self.execute_internal(BundleType::UserEdit, payloads, false)?;
-
-            // Update conflict record to resolved
-            let resolved_value_bytes = match &chosen_value {
-                Some(v) => Some(v.to_msgpack()
-                    .map_err(|e| EngineError::Core(openprod_core::CoreError::Serialization(e.to_string())))?),
-                None => None,
-            };
-            // Get the op_id from the bundle we just created
-            let ops = self.storage.get_ops_by_bundle(bundle_id)?;
-            let resolve_op_id = ops.first().map(|o| o.op_id)
-                .ok_or_else(|| EngineError::ConflictNotFound("no ops in resolve bundle".into()))?;
-
-            self.storage.update_conflict_resolved(
-                conflict_id,
-                hlc,
-                self.identity.actor_id(),
-                resolve_op_id,
-                resolved_value_bytes,
-            )?;
-
-            Ok(bundle_id)
-        })();
-
-        match result {
-            Ok(bundle_id) => {
-                self.exec_batch("COMMIT")?;
-                Ok(bundle_id)
-            }
-            Err(e) => {
-                let _ = self.exec_batch("ROLLBACK");
-                Err(e)
-            }
-        }
-    }
-
-    // ========================================================================
-    // Conflict Queries
-    // ========================================================================
-
-    pub fn get_open_conflicts_for_entity(
-        &self,
-        entity_id: EntityId,
-    ) -> Result<Vec<ConflictRecord>, EngineError> {
-        Ok(self.storage.get_open_conflicts_for_entity(entity_id)?)
-    }
-
-    pub fn get_conflict(
-        &self,
-        conflict_id: ConflictId,
-    ) -> Result<Option<ConflictRecord>, EngineError> {
-        Ok(self.storage.get_conflict(conflict_id)?)
-    }
-
-    // ========================================================================
-    // State Rebuild
-    // ========================================================================
-
-    /// Rebuild materialized state from the oplog. Returns the number of operations replayed.
-    pub fn rebuild_state(&mut self) -> Result<u64, EngineError> {
-        Ok(self.storage.rebuild_from_oplog()?)
-    }
-
-    // ========================================================================
-    // Overlay Lifecycle
-    // ========================================================================
-
-    /// Create a new overlay and make it active.
-    /// If another overlay is currently active, it is auto-stashed.
-    pub fn create_overlay(&mut self, name: &str) -> Result<OverlayId, EngineError> {
-        // Auto-stash current active overlay
-        if let Some(current) = self.overlay_manager.active_overlay_id() {
-            self.stash_overlay(current)?;
-        }
-
-        let overlay_id = OverlayId::new();
-        let hlc = self.clock.tick()?;
-        self.storage.insert_overlay(
-            overlay_id,
-            name,
-            OverlaySource::User.as_str(),
-            OverlayStatus::Active.as_str(),
-            &hlc,
-        )?;
-        self.overlay_manager.set_active(Some(overlay_id));
-        Ok(overlay_id)
-    }
-
-    /// Activate an existing overlay (must be stashed).
-    /// If another overlay is currently active, it is auto-stashed.
-    pub fn activate_overlay(&mut self, overlay_id: OverlayId) -> Result<(), EngineError> {
-        let overlay = self.storage.get_overlay(overlay_id)?
-            .ok_or_else(|| EngineError::OverlayNotFound(overlay_id.to_string()))?;
-        let (_id, _name, _source, status, _created, _updated) = overlay;
-        if status != OverlayStatus::Stashed.as_str() {
-            return Err(EngineError::OverlayNotFound(
-                format!("overlay {} is not stashed (status: {})", overlay_id, status),
-            ));
-        }
-
-        // Auto-stash current active overlay
-        if let Some(current) = self.overlay_manager.active_overlay_id() {
-            self.stash_overlay(current)?;
-        }
-
-        let hlc = self.clock.tick()?;
-        self.storage.update_overlay_status(overlay_id, OverlayStatus::Active.as_str(), &hlc)?;
-        self.overlay_manager.set_active(Some(overlay_id));
-        Ok(())
-    }
-
-    /// Stash an overlay (deactivate without discarding).
-    pub fn stash_overlay(&mut self, overlay_id: OverlayId) -> Result<(), EngineError> {
-        let hlc = self.clock.tick()?;
-        self.storage.update_overlay_status(overlay_id, OverlayStatus::Stashed.as_str(), &hlc)?;
-        if self.overlay_manager.active_overlay_id() == Some(overlay_id) {
-            self.overlay_manager.set_active(None);
-        }
-        Ok(())
-    }
-
-    /// Discard an overlay — removes all overlay ops and the overlay record.
-    pub fn discard_overlay(&mut self, overlay_id: OverlayId) -> Result<(), EngineError> {
-        self.storage.delete_overlay(overlay_id)?;
-        if self.overlay_manager.active_overlay_id() == Some(overlay_id) {
-            self.overlay_manager.set_active(None);
-        }
-        Ok(())
-    }
-
-    /// Get the currently active overlay ID, if any.
-    pub fn active_overlay(&self) -> Option<OverlayId> {
-        self.overlay_manager.active_overlay_id()
-    }
-
-    /// List stashed overlays.
-    pub fn stashed_overlays(&self) -> Result<Vec<(OverlayId, String)>, EngineError> {
-        let raw = self.storage.list_overlays_by_status(OverlayStatus::Stashed.as_str())?;
-        Ok(raw.into_iter().map(|(id, name, _source, _created)| (id, name)).collect())
-    }
-
-    /// Undo the most recent operation in the active overlay.
-    /// Removes the op from overlay_ops and pushes to overlay redo stack.
-    pub fn overlay_undo(&mut self) -> Result<bool, EngineError> {
-        let overlay_id = self.overlay_manager.active_overlay_id()
-            .ok_or(EngineError::NoActiveOverlay)?;
-
-        let op = match self.overlay_manager.pop_overlay_undo() {
-            Some(op) => op,
-            None => return Ok(false),
-        };
-
-        self.storage.delete_overlay_op(op.rowid)?;
-        self.overlay_manager.push_overlay_redo(op);
-        // Verify overlay_id matches (should always be true for active overlay)
-        let _ = overlay_id;
-        Ok(true)
-    }
-
-    /// Redo the most recently undone overlay operation.
-    /// Re-inserts the op into overlay_ops.
-    pub fn overlay_redo(&mut self) -> Result<bool, EngineError> {
-        let overlay_id = self.overlay_manager.active_overlay_id()
-            .ok_or(EngineError::NoActiveOverlay)?;
-
-        let mut op = match self.overlay_manager.pop_overlay_redo() {
-            Some(op) => op,
-            None => return Ok(false),
-        };
-
-        let payload_bytes = op.payload.to_msgpack()?;
-        let rowid = self.storage.insert_overlay_op(
-            overlay_id,
-            op.op_id,
-            &op.hlc,
-            &payload_bytes,
-            op.entity_id,
-            op.field_key.as_deref(),
-            &op.op_type,
-            op.canonical_value_at_creation.as_deref(),
-        )?;
-        op.rowid = rowid;
-        self.overlay_manager.push_overlay_undo(op);
-        Ok(true)
-    }
-
-    // ========================================================================
-    // Overlay Commit & Canonical Drift
-    // ========================================================================
-
-    /// Scan all active/stashed overlays for drift on the given modified fields.
-    /// Called after canonical state changes (ingest_bundle, commit_overlay).
-    fn scan_overlay_drift(&mut self, modified_fields: &[(EntityId, String)]) -> Result<(), EngineError> {
-        for (entity_id, _field_key) in modified_fields {
-            self.storage.mark_overlay_ops_drifted(*entity_id, _field_key)?;
-        }
-        Ok(())
-    }
-
-    /// Commit an overlay — atomically move all overlay ops to canonical storage.
-    /// Returns the BundleId of the committed bundle.
-    /// Fails if there is unresolved drift.
-    pub fn commit_overlay(&mut self, overlay_id: OverlayId) -> Result<BundleId, EngineError> {
-        // Check for unresolved drift
-        let drift_count = self.storage.count_unresolved_drift(overlay_id)?;
-        if drift_count > 0 {
-            return Err(EngineError::UnresolvedDrift(
-                format!("{} drifted field(s) on overlay {}", drift_count, overlay_id),
-            ));
-        }
-
-        // Read all overlay ops ordered by rowid
-        let overlay_ops = self.storage.get_overlay_ops(overlay_id)?;
-        if overlay_ops.is_empty() {
-            // Empty overlay — just discard
-            self.discard_overlay(overlay_id)?;
-            return Err(EngineError::EmptyOverlay(
-                format!("overlay {} has no ops to commit", overlay_id),
-            ));
-        }
-
-        // Deserialize payloads
-        let mut payloads = Vec::new();
-        for (_rowid, _op_id, _hlc, payload_bytes, _entity_id, _op_type, _canon, _drifted, _field_key) in &overlay_ops {
-            let payload = OperationPayload::from_msgpack(payload_bytes)?;
-            payloads.push(payload);
-        }
-
-        // Collect modified fields for drift scanning
-        let modified_fields: Vec<(EntityId, String)> = payloads.iter().filter_map(|p| {
-            match p {
-                OperationPayload::SetField { entity_id, field_key, .. }
-                | OperationPayload::ClearField { entity_id, field_key } => {
-                    Some((*entity_id, field_key.clone()))
-                }
-                _ => None,
-            }
-        }).collect();
-
-        // Deactivate overlay to avoid routing the execute_internal call back to overlay
-        if self.overlay_manager.active_overlay_id() == Some(overlay_id) {
-            self.overlay_manager.set_active(None);
-        }
-
-        // Wrap commit in transaction for atomicity
-        self.exec_batch("BEGIN IMMEDIATE")?;
-
-        let result = (|| -> Result<BundleId, EngineError> {
-            // Execute as canonical (non-undoable)
-            let (bundle_id, _hlc) = self.execute_internal(BundleType::UserEdit, payloads, false)?;
-
-            // Update overlay status to committed
-            let hlc = self.clock.tick()?;
-            self.storage.update_overlay_status(overlay_id, OverlayStatus::Committed.as_str(), &hlc)?;
-
-            // Scan for drift on stashed overlays
-            self.scan_overlay_drift(&modified_fields)?;
-
-            Ok(bundle_id)
-        })();
-
-        match result {
-            Ok(bundle_id) => {
-                self.exec_batch("COMMIT")?;
-                Ok(bundle_id)
-            }
-            Err(e) => {
-                let _ = self.exec_batch("ROLLBACK");
-                Err(e)
-            }
-        }
-    }
-
-    /// Check for drifted fields on an overlay.
-    /// Returns a list of DriftRecord entries showing overlay vs canonical values.
-    pub fn check_drift(&self, overlay_id: OverlayId) -> Result<Vec<DriftRecord>, EngineError> {
-        let drifted_ops = self.storage.get_drifted_overlay_ops(overlay_id)?;
-        let mut records = Vec::new();
-
-        for (_rowid, _op_id, _hlc, payload_bytes, _entity_id_bytes, _op_type, _canon_bytes, _drifted, _field_key) in &drifted_ops {
-            let payload = OperationPayload::from_msgpack(payload_bytes)?;
-            match payload {
-                OperationPayload::SetField { entity_id, field_key, value, .. } => {
-                    let canonical_value = self.storage.get_field(entity_id, &field_key)?;
-                    records.push(DriftRecord {
-                        entity_id,
-                        field_key,
-                        overlay_value: Some(value),
-                        canonical_value,
-                    });
-                }
-                OperationPayload::ClearField { entity_id, field_key } => {
-                    let canonical_value = self.storage.get_field(entity_id, &field_key)?;
-                    records.push(DriftRecord {
-                        entity_id,
-                        field_key,
-                        overlay_value: None,
-                        canonical_value,
-                    });
-                }
-                _ => {}
-            }
-        }
-
-        Ok(records)
-    }
-
-    /// Acknowledge drift on a field — "Keep Mine".
-    /// Clears the drift flag and updates canonical_value_at_creation to new canonical value.
-    pub fn acknowledge_drift(
-        &mut self,
-        overlay_id: OverlayId,
-        entity_id: EntityId,
-        field_key: &str,
-    ) -> Result<(), EngineError> {
-        // Get current canonical value for this field
-        let canonical_value = match self.storage.get_field(entity_id, field_key)? {
-            Some(v) => {
-                let bytes = v.to_msgpack()
-                    .map_err(|e| EngineError::Core(openprod_core::CoreError::Serialization(e.to_string())))?;
-                Some(bytes)
-            }
-            None => None,
-        };
-
-        self.storage.update_canonical_value_at_creation(overlay_id, entity_id, field_key, canonical_value.as_deref())?;
-        self.storage.clear_drift_flag(overlay_id, entity_id, field_key)?;
-        Ok(())
-    }
-
-    /// Knockout a field from the overlay — "Use Canonical".
-    /// Removes the overlay op for this field, so it falls through to canonical.
-    pub fn knockout_field(
-        &mut self,
-        overlay_id: OverlayId,
-        entity_id: EntityId,
-        field_key: &str,
-    ) -> Result<(), EngineError> {
-        self.storage.delete_overlay_ops_for_field(overlay_id, entity_id, field_key)?;
-        Ok(())
-    }
-
-    /// Check if an overlay has any unresolved drift.
-    pub fn has_unresolved_drift(&self, overlay_id: OverlayId) -> Result<bool, EngineError> {
-        Ok(self.storage.count_unresolved_drift(overlay_id)? > 0)
-    }
-}
-
-/// Pre-materialization snapshot of a field's metadata for conflict detection.
-struct FieldMetadataSnapshot {
-    entity_id: EntityId,
-    field_key: String,
-    current_actor: Option<ActorId>,
-    current_hlc: Option<Hlc>,
-    current_op_id: Option<OpId>,
-    current_bundle_vc: Option<VectorClock>,
-    ingested_op_id: OpId,
-    ingested_value: Option<Vec<u8>>,
 }
@@ -0,0 +1,158 @@
+//! Ordered list fields, for `Engine::list_insert`, `list_move` and
+//! `list_remove`. Like rich text, a list isn't materialized in storage: it's
+//! folded from the field's `ListOp`s when read.
+
+use std::collections::{HashMap, HashSet};
+
+use openprod_core::{
+    field_value::FieldValue,
+    ids::{BundleId, EntityId, ListItemId},
+    list::{is_valid_position, position_between, ListOp},
+    operations::{BundleType, CrdtType, OperationPayload},
+};
+use openprod_storage::EngineStorage;
+
+use crate::Engine;
+use crate::error::EngineError;
+use crate::system::check_user_field;
+
+/// One item of a list field, as `Engine::get_list` returns them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    pub item_id: ListItemId,
+    pub value: FieldValue,
+    /// Position key; items read in key order, then by id.
+    pub position: String,
+}
+
+/// Fold a field's ops, oldest first, into its items in list order. Ops that
+/// don't decode or carry a bad position (only a faulty peer sends those) are
+/// skipped. Linear in the ops, plus the final sort.
+pub(crate) fn materialize<'a>(deltas: impl IntoIterator<Item = &'a [u8]>) -> Vec<ListItem> {
+    let mut items: HashMap<ListItemId, ListItem> = HashMap::new();
+    let mut removed: HashSet<ListItemId> = HashSet::new();
+    for delta in deltas {
+        match ListOp::from_msgpack(delta) {
+            Ok(ListOp::Insert { item_id, position, value }) if is_valid_position(&position) => {
+                items.entry(item_id).or_insert(ListItem { item_id, value, position });
+            }
+            // Ops arrive oldest first, so the last move wins
+            Ok(ListOp::Move { item_id, position }) if is_valid_position(&position) => {
+                if let Some(item) = items.get_mut(&item_id) {
+                    item.position = position;
+                }
+            }
+            Ok(ListOp::Remove { item_id }) => {
+                removed.insert(item_id);
+            }
+            _ => {}
+        }
+    }
+    let mut items: Vec<ListItem> = items.into_values().filter(|item| !removed.contains(&item.item_id)).collect();
+    items.sort_by(|a, b| (&a.position, a.item_id).cmp(&(&b.position, b.item_id)));
+    items
+}
+
+/// Where `place` puts new items.
+pub(crate) struct Placement {
+    /// One key per new item, in order.
+    pub keys: Vec<String>,
+    /// New keys for existing items that must shift to make room.
+    pub shifted: Vec<(ListItemId, String)>,
+}
+
+/// Position keys to place `count` items at `index` of `items`. Existing
+/// items only shift after concurrent inserts left neighbours on the same
+/// key: those after `index` are moved up behind the new items.
+pub(crate) fn place(items: &[ListItem], index: usize, count: usize) -> Result<Placement, EngineError> {
+    if index > items.len() {
+        return Err(EngineError::Rejected(format!("list index {index} out of range ({} items)", items.len())));
+    }
+    let before = index.checked_sub(1).map(|i| items[i].position.as_str());
+    let mut end = index;
+    while before.is_some_and(|before| items.get(end).is_some_and(|item| item.position == before)) {
+        end += 1;
+    }
+    let after = items.get(end).map(|item| item.position.as_str());
+
+    let mut keys = Vec::with_capacity(count + end - index);
+    let mut last = before.map(str::to_string);
+    for _ in 0..count + end - index {
+        let key = position_between(last.as_deref(), after)?;
+        keys.push(key.clone());
+        last = Some(key);
+    }
+    let shifted = keys.split_off(count);
+    Ok(Placement { keys, shifted: items[index..end].iter().map(|item| item.item_id).zip(shifted).collect() })
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// A list field's items in order (through the active overlay). Empty if
+    /// nothing was ever inserted.
+    pub fn get_list(&self, entity_id: EntityId, field_key: &str) -> Result<Vec<ListItem>, EngineError> {
+        let deltas = self.crdt_deltas(entity_id, field_key, CrdtType::List)?;
+        Ok(materialize(deltas.iter().map(Vec::as_slice)))
+    }
+
+    /// Insert `value` so it reads at `index` of the list (`len` appends).
+    /// Concurrent inserts, moves and removals from other peers all merge
+    /// without conflicts. Like rich text, list edits aren't undoable.
+    pub fn list_insert(
+        &mut self,
+        entity_id: EntityId,
+        field_key: &str,
+        index: usize,
+        value: FieldValue,
+    ) -> Result<(ListItemId, BundleId), EngineError> {
+        check_user_field(field_key)?;
+        self.require_live_entity(entity_id)?;
+        let items = self.get_list(entity_id, field_key)?;
+        let mut placement = place(&items, index, 1)?;
+        let item_id = ListItemId::new();
+        let mut ops = vec![ListOp::Insert { item_id, position: placement.keys.remove(0), value }];
+        ops.extend(placement.shifted.into_iter().map(|(item_id, position)| ListOp::Move { item_id, position }));
+        let bundle_id = self.apply_list_ops(entity_id, field_key, ops)?;
+        Ok((item_id, bundle_id))
+    }
+
+    /// Move an item so it reads at `index` of the list without it.
+    pub fn list_move(&mut self, entity_id: EntityId, field_key: &str, item_id: ListItemId, index: usize) -> Result<BundleId, EngineError> {
+        check_user_field(field_key)?;
+        self.require_live_entity(entity_id)?;
+        let mut items = self.get_list(entity_id, field_key)?;
+        let from = items
+            .iter()
+            .position(|item| item.item_id == item_id)
+            .ok_or_else(|| EngineError::ListItemNotFound(item_id.to_string()))?;
+        items.remove(from);
+        let mut placement = place(&items, index, 1)?;
+        let mut ops = vec![ListOp::Move { item_id, position: placement.keys.remove(0) }];
+        ops.extend(placement.shifted.into_iter().map(|(item_id, position)| ListOp::Move { item_id, position }));
+        self.apply_list_ops(entity_id, field_key, ops)
+    }
+
+    pub fn list_remove(&mut self, entity_id: EntityId, field_key: &str, item_id: ListItemId) -> Result<BundleId, EngineError> {
+        check_user_field(field_key)?;
+        self.require_live_entity(entity_id)?;
+        if !self.get_list(entity_id, field_key)?.iter().any(|item| item.item_id == item_id) {
+            return Err(EngineError::ListItemNotFound(item_id.to_string()));
+        }
+        self.apply_list_ops(entity_id, field_key, vec![ListOp::Remove { item_id }])
+    }
+
+    fn apply_list_ops(&mut self, entity_id: EntityId, field_key: &str, ops: Vec<ListOp>) -> Result<BundleId, EngineError> {
+        let payloads = ops
+            .iter()
+            .map(|op| {
+                Ok(OperationPayload::ApplyCrdt {
+                    entity_id,
+                    field_key: field_key.to_string(),
+                    crdt_type: CrdtType::List,
+                    delta: op.to_msgpack()?,
+                })
+            })
+            .collect::<Result<Vec<_>, EngineError>>()?;
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, false)?;
+        Ok(bundle_id)
+    }
+}
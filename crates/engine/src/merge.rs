@@ -0,0 +1,169 @@
+//! Line-based three-way merge of text values, for `Engine::suggest_text_merge`.
+
+/// Result of merging text branches against their common base.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextMerge {
+    /// No two branches changed the same lines; the conflict can be resolved
+    /// with this text.
+    Clean(String),
+    /// Some branches changed the same lines differently. The hunks, in
+    /// order, cover the whole text.
+    Conflicted(Vec<MergeHunk>),
+}
+
+/// One stretch of a conflicted merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeHunk {
+    /// Lines every branch agrees on, after applying non-overlapping edits.
+    Resolved(String),
+    /// Lines branches changed differently: the base text and each branch's
+    /// version, in branch order.
+    Conflict { base: String, branches: Vec<String> },
+}
+
+impl TextMerge {
+    pub fn is_clean(&self) -> bool {
+        matches!(self, Self::Clean(_))
+    }
+}
+
+/// Largest LCS table `diff` builds (16 MB of cells). Conflict values come
+/// from peers, so two long rewrites fall back to one edit over the changed
+/// stretch, i.e. a single conflict hunk, instead of a quadratic table.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Base lines `start..end` replaced by `lines` in one branch.
+struct Edit<'a> {
+    start: usize,
+    end: usize,
+    lines: &'a [&'a str],
+}
+
+/// Merge `branches` line by line against `base`, diff3 style. Edits from
+/// different branches that overlap, or insert lines at the same place,
+/// conflict unless they produce the same text. Lines keep their terminators, so a
+/// missing final newline is preserved.
+pub fn merge_text(base: &str, branches: &[&str]) -> TextMerge {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let branch_lines: Vec<Vec<&str>> = branches.iter().map(|b| b.split_inclusive('\n').collect()).collect();
+    let edits: Vec<Vec<Edit>> = branch_lines.iter().map(|lines| diff(&base_lines, lines)).collect();
+
+    // Every edit, by where it starts in the base
+    let mut all: Vec<(usize, &Edit)> =
+        edits.iter().enumerate().flat_map(|(branch, edits)| edits.iter().map(move |e| (branch, e))).collect();
+    all.sort_by_key(|(branch, e)| (e.start, e.end, *branch));
+
+    let mut hunks: Vec<MergeHunk> = Vec::new();
+    let mut position = 0;
+    let mut i = 0;
+    while i < all.len() {
+        // Grow a cluster of edits that overlap, or insert at the same place
+        // (whose order no branch decided)
+        let start = all[i].1.start;
+        let mut end = all[i].1.end;
+        let mut cluster = vec![all[i]];
+        i += 1;
+        while i < all.len()
+            && (all[i].1.start < end
+                || (all[i].1.start == end && all[i].1.end == end && cluster.iter().any(|(_, e)| e.start == end && e.end == end)))
+        {
+            end = end.max(all[i].1.end);
+            cluster.push(all[i]);
+            i += 1;
+        }
+        push_resolved(&mut hunks, base_lines[position..start].concat());
+        position = end;
+
+        let versions: Vec<Option<String>> = (0..branches.len())
+            .map(|branch| {
+                let mut own = cluster.iter().filter(|(b, _)| *b == branch).map(|(_, e)| e).peekable();
+                own.peek()?;
+                let mut text = String::new();
+                let mut at = start;
+                for edit in own {
+                    text.push_str(&base_lines[at..edit.start].concat());
+                    text.push_str(&edit.lines.concat());
+                    at = edit.end;
+                }
+                text.push_str(&base_lines[at..end].concat());
+                Some(text)
+            })
+            .collect();
+        let mut changed = versions.iter().flatten();
+        let first = changed.next().expect("a cluster has at least one edit");
+        if changed.all(|v| v == first) {
+            push_resolved(&mut hunks, first.clone());
+        } else {
+            let base_text = base_lines[start..end].concat();
+            hunks.push(MergeHunk::Conflict {
+                branches: versions.into_iter().map(|v| v.unwrap_or_else(|| base_text.clone())).collect(),
+                base: base_text,
+            });
+        }
+    }
+    push_resolved(&mut hunks, base_lines[position..].concat());
+
+    if hunks.iter().any(|h| matches!(h, MergeHunk::Conflict { .. })) {
+        return TextMerge::Conflicted(hunks);
+    }
+    // Resolved stretches coalesce, so a clean merge is at most one hunk
+    match hunks.pop() {
+        Some(MergeHunk::Resolved(text)) => TextMerge::Clean(text),
+        _ => TextMerge::Clean(String::new()),
+    }
+}
+
+/// Append `text`, joining it to a preceding resolved hunk.
+fn push_resolved(hunks: &mut Vec<MergeHunk>, text: String) {
+    if text.is_empty() {
+        return;
+    }
+    match hunks.last_mut() {
+        Some(MergeHunk::Resolved(last)) => last.push_str(&text),
+        _ => hunks.push(MergeHunk::Resolved(text)),
+    }
+}
+
+/// The edits turning `base` into `other`, from a longest common subsequence
+/// of lines. Common leading and trailing lines are trimmed first, which
+/// keeps the table small for typical edits; past `MAX_DIFF_CELLS` the
+/// trimmed stretch is one replacement.
+fn diff<'a>(base: &[&str], other: &'a [&'a str]) -> Vec<Edit<'a>> {
+    let prefix = base.iter().zip(other).take_while(|(a, b)| a == b).count();
+    let suffix = base[prefix..].iter().rev().zip(other[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let a = &base[prefix..base.len() - suffix];
+    let b = &other[prefix..other.len() - suffix];
+    if a.len().saturating_mul(b.len()) > MAX_DIFF_CELLS {
+        return vec![Edit { start: prefix, end: prefix + a.len(), lines: b }];
+    }
+
+    // lcs[i][j]: longest common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let (mut edit_i, mut edit_j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            if (edit_i, edit_j) != (i, j) {
+                edits.push(Edit { start: prefix + edit_i, end: prefix + i, lines: &other[prefix + edit_j..prefix + j] });
+            }
+            i += 1;
+            j += 1;
+            (edit_i, edit_j) = (i, j);
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            j += 1;
+        } else {
+            i += 1;
+        }
+    }
+    if (edit_i, edit_j) != (i, j) {
+        edits.push(Edit { start: prefix + edit_i, end: prefix + i, lines: &other[prefix + edit_j..prefix + j] });
+    }
+    edits
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use openprod_core::{ids::BundleId, operations::Operation};
+use openprod_storage::EngineStorage;
+
+use crate::Engine;
+
+/// How ingest treats bundles whose ops require module semantics this engine
+/// hasn't declared: a module it doesn't know, or a newer version than it supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModulePolicy {
+    /// Ingest as usual.
+    Accept,
+    /// Ingest, and list each mismatch in `IngestReport::module_mismatches`.
+    #[default]
+    Warn,
+    /// Quarantine the bundle instead of ingesting it; `retry_quarantined` picks
+    /// it up once the app declares a new enough version.
+    Hold,
+}
+
+/// Module versions the embedding app supports. Local ops are stamped with
+/// them, and incoming ops are checked against them on ingest.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleRegistry {
+    versions: BTreeMap<String, String>,
+    policy: ModulePolicy,
+}
+
+/// An op required a module this engine doesn't support at that version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleMismatch {
+    pub bundle_id: BundleId,
+    pub module: String,
+    pub required: String,
+    /// Version declared locally (None = module not declared).
+    pub supported: Option<String>,
+}
+
+impl fmt::Display for ModuleMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.supported {
+            Some(supported) => write!(f, "requires {} {} (have {supported})", self.module, self.required),
+            None => write!(f, "requires {} {} (not declared)", self.module, self.required),
+        }
+    }
+}
+
+impl ModuleRegistry {
+    pub fn new(policy: ModulePolicy) -> Self {
+        Self { versions: BTreeMap::new(), policy }
+    }
+
+    /// Declare support for `module` up to `version` (replacing any earlier declaration).
+    pub fn declare(&mut self, module: impl Into<String>, version: impl Into<String>) -> &mut Self {
+        self.versions.insert(module.into(), version.into());
+        self
+    }
+
+    pub fn version(&self, module: &str) -> Option<&str> {
+        self.versions.get(module).map(String::as_str)
+    }
+
+    pub fn versions(&self) -> &BTreeMap<String, String> {
+        &self.versions
+    }
+
+    pub fn policy(&self) -> ModulePolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: ModulePolicy) {
+        self.policy = policy;
+    }
+
+    /// Every (module, version) required by `operations` that isn't supported,
+    /// once per module at the highest version required.
+    pub fn check(&self, bundle_id: BundleId, operations: &[Operation]) -> Vec<ModuleMismatch> {
+        let mut required: BTreeMap<&str, &str> = BTreeMap::new();
+        for op in operations {
+            for (module, version) in &op.module_versions {
+                let highest = required.entry(module).or_insert(version);
+                if compare_versions(version, highest) == Ordering::Greater {
+                    *highest = version;
+                }
+            }
+        }
+        required
+            .into_iter()
+            .filter_map(|(module, version)| {
+                let supported = self.version(module);
+                let ok = supported.is_some_and(|have| compare_versions(version, have) != Ordering::Greater);
+                (!ok).then(|| ModuleMismatch {
+                    bundle_id,
+                    module: module.to_string(),
+                    required: version.to_string(),
+                    supported: supported.map(str::to_string),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Compare dotted versions ("1.10" > "1.9", "2" == "2.0") component by
+/// component: numerically where both components are numbers, as text otherwise.
+pub(crate) fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+    loop {
+        let (x, y) = match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (x, y) => (x.unwrap_or("0"), y.unwrap_or("0")),
+        };
+        let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Replace the module registry: the versions stamped on local ops and
+    /// checked on ingest, and what to do with bundles that need newer ones.
+    pub fn set_module_registry(&mut self, modules: ModuleRegistry) {
+        self.modules = modules;
+    }
+
+    pub fn module_registry(&self) -> &ModuleRegistry {
+        &self.modules
+    }
+
+    /// Declare support for `module` up to `version`.
+    pub fn declare_module(&mut self, module: impl Into<String>, version: impl Into<String>) {
+        self.modules.declare(module, version);
+    }
+}
@@ -1,14 +1,23 @@
+use std::collections::BTreeMap;
+
 use openprod_core::{
     field_value::FieldValue,
     hlc::Hlc,
     ids::*,
-    operations::OperationPayload,
+    operations::{BundleType, OperationPayload},
 };
+use openprod_storage::{EngineStorage, OverlayOrigin};
+
+use crate::Engine;
+use crate::error::EngineError;
+use crate::ingest::FieldChange;
+use crate::sandbox::{SandboxDiff, SandboxHandle};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OverlaySource {
     User,
     Script,
+    Import,
 }
 
 impl OverlaySource {
@@ -16,6 +25,25 @@ impl OverlaySource {
         match self {
             Self::User => "user",
             Self::Script => "script",
+            Self::Import => "import",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "user" => Some(Self::User),
+            "script" => Some(Self::Script),
+            "import" => Some(Self::Import),
+            _ => None,
+        }
+    }
+
+    /// The type of the bundle the overlay's ops land in when committed.
+    pub fn bundle_type(&self) -> BundleType {
+        match self {
+            Self::User => BundleType::UserEdit,
+            Self::Script => BundleType::ScriptOutput,
+            Self::Import => BundleType::Import,
         }
     }
 }
@@ -39,6 +67,43 @@ impl OverlayStatus {
     }
 }
 
+/// What happens when canonical changes under a field an overlay wrote.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DriftPolicy {
+    /// Flag the field and block commit until the user resolves it.
+    #[default]
+    Block,
+    /// Keep the overlay's value, as `Engine::acknowledge_drift` would.
+    AutoKeepMine,
+    /// Drop the overlay's write, as `Engine::knockout_field` would.
+    AutoUseCanonical,
+    /// Resolve only when no choice is involved: drop the write if the
+    /// overlay left the value as it found it, keep it if canonical now
+    /// agrees with it, and otherwise block.
+    AutoRebaseIfUnchanged,
+}
+
+impl DriftPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::AutoKeepMine => "keep_mine",
+            Self::AutoUseCanonical => "use_canonical",
+            Self::AutoRebaseIfUnchanged => "rebase_if_unchanged",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "block" => Some(Self::Block),
+            "keep_mine" => Some(Self::AutoKeepMine),
+            "use_canonical" => Some(Self::AutoUseCanonical),
+            "rebase_if_unchanged" => Some(Self::AutoRebaseIfUnchanged),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct OverlayRecord {
     pub overlay_id: OverlayId,
@@ -126,3 +191,597 @@ impl OverlayManager {
         self.overlay_redo_stack.pop()
     }
 }
+
+impl<S: EngineStorage> Engine<S> {
+    // ========================================================================
+    // Overlay Lifecycle
+    // ========================================================================
+
+    /// Create a new overlay and make it active.
+    /// If another overlay is currently active, it is auto-stashed.
+    pub fn create_overlay(&mut self, name: &str) -> Result<OverlayId, EngineError> {
+        self.create_overlay_with_source(name, OverlaySource::User, OverlayOrigin::default())
+    }
+
+    /// Create an overlay for a script run or import and make it active, so
+    /// the run's writes are staged there. `origin` records what produced it
+    /// and whether `finish_overlay` commits it on success.
+    pub fn create_overlay_with_source(
+        &mut self,
+        name: &str,
+        source: OverlaySource,
+        origin: OverlayOrigin,
+    ) -> Result<OverlayId, EngineError> {
+        // Auto-stash current active overlay
+        if let Some(current) = self.overlay_manager.active_overlay_id() {
+            self.set_stashed(current)?;
+        }
+
+        let overlay_id = OverlayId::new();
+        let hlc = self.tick()?;
+        self.storage.begin_transaction()?;
+        let result = (|| -> Result<(), EngineError> {
+            self.storage.insert_overlay(overlay_id, name, source.as_str(), OverlayStatus::Active.as_str(), &hlc)?;
+            if origin != OverlayOrigin::default() {
+                self.storage.set_overlay_origin(overlay_id, &origin)?;
+            }
+            Ok(())
+        })();
+        match result {
+            Ok(()) => self.storage.commit_transaction()?,
+            Err(e) => {
+                let _ = self.rollback_transaction();
+                return Err(e);
+            }
+        }
+        self.overlay_manager.set_active(Some(overlay_id));
+        self.changes.mark_all();
+        self.notify_watchers();
+        Ok(overlay_id)
+    }
+
+    /// Activate an existing overlay (must be stashed).
+    /// If another overlay is currently active, it is auto-stashed.
+    pub fn activate_overlay(&mut self, overlay_id: OverlayId) -> Result<(), EngineError> {
+        let overlay = self.storage.get_overlay(overlay_id)?
+            .ok_or_else(|| EngineError::OverlayNotFound(overlay_id.to_string()))?;
+        let (_id, _name, _source, status, _created, _updated) = overlay;
+        if status != OverlayStatus::Stashed.as_str() {
+            return Err(EngineError::OverlayNotFound(
+                format!("overlay {} is not stashed (status: {})", overlay_id, status),
+            ));
+        }
+
+        // Auto-stash current active overlay
+        if let Some(current) = self.overlay_manager.active_overlay_id() {
+            self.set_stashed(current)?;
+        }
+
+        let hlc = self.tick()?;
+        self.storage.update_overlay_status(overlay_id, OverlayStatus::Active.as_str(), &hlc)?;
+        self.overlay_manager.set_active(Some(overlay_id));
+        self.changes.mark_all();
+        self.notify_watchers();
+        Ok(())
+    }
+
+    /// Stash an overlay (deactivate without discarding).
+    pub fn stash_overlay(&mut self, overlay_id: OverlayId) -> Result<(), EngineError> {
+        self.set_stashed(overlay_id)?;
+        self.changes.mark_all();
+        self.notify_watchers();
+        Ok(())
+    }
+
+    /// `stash_overlay` without notifying watchers, for switching overlays in one step.
+    fn set_stashed(&mut self, overlay_id: OverlayId) -> Result<(), EngineError> {
+        let hlc = self.tick()?;
+        self.storage.update_overlay_status(overlay_id, OverlayStatus::Stashed.as_str(), &hlc)?;
+        if self.overlay_manager.active_overlay_id() == Some(overlay_id) {
+            self.overlay_manager.set_active(None);
+        }
+        Ok(())
+    }
+
+    /// Discard an overlay — removes all overlay ops and the overlay record.
+    pub fn discard_overlay(&mut self, overlay_id: OverlayId) -> Result<(), EngineError> {
+        self.storage.delete_overlay(overlay_id)?;
+        if self.overlay_manager.active_overlay_id() == Some(overlay_id) {
+            self.overlay_manager.set_active(None);
+        }
+        self.changes.mark_all();
+        self.notify_watchers();
+        Ok(())
+    }
+
+    /// Reconcile the in-memory active overlay with the overlays table on startup.
+    /// At most one overlay may be active: if the table has several (e.g. a crash
+    /// mid-switch), the most recently created stays active and the rest are stashed.
+    /// Overlay undo/redo stacks are in-memory only and start empty.
+    pub(crate) fn restore_active_overlay(&mut self) -> Result<(), EngineError> {
+        let mut active = self.storage.list_overlays_by_status(OverlayStatus::Active.as_str())?;
+        if let Some((overlay_id, _name, _source, _created)) = active.pop() {
+            for (stale_id, _name, _source, _created) in active {
+                self.stash_overlay(stale_id)?;
+            }
+            self.overlay_manager.set_active(Some(overlay_id));
+        }
+        Ok(())
+    }
+
+    /// Get the currently active overlay ID, if any.
+    pub fn active_overlay(&self) -> Option<OverlayId> {
+        self.overlay_manager.active_overlay_id()
+    }
+
+    /// List stashed overlays.
+    pub fn stashed_overlays(&self) -> Result<Vec<(OverlayId, String)>, EngineError> {
+        let raw = self.storage.list_overlays_by_status(OverlayStatus::Stashed.as_str())?;
+        Ok(raw.into_iter().map(|(id, name, _source, _created)| (id, name)).collect())
+    }
+
+    /// List the open (active or stashed) overlays from one source, oldest
+    /// first, e.g. to review what scripts and imports have staged.
+    pub fn overlays_by_source(&self, source: OverlaySource) -> Result<Vec<(OverlayId, String)>, EngineError> {
+        let mut overlays = self.storage.list_overlays_by_status(OverlayStatus::Active.as_str())?;
+        overlays.extend(self.storage.list_overlays_by_status(OverlayStatus::Stashed.as_str())?);
+        overlays.sort_by_key(|overlay| overlay.3);
+        Ok(overlays
+            .into_iter()
+            .filter(|(_id, _name, overlay_source, _created)| overlay_source == source.as_str())
+            .map(|(id, name, _source, _created)| (id, name))
+            .collect())
+    }
+
+    /// Where an overlay came from.
+    pub fn overlay_source(&self, overlay_id: OverlayId) -> Result<OverlaySource, EngineError> {
+        let (_id, _name, source, _status, _created, _updated) = self.storage.get_overlay(overlay_id)?
+            .ok_or_else(|| EngineError::OverlayNotFound(overlay_id.to_string()))?;
+        Ok(OverlaySource::parse(&source).unwrap_or(OverlaySource::User))
+    }
+
+    /// The script and run that produced an overlay, and whether it commits
+    /// itself when the run succeeds.
+    pub fn overlay_origin(&self, overlay_id: OverlayId) -> Result<OverlayOrigin, EngineError> {
+        self.storage.get_overlay_origin(overlay_id)?
+            .ok_or_else(|| EngineError::OverlayNotFound(overlay_id.to_string()))
+    }
+
+    /// Attach the generating script and run to an overlay, or change whether
+    /// it auto-commits.
+    pub fn set_overlay_origin(&mut self, overlay_id: OverlayId, origin: OverlayOrigin) -> Result<(), EngineError> {
+        self.overlay_origin(overlay_id)?;
+        self.storage.set_overlay_origin(overlay_id, &origin)?;
+        Ok(())
+    }
+
+    /// Wrap up the overlay a script run or import staged its writes in.
+    /// A successful run of an auto-commit overlay is committed and its bundle
+    /// returned. Otherwise — a failed run, no auto-commit, or drift that
+    /// blocks the commit — the overlay is stashed for review and `None`
+    /// returned. An overlay the run left empty is discarded.
+    pub fn finish_overlay(&mut self, overlay_id: OverlayId, succeeded: bool) -> Result<Option<BundleId>, EngineError> {
+        let origin = self.overlay_origin(overlay_id)?;
+        if self.storage.count_overlay_ops(overlay_id)? == 0 {
+            self.discard_overlay(overlay_id)?;
+            return Ok(None);
+        }
+        if succeeded && origin.auto_commit && self.storage.count_unresolved_drift(overlay_id)? == 0 {
+            return self.commit_overlay(overlay_id).map(Some);
+        }
+        self.stash_overlay(overlay_id)?;
+        Ok(None)
+    }
+
+    /// Run `f` against a temporary overlay and report what it would change,
+    /// leaving canonical state alone. On success the overlay is stashed and
+    /// the diff names it: pass it to `commit_overlay` to apply the run, or to
+    /// `discard_overlay` to drop it. If `f` fails the overlay is discarded.
+    /// Whichever overlay was active beforehand is active again afterwards.
+    pub fn run_sandboxed<T>(
+        &mut self,
+        f: impl FnOnce(&mut SandboxHandle<'_, S>) -> Result<T, EngineError>,
+    ) -> Result<(T, SandboxDiff), EngineError> {
+        let previous = self.overlay_manager.active_overlay_id();
+        let overlay_id = self.create_overlay_with_source("sandbox", OverlaySource::Script, OverlayOrigin::default())?;
+        let result = f(&mut SandboxHandle::new(self, overlay_id));
+        let outcome = result.and_then(|value| {
+            self.set_stashed(overlay_id)?;
+            Ok((value, self.sandbox_diff(overlay_id)?))
+        });
+        if outcome.is_err() {
+            self.discard_overlay(overlay_id)?;
+        }
+        match previous {
+            Some(previous) => self.activate_overlay(previous)?,
+            None => {
+                self.changes.mark_all();
+                self.notify_watchers();
+            }
+        }
+        outcome
+    }
+
+    /// Summarize a sandbox overlay's ops against canonical state.
+    fn sandbox_diff(&self, overlay_id: OverlayId) -> Result<SandboxDiff, EngineError> {
+        let ops = self.storage.get_overlay_ops(overlay_id)?;
+        let mut diff = SandboxDiff {
+            overlay_id,
+            field_changes: Vec::new(),
+            entities_created: Vec::new(),
+            entities_deleted: Vec::new(),
+            op_count: ops.len(),
+        };
+        let mut changed: BTreeMap<(EntityId, String), usize> = BTreeMap::new();
+        for (_rowid, _op_id, _hlc, payload_bytes, _entity_id, _op_type, _canon, _drifted, _field_key) in &ops {
+            let (entity_id, field_key, new_value) = match OperationPayload::from_msgpack(payload_bytes)? {
+                OperationPayload::SetField { entity_id, field_key, value } => (entity_id, field_key, Some(value)),
+                OperationPayload::ClearField { entity_id, field_key } => (entity_id, field_key, None),
+                OperationPayload::CreateEntity { entity_id, .. } => {
+                    diff.entities_created.push(entity_id);
+                    continue;
+                }
+                OperationPayload::DeleteEntity { entity_id, .. } => {
+                    diff.entities_deleted.push(entity_id);
+                    continue;
+                }
+                _ => continue,
+            };
+            match changed.get(&(entity_id, field_key.clone())) {
+                Some(&i) => diff.field_changes[i].new_value = new_value,
+                None => {
+                    let old_value = self.storage.get_field(entity_id, &field_key)?;
+                    changed.insert((entity_id, field_key.clone()), diff.field_changes.len());
+                    diff.field_changes.push(FieldChange { entity_id, field_key, old_value, new_value });
+                }
+            }
+        }
+        diff.field_changes.retain(|change| change.old_value != change.new_value);
+        Ok(diff)
+    }
+
+    /// Undo the most recent operation in the active overlay.
+    /// Removes the op from overlay_ops and pushes to overlay redo stack.
+    pub fn overlay_undo(&mut self) -> Result<bool, EngineError> {
+        let overlay_id = self.overlay_manager.active_overlay_id()
+            .ok_or(EngineError::NoActiveOverlay)?;
+
+        let op = match self.overlay_manager.pop_overlay_undo() {
+            Some(op) => op,
+            None => return Ok(false),
+        };
+
+        self.storage.delete_overlay_op(op.rowid)?;
+        self.record_changes([&op.payload]);
+        self.overlay_manager.push_overlay_redo(op);
+        // Verify overlay_id matches (should always be true for active overlay)
+        let _ = overlay_id;
+        self.notify_watchers();
+        Ok(true)
+    }
+
+    /// Redo the most recently undone overlay operation.
+    /// Re-inserts the op into overlay_ops.
+    pub fn overlay_redo(&mut self) -> Result<bool, EngineError> {
+        let overlay_id = self.overlay_manager.active_overlay_id()
+            .ok_or(EngineError::NoActiveOverlay)?;
+
+        let mut op = match self.overlay_manager.pop_overlay_redo() {
+            Some(op) => op,
+            None => return Ok(false),
+        };
+
+        let payload_bytes = op.payload.to_msgpack()?;
+        let rowid = self.storage.insert_overlay_op(
+            overlay_id,
+            op.op_id,
+            &op.hlc,
+            &payload_bytes,
+            op.entity_id,
+            op.field_key.as_deref(),
+            &op.op_type,
+            op.canonical_value_at_creation.as_deref(),
+        )?;
+        op.rowid = rowid;
+        self.record_changes([&op.payload]);
+        self.overlay_manager.push_overlay_undo(op);
+        self.notify_watchers();
+        Ok(true)
+    }
+
+    // ========================================================================
+    // Overlay Commit & Canonical Drift
+    // ========================================================================
+
+    /// Scan all active/stashed overlays for drift on the given modified fields.
+    /// Called after canonical state changes (ingest_bundle, commit_overlay).
+    /// Flag overlay ops on `modified_fields` as drifted, then let each
+    /// overlay's `DriftPolicy` resolve what it can. Returns how many newly
+    /// flagged ops were left for the user.
+    pub(crate) fn scan_overlay_drift(&mut self, modified_fields: &[(EntityId, String)]) -> Result<u64, EngineError> {
+        let mut flagged = 0;
+        for (entity_id, field_key) in modified_fields {
+            flagged += self.storage.mark_overlay_ops_drifted(*entity_id, field_key)?;
+        }
+        if flagged > 0 {
+            let mut overlays = self.storage.list_overlays_by_status(OverlayStatus::Active.as_str())?;
+            overlays.extend(self.storage.list_overlays_by_status(OverlayStatus::Stashed.as_str())?);
+            for (overlay_id, ..) in overlays {
+                flagged = flagged.saturating_sub(self.apply_drift_policy(overlay_id)?);
+            }
+        }
+        Ok(flagged)
+    }
+
+    /// Resolve an overlay's drifted fields as its `DriftPolicy` says.
+    /// Returns how many drifted ops were resolved.
+    fn apply_drift_policy(&mut self, overlay_id: OverlayId) -> Result<u64, EngineError> {
+        let policy = self.drift_policy(overlay_id)?;
+        if policy == DriftPolicy::Block {
+            return Ok(0);
+        }
+        let drifted_before = self.storage.count_unresolved_drift(overlay_id)?;
+        for (_rowid, _op_id, _hlc, payload_bytes, _entity_id, _op_type, canon_bytes, _drifted, _field_key)
+            in self.storage.get_drifted_overlay_ops(overlay_id)?
+        {
+            let (entity_id, field_key, overlay_value) = match OperationPayload::from_msgpack(&payload_bytes)? {
+                OperationPayload::SetField { entity_id, field_key, value, .. } => (entity_id, field_key, Some(value)),
+                OperationPayload::ClearField { entity_id, field_key } => (entity_id, field_key, None),
+                _ => continue,
+            };
+            let keep_mine = match policy {
+                DriftPolicy::Block => continue,
+                DriftPolicy::AutoKeepMine => true,
+                DriftPolicy::AutoUseCanonical => false,
+                DriftPolicy::AutoRebaseIfUnchanged => {
+                    let baseline = canon_bytes.as_deref().map(FieldValue::from_msgpack).transpose()
+                        .map_err(|e| EngineError::Core(openprod_core::CoreError::Serialization(e.to_string())))?;
+                    if overlay_value == self.storage.get_field(entity_id, &field_key)? {
+                        true
+                    } else if overlay_value == baseline {
+                        false
+                    } else {
+                        continue;
+                    }
+                }
+            };
+            if keep_mine {
+                self.acknowledge_drift(overlay_id, entity_id, &field_key)?;
+            } else {
+                self.storage.delete_overlay_ops_for_field(overlay_id, entity_id, &field_key)?;
+                self.changes.mark_all();
+            }
+        }
+        Ok(drifted_before - self.storage.count_unresolved_drift(overlay_id)?)
+    }
+
+    /// Commit an overlay — atomically move all overlay ops to canonical storage.
+    /// Returns the BundleId of the committed bundle.
+    /// Fails if there is unresolved drift.
+    pub fn commit_overlay(&mut self, overlay_id: OverlayId) -> Result<BundleId, EngineError> {
+        // Check for unresolved drift
+        let drift_count = self.storage.count_unresolved_drift(overlay_id)?;
+        if drift_count > 0 {
+            return Err(EngineError::UnresolvedDrift(
+                format!("{} drifted field(s) on overlay {}", drift_count, overlay_id),
+            ));
+        }
+
+        // Read all overlay ops ordered by rowid
+        let overlay_ops = self.storage.get_overlay_ops(overlay_id)?;
+        if overlay_ops.is_empty() {
+            // Empty overlay — just discard
+            self.discard_overlay(overlay_id)?;
+            return Err(EngineError::EmptyOverlay(
+                format!("overlay {} has no ops to commit", overlay_id),
+            ));
+        }
+
+        // Deserialize payloads
+        let mut payloads = Vec::new();
+        for (_rowid, _op_id, _hlc, payload_bytes, _entity_id, _op_type, _canon, _drifted, _field_key) in &overlay_ops {
+            let payload = OperationPayload::from_msgpack(payload_bytes)?;
+            payloads.push(payload);
+        }
+
+        // Collect modified fields for drift scanning
+        let modified_fields: Vec<(EntityId, String)> = payloads.iter().filter_map(|p| {
+            match p {
+                OperationPayload::SetField { entity_id, field_key, .. }
+                | OperationPayload::ClearField { entity_id, field_key } => {
+                    Some((*entity_id, field_key.clone()))
+                }
+                _ => None,
+            }
+        }).collect();
+
+        // Script and import overlays commit as their own bundle types
+        let bundle_type = self.overlay_source(overlay_id)?.bundle_type();
+
+        // Deactivate overlay to avoid routing the execute_internal call back to overlay
+        if self.overlay_manager.active_overlay_id() == Some(overlay_id) {
+            self.overlay_manager.set_active(None);
+        }
+
+        // Wrap commit in transaction for atomicity
+        self.storage.begin_transaction()?;
+
+        let result = (|| -> Result<BundleId, EngineError> {
+            // Execute as canonical (non-undoable)
+            let (bundle_id, _hlc) = self.execute_internal(bundle_type, payloads, false)?;
+
+            // Update overlay status to committed
+            let hlc = self.tick()?;
+            self.storage.update_overlay_status(overlay_id, OverlayStatus::Committed.as_str(), &hlc)?;
+
+            // Scan for drift on stashed overlays
+            self.scan_overlay_drift(&modified_fields)?;
+
+            Ok(bundle_id)
+        })();
+
+        match result {
+            Ok(bundle_id) => {
+                self.storage.commit_transaction()?;
+                Ok(bundle_id)
+            }
+            Err(e) => {
+                let _ = self.rollback_transaction();
+                // Watchers saw the commit's write before it was rolled back
+                self.changes.mark_all();
+                self.notify_watchers();
+                Err(e)
+            }
+        }
+    }
+
+    /// Check for drifted fields on an overlay.
+    /// Returns a list of DriftRecord entries showing overlay vs canonical values.
+    pub fn check_drift(&self, overlay_id: OverlayId) -> Result<Vec<DriftRecord>, EngineError> {
+        let drifted_ops = self.storage.get_drifted_overlay_ops(overlay_id)?;
+        let mut records = Vec::new();
+
+        for (_rowid, _op_id, _hlc, payload_bytes, _entity_id_bytes, _op_type, _canon_bytes, _drifted, _field_key) in &drifted_ops {
+            let payload = OperationPayload::from_msgpack(payload_bytes)?;
+            match payload {
+                OperationPayload::SetField { entity_id, field_key, value, .. } => {
+                    let canonical_value = self.storage.get_field(entity_id, &field_key)?;
+                    records.push(DriftRecord {
+                        entity_id,
+                        field_key,
+                        overlay_value: Some(value),
+                        canonical_value,
+                    });
+                }
+                OperationPayload::ClearField { entity_id, field_key } => {
+                    let canonical_value = self.storage.get_field(entity_id, &field_key)?;
+                    records.push(DriftRecord {
+                        entity_id,
+                        field_key,
+                        overlay_value: None,
+                        canonical_value,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Acknowledge drift on a field — "Keep Mine".
+    /// Clears the drift flag and updates canonical_value_at_creation to new canonical value.
+    pub fn acknowledge_drift(
+        &mut self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<(), EngineError> {
+        // Get current canonical value for this field
+        let canonical_value = match self.storage.get_field(entity_id, field_key)? {
+            Some(v) => {
+                let bytes = v.to_msgpack()
+                    .map_err(|e| EngineError::Core(openprod_core::CoreError::Serialization(e.to_string())))?;
+                Some(bytes)
+            }
+            None => None,
+        };
+
+        self.storage.update_canonical_value_at_creation(overlay_id, entity_id, field_key, canonical_value.as_deref())?;
+        self.storage.clear_drift_flag(overlay_id, entity_id, field_key)?;
+        Ok(())
+    }
+
+    /// Knockout a field from the overlay — "Use Canonical".
+    /// Removes the overlay op for this field, so it falls through to canonical.
+    pub fn knockout_field(
+        &mut self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<(), EngineError> {
+        self.storage.delete_overlay_ops_for_field(overlay_id, entity_id, field_key)?;
+        self.changes.mark_all();
+        self.notify_watchers();
+        Ok(())
+    }
+
+    /// Resolve drift on a field with a merge of both sides — "Merge".
+    /// Replaces the overlay's write to the field with `merged_value` (`None`
+    /// clears the field) and takes the current canonical value as the new
+    /// baseline, so the field is no longer drifted.
+    pub fn merge_drift(
+        &mut self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+        merged_value: Option<FieldValue>,
+    ) -> Result<(), EngineError> {
+        let field_key = field_key.to_string();
+        let payload = match merged_value {
+            Some(value) => OperationPayload::SetField { entity_id, field_key: field_key.clone(), value },
+            None => OperationPayload::ClearField { entity_id, field_key: field_key.clone() },
+        };
+        self.check_permission(self.actor_id(), None, [&payload].into_iter())?;
+        let canonical_value = match self.storage.get_field(entity_id, &field_key)? {
+            Some(v) => Some(v.to_msgpack()
+                .map_err(|e| EngineError::Core(openprod_core::CoreError::Serialization(e.to_string())))?),
+            None => None,
+        };
+        let payload_bytes = payload.to_msgpack()?;
+        let hlc = self.tick()?;
+
+        self.storage.begin_transaction()?;
+        let result = (|| -> Result<(), EngineError> {
+            if self.storage.delete_overlay_ops_for_field(overlay_id, entity_id, &field_key)? == 0 {
+                return Err(EngineError::OverlayNotFound(
+                    format!("overlay {} has no write to {}.{}", overlay_id, entity_id, field_key),
+                ));
+            }
+            self.storage.insert_overlay_op(
+                overlay_id,
+                OpId::new(),
+                &hlc,
+                &payload_bytes,
+                Some(entity_id),
+                Some(&field_key),
+                payload.op_type_name(),
+                canonical_value.as_deref(),
+            )?;
+            Ok(())
+        })();
+        match result {
+            Ok(()) => self.storage.commit_transaction()?,
+            Err(e) => {
+                let _ = self.rollback_transaction();
+                return Err(e);
+            }
+        }
+        self.record_changes([&payload]);
+        self.notify_watchers();
+        Ok(())
+    }
+
+    /// How canonical changes under an overlay's writes are handled.
+    pub fn drift_policy(&self, overlay_id: OverlayId) -> Result<DriftPolicy, EngineError> {
+        let policy = self.storage.get_overlay_drift_policy(overlay_id)?
+            .ok_or_else(|| EngineError::OverlayNotFound(overlay_id.to_string()))?;
+        Ok(DriftPolicy::parse(&policy).unwrap_or_default())
+    }
+
+    /// Set an overlay's drift policy, e.g. `AutoUseCanonical` for a script's
+    /// overlay nobody will be around to acknowledge. Fields already drifted
+    /// are handled under the new policy right away.
+    pub fn set_drift_policy(&mut self, overlay_id: OverlayId, policy: DriftPolicy) -> Result<(), EngineError> {
+        self.drift_policy(overlay_id)?;
+        self.storage.set_overlay_drift_policy(overlay_id, policy.as_str())?;
+        self.apply_drift_policy(overlay_id)?;
+        self.notify_watchers();
+        Ok(())
+    }
+
+    /// Check if an overlay has any unresolved drift.
+    pub fn has_unresolved_drift(&self, overlay_id: OverlayId) -> Result<bool, EngineError> {
+        Ok(self.storage.count_unresolved_drift(overlay_id)? > 0)
+    }
+}
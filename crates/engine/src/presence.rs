@@ -0,0 +1,261 @@
+//! Ephemeral "who is editing what" hints, for `Engine::announce_editing`,
+//! and `PresenceTracker` for the awareness messages defined in core.
+//!
+//! Neither touches storage or the oplog: state lives in memory, expires on
+//! its own, and is lost on restart. Nothing is signed either, so treat it as
+//! advice for the UI, never as a lock that gates writes.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use openprod_core::{
+    ids::{ActorId, EntityId},
+    presence::{CursorPosition, OnlineStatus, PresenceMessage, PresenceState},
+};
+use openprod_storage::EngineStorage;
+use serde::{Deserialize, Serialize};
+
+use crate::Engine;
+use crate::error::EngineError;
+
+/// How long `PresenceTracker` keeps an actor that has gone quiet.
+pub const DEFAULT_PRESENCE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An actor's announcement that it is editing an entity, or one field of it,
+/// as sent between peers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceHint {
+    pub actor_id: ActorId,
+    pub entity_id: EntityId,
+    /// None when the whole entity is being edited.
+    pub field_key: Option<String>,
+    /// How long the hint holds once received; 0 withdraws it. Relative, so
+    /// peers with skewed clocks still agree roughly on when it lapses.
+    pub ttl_ms: u64,
+    /// Increases with every hint the actor sends. A receiver drops hints no
+    /// newer than the last one it applied from that actor, so relayed copies
+    /// can't loop or arrive out of order.
+    pub seq: u64,
+}
+
+/// Someone currently editing an entity, as `Engine::current_editors` reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditorPresence {
+    pub actor_id: ActorId,
+    pub field_key: Option<String>,
+    /// Local wall-clock ms at which the hint lapses unless renewed.
+    pub expires_at: u64,
+}
+
+/// Live hints, keyed by who is editing what.
+#[derive(Debug, Default)]
+pub(crate) struct PresenceTable {
+    hints: HashMap<(ActorId, EntityId, Option<String>), u64>,
+    /// Newest `seq` applied per actor; kept after hints lapse.
+    last_seq: HashMap<ActorId, u64>,
+}
+
+impl PresenceTable {
+    /// The `seq` for this actor's next hint: past anything it sent before,
+    /// and past the last run's hints too, since it starts from the clock.
+    pub(crate) fn next_seq(&self, actor_id: ActorId, now_ms: u64) -> u64 {
+        next_seq(self.last_seq.get(&actor_id).copied().unwrap_or(0), now_ms)
+    }
+
+    /// Apply a hint. False if it's stale and was ignored.
+    pub(crate) fn apply(&mut self, hint: &PresenceHint, now_ms: u64) -> bool {
+        let last = self.last_seq.entry(hint.actor_id).or_insert(0);
+        if hint.seq <= *last {
+            return false;
+        }
+        *last = hint.seq;
+        self.hints.retain(|_, expires_at| *expires_at > now_ms);
+        let key = (hint.actor_id, hint.entity_id, hint.field_key.clone());
+        if hint.ttl_ms == 0 {
+            self.hints.remove(&key);
+        } else {
+            self.hints.insert(key, now_ms.saturating_add(hint.ttl_ms));
+        }
+        true
+    }
+
+    /// Unexpired hints on `entity_id` from anyone but `except`, ordered by
+    /// actor then field.
+    pub(crate) fn editors(&self, entity_id: EntityId, except: ActorId, now_ms: u64) -> Vec<EditorPresence> {
+        let mut editors: Vec<EditorPresence> = self
+            .hints
+            .iter()
+            .filter(|((actor_id, id, _), expires_at)| *id == entity_id && *actor_id != except && **expires_at > now_ms)
+            .map(|((actor_id, _, field_key), expires_at)| EditorPresence {
+                actor_id: *actor_id,
+                field_key: field_key.clone(),
+                expires_at: *expires_at,
+            })
+            .collect();
+        editors.sort_by(|a, b| (a.actor_id, &a.field_key).cmp(&(b.actor_id, &b.field_key)));
+        editors
+    }
+}
+
+/// Latest awareness state per actor, for an app to render who is around.
+/// It doesn't touch the engine: feed it the `PresenceMessage`s the transport
+/// delivers, and send the ones `update` returns. Times are wall-clock ms.
+#[derive(Debug)]
+pub struct PresenceTracker {
+    actor_id: ActorId,
+    timeout_ms: u64,
+    /// Seq of this actor's last message.
+    seq: u64,
+    /// Per remote actor: last seq applied, its state, and when it arrived.
+    remotes: HashMap<ActorId, (u64, PresenceState, u64)>,
+}
+
+impl PresenceTracker {
+    /// A tracker for `actor_id` that forgets remote actors `timeout` after
+    /// their last message; see `DEFAULT_PRESENCE_TIMEOUT`.
+    pub fn new(actor_id: ActorId, timeout: Duration) -> Self {
+        Self {
+            actor_id,
+            timeout_ms: u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX),
+            seq: 0,
+            remotes: HashMap::new(),
+        }
+    }
+
+    pub fn actor_id(&self) -> ActorId {
+        self.actor_id
+    }
+
+    /// The message announcing this actor's new state, to send to peers.
+    /// Resend it periodically as a heartbeat, or peers time the actor out.
+    pub fn update(&mut self, state: PresenceState, now_ms: u64) -> PresenceMessage {
+        self.seq = next_seq(self.seq, now_ms);
+        PresenceMessage { actor_id: self.actor_id, seq: self.seq, state }
+    }
+
+    /// Apply a peer's message. False if it was stale (or our own) and ignored;
+    /// a hub relays only the messages this accepts.
+    pub fn apply(&mut self, message: &PresenceMessage, now_ms: u64) -> bool {
+        if message.actor_id == self.actor_id {
+            return false;
+        }
+        if let Some((seq, _, _)) = self.remotes.get(&message.actor_id)
+            && message.seq <= *seq
+        {
+            return false;
+        }
+        self.remotes.insert(message.actor_id, (message.seq, message.state.clone(), now_ms));
+        true
+    }
+
+    /// An actor's state, if it's online (or away) and not timed out.
+    pub fn state(&self, actor_id: ActorId, now_ms: u64) -> Option<&PresenceState> {
+        let (_, state, seen_at) = self.remotes.get(&actor_id)?;
+        let live = state.status != OnlineStatus::Offline && now_ms.saturating_sub(*seen_at) < self.timeout_ms;
+        live.then_some(state)
+    }
+
+    /// Actors currently online or away, in id order.
+    pub fn online(&self, now_ms: u64) -> Vec<ActorId> {
+        let mut actors: Vec<ActorId> =
+            self.remotes.keys().copied().filter(|actor_id| self.state(*actor_id, now_ms).is_some()).collect();
+        actors.sort();
+        actors
+    }
+
+    /// Actors with `entity_id` selected, in id order.
+    pub fn selecting(&self, entity_id: EntityId, now_ms: u64) -> Vec<ActorId> {
+        self.online(now_ms)
+            .into_iter()
+            .filter(|actor_id| self.state(*actor_id, now_ms).is_some_and(|s| s.selected_entity == Some(entity_id)))
+            .collect()
+    }
+
+    /// Cursors placed in `entity_id`'s fields, in actor id order.
+    pub fn cursors_in(&self, entity_id: EntityId, now_ms: u64) -> Vec<(ActorId, &CursorPosition)> {
+        self.online(now_ms)
+            .into_iter()
+            .filter_map(|actor_id| {
+                let cursor = self.state(actor_id, now_ms)?.cursor.as_ref()?;
+                (cursor.entity_id == entity_id).then_some((actor_id, cursor))
+            })
+            .collect()
+    }
+
+    /// Forget actors that went offline or timed out.
+    pub fn prune(&mut self, now_ms: u64) {
+        let timeout_ms = self.timeout_ms;
+        self.remotes.retain(|_, (_, state, seen_at)| {
+            state.status != OnlineStatus::Offline && now_ms.saturating_sub(*seen_at) < timeout_ms
+        });
+    }
+}
+
+/// The seq after `last`: strictly greater, and at least the wall clock, so
+/// a restarted sender doesn't start again below what peers last saw from it.
+fn next_seq(last: u64, now_ms: u64) -> u64 {
+    (last + 1).max(now_ms)
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Tell peers this actor is editing `entity_id` (or just `field_key` of
+    /// it) for the next `ttl`, so their UIs can warn before a conflicting
+    /// edit. Announce again before it lapses to keep it up. Nothing is
+    /// written: the hint goes to `subscribe_presence` receivers, which a
+    /// transport forwards to its peers.
+    pub fn announce_editing(&mut self, entity_id: EntityId, field_key: Option<&str>, ttl: Duration) -> Result<(), EngineError> {
+        self.require_live_entity(entity_id)?;
+        let ttl_ms = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1);
+        self.send_presence(entity_id, field_key, ttl_ms)
+    }
+
+    /// Withdraw an `announce_editing` hint before its TTL runs out.
+    pub fn stop_editing(&mut self, entity_id: EntityId, field_key: Option<&str>) -> Result<(), EngineError> {
+        self.send_presence(entity_id, field_key, 0)
+    }
+
+    /// Apply a hint a peer sent. Returns false if it was stale and ignored;
+    /// otherwise it's passed on to `subscribe_presence` receivers, so a
+    /// server relays it to its other peers.
+    pub fn receive_presence(&mut self, hint: PresenceHint) -> Result<bool, EngineError> {
+        if !self.presence.apply(&hint, self.clock.now_ms()?) {
+            return Ok(false);
+        }
+        self.notify_presence(hint);
+        Ok(true)
+    }
+
+    /// Other actors editing `entity_id` right now, whole-entity hints with
+    /// `field_key` None. Lapsed hints are left out.
+    pub fn current_editors(&self, entity_id: EntityId) -> Result<Vec<EditorPresence>, EngineError> {
+        Ok(self.presence.editors(entity_id, self.actor_id(), self.clock.now_ms()?))
+    }
+
+    /// Every hint this actor sends and every new one it receives, for a
+    /// transport to forward. Dropped receivers are pruned on the next hint.
+    pub fn subscribe_presence(&mut self) -> Receiver<PresenceHint> {
+        let (sender, receiver) = mpsc::channel();
+        self.presence_listeners.push(sender);
+        receiver
+    }
+
+    fn send_presence(&mut self, entity_id: EntityId, field_key: Option<&str>, ttl_ms: u64) -> Result<(), EngineError> {
+        let now_ms = self.clock.now_ms()?;
+        let actor_id = self.actor_id();
+        let hint = PresenceHint {
+            actor_id,
+            entity_id,
+            field_key: field_key.map(str::to_string),
+            ttl_ms,
+            seq: self.presence.next_seq(actor_id, now_ms),
+        };
+        self.presence.apply(&hint, now_ms);
+        self.notify_presence(hint);
+        Ok(())
+    }
+
+    fn notify_presence(&mut self, hint: PresenceHint) {
+        self.presence_listeners.retain(|listener| listener.send(hint.clone()).is_ok());
+    }
+}
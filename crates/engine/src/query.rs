@@ -0,0 +1,71 @@
+use openprod_core::field_value::FieldValue;
+
+/// Live entities carrying a facet, optionally narrowed by field conditions
+/// (all must hold). Fields are read through the active overlay.
+///
+/// ```ignore
+/// let open = EntityQuery::facet("Task").field_eq("status", FieldValue::Text("open".into()));
+/// engine.watch_query(open)?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityQuery {
+    facet_type: String,
+    conditions: Vec<FieldCondition>,
+}
+
+/// A condition on one field of an [`EntityQuery`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldCondition {
+    Equals { field_key: String, value: FieldValue },
+    Present { field_key: String },
+    Absent { field_key: String },
+}
+
+impl EntityQuery {
+    /// Every live entity with `facet_type` attached.
+    pub fn facet(facet_type: impl Into<String>) -> Self {
+        Self { facet_type: facet_type.into(), conditions: Vec::new() }
+    }
+
+    /// Only entities whose `field_key` equals `value`.
+    pub fn field_eq(mut self, field_key: impl Into<String>, value: FieldValue) -> Self {
+        self.conditions.push(FieldCondition::Equals { field_key: field_key.into(), value });
+        self
+    }
+
+    /// Only entities with a value for `field_key`.
+    pub fn has_field(mut self, field_key: impl Into<String>) -> Self {
+        self.conditions.push(FieldCondition::Present { field_key: field_key.into() });
+        self
+    }
+
+    /// Only entities without a value for `field_key`.
+    pub fn lacks_field(mut self, field_key: impl Into<String>) -> Self {
+        self.conditions.push(FieldCondition::Absent { field_key: field_key.into() });
+        self
+    }
+
+    pub fn facet_type(&self) -> &str {
+        &self.facet_type
+    }
+
+    pub fn conditions(&self) -> &[FieldCondition] {
+        &self.conditions
+    }
+}
+
+impl FieldCondition {
+    pub fn field_key(&self) -> &str {
+        match self {
+            Self::Equals { field_key, .. } | Self::Present { field_key } | Self::Absent { field_key } => field_key,
+        }
+    }
+
+    pub fn matches(&self, value: Option<&FieldValue>) -> bool {
+        match self {
+            Self::Equals { value: expected, .. } => value == Some(expected),
+            Self::Present { .. } => value.is_some(),
+            Self::Absent { .. } => value.is_none(),
+        }
+    }
+}
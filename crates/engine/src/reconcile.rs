@@ -0,0 +1,114 @@
+use openprod_core::{digest::{HlcRange, RangeDigest, RangeMessage}, hlc::Hlc, ids::BundleId};
+use openprod_storage::EngineStorage;
+
+use crate::Engine;
+use crate::error::EngineError;
+
+/// Ranges mismatching on both sides are split into this many sub-ranges.
+pub const RECONCILE_FANOUT: usize = 8;
+
+/// Ranges holding at most this many local bundles are sent as explicit id lists.
+pub const RECONCILE_MAX_IDS: u64 = 16;
+
+/// Outcome of processing one round of range-reconciliation messages.
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileRound {
+    /// Messages to send back; empty when reconciliation is complete.
+    pub reply: Vec<RangeMessage>,
+    /// Bundles the remote holds that we lack (request these).
+    pub missing_locally: Vec<BundleId>,
+    /// Bundles we hold that the remote lacks (send these).
+    pub missing_remotely: Vec<BundleId>,
+}
+
+impl ReconcileRound {
+    pub fn is_done(&self) -> bool {
+        self.reply.is_empty()
+    }
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Digest of the bundles we hold in `range`.
+    pub fn range_digest(&self, range: HlcRange) -> Result<RangeDigest, EngineError> {
+        let ids = self.storage.get_bundle_ids_in_range(&range)?;
+        Ok(RangeDigest::new(range, ids.into_iter().map(|(_, bundle_id)| bundle_id)))
+    }
+
+    /// Opening message of a reconciliation: a digest of our whole oplog.
+    pub fn begin_reconcile(&self) -> Result<Vec<RangeMessage>, EngineError> {
+        Ok(vec![RangeMessage::Digest(self.range_digest(HlcRange::full())?)])
+    }
+
+    /// Process one round of messages from the remote peer.
+    ///
+    /// Matching digests are dropped. A mismatched range is answered with our id
+    /// list when it is small (or cannot be split), otherwise with digests of
+    /// `RECONCILE_FANOUT` sub-ranges split at our own bundle boundaries. Id lists
+    /// are diffed directly and need no reply. Feed each `reply` to the other
+    /// side until it is empty.
+    pub fn reconcile(&self, incoming: &[RangeMessage]) -> Result<ReconcileRound, EngineError> {
+        let mut round = ReconcileRound::default();
+        for message in incoming {
+            match message {
+                RangeMessage::Digest(remote) => {
+                    let local = self.storage.get_bundle_ids_in_range(&remote.range)?;
+                    let local_digest = RangeDigest::new(remote.range, local.iter().map(|(_, id)| *id));
+                    if local_digest.matches(remote) {
+                        continue;
+                    }
+                    if remote.count == 0 {
+                        round.missing_remotely.extend(local.iter().map(|(_, id)| *id));
+                        continue;
+                    }
+                    let sub_ranges = split_range(remote.range, &local);
+                    if local.len() as u64 <= RECONCILE_MAX_IDS || sub_ranges.len() < 2 {
+                        round.reply.push(RangeMessage::Ids {
+                            range: remote.range,
+                            bundle_ids: local.into_iter().map(|(_, id)| id).collect(),
+                        });
+                        continue;
+                    }
+                    for range in sub_ranges {
+                        let ids = local.iter().filter(|(hlc, _)| range.contains(*hlc)).map(|(_, id)| *id);
+                        round.reply.push(RangeMessage::Digest(RangeDigest::new(range, ids)));
+                    }
+                }
+                RangeMessage::Ids { range, bundle_ids } => {
+                    let local: Vec<BundleId> = self
+                        .storage
+                        .get_bundle_ids_in_range(range)?
+                        .into_iter()
+                        .map(|(_, id)| id)
+                        .collect();
+                    round.missing_locally.extend(bundle_ids.iter().filter(|id| !local.contains(id)));
+                    round.missing_remotely.extend(local.iter().filter(|id| !bundle_ids.contains(id)));
+                }
+            }
+        }
+        Ok(round)
+    }
+}
+
+/// Split `range` into up to `RECONCILE_FANOUT` sub-ranges holding roughly equal
+/// shares of `local` (sorted by HLC). Boundaries fall on distinct HLCs, so a
+/// range whose bundles all share one HLC cannot be split.
+fn split_range(range: HlcRange, local: &[(Hlc, BundleId)]) -> Vec<HlcRange> {
+    let mut boundaries: Vec<Hlc> = Vec::new();
+    for k in 1..RECONCILE_FANOUT {
+        let Some((hlc, _)) = local.get(k * local.len() / RECONCILE_FANOUT) else {
+            continue;
+        };
+        if *hlc > range.start && boundaries.last().is_none_or(|last| hlc > last) {
+            boundaries.push(*hlc);
+        }
+    }
+
+    let mut ranges = Vec::with_capacity(boundaries.len() + 1);
+    let mut start = range.start;
+    for boundary in boundaries {
+        ranges.push(HlcRange { start, end: Some(boundary) });
+        start = boundary;
+    }
+    ranges.push(HlcRange { start, end: range.end });
+    ranges
+}
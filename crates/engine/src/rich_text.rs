@@ -0,0 +1,168 @@
+//! Collaborative rich-text fields, for `Engine::apply_text_update` and
+//! `Engine::get_text_document`.
+//!
+//! A rich-text field is an Automerge document. Clients edit their own copy
+//! and send the changes (`AutoCommit::save_incremental`) as updates; each is
+//! stored as an `ApplyCrdt` op of type `RichText`, so documents sync in the
+//! same oplog as records. The merged document isn't materialized: it's built
+//! from the field's updates when read, in whatever order they arrived.
+
+use automerge::{Automerge, ChangeHash, ObjType, ROOT, ReadDoc, ScalarValue, Value};
+use openprod_core::{
+    hlc::Hlc,
+    ids::{BundleId, EntityId, OpId},
+    operations::{BundleType, CrdtType, OperationPayload},
+};
+use openprod_storage::EngineStorage;
+
+use crate::Engine;
+use crate::error::EngineError;
+use crate::system::check_user_field;
+
+/// Key of the text object in a rich-text document's root map.
+pub const TEXT_DOCUMENT_KEY: &str = "text";
+
+/// A rich-text field's document with every update merged.
+#[derive(Debug)]
+pub struct TextDocument {
+    doc: Automerge,
+    updates: usize,
+}
+
+/// A formatting span, e.g. `bold` over `start..end` (character offsets).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMark {
+    pub name: String,
+    pub value: ScalarValue,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl TextDocument {
+    /// Merge updates in any order. One that doesn't parse (only a faulty
+    /// peer could have sent it) is skipped rather than breaking the field.
+    pub(crate) fn merge<'a>(deltas: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        let mut doc = Automerge::new();
+        let mut updates = 0;
+        for delta in deltas {
+            if doc.load_incremental(delta).is_ok() {
+                updates += 1;
+            }
+        }
+        Self { doc, updates }
+    }
+
+    /// The document's text, or "" if no update has created it yet.
+    pub fn text(&self) -> Result<String, EngineError> {
+        match self.text_object()? {
+            Some(text) => self.doc.text(&text).map_err(|e| EngineError::InvalidDocument(e.to_string())),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// Formatting spans over the text, in order.
+    pub fn marks(&self) -> Result<Vec<TextMark>, EngineError> {
+        let Some(text) = self.text_object()? else {
+            return Ok(Vec::new());
+        };
+        let marks = self.doc.marks(&text).map_err(|e| EngineError::InvalidDocument(e.to_string()))?;
+        Ok(marks
+            .into_iter()
+            .map(|mark| TextMark { name: mark.name().to_string(), value: mark.value().clone(), start: mark.start, end: mark.end })
+            .collect())
+    }
+
+    /// The whole document, for a client to load (`AutoCommit::load`) and edit.
+    pub fn save(&self) -> Vec<u8> {
+        self.doc.save()
+    }
+
+    /// Heads of the merged document; a client that has these is up to date.
+    pub fn heads(&self) -> Vec<ChangeHash> {
+        self.doc.get_heads()
+    }
+
+    /// Updates merged into the document.
+    pub fn update_count(&self) -> usize {
+        self.updates
+    }
+
+    /// The underlying Automerge document, for anything beyond text and marks.
+    pub fn automerge(&self) -> &Automerge {
+        &self.doc
+    }
+
+    fn text_object(&self) -> Result<Option<automerge::ObjId>, EngineError> {
+        match self.doc.get(ROOT, TEXT_DOCUMENT_KEY).map_err(|e| EngineError::InvalidDocument(e.to_string()))? {
+            Some((Value::Object(ObjType::Text), id)) => Ok(Some(id)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Check an update parses as Automerge changes before it goes in the oplog,
+/// where a bad one would break the field for every peer.
+pub(crate) fn check_update(update: &[u8]) -> Result<(), EngineError> {
+    if update.is_empty() {
+        return Err(EngineError::InvalidDocument("empty rich-text update".into()));
+    }
+    Automerge::new().load_incremental(update).map_err(|e| EngineError::InvalidDocument(e.to_string()))?;
+    Ok(())
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Append an Automerge update (e.g. `AutoCommit::save_incremental`) to a
+    /// rich-text field. Updates merge in any order, so concurrent edits never
+    /// conflict. Not undoable here: the editor owns undo for its document.
+    pub fn apply_text_update(&mut self, entity_id: EntityId, field_key: &str, update: Vec<u8>) -> Result<BundleId, EngineError> {
+        check_user_field(field_key)?;
+        self.require_live_entity(entity_id)?;
+        check_update(&update)?;
+        let payload = OperationPayload::ApplyCrdt {
+            entity_id,
+            field_key: field_key.to_string(),
+            crdt_type: CrdtType::RichText,
+            delta: update,
+        };
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, vec![payload], false)?;
+        Ok(bundle_id)
+    }
+
+    /// A rich-text field's document, merged from every update to it
+    /// (including the active overlay's). None if it has none.
+    pub fn get_text_document(&self, entity_id: EntityId, field_key: &str) -> Result<Option<TextDocument>, EngineError> {
+        let updates = self.crdt_deltas(entity_id, field_key, CrdtType::RichText)?;
+        if updates.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(TextDocument::merge(updates.iter().map(Vec::as_slice))))
+    }
+
+    /// The deltas of a field's `ApplyCrdt` ops of one type, oldest first,
+    /// followed through the active overlay.
+    pub(crate) fn crdt_deltas(&self, entity_id: EntityId, field_key: &str, crdt_type: CrdtType) -> Result<Vec<Vec<u8>>, EngineError> {
+        let delta_of = |payload: OperationPayload| match payload {
+            OperationPayload::ApplyCrdt { field_key: key, crdt_type: t, delta, .. } if key == field_key && t == crdt_type => Some(delta),
+            _ => None,
+        };
+        let mut deltas: Vec<(Hlc, OpId, Vec<u8>)> = self
+            .storage
+            .get_ops_by_entity(entity_id)?
+            .into_iter()
+            .filter_map(|op| Some((op.hlc, op.op_id, delta_of(op.payload)?)))
+            .collect();
+        if let Some(overlay_id) = self.overlay_manager.active_overlay_id() {
+            for (_rowid, op_id, hlc, payload_bytes, eid, _op_type, _canon, _drifted, _field_key) in self.storage.get_overlay_ops(overlay_id)? {
+                if eid.as_deref() == Some(entity_id.as_bytes().as_slice())
+                    && let (Ok(op_id), Ok(hlc)) = (<[u8; 16]>::try_from(op_id), <[u8; 12]>::try_from(hlc))
+                    && let Ok(payload) = OperationPayload::from_msgpack(&payload_bytes)
+                    && let Some(delta) = delta_of(payload)
+                {
+                    deltas.push((Hlc::from_bytes(&hlc), OpId::from_bytes(op_id), delta));
+                }
+            }
+        }
+        deltas.sort_by_key(|(hlc, op_id, _)| (*hlc, *op_id));
+        Ok(deltas.into_iter().map(|(_, _, delta)| delta).collect())
+    }
+}
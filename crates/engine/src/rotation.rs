@@ -0,0 +1,126 @@
+//! Key rotation records.
+//!
+//! A rotation is stored as a system entity with the `KEY_ROTATION_FACET` facet
+//! and the msgpack `KeyRotation` proof in its `proof` field. The record is
+//! written by the old key, so it syncs ahead of anything the new key signs.
+//! A record only counts if the proof verifies and the entity was created by
+//! the old actor.
+
+use openprod_core::{
+    field_value::FieldValue,
+    identity::KeyRotation,
+    ids::{ActorId, EntityId},
+    operations::{BundleType, OperationPayload},
+};
+use openprod_storage::EngineStorage;
+
+use crate::Engine;
+use crate::error::EngineError;
+
+/// Facet marking key rotation records.
+pub const KEY_ROTATION_FACET: &str = "openprod.key_rotation";
+
+pub(crate) const PROOF_FIELD: &str = "proof";
+
+/// Upper bound on rotation chain length, guarding against malformed cycles.
+pub(crate) const MAX_CHAIN: usize = 64;
+
+/// Whether applying `payload` can change which rotation records count:
+/// anything but writes to other fields, edges, rules and table links.
+pub(crate) fn may_change_rotations(payload: &OperationPayload) -> bool {
+    match payload {
+        OperationPayload::SetField { field_key, .. }
+        | OperationPayload::ClearField { field_key, .. }
+        | OperationPayload::ApplyCrdt { field_key, .. }
+        | OperationPayload::ClearAndAdd { field_key, .. }
+        | OperationPayload::ResolveConflict { field_key, .. } => field_key == PROOF_FIELD,
+        OperationPayload::CreateEdge { .. }
+        | OperationPayload::DeleteEdge { .. }
+        | OperationPayload::SetEdgeProperty { .. }
+        | OperationPayload::ClearEdgeProperty { .. }
+        | OperationPayload::CreateOrderedEdge { .. }
+        | OperationPayload::MoveOrderedEdge { .. }
+        | OperationPayload::RestoreEdge { .. }
+        | OperationPayload::LinkTables { .. }
+        | OperationPayload::UnlinkTables { .. }
+        | OperationPayload::ConfirmFieldMapping { .. }
+        | OperationPayload::CreateRule { .. } => false,
+        _ => true,
+    }
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Replace this engine's signing key with a fresh one, recording a signed
+    /// continuity proof (authored by the old key) so peers treat both keys as
+    /// the same logical actor. The caller must persist the new identity.
+    pub fn rotate_identity(&mut self) -> Result<KeyRotation, EngineError> {
+        let (next, rotation) = self.identity.rotate();
+        let entity_id = EntityId::new();
+        let payloads = vec![
+            OperationPayload::CreateEntity {
+                entity_id,
+                initial_table: Some(KEY_ROTATION_FACET.to_string()),
+            },
+            OperationPayload::SetField {
+                entity_id,
+                field_key: PROOF_FIELD.to_string(),
+                value: FieldValue::Bytes(rotation.to_msgpack()?),
+            },
+        ];
+        self.execute_internal(BundleType::System, payloads, false)?;
+        self.identity = next;
+        Ok(rotation)
+    }
+
+    /// Every valid rotation record: the proof verifies and the record was
+    /// authored by the key being rotated away from.
+    pub fn key_rotations(&self) -> Result<Vec<KeyRotation>, EngineError> {
+        Ok(self.cached_key_rotations()?.to_vec())
+    }
+
+    /// `key_rotations`, verified on first use after a change.
+    pub(crate) fn cached_key_rotations(&self) -> Result<&[KeyRotation], EngineError> {
+        if let Some(rotations) = self.key_rotations.get() {
+            return Ok(rotations);
+        }
+        let rotations = self.load_key_rotations()?;
+        Ok(self.key_rotations.get_or_init(|| rotations))
+    }
+
+    fn load_key_rotations(&self) -> Result<Vec<KeyRotation>, EngineError> {
+        let mut rotations = Vec::new();
+        for entity_id in self.storage.get_entities_by_facet(KEY_ROTATION_FACET)? {
+            let Some(entity) = self.storage.get_entity(entity_id)? else {
+                continue;
+            };
+            if let Some(FieldValue::Bytes(bytes)) = self.storage.get_field(entity_id, PROOF_FIELD)?
+                && let Ok(rotation) = KeyRotation::from_msgpack(&bytes)
+                && rotation.verify().is_ok()
+                && entity.created_by == rotation.old_actor
+            {
+                rotations.push(rotation);
+            }
+        }
+        Ok(rotations)
+    }
+
+    /// The original key of the logical actor `actor_id` belongs to (itself if
+    /// it was never rotated to).
+    pub fn logical_actor(&self, actor_id: ActorId) -> Result<ActorId, EngineError> {
+        Ok(*self.actor_chain(actor_id)?.last().unwrap_or(&actor_id))
+    }
+
+    /// `actor_id` followed by each key it replaced, newest first.
+    pub(crate) fn actor_chain(&self, actor_id: ActorId) -> Result<Vec<ActorId>, EngineError> {
+        let rotations = self.cached_key_rotations()?;
+        let mut chain = vec![actor_id];
+        while chain.len() < MAX_CHAIN {
+            let current = *chain.last().unwrap();
+            match rotations.iter().find(|r| r.new_actor == current) {
+                Some(r) if !chain.contains(&r.old_actor) => chain.push(r.old_actor),
+                _ => break,
+            }
+        }
+        Ok(chain)
+    }
+}
@@ -0,0 +1,86 @@
+//! Speculative writes staged in a throwaway overlay, for `Engine::run_sandboxed`.
+
+use openprod_core::{
+    field_value::FieldValue,
+    ids::*,
+    operations::{BundleType, OperationPayload},
+};
+use openprod_storage::EngineStorage;
+
+use crate::error::EngineError;
+use crate::ingest::FieldChange;
+use crate::Engine;
+
+/// The commands a sandboxed closure can run. Every write lands in the
+/// sandbox's overlay; reads through `engine` see those writes.
+pub struct SandboxHandle<'a, S: EngineStorage> {
+    engine: &'a mut Engine<S>,
+    overlay_id: OverlayId,
+}
+
+impl<'a, S: EngineStorage> SandboxHandle<'a, S> {
+    pub(crate) fn new(engine: &'a mut Engine<S>, overlay_id: OverlayId) -> Self {
+        Self { engine, overlay_id }
+    }
+
+    pub fn overlay_id(&self) -> OverlayId {
+        self.overlay_id
+    }
+
+    /// Read-only access to the engine, as the sandbox sees it.
+    pub fn engine(&self) -> &Engine<S> {
+        self.engine
+    }
+
+    pub fn create_entity_with_fields(
+        &mut self,
+        facet_type: &str,
+        fields: Vec<(&str, FieldValue)>,
+    ) -> Result<EntityId, EngineError> {
+        Ok(self.engine.create_entity_with_fields(facet_type, fields)?.0)
+    }
+
+    pub fn set_field(&mut self, entity_id: EntityId, field_key: &str, value: FieldValue) -> Result<(), EngineError> {
+        self.engine.set_field(entity_id, field_key, value).map(drop)
+    }
+
+    pub fn clear_field(&mut self, entity_id: EntityId, field_key: &str) -> Result<(), EngineError> {
+        self.engine.clear_field(entity_id, field_key).map(drop)
+    }
+
+    pub fn delete_entity(&mut self, entity_id: EntityId) -> Result<(), EngineError> {
+        self.engine.delete_entity(entity_id).map(drop)
+    }
+
+    pub fn create_edge(&mut self, edge_type: &str, source_id: EntityId, target_id: EntityId) -> Result<EdgeId, EngineError> {
+        Ok(self.engine.create_edge(edge_type, source_id, target_id)?.0)
+    }
+
+    /// Stage a raw batch of payloads, as `Engine::execute` would.
+    pub fn execute(&mut self, payloads: Vec<OperationPayload>) -> Result<(), EngineError> {
+        self.engine.execute(BundleType::ScriptOutput, payloads).map(drop)
+    }
+}
+
+/// What a sandboxed run would change in canonical state. The run's writes
+/// stay in the stashed overlay `overlay_id` until the caller passes it to
+/// `Engine::commit_overlay` or `Engine::discard_overlay`.
+#[derive(Debug, Clone)]
+pub struct SandboxDiff {
+    pub overlay_id: OverlayId,
+    /// Fields whose value would change, once each, in the order first written.
+    pub field_changes: Vec<FieldChange>,
+    /// Entities the run created.
+    pub entities_created: Vec<EntityId>,
+    /// Entities the run deleted.
+    pub entities_deleted: Vec<EntityId>,
+    /// Staged ops of any kind, including those not summarized above.
+    pub op_count: usize,
+}
+
+impl SandboxDiff {
+    /// Whether committing would change nothing.
+    pub fn is_empty(&self) -> bool {
+        self.op_count == 0
+    }
+}
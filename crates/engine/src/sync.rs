@@ -0,0 +1,366 @@
+//! Sync plumbing: sealed bundles, capability negotiation, per-peer
+//! outboxes and partial (scoped) sync.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use openprod_core::{
+    capabilities::{Capabilities, CompatibilityReport},
+    hlc::Hlc,
+    ids::{ActorId, BundleId, EntityId},
+    operations::{Bundle, CrdtType, Operation, OperationPayload},
+    sealed::{SealedBundle, WorkspaceKey},
+    sync_scope::SyncScope,
+    vector_clock::VectorClock,
+};
+use openprod_storage::{EdgeRecord, EngineStorage, MATERIALIZED_OP_TYPES, PeerRecord};
+
+use crate::{Engine, SyncPage};
+use crate::error::EngineError;
+use crate::ingest::IngestReport;
+
+impl<S: EngineStorage> Engine<S> {
+    // ========================================================================
+    // Sealed Bundles (end-to-end encryption)
+    // ========================================================================
+
+    /// Set (or clear) the workspace key used to seal outgoing bundles and open
+    /// incoming sealed ones. Not persisted; the application supplies it on startup.
+    pub fn set_workspace_key(&mut self, key: Option<WorkspaceKey>) {
+        self.workspace_key = key;
+    }
+
+    /// Whether a workspace key is set, i.e. outgoing bundles can be sealed.
+    pub fn has_workspace_key(&self) -> bool {
+        self.workspace_key.is_some()
+    }
+
+    /// Seal a stored bundle for sending through an untrusted relay.
+    pub fn seal_bundle(&self, bundle_id: BundleId) -> Result<SealedBundle, EngineError> {
+        let key = self.workspace_key.as_ref().ok_or(EngineError::NoWorkspaceKey)?;
+        let bundle = self
+            .storage
+            .get_bundle(bundle_id)?
+            .ok_or_else(|| EngineError::BundleNotFound(bundle_id.to_string()))?;
+        let operations = self.storage.get_ops_by_bundle(bundle_id)?;
+        Ok(SealedBundle::seal(&bundle, &operations, key)?)
+    }
+
+    /// Decrypt a sealed bundle with the workspace key and ingest it. Without a
+    /// key (or with the wrong one) nothing is stored and an error is returned,
+    /// so the caller can keep the sealed bundle and retry once a key is set.
+    pub fn ingest_sealed(&mut self, sealed: &SealedBundle) -> Result<IngestReport, EngineError> {
+        let key = self.workspace_key.as_ref().ok_or(EngineError::NoWorkspaceKey)?;
+        let (bundle, operations) = sealed.open(key)?;
+        self.ingest_bundle(&bundle, &operations)
+    }
+
+    // ========================================================================
+    // Sync Capabilities
+    // ========================================================================
+
+    /// What this engine can materialize, advertised to peers at sync start.
+    pub fn capabilities(&self) -> Capabilities {
+        // List and rich-text ops aren't materialized, but are merged when read
+        Capabilities::new(MATERIALIZED_OP_TYPES.iter().copied().chain(["ApplyCrdt"]), [CrdtType::List, CrdtType::RichText])
+    }
+
+    /// Sync handshake: compare capabilities with a remote peer.
+    /// Fails if the wire formats differ; otherwise reports what must be parked.
+    pub fn negotiate(&self, remote: &Capabilities) -> Result<CompatibilityReport, EngineError> {
+        let report = self.capabilities().compatibility_with(remote);
+        if !report.can_sync() {
+            return Err(EngineError::IncompatiblePeer(format!(
+                "format version {} (local) vs {} (remote)",
+                report.local_format_version, report.remote_format_version,
+            )));
+        }
+        Ok(report)
+    }
+
+    /// Whether every op in a stored bundle can be materialized by `remote`.
+    /// Bundles that can't should be parked rather than sent.
+    pub fn bundle_supported_by(
+        &self,
+        bundle_id: BundleId,
+        remote: &Capabilities,
+    ) -> Result<bool, EngineError> {
+        let ops = self.storage.get_ops_by_bundle(bundle_id)?;
+        Ok(ops.iter().all(|op| remote.supports(&op.payload)))
+    }
+
+    // ========================================================================
+    // Peers / Outbox
+    // ========================================================================
+
+    /// Register a sync peer (by its actor id), or update its display name.
+    pub fn add_peer(&mut self, peer_id: ActorId, display_name: Option<&str>) -> Result<(), EngineError> {
+        Ok(self.storage.upsert_peer(peer_id, display_name)?)
+    }
+
+    /// Forget a peer and everything it acknowledged. Returns false if unknown.
+    pub fn remove_peer(&mut self, peer_id: ActorId) -> Result<bool, EngineError> {
+        Ok(self.storage.delete_peer(peer_id)?)
+    }
+
+    pub fn peers(&self) -> Result<Vec<PeerRecord>, EngineError> {
+        Ok(self.storage.list_peers()?)
+    }
+
+    pub fn get_peer(&self, peer_id: ActorId) -> Result<Option<PeerRecord>, EngineError> {
+        Ok(self.storage.get_peer(peer_id)?)
+    }
+
+    /// Record that `peer_id` has acknowledged everything covered by `acked`.
+    /// Acknowledgements only move forward: the stored clock is merged with
+    /// `acked`, so a stale or reordered ack never resends bundles. Unknown peers
+    /// are registered on first ack.
+    pub fn mark_peer_synced(&mut self, peer_id: ActorId, acked: &VectorClock) -> Result<(), EngineError> {
+        self.storage.upsert_peer(peer_id, None)?;
+        let mut acked_vc = self
+            .storage
+            .get_peer(peer_id)?
+            .map(|peer| peer.acked_vc)
+            .unwrap_or_default();
+        acked_vc.merge(acked);
+        Ok(self.storage.set_peer_acked_vc(peer_id, &acked_vc)?)
+    }
+
+    /// Outbox for a peer: bundles we hold that it has not acknowledged, in HLC
+    /// order. Bundles authored by the peer itself are never included. Given the
+    /// peer's capabilities, bundles it can't materialize are parked: left out,
+    /// but still unacknowledged, so they are offered again once it upgrades.
+    pub fn pending_bundles_for(
+        &self,
+        peer_id: ActorId,
+        remote: Option<&Capabilities>,
+    ) -> Result<Vec<BundleId>, EngineError> {
+        let acked_vc = self
+            .storage
+            .get_peer(peer_id)?
+            .map(|peer| peer.acked_vc)
+            .unwrap_or_default();
+        let mut outbox = self.bundles_not_covered(&acked_vc, Some(peer_id))?;
+        if let Some(remote) = remote {
+            let mut supported = Vec::with_capacity(outbox.len());
+            for bundle_id in outbox {
+                if self.bundle_supported_by(bundle_id, remote)? {
+                    supported.push(bundle_id);
+                }
+            }
+            outbox = supported;
+        }
+        Ok(outbox)
+    }
+
+    /// Bundles we hold that a peer with vector clock `known` is missing, in HLC
+    /// order. For stateless pull sync where the peer isn't tracked. With a
+    /// `SyncScope` only our own bundles are offered: the rest may have come
+    /// trimmed, and a peer storing one would take it as complete.
+    pub fn bundles_missing_from(&self, known: &VectorClock) -> Result<Vec<BundleId>, EngineError> {
+        self.bundles_not_covered(known, None)
+    }
+
+    fn bundles_not_covered(
+        &self,
+        known: &VectorClock,
+        skip_actor: Option<ActorId>,
+    ) -> Result<Vec<BundleId>, EngineError> {
+        let mut unsent: Vec<(Hlc, BundleId)> = Vec::new();
+        for actor_id in self.storage.get_vector_clock()?.entries().keys() {
+            if Some(*actor_id) == skip_actor || (!self.sync_scope.is_all() && *actor_id != self.actor_id()) {
+                continue;
+            }
+            let after = known.get(actor_id).copied().unwrap_or(Hlc::new(0, 0));
+            for op in self.storage.get_ops_by_actor_after(*actor_id, after)? {
+                if !unsent.iter().any(|(_, id)| *id == op.bundle_id) {
+                    unsent.push((op.hlc, op.bundle_id));
+                }
+            }
+        }
+        unsent.sort();
+        Ok(unsent.into_iter().map(|(_, bundle_id)| bundle_id).collect())
+    }
+
+    // ========================================================================
+    // Partial Sync
+    // ========================================================================
+
+    /// What this engine replicates; see `EngineBuilder::sync_scope`.
+    pub fn sync_scope(&self) -> &SyncScope {
+        &self.sync_scope
+    }
+
+    /// Bundles a peer with vector clock `known` is missing, in HLC order, with
+    /// their ops trimmed to the peer's `scope`; bundles left with none are
+    /// skipped. Ops are judged by the facets entities have here now, so an
+    /// entity that enters the scope later only brings its ops from then on.
+    /// Given the peer's capabilities, bundles it can't materialize are parked
+    /// (left out) until it asks again with capabilities that cover them.
+    pub fn bundles_since(
+        &self,
+        known: &VectorClock,
+        scope: &SyncScope,
+        remote: Option<&Capabilities>,
+    ) -> Result<Vec<(Bundle, Vec<Operation>)>, EngineError> {
+        let mut covered: BTreeMap<EntityId, bool> = BTreeMap::new();
+        let mut bundles = Vec::new();
+        for bundle_id in self.bundles_missing_from(known)? {
+            let operations = self.storage.get_ops_by_bundle(bundle_id)?;
+            if let Some(trimmed) = self.bundle_for_peer(bundle_id, operations, scope, remote, &mut covered)? {
+                bundles.push(trimmed);
+            }
+        }
+        Ok(bundles)
+    }
+
+    /// One page of `bundles_since`: the first `limit` bundles `known` is
+    /// missing, found by reading the oplog a page at a time rather than all
+    /// of it, with the clock to pass as `known` for the next page.
+    pub fn bundles_since_page(
+        &self,
+        known: &VectorClock,
+        scope: &SyncScope,
+        remote: Option<&Capabilities>,
+        limit: usize,
+    ) -> Result<SyncPage, EngineError> {
+        const OPLOG_PAGE: usize = 500;
+        let limit = limit.max(1);
+        let mut bundle_ids = Vec::new();
+        let mut seen = BTreeSet::new();
+        let mut more = false;
+        for op in self.ops_canonical_paged(OPLOG_PAGE) {
+            let op = op?;
+            // Same rules as `bundles_missing_from`
+            if !self.sync_scope.is_all() && op.actor_id != self.actor_id() {
+                continue;
+            }
+            if known.get(&op.actor_id).is_some_and(|have| op.hlc <= *have) || !seen.insert(op.bundle_id) {
+                continue;
+            }
+            if bundle_ids.len() == limit {
+                more = true;
+                break;
+            }
+            bundle_ids.push(op.bundle_id);
+        }
+
+        // The page is an HLC-ordered prefix, so each actor's bundles in it are
+        // a prefix of its missing ones; skipped bundles are passed over too
+        let mut next = known.clone();
+        let mut covered: BTreeMap<EntityId, bool> = BTreeMap::new();
+        let mut bundles = Vec::with_capacity(bundle_ids.len());
+        for bundle_id in bundle_ids {
+            let operations = self.storage.get_ops_by_bundle(bundle_id)?;
+            for op in &operations {
+                next.update(op.actor_id, op.hlc);
+            }
+            if let Some(trimmed) = self.bundle_for_peer(bundle_id, operations, scope, remote, &mut covered)? {
+                bundles.push(trimmed);
+            }
+        }
+        Ok(SyncPage { bundles, next: more.then_some(next) })
+    }
+
+    /// A stored bundle as a peer with `scope` and `remote` capabilities should
+    /// get it: None if it can't materialize it (parked) or no op is in scope.
+    fn bundle_for_peer(
+        &self,
+        bundle_id: BundleId,
+        operations: Vec<Operation>,
+        scope: &SyncScope,
+        remote: Option<&Capabilities>,
+        covered: &mut BTreeMap<EntityId, bool>,
+    ) -> Result<Option<(Bundle, Vec<Operation>)>, EngineError> {
+        if remote.is_some_and(|remote| !operations.iter().all(|op| remote.supports(&op.payload))) {
+            return Ok(None);
+        }
+        let bundle = self
+            .storage
+            .get_bundle(bundle_id)?
+            .ok_or_else(|| EngineError::BundleNotFound(bundle_id.to_string()))?;
+        if scope.is_all() {
+            return Ok(Some((bundle, operations)));
+        }
+        let mut kept = Vec::with_capacity(operations.len());
+        for op in operations {
+            if self.op_in_scope(&op.payload, scope, covered)? {
+                kept.push(op);
+            }
+        }
+        Ok((!kept.is_empty()).then_some((bundle, kept)))
+    }
+
+    /// Whether `scope` takes an op: one on an entity with an in-scope facet,
+    /// attached or not (so detaching it still reaches the peer), or on an
+    /// edge with such an entity at either end. Workspace-wide ops (table
+    /// links, field mappings, rules) always go. `covered` caches entities.
+    fn op_in_scope(
+        &self,
+        payload: &OperationPayload,
+        scope: &SyncScope,
+        covered: &mut BTreeMap<EntityId, bool>,
+    ) -> Result<bool, EngineError> {
+        let entities = match payload {
+            OperationPayload::LinkTables { .. }
+            | OperationPayload::UnlinkTables { .. }
+            | OperationPayload::ConfirmFieldMapping { .. }
+            | OperationPayload::CreateRule { .. } => return Ok(true),
+            OperationPayload::CreateEdge { source_id, target_id, .. }
+            | OperationPayload::CreateOrderedEdge { source_id, target_id, .. } => vec![*source_id, *target_id],
+            OperationPayload::MergeEntities { survivor, absorbed } => vec![*survivor, *absorbed],
+            OperationPayload::SplitEntity { source, new_entity, .. } => vec![*source, *new_entity],
+            _ => match payload.entity_id() {
+                Some(entity_id) => vec![entity_id],
+                None => {
+                    let mut ends = Vec::new();
+                    for edge_id in payload.edge_ids() {
+                        // Edges not materialized here (ordered ones) can't be judged, so go along
+                        let Some(edge) = self.storage.get_edge(edge_id)? else {
+                            return Ok(true);
+                        };
+                        ends.extend([edge.source_id, edge.target_id]);
+                    }
+                    ends
+                }
+            },
+        };
+        for entity_id in entities {
+            let in_scope = match covered.get(&entity_id) {
+                Some(in_scope) => *in_scope,
+                None => {
+                    let in_scope = self.storage.get_facets(entity_id)?.iter().any(|f| scope.covers_facet(&f.facet_type));
+                    covered.insert(entity_id, in_scope);
+                    in_scope
+                }
+            };
+            if in_scope {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// With a `SyncScope`, leave out edges deferred until their other end
+    /// arrives: those with an end known here only as a placeholder with no
+    /// attached facet.
+    pub(crate) fn without_deferred_edges(&self, edges: Vec<EdgeRecord>) -> Result<Vec<EdgeRecord>, EngineError> {
+        if self.sync_scope.is_all() {
+            return Ok(edges);
+        }
+        let mut kept = Vec::with_capacity(edges.len());
+        for edge in edges {
+            if !self.is_unresolved(edge.source_id)? && !self.is_unresolved(edge.target_id)? {
+                kept.push(edge);
+            }
+        }
+        Ok(kept)
+    }
+
+    /// A placeholder entity with no attached facet: nothing but its id is known.
+    fn is_unresolved(&self, entity_id: EntityId) -> Result<bool, EngineError> {
+        if !self.storage.get_entity(entity_id)?.is_some_and(|entity| entity.placeholder) {
+            return Ok(false);
+        }
+        Ok(self.storage.get_facets(entity_id)?.iter().all(|facet| facet.detached))
+    }
+}
@@ -0,0 +1,107 @@
+//! Engine-managed system fields.
+//!
+//! Fields under `SYSTEM_FIELD_PREFIX` carry meaning every app shares (an
+//! entity's title, icon, archived flag), so peers agree on them after sync.
+//! `set_field` and friends refuse these keys; they are written through the
+//! typed helpers (`Engine::set_title`, ...) or `Engine::set_system_field`.
+
+use openprod_core::{
+    field_value::FieldValue,
+    ids::{BundleId, EntityId},
+    operations::{BundleType, OperationPayload},
+};
+use openprod_storage::EngineStorage;
+
+use crate::{Engine, EngineError};
+
+/// Prefix reserved for engine-managed fields.
+pub const SYSTEM_FIELD_PREFIX: &str = "sys:";
+
+/// Display title (`FieldValue::Text`).
+pub const TITLE_FIELD: &str = "sys:title";
+
+/// Icon name or emoji (`FieldValue::Text`).
+pub const ICON_FIELD: &str = "sys:icon";
+
+/// Archived flag (`FieldValue::Boolean`); absent means not archived.
+pub const ARCHIVED_FIELD: &str = "sys:archived";
+
+/// Whether `field_key` is in the reserved namespace.
+pub fn is_system_field(field_key: &str) -> bool {
+    field_key.starts_with(SYSTEM_FIELD_PREFIX)
+}
+
+/// Reject reserved keys from the plain field commands.
+pub(crate) fn check_user_field(field_key: &str) -> Result<(), EngineError> {
+    if is_system_field(field_key) {
+        return Err(EngineError::ReservedField(field_key.to_string()));
+    }
+    Ok(())
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Set a reserved `sys:` field, bypassing the `set_field` guard. Prefer
+    /// the typed helpers below for the well-known keys.
+    pub fn set_system_field(
+        &mut self,
+        entity_id: EntityId,
+        field_key: &str,
+        value: FieldValue,
+    ) -> Result<BundleId, EngineError> {
+        if !is_system_field(field_key) {
+            return Err(EngineError::Rejected(format!("not a system field: {field_key}")));
+        }
+        self.require_live_entity(entity_id)?;
+        let payloads = vec![OperationPayload::SetField { entity_id, field_key: field_key.to_string(), value }];
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
+        Ok(bundle_id)
+    }
+
+    /// Clear a reserved `sys:` field.
+    pub fn clear_system_field(&mut self, entity_id: EntityId, field_key: &str) -> Result<BundleId, EngineError> {
+        if !is_system_field(field_key) {
+            return Err(EngineError::Rejected(format!("not a system field: {field_key}")));
+        }
+        self.require_live_entity(entity_id)?;
+        let payloads = vec![OperationPayload::ClearField { entity_id, field_key: field_key.to_string() }];
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, payloads, true)?;
+        Ok(bundle_id)
+    }
+
+    pub fn set_title(&mut self, entity_id: EntityId, title: &str) -> Result<BundleId, EngineError> {
+        self.set_system_field(entity_id, TITLE_FIELD, FieldValue::Text(title.to_string()))
+    }
+
+    pub fn set_icon(&mut self, entity_id: EntityId, icon: &str) -> Result<BundleId, EngineError> {
+        self.set_system_field(entity_id, ICON_FIELD, FieldValue::Text(icon.to_string()))
+    }
+
+    /// Archive or unarchive an entity. Unarchiving clears the flag.
+    pub fn set_archived(&mut self, entity_id: EntityId, archived: bool) -> Result<BundleId, EngineError> {
+        if archived {
+            self.set_system_field(entity_id, ARCHIVED_FIELD, FieldValue::Boolean(true))
+        } else {
+            self.clear_system_field(entity_id, ARCHIVED_FIELD)
+        }
+    }
+
+    /// The entity's `sys:title`, if set to text.
+    pub fn title(&self, entity_id: EntityId) -> Result<Option<String>, EngineError> {
+        Ok(match self.get_field(entity_id, TITLE_FIELD)? {
+            Some(FieldValue::Text(title)) => Some(title),
+            _ => None,
+        })
+    }
+
+    /// The entity's `sys:icon`, if set to text.
+    pub fn icon(&self, entity_id: EntityId) -> Result<Option<String>, EngineError> {
+        Ok(match self.get_field(entity_id, ICON_FIELD)? {
+            Some(FieldValue::Text(icon)) => Some(icon),
+            _ => None,
+        })
+    }
+
+    pub fn is_archived(&self, entity_id: EntityId) -> Result<bool, EngineError> {
+        Ok(matches!(self.get_field(entity_id, ARCHIVED_FIELD)?, Some(FieldValue::Boolean(true))))
+    }
+}
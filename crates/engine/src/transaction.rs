@@ -0,0 +1,175 @@
+//! Multi-step edits buffered into one bundle, for `Engine::transaction`.
+
+use openprod_core::{
+    field_value::FieldValue,
+    ids::*,
+    operations::OperationPayload,
+};
+use openprod_storage::{EdgeFilter, EngineStorage};
+
+use crate::error::EngineError;
+use crate::{system, Engine};
+
+/// The commands a transaction closure can issue. Each is checked against the
+/// engine's state plus the commands already buffered, so an entity created
+/// earlier in the transaction can be written to, and one deleted can't.
+/// Nothing is written until the closure returns `Ok`.
+pub struct Transaction<'a, S: EngineStorage> {
+    engine: &'a Engine<S>,
+    payloads: Vec<OperationPayload>,
+    created: Vec<EntityId>,
+    deleted: Vec<EntityId>,
+}
+
+impl<'a, S: EngineStorage> Transaction<'a, S> {
+    pub(crate) fn new(engine: &'a Engine<S>) -> Self {
+        Self { engine, payloads: Vec::new(), created: Vec::new(), deleted: Vec::new() }
+    }
+
+    pub(crate) fn into_payloads(self) -> Vec<OperationPayload> {
+        self.payloads
+    }
+
+    /// Run `f` as a nested step. If it fails, the commands it buffered are
+    /// dropped and its error returned, and the transaction carries on from
+    /// where it was, like rolling back to a SQL `SAVEPOINT`.
+    pub fn savepoint<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, EngineError>) -> Result<T, EngineError> {
+        let mark = (self.payloads.len(), self.created.len(), self.deleted.len());
+        let result = f(self);
+        if result.is_err() {
+            self.payloads.truncate(mark.0);
+            self.created.truncate(mark.1);
+            self.deleted.truncate(mark.2);
+        }
+        result
+    }
+
+    /// Commands buffered so far.
+    pub fn len(&self) -> usize {
+        self.payloads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.payloads.is_empty()
+    }
+
+    /// A field's value as the transaction would leave it.
+    pub fn get_field(&self, entity_id: EntityId, field_key: &str) -> Result<Option<FieldValue>, EngineError> {
+        for payload in self.payloads.iter().rev() {
+            match payload {
+                OperationPayload::SetField { entity_id: id, field_key: key, value } if *id == entity_id && key == field_key => {
+                    return Ok(Some(value.clone()));
+                }
+                OperationPayload::ClearField { entity_id: id, field_key: key } if *id == entity_id && key == field_key => {
+                    return Ok(None);
+                }
+                _ => {}
+            }
+        }
+        if self.created.contains(&entity_id) {
+            return Ok(None);
+        }
+        self.engine.get_field(entity_id, field_key)
+    }
+
+    pub fn create_entity_with_fields(
+        &mut self,
+        facet_type: &str,
+        fields: Vec<(&str, FieldValue)>,
+    ) -> Result<EntityId, EngineError> {
+        for (key, _) in &fields {
+            system::check_user_field(key)?;
+        }
+        let entity_id = EntityId::new();
+        self.payloads.push(OperationPayload::CreateEntity { entity_id, initial_table: Some(facet_type.to_string()) });
+        for (key, value) in fields {
+            self.payloads.push(OperationPayload::SetField { entity_id, field_key: key.to_string(), value });
+        }
+        self.created.push(entity_id);
+        Ok(entity_id)
+    }
+
+    pub fn set_field(&mut self, entity_id: EntityId, field_key: &str, value: FieldValue) -> Result<(), EngineError> {
+        system::check_user_field(field_key)?;
+        self.require_live_entity(entity_id)?;
+        self.payloads.push(OperationPayload::SetField { entity_id, field_key: field_key.to_string(), value });
+        Ok(())
+    }
+
+    pub fn clear_field(&mut self, entity_id: EntityId, field_key: &str) -> Result<(), EngineError> {
+        system::check_user_field(field_key)?;
+        self.require_live_entity(entity_id)?;
+        self.payloads.push(OperationPayload::ClearField { entity_id, field_key: field_key.to_string() });
+        Ok(())
+    }
+
+    pub fn attach_facet(&mut self, entity_id: EntityId, facet_type: &str) -> Result<(), EngineError> {
+        self.require_live_entity(entity_id)?;
+        self.payloads.push(OperationPayload::AttachFacet { entity_id, facet_type: facet_type.to_string() });
+        Ok(())
+    }
+
+    /// Delete an entity, cascading to its live edges, including those
+    /// created earlier in the transaction.
+    pub fn delete_entity(&mut self, entity_id: EntityId) -> Result<(), EngineError> {
+        self.require_live_entity(entity_id)?;
+        let storage = self.engine.storage();
+        let mut cascade_edges: Vec<EdgeId> = storage.get_edges_from(entity_id, &EdgeFilter::live())?
+            .iter()
+            .chain(storage.get_edges_to(entity_id, &EdgeFilter::live())?.iter())
+            .map(|e| e.edge_id)
+            .collect();
+        for payload in &self.payloads {
+            if let OperationPayload::CreateEdge { edge_id, source_id, target_id, .. } = payload
+                && (*source_id == entity_id || *target_id == entity_id)
+            {
+                cascade_edges.push(*edge_id);
+            }
+        }
+        self.payloads.push(OperationPayload::DeleteEntity { entity_id, cascade_edges });
+        self.deleted.push(entity_id);
+        Ok(())
+    }
+
+    /// Create an edge between two live entities. Unlike `Engine::create_edge`,
+    /// an existing edge of a unique type (even a deleted one, which a new edge
+    /// would be merged into) is not reused: the transaction is refused
+    /// instead, so it never writes something other than asked.
+    pub fn create_edge(&mut self, edge_type: &str, source_id: EntityId, target_id: EntityId) -> Result<EdgeId, EngineError> {
+        self.require_live_entity(source_id)?;
+        self.require_live_entity(target_id)?;
+        let storage = self.engine.storage();
+        if storage.list_unique_edge_types()?.iter().any(|t| t == edge_type) {
+            let buffered = self.payloads.iter().any(|p| matches!(
+                p,
+                OperationPayload::CreateEdge { edge_type: t, source_id: s, target_id: d, .. }
+                    if t == edge_type && *s == source_id && *d == target_id
+            ));
+            let existing = storage.get_edges_between(source_id, target_id, &EdgeFilter::all().of_type(edge_type))?;
+            if buffered || !existing.is_empty() {
+                return Err(EngineError::Rejected(format!(
+                    "unique {edge_type} edge already joins {source_id} and {target_id}"
+                )));
+            }
+        }
+        let edge_id = EdgeId::new();
+        self.payloads.push(OperationPayload::CreateEdge {
+            edge_id,
+            edge_type: edge_type.to_string(),
+            source_id,
+            target_id,
+            properties: Vec::new(),
+        });
+        Ok(edge_id)
+    }
+
+    fn require_live_entity(&self, entity_id: EntityId) -> Result<(), EngineError> {
+        if self.deleted.contains(&entity_id) {
+            return Err(EngineError::EntityAlreadyDeleted(entity_id.to_string()));
+        }
+        if self.created.contains(&entity_id) {
+            return Ok(());
+        }
+        self.engine.require_live_entity(entity_id)
+    }
+}
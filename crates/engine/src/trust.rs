@@ -0,0 +1,100 @@
+use openprod_core::{ids::ActorId, operations::{Bundle, Operation}};
+use openprod_storage::{EngineStorage, TrustState};
+
+use crate::Engine;
+use crate::error::EngineError;
+use crate::ingest::IngestReport;
+
+/// How `ingest_bundle` treats bundles from actors with no trust decision yet.
+/// Revoked actors are always rejected and pending ones always quarantined;
+/// trusted actors (and this engine's own actor) are always accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrustPolicy {
+    /// Accept bundles from any actor (trust on first use).
+    #[default]
+    AcceptUnknown,
+    /// Mark unknown actors pending and hold their bundles until `approve_actor`.
+    QuarantineUnknown,
+    /// Refuse bundles from unknown actors with `EngineError::UntrustedActor`.
+    RejectUnknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TrustDecision {
+    Accept,
+    Quarantine,
+    Reject,
+}
+
+impl<S: EngineStorage> Engine<S> {
+    pub fn set_trust_policy(&mut self, policy: TrustPolicy) {
+        self.trust_policy = policy;
+    }
+
+    pub fn trust_policy(&self) -> TrustPolicy {
+        self.trust_policy
+    }
+
+    /// Trust decision recorded for `actor_id` (None if never seen or decided).
+    pub fn actor_trust(&self, actor_id: ActorId) -> Result<Option<TrustState>, EngineError> {
+        Ok(self.storage.get_actor_trust(actor_id)?)
+    }
+
+    /// Every actor with a trust decision, including pending ones awaiting approval.
+    pub fn list_actor_trust(&self) -> Result<Vec<(ActorId, TrustState)>, EngineError> {
+        Ok(self.storage.list_actor_trust()?)
+    }
+
+    /// Trust `actor_id` after out-of-band verification of its key, then ingest
+    /// any bundles quarantined while it was pending. Returns their ingest report.
+    pub fn approve_actor(&mut self, actor_id: ActorId) -> Result<IngestReport, EngineError> {
+        self.storage.set_actor_trust(actor_id, TrustState::Trusted)?;
+        let held = self.storage.list_untrusted_bundles(actor_id)?;
+        let batch: Vec<(&Bundle, &[Operation])> =
+            held.iter().map(|h| (&h.bundle, h.operations.as_slice())).collect();
+        let report = self.ingest_batch(&batch)?;
+        self.storage.delete_untrusted_bundles(actor_id)?;
+        Ok(report)
+    }
+
+    /// Revoke `actor_id`'s key: future bundles from it are rejected and any
+    /// quarantined ones are dropped. Already-ingested history is kept. Returns
+    /// the number of quarantined bundles dropped.
+    pub fn revoke_actor(&mut self, actor_id: ActorId) -> Result<usize, EngineError> {
+        self.storage.set_actor_trust(actor_id, TrustState::Revoked)?;
+        Ok(self.storage.delete_untrusted_bundles(actor_id)?)
+    }
+
+    /// Number of bundles held from actors awaiting approval.
+    pub fn quarantined_count(&self) -> Result<u64, EngineError> {
+        Ok(self.storage.count_untrusted_bundles()?)
+    }
+
+    pub(crate) fn trust_decision(&mut self, actor_id: ActorId) -> Result<TrustDecision, EngineError> {
+        if actor_id == self.actor_id() {
+            return Ok(TrustDecision::Accept);
+        }
+        // A rotated key inherits the nearest predecessor's trust decision,
+        // looked up each time so a later change to the predecessor applies
+        let mut state = None;
+        for actor in self.actor_chain(actor_id)? {
+            if let Some(found) = self.storage.get_actor_trust(actor)? {
+                state = Some(found);
+                break;
+            }
+        }
+        Ok(match state {
+            Some(TrustState::Trusted) => TrustDecision::Accept,
+            Some(TrustState::Pending) => TrustDecision::Quarantine,
+            Some(TrustState::Revoked) => TrustDecision::Reject,
+            None => match self.trust_policy {
+                TrustPolicy::AcceptUnknown => TrustDecision::Accept,
+                TrustPolicy::QuarantineUnknown => {
+                    self.storage.set_actor_trust(actor_id, TrustState::Pending)?;
+                    TrustDecision::Quarantine
+                }
+                TrustPolicy::RejectUnknown => TrustDecision::Reject,
+            },
+        })
+    }
+}
@@ -1,24 +1,234 @@
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 
 use openprod_core::{
     field_value::FieldValue,
     hlc::Hlc,
     ids::*,
-    operations::OperationPayload,
+    operations::{BundleType, Operation, OperationPayload},
 };
-use openprod_storage::{EdgeRecord, FacetRecord, SqliteStorage, Storage, StorageError};
+use openprod_storage::{EdgeFilter, EdgeRecord, EngineStorage, FacetRecord, Storage, StorageError};
+
+use crate::{Engine, RevertResult, SkippedEdgeProperty, UndoConflict, UndoResult};
+use crate::error::EngineError;
 
 pub struct UndoManager {
     undo_stack: VecDeque<UndoEntry>,
     redo_stack: VecDeque<UndoEntry>,
+    /// Redo entries below this index outlived an edit in another scope: only
+    /// `redo_scope` reaches them, as a global redo would skip that edit.
+    redo_floor: usize,
     max_depth: usize,
+    group: Option<UndoGroup>,
+}
+
+/// An open undo group: entries pushed while open are merged into one on close.
+struct UndoGroup {
+    /// Nesting depth (only the outermost close merges).
+    depth: usize,
+    /// Entries pushed since the group was opened.
+    count: usize,
 }
 
 pub struct UndoEntry {
+    /// The (last) bundle this entry undoes.
     pub bundle_id: BundleId,
+    /// HLC of that bundle; later writes count as interference.
     pub bundle_hlc: Hlc,
+    /// Earlier bundles coalesced into this entry by an undo group (oldest first).
+    pub merged_bundle_ids: Vec<BundleId>,
+    /// For a merged group, the HLC of the group's last write to each field.
+    /// Fields not listed use `bundle_hlc`.
+    pub field_hlcs: Vec<(EntityId, String, Hlc)>,
+    /// For a merged group, the HLC of the bundle that first snapshotted each
+    /// entity. Entities not listed use `bundle_hlc`.
+    pub entity_hlcs: Vec<(EntityId, Hlc)>,
     pub payloads: Vec<OperationPayload>,
     pub snapshot: PreExecutionSnapshot,
+    /// Scopes this entry belongs to (see `UndoScope`).
+    pub scopes: Vec<UndoScope>,
+}
+
+/// Partition key for scoped undo: an entity (document) or a facet type (table).
+///
+/// An entry belongs to every entity it touches, including edge endpoints, and to
+/// every facet attached to those entities when it was executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UndoScope {
+    Entity(EntityId),
+    Facet(String),
+}
+
+impl UndoEntry {
+    /// True if this entry belongs to `scope`.
+    pub fn in_scope(&self, scope: &UndoScope) -> bool {
+        self.scopes.contains(scope)
+    }
+
+    /// True if this entry undoes `bundle_id`, directly or via a merged group.
+    pub fn covers_bundle(&self, bundle_id: BundleId) -> bool {
+        self.bundle_id == bundle_id || self.merged_bundle_ids.contains(&bundle_id)
+    }
+
+    /// HLC after which a write to the field is interference: when this entry
+    /// last wrote it, not when its last bundle ran.
+    pub fn field_hlc(&self, entity_id: EntityId, field_key: &str) -> Hlc {
+        self.field_hlcs
+            .iter()
+            .find(|(e, k, _)| *e == entity_id && k == field_key)
+            .map_or(self.bundle_hlc, |(_, _, hlc)| *hlc)
+    }
+
+    /// HLC after which another actor's write to the entity is interference.
+    pub fn entity_hlc(&self, entity_id: EntityId) -> Hlc {
+        self.entity_hlcs.iter().find(|(e, _)| *e == entity_id).map_or(self.bundle_hlc, |(_, hlc)| *hlc)
+    }
+
+    /// Coalesce consecutive entries (oldest first) into one.
+    ///
+    /// Snapshots keep the earliest state per key (the state before the group),
+    /// and repeated writes to the same field or edge property keep only the last
+    /// payload, so the inverse restores pre-group values once and redo replays
+    /// the final values.
+    fn merge(entries: Vec<UndoEntry>) -> UndoEntry {
+        let mut bundle_ids = Vec::new();
+        let mut bundle_hlc = None;
+        let mut all_payloads: Vec<OperationPayload> = Vec::new();
+        let mut scopes: Vec<UndoScope> = Vec::new();
+        let mut field_hlcs: Vec<(EntityId, String, Hlc)> = Vec::new();
+        let mut entity_hlcs: Vec<(EntityId, Hlc)> = Vec::new();
+        let mut snapshot = PreExecutionSnapshot {
+            field_states: Vec::new(),
+            entity_states: Vec::new(),
+            edge_states: Vec::new(),
+            facet_states: Vec::new(),
+            edge_property_states: Vec::new(),
+        };
+
+        for entry in entries {
+            // Interference is judged from each bundle's own writes, so a
+            // remote write landing mid-group still counts
+            for f in &entry.snapshot.field_states {
+                let hlc = entry.field_hlc(f.entity_id, &f.field_key);
+                field_hlcs.retain(|(e, k, _)| !(*e == f.entity_id && *k == f.field_key));
+                field_hlcs.push((f.entity_id, f.field_key.clone(), hlc));
+            }
+            for e in &entry.snapshot.entity_states {
+                if !entity_hlcs.iter().any(|(id, _)| *id == e.entity_id) {
+                    entity_hlcs.push((e.entity_id, entry.entity_hlc(e.entity_id)));
+                }
+            }
+            bundle_ids.extend(entry.merged_bundle_ids);
+            bundle_ids.push(entry.bundle_id);
+            bundle_hlc = Some(entry.bundle_hlc);
+            all_payloads.extend(entry.payloads);
+            for scope in entry.scopes {
+                if !scopes.contains(&scope) {
+                    scopes.push(scope);
+                }
+            }
+
+            let snap = entry.snapshot;
+            for f in snap.field_states {
+                if !snapshot.field_states.iter().any(|s| s.entity_id == f.entity_id && s.field_key == f.field_key) {
+                    snapshot.field_states.push(f);
+                }
+            }
+            for e in snap.entity_states {
+                if !snapshot.entity_states.iter().any(|s| s.entity_id == e.entity_id) {
+                    snapshot.entity_states.push(e);
+                }
+            }
+            for e in snap.edge_states {
+                if !snapshot.edge_states.iter().any(|s| s.edge_id == e.edge_id) {
+                    snapshot.edge_states.push(e);
+                }
+            }
+            for f in snap.facet_states {
+                if !snapshot.facet_states.iter().any(|s| s.entity_id == f.entity_id && s.facet_type == f.facet_type) {
+                    snapshot.facet_states.push(f);
+                }
+            }
+            for p in snap.edge_property_states {
+                if !snapshot.edge_property_states.iter().any(|s| s.edge_id == p.edge_id && s.property_key == p.property_key) {
+                    snapshot.edge_property_states.push(p);
+                }
+            }
+        }
+
+        // Keep only the last write per field / edge property
+        let mut payloads: Vec<OperationPayload> = Vec::new();
+        for (i, payload) in all_payloads.iter().enumerate() {
+            let superseded = all_payloads[i + 1..].iter().any(|later| same_write_target(payload, later));
+            if !superseded {
+                payloads.push(payload.clone());
+            }
+        }
+
+        let bundle_id = bundle_ids.pop().expect("merge requires at least one entry");
+        UndoEntry {
+            bundle_id,
+            bundle_hlc: bundle_hlc.expect("merge requires at least one entry"),
+            merged_bundle_ids: bundle_ids,
+            field_hlcs,
+            entity_hlcs,
+            payloads,
+            snapshot,
+            scopes,
+        }
+    }
+}
+
+/// True if both payloads write the same field or edge property.
+fn same_write_target(a: &OperationPayload, b: &OperationPayload) -> bool {
+    fn field_target(p: &OperationPayload) -> Option<(EntityId, &str)> {
+        match p {
+            OperationPayload::SetField { entity_id, field_key, .. }
+            | OperationPayload::ClearField { entity_id, field_key } => Some((*entity_id, field_key.as_str())),
+            _ => None,
+        }
+    }
+    fn edge_property_target(p: &OperationPayload) -> Option<(EdgeId, &str)> {
+        match p {
+            OperationPayload::SetEdgeProperty { edge_id, property_key, .. }
+            | OperationPayload::ClearEdgeProperty { edge_id, property_key } => Some((*edge_id, property_key.as_str())),
+            _ => None,
+        }
+    }
+    (field_target(a).is_some() && field_target(a) == field_target(b))
+        || (edge_property_target(a).is_some() && edge_property_target(a) == edge_property_target(b))
+}
+
+/// Read-only view of an undo/redo entry for history menus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndoSummary {
+    pub bundle_id: BundleId,
+    pub hlc: Hlc,
+    /// Operation type names in bundle order (e.g. "SetField").
+    pub op_types: Vec<String>,
+    /// Entities touched by the bundle, deduplicated, in first-seen order.
+    pub entities: Vec<EntityId>,
+    /// Human label (local relabel or bundle meta), if any.
+    pub label: Option<String>,
+}
+
+impl UndoSummary {
+    pub fn from_entry(entry: &UndoEntry, label: Option<String>) -> Self {
+        let mut entities = Vec::new();
+        for payload in &entry.payloads {
+            if let Some(entity_id) = payload.entity_id()
+                && !entities.contains(&entity_id)
+            {
+                entities.push(entity_id);
+            }
+        }
+        Self {
+            bundle_id: entry.bundle_id,
+            hlc: entry.bundle_hlc,
+            op_types: entry.payloads.iter().map(|p| p.op_type_name().to_string()).collect(),
+            entities,
+            label,
+        }
+    }
 }
 
 pub struct PreExecutionSnapshot {
@@ -69,43 +279,167 @@ impl UndoManager {
         Self {
             undo_stack: VecDeque::new(),
             redo_stack: VecDeque::new(),
+            redo_floor: 0,
             max_depth,
+            group: None,
         }
     }
 
+    /// Undo steps kept per scope before the scope's oldest is dropped.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
     pub fn push_undo(
         &mut self,
         bundle_id: BundleId,
         hlc: Hlc,
         payloads: Vec<OperationPayload>,
         snapshot: PreExecutionSnapshot,
+        scopes: Vec<UndoScope>,
     ) {
         self.undo_stack.push_back(UndoEntry {
             bundle_id,
             bundle_hlc: hlc,
+            merged_bundle_ids: Vec::new(),
+            field_hlcs: Vec::new(),
+            entity_hlcs: Vec::new(),
             payloads,
             snapshot,
+            scopes,
         });
-        // Enforce depth limit by dropping oldest entry
-        if self.undo_stack.len() > self.max_depth {
-            self.undo_stack.pop_front();
+        if let Some(group) = &mut self.group {
+            group.count += 1;
+        }
+        self.enforce_depth();
+    }
+
+    /// Drop the oldest entries of any scope (or of the unscoped entries)
+    /// holding more than `max_depth`, so a busy scope can't push another
+    /// scope's history off the stack.
+    fn enforce_depth(&mut self) {
+        let Some(latest) = self.undo_stack.back() else {
+            return;
+        };
+        if latest.scopes.is_empty() {
+            while self.undo_stack.iter().filter(|e| e.scopes.is_empty()).count() > self.max_depth {
+                let index = self.undo_stack.iter().position(|e| e.scopes.is_empty()).expect("counted above");
+                self.remove_at(index);
+            }
+            return;
+        }
+        for scope in latest.scopes.clone() {
+            while self.undo_stack.iter().filter(|e| e.in_scope(&scope)).count() > self.max_depth {
+                let index = self.undo_stack.iter().position(|e| e.in_scope(&scope)).expect("counted above");
+                self.remove_at(index);
+            }
+        }
+    }
+
+    /// Remove an undo entry, keeping the open group's count to the entries
+    /// still on the stack.
+    fn remove_at(&mut self, index: usize) {
+        let len = self.undo_stack.len();
+        if let Some(group) = &mut self.group
+            && index >= len - group.count.min(len)
+        {
+            group.count -= 1;
         }
+        self.undo_stack.remove(index);
     }
 
     pub fn pop_undo(&mut self) -> Option<UndoEntry> {
         self.undo_stack.pop_back()
     }
 
+    /// Find an entry anywhere in the undo stack by its bundle id.
+    pub fn find_undo(&self, bundle_id: BundleId) -> Option<&UndoEntry> {
+        self.undo_stack.iter().find(|e| e.covers_bundle(bundle_id))
+    }
+
+    /// Remove an entry from anywhere in the undo stack by its bundle id.
+    pub fn remove_undo(&mut self, bundle_id: BundleId) -> Option<UndoEntry> {
+        let index = self.undo_stack.iter().position(|e| e.covers_bundle(bundle_id))?;
+        self.undo_stack.remove(index)
+    }
+
+    /// Bundle id of the most recent undo entry in `scope`.
+    pub fn latest_undo_in_scope(&self, scope: &UndoScope) -> Option<BundleId> {
+        self.undo_stack.iter().rev().find(|e| e.in_scope(scope)).map(|e| e.bundle_id)
+    }
+
+    /// Remove the most recent redo entry in `scope`, leaving other scopes untouched.
+    pub fn remove_latest_redo_in_scope(&mut self, scope: &UndoScope) -> Option<UndoEntry> {
+        let index = self.redo_stack.iter().rposition(|e| e.in_scope(scope))?;
+        if index < self.redo_floor {
+            self.redo_floor -= 1;
+        }
+        self.redo_stack.remove(index)
+    }
+
+    /// Open an undo group. Groups nest; only the outermost `end_group` merges.
+    pub fn begin_group(&mut self) {
+        match &mut self.group {
+            Some(group) => group.depth += 1,
+            None => self.group = Some(UndoGroup { depth: 1, count: 0 }),
+        }
+    }
+
+    /// Close one level of the open undo group. When the outermost level closes,
+    /// all entries pushed since it opened are merged into a single entry.
+    pub fn end_group(&mut self) {
+        if let Some(group) = &mut self.group {
+            group.depth -= 1;
+            if group.depth == 0 {
+                self.close_group();
+            }
+        }
+    }
+
+    /// Close the open undo group regardless of nesting depth.
+    pub fn close_group(&mut self) {
+        let Some(group) = self.group.take() else {
+            return;
+        };
+        // Entries may have been trimmed by the depth limit while the group was open
+        let count = group.count.min(self.undo_stack.len());
+        if count > 1 {
+            let entries: Vec<UndoEntry> = self.undo_stack.drain(self.undo_stack.len() - count..).collect();
+            self.undo_stack.push_back(UndoEntry::merge(entries));
+        }
+    }
+
     pub fn push_redo(&mut self, entry: UndoEntry) {
         self.redo_stack.push_back(entry);
     }
 
     pub fn pop_redo(&mut self) -> Option<UndoEntry> {
-        self.redo_stack.pop_back()
+        if self.redo_stack.len() > self.redo_floor {
+            self.redo_stack.pop_back()
+        } else {
+            None
+        }
+    }
+
+    /// Drop the redo entries a new edit in `scopes` invalidates: those sharing
+    /// a scope with it. Redo in other scopes survives; an unscoped edit
+    /// clears all redo, and unscoped redo entries never survive an edit. The
+    /// survivors are left to `redo_scope`, so a global redo finds nothing.
+    pub fn clear_redo(&mut self, scopes: &[UndoScope]) {
+        self.redo_stack.retain(|e| {
+            !scopes.is_empty() && !e.scopes.is_empty() && !e.scopes.iter().any(|scope| scopes.contains(scope))
+        });
+        self.redo_floor = self.redo_stack.len();
+    }
+
+    /// Undo entries, most recent first.
+    pub fn undo_entries(&self) -> impl Iterator<Item = &UndoEntry> {
+        self.undo_stack.iter().rev()
     }
 
-    pub fn clear_redo(&mut self) {
-        self.redo_stack.clear();
+    /// Redo entries a global redo would reach, most recent first.
+    pub fn redo_entries(&self) -> impl Iterator<Item = &UndoEntry> {
+        self.redo_stack.iter().skip(self.redo_floor).rev()
     }
 
     pub fn undo_depth(&self) -> usize {
@@ -113,13 +447,13 @@ impl UndoManager {
     }
 
     pub fn redo_depth(&self) -> usize {
-        self.redo_stack.len()
+        self.redo_stack.len() - self.redo_floor
     }
 
     /// Capture pre-execution snapshot by examining the payloads and querying current state.
     pub fn capture_snapshot(
         &self,
-        storage: &SqliteStorage,
+        storage: &impl Storage,
         payloads: &[OperationPayload],
     ) -> Result<PreExecutionSnapshot, StorageError> {
         let mut field_states = Vec::new();
@@ -159,15 +493,13 @@ impl UndoManager {
                     let fields = storage.get_fields(*entity_id)?;
 
                     // Also snapshot all connected edges (both from and to)
-                    let edges_from = storage.get_edges_from(*entity_id)?;
-                    let edges_to = storage.get_edges_to(*entity_id)?;
+                    let edges_from = storage.get_edges_from(*entity_id, &EdgeFilter::live())?;
+                    let edges_to = storage.get_edges_to(*entity_id, &EdgeFilter::live())?;
                     for edge in edges_from.iter().chain(edges_to.iter()) {
-                        if !edge.deleted {
-                            edge_states.push(EdgeSnapshot {
-                                edge_id: edge.edge_id,
-                                previous_state: Some(edge.clone()),
-                            });
-                        }
+                        edge_states.push(EdgeSnapshot {
+                            edge_id: edge.edge_id,
+                            previous_state: Some(edge.clone()),
+                        });
                     }
 
                     entity_states.push(EntitySnapshot {
@@ -329,6 +661,120 @@ impl UndoManager {
         })
     }
 
+    /// Reconstruct the pre-execution snapshot of a historical bundle from the oplog.
+    ///
+    /// Field and edge property values come from the latest op with an HLC strictly
+    /// before `before`, read from the entity's or edge's own ops rather than the
+    /// whole oplog. Edges referenced by deletes use their current record, which is
+    /// all `compute_inverse` needs to emit `RestoreEdge`.
+    pub fn capture_historical_snapshot(
+        &self,
+        storage: &impl Storage,
+        payloads: &[OperationPayload],
+        before: Hlc,
+    ) -> Result<PreExecutionSnapshot, StorageError> {
+        let mut field_states = Vec::new();
+        let mut edge_states = Vec::new();
+        let mut edge_property_states = Vec::new();
+
+        for payload in payloads {
+            match payload {
+                OperationPayload::SetField { entity_id, field_key, .. }
+                | OperationPayload::ClearField { entity_id, field_key } => {
+                    field_states.push(FieldSnapshot {
+                        entity_id: *entity_id,
+                        field_key: field_key.clone(),
+                        previous_value: None,
+                        previous_metadata: None,
+                    });
+                }
+                OperationPayload::SetEdgeProperty { edge_id, property_key, .. }
+                | OperationPayload::ClearEdgeProperty { edge_id, property_key } => {
+                    edge_property_states.push(EdgePropertySnapshot {
+                        edge_id: *edge_id,
+                        property_key: property_key.clone(),
+                        previous_value: None,
+                        previous_metadata: None,
+                    });
+                }
+                OperationPayload::DeleteEntity { cascade_edges, .. } => {
+                    for edge_id in cascade_edges {
+                        edge_states.push(EdgeSnapshot {
+                            edge_id: *edge_id,
+                            previous_state: storage.get_edge(*edge_id)?,
+                        });
+                    }
+                }
+                OperationPayload::DeleteEdge { edge_id } => {
+                    edge_states.push(EdgeSnapshot {
+                        edge_id: *edge_id,
+                        previous_state: storage.get_edge(*edge_id)?,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        // Replay each target's own history in canonical order; the last
+        // write before `before` wins
+        let mut entities: Vec<EntityId> = field_states.iter().map(|s| s.entity_id).collect();
+        entities.sort();
+        entities.dedup();
+        for entity_id in entities {
+            for op in storage.get_ops_by_entity(entity_id)? {
+                if op.hlc >= before {
+                    break;
+                }
+                let metadata = Some((op.actor_id, op.hlc));
+                let (field_key, value) = match &op.payload {
+                    OperationPayload::SetField { field_key, value, .. } => (field_key, Some(value.clone())),
+                    OperationPayload::ClearField { field_key, .. } => (field_key, None),
+                    OperationPayload::ResolveConflict { field_key, chosen_value, .. } => (field_key, chosen_value.clone()),
+                    _ => continue,
+                };
+                for snap in field_states.iter_mut().filter(|s| s.entity_id == entity_id && s.field_key == *field_key) {
+                    snap.previous_value = value.clone();
+                    snap.previous_metadata = metadata;
+                }
+            }
+        }
+
+        let mut edges: Vec<EdgeId> = edge_property_states.iter().map(|s| s.edge_id).collect();
+        edges.sort();
+        edges.dedup();
+        for edge_id in edges {
+            for op in storage.get_edge_property_ops(edge_id)? {
+                if op.hlc >= before {
+                    break;
+                }
+                let metadata = Some((op.actor_id, op.hlc));
+                let writes: Vec<(&String, Option<FieldValue>)> = match &op.payload {
+                    OperationPayload::CreateEdge { properties, .. }
+                    | OperationPayload::CreateOrderedEdge { properties, .. } => {
+                        properties.iter().map(|(key, value)| (key, Some(value.clone()))).collect()
+                    }
+                    OperationPayload::SetEdgeProperty { property_key, value, .. } => vec![(property_key, Some(value.clone()))],
+                    OperationPayload::ClearEdgeProperty { property_key, .. } => vec![(property_key, None)],
+                    _ => continue,
+                };
+                for (key, value) in writes {
+                    for snap in edge_property_states.iter_mut().filter(|s| s.edge_id == edge_id && s.property_key == *key) {
+                        snap.previous_value = value.clone();
+                        snap.previous_metadata = metadata;
+                    }
+                }
+            }
+        }
+
+        Ok(PreExecutionSnapshot {
+            field_states,
+            entity_states: Vec::new(),
+            edge_states,
+            facet_states: Vec::new(),
+            edge_property_states,
+        })
+    }
+
     /// Compute inverse operations from a snapshot and original payloads.
     pub fn compute_inverse(&self, entry: &UndoEntry) -> Vec<OperationPayload> {
         let mut inverse = Vec::new();
@@ -464,6 +910,15 @@ impl UndoManager {
                     inverse.push(OperationPayload::DeleteEdge { edge_id: *edge_id });
                 }
 
+                OperationPayload::RestoreFacet { entity_id, facet_type } => {
+                    // Inverse of restore = detach again, keeping a fresh snapshot for redo
+                    inverse.push(OperationPayload::DetachFacet {
+                        entity_id: *entity_id,
+                        facet_type: facet_type.clone(),
+                        preserve_values: true,
+                    });
+                }
+
                 OperationPayload::SetEdgeProperty {
                     edge_id,
                     property_key,
@@ -516,3 +971,526 @@ impl UndoManager {
         inverse
     }
 }
+
+impl<S: EngineStorage> Engine<S> {
+    // ========================================================================
+    // Undo / Redo
+    // ========================================================================
+
+    /// Undo the most recent undoable command.
+    /// Returns `Applied(bundle_id)` if undo was successful.
+    /// Returns `Skipped { conflicts }` if another actor modified the same fields (skip-and-advance).
+    /// Returns `Empty` if there's nothing to undo.
+    pub fn undo(&mut self) -> Result<UndoResult, EngineError> {
+        // Undoing mid-burst reverts the burst so far as one step
+        self.undo_manager.close_group();
+
+        let entry = match self.undo_manager.pop_undo() {
+            Some(entry) => entry,
+            None => return Ok(UndoResult::Empty),
+        };
+
+        // Check for conflicts: another actor modified the same fields after the
+        // original bundle was executed
+        let conflicts = self.undo_conflicts(&entry, false)?;
+
+        // If conflicts, skip and advance (entry is consumed)
+        if !conflicts.is_empty() {
+            return Ok(UndoResult::Skipped { conflicts });
+        }
+
+        self.apply_undo_entry(entry)
+    }
+
+    /// Open an undo group: every undoable command until the matching
+    /// `end_undo_group` is undone (and redone) as a single step. Use this to
+    /// coalesce bursts such as per-keystroke `set_field` calls. Groups nest.
+    pub fn begin_undo_group(&mut self) {
+        self.undo_manager.begin_group();
+    }
+
+    /// Close the innermost open undo group.
+    pub fn end_undo_group(&mut self) {
+        self.undo_manager.end_group();
+    }
+
+    /// Selectively undo a specific bundle that is still on the undo stack, not
+    /// just the most recent one.
+    ///
+    /// Intermediate edits interfere too: if any later write (ours or another
+    /// actor's) touched the same fields, returns `Skipped` and leaves the entry on
+    /// the stack. Returns `Empty` if the bundle is not on the undo stack.
+    pub fn undo_bundle(&mut self, bundle_id: BundleId) -> Result<UndoResult, EngineError> {
+        self.undo_manager.close_group();
+
+        let conflicts = match self.undo_manager.find_undo(bundle_id) {
+            Some(entry) => self.undo_conflicts(entry, true)?,
+            None => return Ok(UndoResult::Empty),
+        };
+        if !conflicts.is_empty() {
+            return Ok(UndoResult::Skipped { conflicts });
+        }
+
+        match self.undo_manager.remove_undo(bundle_id) {
+            Some(entry) => self.apply_undo_entry(entry),
+            None => Ok(UndoResult::Empty),
+        }
+    }
+
+    /// Summaries of the undo stack, most recent first (for an "Undo History" menu).
+    pub fn undo_stack(&self) -> Result<Vec<UndoSummary>, EngineError> {
+        self.undo_manager
+            .undo_entries()
+            .map(|entry| Ok(UndoSummary::from_entry(entry, self.bundle_label(entry.bundle_id)?)))
+            .collect()
+    }
+
+    /// Summaries of the redo stack, most recent first. Entries only
+    /// `redo_scope` can still reach are left out.
+    pub fn redo_stack(&self) -> Result<Vec<UndoSummary>, EngineError> {
+        self.undo_manager
+            .redo_entries()
+            .map(|entry| Ok(UndoSummary::from_entry(entry, self.bundle_label(entry.bundle_id)?)))
+            .collect()
+    }
+
+    /// Fields modified after an undo entry's bundle, which make undoing it unsafe.
+    /// Writes by the local actor only count when `include_own` is set.
+    fn undo_conflicts(
+        &self,
+        entry: &UndoEntry,
+        include_own: bool,
+    ) -> Result<Vec<UndoConflict>, EngineError> {
+        // Writes by this engine's earlier (rotated) keys, and by the user's
+        // other devices when grouping is enabled, are still our own
+        let groups = self.actor_groups()?;
+        let me = self.actor_id();
+        let mut conflicts = Vec::new();
+
+        for field_snap in &entry.snapshot.field_states {
+            if let Some((actor, hlc)) = self.storage.get_field_metadata(
+                field_snap.entity_id,
+                &field_snap.field_key,
+            )?
+                && (include_own || !groups.same(me, actor))
+                && hlc > entry.field_hlc(field_snap.entity_id, &field_snap.field_key)
+            {
+                conflicts.push(UndoConflict {
+                    entity_id: field_snap.entity_id,
+                    field_key: field_snap.field_key.clone(),
+                    modified_by: actor,
+                });
+            }
+        }
+
+        // Also check entity-level conflicts: if undoing a CreateEntity,
+        // check if any other actor wrote fields to it
+        for entity_snap in &entry.snapshot.entity_states {
+            // Undoing a restore deletes the entity again, along with edits made since
+            if entity_snap.existed == Some(true) {
+                for (field_key, _) in &self.storage.get_fields(entity_snap.entity_id)? {
+                    if let Some((actor, hlc)) = self.storage.get_field_metadata(entity_snap.entity_id, field_key)? {
+                        // Our own writes count after the entry's last write to
+                        // the field, anyone else's after it restored the entity
+                        let own = groups.same(me, actor);
+                        let since = if own {
+                            entry.field_hlc(entity_snap.entity_id, field_key)
+                        } else {
+                            entry.entity_hlc(entity_snap.entity_id)
+                        };
+                        if (include_own || !own) && hlc > since {
+                            conflicts.push(UndoConflict {
+                                entity_id: entity_snap.entity_id,
+                                field_key: field_key.clone(),
+                                modified_by: actor,
+                            });
+                        }
+                    }
+                }
+            }
+            // If entity didn't exist before (we're undoing a create), check if others wrote to it
+            if entity_snap.existed.is_none() {
+                let fields = self.storage.get_fields(entity_snap.entity_id)?;
+                for (field_key, _) in &fields {
+                    if let Some((actor, hlc)) = self.storage.get_field_metadata(
+                        entity_snap.entity_id,
+                        field_key,
+                    )?
+                        && (!groups.same(me, actor)
+                            || (include_own && hlc > entry.field_hlc(entity_snap.entity_id, field_key)))
+                    {
+                        conflicts.push(UndoConflict {
+                            entity_id: entity_snap.entity_id,
+                            field_key: field_key.clone(),
+                            modified_by: actor,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(conflicts)
+    }
+
+    /// Execute the inverse of an undo entry and move the entry to the redo stack.
+    fn apply_undo_entry(&mut self, entry: UndoEntry) -> Result<UndoResult, EngineError> {
+        // Compute inverse operations
+        let mut inverse = self.undo_manager.compute_inverse(&entry);
+
+        // For CreateEntity undo -> DeleteEntity, compute fresh cascade_edges from storage
+        self.refresh_cascade_edges(&mut inverse)?;
+
+        // Execute inverse as non-undoable
+        let (bundle_id, _) = self.execute_internal(BundleType::UserEdit, inverse, false)?;
+
+        // Push original entry to redo stack
+        self.undo_manager.push_redo(entry);
+
+        Ok(UndoResult::Applied(bundle_id))
+    }
+
+    /// Redo the most recently undone command.
+    /// Returns `Applied(bundle_id)` if redo was successful.
+    /// Returns `Empty` if there's nothing to redo.
+    pub fn redo(&mut self) -> Result<UndoResult, EngineError> {
+        match self.undo_manager.pop_redo() {
+            Some(entry) => self.redo_entry(entry),
+            None => Ok(UndoResult::Empty),
+        }
+    }
+
+    /// Re-execute an undone entry and push it back onto the undo stack.
+    fn redo_entry(&mut self, entry: UndoEntry) -> Result<UndoResult, EngineError> {
+        // Fix up payloads for current DB state (soft-deleted entities/edges
+        // need RestoreEntity/RestoreEdge instead of CreateEntity/CreateEdge)
+        let mut fixed_payloads = Vec::new();
+        for payload in &entry.payloads {
+            match payload {
+                OperationPayload::CreateEntity { entity_id, initial_table } => {
+                    // Check if entity exists and is soft-deleted
+                    if let Some(entity) = self.storage.get_entity(*entity_id)?
+                        && entity.deleted
+                    {
+                        // Entity exists but is soft-deleted — restore it
+                        fixed_payloads.push(OperationPayload::RestoreEntity {
+                            entity_id: *entity_id,
+                        });
+                        // Re-attach facet if there was an initial_table
+                        if let Some(facet_type) = initial_table {
+                            // Check if the facet is detached and needs reattaching
+                            let facets = self.storage.get_facets(*entity_id)?;
+                            let facet_exists = facets.iter().any(|f| f.facet_type == *facet_type);
+                            if !facet_exists {
+                                fixed_payloads.push(OperationPayload::AttachFacet {
+                                    entity_id: *entity_id,
+                                    facet_type: facet_type.clone(),
+                                });
+                            }
+                        }
+                        continue;
+                    }
+                    // Entity doesn't exist or isn't deleted — use original payload
+                    fixed_payloads.push(payload.clone());
+                }
+
+                OperationPayload::CreateEdge { edge_id, .. } => {
+                    // Check if edge exists and is soft-deleted
+                    if let Some(edge) = self.storage.get_edge(*edge_id)?
+                        && edge.deleted
+                    {
+                        // Edge exists but is soft-deleted — restore it
+                        fixed_payloads.push(OperationPayload::RestoreEdge {
+                            edge_id: *edge_id,
+                        });
+                        continue;
+                    }
+                    // Edge doesn't exist or isn't deleted — use original payload
+                    fixed_payloads.push(payload.clone());
+                }
+
+                _ => {
+                    fixed_payloads.push(payload.clone());
+                }
+            }
+        }
+
+        // Capture snapshot for the fixed payloads (so this redo can be undone)
+        let snapshot = self.undo_manager.capture_snapshot(&self.storage, &fixed_payloads)?;
+        let scopes = self.undo_scopes(&fixed_payloads)?;
+
+        // Execute the fixed payloads (not self-undoable — we manage stack manually)
+        let (bundle_id, hlc) = self.execute_internal(BundleType::UserEdit, fixed_payloads.clone(), false)?;
+
+        // Push new undo entry so this redo can be undone
+        self.undo_manager.push_undo(bundle_id, hlc, fixed_payloads, snapshot, scopes);
+
+        Ok(UndoResult::Applied(bundle_id))
+    }
+
+    /// Undo the most recent command in `scope` (an entity or a facet type),
+    /// leaving commands in other scopes on the stack.
+    ///
+    /// Like `undo()`, an entry whose fields were modified since is skipped and
+    /// consumed, so the next call advances to the previous entry in the scope.
+    /// Returns `Empty` if the scope has nothing to undo.
+    pub fn undo_scope(&mut self, scope: &UndoScope) -> Result<UndoResult, EngineError> {
+        self.undo_manager.close_group();
+
+        let Some(bundle_id) = self.undo_manager.latest_undo_in_scope(scope) else {
+            return Ok(UndoResult::Empty);
+        };
+        let entry = match self.undo_manager.remove_undo(bundle_id) {
+            Some(entry) => entry,
+            None => return Ok(UndoResult::Empty),
+        };
+
+        let conflicts = self.undo_conflicts(&entry, true)?;
+        if !conflicts.is_empty() {
+            return Ok(UndoResult::Skipped { conflicts });
+        }
+
+        self.apply_undo_entry(entry)
+    }
+
+    /// Redo the most recently undone command in `scope`.
+    /// Returns `Empty` if the scope has nothing to redo.
+    pub fn redo_scope(&mut self, scope: &UndoScope) -> Result<UndoResult, EngineError> {
+        match self.undo_manager.remove_latest_redo_in_scope(scope) {
+            Some(entry) => self.redo_entry(entry),
+            None => Ok(UndoResult::Empty),
+        }
+    }
+
+    /// Scopes touched by a command: every entity it references (edge endpoints
+    /// included) and every facet those entities carry before it runs.
+    pub(crate) fn undo_scopes(&self, payloads: &[OperationPayload]) -> Result<Vec<UndoScope>, EngineError> {
+        let mut entities = Vec::new();
+        let mut facets = Vec::new();
+        for payload in payloads {
+            if let Some(entity_id) = payload.entity_id() {
+                entities.push(entity_id);
+            }
+            match payload {
+                OperationPayload::CreateEntity { initial_table: Some(facet_type), .. }
+                | OperationPayload::AttachFacet { facet_type, .. }
+                | OperationPayload::DetachFacet { facet_type, .. } => facets.push(facet_type.clone()),
+                OperationPayload::CreateEdge { source_id, target_id, .. } => {
+                    entities.push(*source_id);
+                    entities.push(*target_id);
+                }
+                OperationPayload::DeleteEdge { edge_id }
+                | OperationPayload::RestoreEdge { edge_id }
+                | OperationPayload::SetEdgeProperty { edge_id, .. }
+                | OperationPayload::ClearEdgeProperty { edge_id, .. } => {
+                    if let Some(edge) = self.storage.get_edge(*edge_id)? {
+                        entities.push(edge.source_id);
+                        entities.push(edge.target_id);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut scopes = Vec::new();
+        for entity_id in entities {
+            for facet in self.storage.get_facets(entity_id)? {
+                if !facet.detached {
+                    facets.push(facet.facet_type);
+                }
+            }
+            let scope = UndoScope::Entity(entity_id);
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+        for facet_type in facets {
+            let scope = UndoScope::Facet(facet_type);
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+        Ok(scopes)
+    }
+
+    /// Fill `cascade_edges` on inverse DeleteEntity payloads from live storage state.
+    fn refresh_cascade_edges(&self, payloads: &mut [OperationPayload]) -> Result<(), EngineError> {
+        for payload in payloads {
+            if let OperationPayload::DeleteEntity { entity_id, cascade_edges } = payload {
+                let edges_from = self.storage.get_edges_from(*entity_id, &EdgeFilter::live())?;
+                let edges_to = self.storage.get_edges_to(*entity_id, &EdgeFilter::live())?;
+                *cascade_edges = edges_from.iter().chain(edges_to.iter()).map(|e| e.edge_id).collect();
+            }
+        }
+        Ok(())
+    }
+
+    // ========================================================================
+    // Revert (Compensating Bundles)
+    // ========================================================================
+
+    /// Revert any historical bundle (local or ingested) by emitting a new signed
+    /// bundle of inverse operations. Unlike `undo()`, this is not limited to the
+    /// local undo stack.
+    ///
+    /// Fields written after the reverted bundle (by anyone, including us) are left
+    /// alone and reported in `skipped`, edge properties in
+    /// `skipped_edge_properties`; everything else is reverted. The compensating
+    /// bundle is itself undoable.
+    pub fn revert_bundle(&mut self, bundle_id: BundleId) -> Result<RevertResult, EngineError> {
+        let bundle = self.storage.get_bundle(bundle_id)?
+            .ok_or_else(|| EngineError::BundleNotFound(bundle_id.to_string()))?;
+        let mut ops = self.storage.get_ops_by_bundle(bundle_id)?;
+        ops.sort();
+        self.revert_ops(bundle_id, ops, bundle.hlc, bundle.hlc)
+    }
+
+    /// Revert every bundle of a closed editing session in one compensating
+    /// bundle, putting what the session touched back to how it was before the
+    /// session's first bundle. As with `revert_bundle`, fields written after
+    /// the session's last bundle are left alone and reported in `skipped`.
+    pub fn revert_session(&mut self, session_id: SessionId) -> Result<RevertResult, EngineError> {
+        if self.active_session() == Some(session_id) {
+            return Err(EngineError::Rejected(format!("session {session_id} is still open")));
+        }
+        let bundles = self.storage.get_bundles_by_session(session_id)?;
+        let (Some(first), Some(last)) = (bundles.first(), bundles.last()) else {
+            return Err(EngineError::Rejected(format!("session {session_id} has no bundles")));
+        };
+        let (first_hlc, last_hlc, bundle_id) = (first.hlc, last.hlc, last.bundle_id);
+
+        let mut ops = Vec::new();
+        for bundle in &bundles {
+            ops.extend(self.storage.get_ops_by_bundle(bundle.bundle_id)?);
+        }
+        ops.sort();
+        // Inverting the first op on each target restores its pre-session state
+        let mut seen = HashSet::new();
+        ops.retain(|op| revert_target(&op.payload).is_none_or(|target| seen.insert(target)));
+        self.revert_ops(bundle_id, ops, first_hlc, last_hlc)
+    }
+
+    /// Emit the inverse of `ops` (in canonical order), restoring values as
+    /// they were before `since` and skipping anything changed after `until`.
+    fn revert_ops(
+        &mut self,
+        bundle_id: BundleId,
+        ops: Vec<Operation>,
+        since: Hlc,
+        until: Hlc,
+    ) -> Result<RevertResult, EngineError> {
+        // Drop payloads whose effect has since been superseded
+        let mut skipped = Vec::new();
+        let mut skipped_edge_properties = Vec::new();
+        let mut payloads = Vec::new();
+        for op in ops {
+            let superseded = match &op.payload {
+                OperationPayload::SetField { entity_id, field_key, .. }
+                | OperationPayload::ClearField { entity_id, field_key } => {
+                    match self.storage.get_field_metadata(*entity_id, field_key)? {
+                        Some((actor, hlc)) if hlc > until => {
+                            skipped.push(UndoConflict {
+                                entity_id: *entity_id,
+                                field_key: field_key.clone(),
+                                modified_by: actor,
+                            });
+                            true
+                        }
+                        _ => false,
+                    }
+                }
+                OperationPayload::SetEdgeProperty { edge_id, property_key, .. }
+                | OperationPayload::ClearEdgeProperty { edge_id, property_key } => {
+                    match self.storage.get_edge_property_metadata(*edge_id, property_key)? {
+                        Some((actor, hlc)) if hlc > until => {
+                            skipped_edge_properties.push(SkippedEdgeProperty {
+                                edge_id: *edge_id,
+                                property_key: property_key.clone(),
+                                modified_by: actor,
+                            });
+                            true
+                        }
+                        _ => false,
+                    }
+                }
+                // Already deleted / restored since: nothing to revert
+                OperationPayload::CreateEntity { entity_id, .. } => {
+                    self.storage.get_entity(*entity_id)?.is_none_or(|e| e.deleted)
+                }
+                OperationPayload::DeleteEntity { entity_id, .. } => {
+                    self.storage.get_entity(*entity_id)?.is_some_and(|e| !e.deleted)
+                }
+                OperationPayload::CreateEdge { edge_id, .. } => {
+                    self.storage.get_edge(*edge_id)?.is_none_or(|e| e.deleted)
+                }
+                OperationPayload::DeleteEdge { edge_id } => {
+                    self.storage.get_edge(*edge_id)?.is_some_and(|e| !e.deleted)
+                }
+                _ => false,
+            };
+            if !superseded {
+                payloads.push(op.payload);
+            }
+        }
+
+        let snapshot = self.undo_manager.capture_historical_snapshot(&self.storage, &payloads, since)?;
+        let entry = UndoEntry {
+            bundle_id,
+            bundle_hlc: since,
+            merged_bundle_ids: Vec::new(),
+            field_hlcs: Vec::new(),
+            entity_hlcs: Vec::new(),
+            payloads,
+            snapshot,
+            scopes: Vec::new(),
+        };
+        let mut inverse = self.undo_manager.compute_inverse(&entry);
+        self.refresh_cascade_edges(&mut inverse)?;
+
+        if inverse.is_empty() {
+            return Ok(RevertResult { bundle_id: None, skipped, skipped_edge_properties });
+        }
+
+        let (revert_bundle_id, _) = self.execute_internal(BundleType::UserEdit, inverse, true)?;
+        Ok(RevertResult {
+            bundle_id: Some(revert_bundle_id),
+            skipped,
+            skipped_edge_properties,
+        })
+    }
+}
+
+/// What a revertible op changes; `revert_session` inverts only the first op
+/// on each target, so later writes in the session don't shadow the original state.
+#[derive(PartialEq, Eq, Hash)]
+enum RevertTarget {
+    Entity(EntityId),
+    Field(EntityId, String),
+    Facet(EntityId, String),
+    Edge(EdgeId),
+    EdgeProperty(EdgeId, String),
+}
+
+fn revert_target(payload: &OperationPayload) -> Option<RevertTarget> {
+    match payload {
+        OperationPayload::CreateEntity { entity_id, .. }
+        | OperationPayload::DeleteEntity { entity_id, .. }
+        | OperationPayload::RestoreEntity { entity_id } => Some(RevertTarget::Entity(*entity_id)),
+        OperationPayload::SetField { entity_id, field_key, .. } | OperationPayload::ClearField { entity_id, field_key } => {
+            Some(RevertTarget::Field(*entity_id, field_key.clone()))
+        }
+        OperationPayload::AttachFacet { entity_id, facet_type }
+        | OperationPayload::DetachFacet { entity_id, facet_type, .. }
+        | OperationPayload::RestoreFacet { entity_id, facet_type } => {
+            Some(RevertTarget::Facet(*entity_id, facet_type.clone()))
+        }
+        OperationPayload::CreateEdge { edge_id, .. }
+        | OperationPayload::CreateOrderedEdge { edge_id, .. }
+        | OperationPayload::DeleteEdge { edge_id }
+        | OperationPayload::RestoreEdge { edge_id } => Some(RevertTarget::Edge(*edge_id)),
+        OperationPayload::SetEdgeProperty { edge_id, property_key, .. }
+        | OperationPayload::ClearEdgeProperty { edge_id, property_key } => {
+            Some(RevertTarget::EdgeProperty(*edge_id, property_key.clone()))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,94 @@
+//! Unique edge types.
+//!
+//! Each declaration is a system entity with the `UNIQUE_EDGE_TYPE_FACET`
+//! facet holding the declared `edge_type`, so it syncs to every peer and all
+//! of them merge duplicate edges alike. Declarations only ever add types:
+//! every `edge_type` ever written to such an entity counts. Declared types
+//! are cached in storage, where materialization reads them, after every
+//! local declaration or ingest.
+
+use std::collections::BTreeSet;
+
+use openprod_core::{
+    field_value::FieldValue,
+    ids::EntityId,
+    operations::{BundleType, OperationPayload},
+};
+use openprod_storage::{EdgeFilter, EdgeRecord, EngineStorage};
+
+use crate::Engine;
+use crate::error::EngineError;
+
+/// Facet marking unique edge type declarations.
+pub const UNIQUE_EDGE_TYPE_FACET: &str = "openprod.unique_edge_type";
+
+pub(crate) const EDGE_TYPE_FIELD: &str = "edge_type";
+
+impl<S: EngineStorage> Engine<S> {
+    /// Allow at most one live edge of `edge_type` per (source, target) pair,
+    /// on every peer: the declaration syncs like other data. `create_edge`
+    /// then reuses (or restores) the existing edge, and duplicates are merged
+    /// wherever they meet, including ones materialized before the declaration
+    /// arrived: the earliest created wins and the rest are deleted. A
+    /// declaration can't be withdrawn. No-op if `edge_type` is already unique.
+    pub fn declare_unique_edge_type(&mut self, edge_type: &str) -> Result<(), EngineError> {
+        if self.unique_edge_types()?.iter().any(|t| t == edge_type) {
+            return Ok(());
+        }
+        let entity_id = EntityId::new();
+        let payloads = vec![
+            OperationPayload::CreateEntity {
+                entity_id,
+                initial_table: Some(UNIQUE_EDGE_TYPE_FACET.to_string()),
+            },
+            OperationPayload::SetField {
+                entity_id,
+                field_key: EDGE_TYPE_FIELD.to_string(),
+                value: FieldValue::Text(edge_type.to_string()),
+            },
+        ];
+        self.execute_internal(BundleType::System, payloads, false)?;
+        self.refresh_unique_edge_types()
+    }
+
+    /// Edge types declared unique, in name order.
+    pub fn unique_edge_types(&self) -> Result<Vec<String>, EngineError> {
+        Ok(self.storage.list_unique_edge_types()?)
+    }
+
+    /// Cache newly declared unique edge types in storage, merging the
+    /// duplicates already materialized for them.
+    pub(crate) fn refresh_unique_edge_types(&mut self) -> Result<(), EngineError> {
+        let known = self.storage.list_unique_edge_types()?;
+        let mut declared = BTreeSet::new();
+        for entity_id in self.storage.get_entities_by_facet(UNIQUE_EDGE_TYPE_FACET)? {
+            for op in self.storage.get_ops_by_entity(entity_id)? {
+                if let OperationPayload::SetField { field_key, value: FieldValue::Text(edge_type), .. } = op.payload
+                    && field_key == EDGE_TYPE_FIELD
+                    && !known.contains(&edge_type)
+                {
+                    declared.insert(edge_type);
+                }
+            }
+        }
+        for edge_type in declared {
+            self.storage.add_unique_edge_type(&edge_type)?;
+        }
+        Ok(())
+    }
+
+    /// The edge a unique `edge_type` keeps between `source_id` and `target_id`
+    /// (live or deleted), or None if the type isn't unique or no edge exists.
+    pub(crate) fn canonical_unique_edge(
+        &self,
+        edge_type: &str,
+        source_id: EntityId,
+        target_id: EntityId,
+    ) -> Result<Option<EdgeRecord>, EngineError> {
+        if !self.storage.list_unique_edge_types()?.iter().any(|t| t == edge_type) {
+            return Ok(None);
+        }
+        let edges = self.storage.get_edges_between(source_id, target_id, &EdgeFilter::all().of_type(edge_type))?;
+        Ok(edges.into_iter().min_by_key(|edge| (edge.created_at, edge.edge_id)))
+    }
+}
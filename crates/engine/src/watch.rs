@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::{Receiver, Sender};
+
+use openprod_core::{field_value::FieldValue, ids::*, operations::OperationPayload};
+use openprod_storage::{ConflictRecord, EdgeRecord};
+
+use crate::query::EntityQuery;
+
+/// An entity as a detail view shows it: fields (with the active overlay
+/// applied), attached facets, and live edges in either direction.
+#[derive(Debug, Clone)]
+pub struct EntityView {
+    pub entity_id: EntityId,
+    pub deleted: bool,
+    pub fields: BTreeMap<String, FieldValue>,
+    pub facets: BTreeSet<String>,
+    pub edges: BTreeMap<EdgeId, EdgeRecord>,
+}
+
+/// One change to a watched entity.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityChange {
+    Deleted,
+    Restored,
+    FieldSet { field_key: String, value: FieldValue },
+    FieldCleared { field_key: String },
+    FacetAttached { facet_type: String },
+    FacetDetached { facet_type: String },
+    EdgeAdded { edge_id: EdgeId, edge_type: String, source_id: EntityId, target_id: EntityId },
+    EdgeRemoved { edge_id: EdgeId },
+}
+
+/// A conflict needing attention, as sent to `Engine::watch_conflicts`
+/// receivers once the ingest that caused it commits.
+#[derive(Debug, Clone)]
+pub enum ConflictEvent {
+    /// Newly detected.
+    Opened(ConflictRecord),
+    /// A resolved conflict reopened by a late concurrent edit.
+    Reopened(ConflictRecord),
+    /// An open conflict gained another branch.
+    Extended(ConflictRecord),
+}
+
+/// Returned by `Engine::watch_entity`: the entity as it is now, then every
+/// later change to it. Dropping `updates` ends the watch.
+#[derive(Debug)]
+pub struct EntityWatch {
+    pub view: EntityView,
+    pub updates: Receiver<EntityChange>,
+}
+
+/// Engine-side end of an `EntityWatch`: the view last sent, to diff against.
+pub(crate) struct EntityWatcher {
+    pub(crate) last: EntityView,
+    pub(crate) sender: Sender<EntityChange>,
+}
+
+impl EntityView {
+    /// What changed between `self` and `next`: deletion state first, then
+    /// fields, facets and edges, each in key order.
+    pub(crate) fn changes_to(&self, next: &EntityView) -> Vec<EntityChange> {
+        let mut changes = Vec::new();
+        match (self.deleted, next.deleted) {
+            (false, true) => changes.push(EntityChange::Deleted),
+            (true, false) => changes.push(EntityChange::Restored),
+            _ => {}
+        }
+
+        for (field_key, value) in &next.fields {
+            if self.fields.get(field_key) != Some(value) {
+                changes.push(EntityChange::FieldSet { field_key: field_key.clone(), value: value.clone() });
+            }
+        }
+        for field_key in self.fields.keys().filter(|k| !next.fields.contains_key(*k)) {
+            changes.push(EntityChange::FieldCleared { field_key: field_key.clone() });
+        }
+
+        for facet_type in next.facets.difference(&self.facets) {
+            changes.push(EntityChange::FacetAttached { facet_type: facet_type.clone() });
+        }
+        for facet_type in self.facets.difference(&next.facets) {
+            changes.push(EntityChange::FacetDetached { facet_type: facet_type.clone() });
+        }
+
+        for (edge_id, edge) in &next.edges {
+            if !self.edges.contains_key(edge_id) {
+                changes.push(EntityChange::EdgeAdded {
+                    edge_id: *edge_id,
+                    edge_type: edge.edge_type.clone(),
+                    source_id: edge.source_id,
+                    target_id: edge.target_id,
+                });
+            }
+        }
+        for edge_id in self.edges.keys().filter(|id| !next.edges.contains_key(*id)) {
+            changes.push(EntityChange::EdgeRemoved { edge_id: *edge_id });
+        }
+        changes
+    }
+}
+
+/// What changed in a live query's results. Entities are listed in id order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryUpdate {
+    /// Entities that now match.
+    pub added: Vec<EntityId>,
+    /// Entities that no longer match (or were deleted).
+    pub removed: Vec<EntityId>,
+    /// Entities that still match but were written to.
+    pub changed: Vec<EntityId>,
+}
+
+impl QueryUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Returned by `Engine::watch_query`: the matching entities now (in id
+/// order), then an update after each write that changes them. Dropping
+/// `updates` ends the watch.
+#[derive(Debug)]
+pub struct QueryWatch {
+    pub results: Vec<EntityId>,
+    pub updates: Receiver<QueryUpdate>,
+}
+
+/// Engine-side end of a `QueryWatch`: the results last sent.
+pub(crate) struct QueryWatcher {
+    pub(crate) query: EntityQuery,
+    pub(crate) results: BTreeSet<EntityId>,
+    pub(crate) sender: Sender<QueryUpdate>,
+}
+
+/// What writes since the last notification touched, so live queries only
+/// re-check entities that may have changed, and only when the write involved
+/// their facet or fields.
+#[derive(Debug, Default)]
+pub(crate) struct ChangeSet {
+    pub(crate) entities: BTreeSet<EntityId>,
+    facets: BTreeSet<String>,
+    fields: BTreeSet<String>,
+    /// An entity was created, deleted or restored.
+    lifecycle: bool,
+    /// Something not attributable to entities (an overlay switch, a merge): re-run everything.
+    all: bool,
+}
+
+impl ChangeSet {
+    pub(crate) fn record(&mut self, payload: &OperationPayload) {
+        match payload {
+            OperationPayload::SetField { entity_id, field_key, .. }
+            | OperationPayload::ClearField { entity_id, field_key }
+            | OperationPayload::ApplyCrdt { entity_id, field_key, .. }
+            | OperationPayload::ClearAndAdd { entity_id, field_key, .. }
+            | OperationPayload::ResolveConflict { entity_id, field_key, .. } => {
+                self.entities.insert(*entity_id);
+                self.fields.insert(field_key.clone());
+            }
+            OperationPayload::AttachFacet { entity_id, facet_type }
+            | OperationPayload::DetachFacet { entity_id, facet_type, .. }
+            | OperationPayload::RestoreFacet { entity_id, facet_type }
+            | OperationPayload::RemoveFromTable { entity_id, table: facet_type, .. } => {
+                self.entities.insert(*entity_id);
+                self.facets.insert(facet_type.clone());
+            }
+            OperationPayload::AddToTable { entity_id, table, defaults } => {
+                self.entities.insert(*entity_id);
+                self.facets.insert(table.clone());
+                self.fields.extend(defaults.iter().map(|(key, _)| key.clone()));
+            }
+            OperationPayload::CreateEntity { entity_id, .. }
+            | OperationPayload::DeleteEntity { entity_id, .. }
+            | OperationPayload::RestoreEntity { entity_id } => {
+                self.entities.insert(*entity_id);
+                self.lifecycle = true;
+            }
+            OperationPayload::MergeEntities { .. }
+            | OperationPayload::SplitEntity { .. }
+            | OperationPayload::LinkTables { .. }
+            | OperationPayload::UnlinkTables { .. } => self.all = true,
+            // Edges and rules don't affect query membership
+            payload => self.entities.extend(payload.entity_id()),
+        }
+    }
+
+    pub(crate) fn mark_all(&mut self) {
+        self.all = true;
+    }
+
+    pub(crate) fn is_all(&self) -> bool {
+        self.all
+    }
+
+    /// Whether membership in `query` may have changed, as opposed to only
+    /// the contents of entities already in it.
+    pub(crate) fn affects(&self, query: &EntityQuery) -> bool {
+        self.all
+            || self.lifecycle
+            || self.facets.contains(query.facet_type())
+            || query.conditions().iter().any(|c| self.fields.contains(c.field_key()))
+    }
+}
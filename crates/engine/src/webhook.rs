@@ -0,0 +1,307 @@
+//! Outbound webhooks: change events (see `cdc`) POSTed as JSON to registered
+//! endpoints after each commit, local or ingested.
+//!
+//! Each endpoint keeps its own position in the change stream, attempt count
+//! and retry time in storage, so delivery picks up where it stopped after a
+//! restart. Delivery is at least once: a batch whose POST failed is sent
+//! again, with the delay doubling from `WEBHOOK_RETRY_INITIAL` up to
+//! `WEBHOOK_RETRY_MAX`. The engine only builds requests; a `WebhookTransport`
+//! sends them.
+//!
+//! ```json
+//! {
+//!   "webhook_id": "01923f4e-9b3e-7f4c-9e40-8eaf3d6b5a43",
+//!   "events": [
+//!     {
+//!       "cursor": 42,
+//!       "op_id": "01923f4e-7b1c-7d2a-9c1e-5b7f0a3e2d10",
+//!       "bundle_id": "01923f4e-7b1c-7d2a-9c1e-5b7f0a3e2d11",
+//!       "actor_id": "5b7f0a3e…",
+//!       "hlc": { "wall_ms": 1767225600000, "counter": 0 },
+//!       "type": "field_changed",
+//!       "entity_id": "01923f4e-7b1c-7d2a-9c1e-5b7f0a3e2d12",
+//!       "field_key": "title",
+//!       "value": { "type": "text", "value": "Hang plot" }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Event types are `entity_upserted` (`entity_id`, `facets`),
+//! `entity_deleted` (`entity_id`), `field_changed` (`entity_id`,
+//! `field_key`, `value`, null once cleared), `edge_changed` (`edge_id`,
+//! `edge_type`, `source_id`, `target_id`, `deleted`) and `conflict`
+//! (`conflict_id`, `entity_id`, `field_key`, `status`).
+
+use std::time::Duration;
+
+use openprod_core::ids::WebhookId;
+use openprod_storage::{EngineStorage, StorageError, WebhookRecord};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::Engine;
+use crate::cdc::{CdcChange, CdcEvent};
+use crate::error::EngineError;
+use crate::export::{to_hex, TypedValue};
+
+/// Most events sent in one request.
+pub const WEBHOOK_BATCH_SIZE: usize = 100;
+
+/// Delay before retrying after the first failed delivery.
+pub const WEBHOOK_RETRY_INITIAL: Duration = Duration::from_secs(1);
+
+/// Upper bound for the retry delay.
+pub const WEBHOOK_RETRY_MAX: Duration = Duration::from_secs(60 * 60);
+
+/// Sends webhook requests for `Engine::dispatch_webhooks`; set one with
+/// `Engine::set_webhook_transport`. Calls are made from `dispatch_webhooks`,
+/// on whichever thread calls it.
+pub trait WebhookTransport: Send {
+    /// POST `body` (JSON) to `url`. An error fails the delivery, which is
+    /// retried later.
+    fn post(&mut self, url: &str, body: &str) -> Result<(), String>;
+}
+
+/// Which events an endpoint receives. The default sends every event.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookFilter {
+    #[serde(default)]
+    changes: Vec<String>,
+    #[serde(default)]
+    facets: Vec<String>,
+}
+
+impl WebhookFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only these event types (e.g. `"field_changed"`).
+    pub fn changes<I, T>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.changes = types.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only changes to entities with one of these facets attached; edges
+    /// match through either endpoint.
+    pub fn facets<I, T>(mut self, facet_types: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.facets = facet_types.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn facet_types(&self) -> &[String] {
+        &self.facets
+    }
+
+    pub fn matches_type(&self, change: &CdcChange) -> bool {
+        self.changes.is_empty() || self.changes.iter().any(|t| t == change.kind())
+    }
+
+    /// The filter as stored on `WebhookRecord::filter`.
+    pub fn to_json(&self) -> Result<String, EngineError> {
+        serde_json::to_string(self).map_err(|e| EngineError::Storage(StorageError::Serialization(e.to_string())))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, EngineError> {
+        serde_json::from_str(json).map_err(|e| EngineError::Storage(StorageError::Serialization(e.to_string())))
+    }
+}
+
+/// Outcome of `Engine::dispatch_webhooks`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebhookReport {
+    /// Events delivered, over all endpoints.
+    pub delivered: usize,
+    /// Endpoints whose delivery failed this time.
+    pub failed: Vec<WebhookFailure>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookFailure {
+    pub webhook_id: WebhookId,
+    pub error: String,
+    /// Failed attempts in a row, this one included.
+    pub attempts: u32,
+    /// Next retry, in milliseconds since the Unix epoch.
+    pub retry_at: i64,
+}
+
+/// Wait after `attempts` failures in a row.
+pub(crate) fn retry_delay(attempts: u32) -> Duration {
+    let doublings = attempts.saturating_sub(1).min(31);
+    WEBHOOK_RETRY_INITIAL.saturating_mul(1 << doublings).min(WEBHOOK_RETRY_MAX)
+}
+
+/// The request body for one batch.
+pub(crate) fn request_body(webhook_id: WebhookId, events: &[CdcEvent]) -> String {
+    json!({ "webhook_id": webhook_id, "events": events.iter().map(event_json).collect::<Vec<_>>() }).to_string()
+}
+
+fn event_json(event: &CdcEvent) -> Value {
+    let mut body = json!({
+        "cursor": event.cursor,
+        "op_id": event.op_id,
+        "bundle_id": event.bundle_id,
+        "actor_id": to_hex(event.actor_id.as_bytes()),
+        "hlc": { "wall_ms": event.hlc.wall_ms(), "counter": event.hlc.counter() },
+        "type": event.change.kind(),
+    });
+    let change = match &event.change {
+        CdcChange::EntityUpserted { entity_id, facets } => json!({ "entity_id": entity_id, "facets": facets }),
+        CdcChange::EntityDeleted { entity_id } => json!({ "entity_id": entity_id }),
+        CdcChange::FieldChanged { entity_id, field_key, value } => json!({
+            "entity_id": entity_id,
+            "field_key": field_key,
+            "value": value.as_ref().map(TypedValue::from),
+        }),
+        CdcChange::EdgeChanged { edge_id, edge_type, source_id, target_id, deleted } => json!({
+            "edge_id": edge_id,
+            "edge_type": edge_type,
+            "source_id": source_id,
+            "target_id": target_id,
+            "deleted": deleted,
+        }),
+        CdcChange::Conflict { conflict_id, entity_id, field_key, status } => json!({
+            "conflict_id": conflict_id,
+            "entity_id": entity_id,
+            "field_key": field_key,
+            "status": status.as_str(),
+        }),
+    };
+    if let (Value::Object(body), Value::Object(change)) = (&mut body, change) {
+        body.extend(change);
+    }
+    body
+}
+
+impl<S: EngineStorage> Engine<S> {
+    /// Send webhook requests through `transport`, replacing any set before.
+    /// Until one is set, events stay queued for every webhook.
+    pub fn set_webhook_transport(&mut self, transport: impl WebhookTransport + 'static) {
+        self.webhook_transport = Some(Box::new(transport));
+    }
+
+    /// Register an endpoint for the change events `filter` selects, starting
+    /// with changes made after this call.
+    pub fn register_webhook(&mut self, url: &str, filter: &WebhookFilter) -> Result<WebhookId, EngineError> {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(EngineError::Rejected(format!("webhook url must be http(s): {url:?}")));
+        }
+        let webhook_id = WebhookId::new();
+        self.storage.insert_webhook(&WebhookRecord {
+            webhook_id,
+            url: url.to_string(),
+            filter: filter.to_json()?,
+            cursor: self.cdc_tail()?,
+            attempts: 0,
+            retry_at: None,
+            last_error: None,
+        })?;
+        Ok(webhook_id)
+    }
+
+    /// Stop delivering to an endpoint. Returns true if it was registered.
+    pub fn unregister_webhook(&mut self, webhook_id: WebhookId) -> Result<bool, EngineError> {
+        Ok(self.storage.delete_webhook(webhook_id)?)
+    }
+
+    /// Registered endpoints and their delivery state, in registration order.
+    pub fn list_webhooks(&self) -> Result<Vec<WebhookRecord>, EngineError> {
+        Ok(self.storage.list_webhooks()?)
+    }
+
+    /// Whether a bundle was stored since webhooks were last dispatched. Writes
+    /// only set this; delivery blocks on the endpoints, so it's left to the
+    /// embedder (e.g. a worker that checks after each write).
+    pub fn webhooks_due(&self) -> bool {
+        self.webhooks_due
+    }
+
+    /// Deliver pending events to every endpoint not waiting to retry, in
+    /// batches of up to `WEBHOOK_BATCH_SIZE`. Never called by the engine
+    /// itself: call it when `webhooks_due`, and on a timer so failed
+    /// deliveries are retried when no new changes arrive.
+    pub fn dispatch_webhooks(&mut self) -> Result<WebhookReport, EngineError> {
+        self.webhooks_due = false;
+        let Some(mut transport) = self.webhook_transport.take() else {
+            return Ok(WebhookReport::default());
+        };
+        let result = self.dispatch_webhooks_with(transport.as_mut());
+        self.webhook_transport = Some(transport);
+        result
+    }
+
+    fn dispatch_webhooks_with(&mut self, transport: &mut dyn WebhookTransport) -> Result<WebhookReport, EngineError> {
+        let mut report = WebhookReport::default();
+        let now = self.clock.now_ms()? as i64;
+        for webhook in self.storage.list_webhooks()? {
+            if webhook.retry_at.is_some_and(|at| at > now) {
+                continue;
+            }
+            let filter = WebhookFilter::from_json(&webhook.filter)?;
+            let mut cursor = webhook.cursor;
+            let mut failing = webhook.attempts > 0;
+            loop {
+                let batch = self.cdc_events(cursor, WEBHOOK_BATCH_SIZE)?;
+                let mut events = Vec::new();
+                for event in batch.events {
+                    if self.webhook_wants(&filter, &event.change)? {
+                        events.push(event);
+                    }
+                }
+                if !events.is_empty()
+                    && let Err(error) = transport.post(&webhook.url, &request_body(webhook.webhook_id, &events))
+                {
+                    let attempts = webhook.attempts + 1;
+                    let retry_at = now + retry_delay(attempts).as_millis() as i64;
+                    self.storage.update_webhook_delivery(webhook.webhook_id, cursor, attempts, Some(retry_at), Some(&error))?;
+                    report.failed.push(WebhookFailure { webhook_id: webhook.webhook_id, error, attempts, retry_at });
+                    break;
+                }
+                report.delivered += events.len();
+                if batch.cursor == cursor && !failing {
+                    break;
+                }
+                self.storage.update_webhook_delivery(webhook.webhook_id, batch.cursor, 0, None, None)?;
+                failing = false;
+                if batch.cursor == cursor {
+                    break;
+                }
+                cursor = batch.cursor;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Whether `filter` lets `change` through.
+    fn webhook_wants(&self, filter: &WebhookFilter, change: &CdcChange) -> Result<bool, EngineError> {
+        if !filter.matches_type(change) {
+            return Ok(false);
+        }
+        if filter.facet_types().is_empty() {
+            return Ok(true);
+        }
+        let entities = match change {
+            CdcChange::EntityUpserted { entity_id, .. }
+            | CdcChange::EntityDeleted { entity_id }
+            | CdcChange::FieldChanged { entity_id, .. }
+            | CdcChange::Conflict { entity_id, .. } => vec![*entity_id],
+            CdcChange::EdgeChanged { source_id, target_id, .. } => vec![*source_id, *target_id],
+        };
+        for entity_id in entities {
+            if self.storage.get_facets(entity_id)?.iter().any(|f| !f.detached && filter.facet_types().contains(&f.facet_type)) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
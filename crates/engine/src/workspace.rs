@@ -0,0 +1,106 @@
+//! Workspaces and file backups, for engines on SQLite.
+
+use std::path::Path;
+
+use openprod_core::{digest::HlcRange, identity::ActorIdentity};
+use openprod_storage::{schema::SCHEMA_VERSION, SqliteStorage, Storage, StorageError};
+
+use crate::Engine;
+use crate::builder::EngineBuilder;
+use crate::error::EngineError;
+use crate::overlay::OverlayManager;
+use crate::undo::UndoManager;
+
+/// Operations that rely on SQLite files (workspaces, backups).
+impl Engine<SqliteStorage> {
+    // ========================================================================
+    // Workspaces
+    // ========================================================================
+
+    /// The workspace of the database file this engine works in.
+    pub fn workspace(&self) -> &str {
+        self.storage.workspace()
+    }
+
+    /// Workspaces in this engine's database file that hold any bundles.
+    pub fn workspaces(&self) -> Result<Vec<String>, EngineError> {
+        Ok(self.storage.workspaces()?)
+    }
+
+    /// Open another workspace of this engine's database file with the same
+    /// identity and default settings; `EngineBuilder::open_workspace` takes
+    /// the same arguments for one that needs configuring. Fails for an
+    /// in-memory database, which no second connection can reach.
+    pub fn open_workspace(&self, workspace: &str) -> Result<Engine<SqliteStorage>, EngineError> {
+        let path = self
+            .storage
+            .path()
+            .ok_or_else(|| StorageError::Backend("an in-memory database has no other workspaces".into()))?;
+        let identity = ActorIdentity::from_secret_bytes(&self.identity.secret_bytes());
+        EngineBuilder::new().open_workspace(identity, path, workspace)
+    }
+
+    // ========================================================================
+    // Backup / Restore
+    // ========================================================================
+
+    /// Write a consistent backup of the database to `path` without closing the engine.
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        Ok(self.storage.backup_to(path)?)
+    }
+
+    /// Replace this engine's data with the backup at `path`. The backup must
+    /// have the current schema version, pass SQLite's integrity check, and hold
+    /// an intact oplog (every bundle's signature, op count and checksum verify);
+    /// otherwise nothing is changed. Undo history is cleared and the active
+    /// overlay is reloaded from the restored data. The whole file is restored,
+    /// so every workspace in it is replaced, and every one is verified.
+    pub fn restore_from(&mut self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        let path = path.as_ref();
+        {
+            let backup = SqliteStorage::open_read_only(path)?;
+            let version = backup.schema_version()?;
+            // Older backups are migrated on restore
+            if version > SCHEMA_VERSION {
+                return Err(EngineError::InvalidBackup(format!(
+                    "schema version {version} is newer than supported {SCHEMA_VERSION}"
+                )));
+            }
+            let problems = backup.quick_check()?;
+            if !problems.is_empty() {
+                return Err(EngineError::InvalidBackup(problems.join("; ")));
+            }
+            let workspaces = if version >= 23 {
+                backup
+                    .workspaces()?
+                    .iter()
+                    .map(|workspace| SqliteStorage::open_read_only_workspace(path, workspace))
+                    .collect::<Result<Vec<_>, _>>()?
+            } else {
+                // Scoped queries need the workspace columns of schema 23, so
+                // an older backup is checked as a migrated in-memory copy
+                let mut copy = SqliteStorage::open_in_memory()?;
+                copy.restore_from(path)?;
+                vec![copy]
+            };
+            for backup in workspaces {
+                for (_, bundle_id) in backup.get_bundle_ids_in_range(&HlcRange::full())? {
+                    let bundle = backup
+                        .get_bundle(bundle_id)?
+                        .ok_or_else(|| EngineError::BundleNotFound(bundle_id.to_string()))?;
+                    let operations = backup.get_ops_by_bundle(bundle_id)?;
+                    self.verify_bundle(&bundle, &operations).map_err(EngineError::InvalidBackup)?;
+                }
+            }
+        }
+
+        self.storage.restore_from(path)?;
+        self.key_rotations.take();
+        self.undo_manager = UndoManager::new(self.undo_manager.max_depth());
+        self.overlay_manager = OverlayManager::new();
+        self.restore_active_overlay()?;
+        self.changes.mark_all();
+        self.notify_watchers();
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+/*
+ * C ABI for the openprod engine. See crates/ffi/src/lib.rs for the
+ * conventions; every function here mirrors one `extern "C"` item there.
+ *
+ * - Every call returns an OpenprodStatus. On failure,
+ *   openprod_last_error_message() describes the error until the next call
+ *   on the same thread.
+ * - Entity ids are 16 raw UUID bytes; actor ids and secret keys 32 bytes.
+ * - Field values, vector clocks and bundle batches are msgpack. Buffers the
+ *   library returns must be released with openprod_buffer_free().
+ * - A handle must not be used from two threads at once.
+ */
+
+#ifndef OPENPROD_H
+#define OPENPROD_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum OpenprodStatus {
+    OPENPROD_OK = 0,
+    OPENPROD_INVALID_ARGUMENT = 1,
+    OPENPROD_NOT_FOUND = 2,
+    OPENPROD_REJECTED = 3,
+    OPENPROD_STORAGE = 4,
+    OPENPROD_ENGINE = 5,
+    OPENPROD_PANIC = 6,
+} OpenprodStatus;
+
+typedef struct OpenprodEngine OpenprodEngine;
+
+typedef struct OpenprodBuffer {
+    uint8_t *data;
+    size_t len;
+} OpenprodBuffer;
+
+/* Errors and buffers */
+const char *openprod_last_error_message(void);
+void openprod_buffer_free(OpenprodBuffer buffer);
+
+/* Lifecycle. secret_key: 32 bytes, or NULL to generate a new identity. */
+OpenprodStatus openprod_engine_open(const char *path, const uint8_t *secret_key, OpenprodEngine **out);
+OpenprodStatus openprod_engine_open_in_memory(const uint8_t *secret_key, OpenprodEngine **out);
+void openprod_engine_free(OpenprodEngine *engine);
+OpenprodStatus openprod_engine_actor_id(const OpenprodEngine *engine, uint8_t out[32]);
+OpenprodStatus openprod_engine_secret_key(const OpenprodEngine *engine, uint8_t out[32]);
+
+/* Edits */
+OpenprodStatus openprod_create_entity(OpenprodEngine *engine, const char *facet_type, uint8_t out_id[16]);
+OpenprodStatus openprod_delete_entity(OpenprodEngine *engine, const uint8_t entity_id[16]);
+OpenprodStatus openprod_set_field(OpenprodEngine *engine, const uint8_t entity_id[16], const char *field_key,
+                                  const uint8_t *value, size_t value_len);
+OpenprodStatus openprod_clear_field(OpenprodEngine *engine, const uint8_t entity_id[16], const char *field_key);
+/* OPENPROD_NOT_FOUND (and an empty buffer) if the field is unset. */
+OpenprodStatus openprod_get_field(const OpenprodEngine *engine, const uint8_t entity_id[16], const char *field_key,
+                                  OpenprodBuffer *out);
+OpenprodStatus openprod_undo(OpenprodEngine *engine, bool *out_undone);
+OpenprodStatus openprod_redo(OpenprodEngine *engine, bool *out_redone);
+
+/* Sync */
+OpenprodStatus openprod_vector_clock(const OpenprodEngine *engine, OpenprodBuffer *out);
+OpenprodStatus openprod_bundles_missing_from(const OpenprodEngine *engine, const uint8_t *vc, size_t vc_len,
+                                             OpenprodBuffer *out);
+OpenprodStatus openprod_ingest(OpenprodEngine *engine, const uint8_t *batch, size_t batch_len, size_t *out_conflicts);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* OPENPROD_H */
@@ -0,0 +1,506 @@
+//! C ABI for embedding the engine in Swift/Kotlin (or any C-capable) apps.
+//!
+//! Conventions (mirrored in `include/openprod.h`):
+//! - Every function returns an `OpenprodStatus`; results go through out
+//!   pointers. On failure `openprod_last_error_message` describes the error.
+//! - Engines are opaque `OpenprodEngine` handles from `openprod_engine_open*`,
+//!   released with `openprod_engine_free`. A handle must not be used from two
+//!   threads at once.
+//! - Entity ids are 16 raw UUID bytes, actor ids and secret keys 32 bytes.
+//! - Field values, vector clocks and bundle batches cross the boundary as
+//!   msgpack. Buffers returned by the library are freed with
+//!   `openprod_buffer_free`.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::ptr;
+
+use openprod_core::{
+    CoreError,
+    field_value::FieldValue,
+    identity::ActorIdentity,
+    ids::EntityId,
+    vector_clock::VectorClock,
+    wire::{Compression, decode_bundle_wire, encode_bundle_wire},
+};
+use openprod_engine::{Engine, EngineError, UndoResult};
+use openprod_storage::{SqliteStorage, Storage, StorageError};
+use serde_bytes::ByteBuf;
+
+/// Result code of every `openprod_*` call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenprodStatus {
+    Ok = 0,
+    /// A null pointer, malformed UTF-8/msgpack, or otherwise unusable input.
+    InvalidArgument = 1,
+    /// The entity (or field, for `openprod_get_field`) does not exist.
+    NotFound = 2,
+    /// The operation is not allowed: deleted entity, ACL, untrusted actor.
+    Rejected = 3,
+    /// The database failed or is unreadable.
+    Storage = 4,
+    /// Any other engine error.
+    Engine = 5,
+    /// The library panicked; the handle should not be used again.
+    Panic = 6,
+}
+
+/// Opaque engine handle.
+pub struct OpenprodEngine {
+    engine: Engine<SqliteStorage>,
+}
+
+/// Bytes owned by the library; release with `openprod_buffer_free`.
+#[repr(C)]
+pub struct OpenprodBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl OpenprodBuffer {
+    const EMPTY: Self = Self { data: ptr::null_mut(), len: 0 };
+
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let bytes = Box::into_raw(bytes.into_boxed_slice());
+        Self { data: bytes.cast(), len: bytes.len() }
+    }
+}
+
+/// An error on its way to the caller: status plus message.
+struct FfiError(OpenprodStatus, String);
+
+impl FfiError {
+    fn invalid(message: impl Into<String>) -> Self {
+        Self(OpenprodStatus::InvalidArgument, message.into())
+    }
+}
+
+impl From<EngineError> for FfiError {
+    fn from(error: EngineError) -> Self {
+        let status = match &error {
+            EngineError::EntityNotFound(_)
+            | EngineError::EdgeNotFound(_)
+            | EngineError::BundleNotFound(_)
+            | EngineError::ConflictNotFound(_)
+            | EngineError::OverlayNotFound(_) => OpenprodStatus::NotFound,
+            EngineError::EntityAlreadyDeleted(_)
+            | EngineError::EntityNotDeleted(_)
+            | EngineError::EdgeNotDeleted(_)
+            | EngineError::BulkValidation(_)
+            | EngineError::PermissionDenied(_)
+            | EngineError::UntrustedActor(_)
+            | EngineError::IncompatiblePeer(_) => OpenprodStatus::Rejected,
+            EngineError::Storage(_) | EngineError::Io(_) => OpenprodStatus::Storage,
+            EngineError::Core(CoreError::Serialization(_) | CoreError::InvalidData(_))
+            | EngineError::ReservedField(_)
+            | EngineError::InvalidDocument(_) => OpenprodStatus::InvalidArgument,
+            _ => OpenprodStatus::Engine,
+        };
+        Self(status, error.to_string())
+    }
+}
+
+impl From<StorageError> for FfiError {
+    fn from(error: StorageError) -> Self {
+        EngineError::from(error).into()
+    }
+}
+
+impl From<CoreError> for FfiError {
+    fn from(error: CoreError) -> Self {
+        EngineError::from(error).into()
+    }
+}
+
+type FfiResult<T = ()> = Result<T, FfiError>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, recording any error (or panic) for `openprod_last_error_message`.
+fn guard(f: impl FnOnce() -> FfiResult) -> OpenprodStatus {
+    let (status, message) = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (OpenprodStatus::Ok, None),
+        Ok(Err(FfiError(status, message))) => (status, Some(message)),
+        Err(_) => (OpenprodStatus::Panic, Some("openprod panicked".to_string())),
+    };
+    LAST_ERROR.with(|last| {
+        *last.borrow_mut() = message.map(|m| CString::new(m.replace('\0', " ")).unwrap_or_default());
+    });
+    status
+}
+
+// ============================================================================
+// Argument helpers
+// ============================================================================
+
+unsafe fn engine_mut<'a>(handle: *mut OpenprodEngine) -> FfiResult<&'a mut Engine<SqliteStorage>> {
+    unsafe { handle.as_mut() }.map(|h| &mut h.engine).ok_or_else(|| FfiError::invalid("null engine handle"))
+}
+
+unsafe fn engine_ref<'a>(handle: *const OpenprodEngine) -> FfiResult<&'a Engine<SqliteStorage>> {
+    unsafe { handle.as_ref() }.map(|h| &h.engine).ok_or_else(|| FfiError::invalid("null engine handle"))
+}
+
+unsafe fn out_mut<'a, T>(out: *mut T) -> FfiResult<&'a mut T> {
+    unsafe { out.as_mut() }.ok_or_else(|| FfiError::invalid("null out pointer"))
+}
+
+unsafe fn string_arg<'a>(s: *const c_char, what: &str) -> FfiResult<&'a str> {
+    if s.is_null() {
+        return Err(FfiError::invalid(format!("null {what}")));
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().map_err(|_| FfiError::invalid(format!("{what} is not UTF-8")))
+}
+
+unsafe fn bytes_arg<'a>(data: *const u8, len: usize, what: &str) -> FfiResult<&'a [u8]> {
+    match (data.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(FfiError::invalid(format!("null {what}"))),
+        (false, _) => Ok(unsafe { std::slice::from_raw_parts(data, len) }),
+    }
+}
+
+unsafe fn array_arg<const N: usize>(data: *const u8, what: &str) -> FfiResult<[u8; N]> {
+    if data.is_null() {
+        return Err(FfiError::invalid(format!("null {what}")));
+    }
+    Ok(unsafe { ptr::read(data.cast::<[u8; N]>()) })
+}
+
+unsafe fn entity_arg(id: *const u8) -> FfiResult<EntityId> {
+    Ok(EntityId::from_bytes(unsafe { array_arg::<16>(id, "entity id") }?))
+}
+
+unsafe fn write_array<const N: usize>(out: *mut u8, bytes: &[u8; N]) -> FfiResult {
+    if out.is_null() {
+        return Err(FfiError::invalid("null out pointer"));
+    }
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out, N) };
+    Ok(())
+}
+
+fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8], what: &str) -> FfiResult<T> {
+    rmp_serde::from_slice(bytes).map_err(|e| FfiError::invalid(format!("{what}: {e}")))
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> FfiResult<Vec<u8>> {
+    rmp_serde::to_vec_named(value).map_err(|e| FfiError(OpenprodStatus::Engine, e.to_string()))
+}
+
+// ============================================================================
+// Errors and buffers
+// ============================================================================
+
+/// Message for the last failed call on this thread, or null. Valid until the
+/// next `openprod_*` call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn openprod_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Release a buffer returned by the library. Null/empty buffers are ignored.
+///
+/// # Safety
+/// `buffer` must come from this library and not have been freed already.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_buffer_free(buffer: OpenprodBuffer) {
+    if !buffer.data.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)) });
+    }
+}
+
+// ============================================================================
+// Lifecycle
+// ============================================================================
+
+/// Open (or create) the database at `path`. `secret_key` is the replica's
+/// 32-byte signing key, or null to generate one (export it with
+/// `openprod_engine_secret_key` and pass it on every later open).
+///
+/// # Safety
+/// `path` must be a NUL-terminated string; `secret_key` null or 32 readable
+/// bytes; `out` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_engine_open(
+    path: *const c_char,
+    secret_key: *const u8,
+    out: *mut *mut OpenprodEngine,
+) -> OpenprodStatus {
+    guard(|| {
+        let path = unsafe { string_arg(path, "path") }?;
+        let storage = SqliteStorage::open(path)?;
+        unsafe { open_with(storage, secret_key, out) }
+    })
+}
+
+/// Open an engine over a fresh in-memory database.
+///
+/// # Safety
+/// As `openprod_engine_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_engine_open_in_memory(
+    secret_key: *const u8,
+    out: *mut *mut OpenprodEngine,
+) -> OpenprodStatus {
+    guard(|| unsafe { open_with(SqliteStorage::open_in_memory()?, secret_key, out) })
+}
+
+unsafe fn open_with(storage: SqliteStorage, secret_key: *const u8, out: *mut *mut OpenprodEngine) -> FfiResult {
+    let out = unsafe { out_mut(out) }?;
+    let identity = if secret_key.is_null() {
+        ActorIdentity::generate()
+    } else {
+        ActorIdentity::from_secret_bytes(&unsafe { array_arg::<32>(secret_key, "secret key") }?)
+    };
+    let engine = Engine::new(identity, storage)?;
+    *out = Box::into_raw(Box::new(OpenprodEngine { engine }));
+    Ok(())
+}
+
+/// Close the engine. Null is ignored.
+///
+/// # Safety
+/// `handle` must come from `openprod_engine_open*` and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_engine_free(handle: *mut OpenprodEngine) {
+    if !handle.is_null() {
+        drop(unsafe { Box::from_raw(handle) });
+    }
+}
+
+/// Write the 32-byte actor id to `out`.
+///
+/// # Safety
+/// `handle` must be live; `out` must have 32 writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_engine_actor_id(handle: *const OpenprodEngine, out: *mut u8) -> OpenprodStatus {
+    guard(|| unsafe { write_array(out, engine_ref(handle)?.actor_id().as_bytes()) })
+}
+
+/// Write the 32-byte secret signing key to `out`, for the app's keychain.
+///
+/// # Safety
+/// `handle` must be live; `out` must have 32 writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_engine_secret_key(handle: *const OpenprodEngine, out: *mut u8) -> OpenprodStatus {
+    guard(|| unsafe { write_array(out, &engine_ref(handle)?.identity().secret_bytes()) })
+}
+
+// ============================================================================
+// Edits
+// ============================================================================
+
+/// Create an entity with facet `facet_type` (or none, if null); writes its
+/// 16-byte id to `out_id`.
+///
+/// # Safety
+/// `handle` must be live; `facet_type` null or NUL-terminated; `out_id` must
+/// have 16 writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_create_entity(
+    handle: *mut OpenprodEngine,
+    facet_type: *const c_char,
+    out_id: *mut u8,
+) -> OpenprodStatus {
+    guard(|| {
+        let engine = unsafe { engine_mut(handle) }?;
+        let facet_type = if facet_type.is_null() { None } else { Some(unsafe { string_arg(facet_type, "facet type") }?) };
+        let (entity_id, _) = engine.create_entity(facet_type)?;
+        unsafe { write_array(out_id, entity_id.as_bytes()) }
+    })
+}
+
+/// Delete an entity (and its edges).
+///
+/// # Safety
+/// `handle` must be live; `entity_id` must point to 16 bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_delete_entity(handle: *mut OpenprodEngine, entity_id: *const u8) -> OpenprodStatus {
+    guard(|| {
+        let engine = unsafe { engine_mut(handle) }?;
+        engine.delete_entity(unsafe { entity_arg(entity_id) }?)?;
+        Ok(())
+    })
+}
+
+/// Set a field to a msgpack-encoded `FieldValue`.
+///
+/// # Safety
+/// `handle` must be live; `entity_id` 16 bytes; `field_key` NUL-terminated;
+/// `value` `value_len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_set_field(
+    handle: *mut OpenprodEngine,
+    entity_id: *const u8,
+    field_key: *const c_char,
+    value: *const u8,
+    value_len: usize,
+) -> OpenprodStatus {
+    guard(|| {
+        let engine = unsafe { engine_mut(handle) }?;
+        let entity_id = unsafe { entity_arg(entity_id) }?;
+        let field_key = unsafe { string_arg(field_key, "field key") }?;
+        let value: FieldValue = decode(unsafe { bytes_arg(value, value_len, "value") }?, "field value")?;
+        engine.set_field(entity_id, field_key, value)?;
+        Ok(())
+    })
+}
+
+/// Clear a field.
+///
+/// # Safety
+/// `handle` must be live; `entity_id` 16 bytes; `field_key` NUL-terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_clear_field(
+    handle: *mut OpenprodEngine,
+    entity_id: *const u8,
+    field_key: *const c_char,
+) -> OpenprodStatus {
+    guard(|| {
+        let engine = unsafe { engine_mut(handle) }?;
+        let entity_id = unsafe { entity_arg(entity_id) }?;
+        engine.clear_field(entity_id, unsafe { string_arg(field_key, "field key") }?)?;
+        Ok(())
+    })
+}
+
+/// Read a field as a msgpack-encoded `FieldValue`. Returns `NotFound` (with
+/// an empty buffer) if the field is unset.
+///
+/// # Safety
+/// `handle` must be live; `entity_id` 16 bytes; `field_key` NUL-terminated;
+/// `out` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_get_field(
+    handle: *const OpenprodEngine,
+    entity_id: *const u8,
+    field_key: *const c_char,
+    out: *mut OpenprodBuffer,
+) -> OpenprodStatus {
+    guard(|| {
+        let engine = unsafe { engine_ref(handle) }?;
+        let out = unsafe { out_mut(out) }?;
+        *out = OpenprodBuffer::EMPTY;
+        let entity_id = unsafe { entity_arg(entity_id) }?;
+        let field_key = unsafe { string_arg(field_key, "field key") }?;
+        match engine.get_field(entity_id, field_key)? {
+            Some(value) => {
+                *out = OpenprodBuffer::from_vec(encode(&value)?);
+                Ok(())
+            }
+            None => Err(FfiError(OpenprodStatus::NotFound, format!("field {field_key} is not set"))),
+        }
+    })
+}
+
+/// Undo this replica's most recent edit. `out_undone` (optional) is set to
+/// whether an undo was applied (false if there was nothing to undo or it
+/// was skipped because others edited the same fields since).
+///
+/// # Safety
+/// `handle` must be live; `out_undone` null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_undo(handle: *mut OpenprodEngine, out_undone: *mut bool) -> OpenprodStatus {
+    guard(|| {
+        let undone = matches!(unsafe { engine_mut(handle) }?.undo()?, UndoResult::Applied(_));
+        if let Some(out) = unsafe { out_undone.as_mut() } {
+            *out = undone;
+        }
+        Ok(())
+    })
+}
+
+/// Redo the most recently undone edit. `out_redone` as for `openprod_undo`.
+///
+/// # Safety
+/// `handle` must be live; `out_redone` null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_redo(handle: *mut OpenprodEngine, out_redone: *mut bool) -> OpenprodStatus {
+    guard(|| {
+        let redone = matches!(unsafe { engine_mut(handle) }?.redo()?, UndoResult::Applied(_));
+        if let Some(out) = unsafe { out_redone.as_mut() } {
+            *out = redone;
+        }
+        Ok(())
+    })
+}
+
+// ============================================================================
+// Sync
+// ============================================================================
+
+/// The engine's vector clock, msgpack-encoded, to send to a peer.
+///
+/// # Safety
+/// `handle` must be live; `out` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_vector_clock(handle: *const OpenprodEngine, out: *mut OpenprodBuffer) -> OpenprodStatus {
+    guard(|| {
+        let engine = unsafe { engine_ref(handle) }?;
+        let out = unsafe { out_mut(out) }?;
+        *out = OpenprodBuffer::from_vec(engine.get_vector_clock()?.to_msgpack()?);
+        Ok(())
+    })
+}
+
+/// Bundles a peer with vector clock `vc` (from `openprod_vector_clock`) has
+/// not seen, as a msgpack array of bundle wire envelopes in HLC order.
+///
+/// # Safety
+/// `handle` must be live; `vc` `vc_len` readable bytes; `out` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_bundles_missing_from(
+    handle: *const OpenprodEngine,
+    vc: *const u8,
+    vc_len: usize,
+    out: *mut OpenprodBuffer,
+) -> OpenprodStatus {
+    guard(|| {
+        let engine = unsafe { engine_ref(handle) }?;
+        let out = unsafe { out_mut(out) }?;
+        let vc = VectorClock::from_msgpack(unsafe { bytes_arg(vc, vc_len, "vector clock") }?)?;
+        let mut envelopes = Vec::new();
+        for bundle_id in engine.bundles_missing_from(&vc)? {
+            let bundle = engine
+                .storage()
+                .get_bundle(bundle_id)?
+                .ok_or_else(|| EngineError::BundleNotFound(bundle_id.to_string()))?;
+            let operations = engine.get_ops_by_bundle(bundle_id)?;
+            envelopes.push(ByteBuf::from(encode_bundle_wire(&bundle, &operations, Compression::Zstd)?));
+        }
+        *out = OpenprodBuffer::from_vec(encode(&envelopes)?);
+        Ok(())
+    })
+}
+
+/// Ingest a msgpack array of bundle wire envelopes from a peer.
+/// `out_conflicts` (optional) receives the number of conflicts raised.
+///
+/// # Safety
+/// `handle` must be live; `batch` `batch_len` readable bytes; `out_conflicts`
+/// null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn openprod_ingest(
+    handle: *mut OpenprodEngine,
+    batch: *const u8,
+    batch_len: usize,
+    out_conflicts: *mut usize,
+) -> OpenprodStatus {
+    guard(|| {
+        let engine = unsafe { engine_mut(handle) }?;
+        let envelopes: Vec<ByteBuf> = decode(unsafe { bytes_arg(batch, batch_len, "batch") }?, "bundle batch")?;
+        let batch = envelopes
+            .iter()
+            .map(|envelope| decode_bundle_wire(envelope))
+            .collect::<Result<Vec<_>, _>>()?;
+        let report = engine.ingest_bundles(&batch)?;
+        if let Some(out) = unsafe { out_conflicts.as_mut() } {
+            *out = report.conflicts.len();
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,134 @@
+//! Drives the C ABI the way a host app would: raw pointers, status codes and
+//! msgpack buffers.
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use openprod_core::field_value::FieldValue;
+use openprod_ffi::*;
+
+fn check(status: OpenprodStatus) {
+    if status != OpenprodStatus::Ok {
+        let message = unsafe { CStr::from_ptr(openprod_last_error_message()) };
+        panic!("{status:?}: {}", message.to_string_lossy());
+    }
+}
+
+fn open() -> *mut OpenprodEngine {
+    let mut engine = ptr::null_mut();
+    check(unsafe { openprod_engine_open_in_memory(ptr::null(), &mut engine) });
+    engine
+}
+
+/// Take ownership of a library buffer's bytes.
+fn take(buffer: OpenprodBuffer) -> Vec<u8> {
+    let bytes = unsafe { std::slice::from_raw_parts(buffer.data, buffer.len) }.to_vec();
+    unsafe { openprod_buffer_free(buffer) };
+    bytes
+}
+
+fn get_field(engine: *mut OpenprodEngine, entity: &[u8; 16], key: &CStr) -> (OpenprodStatus, Option<FieldValue>) {
+    let mut out = OpenprodBuffer { data: ptr::null_mut(), len: 0 };
+    let status = unsafe { openprod_get_field(engine, entity.as_ptr(), key.as_ptr(), &mut out) };
+    let value = (status == OpenprodStatus::Ok).then(|| rmp_serde::from_slice(&take(out)).unwrap());
+    (status, value)
+}
+
+#[test]
+fn edit_undo_and_sync_through_c_abi() {
+    let alice = open();
+    let bob = open();
+    let label = c"label";
+
+    let mut cue = [0u8; 16];
+    check(unsafe { openprod_create_entity(alice, c"Cue".as_ptr(), cue.as_mut_ptr()) });
+    assert_eq!(get_field(alice, &cue, label).0, OpenprodStatus::NotFound);
+
+    for text in ["Q1", "Q2"] {
+        let value = rmp_serde::to_vec_named(&FieldValue::Text(text.into())).unwrap();
+        check(unsafe { openprod_set_field(alice, cue.as_ptr(), label.as_ptr(), value.as_ptr(), value.len()) });
+    }
+    let mut undone = false;
+    check(unsafe { openprod_undo(alice, &mut undone) });
+    assert!(undone);
+    assert_eq!(get_field(alice, &cue, label).1, Some(FieldValue::Text("Q1".into())));
+
+    // Bob pulls everything Alice has that his clock hasn't seen
+    let mut vc = OpenprodBuffer { data: ptr::null_mut(), len: 0 };
+    check(unsafe { openprod_vector_clock(bob, &mut vc) });
+    let vc = take(vc);
+    let mut batch = OpenprodBuffer { data: ptr::null_mut(), len: 0 };
+    check(unsafe { openprod_bundles_missing_from(alice, vc.as_ptr(), vc.len(), &mut batch) });
+    let batch = take(batch);
+    let mut conflicts = usize::MAX;
+    check(unsafe { openprod_ingest(bob, batch.as_ptr(), batch.len(), &mut conflicts) });
+    assert_eq!(conflicts, 0);
+    assert_eq!(get_field(bob, &cue, label).1, Some(FieldValue::Text("Q1".into())));
+
+    // Now in sync: nothing left to send
+    let mut vc = OpenprodBuffer { data: ptr::null_mut(), len: 0 };
+    check(unsafe { openprod_vector_clock(bob, &mut vc) });
+    let vc = take(vc);
+    let mut batch = OpenprodBuffer { data: ptr::null_mut(), len: 0 };
+    check(unsafe { openprod_bundles_missing_from(alice, vc.as_ptr(), vc.len(), &mut batch) });
+    let envelopes: Vec<serde_bytes::ByteBuf> = rmp_serde::from_slice(&take(batch)).unwrap();
+    assert!(envelopes.is_empty());
+
+    unsafe {
+        openprod_engine_free(alice);
+        openprod_engine_free(bob);
+    }
+}
+
+#[test]
+fn errors_map_to_status_codes() {
+    let engine = open();
+    let key = c"label";
+    let missing = [7u8; 16];
+
+    let value = rmp_serde::to_vec_named(&FieldValue::Integer(1)).unwrap();
+    let status = unsafe { openprod_set_field(engine, missing.as_ptr(), key.as_ptr(), value.as_ptr(), value.len()) };
+    assert_eq!(status, OpenprodStatus::NotFound);
+    let message = unsafe { CStr::from_ptr(openprod_last_error_message()) };
+    assert!(message.to_string_lossy().contains("entity not found"));
+
+    let mut cue = [0u8; 16];
+    check(unsafe { openprod_create_entity(engine, ptr::null(), cue.as_mut_ptr()) });
+    assert!(openprod_last_error_message().is_null());
+    let garbage = [0xc1u8];
+    let status = unsafe { openprod_set_field(engine, cue.as_ptr(), key.as_ptr(), garbage.as_ptr(), 1) };
+    assert_eq!(status, OpenprodStatus::InvalidArgument);
+    let status = unsafe { openprod_set_field(ptr::null_mut(), cue.as_ptr(), key.as_ptr(), value.as_ptr(), 1) };
+    assert_eq!(status, OpenprodStatus::InvalidArgument);
+
+    check(unsafe { openprod_delete_entity(engine, cue.as_ptr()) });
+    let status = unsafe { openprod_clear_field(engine, cue.as_ptr(), key.as_ptr()) };
+    assert_eq!(status, OpenprodStatus::Rejected);
+
+    unsafe { openprod_engine_free(engine) };
+}
+
+#[test]
+fn reopening_with_exported_key_keeps_identity() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = CString::new(dir.path().join("show.db").to_str().unwrap()).unwrap();
+
+    let mut engine = ptr::null_mut();
+    check(unsafe { openprod_engine_open(path.as_ptr(), ptr::null(), &mut engine) });
+    let (mut actor, mut secret) = ([0u8; 32], [0u8; 32]);
+    check(unsafe { openprod_engine_actor_id(engine, actor.as_mut_ptr()) });
+    check(unsafe { openprod_engine_secret_key(engine, secret.as_mut_ptr()) });
+    let mut cue = [0u8; 16];
+    check(unsafe { openprod_create_entity(engine, c"Cue".as_ptr(), cue.as_mut_ptr()) });
+    unsafe { openprod_engine_free(engine) };
+
+    let mut reopened = ptr::null_mut();
+    check(unsafe { openprod_engine_open(path.as_ptr(), secret.as_ptr(), &mut reopened) });
+    let mut reopened_actor = [0u8; 32];
+    check(unsafe { openprod_engine_actor_id(reopened, reopened_actor.as_mut_ptr()) });
+    assert_eq!(reopened_actor, actor);
+    // The entity survived: it can still be edited
+    let value = rmp_serde::to_vec_named(&FieldValue::Integer(1)).unwrap();
+    check(unsafe { openprod_set_field(reopened, cue.as_ptr(), c"number".as_ptr(), value.as_ptr(), value.len()) });
+    unsafe { openprod_engine_free(reopened) };
+}
@@ -0,0 +1,46 @@
+//! Generates the service stubs for `proto/openprod.proto` without `protoc`:
+//! messages are hand-written in `src/proto.rs`, so only the service needs
+//! describing here.
+
+use tonic_build::manual::{Builder, Method, Service};
+
+/// (rust name, route name, input, output, server-streaming)
+const METHODS: &[(&str, &str, &str, &str, bool)] = &[
+    ("create_entity", "CreateEntity", "CreateEntityRequest", "CreateEntityReply", false),
+    ("delete_entity", "DeleteEntity", "EntityRequest", "BundleReply", false),
+    ("set_field", "SetField", "SetFieldRequest", "BundleReply", false),
+    ("clear_field", "ClearField", "FieldRequest", "BundleReply", false),
+    ("create_edge", "CreateEdge", "CreateEdgeRequest", "CreateEdgeReply", false),
+    ("delete_edge", "DeleteEdge", "EdgeRequest", "BundleReply", false),
+    ("undo", "Undo", "Empty", "UndoReply", false),
+    ("redo", "Redo", "Empty", "UndoReply", false),
+    ("get_entity", "GetEntity", "EntityRequest", "EntityReply", false),
+    ("get_field", "GetField", "FieldRequest", "GetFieldReply", false),
+    ("entities_by_facet", "EntitiesByFacet", "FacetRequest", "EntityIds", false),
+    ("list_conflicts", "ListConflicts", "EntityRequest", "ConflictList", false),
+    ("resolve_conflict", "ResolveConflict", "ResolveConflictRequest", "BundleReply", false),
+    ("create_overlay", "CreateOverlay", "CreateOverlayRequest", "OverlayReply", false),
+    ("activate_overlay", "ActivateOverlay", "OverlayRequest", "Empty", false),
+    ("stash_overlay", "StashOverlay", "OverlayRequest", "Empty", false),
+    ("commit_overlay", "CommitOverlay", "OverlayRequest", "BundleReply", false),
+    ("discard_overlay", "DiscardOverlay", "OverlayRequest", "Empty", false),
+    ("watch", "Watch", "Empty", "BundleEvent", true),
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let mut service = Service::builder().name("Engine").package("openprod.v1");
+    for &(name, route, input, output, streaming) in METHODS {
+        let mut method = Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("super::{input}"))
+            .output_type(format!("super::{output}"))
+            .codec_path("tonic_prost::ProstCodec");
+        if streaming {
+            method = method.server_streaming();
+        }
+        service = service.method(method.build());
+    }
+    Builder::new().compile(&[service.build()]);
+}
@@ -0,0 +1,382 @@
+//! gRPC server exposing one shared engine to other processes on the machine.
+//! The wire contract is `proto/openprod.proto`; generate clients from it.
+
+pub mod proto;
+
+use std::pin::Pin;
+use std::sync::MutexGuard;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+use openprod_core::field_value::FieldValue;
+use openprod_core::ids::{BlobHash, BundleId, ConflictId, EdgeId, EntityId, OverlayId};
+use openprod_engine::{Engine, EngineError, UndoResult};
+use openprod_net::SharedEngine;
+use openprod_storage::{ConflictRecord, Storage};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::Stream;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+
+use crate::proto::engine_server::{self, EngineServer};
+use crate::proto::value::Kind;
+
+/// How often a Watch bridge checks whether its client has gone away.
+const NOTIFY_POLL: Duration = Duration::from_millis(100);
+/// Events buffered per Watch stream before the bridge waits for the client.
+const WATCH_CAPACITY: usize = 64;
+
+/// Serve `engine` on `listener` until the listener fails.
+pub async fn serve(engine: SharedEngine, listener: TcpListener) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(service(engine))
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+}
+
+/// The service, for mounting on a caller-built `tonic` server (e.g. on a Unix socket).
+pub fn service(engine: SharedEngine) -> EngineServer<GrpcEngine> {
+    EngineServer::new(GrpcEngine { engine })
+}
+
+/// `openprod.v1.Engine` over a shared engine. The lock is held for single
+/// engine calls only, never across awaits.
+pub struct GrpcEngine {
+    engine: SharedEngine,
+}
+
+impl GrpcEngine {
+    fn lock(&self) -> Result<MutexGuard<'_, Engine>, Status> {
+        self.engine.lock().map_err(|_| Status::internal("engine lock poisoned"))
+    }
+}
+
+fn status(error: EngineError) -> Status {
+    match error {
+        EngineError::EntityNotFound(_)
+        | EngineError::EdgeNotFound(_)
+        | EngineError::BundleNotFound(_)
+        | EngineError::ConflictNotFound(_)
+        | EngineError::OverlayNotFound(_) => Status::not_found(error.to_string()),
+        EngineError::EntityAlreadyDeleted(_)
+        | EngineError::EntityNotDeleted(_)
+        | EngineError::EdgeNotDeleted(_)
+        | EngineError::ConflictAlreadyResolved(_)
+        | EngineError::NoActiveOverlay
+        | EngineError::EmptyOverlay(_)
+        | EngineError::UnresolvedDrift(_)
+        | EngineError::BulkValidation(_) => Status::failed_precondition(error.to_string()),
+        EngineError::ReservedField(_) | EngineError::InvalidDocument(_) => {
+            Status::invalid_argument(error.to_string())
+        }
+        EngineError::PermissionDenied(_) | EngineError::UntrustedActor(_) => {
+            Status::permission_denied(error.to_string())
+        }
+        _ => Status::internal(error.to_string()),
+    }
+}
+
+fn uuid_arg(bytes: &[u8], what: &str) -> Result<[u8; 16], Status> {
+    bytes.try_into().map_err(|_| Status::invalid_argument(format!("{what} must be 16 bytes")))
+}
+
+fn entity_arg(bytes: &[u8]) -> Result<EntityId, Status> {
+    uuid_arg(bytes, "entity id").map(EntityId::from_bytes)
+}
+
+fn bundle_reply(bundle_id: BundleId) -> Response<proto::BundleReply> {
+    Response::new(proto::BundleReply { bundle_id: bundle_id.as_bytes().to_vec() })
+}
+
+fn to_field_value(value: Option<proto::Value>) -> Result<FieldValue, Status> {
+    let Some(kind) = value.and_then(|v| v.kind) else {
+        return Err(Status::invalid_argument("missing value"));
+    };
+    Ok(match kind {
+        Kind::Null(_) => FieldValue::Null,
+        Kind::Text(s) => FieldValue::Text(s),
+        Kind::Integer(i) => FieldValue::Integer(i),
+        Kind::Float(f) => FieldValue::Float(f),
+        Kind::Boolean(b) => FieldValue::Boolean(b),
+        Kind::Timestamp(t) => FieldValue::Timestamp(t),
+        Kind::EntityRef(id) => FieldValue::EntityRef(entity_arg(&id)?),
+        Kind::BlobRef(hash) => FieldValue::BlobRef(BlobHash::from_bytes(
+            hash.try_into().map_err(|_| Status::invalid_argument("blob hash must be 32 bytes"))?,
+        )),
+        Kind::Bytes(bytes) => FieldValue::Bytes(bytes),
+    })
+}
+
+fn from_field_value(value: FieldValue) -> proto::Value {
+    let kind = match value {
+        FieldValue::Null => Kind::Null(true),
+        FieldValue::Text(s) => Kind::Text(s),
+        FieldValue::Integer(i) => Kind::Integer(i),
+        FieldValue::Float(f) => Kind::Float(f),
+        FieldValue::Boolean(b) => Kind::Boolean(b),
+        FieldValue::Timestamp(t) => Kind::Timestamp(t),
+        FieldValue::EntityRef(id) => Kind::EntityRef(id.as_bytes().to_vec()),
+        FieldValue::BlobRef(hash) => Kind::BlobRef(hash.as_bytes().to_vec()),
+        FieldValue::Bytes(bytes) => Kind::Bytes(bytes),
+    };
+    proto::Value { kind: Some(kind) }
+}
+
+fn from_conflict(record: ConflictRecord) -> Result<proto::Conflict, Status> {
+    let values = record
+        .values
+        .into_iter()
+        .map(|candidate| {
+            let value = candidate
+                .value
+                .map(|bytes| FieldValue::from_msgpack(&bytes).map(from_field_value))
+                .transpose()
+                .map_err(|e| Status::internal(e.to_string()))?;
+            Ok(proto::ConflictValue { value, actor_id: candidate.actor_id.as_bytes().to_vec() })
+        })
+        .collect::<Result<_, Status>>()?;
+    Ok(proto::Conflict {
+        conflict_id: record.conflict_id.as_bytes().to_vec(),
+        entity_id: record.entity_id.as_bytes().to_vec(),
+        field_key: record.field_key,
+        values,
+    })
+}
+
+fn undo_reply(result: UndoResult) -> Response<proto::UndoReply> {
+    Response::new(proto::UndoReply { applied: matches!(result, UndoResult::Applied(_)) })
+}
+
+#[tonic::async_trait]
+impl engine_server::Engine for GrpcEngine {
+    async fn create_entity(
+        &self,
+        request: Request<proto::CreateEntityRequest>,
+    ) -> Result<Response<proto::CreateEntityReply>, Status> {
+        let request = request.into_inner();
+        let mut fields = Vec::with_capacity(request.fields.len());
+        for field in request.fields {
+            fields.push((field.key, to_field_value(field.value)?));
+        }
+        let fields = fields.iter().map(|(key, value)| (key.as_str(), value.clone())).collect();
+        let (entity_id, bundle_id) =
+            self.lock()?.create_entity_with_fields(&request.facet_type, fields).map_err(status)?;
+        Ok(Response::new(proto::CreateEntityReply {
+            entity_id: entity_id.as_bytes().to_vec(),
+            bundle_id: bundle_id.as_bytes().to_vec(),
+        }))
+    }
+
+    async fn delete_entity(
+        &self,
+        request: Request<proto::EntityRequest>,
+    ) -> Result<Response<proto::BundleReply>, Status> {
+        let entity_id = entity_arg(&request.into_inner().entity_id)?;
+        Ok(bundle_reply(self.lock()?.delete_entity(entity_id).map_err(status)?))
+    }
+
+    async fn set_field(
+        &self,
+        request: Request<proto::SetFieldRequest>,
+    ) -> Result<Response<proto::BundleReply>, Status> {
+        let request = request.into_inner();
+        let entity_id = entity_arg(&request.entity_id)?;
+        let value = to_field_value(request.value)?;
+        Ok(bundle_reply(self.lock()?.set_field(entity_id, &request.field_key, value).map_err(status)?))
+    }
+
+    async fn clear_field(
+        &self,
+        request: Request<proto::FieldRequest>,
+    ) -> Result<Response<proto::BundleReply>, Status> {
+        let request = request.into_inner();
+        let entity_id = entity_arg(&request.entity_id)?;
+        Ok(bundle_reply(self.lock()?.clear_field(entity_id, &request.field_key).map_err(status)?))
+    }
+
+    async fn create_edge(
+        &self,
+        request: Request<proto::CreateEdgeRequest>,
+    ) -> Result<Response<proto::CreateEdgeReply>, Status> {
+        let request = request.into_inner();
+        let source_id = entity_arg(&request.source_id)?;
+        let target_id = entity_arg(&request.target_id)?;
+        let (edge_id, bundle_id) =
+            self.lock()?.create_edge(&request.edge_type, source_id, target_id).map_err(status)?;
+        Ok(Response::new(proto::CreateEdgeReply {
+            edge_id: edge_id.as_bytes().to_vec(),
+            bundle_id: bundle_id.as_bytes().to_vec(),
+        }))
+    }
+
+    async fn delete_edge(
+        &self,
+        request: Request<proto::EdgeRequest>,
+    ) -> Result<Response<proto::BundleReply>, Status> {
+        let edge_id = EdgeId::from_bytes(uuid_arg(&request.into_inner().edge_id, "edge id")?);
+        Ok(bundle_reply(self.lock()?.delete_edge(edge_id).map_err(status)?))
+    }
+
+    async fn undo(&self, _: Request<proto::Empty>) -> Result<Response<proto::UndoReply>, Status> {
+        Ok(undo_reply(self.lock()?.undo().map_err(status)?))
+    }
+
+    async fn redo(&self, _: Request<proto::Empty>) -> Result<Response<proto::UndoReply>, Status> {
+        Ok(undo_reply(self.lock()?.redo().map_err(status)?))
+    }
+
+    async fn get_entity(
+        &self,
+        request: Request<proto::EntityRequest>,
+    ) -> Result<Response<proto::EntityReply>, Status> {
+        let entity_id = entity_arg(&request.into_inner().entity_id)?;
+        let engine = self.lock()?;
+        let record = engine
+            .get_entity(entity_id)
+            .map_err(status)?
+            .ok_or_else(|| Status::not_found(format!("entity not found: {entity_id}")))?;
+        let facets = engine.get_facets(entity_id).map_err(status)?;
+        let fields = engine.get_fields(entity_id).map_err(status)?;
+        Ok(Response::new(proto::EntityReply {
+            entity_id: entity_id.as_bytes().to_vec(),
+            deleted: record.deleted,
+            facets: facets.into_iter().filter(|f| !f.detached).map(|f| f.facet_type).collect(),
+            fields: fields
+                .into_iter()
+                .map(|(key, value)| proto::Field { key, value: Some(from_field_value(value)) })
+                .collect(),
+        }))
+    }
+
+    async fn get_field(
+        &self,
+        request: Request<proto::FieldRequest>,
+    ) -> Result<Response<proto::GetFieldReply>, Status> {
+        let request = request.into_inner();
+        let entity_id = entity_arg(&request.entity_id)?;
+        let value = self.lock()?.get_field(entity_id, &request.field_key).map_err(status)?;
+        Ok(Response::new(proto::GetFieldReply { value: value.map(from_field_value) }))
+    }
+
+    async fn entities_by_facet(
+        &self,
+        request: Request<proto::FacetRequest>,
+    ) -> Result<Response<proto::EntityIds>, Status> {
+        let entities = self.lock()?.get_entities_by_facet(&request.into_inner().facet_type).map_err(status)?;
+        Ok(Response::new(proto::EntityIds {
+            entity_ids: entities.iter().map(|id| id.as_bytes().to_vec()).collect(),
+        }))
+    }
+
+    async fn list_conflicts(
+        &self,
+        request: Request<proto::EntityRequest>,
+    ) -> Result<Response<proto::ConflictList>, Status> {
+        let entity_id = entity_arg(&request.into_inner().entity_id)?;
+        let records = self.lock()?.get_open_conflicts_for_entity(entity_id).map_err(status)?;
+        let conflicts = records.into_iter().map(from_conflict).collect::<Result<_, _>>()?;
+        Ok(Response::new(proto::ConflictList { conflicts }))
+    }
+
+    async fn resolve_conflict(
+        &self,
+        request: Request<proto::ResolveConflictRequest>,
+    ) -> Result<Response<proto::BundleReply>, Status> {
+        let request = request.into_inner();
+        let conflict_id = ConflictId::from_bytes(uuid_arg(&request.conflict_id, "conflict id")?);
+        let value = match request.value {
+            Some(value) => Some(to_field_value(Some(value))?).filter(|v| !v.is_null()),
+            None => None,
+        };
+        Ok(bundle_reply(self.lock()?.resolve_conflict(conflict_id, value).map_err(status)?))
+    }
+
+    async fn create_overlay(
+        &self,
+        request: Request<proto::CreateOverlayRequest>,
+    ) -> Result<Response<proto::OverlayReply>, Status> {
+        let overlay_id = self.lock()?.create_overlay(&request.into_inner().name).map_err(status)?;
+        Ok(Response::new(proto::OverlayReply { overlay_id: overlay_id.as_bytes().to_vec() }))
+    }
+
+    async fn activate_overlay(
+        &self,
+        request: Request<proto::OverlayRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let overlay_id = overlay_arg(request)?;
+        self.lock()?.activate_overlay(overlay_id).map_err(status)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn stash_overlay(
+        &self,
+        request: Request<proto::OverlayRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let overlay_id = overlay_arg(request)?;
+        self.lock()?.stash_overlay(overlay_id).map_err(status)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn commit_overlay(
+        &self,
+        request: Request<proto::OverlayRequest>,
+    ) -> Result<Response<proto::BundleReply>, Status> {
+        let overlay_id = overlay_arg(request)?;
+        Ok(bundle_reply(self.lock()?.commit_overlay(overlay_id).map_err(status)?))
+    }
+
+    async fn discard_overlay(
+        &self,
+        request: Request<proto::OverlayRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let overlay_id = overlay_arg(request)?;
+        self.lock()?.discard_overlay(overlay_id).map_err(status)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<proto::BundleEvent, Status>> + Send>>;
+
+    async fn watch(&self, _: Request<proto::Empty>) -> Result<Response<Self::WatchStream>, Status> {
+        let bundle_rx = self.lock()?.subscribe_bundles();
+        let engine = self.engine.clone();
+        let (event_tx, event_rx) = mpsc::channel(WATCH_CAPACITY);
+
+        // Bridge the engine's change-notification hook into the response stream
+        tokio::task::spawn_blocking(move || loop {
+            match bundle_rx.recv_timeout(NOTIFY_POLL) {
+                Ok(bundle_id) => {
+                    let event = bundle_event(&engine, bundle_id);
+                    if event_tx.blocking_send(event).is_err() {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) if !event_tx.is_closed() => {}
+                Err(_) => break,
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(event_rx))))
+    }
+}
+
+fn overlay_arg(request: Request<proto::OverlayRequest>) -> Result<OverlayId, Status> {
+    uuid_arg(&request.into_inner().overlay_id, "overlay id").map(OverlayId::from_bytes)
+}
+
+fn bundle_event(engine: &SharedEngine, bundle_id: BundleId) -> Result<proto::BundleEvent, Status> {
+    let engine = engine.lock().map_err(|_| Status::internal("engine lock poisoned"))?;
+    let bundle = engine
+        .storage()
+        .get_bundle(bundle_id)
+        .map_err(|e| status(e.into()))?
+        .ok_or_else(|| Status::not_found(format!("bundle not found: {bundle_id}")))?;
+    let ids = |ids: &[EntityId]| ids.iter().map(|id| id.as_bytes().to_vec()).collect();
+    Ok(proto::BundleEvent {
+        bundle_id: bundle_id.as_bytes().to_vec(),
+        actor_id: bundle.actor_id.as_bytes().to_vec(),
+        op_count: bundle.op_count,
+        created: ids(&bundle.creates),
+        deleted: ids(&bundle.deletes),
+    })
+}
+
@@ -0,0 +1,221 @@
+//! Messages and service stubs for `proto/openprod.proto` (package `openprod.v1`).
+//! Field tags here must match the `.proto` file.
+
+use prost::{Message, Oneof};
+
+include!(concat!(env!("OUT_DIR"), "/openprod.v1.Engine.rs"));
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Empty {}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Value {
+    #[prost(oneof = "value::Kind", tags = "1, 2, 3, 4, 5, 6, 7, 8, 9")]
+    pub kind: Option<value::Kind>,
+}
+
+pub mod value {
+    use super::Oneof;
+
+    #[derive(Clone, PartialEq, Oneof)]
+    pub enum Kind {
+        #[prost(bool, tag = "1")]
+        Null(bool),
+        #[prost(string, tag = "2")]
+        Text(String),
+        #[prost(int64, tag = "3")]
+        Integer(i64),
+        #[prost(double, tag = "4")]
+        Float(f64),
+        #[prost(bool, tag = "5")]
+        Boolean(bool),
+        #[prost(int64, tag = "6")]
+        Timestamp(i64),
+        #[prost(bytes, tag = "7")]
+        EntityRef(Vec<u8>),
+        #[prost(bytes, tag = "8")]
+        BlobRef(Vec<u8>),
+        #[prost(bytes, tag = "9")]
+        Bytes(Vec<u8>),
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Field {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CreateEntityRequest {
+    #[prost(string, tag = "1")]
+    pub facet_type: String,
+    #[prost(message, repeated, tag = "2")]
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CreateEntityReply {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub bundle_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EntityRequest {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FieldRequest {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub field_key: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SetFieldRequest {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub field_key: String,
+    #[prost(message, optional, tag = "3")]
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CreateEdgeRequest {
+    #[prost(string, tag = "1")]
+    pub edge_type: String,
+    #[prost(bytes, tag = "2")]
+    pub source_id: Vec<u8>,
+    #[prost(bytes, tag = "3")]
+    pub target_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CreateEdgeReply {
+    #[prost(bytes, tag = "1")]
+    pub edge_id: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub bundle_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EdgeRequest {
+    #[prost(bytes, tag = "1")]
+    pub edge_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BundleReply {
+    #[prost(bytes, tag = "1")]
+    pub bundle_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct UndoReply {
+    #[prost(bool, tag = "1")]
+    pub applied: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EntityReply {
+    #[prost(bytes, tag = "1")]
+    pub entity_id: Vec<u8>,
+    #[prost(bool, tag = "2")]
+    pub deleted: bool,
+    #[prost(string, repeated, tag = "3")]
+    pub facets: Vec<String>,
+    #[prost(message, repeated, tag = "4")]
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GetFieldReply {
+    #[prost(message, optional, tag = "1")]
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FacetRequest {
+    #[prost(string, tag = "1")]
+    pub facet_type: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EntityIds {
+    #[prost(bytes, repeated, tag = "1")]
+    pub entity_ids: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ConflictValue {
+    #[prost(message, optional, tag = "1")]
+    pub value: Option<Value>,
+    #[prost(bytes, tag = "2")]
+    pub actor_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Conflict {
+    #[prost(bytes, tag = "1")]
+    pub conflict_id: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub entity_id: Vec<u8>,
+    #[prost(string, tag = "3")]
+    pub field_key: String,
+    #[prost(message, repeated, tag = "4")]
+    pub values: Vec<ConflictValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ConflictList {
+    #[prost(message, repeated, tag = "1")]
+    pub conflicts: Vec<Conflict>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ResolveConflictRequest {
+    #[prost(bytes, tag = "1")]
+    pub conflict_id: Vec<u8>,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CreateOverlayRequest {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct OverlayReply {
+    #[prost(bytes, tag = "1")]
+    pub overlay_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct OverlayRequest {
+    #[prost(bytes, tag = "1")]
+    pub overlay_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BundleEvent {
+    #[prost(bytes, tag = "1")]
+    pub bundle_id: Vec<u8>,
+    #[prost(bytes, tag = "2")]
+    pub actor_id: Vec<u8>,
+    #[prost(uint32, tag = "3")]
+    pub op_count: u32,
+    #[prost(bytes, repeated, tag = "4")]
+    pub created: Vec<Vec<u8>>,
+    #[prost(bytes, repeated, tag = "5")]
+    pub deleted: Vec<Vec<u8>>,
+}
@@ -0,0 +1,145 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use openprod_core::{field_value::FieldValue, identity::ActorIdentity};
+use openprod_engine::Engine;
+use openprod_grpc::proto::{self, engine_client::EngineClient, value::Kind};
+use openprod_net::SharedEngine;
+use openprod_storage::SqliteStorage;
+use tokio::net::TcpListener;
+use tonic::Code;
+use tonic::transport::Channel;
+
+fn shared_engine() -> Result<SharedEngine, Box<dyn std::error::Error>> {
+    let storage = SqliteStorage::open_in_memory()?;
+    Ok(Arc::new(Mutex::new(Engine::new(ActorIdentity::generate(), storage)?)))
+}
+
+/// Serve `engine` on an ephemeral port and connect a client to it.
+async fn connect(engine: SharedEngine) -> Result<EngineClient<Channel>, Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(openprod_grpc::serve(engine, listener));
+    Ok(EngineClient::connect(format!("http://{addr}")).await?)
+}
+
+fn text(s: &str) -> Option<proto::Value> {
+    Some(proto::Value { kind: Some(Kind::Text(s.into())) })
+}
+
+#[tokio::test]
+async fn commands_and_queries_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let engine = shared_engine()?;
+    let mut client = connect(engine.clone()).await?;
+
+    let created = client
+        .create_entity(proto::CreateEntityRequest {
+            facet_type: "Cue".into(),
+            fields: vec![proto::Field { key: "label".into(), value: text("Q1") }],
+        })
+        .await?
+        .into_inner();
+    let entity_id = created.entity_id.clone();
+    client
+        .set_field(proto::SetFieldRequest { entity_id: entity_id.clone(), field_key: "label".into(), value: text("Q2") })
+        .await?;
+
+    let field = proto::FieldRequest { entity_id: entity_id.clone(), field_key: "label".into() };
+    assert_eq!(client.get_field(field.clone()).await?.into_inner().value, text("Q2"));
+    assert!(client.undo(proto::Empty {}).await?.into_inner().applied);
+    assert_eq!(client.get_field(field.clone()).await?.into_inner().value, text("Q1"));
+
+    // The shared engine sees the same state as gRPC clients
+    let cue = openprod_core::ids::EntityId::from_bytes(entity_id.as_slice().try_into()?);
+    assert_eq!(engine.lock().unwrap().get_field(cue, "label")?, Some(FieldValue::Text("Q1".into())));
+
+    let entity = client.get_entity(proto::EntityRequest { entity_id: entity_id.clone() }).await?.into_inner();
+    assert_eq!(entity.facets, vec!["Cue".to_string()]);
+    assert_eq!(entity.fields, vec![proto::Field { key: "label".into(), value: text("Q1") }]);
+    let cues = client.entities_by_facet(proto::FacetRequest { facet_type: "Cue".into() }).await?.into_inner();
+    assert_eq!(cues.entity_ids, vec![entity_id.clone()]);
+
+    // Overlay lifecycle: edits stay out of canonical state until commit
+    let overlay = client.create_overlay(proto::CreateOverlayRequest { name: "draft".into() }).await?.into_inner();
+    client
+        .set_field(proto::SetFieldRequest { entity_id: entity_id.clone(), field_key: "label".into(), value: text("Draft") })
+        .await?;
+    let overlay = proto::OverlayRequest { overlay_id: overlay.overlay_id };
+    client.stash_overlay(overlay.clone()).await?;
+    assert_eq!(client.get_field(field.clone()).await?.into_inner().value, text("Q1"));
+    client.activate_overlay(overlay.clone()).await?;
+    client.commit_overlay(overlay).await?;
+    assert_eq!(client.get_field(field).await?.into_inner().value, text("Draft"));
+
+    // Errors map to gRPC status codes
+    let missing = client.get_entity(proto::EntityRequest { entity_id: vec![7; 16] }).await.unwrap_err();
+    assert_eq!(missing.code(), Code::NotFound);
+    let malformed = client.get_entity(proto::EntityRequest { entity_id: vec![1, 2, 3] }).await.unwrap_err();
+    assert_eq!(malformed.code(), Code::InvalidArgument);
+    client.delete_entity(proto::EntityRequest { entity_id: entity_id.clone() }).await?;
+    let deleted = client
+        .clear_field(proto::FieldRequest { entity_id, field_key: "label".into() })
+        .await
+        .unwrap_err();
+    assert_eq!(deleted.code(), Code::FailedPrecondition);
+    Ok(())
+}
+
+#[tokio::test]
+async fn conflicts_can_be_listed_and_resolved() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let mut client = connect(server.clone()).await?;
+    let mut peer = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+
+    let (cue, _) = peer.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    let sync = |from: &Engine, to: &mut Engine| -> Result<(), Box<dyn std::error::Error>> {
+        let mut batch = Vec::new();
+        for bundle_id in from.bundles_missing_from(&to.get_vector_clock()?)? {
+            let bundle = openprod_storage::Storage::get_bundle(from.storage(), bundle_id)?.ok_or("missing")?;
+            batch.push((bundle, from.get_ops_by_bundle(bundle_id)?));
+        }
+        to.ingest_bundles(&batch)?;
+        Ok(())
+    };
+    sync(&peer, &mut server.lock().unwrap())?;
+    server.lock().unwrap().set_field(cue, "label", FieldValue::Text("Server".into()))?;
+    peer.set_field(cue, "label", FieldValue::Text("Peer".into()))?;
+    sync(&peer, &mut server.lock().unwrap())?;
+
+    let entity = proto::EntityRequest { entity_id: cue.as_bytes().to_vec() };
+    let conflicts = client.list_conflicts(entity.clone()).await?.into_inner().conflicts;
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].field_key, "label");
+    assert_eq!(conflicts[0].values.len(), 2);
+
+    client
+        .resolve_conflict(proto::ResolveConflictRequest {
+            conflict_id: conflicts[0].conflict_id.clone(),
+            value: text("Both"),
+        })
+        .await?;
+    assert!(client.list_conflicts(entity).await?.into_inner().conflicts.is_empty());
+    assert_eq!(server.lock().unwrap().get_field(cue, "label")?, Some(FieldValue::Text("Both".into())));
+    Ok(())
+}
+
+#[tokio::test]
+async fn watch_streams_new_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let engine = shared_engine()?;
+    let mut client = connect(engine.clone()).await?;
+    let mut events = client.watch(proto::Empty {}).await?.into_inner();
+
+    // Edits from the embedding process and from gRPC clients both show up
+    let (cue, _) = engine.lock().unwrap().create_entity(Some("Cue"))?;
+    client
+        .set_field(proto::SetFieldRequest { entity_id: cue.as_bytes().to_vec(), field_key: "label".into(), value: text("Q1") })
+        .await?;
+
+    let first = tokio::time::timeout(Duration::from_secs(5), events.message()).await??.ok_or("stream ended")?;
+    assert_eq!(first.created, vec![cue.as_bytes().to_vec()]);
+    assert_eq!(first.actor_id, engine.lock().unwrap().actor_id().as_bytes().to_vec());
+    let second = tokio::time::timeout(Duration::from_secs(5), events.message()).await??.ok_or("stream ended")?;
+    assert_eq!(second.op_count, 1);
+    assert!(second.created.is_empty());
+    Ok(())
+}
@@ -0,0 +1,231 @@
+//! Random multi-peer scenarios for property-based convergence testing.
+//!
+//! A [`Scenario`] is a list of [`Step`]s — local edits on a peer, one-way
+//! syncs, and network partitions — generated by [`scenario_strategy`]. Steps
+//! name entities and edges by creation order rather than by id, so proptest
+//! can shrink a failing scenario by dropping steps without invalidating the
+//! ones that remain. [`run`] plays a scenario, heals the network, syncs
+//! everyone and hands back the network for [`check_converged`].
+
+use std::collections::BTreeMap;
+
+use openprod_core::{
+    field_value::FieldValue,
+    ids::{EdgeId, EntityId},
+    vector_clock::VectorClock,
+};
+use openprod_engine::Engine;
+use proptest::prelude::*;
+
+use crate::TestNetwork;
+
+/// Facet every scenario entity is created with.
+pub const FACET: &str = "Item";
+
+/// Field keys scenario edits draw from; kept small so peers collide often.
+pub const FIELD_KEYS: [&str; 3] = ["a", "b", "c"];
+
+/// Edge type used by `Link` steps.
+pub const EDGE_TYPE: &str = "links";
+
+type Error = Box<dyn std::error::Error>;
+
+/// One action in a scenario. `entity`, `source`, `target` and `edge` index
+/// (modulo) the entities or edges created so far anywhere in the network; a
+/// step whose target the acting peer hasn't seen, or has seen deleted, does
+/// nothing.
+#[derive(Debug, Clone)]
+pub enum Step {
+    Create { peer: usize },
+    SetField { peer: usize, entity: usize, field: usize, value: i64 },
+    ClearField { peer: usize, entity: usize, field: usize },
+    Delete { peer: usize, entity: usize },
+    Link { peer: usize, source: usize, target: usize },
+    Unlink { peer: usize, edge: usize },
+    /// Send `to` everything `from` has that `to` lacks. Lost if a partition
+    /// separates them.
+    Sync { from: usize, to: usize },
+    /// Split the network (see `TestNetwork::partition`): peers whose bit is
+    /// set in `side` can only sync with each other.
+    Partition { side: u32 },
+    Heal,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub peers: usize,
+    pub steps: Vec<Step>,
+}
+
+/// Scenarios over 2..=`max_peers` peers with up to `max_steps` steps.
+pub fn scenario_strategy(max_peers: usize, max_steps: usize) -> impl Strategy<Value = Scenario> {
+    assert!((2..=32).contains(&max_peers), "scenarios need 2..=32 peers");
+    (2..=max_peers).prop_flat_map(move |peers| {
+        prop::collection::vec(step_strategy(peers), 0..=max_steps).prop_map(move |steps| Scenario { peers, steps })
+    })
+}
+
+fn step_strategy(peers: usize) -> impl Strategy<Value = Step> {
+    let peer = 0..peers;
+    let index = 0..16usize;
+    let field = 0..FIELD_KEYS.len();
+    prop_oneof![
+        3 => peer.clone().prop_map(|peer| Step::Create { peer }),
+        6 => (peer.clone(), index.clone(), field.clone(), 0..4i64)
+            .prop_map(|(peer, entity, field, value)| Step::SetField { peer, entity, field, value }),
+        2 => (peer.clone(), index.clone(), field)
+            .prop_map(|(peer, entity, field)| Step::ClearField { peer, entity, field }),
+        1 => (peer.clone(), index.clone()).prop_map(|(peer, entity)| Step::Delete { peer, entity }),
+        2 => (peer.clone(), index.clone(), index.clone())
+            .prop_map(|(peer, source, target)| Step::Link { peer, source, target }),
+        1 => (peer.clone(), index).prop_map(|(peer, edge)| Step::Unlink { peer, edge }),
+        5 => (peer.clone(), peer).prop_map(|(from, to)| Step::Sync { from, to }),
+        1 => (1..(1u32 << peers) - 1).prop_map(|side| Step::Partition { side }),
+        1 => Just(Step::Heal),
+    ]
+}
+
+/// The outcome of [`run`]: the synced network plus every entity and edge the
+/// scenario created, in creation order.
+pub struct Outcome {
+    pub peers: usize,
+    pub network: TestNetwork,
+    pub entities: Vec<EntityId>,
+    pub edges: Vec<EdgeId>,
+}
+
+/// Play `scenario` on a fresh network, then heal any partition and sync all
+/// peers to quiescence.
+pub fn run(scenario: &Scenario) -> Result<Outcome, Error> {
+    let mut network = TestNetwork::new();
+    for _ in 0..scenario.peers {
+        network.add_peer()?;
+    }
+    let mut entities: Vec<EntityId> = Vec::new();
+    let mut edges: Vec<EdgeId> = Vec::new();
+
+    for step in &scenario.steps {
+        match *step {
+            Step::Create { peer } => {
+                entities.push(network.peer_mut(peer).create_record(FACET, Vec::new())?);
+            }
+            Step::SetField { peer, entity, field, value } => {
+                if let Some(entity_id) = live_entity(&network, peer, &entities, entity)? {
+                    let key = FIELD_KEYS[field];
+                    network.peer_mut(peer).set_field(entity_id, key, FieldValue::Integer(value))?;
+                }
+            }
+            Step::ClearField { peer, entity, field } => {
+                if let Some(entity_id) = live_entity(&network, peer, &entities, entity)? {
+                    network.peer_mut(peer).clear_field(entity_id, FIELD_KEYS[field])?;
+                }
+            }
+            Step::Delete { peer, entity } => {
+                if let Some(entity_id) = live_entity(&network, peer, &entities, entity)? {
+                    network.peer_mut(peer).delete_entity(entity_id)?;
+                }
+            }
+            Step::Link { peer, source, target } => {
+                let source = live_entity(&network, peer, &entities, source)?;
+                let target = live_entity(&network, peer, &entities, target)?;
+                if let (Some(source), Some(target)) = (source, target) {
+                    edges.push(network.peer_mut(peer).create_edge(EDGE_TYPE, source, target)?);
+                }
+            }
+            Step::Unlink { peer, edge } => {
+                if edges.is_empty() {
+                    continue;
+                }
+                let edge_id = edges[edge % edges.len()];
+                let engine = &network.peer(peer).engine;
+                if engine.get_edge(edge_id)?.is_some_and(|e| !e.deleted) {
+                    network.peer_mut(peer).delete_edge(edge_id)?;
+                }
+            }
+            Step::Sync { from, to } => {
+                if from != to {
+                    network.sync_to(from, to)?;
+                }
+            }
+            Step::Partition { side } => {
+                let side: Vec<usize> = (0..scenario.peers).filter(|peer| side >> peer & 1 == 1).collect();
+                network.partition(&side);
+            }
+            Step::Heal => network.heal(),
+        }
+    }
+
+    network.heal();
+    network.sync_all()?;
+    Ok(Outcome { peers: scenario.peers, network, entities, edges })
+}
+
+/// The entity at `index` (modulo) if `peer` holds it and it isn't deleted.
+fn live_entity(
+    network: &TestNetwork,
+    peer: usize,
+    entities: &[EntityId],
+    index: usize,
+) -> Result<Option<EntityId>, Error> {
+    if entities.is_empty() {
+        return Ok(None);
+    }
+    let entity_id = entities[index % entities.len()];
+    let live = network.peer(peer).engine.get_entity(entity_id)?.is_some_and(|e| !e.deleted);
+    Ok(live.then_some(entity_id))
+}
+
+/// Materialized state of one entity as a peer sees it: deleted flag, facets
+/// (with detached flag) and fields, each sorted.
+pub type EntityState = (bool, Vec<(String, bool)>, Vec<(String, FieldValue)>);
+
+/// Everything compared across peers after a scenario.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PeerState {
+    pub vector_clock: VectorClock,
+    pub entities: BTreeMap<EntityId, Option<EntityState>>,
+    pub edges: BTreeMap<EdgeId, Option<bool>>,
+}
+
+/// Snapshot the scenario's entities and edges as peer `index` sees them.
+pub fn peer_state(outcome: &Outcome, index: usize) -> Result<PeerState, Error> {
+    state_of(&outcome.network.peer(index).engine, &outcome.entities, &outcome.edges)
+}
+
+/// Snapshot `entities` and `edges` as `engine` sees them.
+pub(crate) fn state_of(engine: &Engine, entities: &[EntityId], edges: &[EdgeId]) -> Result<PeerState, Error> {
+    let mut entity_states = BTreeMap::new();
+    for &entity_id in entities {
+        let state = match engine.get_entity(entity_id)? {
+            Some(record) => {
+                let mut facets: Vec<_> =
+                    engine.get_facets(entity_id)?.into_iter().map(|f| (f.facet_type, f.detached)).collect();
+                facets.sort();
+                let mut fields = engine.get_fields(entity_id)?;
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                Some((record.deleted, facets, fields))
+            }
+            None => None,
+        };
+        entity_states.insert(entity_id, state);
+    }
+    let mut edge_states = BTreeMap::new();
+    for &edge_id in edges {
+        edge_states.insert(edge_id, engine.get_edge(edge_id)?.map(|e| e.deleted));
+    }
+    Ok(PeerState { vector_clock: engine.get_vector_clock()?, entities: entity_states, edges: edge_states })
+}
+
+/// Check every peer ended with the same vector clock and materialized state
+/// as peer 0. The error names the first peer that diverged and how.
+pub fn check_converged(outcome: &Outcome) -> Result<(), String> {
+    let state = |index| peer_state(outcome, index).map_err(|e| format!("peer {index}: {e}"));
+    let expected = state(0)?;
+    for index in 1..outcome.peers {
+        let actual = state(index)?;
+        if actual != expected {
+            return Err(format!("peer {index} diverged from peer 0:\n  peer 0: {expected:?}\n  peer {index}: {actual:?}"));
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,7 @@
 pub mod peer;
 pub mod network;
+pub mod convergence;
+pub mod script;
 
 pub use peer::TestPeer;
-pub use network::TestNetwork;
+pub use network::{LinkProfile, TestNetwork};
@@ -1,17 +1,81 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tempfile::TempDir;
 
 use openprod_core::{
-    hlc::Hlc,
-    ids::*,
-    operations::{Bundle, BundleType, Operation},
+    capabilities::{Capabilities, CompatibilityReport},
+    ids::BundleId,
+    operations::{Bundle, Operation},
     vector_clock::VectorClock,
 };
+use openprod_core::presence::{PresenceMessage, PresenceState};
+use openprod_engine::{EngineError, PresenceTracker, DEFAULT_PRESENCE_TIMEOUT};
 use openprod_storage::{ConflictRecord, Storage, StorageError};
 
 use crate::TestPeer;
 
+type BundleWithOps = (Bundle, Vec<Operation>);
+
+/// Ops read per page while scanning the sender's oplog.
+const SYNC_PAGE_SIZE: usize = 500;
+
+/// Rounds `sync_all` keeps retrying without progress when some link loses
+/// bundles, before deciding the network can't converge (e.g. it's partitioned).
+const LOSSY_IDLE_ROUNDS: usize = 32;
+
+/// How the link between two peers behaves. The default is a perfect link:
+/// every bundle arrives once, immediately, in the order it was sent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinkProfile {
+    /// Ticks between sending a bundle and its arrival.
+    pub latency: u64,
+    /// Extra delay of up to this many ticks, drawn per bundle, so bundles
+    /// sent together can arrive out of order.
+    pub jitter: u64,
+    /// Probability that a bundle is delivered twice.
+    pub duplicate: f64,
+    /// Probability that a bundle is lost in transit.
+    pub drop: f64,
+}
+
+impl LinkProfile {
+    fn is_perfect(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A bundle on its way to a peer over an imperfect link.
+struct InFlight {
+    arrives_at: u64,
+    from: usize,
+    to: usize,
+    bundle: BundleWithOps,
+}
+
 pub struct TestNetwork {
     peers: Vec<TestPeer>,
+    /// Per-peer awareness state, fed by `set_presence`; lost on restart.
+    presence: Vec<PresenceTracker>,
+    /// Per-peer capability overrides, to simulate peers running other versions.
+    capabilities: Vec<Option<Capabilities>>,
+    /// Bundles withheld from (from, to) because `to` can't materialize them.
+    parked: BTreeMap<(usize, usize), Vec<BundleId>>,
+    /// Link behaviour per unordered peer pair (lower index first); absent means perfect.
+    links: BTreeMap<(usize, usize), LinkProfile>,
+    /// Peers on one side of the current partition, if any.
+    partition: Option<BTreeSet<usize>>,
+    /// Bundles sent over links with latency, in send order.
+    in_flight: Vec<InFlight>,
+    /// Simulated time, advanced by `advance`.
+    now: u64,
+    /// Drives jitter, duplication and drops; seeded so runs are repeatable.
+    rng: StdRng,
+    /// Where restarted peers keep their databases, created on first restart.
+    disk: Option<TempDir>,
+    /// Restarts so far; each writes a fresh file so a peer already running
+    /// from disk is never backed up onto itself.
+    restarts: usize,
 }
 
 impl Default for TestNetwork {
@@ -22,13 +86,27 @@ impl Default for TestNetwork {
 
 impl TestNetwork {
     pub fn new() -> Self {
-        Self { peers: Vec::new() }
+        Self {
+            peers: Vec::new(),
+            presence: Vec::new(),
+            capabilities: Vec::new(),
+            parked: BTreeMap::new(),
+            links: BTreeMap::new(),
+            partition: None,
+            in_flight: Vec::new(),
+            now: 0,
+            rng: StdRng::seed_from_u64(0),
+            disk: None,
+            restarts: 0,
+        }
     }
 
-    pub fn add_peer(&mut self) -> Result<usize, StorageError> {
+    pub fn add_peer(&mut self) -> Result<usize, EngineError> {
         let peer = TestPeer::new()?;
         let index = self.peers.len();
+        self.presence.push(PresenceTracker::new(peer.actor_id(), DEFAULT_PRESENCE_TIMEOUT));
         self.peers.push(peer);
+        self.capabilities.push(None);
         Ok(index)
     }
 
@@ -40,79 +118,226 @@ impl TestNetwork {
         &mut self.peers[index]
     }
 
+    /// Restart a peer: write its database to disk, shut it down and open it
+    /// again under the same identity. Bundles in flight to it still arrive.
+    pub fn restart_peer(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = match &self.disk {
+            Some(dir) => dir,
+            None => self.disk.insert(tempfile::tempdir()?),
+        };
+        let path = dir.path().join(format!("peer-{index}-{}.db", self.restarts));
+        self.restarts += 1;
+        self.peers[index].save_to(&path)?;
+        let peer = self.peers.remove(index);
+        self.peers.insert(index, peer.reopen(&path)?);
+        self.presence[index] = PresenceTracker::new(self.peers[index].actor_id(), DEFAULT_PRESENCE_TIMEOUT);
+        Ok(())
+    }
+
+    /// Set a peer's awareness state and broadcast it straight away to every
+    /// peer on its side of any partition. Presence ignores link profiles, and
+    /// trackers read simulated ticks as milliseconds.
+    pub fn set_presence(&mut self, index: usize, state: PresenceState) -> PresenceMessage {
+        let message = self.presence[index].update(state, self.now);
+        for to in 0..self.peers.len() {
+            if to != index && !self.is_partitioned(index, to) {
+                self.presence[to].apply(&message, self.now);
+            }
+        }
+        message
+    }
+
+    /// What a peer knows about everyone else's presence.
+    pub fn presence(&self, index: usize) -> &PresenceTracker {
+        &self.presence[index]
+    }
+
+    /// Override the capabilities a peer advertises (e.g. to simulate an older build).
+    pub fn set_capabilities(&mut self, index: usize, capabilities: Capabilities) {
+        self.capabilities[index] = Some(capabilities);
+    }
+
+    /// Capabilities a peer advertises during the sync handshake.
+    pub fn capabilities(&self, index: usize) -> Capabilities {
+        self.capabilities[index]
+            .clone()
+            .unwrap_or_else(|| self.peers[index].engine.capabilities())
+    }
+
+    /// Compatibility report as seen by `from` when syncing to `to`.
+    pub fn compatibility(&self, from_idx: usize, to_idx: usize) -> CompatibilityReport {
+        self.capabilities(from_idx).compatibility_with(&self.capabilities(to_idx))
+    }
+
+    /// Set how the link between peers `a` and `b` behaves, in both directions.
+    pub fn set_link(&mut self, a: usize, b: usize, profile: LinkProfile) {
+        self.links.insert((a.min(b), a.max(b)), profile);
+    }
+
+    /// How the link between peers `a` and `b` behaves.
+    pub fn link(&self, a: usize, b: usize) -> LinkProfile {
+        self.links.get(&(a.min(b), a.max(b))).cloned().unwrap_or_default()
+    }
+
+    /// Reseed the randomness behind jitter, duplication and drops.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Cut every link between `side` and the remaining peers. Bundles sent
+    /// across the cut, or arriving across it, are lost. Replaces any earlier
+    /// partition.
+    pub fn partition(&mut self, side: &[usize]) {
+        self.partition = Some(side.iter().copied().collect());
+    }
+
+    /// Restore every link cut by `partition`.
+    pub fn heal(&mut self) {
+        self.partition = None;
+    }
+
+    /// Whether a partition currently separates peers `a` and `b`.
+    pub fn is_partitioned(&self, a: usize, b: usize) -> bool {
+        self.partition.as_ref().is_some_and(|side| side.contains(&a) != side.contains(&b))
+    }
+
+    /// Simulated time, in ticks.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Number of bundles sent but not yet delivered.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Move simulated time forward and deliver every bundle that has arrived,
+    /// in arrival order. Returns the conflicts detected.
+    pub fn advance(&mut self, ticks: u64) -> Result<Vec<ConflictRecord>, Box<dyn std::error::Error>> {
+        self.now += ticks;
+        self.deliver_arrived()
+    }
+
+    /// Advance time until nothing is in flight. Returns the conflicts detected.
+    pub fn flush(&mut self) -> Result<Vec<ConflictRecord>, Box<dyn std::error::Error>> {
+        let mut conflicts = Vec::new();
+        while let Some(next) = self.in_flight.iter().map(|m| m.arrives_at).min() {
+            conflicts.extend(self.advance(next.saturating_sub(self.now))?);
+        }
+        Ok(conflicts)
+    }
+
+    /// Bundles `from` is holding back from `to` because `to` can't materialize them.
+    pub fn parked(&self, from_idx: usize, to_idx: usize) -> &[BundleId] {
+        self.parked.get(&(from_idx, to_idx)).map(Vec::as_slice).unwrap_or(&[])
+    }
+
     /// Sync bundles from peer `from_idx` to peer `to_idx`.
     /// Uses vector clock diff to determine what needs syncing.
+    /// Bundles containing ops `to` can't materialize are parked and retried on
+    /// later syncs (e.g. after `to` upgrades).
+    /// Bundles travel over the link between the two peers (see `set_link`);
+    /// ones delayed by latency arrive on a later `advance`.
     /// Returns any conflicts detected during ingestion.
     pub fn sync_to(
         &mut self,
         from_idx: usize,
         to_idx: usize,
     ) -> Result<Vec<ConflictRecord>, Box<dyn std::error::Error>> {
-        // 1. Extract vector clock from `to` and canonical ops from `from` (immutable borrows)
+        // 1. Handshake: refuse to sync across wire format versions
+        let to_caps = self.capabilities(to_idx);
+        let report = self.compatibility(from_idx, to_idx);
+        if !report.can_sync() {
+            return Err(Box::new(EngineError::IncompatiblePeer(format!(
+                "format version {} vs {}",
+                report.local_format_version, report.remote_format_version,
+            ))));
+        }
+
+        // 2. Extract vector clock from `to` (immutable borrow)
         let to_vc = self.peers[to_idx].engine.get_vector_clock()?;
-        let from_ops = self.peers[from_idx].engine.get_ops_canonical()?;
 
-        // 2. Find unseen bundle_ids: ops whose actor+hlc is ahead of `to`'s vector clock
-        let mut unseen_bundle_ids = Vec::new();
-        let mut seen = BTreeSet::new();
-        for op in &from_ops {
+        // 3. Find unseen bundle_ids: ops of `from` whose actor+hlc is ahead of `to`'s
+        //    vector clock, plus anything parked on a previous sync
+        let mut candidate_ids = self.parked.remove(&(from_idx, to_idx)).unwrap_or_default();
+        let mut seen: BTreeSet<BundleId> = candidate_ids.iter().copied().collect();
+        for op in self.peers[from_idx].engine.ops_canonical_paged(SYNC_PAGE_SIZE) {
+            let op = op?;
             let is_new = match to_vc.get(&op.actor_id) {
                 Some(max_hlc) => op.hlc > *max_hlc,
                 None => true,
             };
             if is_new && seen.insert(op.bundle_id) {
-                unseen_bundle_ids.push((op.bundle_id, op.hlc));
+                candidate_ids.push(op.bundle_id);
             }
         }
 
-        // Sort by HLC for correct causal ingestion order
-        unseen_bundle_ids.sort_by(|a, b| a.1.cmp(&b.1));
+        // 4. Load the stored bundles (original type, signature and meta) from `from`,
+        //    parking the ones `to` can't materialize
+        let mut supported = Vec::new();
+        let mut still_parked = Vec::new();
+        for bundle_id in candidate_ids {
+            if self.peers[from_idx].engine.bundle_supported_by(bundle_id, &to_caps)? {
+                supported.push(bundle_id);
+            } else {
+                still_parked.push(bundle_id);
+            }
+        }
+        if !still_parked.is_empty() {
+            self.parked.insert((from_idx, to_idx), still_parked);
+        }
+        let signed_bundles = self.load_bundles(from_idx, &supported)?;
 
-        // 3. Extract all bundle data from `from` peer into owned structures
-        struct BundleData {
-            bundle_id: BundleId,
-            hlc: Hlc,
-            ops: Vec<Operation>,
-            vc: Option<VectorClock>,
+        // 5. Ingest into `to` peer in one batch (mutable borrow, no overlap with `from`),
+        //    or put the bundles on the wire if the link isn't perfect
+        let link = self.link(from_idx, to_idx);
+        if link.is_perfect() && !self.is_partitioned(from_idx, to_idx) {
+            return Ok(self.peers[to_idx].engine.ingest_bundles(&signed_bundles)?.conflicts);
         }
+        self.send(from_idx, to_idx, &link, signed_bundles);
+        self.deliver_arrived()
+    }
 
-        let mut bundles_to_sync = Vec::new();
-        for (bundle_id, hlc) in &unseen_bundle_ids {
-            let ops = self.peers[from_idx].engine.get_ops_by_bundle(*bundle_id)?;
-            let vc = self.peers[from_idx]
-                .engine
-                .storage()
-                .get_bundle_vector_clock(*bundle_id)?;
-            bundles_to_sync.push(BundleData {
-                bundle_id: *bundle_id,
-                hlc: *hlc,
-                ops,
-                vc,
-            });
-        }
-
-        // 4. Build signed bundles (immutable borrow of `from` peer for identity)
-        let mut signed_bundles: Vec<(Bundle, Vec<Operation>)> = Vec::new();
-        for data in bundles_to_sync {
-            let bundle = Bundle::new_signed(
-                data.bundle_id,
-                self.peers[from_idx].engine.identity(),
-                data.hlc,
-                BundleType::UserEdit,
-                &data.ops,
-                data.vc,
-            )?;
-            signed_bundles.push((bundle, data.ops));
-        }
-
-        // 5. Ingest into `to` peer (mutable borrow, no overlap with `from`)
-        let mut all_conflicts = Vec::new();
-        for (bundle, ops) in &signed_bundles {
-            let conflicts = self.peers[to_idx].engine.ingest_bundle(bundle, ops)?;
-            all_conflicts.extend(conflicts);
+    /// Put bundles on the wire from `from` to `to`, applying the link's drops,
+    /// duplication, latency and jitter.
+    fn send(&mut self, from: usize, to: usize, link: &LinkProfile, bundles: Vec<BundleWithOps>) {
+        if self.is_partitioned(from, to) {
+            return;
         }
+        for bundle in bundles {
+            if self.rng.gen_bool(link.drop) {
+                continue;
+            }
+            let copies = if self.rng.gen_bool(link.duplicate) { 2 } else { 1 };
+            for _ in 0..copies {
+                let arrives_at = self.now + link.latency + self.rng.gen_range(0..=link.jitter);
+                self.in_flight.push(InFlight { arrives_at, from, to, bundle: bundle.clone() });
+            }
+        }
+    }
 
-        Ok(all_conflicts)
+    /// Ingest every in-flight bundle whose arrival time has come, batched per
+    /// receiving peer in arrival order. Bundles arriving across a partition are lost.
+    fn deliver_arrived(&mut self) -> Result<Vec<ConflictRecord>, Box<dyn std::error::Error>> {
+        let now = self.now;
+        let (mut arrived, pending): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.in_flight).into_iter().partition(|m| m.arrives_at <= now);
+        self.in_flight = pending;
+        // Stable, so bundles arriving on the same tick keep their send order
+        arrived.sort_by_key(|m| m.arrives_at);
+
+        let mut batches: BTreeMap<usize, Vec<BundleWithOps>> = BTreeMap::new();
+        for message in arrived {
+            if self.is_partitioned(message.from, message.to) {
+                continue;
+            }
+            batches.entry(message.to).or_default().push(message.bundle);
+        }
+        let mut conflicts = Vec::new();
+        for (to, batch) in batches {
+            conflicts.extend(self.peers[to].engine.ingest_bundles(&batch)?.conflicts);
+        }
+        Ok(conflicts)
     }
 
     /// Bidirectional sync between two peers.
@@ -127,43 +352,102 @@ impl TestNetwork {
         Ok(conflicts)
     }
 
-    /// Full mesh sync: repeat pairwise syncing until all peers are quiescent
-    /// (all vector clocks are equal). Returns all detected conflicts.
+    /// Bidirectional sync driven by range-digest reconciliation instead of vector
+    /// clocks: peers exchange digest rounds until every mismatched range is
+    /// resolved, then each sends the other what it lacks. Returns the number of
+    /// message rounds exchanged and all detected conflicts.
+    pub fn sync_by_digest(
+        &mut self,
+        a: usize,
+        b: usize,
+    ) -> Result<(usize, Vec<ConflictRecord>), Box<dyn std::error::Error>> {
+        let mut a_needs: Vec<BundleId> = Vec::new();
+        let mut b_needs: Vec<BundleId> = Vec::new();
+        let mut messages = self.peers[a].engine.begin_reconcile()?;
+        let mut rounds = 0;
+        // Alternate receivers: b answers a's opening digest, then a, then b, ...
+        let (mut receiver, mut sender) = (b, a);
+        while !messages.is_empty() {
+            rounds += 1;
+            let round = self.peers[receiver].engine.reconcile(&messages)?;
+            let (receiver_needs, sender_needs) = if receiver == a {
+                (&mut a_needs, &mut b_needs)
+            } else {
+                (&mut b_needs, &mut a_needs)
+            };
+            receiver_needs.extend(round.missing_locally);
+            sender_needs.extend(round.missing_remotely);
+            messages = round.reply;
+            std::mem::swap(&mut receiver, &mut sender);
+        }
+
+        let to_a = self.load_bundles(b, &a_needs)?;
+        let to_b = self.load_bundles(a, &b_needs)?;
+        let mut conflicts = self.peers[a].engine.ingest_bundles(&to_a)?.conflicts;
+        conflicts.extend(self.peers[b].engine.ingest_bundles(&to_b)?.conflicts);
+        Ok((rounds, conflicts))
+    }
+
+    /// Stored bundles (with ops) from a peer, sorted by HLC for ingestion.
+    fn load_bundles(
+        &self,
+        index: usize,
+        bundle_ids: &[BundleId],
+    ) -> Result<Vec<BundleWithOps>, Box<dyn std::error::Error>> {
+        let engine = &self.peers[index].engine;
+        let mut bundles = Vec::with_capacity(bundle_ids.len());
+        for bundle_id in bundle_ids {
+            let bundle = engine
+                .storage()
+                .get_bundle(*bundle_id)?
+                .ok_or_else(|| StorageError::NotFound(format!("bundle {bundle_id}")))?;
+            bundles.push((bundle, engine.get_ops_by_bundle(*bundle_id)?));
+        }
+        bundles.sort_by_key(|(bundle, _)| bundle.hlc);
+        Ok(bundles)
+    }
+
+    /// Full mesh sync: repeat pairwise syncing, delivering anything in
+    /// flight after each round, until all peers are quiescent (all vector
+    /// clocks are equal) or rounds stop making progress. Over lossy links a
+    /// round can deliver nothing, so several idle rounds are tolerated before
+    /// giving up. Returns all detected conflicts.
     pub fn sync_all(&mut self) -> Result<Vec<ConflictRecord>, Box<dyn std::error::Error>> {
         let mut all_conflicts = Vec::new();
         let n = self.peers.len();
         if n <= 1 {
             return Ok(all_conflicts);
         }
+        let lossy = self.links.values().any(|link| link.drop > 0.0);
+        let max_idle_rounds = if lossy { LOSSY_IDLE_ROUNDS } else { 1 };
+        let mut idle_rounds = 0;
 
         loop {
-            let mut synced_any = false;
+            let before = self.vector_clocks()?;
             for i in 0..n {
                 for j in 0..n {
                     if i != j {
-                        let conflicts = self.sync_to(i, j)?;
-                        if !conflicts.is_empty() {
-                            synced_any = true;
-                        }
-                        all_conflicts.extend(conflicts);
+                        all_conflicts.extend(self.sync_to(i, j)?);
                     }
                 }
             }
+            all_conflicts.extend(self.flush()?);
 
             // Check quiescence: all vector clocks should be equal
-            let vc0 = self.peers[0].engine.get_vector_clock()?;
-            let all_equal = (1..n).all(|i| {
-                self.peers[i]
-                    .engine
-                    .get_vector_clock()
-                    .map(|vc| vc == vc0)
-                    .unwrap_or(false)
-            });
-            if all_equal || !synced_any {
+            let after = self.vector_clocks()?;
+            if after.iter().all(|vc| *vc == after[0]) {
+                break;
+            }
+            idle_rounds = if after == before { idle_rounds + 1 } else { 0 };
+            if idle_rounds >= max_idle_rounds {
                 break;
             }
         }
 
         Ok(all_conflicts)
     }
+
+    fn vector_clocks(&self) -> Result<Vec<VectorClock>, EngineError> {
+        self.peers.iter().map(|peer| peer.engine.get_vector_clock()).collect()
+    }
 }
@@ -1,22 +1,75 @@
+use std::cell::Cell;
+use std::path::Path;
+
 use openprod_core::{
     field_value::FieldValue,
     identity::ActorIdentity,
     ids::*,
     operations::*,
 };
-use openprod_engine::Engine;
-use openprod_storage::{SqliteStorage, StorageError};
+use openprod_engine::{Engine, EngineError};
+use openprod_storage::SqliteStorage;
+
+thread_local! {
+    /// Next identity seed for peers created on this thread. libtest runs each test
+    /// on its own thread, so every test sees the same sequence of actor ids.
+    static NEXT_SEED: Cell<u64> = const { Cell::new(1) };
+}
+
+/// Allocate the next deterministic identity seed for this thread.
+fn next_seed() -> u64 {
+    NEXT_SEED.with(|seed| {
+        let value = seed.get();
+        seed.set(value + 1);
+        value
+    })
+}
 
 pub struct TestPeer {
     pub engine: Engine,
 }
 
 impl TestPeer {
-    pub fn new() -> Result<Self, StorageError> {
-        let identity = ActorIdentity::generate();
+    /// Create a peer with the next deterministic identity for this test.
+    pub fn new() -> Result<Self, EngineError> {
+        Self::with_seed(next_seed())
+    }
+
+    /// Create a peer whose identity is derived from `seed`.
+    pub fn with_seed(seed: u64) -> Result<Self, EngineError> {
+        let identity = ActorIdentity::from_seed(seed);
         let storage = SqliteStorage::open_in_memory()?;
         Ok(Self {
-            engine: Engine::new(identity, storage),
+            engine: Engine::new(identity, storage)?,
+        })
+    }
+
+    /// Create a peer with the next deterministic identity, backed by a
+    /// database file at `path` instead of memory.
+    pub fn on_disk(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::open(path, ActorIdentity::from_seed(next_seed()))
+    }
+
+    /// Copy this peer's database to `path`, replacing any file there.
+    pub fn save_to(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        self.engine.storage().backup_to(path)?;
+        Ok(())
+    }
+
+    /// Shut this peer down and start it again from the database at `path`,
+    /// under the same identity. Anything not in that database is gone, as
+    /// after a crash: overlays and pending bundles come back, but the undo
+    /// stack (held in memory) starts empty.
+    pub fn reopen(self, path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let identity = ActorIdentity::from_secret_bytes(&self.identity().secret_bytes());
+        drop(self);
+        Self::open(path, identity)
+    }
+
+    fn open(path: impl AsRef<Path>, identity: ActorIdentity) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref().to_str().ok_or("database path is not valid UTF-8")?;
+        Ok(Self {
+            engine: Engine::new(identity, SqliteStorage::open(path)?)?,
         })
     }
 
@@ -190,6 +243,18 @@ impl TestPeer {
         Ok(())
     }
 
+    /// Replace an overlay's drifted value with a merge of both sides ("Merge").
+    pub fn merge_drift(
+        &mut self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+        merged_value: Option<FieldValue>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.engine.merge_drift(overlay_id, entity_id, field_key, merged_value)?;
+        Ok(())
+    }
+
     // Conflict convenience methods
 
     /// Get open conflicts for an entity.
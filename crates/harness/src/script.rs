@@ -0,0 +1,331 @@
+//! Declarative, deterministic test scenarios.
+//!
+//! A [`Script`] names its peers, then lists steps — local edits, syncs,
+//! network conditions and expectations — that run in order against a fresh
+//! [`TestNetwork`]. Scripts are plain data, so they can be built in Rust or
+//! written as TOML and attached to a bug report:
+//!
+//! ```toml
+//! peers = ["alice", "bob"]
+//!
+//! [[step]]
+//! do = "create"
+//! peer = "alice"
+//! facet = "Cue"
+//! name = "q1"
+//! fields = { label = "Q1" }
+//!
+//! [[step]]
+//! do = "sync"
+//! from = "alice"
+//! to = "bob"
+//!
+//! [[step]]
+//! do = "expect_field"
+//! peer = "bob"
+//! entity = "q1"
+//! field = "label"
+//! value = "Q1"
+//! ```
+//!
+//! Entities and edges are referred to by the name given when they were
+//! created. Peers get the same deterministic identities on every run.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use openprod_core::{
+    field_value::FieldValue,
+    ids::{EdgeId, EntityId},
+};
+use openprod_engine::UndoResult;
+use serde::{Deserialize, Serialize};
+
+use crate::convergence::state_of;
+use crate::{LinkProfile, TestNetwork};
+
+/// A scenario: named peers and the steps to run against them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Script {
+    pub peers: Vec<String>,
+    #[serde(default, rename = "step")]
+    pub steps: Vec<Step>,
+}
+
+/// A field value as written in a script. TOML strings, integers, floats and
+/// booleans map to the matching `FieldValue`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ScriptValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl From<&ScriptValue> for FieldValue {
+    fn from(value: &ScriptValue) -> Self {
+        match value {
+            ScriptValue::Bool(b) => FieldValue::Boolean(*b),
+            ScriptValue::Integer(i) => FieldValue::Integer(*i),
+            ScriptValue::Float(f) => FieldValue::Float(*f),
+            ScriptValue::Text(s) => FieldValue::Text(s.clone()),
+        }
+    }
+}
+
+/// One step of a script, tagged by `do` in TOML.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "do", rename_all = "snake_case")]
+pub enum Step {
+    Create {
+        peer: String,
+        facet: String,
+        name: String,
+        #[serde(default)]
+        fields: BTreeMap<String, ScriptValue>,
+    },
+    Set { peer: String, entity: String, field: String, value: ScriptValue },
+    Clear { peer: String, entity: String, field: String },
+    Delete { peer: String, entity: String },
+    CreateEdge { peer: String, edge_type: String, source: String, target: String, name: String },
+    DeleteEdge { peer: String, edge: String },
+    Undo { peer: String },
+    Redo { peer: String },
+    /// One-way sync, see `TestNetwork::sync_to`.
+    Sync { from: String, to: String },
+    SyncAll,
+    /// Set how the link between two peers behaves; omitted knobs are perfect.
+    SetLink {
+        a: String,
+        b: String,
+        #[serde(default)]
+        latency: u64,
+        #[serde(default)]
+        jitter: u64,
+        #[serde(default)]
+        duplicate: f64,
+        #[serde(default)]
+        drop: f64,
+    },
+    /// Cut the listed peers off from the rest.
+    Partition { side: Vec<String> },
+    Heal,
+    Advance { ticks: u64 },
+    Flush,
+    /// The field has `value` on `peer`; omit `value` to expect it unset.
+    ExpectField {
+        peer: String,
+        entity: String,
+        field: String,
+        #[serde(default)]
+        value: Option<ScriptValue>,
+    },
+    ExpectDeleted {
+        peer: String,
+        entity: String,
+        #[serde(default = "yes")]
+        deleted: bool,
+    },
+    ExpectConflicts { peer: String, count: usize },
+    /// Every peer has the same vector clock and sees the same state for every
+    /// named entity and edge.
+    ExpectConverged,
+}
+
+fn yes() -> bool {
+    true
+}
+
+/// Why a script failed to parse or run. Run failures carry the 1-based step
+/// number, so a report can point at the line that broke.
+#[derive(Debug)]
+pub struct ScriptError {
+    pub step: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.step {
+            Some(step) => write!(f, "step {step}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl ScriptError {
+    fn new(message: impl Into<String>) -> Self {
+        Self { step: None, message: message.into() }
+    }
+}
+
+type Error = Box<dyn std::error::Error>;
+
+/// The network a script ran against, with its names resolved, for further
+/// assertions in Rust.
+pub struct ScriptRun {
+    pub network: TestNetwork,
+    pub peers: BTreeMap<String, usize>,
+    pub entities: BTreeMap<String, EntityId>,
+    pub edges: BTreeMap<String, EdgeId>,
+}
+
+impl ScriptRun {
+    pub fn peer(&self, name: &str) -> Result<usize, ScriptError> {
+        self.peers.get(name).copied().ok_or_else(|| ScriptError::new(format!("no peer named {name:?}")))
+    }
+
+    pub fn entity(&self, name: &str) -> Result<EntityId, ScriptError> {
+        self.entities.get(name).copied().ok_or_else(|| ScriptError::new(format!("no entity named {name:?}")))
+    }
+
+    pub fn edge(&self, name: &str) -> Result<EdgeId, ScriptError> {
+        self.edges.get(name).copied().ok_or_else(|| ScriptError::new(format!("no edge named {name:?}")))
+    }
+}
+
+impl Script {
+    /// A script over the named peers, with no steps yet.
+    pub fn new(peers: &[&str]) -> Self {
+        Self { peers: peers.iter().map(|p| p.to_string()).collect(), steps: Vec::new() }
+    }
+
+    /// Append a step.
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, ScriptError> {
+        toml::from_str(source).map_err(|e| ScriptError::new(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> Result<String, ScriptError> {
+        toml::to_string(self).map_err(|e| ScriptError::new(e.to_string()))
+    }
+
+    /// Run every step in order, stopping at the first error or failed expectation.
+    pub fn run(&self) -> Result<ScriptRun, ScriptError> {
+        let mut run = ScriptRun {
+            network: TestNetwork::new(),
+            peers: BTreeMap::new(),
+            entities: BTreeMap::new(),
+            edges: BTreeMap::new(),
+        };
+        for name in &self.peers {
+            let index = run.network.add_peer().map_err(|e| ScriptError::new(e.to_string()))?;
+            if run.peers.insert(name.clone(), index).is_some() {
+                return Err(ScriptError::new(format!("peer {name:?} is declared twice")));
+            }
+        }
+        for (index, step) in self.steps.iter().enumerate() {
+            apply(&mut run, step).map_err(|e| ScriptError { step: Some(index + 1), message: e.to_string() })?;
+        }
+        Ok(run)
+    }
+}
+
+fn apply(run: &mut ScriptRun, step: &Step) -> Result<(), Error> {
+    match step {
+        Step::Create { peer, facet, name, fields } => {
+            let peer = run.peer(peer)?;
+            let fields = fields.iter().map(|(key, value)| (key.as_str(), value.into())).collect();
+            let entity_id = run.network.peer_mut(peer).create_record(facet, fields)?;
+            if run.entities.insert(name.clone(), entity_id).is_some() {
+                return Err(format!("entity name {name:?} is already taken").into());
+            }
+        }
+        Step::Set { peer, entity, field, value } => {
+            let (peer, entity_id) = (run.peer(peer)?, run.entity(entity)?);
+            run.network.peer_mut(peer).set_field(entity_id, field, value.into())?;
+        }
+        Step::Clear { peer, entity, field } => {
+            let (peer, entity_id) = (run.peer(peer)?, run.entity(entity)?);
+            run.network.peer_mut(peer).clear_field(entity_id, field)?;
+        }
+        Step::Delete { peer, entity } => {
+            let (peer, entity_id) = (run.peer(peer)?, run.entity(entity)?);
+            run.network.peer_mut(peer).delete_entity(entity_id)?;
+        }
+        Step::CreateEdge { peer, edge_type, source, target, name } => {
+            let (peer, source, target) = (run.peer(peer)?, run.entity(source)?, run.entity(target)?);
+            let edge_id = run.network.peer_mut(peer).create_edge(edge_type, source, target)?;
+            if run.edges.insert(name.clone(), edge_id).is_some() {
+                return Err(format!("edge name {name:?} is already taken").into());
+            }
+        }
+        Step::DeleteEdge { peer, edge } => {
+            let (peer, edge_id) = (run.peer(peer)?, run.edge(edge)?);
+            run.network.peer_mut(peer).delete_edge(edge_id)?;
+        }
+        Step::Undo { peer } | Step::Redo { peer } => {
+            let engine = &mut run.network.peer_mut(run.peer(peer)?).engine;
+            let result = if matches!(step, Step::Undo { .. }) { engine.undo()? } else { engine.redo()? };
+            if let UndoResult::Skipped { conflicts } = result {
+                return Err(format!("skipped: {} fields were since edited by others", conflicts.len()).into());
+            }
+        }
+        Step::Sync { from, to } => {
+            let (from, to) = (run.peer(from)?, run.peer(to)?);
+            run.network.sync_to(from, to)?;
+        }
+        Step::SyncAll => {
+            run.network.sync_all()?;
+        }
+        Step::SetLink { a, b, latency, jitter, duplicate, drop } => {
+            let profile = LinkProfile { latency: *latency, jitter: *jitter, duplicate: *duplicate, drop: *drop };
+            let (a, b) = (run.peer(a)?, run.peer(b)?);
+            run.network.set_link(a, b, profile);
+        }
+        Step::Partition { side } => {
+            let side = side.iter().map(|peer| run.peer(peer)).collect::<Result<Vec<_>, _>>()?;
+            run.network.partition(&side);
+        }
+        Step::Heal => run.network.heal(),
+        Step::Advance { ticks } => {
+            run.network.advance(*ticks)?;
+        }
+        Step::Flush => {
+            run.network.flush()?;
+        }
+        Step::ExpectField { peer, entity, field, value } => {
+            let (index, entity_id) = (run.peer(peer)?, run.entity(entity)?);
+            let actual = run.network.peer(index).engine.get_field(entity_id, field)?;
+            let expected: Option<FieldValue> = value.as_ref().map(Into::into);
+            if actual != expected {
+                return Err(format!("{peer} sees {entity}.{field} = {actual:?}, expected {expected:?}").into());
+            }
+        }
+        Step::ExpectDeleted { peer, entity, deleted } => {
+            let (index, entity_id) = (run.peer(peer)?, run.entity(entity)?);
+            let record = run.network.peer(index).engine.get_entity(entity_id)?;
+            let record = record.ok_or_else(|| format!("{peer} has never seen {entity}"))?;
+            if record.deleted != *deleted {
+                return Err(format!("{peer} sees {entity} deleted = {}, expected {deleted}", record.deleted).into());
+            }
+        }
+        Step::ExpectConflicts { peer, count } => {
+            let open = run.network.peer(run.peer(peer)?).engine.get_open_conflicts()?.len();
+            if open != *count {
+                return Err(format!("{peer} has {open} open conflicts, expected {count}").into());
+            }
+        }
+        Step::ExpectConverged => {
+            let entities: Vec<EntityId> = run.entities.values().copied().collect();
+            let edges: Vec<EdgeId> = run.edges.values().copied().collect();
+            let mut names = run.peers.iter();
+            let Some((first, &index)) = names.next() else { return Ok(()) };
+            let expected = state_of(&run.network.peer(index).engine, &entities, &edges)?;
+            for (name, &index) in names {
+                let actual = state_of(&run.network.peer(index).engine, &entities, &edges)?;
+                if actual != expected {
+                    return Err(format!("{name} diverged from {first}:\n  {first}: {expected:?}\n  {name}: {actual:?}").into());
+                }
+            }
+        }
+    }
+    Ok(())
+}
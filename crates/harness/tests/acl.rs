@@ -0,0 +1,143 @@
+use openprod_core::{field_value::FieldValue, hlc::Hlc, ids::*, operations::*};
+use openprod_engine::{EngineError, Role, SetAside};
+use openprod_harness::TestNetwork;
+
+/// A task created by `peer` with its current clock, signed directly so its own
+/// engine's permission check is bypassed (as a modified client could).
+fn forged_write(net: &TestNetwork, peer: usize) -> Result<(Bundle, Vec<Operation>), Box<dyn std::error::Error>> {
+    let engine = &net.peer(peer).engine;
+    let creator_vc = engine.get_vector_clock()?;
+    let latest = creator_vc.entries().values().max().map_or(0, |hlc| hlc.wall_ms());
+    let (bundle_id, hlc) = (BundleId::new(), Hlc::new(latest + 1, 0));
+    let payload = OperationPayload::CreateEntity { entity_id: EntityId::new(), initial_table: Some("Task".into()) };
+    let op = Operation::new_signed(engine.identity(), hlc, bundle_id, Default::default(), payload)?;
+    let bundle = Bundle::new_signed(bundle_id, engine.identity(), hlc, BundleType::UserEdit, std::slice::from_ref(&op), Some(creator_vc))?;
+    Ok((bundle, vec![op]))
+}
+
+fn is_permission_denied(err: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(err.downcast_ref::<EngineError>(), Some(EngineError::PermissionDenied(_)))
+}
+
+// ============================================================================
+// Roles
+// ============================================================================
+
+#[test]
+fn workspace_is_open_until_first_grant() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let a_id = net.peer(a).actor_id();
+    let b = net.add_peer()?;
+    let b_id = net.peer(b).actor_id();
+    assert!(!net.peer(a).engine.acl_enabled()?);
+
+    // Bootstrapping makes the granter an admin too
+    net.peer_mut(a).engine.grant_role(b_id, Role::Reader)?;
+    assert!(net.peer(a).engine.acl_enabled()?);
+    assert_eq!(net.peer(a).engine.role_of(a_id)?, Some(Role::Admin));
+    assert_eq!(net.peer(a).engine.role_of(b_id)?, Some(Role::Reader));
+    assert_eq!(net.peer(a).engine.acl_entries()?.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn readers_cannot_write_locally_or_via_sync() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let b_id = net.peer(b).actor_id();
+    net.peer_mut(a).engine.grant_role(b_id, Role::Reader)?;
+    net.sync_to(a, b)?;
+
+    // b's own engine refuses local edits
+    let err = net.peer_mut(b).create_record("Task", vec![]).unwrap_err();
+    assert!(is_permission_denied(err.as_ref()));
+
+    // A write b signs anyway is quarantined by its peers, not applied
+    let (bundle, operations) = forged_write(&net, b)?;
+    let preview = net.peer_mut(a).engine.preview_ingest(&bundle, &operations)?;
+    assert!(matches!(preview.set_aside, Some(SetAside::Denied(_))));
+    let report = net.peer_mut(a).engine.ingest_bundle(&bundle, &operations)?;
+    assert_eq!((report.bundles_applied, report.bundles_denied), (0, 1));
+    let quarantined = net.peer(a).engine.list_quarantined()?;
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].bundle_id, Some(bundle.bundle_id));
+    assert!(quarantined[0].reason.starts_with("permission denied"), "{}", quarantined[0].reason);
+
+    // Retrying can't change the verdict
+    let result = net.peer_mut(a).engine.retry_quarantined(quarantined[0].quarantine_id);
+    assert!(matches!(result, Err(EngineError::PermissionDenied(_))));
+    assert_eq!(net.peer(a).engine.list_quarantined()?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn writes_are_judged_by_the_acl_their_author_had_seen() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let c = net.add_peer()?;
+    let b_id = net.peer(b).actor_id();
+
+    // b writes while the workspace is still open to it...
+    let task = net.peer_mut(b).create_record("Task", vec![])?;
+    net.peer_mut(a).engine.grant_role(b_id, Role::Writer)?;
+    net.sync_to(a, b)?;
+    // ...and again as a writer, concurrently with a revoking it
+    net.peer_mut(b).set_field(task, "title", FieldValue::Text("b".into()))?;
+    net.peer_mut(a).engine.revoke_role(b_id)?;
+
+    // c learns of the revocation first, a only ever had it: both still accept
+    net.sync_to(a, c)?;
+    net.sync_to(b, c)?;
+    net.sync_to(b, a)?;
+    for peer in [a, c] {
+        assert_eq!(net.peer(peer).engine.get_field(task, "title")?, Some(FieldValue::Text("b".into())));
+        assert!(net.peer(peer).engine.list_quarantined()?.is_empty());
+    }
+    Ok(())
+}
+
+#[test]
+fn denied_pending_bundles_are_quarantined_too() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let c = net.add_peer()?;
+    let b_id = net.peer(b).actor_id();
+    net.peer_mut(a).engine.grant_role(b_id, Role::Reader)?;
+    net.sync_to(a, b)?;
+
+    // Waits at c for the ACL it depends on, then is denied on release
+    let (bundle, operations) = forged_write(&net, b)?;
+    let report = net.peer_mut(c).engine.ingest_bundle(&bundle, &operations)?;
+    assert_eq!(report.bundles_buffered, 1);
+    net.sync_to(a, c)?;
+    assert_eq!(net.peer(c).engine.pending_count()?, 0);
+    let quarantined = net.peer(c).engine.list_quarantined()?;
+    assert_eq!(quarantined.iter().map(|q| q.bundle_id).collect::<Vec<_>>(), vec![Some(bundle.bundle_id)]);
+    Ok(())
+}
+
+#[test]
+fn only_admins_change_the_acl() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let c = net.add_peer()?;
+    let c_id = net.peer(c).actor_id();
+    let b_id = net.peer(b).actor_id();
+
+    net.peer_mut(a).engine.grant_role(b_id, Role::Writer)?;
+    net.sync_to(a, b)?;
+    let result = net.peer_mut(b).engine.grant_role(c_id, Role::Admin);
+    assert!(matches!(result, Err(EngineError::PermissionDenied(_))));
+
+    net.peer_mut(a).engine.revoke_role(b_id)?;
+    assert_eq!(net.peer(a).engine.role_of(b_id)?, None);
+    net.sync_to(a, b)?;
+    let result = net.peer_mut(b).engine.create_entity(Some("Task"));
+    assert!(matches!(result, Err(EngineError::PermissionDenied(_))));
+    Ok(())
+}
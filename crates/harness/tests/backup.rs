@@ -0,0 +1,159 @@
+use openprod_core::{field_value::FieldValue, identity::ActorIdentity, sync_scope::SyncScope, vector_clock::VectorClock};
+use openprod_engine::{Engine, EngineBuilder, EngineError, UndoResult};
+use openprod_storage::{SqliteStorage, StorageError};
+
+// ============================================================================
+// Backup / Restore
+// ============================================================================
+
+#[test]
+fn backup_while_open_and_restore_into_another_engine() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let backup = dir.path().join("backup.db");
+
+    let mut source = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open_in_memory()?)?;
+    let (task, _) = source.create_entity_with_fields("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    let overlay_id = source.create_overlay("draft")?;
+    source.set_field(task, "title", FieldValue::Text("draft".into()))?;
+    source.backup_to(&backup)?;
+
+    // Writes after the backup are not in it
+    source.set_field(task, "status", FieldValue::Text("later".into()))?;
+
+    let mut restored = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open_in_memory()?)?;
+    restored.create_entity(Some("Scratch"))?;
+    restored.restore_from(&backup)?;
+
+    assert_eq!(restored.op_count()?, 2);
+    assert_eq!(restored.active_overlay(), Some(overlay_id));
+    assert_eq!(restored.get_field(task, "title")?, Some(FieldValue::Text("draft".into())));
+    assert_eq!(restored.get_field(task, "status")?, None);
+    assert!(matches!(restored.undo()?, UndoResult::Empty));
+    Ok(())
+}
+
+#[test]
+fn tampered_backup_is_rejected_and_leaves_engine_untouched() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let backup = dir.path().join("backup.db");
+
+    let mut source = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open_in_memory()?)?;
+    source.create_entity_with_fields("Task", vec![("title", FieldValue::Text("honest".into()))])?;
+    source.backup_to(&backup)?;
+
+    let conn = rusqlite::Connection::open(&backup)?;
+    conn.execute("UPDATE oplog SET signature = zeroblob(64) WHERE op_type = 'SetField'", [])?;
+    drop(conn);
+
+    let mut target = Engine::new(ActorIdentity::from_seed(2), SqliteStorage::open_in_memory()?)?;
+    let (mine, _) = target.create_entity(Some("Task"))?;
+    let result = target.restore_from(&backup);
+    assert!(matches!(result, Err(EngineError::InvalidBackup(_))));
+    assert_eq!(target.op_count()?, 1);
+    assert!(target.get_entity(mine)?.is_some());
+    Ok(())
+}
+
+// ============================================================================
+// Workspaces
+// ============================================================================
+
+#[test]
+fn workspaces_in_one_file_are_isolated() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("projects.db");
+    let path = path.to_str().unwrap();
+
+    let mut alpha = EngineBuilder::new().open_workspace(ActorIdentity::from_seed(1), path, "alpha")?;
+    let mut beta = alpha.open_workspace("beta")?;
+    let (task, _) = alpha.create_entity_with_fields("Task", vec![("title", FieldValue::Text("alpha".into()))])?;
+    let (cue, _) = beta.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    beta.set_field(cue, "label", FieldValue::Text("Q2".into()))?;
+
+    assert_eq!((alpha.op_count()?, beta.op_count()?), (2, 3));
+    assert_eq!(alpha.get_entities_by_facet("Task")?, vec![task]);
+    assert!(alpha.get_entity(cue)?.is_none());
+    assert!(beta.get_entity(task)?.is_none());
+    assert!(beta.get_entities_by_facet("Task")?.is_empty());
+    assert_eq!(alpha.workspaces()?, vec!["alpha".to_string(), "beta".to_string()]);
+
+    // The default workspace of the same file is empty
+    let default = EngineBuilder::new().open(ActorIdentity::from_seed(1), path)?;
+    assert_eq!(default.op_count()?, 0);
+    assert_eq!(default.get_vector_clock()?, VectorClock::new());
+
+    drop(alpha);
+    let alpha = EngineBuilder::new().open_workspace(ActorIdentity::from_seed(1), path, "alpha")?;
+    assert_eq!(alpha.workspace(), "alpha");
+    assert_eq!(alpha.get_field(task, "title")?, Some(FieldValue::Text("alpha".into())));
+    assert_eq!(alpha.op_count()?, 2);
+    Ok(())
+}
+
+#[test]
+fn bundle_from_another_workspace_is_refused() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("projects.db");
+
+    let mut alpha = EngineBuilder::new().open_workspace(ActorIdentity::from_seed(1), path.to_str().unwrap(), "alpha")?;
+    alpha.create_entity(Some("Task"))?;
+    let mut beta = alpha.open_workspace("beta")?;
+    let (bundle, operations) = alpha.bundles_since(&VectorClock::new(), &SyncScope::All, None)?.remove(0);
+
+    let result = beta.ingest_bundle(&bundle, &operations);
+    assert!(matches!(result, Err(EngineError::Storage(StorageError::ConstraintViolation(_)))), "{result:?}");
+    assert_eq!(beta.op_count()?, 0);
+    assert_eq!(alpha.op_count()?, 1);
+    Ok(())
+}
+
+#[test]
+fn backup_from_before_workspaces_restores_into_the_default_workspace() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let backup = dir.path().join("backup.db");
+
+    let mut source = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open_in_memory()?)?;
+    let (task, _) = source.create_entity_with_fields("Task", vec![("title", FieldValue::Text("v22".into()))])?;
+    source.backup_to(&backup)?;
+
+    // Roll the backup back to schema 22, before tables carried a workspace
+    let conn = rusqlite::Connection::open(&backup)?;
+    let indexes: Vec<String> = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'index' AND sql LIKE '%workspace_id%'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    for index in indexes {
+        conn.execute_batch(&format!("DROP INDEX {index}"))?;
+    }
+    let tables: Vec<String> = conn
+        .prepare(
+            "SELECT m.name FROM sqlite_master m JOIN pragma_table_info(m.name) c
+             WHERE m.type = 'table' AND c.name = 'workspace_id' AND c.pk = 0 AND m.name <> 'quarantined_bundles'",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    for table in tables {
+        conn.execute_batch(&format!("ALTER TABLE {table} DROP COLUMN workspace_id"))?;
+    }
+    conn.execute_batch(
+        "DROP TABLE materialization_state;
+         CREATE TABLE materialization_state (id INTEGER PRIMARY KEY, watermark BLOB NOT NULL);
+         ALTER TABLE oplog DROP COLUMN edge_id;",
+    )?;
+    conn.execute("DELETE FROM schema_version WHERE version >= 23", [])?;
+    drop(conn);
+
+    let mut restored = Engine::new(ActorIdentity::from_seed(2), SqliteStorage::open_in_memory()?)?;
+    restored.restore_from(&backup)?;
+    assert_eq!(restored.get_field(task, "title")?, Some(FieldValue::Text("v22".into())));
+    assert_eq!(restored.workspaces()?, vec![openprod_storage::schema::DEFAULT_WORKSPACE.to_string()]);
+    Ok(())
+}
+
+#[test]
+fn in_memory_engine_has_no_other_workspaces() -> Result<(), Box<dyn std::error::Error>> {
+    let engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open_in_memory()?)?;
+    assert_eq!(engine.workspace(), openprod_storage::schema::DEFAULT_WORKSPACE);
+    assert!(engine.open_workspace("other").is_err());
+    Ok(())
+}
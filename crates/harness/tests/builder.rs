@@ -0,0 +1,234 @@
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::{Hlc, ManualClock},
+    identity::ActorIdentity,
+    ids::{BundleId, EntityId, OpId},
+    operations::*,
+    sealed::WorkspaceKey,
+};
+use openprod_engine::{Engine, EngineBuilder, EngineError, ModulePolicy, ModuleRegistry, TrustPolicy, UndoResult};
+use openprod_harness::TestPeer;
+use openprod_storage::{SqliteStorage, Storage};
+
+fn latest_bundle(from: &TestPeer) -> Result<(Bundle, Vec<Operation>), Box<dyn std::error::Error>> {
+    let ops = from.engine.get_ops_canonical()?;
+    let bundle_id = ops.last().unwrap().bundle_id;
+    let bundle = from.engine.storage().get_bundle(bundle_id)?.unwrap();
+    Ok((bundle, from.engine.get_ops_by_bundle(bundle_id)?))
+}
+
+// ============================================================================
+// Defaults
+// ============================================================================
+
+#[test]
+fn default_builder_matches_engine_new() -> Result<(), Box<dyn std::error::Error>> {
+    let built = EngineBuilder::new().open_in_memory(ActorIdentity::generate())?;
+    let new = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+    assert_eq!(built.trust_policy(), new.trust_policy());
+    assert_eq!(built.group_user_devices(), new.group_user_devices());
+    assert_eq!(built.module_registry().policy(), new.module_registry().policy());
+    Ok(())
+}
+
+#[test]
+fn builder_applies_policies_and_workspace_key() -> Result<(), Box<dyn std::error::Error>> {
+    let mut modules = ModuleRegistry::new(ModulePolicy::Hold);
+    modules.declare("schedule", "1");
+    let mut engine = EngineBuilder::new()
+        .trust_policy(TrustPolicy::RejectUnknown)
+        .group_user_devices(false)
+        .modules(modules)
+        .workspace_key(WorkspaceKey::generate())
+        .open_in_memory(ActorIdentity::generate())?;
+
+    assert_eq!(engine.trust_policy(), TrustPolicy::RejectUnknown);
+    assert!(!engine.group_user_devices());
+    assert_eq!(engine.module_registry().version("schedule"), Some("1"));
+    let (_, bundle_id) = engine.create_entity(Some("Task"))?;
+    assert!(engine.seal_bundle(bundle_id).is_ok());
+    Ok(())
+}
+
+// ============================================================================
+// Undo Depth
+// ============================================================================
+
+#[test]
+fn undo_depth_bounds_the_undo_stack() -> Result<(), Box<dyn std::error::Error>> {
+    let mut engine = EngineBuilder::new().undo_depth(2).open_in_memory(ActorIdentity::generate())?;
+    let (entity_id, _) = engine.create_entity(Some("Task"))?;
+    for n in 0..3 {
+        engine.set_field(entity_id, "n", FieldValue::Integer(n))?;
+    }
+
+    assert!(matches!(engine.undo()?, UndoResult::Applied(_)));
+    assert!(matches!(engine.undo()?, UndoResult::Applied(_)));
+    assert!(matches!(engine.undo()?, UndoResult::Empty));
+    assert_eq!(engine.get_field(entity_id, "n")?, Some(FieldValue::Integer(0)));
+    Ok(())
+}
+
+// ============================================================================
+// Ingest Options
+// ============================================================================
+
+#[test]
+fn verify_signatures_rejects_tampered_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("honest".into()))])?;
+    let (bundle, mut ops) = latest_bundle(&a)?;
+    for op in &mut ops {
+        if let OperationPayload::SetField { value, .. } = &mut op.payload {
+            *value = FieldValue::Text("forged".into());
+        }
+    }
+
+    let mut strict = EngineBuilder::new().verify_signatures(true).open_in_memory(ActorIdentity::generate())?;
+    assert!(matches!(strict.ingest_bundle(&bundle, &ops), Err(EngineError::InvalidBundle(_))));
+    assert!(strict.get_entity(entity_id)?.is_none());
+
+    let (bundle, ops) = latest_bundle(&a)?;
+    assert_eq!(strict.ingest_bundle(&bundle, &ops)?.bundles_applied, 1);
+    Ok(())
+}
+
+#[test]
+fn verify_on_append_checks_every_write() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("whole".into()))])?;
+    let (bundle, mut ops) = latest_bundle(&a)?;
+    let dropped = ops.pop().unwrap();
+
+    let mut checked = EngineBuilder::new().verify_on_append(true).open_in_memory(ActorIdentity::generate())?;
+    let Err(EngineError::InvalidBundle(reason)) = checked.ingest_bundle(&bundle, &ops) else {
+        panic!("truncated bundle was stored");
+    };
+    assert!(reason.contains("ops, found"), "{reason}");
+    assert!(checked.get_entity(entity_id)?.is_none());
+
+    // A forged op with the right count fails on its signature
+    let mut forged = dropped.clone();
+    forged.hlc = Hlc::new(forged.hlc.wall_ms() + 1, 0);
+    ops.push(forged);
+    let Err(EngineError::InvalidBundle(reason)) = checked.ingest_bundle(&bundle, &ops) else {
+        panic!("forged bundle was stored");
+    };
+    assert!(reason.contains(&format!("operation {}: bad signature", dropped.op_id)), "{reason}");
+    assert!(bundle.verify(&ops).is_err());
+
+    // Intact bundles and local writes go through
+    ops.pop();
+    ops.push(dropped);
+    bundle.verify(&ops)?;
+    assert_eq!(checked.ingest_bundle(&bundle, &ops)?.bundles_applied, 1);
+    checked.set_field(entity_id, "title", FieldValue::Text("local".into()))?;
+    assert_eq!(checked.get_field(entity_id, "title")?, Some(FieldValue::Text("local".into())));
+    Ok(())
+}
+
+#[test]
+fn drift_scan_on_ingest_can_be_turned_off() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("base".into()))])?;
+    let (create, create_ops) = latest_bundle(&a)?;
+    a.set_field(entity_id, "title", FieldValue::Text("canonical".into()))?;
+    let (edit, edit_ops) = latest_bundle(&a)?;
+
+    let mut relay = EngineBuilder::new().scan_drift_on_ingest(false).open_in_memory(ActorIdentity::generate())?;
+    relay.ingest_bundle(&create, &create_ops)?;
+    let overlay_id = relay.create_overlay("draft")?;
+    relay.set_field(entity_id, "title", FieldValue::Text("draft".into()))?;
+
+    assert_eq!(relay.ingest_bundle(&edit, &edit_ops)?.drift_flagged, 0);
+    assert!(!relay.has_unresolved_drift(overlay_id)?);
+    Ok(())
+}
+
+// ============================================================================
+// Derived Op Ids
+// ============================================================================
+
+#[test]
+fn derived_op_ids_can_be_recomputed_from_the_op() -> Result<(), Box<dyn std::error::Error>> {
+    let mut engine = EngineBuilder::new().derive_op_ids(true).open_in_memory(ActorIdentity::generate())?;
+    let (_, bundle_id) = engine.create_entity_with_fields(
+        "Task",
+        vec![("title", FieldValue::Text("a".into())), ("notes", FieldValue::Text("b".into()))],
+    )?;
+    let ops = engine.get_ops_by_bundle(bundle_id)?;
+    assert_eq!(ops.len(), 3);
+    for (position, op) in (0u32..).zip(&ops) {
+        assert_eq!(op.op_id, OpId::derive(&engine.actor_id(), &bundle_id, position, &op.payload.to_msgpack()?));
+        assert_eq!(op.op_id.as_uuid().get_version_num(), 8);
+        op.verify_signature()?;
+    }
+
+    // Random ids by default
+    let mut engine = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+    let (_, bundle_id) = engine.create_entity(Some("Task"))?;
+    assert_eq!(engine.get_ops_by_bundle(bundle_id)?[0].op_id.as_uuid().get_version_num(), 7);
+    Ok(())
+}
+
+#[test]
+fn derived_ops_are_reproducible() -> Result<(), Box<dyn std::error::Error>> {
+    let bundle_id = BundleId::from_bytes([1; 16]);
+    let payload = OperationPayload::CreateEntity { entity_id: EntityId::from_bytes([2; 16]), initial_table: None };
+    let op = |position| {
+        Operation::new_signed_derived(
+            &ActorIdentity::from_seed(7),
+            Hlc::new(1_700_000_000_000, 0),
+            bundle_id,
+            position,
+            Default::default(),
+            payload.clone(),
+        )
+    };
+    assert_eq!(op(0)?, op(0)?);
+    assert_ne!(op(0)?.op_id, op(1)?.op_id);
+    Ok(())
+}
+
+// ============================================================================
+// Clock Source
+// ============================================================================
+
+#[test]
+fn manual_clock_source_drives_bundle_timestamps() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = ManualClock::new(1_700_000_000_000);
+    let mut engine = EngineBuilder::new().clock_source(clock.clone()).open_in_memory(ActorIdentity::generate())?;
+
+    let (entity_id, first) = engine.create_entity(Some("Task"))?;
+    let second = engine.set_field(entity_id, "title", FieldValue::Text("a".into()))?;
+    clock.advance(60_000);
+    let third = engine.set_field(entity_id, "title", FieldValue::Text("b".into()))?;
+
+    let hlc = |bundle_id| -> Result<Hlc, Box<dyn std::error::Error>> {
+        Ok(engine.storage().get_bundle(bundle_id)?.unwrap().hlc)
+    };
+    assert_eq!(hlc(first)?, Hlc::new(1_700_000_000_000, 0));
+    assert_eq!(hlc(second)?, Hlc::new(1_700_000_000_000, 1));
+    assert_eq!(hlc(third)?, Hlc::new(1_700_000_060_000, 0));
+    Ok(())
+}
+
+// ============================================================================
+// SQLite Pragmas
+// ============================================================================
+
+#[test]
+fn sqlite_pragmas_are_applied_on_open() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("show.db");
+    let engine = EngineBuilder::new()
+        .sqlite_pragma("synchronous", "FULL")
+        .sqlite_pragma("cache_size", "-1000")
+        .open(ActorIdentity::generate(), path.to_str().unwrap())?;
+
+    // synchronous = FULL reads back as 2
+    assert_eq!(engine.storage().pragma("synchronous")?, "2");
+    assert_eq!(engine.storage().pragma("cache_size")?, "-1000");
+    assert_eq!(engine.storage().pragma("journal_mode")?, "wal");
+    Ok(())
+}
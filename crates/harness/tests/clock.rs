@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::{Hlc, ManualClock},
+    identity::ActorIdentity,
+    ids::BundleId,
+    operations::*,
+    CoreError,
+};
+use openprod_engine::{Engine, EngineBuilder, EngineError};
+use openprod_storage::Storage;
+
+const T0: u64 = 1_700_000_000_000;
+const HOUR_MS: u64 = 3_600_000;
+
+fn engine_at(clock: &ManualClock) -> Result<Engine, EngineError> {
+    EngineBuilder::new().clock_source(clock.clone()).open_in_memory(ActorIdentity::generate())
+}
+
+fn bundle(engine: &Engine, bundle_id: BundleId) -> Result<(Bundle, Vec<Operation>), Box<dyn std::error::Error>> {
+    let bundle = engine.storage().get_bundle(bundle_id)?.unwrap();
+    Ok((bundle, engine.get_ops_by_bundle(bundle_id)?))
+}
+
+fn hlc_of(engine: &Engine, bundle_id: BundleId) -> Result<Hlc, Box<dyn std::error::Error>> {
+    Ok(engine.storage().get_bundle(bundle_id)?.unwrap().hlc)
+}
+
+// ============================================================================
+// Skew Detection
+// ============================================================================
+
+#[test]
+fn bundles_within_tolerance_advance_the_local_clock() -> Result<(), Box<dyn std::error::Error>> {
+    let mut ahead = engine_at(&ManualClock::new(T0 + 60_000))?;
+    let mut local = engine_at(&ManualClock::new(T0))?;
+
+    let (entity_id, created) = ahead.create_entity(Some("Task"))?;
+    let (remote, ops) = bundle(&ahead, created)?;
+    let report = local.ingest_bundle(&remote, &ops)?;
+    assert!(report.clock_skew.is_empty());
+
+    // The local edit orders after the remote one despite the slower wall clock
+    let edit = local.set_field(entity_id, "title", FieldValue::Text("local".into()))?;
+    assert!(hlc_of(&local, edit)? > remote.hlc);
+    Ok(())
+}
+
+#[test]
+fn skewed_bundles_are_reported_and_do_not_drag_the_clock() -> Result<(), Box<dyn std::error::Error>> {
+    let mut ahead = engine_at(&ManualClock::new(T0 + 2 * HOUR_MS))?;
+    let mut local = engine_at(&ManualClock::new(T0))?;
+
+    let (entity_id, created) = ahead.create_entity(Some("Task"))?;
+    let (remote, ops) = bundle(&ahead, created)?;
+    let report = local.ingest_bundle(&remote, &ops)?;
+    assert_eq!(report.bundles_applied, 1);
+    assert_eq!(report.clock_skew.len(), 1);
+    let skew = &report.clock_skew[0];
+    assert_eq!((skew.bundle_id, skew.actor_id), (created, ahead.actor_id()));
+    assert_eq!(skew.ahead, Duration::from_millis(2 * HOUR_MS));
+
+    // Still ingested, but local timestamps stay on local time
+    assert!(local.get_entity(entity_id)?.is_some());
+    let edit = local.set_field(entity_id, "title", FieldValue::Text("local".into()))?;
+    assert_eq!(hlc_of(&local, edit)?.wall_ms(), T0);
+
+    // Re-ingesting is a no-op and isn't reported again
+    assert!(local.ingest_bundle(&remote, &ops)?.clock_skew.is_empty());
+    Ok(())
+}
+
+#[test]
+fn skew_tolerance_is_configurable() -> Result<(), Box<dyn std::error::Error>> {
+    let mut ahead = engine_at(&ManualClock::new(T0 + 2 * HOUR_MS))?;
+    let mut local = EngineBuilder::new()
+        .clock_source(ManualClock::new(T0))
+        .clock_skew_tolerance(Duration::from_millis(3 * HOUR_MS))
+        .open_in_memory(ActorIdentity::generate())?;
+    assert_eq!(local.clock_skew_tolerance(), Duration::from_millis(3 * HOUR_MS));
+
+    let (entity_id, created) = ahead.create_entity(Some("Task"))?;
+    let (remote, ops) = bundle(&ahead, created)?;
+    assert!(local.ingest_bundle(&remote, &ops)?.clock_skew.is_empty());
+    let edit = local.set_field(entity_id, "title", FieldValue::Text("local".into()))?;
+    assert!(hlc_of(&local, edit)? > remote.hlc);
+    Ok(())
+}
+
+// ============================================================================
+// Clamping
+// ============================================================================
+
+#[test]
+fn clamped_clock_refuses_writes_after_stepping_back() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = ManualClock::new(T0);
+    let mut engine = EngineBuilder::new()
+        .clock_source(clock.clone())
+        .clock_skew_tolerance(Duration::from_secs(1))
+        .clamp_clock_ahead(true)
+        .open_in_memory(ActorIdentity::generate())?;
+    let (entity_id, _) = engine.create_entity(Some("Task"))?;
+
+    clock.set(T0 - HOUR_MS);
+    let ops_before = engine.op_count()?;
+    let err = engine.set_field(entity_id, "title", FieldValue::Text("late".into())).unwrap_err();
+    assert!(matches!(err, EngineError::Core(CoreError::HlcClockAhead { ahead_ms: HOUR_MS, .. })), "{err}");
+    assert_eq!(engine.op_count()?, ops_before);
+
+    // Unclamped, writes carry on from the last timestamp
+    engine.set_clamp_clock_ahead(false);
+    let edit = engine.set_field(entity_id, "title", FieldValue::Text("late".into()))?;
+    assert_eq!(hlc_of(&engine, edit)?, Hlc::new(T0, 1));
+    Ok(())
+}
+
+// ============================================================================
+// Restart After Rollback
+// ============================================================================
+
+#[test]
+fn restart_after_clock_rollback_keeps_timestamps_monotonic() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("show.db");
+    let identity = ActorIdentity::generate();
+    let clock = ManualClock::new(T0);
+    let open = |identity: &ActorIdentity| {
+        EngineBuilder::new()
+            .clock_source(clock.clone())
+            .clock_skew_tolerance(Duration::from_secs(1))
+            .open(ActorIdentity::from_secret_bytes(&identity.secret_bytes()), path.to_str().unwrap())
+    };
+
+    let mut engine = open(&identity)?;
+    let (entity_id, _) = engine.create_entity(Some("Task"))?;
+    let edit = engine.set_field(entity_id, "title", FieldValue::Text("before".into()))?;
+    let before = hlc_of(&engine, edit)?;
+    assert_eq!(engine.storage().get_last_hlc()?, Some(before));
+    assert_eq!(engine.clock_regression(), None);
+    drop(engine);
+
+    // Reopened after the system clock was set back an hour
+    clock.set(T0 - HOUR_MS);
+    let mut engine = open(&identity)?;
+    assert_eq!(engine.clock_regression(), Some(Duration::from_millis(HOUR_MS)));
+    let edit = engine.set_field(entity_id, "title", FieldValue::Text("after".into()))?;
+    assert_eq!(hlc_of(&engine, edit)?, Hlc::new(before.wall_ms(), before.counter() + 1));
+    assert_eq!(engine.get_field(entity_id, "title")?, Some(FieldValue::Text("after".into())));
+    drop(engine);
+
+    // Clamped, the regression fails the open instead
+    let Err(EngineError::Core(CoreError::ClockRegression { behind_ms, max_ms })) = EngineBuilder::new()
+        .clock_source(clock.clone())
+        .clock_skew_tolerance(Duration::from_secs(1))
+        .clamp_clock_ahead(true)
+        .open(ActorIdentity::from_secret_bytes(&identity.secret_bytes()), path.to_str().unwrap())
+    else {
+        panic!("expected ClockRegression");
+    };
+    assert_eq!((behind_ms, max_ms), (HOUR_MS, 1_000));
+    Ok(())
+}
+
+#[test]
+fn restart_resumes_above_merged_remote_timestamps() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("show.db");
+    let identity = ActorIdentity::generate();
+    let clock = ManualClock::new(T0);
+    let open = || {
+        EngineBuilder::new()
+            .clock_source(clock.clone())
+            .open(ActorIdentity::from_secret_bytes(&identity.secret_bytes()), path.to_str().unwrap())
+    };
+
+    // A peer a minute ahead, within tolerance
+    let mut ahead = engine_at(&ManualClock::new(T0 + 60_000))?;
+    let (entity_id, created) = ahead.create_entity(Some("Task"))?;
+    let remote_edit = ahead.set_field(entity_id, "title", FieldValue::Text("remote".into()))?;
+    let mut engine = open()?;
+    for bundle_id in [created, remote_edit] {
+        let (remote, ops) = bundle(&ahead, bundle_id)?;
+        engine.ingest_bundle(&remote, &ops)?;
+    }
+    drop(engine);
+
+    // Restarted before physical time caught up: the edit still follows the remote one
+    let mut engine = open()?;
+    let edit = engine.set_field(entity_id, "title", FieldValue::Text("local".into()))?;
+    assert!(hlc_of(&engine, edit)? > hlc_of(&ahead, remote_edit)?);
+    assert_eq!(engine.get_field(entity_id, "title")?, Some(FieldValue::Text("local".into())));
+    Ok(())
+}
+
+#[test]
+fn max_clock_counter_bounds_writes_within_one_millisecond() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = ManualClock::new(T0);
+    let mut engine = EngineBuilder::new()
+        .clock_source(clock.clone())
+        .max_clock_counter(1)
+        .open_in_memory(ActorIdentity::generate())?;
+    assert_eq!(engine.max_clock_counter(), 1);
+    let (entity_id, _) = engine.create_entity(Some("Task"))?;
+    engine.set_field(entity_id, "title", FieldValue::Text("one".into()))?;
+    let err = engine.set_field(entity_id, "title", FieldValue::Text("two".into())).unwrap_err();
+    assert!(matches!(err, EngineError::Core(CoreError::HlcCounterOverflow)), "{err}");
+
+    clock.advance(1);
+    let edit = engine.set_field(entity_id, "title", FieldValue::Text("two".into()))?;
+    assert_eq!(hlc_of(&engine, edit)?, Hlc::new(T0 + 1, 0));
+    Ok(())
+}
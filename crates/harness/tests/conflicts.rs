@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::Hlc,
+    identity::ActorIdentity,
+    ids::*,
+    operations::{BundleType, OperationPayload},
+};
+use openprod_engine::{ConflictEvent, Engine, EngineError};
+use openprod_harness::{TestNetwork, TestPeer};
+use openprod_storage::{
+    ConflictRecord, ConflictStatus, ConflictTriage, ConflictValue, EngineStorage, MemoryStorage, OverlayStore,
+};
+
+/// Two peers that edited `title` on the same task concurrently, with `a`'s
+/// edit synced to `b`. Returns the task and the conflict `b` detected.
+fn concurrent_titles(net: &mut TestNetwork) -> Result<(EntityId, ConflictRecord), Box<dyn std::error::Error>> {
+    let (a, b) = (0, 1);
+    let task = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("draft".into()))])?;
+    net.sync_to(a, b)?;
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("from a".into()))?;
+    net.peer_mut(b).set_field(task, "title", FieldValue::Text("from b".into()))?;
+    let mut conflicts = net.sync_to(a, b)?;
+    assert_eq!(conflicts.len(), 1);
+    Ok((task, conflicts.remove(0)))
+}
+
+// ============================================================================
+// Inbox
+// ============================================================================
+
+#[test]
+fn new_conflicts_notify_and_count_until_seen() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let (a, b) = (net.add_peer()?, net.add_peer()?);
+    let events = net.peer_mut(b).engine.watch_conflicts();
+
+    let (task, conflict) = concurrent_titles(&mut net)?;
+    let Ok(ConflictEvent::Opened(opened)) = events.try_recv() else { panic!("expected an Opened event") };
+    assert_eq!(opened.conflict_id, conflict.conflict_id);
+    assert!(events.try_recv().is_err());
+
+    let engine = &mut net.peer_mut(b).engine;
+    assert_eq!(engine.unseen_conflict_count()?, 1);
+    assert_eq!(engine.conflict_triage(conflict.conflict_id)?, ConflictTriage::default());
+    engine.mark_conflict_seen(conflict.conflict_id, true)?;
+    assert_eq!(engine.unseen_conflict_count()?, 0);
+    assert!(engine.conflict_triage(conflict.conflict_id)?.seen_at.is_some());
+    engine.mark_conflict_seen(conflict.conflict_id, false)?;
+    assert_eq!(engine.unseen_conflict_count()?, 1);
+    engine.mark_conflict_seen(conflict.conflict_id, true)?;
+
+    // A late edit that missed the resolution reopens it as unseen
+    engine.resolve_conflict(conflict.conflict_id, Some(FieldValue::Text("agreed".into())))?;
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("late".into()))?;
+    net.sync_to(a, b)?;
+    let Ok(ConflictEvent::Reopened(reopened)) = events.try_recv() else { panic!("expected a Reopened event") };
+    assert_eq!(reopened.conflict_id, conflict.conflict_id);
+    let engine = &net.peer(b).engine;
+    assert_eq!(engine.unseen_conflict_count()?, 1);
+    assert_eq!(engine.conflict_triage(conflict.conflict_id)?, ConflictTriage::default());
+
+    // Triage is local: the other peer's conflicts are untouched
+    assert_eq!(net.peer(a).engine.unseen_conflict_count()?, 0);
+    Ok(())
+}
+
+#[test]
+fn snoozed_conflicts_leave_the_count_until_they_wake() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    net.add_peer()?;
+    let b = net.add_peer()?;
+    let (_, conflict) = concurrent_titles(&mut net)?;
+    let engine = &mut net.peer_mut(b).engine;
+
+    engine.snooze_conflict(conflict.conflict_id, Duration::from_secs(3_600))?;
+    assert_eq!(engine.unseen_conflict_count()?, 0);
+    engine.snooze_conflict(conflict.conflict_id, Duration::ZERO)?;
+    assert_eq!(engine.unseen_conflict_count()?, 1);
+
+    // Resolved conflicts never count
+    engine.resolve_conflict(conflict.conflict_id, None)?;
+    assert_eq!(engine.unseen_conflict_count()?, 0);
+
+    let err = engine.mark_conflict_seen(ConflictId::new(), true).unwrap_err();
+    assert!(matches!(err, EngineError::ConflictNotFound(_)));
+    Ok(())
+}
+
+/// Store an open conflict on a fresh task directly, as detection would.
+fn seed_conflict<S: EngineStorage>(engine: &mut Engine<S>) -> Result<ConflictId, Box<dyn std::error::Error>> {
+    let (entity_id, bundle_id) = engine.create_entity_with_fields("Task", vec![])?;
+    let tip = |byte: u8| ConflictValue {
+        value: None,
+        actor_id: ActorId::from_bytes([byte; 32]),
+        hlc: Hlc::new(1_000 + u64::from(byte), 0),
+        op_id: OpId::new(),
+    };
+    let conflict_id = ConflictId::new();
+    engine.storage_mut().insert_conflict(&ConflictRecord {
+        conflict_id,
+        entity_id,
+        field_key: "title".into(),
+        status: ConflictStatus::Open,
+        values: vec![tip(1), tip(2)],
+        detected_at: Hlc::new(2_000, 0),
+        detected_in_bundle: bundle_id,
+        resolved_at: None,
+        resolved_by: None,
+        resolved_op_id: None,
+        resolved_value: None,
+        reopened_at: None,
+        reopened_by_op: None,
+    })?;
+    Ok(conflict_id)
+}
+
+#[test]
+fn memory_storage_triage_matches_sqlite() -> Result<(), Box<dyn std::error::Error>> {
+    fn exercise<S: EngineStorage>(mut engine: Engine<S>) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
+        let first = seed_conflict(&mut engine)?;
+        let second = seed_conflict(&mut engine)?;
+        let mut counts = vec![engine.storage().count_unseen_conflicts(5_000)?];
+        engine.storage_mut().set_conflict_triage(first, ConflictTriage { seen_at: Some(4_000), snoozed_until: None })?;
+        engine.storage_mut().set_conflict_triage(second, ConflictTriage { seen_at: None, snoozed_until: Some(6_000) })?;
+        counts.push(engine.storage().count_unseen_conflicts(5_000)?);
+        counts.push(engine.storage().count_unseen_conflicts(6_000)?);
+
+        // A new branch clears the triage
+        let tip = ConflictValue { value: None, actor_id: ActorId::from_bytes([3; 32]), hlc: Hlc::new(3_000, 0), op_id: OpId::new() };
+        engine.storage_mut().add_conflict_value(first, &tip)?;
+        assert_eq!(engine.storage().get_conflict_triage(first)?, Some(ConflictTriage::default()));
+        counts.push(engine.storage().count_unseen_conflicts(5_000)?);
+
+        assert_eq!(engine.storage().get_conflict_triage(ConflictId::new())?, None);
+        assert!(engine.storage_mut().set_conflict_triage(ConflictId::new(), ConflictTriage::default()).is_err());
+        Ok(counts)
+    }
+
+    let memory = exercise(Engine::new(ActorIdentity::generate(), MemoryStorage::new())?)?;
+    assert_eq!(memory, vec![2, 0, 1, 1]);
+    assert_eq!(exercise(TestPeer::new()?.engine)?, memory);
+    Ok(())
+}
+
+// ============================================================================
+// Branch overlays
+// ============================================================================
+
+#[test]
+fn overlay_from_conflict_previews_a_branch_with_its_bundle() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let (a, b) = (net.add_peer()?, net.add_peer()?);
+    let task = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("draft".into()))])?;
+    net.sync_to(a, b)?;
+    net.peer_mut(a).execute_bundle(
+        BundleType::UserEdit,
+        vec![
+            OperationPayload::SetField { entity_id: task, field_key: "title".into(), value: FieldValue::Text("from a".into()) },
+            OperationPayload::SetField { entity_id: task, field_key: "estimate".into(), value: FieldValue::Integer(3) },
+        ],
+    )?;
+    net.peer_mut(b).set_field(task, "title", FieldValue::Text("from b".into()))?;
+    let conflict = net.sync_to(a, b)?.remove(0);
+    let tip_of = |actor: ActorId| conflict.values.iter().find(|tip| tip.actor_id == actor).map(|tip| tip.op_id).unwrap();
+    let (tip_a, tip_b) = (tip_of(net.peer(a).actor_id()), tip_of(net.peer(b).actor_id()));
+
+    let engine = &mut net.peer_mut(b).engine;
+    let canonical = engine.get_field(task, "title")?;
+    let overlay = engine.overlay_from_conflict(conflict.conflict_id, tip_a)?;
+    assert_eq!(engine.active_overlay(), Some(overlay));
+    assert_eq!(engine.storage().count_overlay_ops(overlay)?, 2);
+    assert_eq!(engine.get_field(task, "title")?, Some(FieldValue::Text("from a".into())));
+    assert_eq!(engine.get_field(task, "estimate")?, Some(FieldValue::Integer(3)));
+
+    // Another branch replaces the preview; discarding leaves the conflict open
+    let other = engine.overlay_from_conflict(conflict.conflict_id, tip_b)?;
+    assert_eq!(engine.storage().count_overlay_ops(other)?, 1);
+    assert_eq!(engine.get_field(task, "title")?, Some(FieldValue::Text("from b".into())));
+    engine.discard_overlay(other)?;
+    engine.discard_overlay(overlay)?;
+    assert_eq!(engine.get_field(task, "title")?, canonical);
+    assert_eq!(engine.unseen_conflict_count()?, 1);
+
+    let err = engine.overlay_from_conflict(conflict.conflict_id, OpId::new()).unwrap_err();
+    assert!(matches!(err, EngineError::NotConflictTip(_)));
+    engine.resolve_conflict(conflict.conflict_id, None)?;
+    let err = engine.overlay_from_conflict(conflict.conflict_id, tip_a).unwrap_err();
+    assert!(matches!(err, EngineError::ConflictAlreadyResolved(_)));
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use openprod_harness::convergence::{check_converged, run, scenario_strategy, Scenario, Step};
+use proptest::prelude::*;
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn random_scenarios_converge(scenario in scenario_strategy(4, 40)) {
+        let outcome = run(&scenario).map_err(|e| TestCaseError::fail(e.to_string()))?;
+        check_converged(&outcome).map_err(TestCaseError::fail)?;
+    }
+}
+
+#[test]
+fn concurrent_delete_and_edit_across_partition_converge() -> Result<(), Box<dyn std::error::Error>> {
+    let scenario = Scenario {
+        peers: 3,
+        steps: vec![
+            Step::Create { peer: 0 },
+            Step::Create { peer: 1 },
+            Step::Sync { from: 0, to: 1 },
+            Step::Sync { from: 1, to: 2 },
+            Step::Partition { side: 0b001 },
+            Step::Delete { peer: 0, entity: 0 },
+            Step::Link { peer: 1, source: 0, target: 1 },
+            Step::SetField { peer: 2, entity: 0, field: 0, value: 1 },
+            // Dropped: the partition separates peer 0 from peer 1
+            Step::Sync { from: 0, to: 1 },
+            Step::Sync { from: 2, to: 1 },
+            Step::Heal,
+        ],
+    };
+    let outcome = run(&scenario)?;
+    check_converged(&outcome)?;
+    assert_eq!(outcome.edges.len(), 1);
+    Ok(())
+}
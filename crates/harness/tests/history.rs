@@ -0,0 +1,306 @@
+use openprod_core::{field_value::FieldValue, hlc::{physical_now, Hlc}, identity::ActorIdentity, ids::*, operations::*};
+use openprod_engine::{EngineBuilder, EngineError, EntityEvent, TimelineFilter};
+use openprod_harness::{TestNetwork, TestPeer};
+use openprod_storage::{MemoryStorage, Storage};
+
+// ============================================================================
+// Timeline & Labels
+// ============================================================================
+
+fn import_payloads(entity_id: EntityId) -> Vec<OperationPayload> {
+    vec![
+        OperationPayload::CreateEntity {
+            entity_id,
+            initial_table: Some("Contact".into()),
+        },
+        OperationPayload::SetField {
+            entity_id,
+            field_key: "name".into(),
+            value: FieldValue::Text("Imported".into()),
+        },
+    ]
+}
+
+#[test]
+fn timeline_filters_by_meta_label() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    peer.create_record("Task", vec![("title", FieldValue::Text("manual".into()))])?;
+    let import_id = peer.engine.execute_with_meta(
+        BundleType::Import,
+        import_payloads(EntityId::new()),
+        BundleMeta::with_label("Import"),
+    )?;
+
+    let all = peer.engine.timeline(&TimelineFilter::new())?;
+    assert_eq!(all.len(), 2);
+    assert!(all[0].hlc < all[1].hlc);
+    assert_eq!(all[0].label, None);
+
+    let imports = peer.engine.timeline(&TimelineFilter::new().label("Import"))?;
+    assert_eq!(imports.len(), 1);
+    assert_eq!(imports[0].bundle_id, import_id);
+    assert_eq!(imports[0].bundle_type, BundleType::Import);
+    assert_eq!(imports[0].op_count, 2);
+    Ok(())
+}
+
+#[test]
+fn relabel_is_local_and_overrides_meta() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+
+    let import_id = net.peer_mut(a).engine.execute_with_meta(
+        BundleType::Import,
+        import_payloads(EntityId::new()),
+        BundleMeta::with_label("Import"),
+    )?;
+    let ops_before = net.peer(a).engine.op_count()?;
+    net.peer_mut(a).engine.relabel_bundle(import_id, "Q3 contacts")?;
+    assert_eq!(net.peer(a).engine.op_count()?, ops_before);
+
+    let relabeled = net.peer(a).engine.timeline(&TimelineFilter::new().label("Q3 contacts"))?;
+    assert_eq!(relabeled.len(), 1);
+    assert!(net.peer(a).engine.timeline(&TimelineFilter::new().label("Import"))?.is_empty());
+
+    // Peers see the original meta label, not the local relabel
+    net.sync_to(a, b)?;
+    let on_b = net.peer(b).engine.timeline(&TimelineFilter::new().label("Import"))?;
+    assert_eq!(on_b.len(), 1);
+    assert_eq!(on_b[0].bundle_id, import_id);
+    Ok(())
+}
+
+#[test]
+fn undecodable_meta_does_not_break_the_timeline() -> Result<(), Box<dyn std::error::Error>> {
+    let a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let (bundle_id, hlc) = (BundleId::new(), Hlc::new(physical_now()?, 0));
+    let payload = OperationPayload::CreateEntity { entity_id: EntityId::new(), initial_table: None };
+    let ops = vec![Operation::new_signed(a.engine.identity(), hlc, bundle_id, Default::default(), payload)?];
+    let mut bundle = Bundle::new_signed(bundle_id, a.engine.identity(), hlc, BundleType::UserEdit, &ops, None)?;
+    // Meta is not signed, so a peer can send anything
+    bundle.meta = Some(vec![0xc1]);
+    b.engine.ingest_bundle(&bundle, &ops)?;
+
+    let timeline = b.engine.timeline(&TimelineFilter::new())?;
+    assert_eq!(timeline.len(), 1);
+    assert_eq!((timeline[0].bundle_id, timeline[0].label.as_deref()), (bundle_id, None));
+    Ok(())
+}
+
+#[test]
+fn timeline_filters_by_actor_and_type() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    net.peer_mut(a).create_record("Task", vec![])?;
+    net.peer_mut(b).create_record("Task", vec![])?;
+    net.sync_all()?;
+
+    let actor_a = net.peer(a).actor_id();
+    let from_a = net.peer(b).engine.timeline(&TimelineFilter::new().actor(actor_a))?;
+    assert_eq!(from_a.len(), 1);
+    assert_eq!(from_a[0].actor_id, actor_a);
+
+    let imports = net.peer(b).engine.timeline(&TimelineFilter::new().bundle_type(BundleType::Import))?;
+    assert!(imports.is_empty());
+    Ok(())
+}
+
+#[test]
+fn relabel_unknown_bundle_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let result = peer.engine.relabel_bundle(BundleId::new(), "x");
+    assert!(matches!(result, Err(EngineError::BundleNotFound(_))));
+    Ok(())
+}
+
+#[test]
+fn bundles_are_found_by_meta_tag() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let task = net.peer_mut(a).create_record("Task", vec![])?;
+    let import_id = net.peer_mut(a).engine.execute_with_meta(
+        BundleType::Import,
+        import_payloads(EntityId::new()),
+        BundleMeta::with_label("Imported from CSV").description("2 rows from contacts.csv").tag("import"),
+    )?;
+
+    // Typed commands pick up meta from the surrounding scope
+    let rename = BundleMeta::with_label("Bulk rename").tag("bulk-rename").tag("import");
+    let renamed = net.peer_mut(a).engine.with_bundle_meta(rename, |engine| {
+        engine.set_field_bulk(vec![(task, "title", FieldValue::Text("renamed".into()))])
+    })?;
+    let plain = net.peer_mut(a).engine.set_field(task, "title", FieldValue::Text("again".into()))?;
+    assert_eq!(net.peer(a).engine.storage().get_bundle(plain)?.unwrap().meta, None);
+
+    let imports = net.peer(a).engine.find_bundles_by_tag("import")?;
+    assert_eq!(imports.iter().map(|e| e.bundle_id).collect::<Vec<_>>(), vec![import_id, renamed]);
+    assert_eq!(imports[0].label.as_deref(), Some("Imported from CSV"));
+    assert_eq!(imports[1].label.as_deref(), Some("Bulk rename"));
+    assert!(net.peer(a).engine.find_bundles_by_tag("export")?.is_empty());
+
+    // Tags travel with the bundle and are indexed on ingest
+    net.sync_to(a, b)?;
+    let on_b = net.peer(b).engine.find_bundles_by_tag("bulk-rename")?;
+    assert_eq!(on_b.len(), 1);
+    assert_eq!(on_b[0].bundle_id, renamed);
+    let meta = net.peer(b).engine.storage().get_bundle(import_id)?.unwrap().decode_meta()?.unwrap();
+    assert_eq!(meta.description.as_deref(), Some("2 rows from contacts.csv"));
+    Ok(())
+}
+
+#[test]
+fn default_bundle_meta_fills_every_local_bundle() -> Result<(), Box<dyn std::error::Error>> {
+    let defaults = BundleMeta { origin_device: Some("stage-ipad".into()), app_version: Some("2.1.0".into()), ..Default::default() }
+        .tag("mobile");
+    let mut engine = EngineBuilder::new()
+        .default_bundle_meta(defaults)
+        .build(ActorIdentity::generate(), MemoryStorage::new())?;
+    let (_, created) = engine.create_entity(Some("Task"))?;
+    let imported = engine.execute_with_meta(
+        BundleType::Import,
+        import_payloads(EntityId::new()),
+        BundleMeta { app_version: Some("2.2.0".into()), ..BundleMeta::with_label("Import") }.tag("import"),
+    )?;
+
+    let meta = |bundle_id| -> Result<BundleMeta, Box<dyn std::error::Error>> {
+        Ok(engine.storage().get_bundle(bundle_id)?.unwrap().decode_meta()?.unwrap())
+    };
+    assert_eq!(meta(created)?.origin_device.as_deref(), Some("stage-ipad"));
+    let import_meta = meta(imported)?;
+    assert_eq!(import_meta.app_version.as_deref(), Some("2.2.0"));
+    assert_eq!(import_meta.origin_device.as_deref(), Some("stage-ipad"));
+    assert_eq!(import_meta.label.as_deref(), Some("Import"));
+    assert_eq!(engine.find_bundles_by_tag("mobile")?.len(), 2);
+    assert_eq!(engine.find_bundles_by_tag("import")?.len(), 1);
+    Ok(())
+}
+
+// ============================================================================
+// Editing Sessions
+// ============================================================================
+
+#[test]
+fn session_stamps_bundles_until_ended() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let before = net.peer_mut(a).create_record("Task", vec![])?;
+    let session = net.peer_mut(a).engine.begin_session("Import cues.csv")?;
+    assert_eq!(net.peer(a).engine.active_session(), Some(session));
+    assert!(matches!(net.peer_mut(a).engine.begin_session("nested"), Err(EngineError::Rejected(_))));
+
+    let (_, created) = net.peer_mut(a).engine.create_entity(Some("Cue"))?;
+    let imported = net.peer_mut(a).engine.execute_with_meta(
+        BundleType::Import,
+        import_payloads(EntityId::new()),
+        BundleMeta::with_label("Row 2"),
+    )?;
+    assert_eq!(net.peer_mut(a).engine.end_session(), Some(session));
+    assert_eq!(net.peer_mut(a).engine.end_session(), None);
+    let after = net.peer_mut(a).engine.set_field(before, "title", FieldValue::Text("later".into()))?;
+    assert_eq!(net.peer(a).engine.storage().get_bundle(after)?.unwrap().meta, None);
+
+    let bundles = net.peer(a).engine.get_session_bundles(session)?;
+    assert_eq!(bundles.iter().map(|e| e.bundle_id).collect::<Vec<_>>(), vec![created, imported]);
+    // The session label fills in for bundles without their own
+    assert_eq!(bundles[0].label.as_deref(), Some("Import cues.csv"));
+    assert_eq!(bundles[1].label.as_deref(), Some("Row 2"));
+    assert!(net.peer(a).engine.get_session_bundles(SessionId::new())?.is_empty());
+
+    net.sync_to(a, b)?;
+    assert_eq!(net.peer(b).engine.get_session_bundles(session)?.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn revert_session_restores_state_from_before_the_session() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let task = peer.create_record("Task", vec![("title", FieldValue::Text("draft".into()))])?;
+    let session = peer.engine.begin_session("Cleanup")?;
+    peer.set_field(task, "title", FieldValue::Text("first pass".into()))?;
+    peer.set_field(task, "title", FieldValue::Text("second pass".into()))?;
+    peer.set_field(task, "notes", FieldValue::Text("tidy".into()))?;
+    let (scratch, _) = peer.engine.create_entity(Some("Note"))?;
+    assert!(matches!(peer.engine.revert_session(session), Err(EngineError::Rejected(_))));
+    peer.engine.end_session();
+
+    // Written after the session: left alone
+    peer.set_field(task, "notes", FieldValue::Text("kept".into()))?;
+
+    let result = peer.engine.revert_session(session)?;
+    assert!(result.bundle_id.is_some());
+    assert_eq!(result.skipped.iter().map(|c| c.field_key.as_str()).collect::<Vec<_>>(), vec!["notes"]);
+    assert_eq!(peer.engine.get_field(task, "title")?, Some(FieldValue::Text("draft".into())));
+    assert_eq!(peer.engine.get_field(task, "notes")?, Some(FieldValue::Text("kept".into())));
+    assert!(peer.engine.storage().get_entity(scratch)?.unwrap().deleted);
+    assert!(matches!(peer.engine.revert_session(SessionId::new()), Err(EngineError::Rejected(_))));
+    Ok(())
+}
+
+// ============================================================================
+// Entity History & Blame
+// ============================================================================
+
+#[test]
+fn entity_history_collects_every_affecting_op() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let task = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    let project = net.peer_mut(a).create_record("Project", vec![])?;
+    let edge = net.peer_mut(a).create_edge("belongs_to", project, task)?;
+    net.sync_all()?;
+
+    // Concurrent edits, then a resolution
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("from a".into()))?;
+    net.peer_mut(b).set_field(task, "title", FieldValue::Text("from b".into()))?;
+    net.sync_all()?;
+    let conflict = net.peer(a).get_open_conflicts(task)?[0].conflict_id;
+    net.peer_mut(a).resolve_conflict(conflict, Some(FieldValue::Text("merged".into())))?;
+    net.peer_mut(a).delete_edge(edge)?;
+    net.peer_mut(a).delete_entity(task)?;
+
+    let events: Vec<EntityEvent> =
+        net.peer(a).engine.entity_history(task)?.into_iter().map(|e| e.event).collect();
+    assert!(matches!(events[0], EntityEvent::Created { .. }));
+    assert!(events.contains(&EntityEvent::FieldSet {
+        field_key: "title".into(),
+        value: FieldValue::Text("from b".into()),
+    }));
+    assert!(events.iter().any(|e| matches!(e, EntityEvent::EdgeCreated { edge_id, .. } if *edge_id == edge)));
+    assert!(events.iter().any(|e| matches!(e, EntityEvent::ConflictResolved { field_key, .. } if field_key == "title")));
+    assert!(events.contains(&EntityEvent::EdgeDeleted { edge_id: edge }));
+    assert_eq!(events.last(), Some(&EntityEvent::Deleted));
+
+    // The project's history has the edge but none of the task's field edits
+    let project_events = net.peer(a).engine.entity_history(project)?;
+    assert!(project_events.iter().all(|e| !matches!(e.event, EntityEvent::FieldSet { .. })));
+    assert!(project_events.iter().any(|e| e.event == EntityEvent::EdgeDeleted { edge_id: edge }));
+    Ok(())
+}
+
+#[test]
+fn field_blame_reports_last_writer_per_field() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let task = net.peer_mut(a).create_record(
+        "Task",
+        vec![("title", FieldValue::Text("t".into())), ("notes", FieldValue::Text("n".into()))],
+    )?;
+    net.sync_all()?;
+    net.peer_mut(b).set_field(task, "notes", FieldValue::Text("edited".into()))?;
+    net.sync_all()?;
+
+    let blame = net.peer(a).engine.field_blame(task)?;
+    let writers: Vec<(&str, ActorId)> = blame.iter().map(|f| (f.field_key.as_str(), f.actor_id)).collect();
+    assert_eq!(
+        writers,
+        vec![("notes", net.peer(b).actor_id()), ("title", net.peer(a).actor_id())]
+    );
+    Ok(())
+}
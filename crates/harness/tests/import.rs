@@ -0,0 +1,258 @@
+use openprod_core::{
+    field_value::{FieldValue, ValueType},
+    ids::{EdgeId, EntityId, TableId},
+    operations::{BundleType, OperationPayload},
+};
+use openprod_engine::{
+    EngineError, ExportDocument, ExportFilter, ImportOptions, ImportRowError, TypedValue, EXPORT_FORMAT, EXPORT_VERSION,
+};
+use openprod_harness::{TestNetwork, TestPeer};
+use openprod_storage::{MaterializationStore, Storage};
+
+fn contacts(source: TableId) -> ImportOptions {
+    ImportOptions::new("Contact", source, TableId::new()).map("Name", "name").map("E-mail", "email")
+}
+
+// ============================================================================
+// CSV Import
+// ============================================================================
+
+#[test]
+fn csv_rows_become_records_in_import_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let csv = "Name,E-mail,Shirt\r\n\
+               \"Doe, Jane\",jane@example.com,M\r\n\
+               Sam,,L\r\n\
+               \"Lee \"\"Q\"\"\nLi\",lee@example.com,S\r\n";
+    let source = TableId::new();
+    let report = peer.engine.import_csv(csv, &contacts(source).chunk_size(2))?;
+
+    assert!(report.errors.is_empty());
+    assert_eq!(report.entity_ids.len(), 3);
+    assert_eq!(report.bundle_ids.len(), 2);
+    for bundle_id in &report.bundle_ids {
+        assert_eq!(peer.engine.storage().get_bundle(*bundle_id)?.unwrap().bundle_type, BundleType::Import);
+    }
+    let mappings: Vec<(String, String)> = peer
+        .engine
+        .get_ops_by_bundle(report.bundle_ids[0])?
+        .into_iter()
+        .filter_map(|op| match op.payload {
+            OperationPayload::ConfirmFieldMapping { source_table, source_field, target_field, .. } => {
+                assert_eq!(source_table, source);
+                Some((source_field, target_field))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(mappings, vec![("Name".into(), "name".into()), ("E-mail".into(), "email".into())]);
+
+    let [jane, sam, lee] = report.entity_ids[..] else { panic!("expected three entities") };
+    assert_eq!(peer.engine.get_field(jane, "name")?, Some(FieldValue::Text("Doe, Jane".into())));
+    assert_eq!(peer.engine.get_field(lee, "name")?, Some(FieldValue::Text("Lee \"Q\"\nLi".into())));
+    assert_eq!(peer.engine.get_field(sam, "email")?, None);
+    assert_eq!(peer.engine.get_field(jane, "Shirt")?, None);
+    let mut imported = peer.engine.get_entities_by_facet("Contact")?;
+    imported.sort();
+    let mut expected = report.entity_ids.clone();
+    expected.sort();
+    assert_eq!(imported, expected);
+    Ok(())
+}
+
+#[test]
+fn malformed_csv_rows_are_reported_and_skipped() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let csv = "Name,E-mail\nJane,jane@example.com\n\nSam\nLee,lee@example.com\n\"Unclosed,x\n";
+    let report = peer.engine.import_csv(csv, &contacts(TableId::new()))?;
+
+    assert_eq!(report.entity_ids.len(), 2);
+    assert_eq!(report.bundle_ids.len(), 1);
+    assert_eq!(
+        report.errors,
+        vec![
+            ImportRowError { row: 2, message: "expected 2 fields, found 1".into() },
+            ImportRowError { row: 4, message: "unterminated quoted field".into() },
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn typed_columns_are_parsed_from_their_canonical_text() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let csv = "Name,Age,Joined\n\
+               Jane, 34 ,2023-11-14T22:13:20.000Z\n\
+               Sam,unknown,2024-01-01\n\
+               Lee,,1700000000000\n";
+    let options = ImportOptions::new("Contact", TableId::new(), TableId::new())
+        .map("Name", "name")
+        .map("Age", "age")
+        .map("Joined", "joined")
+        .value_type("age", ValueType::Integer)
+        .value_type("joined", ValueType::Timestamp);
+    assert_eq!(options.type_of("name"), ValueType::Text);
+    let report = peer.engine.import_csv(csv, &options)?;
+
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].row, 2);
+    assert!(report.errors[0].message.starts_with("age: "), "{}", report.errors[0].message);
+    let [jane, lee] = report.entity_ids[..] else { panic!("expected two entities") };
+    assert_eq!(peer.engine.get_field(jane, "age")?, Some(FieldValue::Integer(34)));
+    assert_eq!(peer.engine.get_field(jane, "joined")?, Some(FieldValue::Timestamp(1_700_000_000_000)));
+    assert_eq!(peer.engine.get_field(lee, "age")?, None);
+    assert_eq!(peer.engine.get_field(lee, "joined")?, Some(FieldValue::Timestamp(1_700_000_000_000)));
+    assert_eq!(peer.engine.get_field(jane, "name")?, Some(FieldValue::Text("Jane".into())));
+    Ok(())
+}
+
+#[test]
+fn import_with_unusable_mapping_is_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let csv = "Name,E-mail\nJane,jane@example.com\n";
+    let unmapped = ImportOptions::new("Contact", TableId::new(), TableId::new());
+    assert!(matches!(peer.engine.import_csv(csv, &unmapped), Err(EngineError::Rejected(_))));
+    let missing = contacts(TableId::new()).map("Phone", "phone");
+    assert!(matches!(peer.engine.import_csv(csv, &missing), Err(EngineError::Rejected(_))));
+    let reserved = contacts(TableId::new()).map("Name", "sys:title");
+    assert!(matches!(peer.engine.import_csv(csv, &reserved), Err(EngineError::ReservedField(_))));
+    assert!(matches!(peer.engine.import_csv("", &contacts(TableId::new())), Err(EngineError::Rejected(_))));
+    assert!(peer.engine.get_entities_by_facet("Contact")?.is_empty());
+    Ok(())
+}
+
+// ============================================================================
+// Field Mappings
+// ============================================================================
+
+#[test]
+fn confirmed_mappings_sync_and_are_reused_by_later_imports() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let (source, target) = (TableId::new(), TableId::new());
+    let options = ImportOptions::new("Contact", source, target).map("Name", "name").map("E-mail", "email");
+    net.peer_mut(a).engine.import_csv("Name,E-mail\nJane,jane@example.com\n", &options)?;
+    net.sync_to(a, b)?;
+
+    let mappings = net.peer(b).engine.get_field_mappings(source)?;
+    let pairs: Vec<(&str, &str)> =
+        mappings.iter().map(|m| (m.source_field.as_str(), m.target_field.as_str())).collect();
+    assert_eq!(pairs, vec![("E-mail", "email"), ("Name", "name")]);
+    assert!(mappings.iter().all(|m| m.target_table == target && m.confirmed_by == net.peer(a).engine.actor_id()));
+    assert!(net.peer(b).engine.get_field_mappings(target)?.is_empty());
+
+    // No columns mapped: the confirmed mapping is reused, and not re-confirmed
+    let reuse = ImportOptions::new("Contact", source, target);
+    let report = net.peer_mut(b).engine.import_csv("E-mail,Name\nsam@example.com,Sam\n", &reuse)?;
+    let sam = report.entity_ids[0];
+    assert_eq!(net.peer(b).engine.get_field(sam, "email")?, Some(FieldValue::Text("sam@example.com".into())));
+    let ops = net.peer(b).engine.get_ops_by_bundle(report.bundle_ids[0])?;
+    assert!(!ops.iter().any(|op| matches!(op.payload, OperationPayload::ConfirmFieldMapping { .. })));
+    let unknown = ImportOptions::new("Contact", TableId::new(), target);
+    assert!(matches!(net.peer_mut(b).engine.import_csv("Name\nLee\n", &unknown), Err(EngineError::Rejected(_))));
+
+    // A later confirmation replaces the earlier one, and survives a rebuild
+    let remap = ImportOptions::new("Contact", source, target).map("E-mail", "work_email");
+    net.peer_mut(b).engine.import_csv("E-mail\nlee@example.com\n", &remap)?;
+    net.peer_mut(b).engine.storage_mut().rebuild_from_oplog()?;
+    net.sync_to(b, a)?;
+    for peer in [a, b] {
+        let mappings = net.peer(peer).engine.get_field_mappings(source)?;
+        let email = mappings.iter().find(|m| m.source_field == "E-mail").unwrap();
+        assert_eq!(email.target_field, "work_email");
+        assert_eq!(mappings.len(), 2);
+    }
+    Ok(())
+}
+
+// ============================================================================
+// JSON Export
+// ============================================================================
+
+#[test]
+fn json_export_round_trips_entities_and_edges() -> Result<(), Box<dyn std::error::Error>> {
+    let mut source = TestPeer::new()?;
+    let (cue, _) = source.engine.create_entity_with_fields(
+        "Cue",
+        vec![
+            ("number", FieldValue::Integer(12)),
+            ("label", FieldValue::Text("Blackout".into())),
+            ("fade", FieldValue::Float(2.5)),
+            ("called", FieldValue::Boolean(true)),
+            ("checksum", FieldValue::Bytes(vec![0xde, 0xad])),
+        ],
+    )?;
+    let (page, _) = source.engine.create_entity_with_fields("Page", vec![("number", FieldValue::Integer(4))])?;
+    source.engine.set_field(cue, "page", FieldValue::EntityRef(page))?;
+    source.engine.attach_facet(cue, "Sound")?;
+    let (edge, _) =
+        source.engine.create_edge_with_properties("on_page", cue, page, vec![("order", FieldValue::Integer(1))])?;
+    let deleted = source.create_record("Cue", vec![])?;
+    source.delete_entity(deleted)?;
+
+    let json = source.engine.export_json(&ExportFilter::new())?;
+    assert!(json.contains(r#""type": "integer""#));
+    let document = ExportDocument::from_json(&json)?;
+    assert_eq!((document.format.as_str(), document.version), (EXPORT_FORMAT, EXPORT_VERSION));
+    assert_eq!(document.entities.len(), 2);
+    let exported_cue = document.entities.iter().find(|e| e.id == cue).unwrap();
+    assert_eq!(exported_cue.facets, vec!["Cue".to_string(), "Sound".to_string()]);
+    assert_eq!(exported_cue.fields["checksum"], TypedValue::Bytes("dead".into()));
+    assert_eq!(document.edges.len(), 1);
+    assert_eq!((document.edges[0].id, document.edges[0].edge_type.as_str()), (edge, "on_page"));
+    // Exporting unchanged data gives the same document
+    assert_eq!(source.engine.export_json(&ExportFilter::new())?, json);
+
+    let mut target = TestPeer::new()?;
+    let imported = target.engine.import_json(&json)?;
+    assert_eq!(imported.bundle_ids.len(), 1);
+    assert_eq!(target.engine.storage().get_bundle(imported.bundle_ids[0])?.unwrap().bundle_type, BundleType::Import);
+    let (new_cue, new_page) = (imported.entity_ids[&cue], imported.entity_ids[&page]);
+    assert_ne!(new_cue, cue);
+    let mut fields = target.engine.get_fields(new_cue)?;
+    fields.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut expected = source.engine.get_fields(cue)?;
+    expected.sort_by(|a, b| a.0.cmp(&b.0));
+    for (key, value) in expected.iter_mut() {
+        if key == "page" {
+            *value = FieldValue::EntityRef(new_page);
+        }
+    }
+    assert_eq!(fields, expected);
+    let mut facets: Vec<String> = target.engine.get_facets(new_cue)?.into_iter().map(|f| f.facet_type).collect();
+    facets.sort();
+    assert_eq!(facets, vec!["Cue".to_string(), "Sound".to_string()]);
+    let new_edge = target.engine.storage().get_edge(imported.edge_ids[&edge])?.unwrap();
+    assert_eq!((new_edge.source_id, new_edge.target_id), (new_cue, new_page));
+    assert_eq!(target.engine.get_edge_properties(new_edge.edge_id)?, vec![("order".to_string(), FieldValue::Integer(1))]);
+
+    // Filtered: only cues, so the edge to the page is left out
+    let cues = ExportDocument::from_json(&source.engine.export_json(&ExportFilter::new().facets(["Cue"]))?)?;
+    assert_eq!(cues.entities.iter().map(|e| e.id).collect::<Vec<_>>(), vec![cue]);
+    assert!(cues.edges.is_empty());
+    Ok(())
+}
+
+#[test]
+fn invalid_json_documents_write_nothing() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let dangling = format!(
+        r#"{{"format": "{EXPORT_FORMAT}", "version": 1,
+            "entities": [{{"id": "{a}", "facets": ["Cue"]}}],
+            "edges": [{{"id": "{e}", "type": "on_page", "source": "{a}", "target": "{b}"}}]}}"#,
+        a = EntityId::new(),
+        b = EntityId::new(),
+        e = EdgeId::new(),
+    );
+    for json in [
+        "not json".to_string(),
+        r#"{"format": "something.else", "version": 1, "entities": []}"#.to_string(),
+        format!(r#"{{"format": "{EXPORT_FORMAT}", "version": {}, "entities": []}}"#, EXPORT_VERSION + 1),
+        dangling,
+    ] {
+        assert!(matches!(peer.engine.import_json(&json), Err(EngineError::InvalidDocument(_))), "{json}");
+    }
+    assert!(peer.engine.get_entities_by_facet("Cue")?.is_empty());
+    Ok(())
+}
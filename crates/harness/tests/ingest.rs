@@ -0,0 +1,887 @@
+use openprod_core::{field_value::FieldValue, hlc::{physical_now, Hlc}, identity::ActorIdentity, ids::*, operations::*, proto, sealed::WorkspaceKey};
+use openprod_engine::{Engine, EngineBuilder, EngineError, ModulePolicy, ModuleRegistry, SetAside, TrustPolicy};
+use prost::Message;
+use openprod_harness::TestPeer;
+use openprod_storage::{LabelStore, MemoryStorage, PendingStore, Storage, StorageError};
+
+/// Helper: extract the latest bundle (and its ops) from a peer, signed as it would be on the wire.
+fn latest_bundle(from: &TestPeer) -> Result<(Bundle, Vec<Operation>), Box<dyn std::error::Error>> {
+    let ops = from.engine.get_ops_canonical()?;
+    let last_op = ops.last().unwrap();
+    let bundle_id = last_op.bundle_id;
+    let bundle_ops = from.engine.get_ops_by_bundle(bundle_id)?;
+    let vc = from.engine.storage().get_bundle_vector_clock(bundle_id)?;
+    let bundle = Bundle::new_signed(
+        bundle_id,
+        from.engine.identity(),
+        last_op.hlc,
+        BundleType::UserEdit,
+        &bundle_ops,
+        vc,
+    )?;
+    Ok((bundle, bundle_ops))
+}
+
+type BundleWithOps = (Bundle, Vec<Operation>);
+
+/// Helper: every stored bundle (and its ops) from a peer, in HLC order.
+fn all_bundles(from: &TestPeer) -> Result<Vec<BundleWithOps>, Box<dyn std::error::Error>> {
+    let mut result = Vec::new();
+    for (bundle, _) in from.engine.storage().list_bundles_with_labels()? {
+        let ops = from.engine.get_ops_by_bundle(bundle.bundle_id)?;
+        result.push((bundle, ops));
+    }
+    Ok(result)
+}
+
+/// Helper: create an entity on `a` and replicate it to `b`.
+fn shared_entity(
+    a: &mut TestPeer,
+    b: &mut TestPeer,
+    fields: Vec<(&str, FieldValue)>,
+) -> Result<EntityId, Box<dyn std::error::Error>> {
+    let entity_id = a.create_record("Task", fields)?;
+    let (bundle, ops) = latest_bundle(a)?;
+    b.engine.ingest_bundle(&bundle, &ops)?;
+    Ok(entity_id)
+}
+
+// ============================================================================
+// Dry-Run Ingest Preview
+// ============================================================================
+
+#[test]
+fn preview_reports_created_entity_without_writing() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("Hello".into()))])?;
+    let (bundle, ops) = latest_bundle(&a)?;
+
+    let preview = b.engine.preview_ingest(&bundle, &ops)?;
+    assert_eq!(preview.entities_created, vec![entity_id]);
+    assert!(preview.entities_deleted.is_empty());
+    assert_eq!(preview.field_changes.len(), 1);
+    assert_eq!(preview.field_changes[0].field_key, "title");
+    assert_eq!(preview.field_changes[0].old_value, None);
+    assert_eq!(preview.field_changes[0].new_value, Some(FieldValue::Text("Hello".into())));
+    assert!(preview.conflicts.is_empty());
+
+    // Nothing was written
+    assert!(b.engine.get_entity(entity_id)?.is_none());
+    assert_eq!(b.engine.op_count()?, 0);
+    assert!(b.engine.get_vector_clock()?.entries().is_empty());
+
+    // Real ingest still succeeds afterwards
+    b.engine.ingest_bundle(&bundle, &ops)?;
+    assert!(b.engine.get_entity(entity_id)?.is_some());
+    Ok(())
+}
+
+#[test]
+fn preview_reports_field_overwrite_and_delete() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let entity_id = shared_entity(&mut a, &mut b, vec![("status", FieldValue::Text("open".into()))])?;
+
+    a.set_field(entity_id, "status", FieldValue::Text("done".into()))?;
+    let (bundle, ops) = latest_bundle(&a)?;
+    let preview = b.engine.preview_ingest(&bundle, &ops)?;
+    assert_eq!(preview.field_changes.len(), 1);
+    assert_eq!(preview.field_changes[0].old_value, Some(FieldValue::Text("open".into())));
+    assert_eq!(preview.field_changes[0].new_value, Some(FieldValue::Text("done".into())));
+    assert_eq!(b.engine.get_field(entity_id, "status")?, Some(FieldValue::Text("open".into())));
+    b.engine.ingest_bundle(&bundle, &ops)?;
+
+    a.delete_entity(entity_id)?;
+    let (bundle, ops) = latest_bundle(&a)?;
+    let preview = b.engine.preview_ingest(&bundle, &ops)?;
+    assert_eq!(preview.entities_deleted, vec![entity_id]);
+    assert!(!b.engine.get_entity(entity_id)?.unwrap().deleted);
+    Ok(())
+}
+
+#[test]
+fn preview_reports_conflict_without_persisting_it() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let entity_id = shared_entity(&mut a, &mut b, vec![("title", FieldValue::Text("base".into()))])?;
+
+    // Concurrent edits
+    a.set_field(entity_id, "title", FieldValue::Text("from A".into()))?;
+    b.set_field(entity_id, "title", FieldValue::Text("from B".into()))?;
+
+    let (bundle, ops) = latest_bundle(&a)?;
+    let preview = b.engine.preview_ingest(&bundle, &ops)?;
+    assert_eq!(preview.conflicts.len(), 1);
+    assert_eq!(preview.conflicts[0].field_key, "title");
+    assert!(b.get_open_conflicts(entity_id)?.is_empty());
+
+    // Preview matches the real outcome
+    let conflicts = b.engine.ingest_bundle(&bundle, &ops)?.conflicts;
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].field_key, "title");
+    Ok(())
+}
+
+#[test]
+fn preview_of_already_ingested_bundle_is_empty() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    shared_entity(&mut a, &mut b, vec![("title", FieldValue::Text("x".into()))])?;
+
+    let (bundle, ops) = latest_bundle(&a)?;
+    let preview = b.engine.preview_ingest(&bundle, &ops)?;
+    assert!(preview.is_empty());
+    Ok(())
+}
+
+#[test]
+fn preview_reports_overlay_drift_without_marking_it() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let entity_id = shared_entity(&mut a, &mut b, vec![("title", FieldValue::Text("base".into()))])?;
+
+    let overlay_id = b.create_overlay("draft")?;
+    b.set_field(entity_id, "title", FieldValue::Text("draft".into()))?;
+    b.stash_overlay(overlay_id)?;
+
+    a.set_field(entity_id, "title", FieldValue::Text("canonical".into()))?;
+    let (bundle, ops) = latest_bundle(&a)?;
+    let preview = b.engine.preview_ingest(&bundle, &ops)?;
+    assert_eq!(preview.drift.len(), 1);
+    assert_eq!(preview.drift[0].overlay_id, overlay_id);
+    assert_eq!(preview.drift[0].drift.field_key, "title");
+    assert_eq!(preview.drift[0].drift.canonical_value, Some(FieldValue::Text("canonical".into())));
+    assert!(!b.engine.has_unresolved_drift(overlay_id)?);
+    Ok(())
+}
+
+#[test]
+fn preview_of_out_of_order_bundle_reports_missing_dependencies() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+
+    let entity_id = a.create_record("Task", vec![])?;
+    a.set_field(entity_id, "title", FieldValue::Text("x".into()))?;
+    let (bundle, ops) = latest_bundle(&a)?;
+
+    let preview = b.engine.preview_ingest(&bundle, &ops)?;
+    assert!(preview.would_buffer());
+    assert_eq!(preview.missing_dependencies[0].actor_id, a.actor_id());
+    assert!(preview.field_changes.is_empty());
+    assert_eq!(b.engine.pending_count()?, 0);
+    Ok(())
+}
+
+#[test]
+fn preview_of_unknown_actor_reports_quarantine() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    b.engine.set_trust_policy(TrustPolicy::QuarantineUnknown);
+
+    a.create_record("Task", vec![("title", FieldValue::Text("x".into()))])?;
+    let (bundle, ops) = latest_bundle(&a)?;
+    let preview = b.engine.preview_ingest(&bundle, &ops)?;
+    assert!(preview.would_set_aside());
+    assert_eq!(preview.set_aside, Some(SetAside::Untrusted));
+    assert!(preview.entities_created.is_empty());
+    assert_eq!(b.engine.quarantined_count()?, 0);
+    assert_eq!(b.engine.actor_trust(a.actor_id())?, None);
+
+    // Rejecting policies fail the preview just as they fail ingest
+    b.engine.set_trust_policy(TrustPolicy::RejectUnknown);
+    assert!(matches!(b.engine.preview_ingest(&bundle, &ops), Err(EngineError::UntrustedActor(_))));
+    Ok(())
+}
+
+// ============================================================================
+// Causal Buffering
+// ============================================================================
+
+#[test]
+fn out_of_order_bundle_waits_for_its_dependency() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    a.set_field(entity_id, "title", FieldValue::Text("v2".into()))?;
+    let bundles = all_bundles(&a)?;
+    let (create, create_ops) = &bundles[0];
+    let (edit, edit_ops) = &bundles[1];
+
+    // The edit arrives first: buffered, nothing materialized
+    assert!(b.engine.ingest_bundle(edit, edit_ops)?.conflicts.is_empty());
+    assert_eq!(b.engine.op_count()?, 0);
+    let pending = b.engine.pending_bundles()?;
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].bundle_id, edit.bundle_id);
+    assert_eq!(pending[0].missing.len(), 1);
+    assert_eq!(pending[0].missing[0].actor_id, a.actor_id());
+    assert_eq!(pending[0].missing[0].have, None);
+
+    // Delivering the dependency releases the edit
+    b.engine.ingest_bundle(create, create_ops)?;
+    assert_eq!(b.engine.pending_count()?, 0);
+    assert_eq!(b.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v2".into())));
+    Ok(())
+}
+
+#[test]
+fn stuck_bundle_can_be_flushed_or_discarded() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+
+    let entity_id = a.create_record("Task", vec![])?;
+    a.set_field(entity_id, "title", FieldValue::Text("x".into()))?;
+    let bundles = all_bundles(&a)?;
+    let (edit, edit_ops) = &bundles[1];
+
+    b.engine.ingest_bundle(edit, edit_ops)?;
+    // Flushing without the dependency makes no progress
+    assert!(b.engine.flush_pending()?.conflicts.is_empty());
+    assert_eq!(b.engine.pending_count()?, 1);
+
+    assert!(b.engine.discard_pending(edit.bundle_id)?);
+    assert!(!b.engine.discard_pending(edit.bundle_id)?);
+    assert!(b.engine.pending_bundles()?.is_empty());
+    assert_eq!(b.engine.op_count()?, 0);
+    Ok(())
+}
+
+#[test]
+fn released_bundle_reports_its_conflicts() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let mut c = TestPeer::new()?;
+    let entity_id = shared_entity(&mut a, &mut b, vec![("title", FieldValue::Text("base".into()))])?;
+    let (create, create_ops) = all_bundles(&a)?.remove(0);
+    c.engine.ingest_bundle(&create, &create_ops)?;
+
+    // b edits, then a edits concurrently after seeing a further change of its own
+    b.set_field(entity_id, "title", FieldValue::Text("from B".into()))?;
+    a.set_field(entity_id, "status", FieldValue::Text("open".into()))?;
+    a.set_field(entity_id, "title", FieldValue::Text("from A".into()))?;
+    let a_bundles = all_bundles(&a)?;
+    let (b_edit, b_ops) = latest_bundle(&b)?;
+
+    // c gets b's edit, then a's title edit before a's status edit
+    c.engine.ingest_bundle(&b_edit, &b_ops)?;
+    assert!(c.engine.ingest_bundle(&a_bundles[2].0, &a_bundles[2].1)?.conflicts.is_empty());
+    assert_eq!(c.engine.pending_count()?, 1);
+
+    let conflicts = c.engine.ingest_bundle(&a_bundles[1].0, &a_bundles[1].1)?.conflicts;
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].field_key, "title");
+    assert_eq!(c.engine.pending_count()?, 0);
+    Ok(())
+}
+
+#[test]
+fn pending_chain_is_released_by_its_root() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let mut c = TestPeer::new()?;
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    a.set_field(entity_id, "title", FieldValue::Text("v2".into()))?;
+    a.set_field(entity_id, "title", FieldValue::Text("v3".into()))?;
+    let a_bundles = all_bundles(&a)?;
+    for (bundle, ops) in &a_bundles {
+        b.engine.ingest_bundle(bundle, ops)?;
+    }
+    b.set_field(entity_id, "status", FieldValue::Text("done".into()))?;
+    let (b_edit, b_ops) = latest_bundle(&b)?;
+
+    // Everything but the root arrives first, newest first
+    c.engine.ingest_bundle(&b_edit, &b_ops)?;
+    c.engine.ingest_bundle(&a_bundles[2].0, &a_bundles[2].1)?;
+    c.engine.ingest_bundle(&a_bundles[1].0, &a_bundles[1].1)?;
+    assert_eq!(c.engine.pending_count()?, 3);
+
+    let report = c.engine.ingest_bundle(&a_bundles[0].0, &a_bundles[0].1)?;
+    assert_eq!((report.bundles_applied, report.bundles_buffered), (4, 0));
+    assert_eq!(c.engine.pending_count()?, 0);
+    assert_eq!(c.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v3".into())));
+    assert_eq!(c.engine.get_field(entity_id, "status")?, Some(FieldValue::Text("done".into())));
+    Ok(())
+}
+
+// ============================================================================
+// Batch Ingest
+// ============================================================================
+
+#[test]
+fn batch_ingest_matches_sequential_ingest() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut sequential = TestPeer::new()?;
+    let mut batched = TestPeer::new()?;
+
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    for i in 2..=5 {
+        a.set_field(entity_id, "title", FieldValue::Text(format!("v{i}")))?;
+    }
+    a.clear_field(entity_id, "title")?;
+    a.set_field(entity_id, "status", FieldValue::Text("open".into()))?;
+    let bundles = all_bundles(&a)?;
+
+    for (bundle, ops) in &bundles {
+        sequential.engine.ingest_bundle(bundle, ops)?;
+    }
+    batched.engine.ingest_bundles(&bundles)?;
+
+    assert_eq!(batched.engine.op_count()?, sequential.engine.op_count()?);
+    assert_eq!(batched.engine.get_fields(entity_id)?, sequential.engine.get_fields(entity_id)?);
+    assert_eq!(batched.engine.get_field(entity_id, "title")?, None);
+    assert_eq!(batched.engine.get_vector_clock()?, a.engine.get_vector_clock()?);
+    Ok(())
+}
+
+#[test]
+fn large_bundle_ingests_across_insert_batches() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+
+    // Enough ops to span several multi-row oplog inserts, plus a partial one
+    let keys: Vec<String> = (0..1234).map(|i| format!("f{i}")).collect();
+    let fields = keys.iter().enumerate().map(|(i, k)| (k.as_str(), FieldValue::Integer(i as i64))).collect();
+    let entity_id = a.create_record("Cue", fields)?;
+    let (bundle, ops) = latest_bundle(&a)?;
+    b.engine.ingest_bundle(&bundle, &ops)?;
+
+    assert_eq!(b.engine.op_count()?, a.engine.op_count()?);
+    assert_eq!(b.engine.get_ops_by_bundle(bundle.bundle_id)?.len(), ops.len());
+    assert_eq!(b.engine.get_fields(entity_id)?, a.engine.get_fields(entity_id)?);
+    assert_eq!(b.engine.get_vector_clock()?, a.engine.get_vector_clock()?);
+    let actors = b.engine.list_actors()?;
+    let author = actors.iter().find(|r| r.actor_id == a.engine.actor_id()).ok_or("author not recorded")?;
+    assert_eq!(author.op_count, ops.len() as u64);
+    assert_eq!(author.first_seen_at, ops[0].hlc);
+    Ok(())
+}
+
+#[test]
+fn batch_ingest_reports_conflicts_across_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let entity_id = shared_entity(&mut a, &mut b, vec![("title", FieldValue::Text("base".into()))])?;
+
+    // b edits while a makes several edits of its own, unaware of b
+    b.set_field(entity_id, "title", FieldValue::Text("from B".into()))?;
+    a.set_field(entity_id, "title", FieldValue::Text("A1".into()))?;
+    a.set_field(entity_id, "title", FieldValue::Text("A2".into()))?;
+
+    let mut batch = all_bundles(&a)?;
+    batch.remove(0);
+    let conflicts = b.engine.ingest_bundles(&batch)?.conflicts;
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].field_key, "title");
+    assert_eq!(b.get_open_conflicts(entity_id)?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn batch_ingest_reorders_out_of_order_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+
+    let entity_id = a.create_record("Task", vec![])?;
+    a.set_field(entity_id, "title", FieldValue::Text("x".into()))?;
+    let mut batch = all_bundles(&a)?;
+    batch.reverse();
+
+    b.engine.ingest_bundles(&batch)?;
+    assert_eq!(b.engine.pending_count()?, 0);
+    assert_eq!(b.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("x".into())));
+    Ok(())
+}
+
+// ============================================================================
+// Sealed Bundles
+// ============================================================================
+
+#[test]
+fn sealed_bundles_need_the_workspace_key() -> Result<(), Box<dyn std::error::Error>> {
+    let key = WorkspaceKey::generate();
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    a.engine.set_workspace_key(Some(key.clone()));
+
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("secret".into()))])?;
+    let (bundle, _) = all_bundles(&a)?.remove(0);
+    let sealed = a.engine.seal_bundle(bundle.bundle_id)?;
+
+    // Without the key b refuses and stores nothing
+    assert!(matches!(b.engine.ingest_sealed(&sealed), Err(EngineError::NoWorkspaceKey)));
+    b.engine.set_workspace_key(Some(WorkspaceKey::generate()));
+    assert!(matches!(b.engine.ingest_sealed(&sealed), Err(EngineError::Core(_))));
+    assert_eq!(b.engine.op_count()?, 0);
+
+    b.engine.set_workspace_key(Some(key));
+    b.engine.ingest_sealed(&sealed)?;
+    assert_eq!(b.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("secret".into())));
+    Ok(())
+}
+
+// ============================================================================
+// Protobuf Bundles
+// ============================================================================
+
+/// Helper: a bundle creating a titled Task, built the way a non-Rust client
+/// following `wire.proto` would: messages only, signed over their encoding.
+fn proto_client_bundle(identity: &ActorIdentity, entity_id: EntityId, title: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    use proto::payload::Kind;
+    let actor_id = identity.actor_id().as_bytes().to_vec();
+    let bundle_id = BundleId::new().as_bytes().to_vec();
+    let entity = entity_id.as_bytes().to_vec();
+    let wall_ms = physical_now()?;
+    let payloads = [
+        Kind::CreateEntity(proto::CreateEntity { entity_id: entity.clone(), initial_table: None }),
+        Kind::AttachFacet(proto::AttachFacet { entity_id: entity.clone(), facet_type: "Task".into() }),
+        Kind::SetField(proto::SetField {
+            entity_id: entity.clone(),
+            field_key: "title".into(),
+            value: Some(proto::Value { kind: Some(proto::value::Kind::Text(title.into())) }),
+        }),
+    ];
+
+    let mut checksum = blake3::Hasher::new();
+    let mut operations = Vec::new();
+    for (counter, kind) in payloads.into_iter().enumerate() {
+        let payload = proto::Payload { kind: Some(kind) };
+        checksum.update(&payload.encode_length_delimited_to_vec());
+        let input = proto::OperationSigningInput {
+            op_id: OpId::new().as_bytes().to_vec(),
+            actor_id: actor_id.clone(),
+            hlc: Some(proto::Hlc { wall_ms, counter: counter as u32 }),
+            module_versions: vec![],
+            payload: Some(payload),
+        };
+        let signature = identity.sign(&[proto::OPERATION_SIGNING_CONTEXT, &input.encode_to_vec()].concat());
+        operations.push(proto::Operation {
+            op_id: input.op_id,
+            actor_id: input.actor_id,
+            hlc: input.hlc,
+            bundle_id: bundle_id.clone(),
+            module_versions: input.module_versions,
+            payload: input.payload,
+            signature: signature.as_bytes().to_vec(),
+        });
+    }
+
+    let input = proto::BundleSigningInput {
+        bundle_id,
+        actor_id,
+        hlc: Some(proto::Hlc { wall_ms, counter: 2 }),
+        bundle_type: proto::BundleType::UserEdit as i32,
+        op_count: operations.len() as u32,
+        checksum: checksum.finalize().as_bytes().to_vec(),
+        creator_vc: None,
+    };
+    let signature = identity.sign(&[proto::BUNDLE_SIGNING_CONTEXT, &input.encode_to_vec()].concat());
+    let bundle = proto::Bundle {
+        bundle_id: input.bundle_id,
+        actor_id: input.actor_id,
+        hlc: input.hlc,
+        bundle_type: input.bundle_type,
+        op_count: input.op_count,
+        checksum: input.checksum,
+        creates: vec![entity],
+        deletes: vec![],
+        meta: None,
+        signature: signature.as_bytes().to_vec(),
+        creator_vc: None,
+    };
+    Ok(proto::SignedBundle { bundle: Some(bundle), operations }.encode_to_vec())
+}
+
+#[test]
+fn bundle_built_from_proto_messages_ingests() -> Result<(), Box<dyn std::error::Error>> {
+    let entity_id = EntityId::new();
+    let bytes = proto_client_bundle(&ActorIdentity::generate(), entity_id, "from mobile")?;
+    let (bundle, ops) = proto::decode_bundle(&bytes)?;
+
+    let mut strict = EngineBuilder::new().verify_signatures(true).open_in_memory(ActorIdentity::generate())?;
+    assert_eq!(strict.ingest_bundle(&bundle, &ops)?.bundles_applied, 1);
+    assert_eq!(strict.get_field(entity_id, "title")?, Some(FieldValue::Text("from mobile".into())));
+
+    // Stored and relayed, it still verifies on the next peer
+    let (stored, _) = strict.storage().list_bundles_with_labels()?.remove(0);
+    let stored_ops = strict.get_ops_by_bundle(stored.bundle_id)?;
+    let mut next = EngineBuilder::new().verify_signatures(true).open_in_memory(ActorIdentity::generate())?;
+    assert_eq!(next.ingest_bundle(&stored, &stored_ops)?.bundles_applied, 1);
+    assert_eq!(next.get_field(entity_id, "title")?, Some(FieldValue::Text("from mobile".into())));
+    Ok(())
+}
+
+#[test]
+fn tampered_proto_bundle_is_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let entity_id = EntityId::new();
+    let bytes = proto_client_bundle(&ActorIdentity::generate(), entity_id, "honest")?;
+    let (bundle, mut ops) = proto::decode_bundle(&bytes)?;
+    if let OperationPayload::SetField { value, .. } = &mut ops[2].payload {
+        *value = FieldValue::Text("forged".into());
+    }
+
+    let mut strict = EngineBuilder::new().verify_signatures(true).open_in_memory(ActorIdentity::generate())?;
+    assert!(matches!(strict.ingest_bundle(&bundle, &ops), Err(EngineError::InvalidBundle(_))));
+    assert!(strict.get_entity(entity_id)?.is_none());
+    Ok(())
+}
+
+// ============================================================================
+// Ingest Report
+// ============================================================================
+
+#[test]
+fn report_counts_applied_and_already_present_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    a.create_record("Task", vec![("title", FieldValue::Text("t".into())), ("done", FieldValue::Boolean(false))])?;
+    let (bundle, ops) = latest_bundle(&a)?;
+
+    let report = b.engine.ingest_bundle(&bundle, &ops)?;
+    assert_eq!(report.bundles_applied, 1);
+    assert_eq!(report.entities_created, 1);
+    assert_eq!(report.ops_applied, ops.len());
+    assert_eq!(report.ops_skipped_lww, 0);
+    assert!(report.conflicts.is_empty());
+
+    let again = b.engine.ingest_bundle(&bundle, &ops)?;
+    assert_eq!(again.bundles_applied, 0);
+    assert_eq!(again.bundles_already_present, 1);
+    assert_eq!(again.ops_applied, 0);
+    assert_eq!(again.entities_created, 0);
+    Ok(())
+}
+
+#[test]
+fn report_counts_buffered_and_released_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    a.set_field(entity_id, "title", FieldValue::Text("v2".into()))?;
+    let bundles = all_bundles(&a)?;
+
+    let report = b.engine.ingest_bundle(&bundles[1].0, &bundles[1].1)?;
+    assert_eq!((report.bundles_applied, report.bundles_buffered), (0, 1));
+
+    let report = b.engine.ingest_bundle(&bundles[0].0, &bundles[0].1)?;
+    assert_eq!((report.bundles_applied, report.bundles_buffered), (2, 0));
+    assert_eq!(report.ops_applied, bundles[0].1.len() + bundles[1].1.len());
+    Ok(())
+}
+
+#[test]
+fn report_classifies_conflicts_and_lww_losers() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let mut c = TestPeer::new()?;
+    let mut d = TestPeer::new()?;
+    let entity_id = shared_entity(&mut a, &mut b, vec![("title", FieldValue::Text("base".into()))])?;
+    let (create, create_ops) = all_bundles(&a)?.remove(0);
+    c.engine.ingest_bundle(&create, &create_ops)?;
+    d.engine.ingest_bundle(&create, &create_ops)?;
+
+    a.set_field(entity_id, "title", FieldValue::Text("A".into()))?;
+    c.set_field(entity_id, "title", FieldValue::Text("C".into()))?;
+    b.set_field(entity_id, "title", FieldValue::Text("B".into()))?;
+
+    let (bundle, ops) = latest_bundle(&a)?;
+    let opened = b.engine.ingest_bundle(&bundle, &ops)?;
+    assert_eq!(opened.conflicts_opened, 1);
+    assert_eq!(opened.ops_applied + opened.ops_skipped_lww, 1);
+    let a_won = b.engine.get_field(entity_id, "title")? == Some(FieldValue::Text("A".into()));
+    assert_eq!(opened.ops_skipped_lww, usize::from(!a_won));
+
+    let (bundle, ops) = latest_bundle(&c)?;
+    let extended = b.engine.ingest_bundle(&bundle, &ops)?;
+    assert_eq!((extended.conflicts_opened, extended.conflicts_extended), (0, 1));
+
+    b.engine.resolve_conflict(opened.conflicts[0].conflict_id, Some(FieldValue::Text("agreed".into())))?;
+    d.set_field(entity_id, "title", FieldValue::Text("D".into()))?;
+    let (bundle, ops) = latest_bundle(&d)?;
+    let reopened = b.engine.ingest_bundle(&bundle, &ops)?;
+    assert_eq!(reopened.conflicts_reopened, 1);
+    assert_eq!(reopened.conflicts.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn report_counts_newly_drifted_overlay_ops() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let entity_id = shared_entity(&mut a, &mut b, vec![("title", FieldValue::Text("base".into()))])?;
+
+    b.create_overlay("draft")?;
+    b.set_field(entity_id, "title", FieldValue::Text("draft".into()))?;
+
+    a.set_field(entity_id, "title", FieldValue::Text("canonical".into()))?;
+    let (bundle, ops) = latest_bundle(&a)?;
+    assert_eq!(b.engine.ingest_bundle(&bundle, &ops)?.drift_flagged, 1);
+    Ok(())
+}
+
+// ============================================================================
+// Quarantine
+// ============================================================================
+
+fn raw_bundles(from: &TestPeer) -> Result<Vec<RawBundle>, Box<dyn std::error::Error>> {
+    all_bundles(from)?.iter().map(|(bundle, ops)| Ok(RawBundle::encode(bundle, ops)?)).collect()
+}
+
+#[test]
+fn undecodable_bundles_are_quarantined_and_the_rest_ingested() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let mut c = TestPeer::new()?;
+    let broken = a.create_record("Task", vec![("title", FieldValue::Text("a".into()))])?;
+    let fine = b.create_record("Task", vec![("title", FieldValue::Text("b".into()))])?;
+
+    let mut batch = raw_bundles(&a)?;
+    batch[0].operations[0].truncate(3);
+    batch.extend(raw_bundles(&b)?);
+    let report = c.engine.ingest_raw_bundles(&batch)?;
+    assert_eq!((report.bundles_quarantined, report.bundles_applied), (1, 1));
+
+    assert!(c.engine.get_entity(broken)?.is_none());
+    assert_eq!(c.engine.get_field(fine, "title")?, Some(FieldValue::Text("b".into())));
+    let quarantined = c.engine.list_quarantined()?;
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].actor_id, Some(a.actor_id()));
+    assert!(quarantined[0].reason.contains("operation 0"), "{}", quarantined[0].reason);
+
+    // Receiving the same bytes again doesn't add a second entry
+    c.engine.ingest_raw_bundles(&batch[..1])?;
+    assert_eq!(c.engine.list_quarantined()?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn tampered_bundles_stay_quarantined_until_discarded() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("honest".into()))])?;
+
+    let (bundle, mut ops) = all_bundles(&a)?.remove(0);
+    for op in &mut ops {
+        if let OperationPayload::SetField { value, .. } = &mut op.payload {
+            *value = FieldValue::Text("forged".into());
+        }
+    }
+    b.engine.ingest_raw_bundles(&[RawBundle::encode(&bundle, &ops)?])?;
+    assert!(b.engine.get_entity(entity_id)?.is_none());
+
+    let entry = b.engine.list_quarantined()?.remove(0);
+    assert_eq!(entry.bundle_id, Some(bundle.bundle_id));
+    assert!(entry.reason.contains("bad signature"), "{}", entry.reason);
+    assert!(matches!(b.engine.retry_quarantined(entry.quarantine_id), Err(EngineError::InvalidBundle(_))));
+    assert_eq!(b.engine.list_quarantined()?.len(), 1);
+
+    assert!(b.engine.discard_quarantined(entry.quarantine_id)?);
+    assert!(!b.engine.discard_quarantined(entry.quarantine_id)?);
+    assert!(matches!(b.engine.retry_quarantined(entry.quarantine_id), Err(EngineError::QuarantineNotFound(_))));
+    Ok(())
+}
+
+#[test]
+fn retrying_a_quarantined_bundle_ingests_it_once_it_verifies() -> Result<(), Box<dyn std::error::Error>> {
+    use openprod_storage::QuarantineStore;
+
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("later".into()))])?;
+
+    // As left behind by an older build that couldn't decode the payload
+    let raw = raw_bundles(&a)?.remove(0);
+    let quarantine_id = b.engine.storage_mut().insert_quarantined_bundle(&raw, None, None, "unknown payload type")?;
+
+    b.engine.retry_quarantined(quarantine_id)?;
+    assert_eq!(b.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("later".into())));
+    assert!(b.engine.list_quarantined()?.is_empty());
+    Ok(())
+}
+
+// ============================================================================
+// Module Versions
+// ============================================================================
+
+/// A peer whose ops declare `schedule` at `version`, and one record written with it.
+fn schedule_author(version: &str) -> Result<(TestPeer, EntityId), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    a.engine.declare_module("schedule", version);
+    let entity_id = a.create_record("Cue", vec![("at", FieldValue::Integer(90))])?;
+    Ok((a, entity_id))
+}
+
+#[test]
+fn local_ops_carry_declared_module_versions() -> Result<(), Box<dyn std::error::Error>> {
+    let (a, _) = schedule_author("2.1")?;
+    let (_, ops) = latest_bundle(&a)?;
+    assert!(ops.iter().all(|op| op.module_versions.get("schedule").map(String::as_str) == Some("2.1")));
+    Ok(())
+}
+
+#[test]
+fn newer_module_versions_are_ingested_with_a_warning_by_default() -> Result<(), Box<dyn std::error::Error>> {
+    let (a, entity_id) = schedule_author("2.10")?;
+    let (bundle, ops) = latest_bundle(&a)?;
+
+    // 2.10 is newer than 2.9
+    let mut b = TestPeer::new()?;
+    b.engine.declare_module("schedule", "2.9");
+    let report = b.engine.ingest_bundle(&bundle, &ops)?;
+    assert_eq!(report.bundles_applied, 1);
+    assert_eq!(report.module_mismatches.len(), 1);
+    assert_eq!(report.module_mismatches[0].bundle_id, bundle.bundle_id);
+    assert_eq!(report.module_mismatches[0].supported.as_deref(), Some("2.9"));
+    assert!(b.engine.get_entity(entity_id)?.is_some());
+
+    // An undeclared module is a mismatch too
+    let mut c = TestPeer::new()?;
+    assert_eq!(c.engine.ingest_bundle(&bundle, &ops)?.module_mismatches[0].supported, None);
+
+    let mut d = TestPeer::new()?;
+    d.engine.declare_module("schedule", "2.10.0");
+    assert!(d.engine.ingest_bundle(&bundle, &ops)?.module_mismatches.is_empty());
+
+    let mut e = TestPeer::new()?;
+    e.engine.set_module_registry(ModuleRegistry::new(ModulePolicy::Accept));
+    assert!(e.engine.ingest_bundle(&bundle, &ops)?.module_mismatches.is_empty());
+    Ok(())
+}
+
+#[test]
+fn held_bundles_wait_in_quarantine_until_the_module_is_upgraded() -> Result<(), Box<dyn std::error::Error>> {
+    let (a, entity_id) = schedule_author("3")?;
+    let (bundle, ops) = latest_bundle(&a)?;
+
+    let mut b = TestPeer::new()?;
+    let mut modules = ModuleRegistry::new(ModulePolicy::Hold);
+    modules.declare("schedule", "2.4");
+    b.engine.set_module_registry(modules);
+
+    let report = b.engine.ingest_bundle(&bundle, &ops)?;
+    assert_eq!((report.bundles_applied, report.bundles_held), (0, 1));
+    assert!(b.engine.get_entity(entity_id)?.is_none());
+    let entry = b.engine.list_quarantined()?.remove(0);
+    assert_eq!(entry.bundle_id, Some(bundle.bundle_id));
+    assert!(entry.reason.contains("schedule 3 (have 2.4)"), "{}", entry.reason);
+
+    assert!(matches!(b.engine.retry_quarantined(entry.quarantine_id), Err(EngineError::UnsupportedModule(_))));
+    b.engine.declare_module("schedule", "3.0");
+    assert_eq!(b.engine.retry_quarantined(entry.quarantine_id)?.bundles_applied, 1);
+    assert!(b.engine.get_entity(entity_id)?.is_some());
+    assert!(b.engine.list_quarantined()?.is_empty());
+    Ok(())
+}
+
+// ============================================================================
+// Duplicate Ops
+// ============================================================================
+
+#[test]
+fn resent_bundles_are_skipped_and_reported() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    let (create, create_ops) = latest_bundle(&a)?;
+    a.set_field(entity_id, "title", FieldValue::Text("v2".into()))?;
+    let (edit, edit_ops) = latest_bundle(&a)?;
+    b.engine.ingest_bundle(&create, &create_ops)?;
+
+    // The create arrives again along another path, together with the edit
+    let report = b.engine.ingest_bundles(&[(create.clone(), create_ops.clone()), (edit, edit_ops.clone())])?;
+    assert_eq!((report.bundles_applied, report.bundles_already_present), (1, 1));
+    assert_eq!(report.ops_duplicate, create_ops.len());
+    assert_eq!(report.ops_applied, edit_ops.len());
+    assert_eq!(report.entities_created, 0);
+    assert!(report.conflicts.is_empty());
+
+    assert_eq!(b.engine.op_count()?, a.engine.op_count()?);
+    assert_eq!(b.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v2".into())));
+    // Stored whole, so it can be served on
+    create.verify(&b.engine.get_ops_by_bundle(create.bundle_id)?)?;
+    Ok(())
+}
+
+#[test]
+fn ops_of_another_bundle_are_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    a.create_record("Task", vec![("title", FieldValue::Text("t".into()))])?;
+    let (create, create_ops) = latest_bundle(&a)?;
+
+    // A relay re-bundling ops under a fresh id: they still name their own bundle
+    let relayed = Bundle::new_signed(BundleId::new(), a.engine.identity(), create.hlc, BundleType::UserEdit, &create_ops, None)?;
+    assert!(relayed.verify(&create_ops).is_err());
+    // Refused even without signature checks, as it could never be served whole
+    let result = b.engine.ingest_bundle(&relayed, &create_ops);
+    assert!(matches!(result, Err(EngineError::Storage(StorageError::ConstraintViolation(_)))), "{result:?}");
+    assert_eq!(b.engine.op_count()?, 0);
+    Ok(())
+}
+
+#[test]
+fn bad_pending_bundle_is_quarantined_on_release() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    let (create, create_ops) = latest_bundle(&a)?;
+    a.set_field(entity_id, "title", FieldValue::Text("v2".into()))?;
+    let (edit, edit_ops) = latest_bundle(&a)?;
+
+    // Ops claiming another bundle are refused before they are buffered
+    let relayed = Bundle::new_signed(BundleId::new(), a.engine.identity(), edit.hlc, BundleType::UserEdit, &edit_ops, edit.creator_vc.clone())?;
+    let result = b.engine.ingest_bundle(&relayed, &edit_ops);
+    assert!(matches!(result, Err(EngineError::Storage(StorageError::ConstraintViolation(_)))), "{result:?}");
+    assert_eq!(b.engine.pending_count()?, 0);
+
+    // One buffered anyway (e.g. before the check existed) is set aside on release
+    b.engine.storage_mut().insert_pending_bundle(&relayed, &edit_ops)?;
+    let report = b.engine.ingest_bundle(&create, &create_ops)?;
+    assert_eq!((report.bundles_applied, report.bundles_quarantined), (1, 1));
+    assert_eq!(b.engine.pending_count()?, 0);
+    assert_eq!(b.engine.list_quarantined()?.len(), 1);
+    assert_eq!(b.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v1".into())));
+
+    // Later ingest is no longer blocked
+    b.engine.ingest_bundle(&edit, &edit_ops)?;
+    assert_eq!(b.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v2".into())));
+    Ok(())
+}
+
+#[test]
+fn ops_repeated_within_a_bundle_are_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let a = TestPeer::new()?;
+    let mut b = TestPeer::new()?;
+    let (bundle_id, hlc) = (BundleId::new(), Hlc::new(physical_now()?, 0));
+    let payload = OperationPayload::CreateEntity { entity_id: EntityId::new(), initial_table: None };
+    let op = Operation::new_signed(a.engine.identity(), hlc, bundle_id, Default::default(), payload)?;
+    let ops = vec![op.clone(), op];
+    let bundle = Bundle::new_signed(bundle_id, a.engine.identity(), hlc, BundleType::UserEdit, &ops, None)?;
+
+    // Signed over both copies, so keeping one would leave the bundle short
+    bundle.verify(&ops)?;
+    let result = b.engine.ingest_bundle(&bundle, &ops);
+    assert!(matches!(result, Err(EngineError::Storage(StorageError::ConstraintViolation(_)))), "{result:?}");
+    assert_eq!(b.engine.op_count()?, 0);
+    Ok(())
+}
+
+#[test]
+fn memory_storage_skips_resent_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = Engine::new(ActorIdentity::generate(), MemoryStorage::new())?;
+    let entity_id = a.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    let (create, create_ops) = latest_bundle(&a)?;
+    a.set_field(entity_id, "title", FieldValue::Text("v2".into()))?;
+    let (edit, edit_ops) = latest_bundle(&a)?;
+    b.ingest_bundle(&create, &create_ops)?;
+
+    let report = b.ingest_bundles(&[(create.clone(), create_ops.clone()), (edit, edit_ops.clone())])?;
+    assert_eq!((report.ops_applied, report.ops_duplicate), (edit_ops.len(), create_ops.len()));
+    assert_eq!(b.op_count()?, a.engine.op_count()?);
+    assert_eq!(b.get_field(entity_id, "title")?, Some(FieldValue::Text("v2".into())));
+    create.verify(&b.get_ops_by_bundle(create.bundle_id)?)?;
+    Ok(())
+}
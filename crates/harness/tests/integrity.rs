@@ -0,0 +1,242 @@
+use openprod_core::{field_value::FieldValue, identity::ActorIdentity};
+use openprod_engine::Engine;
+use openprod_storage::Divergence;
+use openprod_storage::{MaterializationStore, MaterializeProgress};
+use openprod_harness::TestNetwork;
+use openprod_storage::SqliteStorage;
+
+// ============================================================================
+// Integrity Verification
+// ============================================================================
+
+#[test]
+fn synced_peers_verify_clean() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let task = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    let project = net.peer_mut(b).create_record("Project", vec![])?;
+    net.sync_all()?;
+    let edge = net.peer_mut(a).create_edge("belongs_to", task, project)?;
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("from a".into()))?;
+    net.peer_mut(b).set_field(task, "title", FieldValue::Text("from b".into()))?;
+    net.sync_all()?;
+    net.peer_mut(b).delete_edge(edge)?;
+    net.sync_all()?;
+
+    for peer in [a, b] {
+        let report = net.peer_mut(peer).engine.verify_integrity()?;
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(report.ops_checked as u64, net.peer(peer).engine.op_count()?);
+    }
+    // Verification is read-only
+    assert_eq!(net.peer(a).engine.get_field(task, "title")?, net.peer(b).engine.get_field(task, "title")?);
+    Ok(())
+}
+
+#[test]
+fn tampering_is_reported() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.db");
+    let path_str = path.to_str().unwrap();
+
+    let mut engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open(path_str)?)?;
+    let (task, _) = engine.create_entity_with_fields("Task", vec![("title", FieldValue::Text("honest".into()))])?;
+    engine.create_entity(Some("Note"))?;
+    drop(engine);
+
+    let conn = rusqlite::Connection::open(&path)?;
+    conn.execute("UPDATE oplog SET signature = zeroblob(64) WHERE op_type = 'SetField'", [])?;
+    conn.execute("UPDATE fields SET value = NULL WHERE field_key = 'title'", [])?;
+    conn.execute("PRAGMA foreign_keys = OFF", [])?;
+    conn.execute(
+        "DELETE FROM bundles WHERE bundle_id = (SELECT bundle_id FROM oplog WHERE op_type = 'CreateEntity' ORDER BY hlc DESC LIMIT 1)",
+        [],
+    )?;
+    drop(conn);
+
+    let mut engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open(path_str)?)?;
+    let report = engine.verify_integrity()?;
+    assert!(!report.is_ok());
+    assert_eq!(report.bundles_checked, 1);
+    assert_eq!(report.bundle_errors.len(), 1);
+    assert!(report.bundle_errors[0].1.contains("bad signature"));
+    assert!(report.reference_errors.iter().any(|e| e.contains("operations without a bundle")));
+    assert!(!report.replay_matches);
+    assert_eq!(engine.get_field(task, "title")?, None);
+    Ok(())
+}
+
+// ============================================================================
+// Consistency Check and Repair
+// ============================================================================
+
+#[test]
+fn consistency_check_is_clean_after_sync() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let task = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    net.sync_all()?;
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("from a".into()))?;
+    net.peer_mut(b).set_field(task, "title", FieldValue::Text("from b".into()))?;
+    net.sync_all()?;
+
+    for peer in [a, b] {
+        let report = net.peer_mut(peer).engine.check_consistency()?;
+        assert!(report.is_ok(), "{report:?}");
+        let repair = net.peer_mut(peer).engine.repair()?;
+        assert_eq!((repair.rematerialized, repair.orphans_removed), (0, 0));
+    }
+    Ok(())
+}
+
+#[test]
+fn repair_rematerializes_damaged_rows() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.db");
+    let path_str = path.to_str().unwrap();
+
+    let mut engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open(path_str)?)?;
+    let (task, _) = engine.create_entity_with_fields(
+        "Task",
+        vec![("title", FieldValue::Text("honest".into())), ("notes", FieldValue::Text("kept".into()))],
+    )?;
+    let (other, _) = engine.create_entity_with_fields("Task", vec![("title", FieldValue::Text("other".into()))])?;
+    drop(engine);
+
+    let conn = rusqlite::Connection::open(&path)?;
+    conn.execute("PRAGMA foreign_keys = OFF", [])?;
+    conn.execute("UPDATE fields SET value = NULL WHERE entity_id = ?1 AND field_key = 'title'", [task.as_bytes()])?;
+    conn.execute("DELETE FROM fields WHERE entity_id = ?1 AND field_key = 'notes'", [task.as_bytes()])?;
+    conn.execute("DELETE FROM entities WHERE entity_id = ?1", [other.as_bytes()])?;
+    conn.execute(
+        "INSERT INTO conflict_values (conflict_id, actor_id, hlc, op_id, value) VALUES (zeroblob(16), zeroblob(32), zeroblob(12), zeroblob(16), NULL)",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO overlay_ops (overlay_id, op_id, hlc, payload, op_type) VALUES (zeroblob(16), zeroblob(16), zeroblob(12), x'00', 'SetField')",
+        [],
+    )?;
+    drop(conn);
+
+    let mut engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open(path_str)?)?;
+    let report = engine.check_consistency()?;
+    assert!(!report.is_ok());
+    let orphans: Vec<_> = report.orphans.iter().map(|o| (o.table, o.parent, o.count)).collect();
+    assert_eq!(
+        orphans,
+        vec![
+            ("fields", "entities", 1),
+            ("facets", "entities", 1),
+            ("conflict_values", "conflicts", 1),
+            ("overlay_ops", "overlays", 1),
+        ]
+    );
+    let divergent: Vec<_> = report.divergent.iter().map(|d| (d.table, d.divergence)).collect();
+    assert!(divergent.contains(&("entities", Divergence::Missing)));
+    assert!(divergent.contains(&("fields", Divergence::Changed)));
+    assert!(divergent.contains(&("fields", Divergence::Missing)));
+    let task_hex = task.to_string().replace('-', "");
+    assert!(report.divergent.iter().filter(|d| d.table == "fields").all(|d| d.key.starts_with(&task_hex)));
+    assert_eq!(report.replay_error, None);
+    // Checking changes nothing
+    assert_eq!(engine.get_field(task, "title")?, None);
+
+    let repair = engine.repair()?;
+    assert_eq!(repair.rematerialized, 3);
+    assert_eq!(repair.orphans_removed, 2);
+    assert!(repair.remaining.is_ok(), "{:?}", repair.remaining);
+    assert_eq!(engine.get_field(task, "title")?, Some(FieldValue::Text("honest".into())));
+    assert_eq!(engine.get_field(task, "notes")?, Some(FieldValue::Text("kept".into())));
+    assert_eq!(engine.get_field(other, "title")?, Some(FieldValue::Text("other".into())));
+    assert!(engine.verify_integrity()?.is_ok());
+    Ok(())
+}
+
+// ============================================================================
+// Incremental Materialization
+// ============================================================================
+
+#[test]
+fn tail_replay_matches_full_rebuild() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let task = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    let project = net.peer_mut(a).create_record("Project", vec![("name", FieldValue::Text("p".into()))])?;
+    net.peer_mut(a).engine.attach_facet(task, "Billable")?;
+    net.sync_all()?;
+    assert!(net.peer_mut(a).engine.verify_integrity()?.is_ok());
+    assert!(net.peer(a).engine.storage().materialization_watermark()?.is_some());
+    let ops_before = net.peer(a).engine.op_count()?;
+
+    // Tail: edits and deletes touching rows created before the checkpoint
+    net.peer_mut(b).set_field(task, "title", FieldValue::Text("v2".into()))?;
+    net.peer_mut(b).create_edge("belongs_to", task, project)?;
+    net.peer_mut(b).detach_facet(task, "Billable", true)?;
+    net.peer_mut(b).delete_entity(project)?;
+    net.peer_mut(b).create_record("Task", vec![("title", FieldValue::Text("new".into()))])?;
+    net.sync_all()?;
+
+    let engine = &mut net.peer_mut(a).engine;
+    let expected_tail = engine.op_count()? - ops_before;
+    let mut reports = Vec::new();
+    let replayed = engine.recover_state(&mut |p| reports.push(p))?;
+    assert!(replayed >= expected_tail);
+    assert_eq!(reports.last(), Some(&MaterializeProgress { replayed, total: replayed }));
+    let incremental = engine.storage().materialized_digest()?;
+
+    engine.rebuild_state()?;
+    assert_eq!(engine.storage().materialized_digest()?, incremental);
+    Ok(())
+}
+
+#[test]
+fn recovery_replays_only_since_the_checkpoint() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.db");
+    let path_str = path.to_str().unwrap();
+
+    let mut engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open(path_str)?)?;
+    let (old, _) = engine.create_entity_with_fields("Task", vec![("title", FieldValue::Text("old".into()))])?;
+    assert!(engine.verify_integrity()?.is_ok());
+    let (new, _) = engine.create_entity_with_fields("Task", vec![("title", FieldValue::Text("new".into()))])?;
+    drop(engine);
+
+    let conn = rusqlite::Connection::open(&path)?;
+    conn.execute("UPDATE fields SET value = NULL WHERE field_key = 'title'", [])?;
+    drop(conn);
+
+    let mut engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open(path_str)?)?;
+    assert_eq!(engine.recover_state(&mut |_| {})?, 2);
+    assert_eq!(engine.get_field(new, "title")?, Some(FieldValue::Text("new".into())));
+    // Damage before the checkpoint needs a full rebuild
+    assert_eq!(engine.get_field(old, "title")?, None);
+    engine.rebuild_state()?;
+    assert_eq!(engine.get_field(old, "title")?, Some(FieldValue::Text("old".into())));
+    Ok(())
+}
+
+#[test]
+fn recovery_replays_older_ops_synced_after_the_checkpoint() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let task = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    net.sync_all()?;
+
+    // b's offline edit is stamped before a's checkpoint but arrives after it
+    net.peer_mut(b).set_field(task, "notes", FieldValue::Text("offline".into()))?;
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("v2".into()))?;
+    assert!(net.peer_mut(a).engine.verify_integrity()?.is_ok());
+    net.sync_to(b, a)?;
+
+    let engine = &mut net.peer_mut(a).engine;
+    engine.storage().conn().execute("UPDATE fields SET value = NULL WHERE field_key = 'notes'", [])?;
+    assert!(engine.recover_state(&mut |_| {})? >= 1);
+    assert_eq!(engine.get_field(task, "notes")?, Some(FieldValue::Text("offline".into())));
+    assert_eq!(engine.get_field(task, "title")?, Some(FieldValue::Text("v2".into())));
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use std::sync::{Arc, Mutex};
+
+use openprod_core::{
+    field_value::FieldValue,
+    operations::{BundleMeta, BundleType, OperationPayload},
+};
+use openprod_engine::{CompletedWrite, EngineError, Interceptor, PendingWrite};
+use openprod_harness::{TestNetwork, TestPeer};
+use openprod_storage::Storage;
+
+fn is_rejected(err: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(err.downcast_ref::<EngineError>(), Some(EngineError::Rejected(_)))
+}
+
+/// Refuses negative budgets and trims text values.
+struct BudgetRules;
+
+impl Interceptor for BudgetRules {
+    fn before_execute(&mut self, write: &mut PendingWrite) -> Result<(), EngineError> {
+        for payload in &mut write.payloads {
+            if let OperationPayload::SetField { field_key, value, .. } = payload {
+                match value {
+                    FieldValue::Integer(n) if field_key == "budget" && *n < 0 => {
+                        return Err(EngineError::Rejected(format!("budget {n} is negative")));
+                    }
+                    FieldValue::Text(text) => *text = text.trim().to_string(),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Stamps every bundle with who wrote it.
+struct Audit(&'static str);
+
+impl Interceptor for Audit {
+    fn before_execute(&mut self, write: &mut PendingWrite) -> Result<(), EngineError> {
+        write.meta_mut().attributes.insert("audited_by".into(), self.0.into());
+        Ok(())
+    }
+}
+
+/// Records every completed write.
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<CompletedWrite>>>);
+
+impl Interceptor for Recorder {
+    fn after_execute(&mut self, write: &CompletedWrite) {
+        self.0.lock().unwrap().push(write.clone());
+    }
+}
+
+impl Recorder {
+    fn writes(&self) -> Vec<CompletedWrite> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+// ============================================================================
+// Before Execute
+// ============================================================================
+
+#[test]
+fn interceptor_can_reject_a_write() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    peer.engine.add_interceptor(BudgetRules);
+    let entity_id = peer.create_record("Project", vec![("budget", FieldValue::Integer(100))])?;
+    let ops_before = peer.engine.op_count()?;
+
+    let err = peer.set_field(entity_id, "budget", FieldValue::Integer(-5)).unwrap_err();
+    assert!(is_rejected(err.as_ref()), "{err}");
+    assert_eq!(peer.engine.op_count()?, ops_before);
+    assert_eq!(peer.engine.get_field(entity_id, "budget")?, Some(FieldValue::Integer(100)));
+    Ok(())
+}
+
+#[test]
+fn interceptor_can_rewrite_payloads() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    peer.engine.add_interceptor(BudgetRules);
+    let entity_id = peer.create_record("Project", vec![("name", FieldValue::Text("  Tour  ".into()))])?;
+    assert_eq!(peer.engine.get_field(entity_id, "name")?, Some(FieldValue::Text("Tour".into())));
+
+    // Undo restores the rewritten value, not the one originally passed in
+    peer.set_field(entity_id, "name", FieldValue::Text(" Tour 2026 ".into()))?;
+    peer.engine.undo()?;
+    assert_eq!(peer.engine.get_field(entity_id, "name")?, Some(FieldValue::Text("Tour".into())));
+    Ok(())
+}
+
+#[test]
+fn interceptors_annotate_bundle_meta_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    peer.engine.add_interceptor(Audit("first"));
+    peer.engine.add_interceptor(Audit("second"));
+
+    let (entity_id, _) = peer.engine.create_entity(Some("Task"))?;
+    let payloads = vec![OperationPayload::SetField {
+        entity_id,
+        field_key: "title".into(),
+        value: FieldValue::Text("t".into()),
+    }];
+    let bundle_id = peer.engine.execute_with_meta(BundleType::UserEdit, payloads, BundleMeta::with_label("Import"))?;
+
+    let meta = peer.engine.storage().get_bundle(bundle_id)?.unwrap().decode_meta()?.unwrap();
+    assert_eq!(meta.label.as_deref(), Some("Import"));
+    assert_eq!(meta.attributes.get("audited_by").map(String::as_str), Some("second"));
+    Ok(())
+}
+
+// ============================================================================
+// After Execute
+// ============================================================================
+
+#[test]
+fn after_hook_sees_canonical_and_overlay_writes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let recorder = Recorder::default();
+    peer.engine.add_interceptor(Audit("app"));
+    peer.engine.add_interceptor(recorder.clone());
+
+    let entity_id = peer.create_record("Task", vec![])?;
+    let overlay_id = peer.create_overlay("draft")?;
+    peer.set_field(entity_id, "title", FieldValue::Text("draft".into()))?;
+
+    let writes = recorder.writes();
+    assert_eq!(writes.len(), 2);
+    assert_eq!(writes[0].overlay_id, None);
+    assert!(peer.engine.storage().get_bundle(writes[0].bundle_id)?.is_some());
+    assert!(writes[0].meta.as_ref().is_some_and(|m| m.attributes.contains_key("audited_by")));
+    assert_eq!(writes[1].overlay_id, Some(overlay_id));
+    assert_eq!(writes[1].meta, None);
+    Ok(())
+}
+
+#[test]
+fn ingested_bundles_bypass_interceptors() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let recorder = Recorder::default();
+    net.peer_mut(b).engine.add_interceptor(BudgetRules);
+    net.peer_mut(b).engine.add_interceptor(recorder.clone());
+
+    let entity_id = net.peer_mut(a).create_record("Project", vec![("budget", FieldValue::Integer(-1))])?;
+    net.sync_to(a, b)?;
+    assert_eq!(net.peer(b).engine.get_field(entity_id, "budget")?, Some(FieldValue::Integer(-1)));
+    assert!(recorder.writes().is_empty());
+
+    net.peer_mut(b).engine.clear_interceptors();
+    net.peer_mut(b).set_field(entity_id, "budget", FieldValue::Integer(-2))?;
+    assert!(recorder.writes().is_empty());
+    Ok(())
+}
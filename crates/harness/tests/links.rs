@@ -0,0 +1,114 @@
+use openprod_core::field_value::FieldValue;
+use openprod_harness::{LinkProfile, TestNetwork};
+
+fn network(peers: usize) -> Result<TestNetwork, Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    for _ in 0..peers {
+        net.add_peer()?;
+    }
+    Ok(net)
+}
+
+#[test]
+fn latency_delays_delivery_until_time_advances() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = network(2)?;
+    net.set_link(0, 1, LinkProfile { latency: 3, ..Default::default() });
+    let cue = net.peer_mut(0).create_record("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+
+    net.sync_to(0, 1)?;
+    assert_eq!(net.in_flight(), 1);
+    assert!(net.peer(1).engine.get_entity(cue)?.is_none());
+
+    net.advance(2)?;
+    assert!(net.peer(1).engine.get_entity(cue)?.is_none());
+    net.advance(1)?;
+    assert_eq!(net.in_flight(), 0);
+    assert_eq!(net.peer(1).engine.get_field(cue, "label")?, Some(FieldValue::Text("Q1".into())));
+    Ok(())
+}
+
+#[test]
+fn reordered_bundles_wait_for_their_dependencies() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = network(2)?;
+    net.set_link(0, 1, LinkProfile { jitter: 20, ..Default::default() });
+    let cue = net.peer_mut(0).create_record("Cue", vec![])?;
+    for i in 0..10 {
+        net.peer_mut(0).set_field(cue, "number", FieldValue::Integer(i))?;
+    }
+
+    net.sync_to(0, 1)?;
+    net.flush()?;
+    assert_eq!(net.peer(1).engine.pending_count()?, 0);
+    assert_eq!(net.peer(1).engine.get_field(cue, "number")?, Some(FieldValue::Integer(9)));
+    assert_eq!(net.peer(1).engine.get_vector_clock()?, net.peer(0).engine.get_vector_clock()?);
+    Ok(())
+}
+
+#[test]
+fn duplicated_bundles_are_ingested_once() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = network(2)?;
+    net.set_link(0, 1, LinkProfile { duplicate: 1.0, ..Default::default() });
+    let cue = net.peer_mut(0).create_record("Cue", vec![])?;
+    net.peer_mut(0).set_field(cue, "label", FieldValue::Text("Q1".into()))?;
+
+    let conflicts = net.sync_to(0, 1)?;
+    assert!(conflicts.is_empty());
+    assert_eq!(net.peer(1).engine.get_ops_canonical()?.len(), net.peer(0).engine.get_ops_canonical()?.len());
+    Ok(())
+}
+
+#[test]
+fn sync_all_retries_past_dropped_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = network(3)?;
+    net.set_seed(7);
+    let lossy = LinkProfile { latency: 1, jitter: 2, drop: 0.5, duplicate: 0.2 };
+    for (a, b) in [(0, 1), (1, 2), (0, 2)] {
+        net.set_link(a, b, lossy.clone());
+    }
+    for peer in 0..3 {
+        let cue = net.peer_mut(peer).create_record("Cue", vec![])?;
+        for i in 0..5 {
+            net.peer_mut(peer).set_field(cue, "number", FieldValue::Integer(i))?;
+        }
+    }
+
+    net.sync_all()?;
+    let expected = net.peer(0).engine.get_vector_clock()?;
+    for peer in 1..3 {
+        assert_eq!(net.peer(peer).engine.get_vector_clock()?, expected);
+        assert_eq!(net.peer(peer).engine.pending_count()?, 0);
+    }
+    Ok(())
+}
+
+#[test]
+fn partitioned_peers_diverge_until_healed() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = network(3)?;
+    let cue = net.peer_mut(0).create_record("Cue", vec![])?;
+    net.sync_all()?;
+
+    net.partition(&[0]);
+    assert!(net.is_partitioned(0, 2) && !net.is_partitioned(1, 2));
+    net.peer_mut(0).set_field(cue, "label", FieldValue::Text("isolated".into()))?;
+    net.peer_mut(2).set_field(cue, "notes", FieldValue::Text("majority".into()))?;
+    net.sync_all()?;
+    assert_eq!(net.peer(1).engine.get_field(cue, "label")?, None);
+    assert_eq!(net.peer(1).engine.get_field(cue, "notes")?, Some(FieldValue::Text("majority".into())));
+    assert_eq!(net.peer(0).engine.get_field(cue, "notes")?, None);
+
+    // Bundles already on the wire when the link is cut are lost too
+    net.heal();
+    net.set_link(0, 1, LinkProfile { latency: 5, ..Default::default() });
+    net.sync_to(0, 1)?;
+    net.partition(&[0]);
+    net.flush()?;
+    assert_eq!(net.peer(1).engine.get_field(cue, "label")?, None);
+
+    net.heal();
+    net.sync_all()?;
+    for peer in 0..3 {
+        assert_eq!(net.peer(peer).engine.get_field(cue, "label")?, Some(FieldValue::Text("isolated".into())));
+        assert_eq!(net.peer(peer).engine.get_field(cue, "notes")?, Some(FieldValue::Text("majority".into())));
+    }
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use openprod_core::{capabilities::Capabilities, field_value::FieldValue, ids::ListItemId};
+use openprod_engine::automerge::{marks::{ExpandMark, Mark}, transaction::Transactable, AutoCommit, ObjType, ReadDoc, ScalarValue, ROOT};
+use openprod_engine::{merge_text, EngineError, MergeHunk, TextMerge, TEXT_DOCUMENT_KEY};
+use openprod_harness::TestNetwork;
+
+// ============================================================================
+// merge_text
+// ============================================================================
+
+#[test]
+fn edits_to_different_lines_merge_cleanly() {
+    let base = "intro\nbody\noutro\n";
+    assert_eq!(
+        merge_text(base, &["INTRO\nbody\noutro\n", "intro\nbody\noutro\nappendix\n"]),
+        TextMerge::Clean("INTRO\nbody\noutro\nappendix\n".into())
+    );
+    // Identical edits on both sides are not a conflict
+    assert_eq!(merge_text(base, &["intro\nBODY\noutro\n", "intro\nBODY\noutro\n"]), TextMerge::Clean("intro\nBODY\noutro\n".into()));
+    // A branch that left the text alone takes the other's edit
+    assert_eq!(merge_text(base, &[base, "intro\noutro\n"]), TextMerge::Clean("intro\noutro\n".into()));
+    // No final newline survives the merge
+    assert_eq!(merge_text("a\nb", &["A\nb", "a\nB"]), TextMerge::Clean("A\nB".into()));
+}
+
+#[test]
+fn overlapping_edits_produce_conflict_hunks() {
+    let merge = merge_text("one\ntwo\nthree\n", &["one\n2\nthree\n", "one\nTWO\nthree\n", "ONE\ntwo\nthree\n"]);
+    assert_eq!(
+        merge,
+        TextMerge::Conflicted(vec![
+            MergeHunk::Resolved("ONE\n".into()),
+            MergeHunk::Conflict {
+                base: "two\n".into(),
+                branches: vec!["2\n".into(), "TWO\n".into(), "two\n".into()],
+            },
+            MergeHunk::Resolved("three\n".into()),
+        ])
+    );
+    assert!(!merge.is_clean());
+
+    // Without a shared base, differing texts conflict as a whole
+    assert_eq!(
+        merge_text("", &["left\n", "right\n"]),
+        TextMerge::Conflicted(vec![MergeHunk::Conflict {
+            base: String::new(),
+            branches: vec!["left\n".into(), "right\n".into()],
+        }])
+    );
+}
+
+#[test]
+fn long_rewrites_conflict_as_one_hunk() {
+    let lines = |prefix: &str| (0..3_000).map(|i| format!("{prefix} {i}\n")).collect::<String>();
+    let (base, left, right) = (lines("base"), lines("left"), lines("right"));
+    let text = format!("title\n{base}");
+    let merge = merge_text(&text, &[&format!("title\n{left}"), &format!("title\n{right}")]);
+    assert_eq!(
+        merge,
+        TextMerge::Conflicted(vec![
+            MergeHunk::Resolved("title\n".into()),
+            MergeHunk::Conflict { base, branches: vec![left.clone(), right] },
+        ])
+    );
+    // A branch that left it alone still takes the rewrite
+    assert_eq!(merge_text(&text, &[&text, &format!("title\n{left}")]), TextMerge::Clean(format!("title\n{left}")));
+}
+
+// ============================================================================
+// Engine::suggest_text_merge
+// ============================================================================
+
+#[test]
+fn suggest_text_merge_uses_the_last_shared_value() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let doc = net.peer_mut(a).create_record("Doc", vec![("body", FieldValue::Text("title\nfirst\n".into()))])?;
+    net.peer_mut(a).set_field(doc, "body", FieldValue::Text("title\nfirst\nsecond\nthird\n".into()))?;
+    net.sync_to(a, b)?;
+
+    net.peer_mut(a).set_field(doc, "body", FieldValue::Text("Title\nfirst\nsecond\nthird\n".into()))?;
+    net.peer_mut(b).set_field(doc, "body", FieldValue::Text("title\nfirst\nsecond\nthird\nfourth\n".into()))?;
+    let conflicts = net.sync_to(a, b)?;
+    assert_eq!(conflicts.len(), 1);
+
+    let engine = &mut net.peer_mut(b).engine;
+    let TextMerge::Clean(merged) = engine.suggest_text_merge(conflicts[0].conflict_id)? else {
+        panic!("edits to different lines should merge cleanly");
+    };
+    assert_eq!(merged, "Title\nfirst\nsecond\nthird\nfourth\n");
+    engine.resolve_conflict(conflicts[0].conflict_id, Some(FieldValue::Text(merged.clone())))?;
+    assert_eq!(engine.get_field(doc, "body")?, Some(FieldValue::Text(merged)));
+    Ok(())
+}
+
+#[test]
+fn suggest_text_merge_reports_clashing_lines_and_rejects_other_values() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let doc = net
+        .peer_mut(a)
+        .create_record("Doc", vec![("body", FieldValue::Text("x\ny\n".into())), ("count", FieldValue::Integer(1))])?;
+    net.sync_to(a, b)?;
+
+    net.peer_mut(a).set_field(doc, "body", FieldValue::Text("x\nfrom a\n".into()))?;
+    net.peer_mut(a).set_field(doc, "count", FieldValue::Integer(2))?;
+    net.peer_mut(b).set_field(doc, "body", FieldValue::Text("x\nfrom b\n".into()))?;
+    net.peer_mut(b).set_field(doc, "count", FieldValue::Integer(3))?;
+    let conflicts = net.sync_to(a, b)?;
+    let engine = &net.peer(b).engine;
+
+    let body = conflicts.iter().find(|c| c.field_key == "body").expect("body conflict");
+    let hunks = match engine.suggest_text_merge(body.conflict_id)? {
+        TextMerge::Conflicted(hunks) => hunks,
+        clean => panic!("expected a conflict, got {clean:?}"),
+    };
+    let MergeHunk::Conflict { base, mut branches } = hunks[1].clone() else { panic!("expected a conflict hunk") };
+    assert_eq!(hunks[0], MergeHunk::Resolved("x\n".into()));
+    assert_eq!(base, "y\n");
+    branches.sort();
+    assert_eq!(branches, vec!["from a\n".to_string(), "from b\n".to_string()]);
+
+    let count = conflicts.iter().find(|c| c.field_key == "count").expect("count conflict");
+    let err = engine.suggest_text_merge(count.conflict_id).unwrap_err();
+    assert!(matches!(err, EngineError::NotTextConflict(_)));
+    Ok(())
+}
+
+// ============================================================================
+// Rich Text
+// ============================================================================
+
+#[test]
+fn concurrent_rich_text_edits_merge_on_every_peer() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let doc_id = net.peer_mut(a).create_record("Doc", vec![("title", FieldValue::Text("Notes".into()))])?;
+    assert!(net.peer(a).engine.get_text_document(doc_id, "body")?.is_none());
+
+    let mut alice = AutoCommit::new();
+    let text = alice.put_object(ROOT, TEXT_DOCUMENT_KEY, ObjType::Text)?;
+    alice.splice_text(&text, 0, 0, "hello world")?;
+    net.peer_mut(a).engine.apply_text_update(doc_id, "body", alice.save_incremental())?;
+    net.sync_all()?;
+
+    // Bob starts from the merged document; both then edit concurrently
+    let mut bob = AutoCommit::load(&net.peer(b).engine.get_text_document(doc_id, "body")?.unwrap().save())?;
+    let bob_text = bob.get(ROOT, TEXT_DOCUMENT_KEY)?.unwrap().1;
+    bob.splice_text(&bob_text, 0, 0, "Bob: ")?;
+    net.peer_mut(b).engine.apply_text_update(doc_id, "body", bob.save_incremental())?;
+    alice.mark(&text, Mark::new("bold".into(), true, 6, 11), ExpandMark::None)?;
+    alice.splice_text(&text, 11, 0, "!")?;
+    net.peer_mut(a).engine.apply_text_update(doc_id, "body", alice.save_incremental())?;
+    assert!(net.sync_all()?.is_empty());
+
+    for peer in [a, b] {
+        let merged = net.peer(peer).engine.get_text_document(doc_id, "body")?.unwrap();
+        assert_eq!(merged.text()?, "Bob: hello world!");
+        let marks = merged.marks()?;
+        assert_eq!((marks[0].name.as_str(), &marks[0].value, marks[0].start, marks[0].end), ("bold", &ScalarValue::Boolean(true), 11, 16));
+        assert_eq!(merged.update_count(), 3);
+    }
+    // The document sits beside the record's ordinary fields
+    assert_eq!(net.peer(b).engine.get_field(doc_id, "title")?, Some(FieldValue::Text("Notes".into())));
+    assert_eq!(net.peer(b).engine.get_field(doc_id, "body")?, None);
+    Ok(())
+}
+
+#[test]
+fn rich_text_updates_are_checked_staged_and_parked() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let doc_id = net.peer_mut(a).create_record("Doc", vec![])?;
+    let mut draft = AutoCommit::new();
+    let text = draft.put_object(ROOT, TEXT_DOCUMENT_KEY, ObjType::Text)?;
+    draft.splice_text(&text, 0, 0, "draft")?;
+    let update = draft.save_incremental();
+
+    let engine = &mut net.peer_mut(a).engine;
+    assert!(matches!(engine.apply_text_update(doc_id, "body", Vec::new()), Err(EngineError::InvalidDocument(_))));
+    assert!(matches!(engine.apply_text_update(doc_id, "body", vec![1, 2, 3]), Err(EngineError::InvalidDocument(_))));
+
+    // Updates in an overlay are read through it, and go with it
+    let overlay = engine.create_overlay("draft")?;
+    engine.apply_text_update(doc_id, "body", update.clone())?;
+    assert_eq!(engine.get_text_document(doc_id, "body")?.unwrap().text()?, "draft");
+    engine.discard_overlay(overlay)?;
+    assert!(engine.get_text_document(doc_id, "body")?.is_none());
+
+    // A build that can't merge rich text has the update parked
+    engine.apply_text_update(doc_id, "body", update)?;
+    let older = Capabilities::new(net.peer(b).engine.capabilities().payload_types, []);
+    net.set_capabilities(b, older);
+    net.sync_to(a, b)?;
+    assert_eq!(net.parked(a, b).len(), 1);
+    assert!(net.peer(b).engine.get_text_document(doc_id, "body")?.is_none());
+    Ok(())
+}
+
+// ============================================================================
+// Ordered Lists
+// ============================================================================
+
+fn list_values(net: &TestNetwork, peer: usize, entity_id: openprod_core::ids::EntityId) -> Result<Vec<String>, EngineError> {
+    Ok(net.peer(peer).engine.get_list(entity_id, "items")?.into_iter().map(|item| match item.value {
+        FieldValue::Text(text) => text,
+        other => format!("{other:?}"),
+    }).collect())
+}
+
+#[test]
+fn concurrent_list_edits_converge_without_conflicts() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let list = net.peer_mut(a).create_record("Checklist", vec![])?;
+    let insert = |net: &mut TestNetwork, peer: usize, index: usize, text: &str| -> Result<ListItemId, EngineError> {
+        Ok(net.peer_mut(peer).engine.list_insert(list, "items", index, FieldValue::Text(text.into()))?.0)
+    };
+    insert(&mut net, a, 0, "milk")?;
+    let eggs = insert(&mut net, a, 1, "eggs")?;
+    let bread = insert(&mut net, a, 1, "bread")?;
+    assert_eq!(list_values(&net, a, list)?, vec!["milk", "bread", "eggs"]);
+    net.sync_all()?;
+
+    // Both peers insert at the front at once, and reshuffle the rest
+    net.peer_mut(a).engine.list_move(list, "items", bread, 2)?; // to the end
+    insert(&mut net, a, 0, "coffee")?;
+    net.peer_mut(b).engine.list_remove(list, "items", eggs)?;
+    insert(&mut net, b, 0, "tea")?;
+    insert(&mut net, b, 3, "jam")?;
+    assert!(net.sync_all()?.is_empty());
+    let merged = list_values(&net, a, list)?;
+    assert_eq!(merged, list_values(&net, b, list)?);
+    assert_eq!(merged[2..], ["milk", "jam", "bread"]);
+    assert!(merged[..2].contains(&"tea".to_string()) && merged[..2].contains(&"coffee".to_string()));
+
+    // The concurrent front inserts landed on the same position key; inserting
+    // between them still works, shifting the second behind the new item
+    let items = net.peer(b).engine.get_list(list, "items")?;
+    assert_eq!(items[0].position, items[1].position);
+    insert(&mut net, b, 1, "juice")?;
+    net.sync_all()?;
+    let merged = list_values(&net, a, list)?;
+    assert_eq!(merged[1], "juice");
+    assert_eq!(merged, list_values(&net, b, list)?);
+    assert_eq!(merged.len(), 6);
+    Ok(())
+}
+
+#[test]
+fn list_edits_are_checked_against_the_current_list() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let list = net.peer_mut(a).create_record("Checklist", vec![])?;
+    let engine = &mut net.peer_mut(a).engine;
+    assert!(engine.get_list(list, "items")?.is_empty());
+
+    assert!(matches!(engine.list_insert(list, "items", 1, FieldValue::Integer(1)), Err(EngineError::Rejected(_))));
+    let (item, _) = engine.list_insert(list, "items", 0, FieldValue::Integer(1))?;
+    assert!(matches!(engine.list_move(list, "items", item, 2), Err(EngineError::Rejected(_))));
+    assert!(matches!(engine.list_move(list, "other", item, 0), Err(EngineError::ListItemNotFound(_))));
+    assert!(matches!(engine.list_insert(list, "sys:order", 0, FieldValue::Integer(2)), Err(EngineError::ReservedField(_))));
+
+    engine.list_remove(list, "items", item)?;
+    assert!(matches!(engine.list_remove(list, "items", item), Err(EngineError::ListItemNotFound(_))));
+    assert!(engine.get_list(list, "items")?.is_empty());
+    Ok(())
+}
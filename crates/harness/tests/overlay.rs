@@ -0,0 +1,318 @@
+use openprod_core::{field_value::FieldValue, hlc::Hlc, identity::ActorIdentity, ids::*, operations::BundleType};
+use openprod_engine::{DriftPolicy, Engine, EngineError, FieldChange, OverlaySource};
+use openprod_harness::{TestNetwork, TestPeer};
+use openprod_storage::{OverlayOrigin, OverlayStore, SqliteStorage, Storage};
+
+// ============================================================================
+// Active Overlay Persistence Across Restarts
+// ============================================================================
+
+#[test]
+fn active_overlay_survives_restart() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("workspace.db");
+    let path = path.to_str().unwrap();
+
+    let (entity_id, overlay_id) = {
+        let mut engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open(path)?)?;
+        let (entity_id, _) = engine.create_entity_with_fields(
+            "Task",
+            vec![("title", FieldValue::Text("canonical".into()))],
+        )?;
+        let overlay_id = engine.create_overlay("draft")?;
+        engine.set_field(entity_id, "title", FieldValue::Text("draft".into()))?;
+        (entity_id, overlay_id)
+    };
+
+    // Restart: the overlay is still active and still shadows canonical
+    let mut engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open(path)?)?;
+    assert_eq!(engine.active_overlay(), Some(overlay_id));
+    assert_eq!(engine.get_field(entity_id, "title")?, Some(FieldValue::Text("draft".into())));
+
+    // New writes keep going to the overlay
+    engine.set_field(entity_id, "status", FieldValue::Text("wip".into()))?;
+    assert_eq!(engine.storage().count_overlay_ops(overlay_id)?, 2);
+
+    engine.commit_overlay(overlay_id)?;
+    assert_eq!(engine.active_overlay(), None);
+    assert_eq!(engine.get_field(entity_id, "title")?, Some(FieldValue::Text("draft".into())));
+    Ok(())
+}
+
+#[test]
+fn restart_without_active_overlay_stays_canonical() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("workspace.db");
+    let path = path.to_str().unwrap();
+
+    let overlay_id = {
+        let mut engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open(path)?)?;
+        let overlay_id = engine.create_overlay("draft")?;
+        engine.stash_overlay(overlay_id)?;
+        overlay_id
+    };
+
+    let engine = Engine::new(ActorIdentity::from_seed(1), SqliteStorage::open(path)?)?;
+    assert_eq!(engine.active_overlay(), None);
+    assert_eq!(engine.stashed_overlays()?, vec![(overlay_id, "draft".to_string())]);
+    Ok(())
+}
+
+#[test]
+fn restart_reconciles_multiple_active_overlays() -> Result<(), Box<dyn std::error::Error>> {
+    let mut storage = SqliteStorage::open_in_memory()?;
+    let older = OverlayId::new();
+    let newer = OverlayId::new();
+    storage.insert_overlay(older, "older", "user", "active", &Hlc::new(1000, 0))?;
+    storage.insert_overlay(newer, "newer", "user", "active", &Hlc::new(2000, 0))?;
+
+    let engine = Engine::new(ActorIdentity::from_seed(1), storage)?;
+    assert_eq!(engine.active_overlay(), Some(newer));
+    assert_eq!(engine.stashed_overlays()?, vec![(older, "older".to_string())]);
+    Ok(())
+}
+
+// ============================================================================
+// Drift Merge
+// ============================================================================
+
+#[test]
+fn merge_drift_replaces_the_overlay_value_and_rebases() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let (a, b) = (net.add_peer()?, net.add_peer()?);
+    let task = net.peer_mut(a).create_record("Task", vec![("body", FieldValue::Text("one\ntwo\n".into()))])?;
+    net.sync_to(a, b)?;
+
+    let overlay_id = net.peer_mut(b).create_overlay("draft")?;
+    net.peer_mut(b).set_field(task, "body", FieldValue::Text("ONE\ntwo\n".into()))?;
+    net.peer_mut(b).set_field(task, "status", FieldValue::Text("wip".into()))?;
+    net.peer_mut(a).set_field(task, "body", FieldValue::Text("one\nTWO\n".into()))?;
+    net.sync_to(a, b)?;
+
+    let engine = &mut net.peer_mut(b).engine;
+    assert!(engine.has_unresolved_drift(overlay_id)?);
+    engine.merge_drift(overlay_id, task, "body", Some(FieldValue::Text("ONE\nTWO\n".into())))?;
+    assert!(!engine.has_unresolved_drift(overlay_id)?);
+    assert_eq!(engine.storage().count_overlay_ops(overlay_id)?, 2);
+    assert_eq!(engine.get_field(task, "body")?, Some(FieldValue::Text("ONE\nTWO\n".into())));
+
+    // Only fields the overlay wrote can be merged
+    let err = engine.merge_drift(overlay_id, task, "estimate", None).unwrap_err();
+    assert!(matches!(err, EngineError::OverlayNotFound(_)));
+
+    engine.commit_overlay(overlay_id)?;
+    assert_eq!(engine.get_field(task, "body")?, Some(FieldValue::Text("ONE\nTWO\n".into())));
+    assert_eq!(engine.get_field(task, "status")?, Some(FieldValue::Text("wip".into())));
+    Ok(())
+}
+
+// ============================================================================
+// Drift Policies
+// ============================================================================
+
+#[test]
+fn drift_policies_resolve_drift_without_the_user() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let (a, b) = (net.add_peer()?, net.add_peer()?);
+    let text = |s: &str| FieldValue::Text(s.into());
+    let task = net.peer_mut(a).create_record("Task", vec![("title", text("draft")), ("status", text("todo"))])?;
+    net.sync_to(a, b)?;
+
+    let keep = net.peer_mut(b).create_overlay("keep")?;
+    net.peer_mut(b).engine.set_drift_policy(keep, DriftPolicy::AutoKeepMine)?;
+    net.peer_mut(b).set_field(task, "title", text("mine"))?;
+    let drop = net.peer_mut(b).create_overlay("drop")?;
+    net.peer_mut(b).engine.set_drift_policy(drop, DriftPolicy::AutoUseCanonical)?;
+    net.peer_mut(b).set_field(task, "title", text("scripted"))?;
+    let blocked = net.peer_mut(b).create_overlay("blocked")?;
+    net.peer_mut(b).set_field(task, "title", text("held"))?;
+
+    net.peer_mut(a).set_field(task, "title", text("theirs"))?;
+    net.sync_to(a, b)?;
+    let engine = &mut net.peer_mut(b).engine;
+    assert_eq!(engine.drift_policy(blocked)?, DriftPolicy::Block);
+    assert!(engine.has_unresolved_drift(blocked)?);
+    assert!(!engine.has_unresolved_drift(keep)?);
+    assert!(!engine.has_unresolved_drift(drop)?);
+    assert_eq!(engine.storage().count_overlay_ops(drop)?, 0);
+    assert_eq!(engine.get_field(task, "title")?, Some(text("held")));
+
+    // A policy set later handles drift already flagged
+    engine.set_drift_policy(blocked, DriftPolicy::AutoUseCanonical)?;
+    assert!(!engine.has_unresolved_drift(blocked)?);
+    assert_eq!(engine.get_field(task, "title")?, Some(text("theirs")));
+
+    engine.activate_overlay(keep)?;
+    assert_eq!(engine.get_field(task, "title")?, Some(text("mine")));
+    engine.commit_overlay(keep)?;
+    assert_eq!(engine.get_field(task, "title")?, Some(text("mine")));
+
+    let err = engine.set_drift_policy(OverlayId::new(), DriftPolicy::AutoKeepMine).unwrap_err();
+    assert!(matches!(err, EngineError::OverlayNotFound(_)));
+    Ok(())
+}
+
+#[test]
+fn rebase_if_unchanged_only_resolves_drift_without_a_choice() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let (a, b) = (net.add_peer()?, net.add_peer()?);
+    let text = |s: &str| FieldValue::Text(s.into());
+    let task = net.peer_mut(a).create_record(
+        "Task",
+        vec![("title", text("draft")), ("status", text("todo")), ("owner", text("ann"))],
+    )?;
+    net.sync_to(a, b)?;
+
+    let overlay_id = net.peer_mut(b).create_overlay("import")?;
+    net.peer_mut(b).engine.set_drift_policy(overlay_id, DriftPolicy::AutoRebaseIfUnchanged)?;
+    net.peer_mut(b).set_field(task, "title", text("draft"))?;
+    net.peer_mut(b).set_field(task, "status", text("done"))?;
+    net.peer_mut(b).set_field(task, "owner", text("bo"))?;
+    net.peer_mut(a).set_field(task, "title", text("final"))?;
+    net.peer_mut(a).set_field(task, "status", text("done"))?;
+    net.peer_mut(a).set_field(task, "owner", text("cy"))?;
+    net.sync_to(a, b)?;
+
+    // Untouched title follows canonical, agreeing status is kept, owner waits
+    let engine = &mut net.peer_mut(b).engine;
+    let drift = engine.check_drift(overlay_id)?;
+    assert_eq!(drift.len(), 1);
+    assert_eq!(drift[0].field_key, "owner");
+    assert_eq!(engine.storage().count_overlay_ops(overlay_id)?, 2);
+    assert_eq!(engine.get_field(task, "title")?, Some(text("final")));
+    assert_eq!(engine.get_field(task, "status")?, Some(text("done")));
+    assert_eq!(engine.get_field(task, "owner")?, Some(text("bo")));
+    Ok(())
+}
+
+// ============================================================================
+// Script and Import Overlays
+// ============================================================================
+
+#[test]
+fn script_overlays_record_provenance_and_auto_commit() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let task = peer.create_record("Task", vec![("title", FieldValue::Text("draft".into()))])?;
+    let origin = OverlayOrigin { script_id: Some("triage".into()), run_id: Some("run-7".into()), auto_commit: true };
+    let engine = &mut peer.engine;
+
+    let draft = engine.create_overlay("by hand")?;
+    let run = engine.create_overlay_with_source("triage run", OverlaySource::Script, origin.clone())?;
+    engine.set_field(task, "title", FieldValue::Text("triaged".into()))?;
+    assert_eq!(engine.overlay_source(run)?, OverlaySource::Script);
+    assert_eq!(engine.overlay_origin(run)?, origin);
+    assert_eq!(engine.overlay_origin(draft)?, OverlayOrigin::default());
+    assert_eq!(engine.overlays_by_source(OverlaySource::Script)?, vec![(run, "triage run".to_string())]);
+    assert_eq!(engine.overlays_by_source(OverlaySource::User)?, vec![(draft, "by hand".to_string())]);
+
+    // A successful auto-commit run lands as script output
+    let bundle_id = engine.finish_overlay(run, true)?.expect("auto-commit overlay is committed");
+    assert_eq!(engine.storage().get_bundle(bundle_id)?.unwrap().bundle_type, BundleType::ScriptOutput);
+    assert_eq!(engine.get_field(task, "title")?, Some(FieldValue::Text("triaged".into())));
+    assert!(engine.overlays_by_source(OverlaySource::Script)?.is_empty());
+
+    // A run that staged nothing leaves no overlay behind
+    let empty = engine.create_overlay_with_source("no-op run", OverlaySource::Script, origin)?;
+    assert_eq!(engine.finish_overlay(empty, true)?, None);
+    assert!(engine.overlay_origin(empty).is_err());
+    Ok(())
+}
+
+#[test]
+fn failed_or_manual_runs_are_stashed_for_review() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let task = peer.create_record("Task", vec![("title", FieldValue::Text("draft".into()))])?;
+    let engine = &mut peer.engine;
+
+    let import = engine.create_overlay_with_source("csv", OverlaySource::Import, OverlayOrigin::default())?;
+    engine.set_field(task, "title", FieldValue::Text("imported".into()))?;
+    assert_eq!(engine.finish_overlay(import, true)?, None);
+    assert_eq!(engine.active_overlay(), None);
+    assert_eq!(engine.get_field(task, "title")?, Some(FieldValue::Text("draft".into())));
+    assert_eq!(engine.overlays_by_source(OverlaySource::Import)?, vec![(import, "csv".to_string())]);
+
+    // Reviewed and committed by hand, it is still an import
+    let bundle_id = engine.commit_overlay(import)?;
+    assert_eq!(engine.storage().get_bundle(bundle_id)?.unwrap().bundle_type, BundleType::Import);
+
+    let origin = OverlayOrigin { script_id: Some("cleanup".into()), run_id: None, auto_commit: true };
+    let failed = engine.create_overlay_with_source("cleanup run", OverlaySource::Script, origin)?;
+    engine.set_field(task, "title", FieldValue::Text("half done".into()))?;
+    engine.set_overlay_origin(failed, OverlayOrigin { run_id: Some("run-2".into()), ..engine.overlay_origin(failed)? })?;
+    assert_eq!(engine.finish_overlay(failed, false)?, None);
+    assert_eq!(engine.stashed_overlays()?, vec![(failed, "cleanup run".to_string())]);
+    assert_eq!(engine.overlay_origin(failed)?.run_id.as_deref(), Some("run-2"));
+
+    let err = engine.finish_overlay(OverlayId::new(), true).unwrap_err();
+    assert!(matches!(err, EngineError::OverlayNotFound(_)));
+    Ok(())
+}
+
+// ============================================================================
+// Sandboxed Runs
+// ============================================================================
+
+#[test]
+fn run_sandboxed_previews_writes_until_committed() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let text = |s: &str| FieldValue::Text(s.into());
+    let task = peer.create_record("Task", vec![("title", text("draft")), ("status", text("todo"))])?;
+    let stale = peer.create_record("Task", vec![])?;
+    let engine = &mut peer.engine;
+
+    let (created, diff) = engine.run_sandboxed(|sandbox| {
+        sandbox.set_field(task, "title", text("first pass"))?;
+        sandbox.set_field(task, "title", text("final"))?;
+        sandbox.set_field(task, "status", text("todo"))?;
+        sandbox.delete_entity(stale)?;
+        assert_eq!(sandbox.engine().get_field(task, "title")?, Some(text("final")));
+        sandbox.create_entity_with_fields("Task", vec![("title", text("follow-up"))])
+    })?;
+
+    // Canonical is untouched; the diff shows net changes only
+    assert_eq!(engine.active_overlay(), None);
+    assert_eq!(engine.get_field(task, "title")?, Some(text("draft")));
+    assert!(engine.get_entity(created)?.is_none());
+    assert_eq!(
+        diff.field_changes,
+        vec![
+            FieldChange { entity_id: task, field_key: "title".into(), old_value: Some(text("draft")), new_value: Some(text("final")) },
+            FieldChange { entity_id: created, field_key: "title".into(), old_value: None, new_value: Some(text("follow-up")) },
+        ]
+    );
+    assert_eq!(diff.entities_created, vec![created]);
+    assert_eq!(diff.entities_deleted, vec![stale]);
+    assert_eq!(diff.op_count, 6);
+
+    engine.commit_overlay(diff.overlay_id)?;
+    assert_eq!(engine.get_field(task, "title")?, Some(text("final")));
+    assert!(engine.get_entity(created)?.is_some());
+    Ok(())
+}
+
+#[test]
+fn failed_sandboxed_runs_leave_nothing_behind() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let task = peer.create_record("Task", vec![("title", FieldValue::Text("draft".into()))])?;
+    let draft = peer.create_overlay("draft")?;
+    peer.set_field(task, "title", FieldValue::Text("in draft".into()))?;
+    let engine = &mut peer.engine;
+
+    let err = engine
+        .run_sandboxed(|sandbox| {
+            sandbox.set_field(task, "title", FieldValue::Text("scratch".into()))?;
+            sandbox.set_field(EntityId::new(), "title", FieldValue::Text("nowhere".into()))
+        })
+        .unwrap_err();
+    assert!(matches!(err, EngineError::EntityNotFound(_)));
+    assert_eq!(engine.active_overlay(), Some(draft));
+    assert_eq!(engine.get_field(task, "title")?, Some(FieldValue::Text("in draft".into())));
+    assert!(engine.overlays_by_source(OverlaySource::Script)?.is_empty());
+
+    // A run that writes nothing has an empty diff
+    let ((), diff) = engine.run_sandboxed(|_| Ok(()))?;
+    assert!(diff.is_empty());
+    engine.discard_overlay(diff.overlay_id)?;
+    assert_eq!(engine.active_overlay(), Some(draft));
+    Ok(())
+}
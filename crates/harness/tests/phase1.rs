@@ -4,11 +4,15 @@ use openprod_core::{
     operations::*,
 };
 use openprod_harness::{TestNetwork, TestPeer};
-use openprod_engine::EngineError;
-use openprod_storage::StorageError;
+use openprod_engine::{
+    is_system_field, DuplicateOptions, Engine, EngineBuilder, EngineError, UndoResult, ARCHIVED_FIELD, ICON_FIELD,
+    TITLE_FIELD,
+};
+use openprod_core::identity::ActorIdentity;
+use openprod_storage::{EdgeFilter, EdgeRecord, EngineStorage, MemoryStorage, Storage, StorageError};
 
 // ============================================================================
-// Entity/Field CRUD (7 tests)
+// Entity/Field CRUD (8 tests)
 // ============================================================================
 
 #[test]
@@ -189,6 +193,53 @@ fn query_entities_by_facet() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn duplicate_entity_copies_in_one_bundle() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let project = peer.create_record("Project", vec![])?;
+    let person = peer.create_record("Person", vec![])?;
+    let task = peer.create_record("Task", vec![("title", FieldValue::Text("Hang lights".into()))])?;
+    peer.engine.attach_facet(task, "Billable")?;
+    peer.engine.set_field(task, "project", FieldValue::EntityRef(project))?;
+    peer.engine.set_field(task, "blocked_by", FieldValue::EntityRef(task))?;
+    let assigned = peer.create_edge_with_properties("assigned_to", task, person, vec![("role", FieldValue::EntityRef(task))])?;
+    peer.create_edge("follows", task, task)?;
+    let ops_before = peer.engine.op_count()?;
+
+    // By default: every facet and field, references to the source remapped, no edges
+    let (copy, bundle) = peer.engine.duplicate_entity(task, &DuplicateOptions::new())?;
+    assert_ne!(copy, task);
+    assert_eq!(peer.engine.op_count()?, ops_before + 6);
+    assert_eq!(peer.engine.get_ops_by_bundle(bundle)?.len(), 6);
+    let mut facets: Vec<String> = peer.engine.get_facets(copy)?.into_iter().map(|f| f.facet_type).collect();
+    facets.sort();
+    assert_eq!(facets, vec!["Billable".to_string(), "Task".to_string()]);
+    assert_eq!(peer.engine.get_field(copy, "title")?, Some(FieldValue::Text("Hang lights".into())));
+    assert_eq!(peer.engine.get_field(copy, "project")?, Some(FieldValue::EntityRef(project)));
+    assert_eq!(peer.engine.get_field(copy, "blocked_by")?, Some(FieldValue::EntityRef(copy)));
+    assert!(peer.engine.get_edges_from(copy, &EdgeFilter::all())?.is_empty());
+
+    // Narrowed, with outgoing edges
+    let options = DuplicateOptions::new().facets(["Task"]).fields(["title"]).outgoing_edges(true);
+    let (copy, _) = peer.engine.duplicate_entity(task, &options)?;
+    let facets: Vec<String> = peer.engine.get_facets(copy)?.into_iter().map(|f| f.facet_type).collect();
+    assert_eq!(facets, vec!["Task".to_string()]);
+    assert_eq!(peer.engine.get_fields(copy)?.len(), 1);
+    let mut edges: Vec<(String, EntityId)> =
+        peer.engine.get_edges_from(copy, &EdgeFilter::all())?.into_iter().map(|e| (e.edge_type, e.target_id)).collect();
+    edges.sort();
+    assert_eq!(edges, vec![("assigned_to".to_string(), person), ("follows".to_string(), copy)]);
+    let copied = peer.engine.get_edges_between(copy, person, &EdgeFilter::all())?;
+    assert_ne!(copied[0].edge_id, assigned);
+    assert_eq!(peer.engine.get_edge_property(copied[0].edge_id, "role")?, Some(FieldValue::EntityRef(copy)));
+
+    // One command, one undo step
+    assert!(matches!(peer.engine.undo()?, UndoResult::Applied(_)));
+    assert!(peer.engine.get_entity(copy)?.unwrap().deleted);
+    assert!(matches!(peer.engine.duplicate_entity(copy, &options), Err(EngineError::EntityAlreadyDeleted(_))));
+    Ok(())
+}
+
 // ============================================================================
 // Signatures (2 tests)
 // ============================================================================
@@ -364,7 +415,7 @@ fn operation_count_tracks_correctly() -> Result<(), Box<dyn std::error::Error>>
 }
 
 // ============================================================================
-// Edges (3 tests)
+// Edges (6 tests)
 // ============================================================================
 
 #[test]
@@ -377,7 +428,7 @@ fn create_and_query_edge() -> Result<(), Box<dyn std::error::Error>> {
     let edge_id = peer.create_edge("relates_to", entity_a, entity_b)?;
 
     // Query edges from A
-    let edges_from_a = peer.engine.get_edges_from(entity_a)?;
+    let edges_from_a = peer.engine.get_edges_from(entity_a, &EdgeFilter::all())?;
     assert_eq!(edges_from_a.len(), 1);
     assert_eq!(edges_from_a[0].edge_id, edge_id);
     assert_eq!(edges_from_a[0].source_id, entity_a);
@@ -386,7 +437,7 @@ fn create_and_query_edge() -> Result<(), Box<dyn std::error::Error>> {
     assert!(!edges_from_a[0].deleted);
 
     // Query edges to B
-    let edges_to_b = peer.engine.get_edges_to(entity_b)?;
+    let edges_to_b = peer.engine.get_edges_to(entity_b, &EdgeFilter::all())?;
     assert_eq!(edges_to_b.len(), 1);
     assert_eq!(edges_to_b[0].edge_id, edge_id);
 
@@ -403,7 +454,7 @@ fn delete_edge() -> Result<(), Box<dyn std::error::Error>> {
     let edge_id = peer.create_edge("relates_to", entity_a, entity_b)?;
 
     // Verify edge exists and not deleted
-    let edges = peer.engine.get_edges_from(entity_a)?;
+    let edges = peer.engine.get_edges_from(entity_a, &EdgeFilter::all())?;
     assert_eq!(edges.len(), 1);
     assert!(!edges[0].deleted);
 
@@ -411,7 +462,7 @@ fn delete_edge() -> Result<(), Box<dyn std::error::Error>> {
     peer.delete_edge(edge_id)?;
 
     // Verify edge has deleted=true
-    let edges = peer.engine.get_edges_from(entity_a)?;
+    let edges = peer.engine.get_edges_from(entity_a, &EdgeFilter::all())?;
     assert_eq!(edges.len(), 1);
     assert!(edges[0].deleted);
 
@@ -431,10 +482,10 @@ fn delete_entity_cascades_edges() -> Result<(), Box<dyn std::error::Error>> {
     let edge_ca = peer.create_edge("link", entity_c, entity_a)?;
 
     // Verify edges are alive
-    let from_a = peer.engine.get_edges_from(entity_a)?;
+    let from_a = peer.engine.get_edges_from(entity_a, &EdgeFilter::all())?;
     assert_eq!(from_a.len(), 1);
     assert!(!from_a[0].deleted);
-    let to_a = peer.engine.get_edges_to(entity_a)?;
+    let to_a = peer.engine.get_edges_to(entity_a, &EdgeFilter::all())?;
     assert_eq!(to_a.len(), 1);
     assert!(!to_a[0].deleted);
 
@@ -446,13 +497,13 @@ fn delete_entity_cascades_edges() -> Result<(), Box<dyn std::error::Error>> {
     assert!(entity.deleted);
 
     // Verify edge A->B is soft-deleted
-    let from_a = peer.engine.get_edges_from(entity_a)?;
+    let from_a = peer.engine.get_edges_from(entity_a, &EdgeFilter::all())?;
     assert_eq!(from_a.len(), 1);
     assert_eq!(from_a[0].edge_id, edge_ab);
     assert!(from_a[0].deleted);
 
     // Verify edge C->A is soft-deleted
-    let to_a = peer.engine.get_edges_to(entity_a)?;
+    let to_a = peer.engine.get_edges_to(entity_a, &EdgeFilter::all())?;
     assert_eq!(to_a.len(), 1);
     assert_eq!(to_a[0].edge_id, edge_ca);
     assert!(to_a[0].deleted);
@@ -460,6 +511,459 @@ fn delete_entity_cascades_edges() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn restore_entity_restores_cascaded_edges() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_a = peer.create_record("Test", vec![])?;
+    let entity_b = peer.create_record("Test", vec![])?;
+    let entity_c = peer.create_record("Test", vec![])?;
+    let edge_ab = peer.create_edge("link", entity_a, entity_b)?;
+    let edge_ca = peer.create_edge("link", entity_c, entity_a)?;
+    let stale = peer.create_edge("old_link", entity_a, entity_b)?;
+    peer.delete_edge(stale)?;
+
+    peer.delete_entity(entity_c)?;
+    peer.delete_entity(entity_a)?;
+    let deleted = |peer: &TestPeer, edge_id| -> Result<bool, EngineError> {
+        Ok(peer.engine.get_edge(edge_id)?.unwrap().deleted)
+    };
+
+    // C is still deleted, so C->A stays down; the separately deleted edge is left alone
+    peer.engine.restore_entity(entity_a)?;
+    assert!(!peer.engine.get_entity(entity_a)?.unwrap().deleted);
+    assert!(!deleted(&peer, edge_ab)?);
+    assert!(deleted(&peer, edge_ca)?);
+    assert!(deleted(&peer, stale)?);
+
+    peer.engine.restore_entity(entity_c)?;
+    assert!(!deleted(&peer, edge_ca)?);
+    assert!(matches!(peer.engine.restore_entity(entity_c), Err(EngineError::EntityNotDeleted(_))));
+    assert!(matches!(peer.engine.restore_entity(EntityId::new()), Err(EngineError::EntityNotFound(_))));
+
+    // Undo puts C and its edge back the way they were
+    assert!(matches!(peer.engine.undo()?, UndoResult::Applied(_)));
+    assert!(peer.engine.get_entity(entity_c)?.unwrap().deleted);
+    assert!(deleted(&peer, edge_ca)?);
+    Ok(())
+}
+
+fn ids(edges: Vec<EdgeRecord>) -> Vec<EdgeId> {
+    let mut ids: Vec<EdgeId> = edges.into_iter().map(|e| e.edge_id).collect();
+    ids.sort();
+    ids
+}
+
+fn check_edge_queries<S: EngineStorage>(engine: &mut Engine<S>) -> Result<(), Box<dyn std::error::Error>> {
+    let (a, _) = engine.create_entity(Some("Test"))?;
+    let (b, _) = engine.create_entity(Some("Test"))?;
+    let (c, _) = engine.create_entity(Some("Test"))?;
+    let (feeds_ab, _) = engine.create_edge("feeds", a, b)?;
+    let (powers_ab, _) = engine.create_edge("powers", a, b)?;
+    let (feeds_ba, _) = engine.create_edge("feeds", b, a)?;
+    let (feeds_ac, _) = engine.create_edge("feeds", a, c)?;
+    engine.delete_edge(powers_ab)?;
+
+    let mut from_a = vec![feeds_ab, feeds_ac];
+    from_a.sort();
+    assert_eq!(ids(engine.get_edges_from(a, &EdgeFilter::live())?), from_a);
+    assert!(engine.get_edges_from(a, &EdgeFilter::live().of_type("powers"))?.is_empty());
+    assert_eq!(ids(engine.get_edges_from(a, &EdgeFilter::all().of_type("powers"))?), vec![powers_ab]);
+    assert_eq!(ids(engine.get_edges_to(a, &EdgeFilter::live().of_type("feeds"))?), vec![feeds_ba]);
+
+    // Between is directional
+    assert_eq!(ids(engine.get_edges_between(a, b, &EdgeFilter::live())?), vec![feeds_ab]);
+    let mut all_ab = vec![feeds_ab, powers_ab];
+    all_ab.sort();
+    assert_eq!(ids(engine.get_edges_between(a, b, &EdgeFilter::all())?), all_ab);
+    assert_eq!(ids(engine.get_edges_between(b, a, &EdgeFilter::live())?), vec![feeds_ba]);
+    assert!(engine.get_edges_between(b, c, &EdgeFilter::all())?.is_empty());
+
+    let mut feeds = vec![feeds_ab, feeds_ba, feeds_ac];
+    feeds.sort();
+    assert_eq!(ids(engine.get_edges_by_type("feeds")?), feeds);
+    engine.delete_entity(c)?;
+    feeds.retain(|id| *id != feeds_ac);
+    assert_eq!(ids(engine.get_edges_by_type("feeds")?), feeds);
+    assert!(engine.get_edges_by_type("powers")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn edge_queries_filter_by_type_and_endpoints() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    check_edge_queries(&mut peer.engine)
+}
+
+#[test]
+fn edge_queries_on_memory_storage() -> Result<(), Box<dyn std::error::Error>> {
+    check_edge_queries(&mut Engine::new(ActorIdentity::generate(), MemoryStorage::new())?)
+}
+
+// ============================================================================
+// Unique Edges (5 tests)
+// ============================================================================
+
+#[test]
+fn unique_edge_type_reuses_existing_edge() -> Result<(), Box<dyn std::error::Error>> {
+    let mut engine = EngineBuilder::new().unique_edge_type("assigned_to").open_in_memory(ActorIdentity::generate())?;
+    assert_eq!(engine.unique_edge_types()?, vec!["assigned_to".to_string()]);
+    let (task, _) = engine.create_entity(Some("Task"))?;
+    let (person, _) = engine.create_entity(Some("Person"))?;
+
+    let (edge, created) = engine.create_edge("assigned_to", task, person)?;
+    assert_eq!(engine.create_edge("assigned_to", task, person)?, (edge, created));
+
+    // Properties land on the existing edge in a bundle of their own
+    let (again, bundle) =
+        engine.create_edge_with_properties("assigned_to", task, person, vec![("role", FieldValue::Text("lead".into()))])?;
+    assert_eq!(again, edge);
+    assert_ne!(bundle, created);
+    assert_eq!(engine.get_edge_property(edge, "role")?, Some(FieldValue::Text("lead".into())));
+
+    // A deleted edge is restored rather than duplicated
+    engine.delete_edge(edge)?;
+    let (restored, _) = engine.create_edge("assigned_to", task, person)?;
+    assert_eq!(restored, edge);
+    assert!(!engine.get_edge(edge)?.unwrap().deleted);
+    assert_eq!(ids(engine.get_edges_between(task, person, &EdgeFilter::all())?), vec![edge]);
+
+    // The other direction and other types are unaffected
+    let (reverse, _) = engine.create_edge("assigned_to", person, task)?;
+    assert_ne!(reverse, edge);
+    let (first, _) = engine.create_edge("mentions", task, person)?;
+    let (second, _) = engine.create_edge("mentions", task, person)?;
+    assert_ne!(first, second);
+    Ok(())
+}
+
+/// Each engine creates the same unique edge without seeing the other's, then
+/// they swap bundles: both must keep the earliest created and delete the other.
+/// Only `a` declares the type; `b` learns of it by syncing.
+fn check_concurrent_unique_edges<S: EngineStorage>(
+    mut a: Engine<S>,
+    mut b: Engine<S>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    a.declare_unique_edge_type("assigned_to")?;
+    let (task, _) = a.create_entity(Some("Task"))?;
+    let (person, _) = a.create_entity(Some("Person"))?;
+    b.ingest_bundles(&all_bundles(&a)?)?;
+    assert_eq!(b.unique_edge_types()?, vec!["assigned_to".to_string()]);
+
+    let (edge_a, _) = a.create_edge("assigned_to", task, person)?;
+    let (edge_b, _) = b.create_edge_with_properties("assigned_to", task, person, vec![("role", FieldValue::Text("lead".into()))])?;
+    a.ingest_bundles(&all_bundles(&b)?)?;
+    b.ingest_bundles(&all_bundles(&a)?)?;
+
+    let created = |engine: &Engine<S>, edge: EdgeId| -> Result<_, EngineError> {
+        let record = engine.get_edge(edge)?.unwrap();
+        Ok((record.created_at, record.edge_id))
+    };
+    let (winner, loser) = if created(&a, edge_a)? < created(&a, edge_b)? { (edge_a, edge_b) } else { (edge_b, edge_a) };
+    for engine in [&mut a, &mut b] {
+        let filter = EdgeFilter::live().of_type("assigned_to");
+        assert_eq!(ids(engine.get_edges_between(task, person, &filter)?), vec![winner]);
+        assert!(engine.get_edge(loser)?.unwrap().deleted);
+        engine.rebuild_state()?;
+        assert_eq!(ids(engine.get_edges_between(task, person, &filter)?), vec![winner]);
+    }
+
+    // Later local creates reuse the survivor
+    assert_eq!(a.create_edge("assigned_to", task, person)?.0, winner);
+    Ok(())
+}
+
+#[test]
+fn concurrent_unique_edges_merge_deterministically() -> Result<(), Box<dyn std::error::Error>> {
+    check_concurrent_unique_edges(TestPeer::new()?.engine, TestPeer::new()?.engine)
+}
+
+#[test]
+fn concurrent_unique_edges_merge_on_memory_storage() -> Result<(), Box<dyn std::error::Error>> {
+    check_concurrent_unique_edges(
+        Engine::new(ActorIdentity::generate(), MemoryStorage::new())?,
+        Engine::new(ActorIdentity::generate(), MemoryStorage::new())?,
+    )
+}
+
+/// Duplicates synced before the type is declared are merged once the
+/// declaration reaches each engine, into the same survivor everywhere.
+fn check_late_unique_declaration<S: EngineStorage>(
+    mut a: Engine<S>,
+    mut b: Engine<S>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (task, _) = a.create_entity(Some("Task"))?;
+    let (person, _) = a.create_entity(Some("Person"))?;
+    b.ingest_bundles(&all_bundles(&a)?)?;
+    let (first, _) = a.create_edge("assigned_to", task, person)?;
+    let (second, _) = b.create_edge("assigned_to", task, person)?;
+    a.ingest_bundles(&all_bundles(&b)?)?;
+    b.ingest_bundles(&all_bundles(&a)?)?;
+    let filter = EdgeFilter::live().of_type("assigned_to");
+    assert_eq!(b.get_edges_between(task, person, &filter)?.len(), 2);
+
+    a.declare_unique_edge_type("assigned_to")?;
+    b.ingest_bundles(&all_bundles(&a)?)?;
+    let on_a = ids(a.get_edges_between(task, person, &filter)?);
+    assert_eq!(on_a.len(), 1);
+    assert_eq!(ids(b.get_edges_between(task, person, &filter)?), on_a);
+
+    // With the survivor deleted, only it can come back
+    let (kept, merged) = if on_a[0] == first { (first, second) } else { (second, first) };
+    b.delete_edge(kept)?;
+    assert!(matches!(b.restore_edge(merged), Err(EngineError::Rejected(_))));
+    b.restore_edge(kept)?;
+    assert_eq!(ids(b.get_edges_between(task, person, &filter)?), vec![kept]);
+    Ok(())
+}
+
+#[test]
+fn late_unique_declaration_merges_existing_duplicates() -> Result<(), Box<dyn std::error::Error>> {
+    check_late_unique_declaration(TestPeer::new()?.engine, TestPeer::new()?.engine)
+}
+
+#[test]
+fn late_unique_declaration_merges_on_memory_storage() -> Result<(), Box<dyn std::error::Error>> {
+    check_late_unique_declaration(
+        Engine::new(ActorIdentity::generate(), MemoryStorage::new())?,
+        Engine::new(ActorIdentity::generate(), MemoryStorage::new())?,
+    )
+}
+
+// ============================================================================
+// Trash (2 tests)
+// ============================================================================
+
+fn check_trash_listing<S: EngineStorage>(engine: &mut Engine<S>) -> Result<(), Box<dyn std::error::Error>> {
+    let (older, _) = engine.create_entity(Some("Task"))?;
+    let (task, _) = engine.create_entity(Some("Task"))?;
+    let (person, _) = engine.create_entity(Some("Person"))?;
+    let (edge, _) = engine.create_edge("assigned_to", task, person)?;
+
+    engine.delete_entity(older)?;
+    let since = engine.get_vector_clock()?.entries().values().max().copied();
+    let task_deleted = engine.delete_entity(task)?;
+    engine.delete_entity(person)?;
+
+    let listed = |engine: &Engine<S>, facet_type, since| -> Result<Vec<EntityId>, EngineError> {
+        Ok(engine.list_deleted_entities(facet_type, since)?.into_iter().map(|(e, _)| e.entity_id).collect())
+    };
+    assert_eq!(listed(engine, None, None)?, vec![person, task, older]);
+    assert_eq!(listed(engine, Some("Task"), None)?, vec![task, older]);
+    assert_eq!(listed(engine, None, since)?, vec![person, task]);
+    assert_eq!(listed(engine, Some("Task"), since)?, vec![task]);
+
+    let (record, deletion) = &engine.list_deleted_entities(Some("Task"), since)?[0];
+    assert!(record.deleted);
+    assert_eq!(deletion.deleted_by, engine.actor_id());
+    assert_eq!(deletion.deleted_in_bundle, task_deleted);
+    let edges = engine.list_deleted_edges()?;
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0].0.edge_id, edge);
+    assert_eq!(edges[0].1.deleted_in_bundle, task_deleted);
+
+    // The edge stays in the trash while its other end is still deleted
+    engine.restore_entity(task)?;
+    assert_eq!(listed(engine, Some("Task"), None)?, vec![older]);
+    assert_eq!(engine.list_deleted_edges()?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn trash_lists_deleted_records_newest_first() -> Result<(), Box<dyn std::error::Error>> {
+    check_trash_listing(&mut TestPeer::new()?.engine)
+}
+
+#[test]
+fn trash_listing_on_memory_storage() -> Result<(), Box<dyn std::error::Error>> {
+    check_trash_listing(&mut Engine::new(ActorIdentity::generate(), MemoryStorage::new())?)
+}
+
+// ============================================================================
+// Bulk Commands (2 tests)
+// ============================================================================
+
+#[test]
+fn bulk_commands_write_one_undoable_bundle() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let rows: Vec<EntityId> = (0..3).map(|_| peer.create_record("Row", vec![])).collect::<Result<_, _>>()?;
+    let other = peer.create_record("Note", vec![])?;
+    let link = peer.create_edge("link", rows[0], rows[1])?;
+    let undo_depth = peer.engine.undo_stack()?.len();
+
+    let changes = rows.iter().map(|&row| (row, "status", FieldValue::Text("done".into()))).collect();
+    let bundle_id = peer.engine.set_field_bulk(changes)?;
+    assert_eq!(peer.engine.get_ops_by_bundle(bundle_id)?.len(), 3);
+    assert_eq!(peer.engine.undo_stack()?.len(), undo_depth + 1);
+
+    // Both ends of the edge go in one bundle; the edge is cascaded once
+    peer.engine.delete_by_facet("Row")?.expect("rows to delete");
+    assert!(peer.engine.get_edge(link)?.unwrap().deleted);
+    assert!(!peer.engine.get_entity(other)?.unwrap().deleted);
+    assert_eq!(peer.engine.delete_by_facet("Row")?, None);
+
+    assert!(matches!(peer.engine.undo()?, UndoResult::Applied(_)));
+    for &row in &rows {
+        assert!(!peer.engine.get_entity(row)?.unwrap().deleted);
+    }
+    assert!(!peer.engine.get_edge(link)?.unwrap().deleted);
+    assert!(matches!(peer.engine.undo()?, UndoResult::Applied(_)));
+    for &row in &rows {
+        assert_eq!(peer.engine.get_field(row, "status")?, None);
+    }
+    Ok(())
+}
+
+#[test]
+fn bulk_commands_reject_every_invalid_item() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let live = peer.create_record("Row", vec![])?;
+    let gone = peer.create_record("Row", vec![])?;
+    peer.delete_entity(gone)?;
+    let missing = EntityId::new();
+    let undo_depth = peer.engine.undo_stack()?.len();
+
+    let changes = vec![
+        (live, "status", FieldValue::Integer(1)),
+        (gone, "status", FieldValue::Integer(2)),
+        (missing, "status", FieldValue::Integer(3)),
+    ];
+    let Err(EngineError::BulkValidation(errors)) = peer.engine.set_field_bulk(changes) else {
+        panic!("expected bulk validation error");
+    };
+    assert_eq!(errors.iter().map(|e| e.index).collect::<Vec<_>>(), vec![1, 2]);
+    assert!(matches!(errors[0].error, EngineError::EntityAlreadyDeleted(_)));
+    assert!(matches!(errors[1].error, EngineError::EntityNotFound(_)));
+    assert_eq!(peer.engine.get_field(live, "status")?, None);
+
+    let Err(EngineError::BulkValidation(errors)) = peer.engine.delete_entities(&[missing, live]) else {
+        panic!("expected bulk validation error");
+    };
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].index, 0);
+    assert!(!peer.engine.get_entity(live)?.unwrap().deleted);
+    assert!(matches!(peer.engine.delete_entities(&[]), Err(EngineError::Rejected(_))));
+    assert_eq!(peer.engine.undo_stack()?.len(), undo_depth);
+    Ok(())
+}
+
+// ============================================================================
+// Transactions (2 tests)
+// ============================================================================
+
+#[test]
+fn transaction_writes_its_steps_as_one_bundle() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let show = peer.create_record("Show", vec![])?;
+    let undo_depth = peer.engine.undo_stack()?.len();
+
+    let ((cue, crew), bundle_id) = peer.engine.transaction(|tx| {
+        let cue = tx.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+        tx.set_field(cue, "label", FieldValue::Text("Q1 go".into()))?;
+        assert_eq!(tx.get_field(cue, "label")?, Some(FieldValue::Text("Q1 go".into())));
+        tx.create_edge("belongs_to", cue, show)?;
+        // A failed step is rolled back without abandoning the transaction
+        let crew = tx.create_entity_with_fields("Crew", vec![])?;
+        let step = tx.savepoint(|tx| {
+            tx.create_edge("assigned", crew, cue)?;
+            tx.delete_entity(crew)?;
+            tx.set_field(crew, "name", FieldValue::Text("gone".into()))
+        });
+        assert!(matches!(step, Err(EngineError::EntityAlreadyDeleted(_))));
+        assert_eq!(tx.len(), 5);
+        Ok((cue, crew))
+    })?;
+
+    let bundle_id = bundle_id.expect("a bundle was written");
+    assert_eq!(peer.engine.get_ops_by_bundle(bundle_id)?.len(), 5);
+    assert_eq!(peer.engine.undo_stack()?.len(), undo_depth + 1);
+    assert_eq!(peer.engine.get_field(cue, "label")?, Some(FieldValue::Text("Q1 go".into())));
+    assert!(!peer.engine.get_entity(crew)?.unwrap().deleted);
+    assert_eq!(peer.engine.storage().get_edges_from(crew, &EdgeFilter::all())?.len(), 0);
+
+    assert!(matches!(peer.engine.undo()?, UndoResult::Applied(_)));
+    assert!(peer.engine.get_entity(cue)?.unwrap().deleted);
+    Ok(())
+}
+
+#[test]
+fn failed_transaction_writes_nothing() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let row = peer.create_record("Row", vec![])?;
+    let undo_depth = peer.engine.undo_stack()?.len();
+
+    let err = peer
+        .engine
+        .transaction(|tx| {
+            tx.set_field(row, "status", FieldValue::Text("done".into()))?;
+            tx.delete_entity(row)?;
+            tx.clear_field(row, "status")
+        })
+        .unwrap_err();
+    assert!(matches!(err, EngineError::EntityAlreadyDeleted(_)));
+    let err = peer.engine.transaction(|tx| tx.set_field(EntityId::new(), "status", FieldValue::Integer(1))).unwrap_err();
+    assert!(matches!(err, EngineError::EntityNotFound(_)));
+    assert_eq!(peer.engine.get_field(row, "status")?, None);
+    assert!(!peer.engine.get_entity(row)?.unwrap().deleted);
+    assert_eq!(peer.engine.undo_stack()?.len(), undo_depth);
+
+    let ((), bundle_id) = peer.engine.transaction(|tx| tx.savepoint(|_| Ok(())))?;
+    assert_eq!(bundle_id, None);
+    Ok(())
+}
+
+// ============================================================================
+// Idempotency Keys (2 tests)
+// ============================================================================
+
+#[test]
+fn idempotent_commands_write_once_per_key() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let (cue, created) = peer.engine.idempotent("create").create_entity_with_fields("Cue", vec![])?;
+    let show = peer.create_record("Show", vec![])?;
+    let (edge, linked) = peer.engine.idempotent("link").create_edge("belongs_to", cue, show)?;
+    let set = peer.engine.idempotent("set").set_field(cue, "label", FieldValue::Text("Q1".into()))?;
+    let undo_depth = peer.engine.undo_stack()?.len();
+
+    assert_eq!(peer.engine.idempotent("create").create_entity_with_fields("Cue", vec![])?, (cue, created));
+    assert_eq!(peer.engine.idempotent("link").create_edge("belongs_to", cue, show)?, (edge, linked));
+    assert_eq!(peer.engine.idempotent("set").set_field(cue, "label", FieldValue::Text("Q2".into()))?, set);
+    assert_eq!(peer.engine.get_field(cue, "label")?, Some(FieldValue::Text("Q1".into())));
+    assert_eq!(peer.engine.undo_stack()?.len(), undo_depth);
+    assert_eq!(peer.engine.storage().get_edges_from(cue, &EdgeFilter::all())?.len(), 1);
+
+    // Deleting twice returns the first delete instead of failing
+    let deleted = peer.engine.idempotent("delete").delete_entity(cue)?;
+    assert_eq!(peer.engine.idempotent("delete").delete_entity(cue)?, deleted);
+
+    // A key can't stand in for a different kind of result
+    let err = peer.engine.idempotent("set").create_entity_with_fields("Cue", vec![]).unwrap_err();
+    assert!(matches!(err, EngineError::Rejected(_)));
+    Ok(())
+}
+
+#[test]
+fn idempotency_keys_are_per_actor_and_kept_by_every_backend() -> Result<(), Box<dyn std::error::Error>> {
+    fn exercise<S: EngineStorage>(engine: &mut Engine<S>) -> Result<(), Box<dyn std::error::Error>> {
+        let (task, _) = engine.create_entity_with_fields("Task", vec![])?;
+        let first = engine.idempotent("k").set_field(task, "n", FieldValue::Integer(1))?;
+        // A failed command records nothing, so its retry runs
+        assert!(engine.idempotent("bad").set_field(EntityId::new(), "n", FieldValue::Integer(1)).is_err());
+        assert!(engine.idempotent("bad").original()?.is_none());
+        assert_eq!(engine.idempotent("k").set_field(task, "n", FieldValue::Integer(2))?, first);
+        assert_eq!(engine.get_field(task, "n")?, Some(FieldValue::Integer(1)));
+        Ok(())
+    }
+    exercise(&mut Engine::new(ActorIdentity::generate(), MemoryStorage::new())?)?;
+
+    // Another actor's key, synced in, doesn't count as ours
+    let mut net = TestNetwork::new();
+    let (a, b) = (net.add_peer()?, net.add_peer()?);
+    exercise(&mut net.peer_mut(a).engine)?;
+    net.sync_to(a, b)?;
+    assert!(net.peer_mut(b).engine.idempotent("k").original()?.is_none());
+    Ok(())
+}
+
 // ============================================================================
 // Error Handling (1 test)
 // ============================================================================
@@ -593,6 +1097,350 @@ fn multiple_peers_independent() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+// ============================================================================
+// Storage Backends (4 tests)
+// ============================================================================
+
+/// Written against the trait bound only, as code targeting any backend would be.
+fn rename_equipment<S: EngineStorage>(
+    engine: &mut Engine<S>,
+    name: &str,
+) -> Result<EntityId, EngineError> {
+    let (entity_id, _) = engine.create_entity_with_fields(
+        "Equipment",
+        vec![("name", FieldValue::Text("Spotlight".into()))],
+    )?;
+    engine.set_field(entity_id, "name", FieldValue::Text(name.into()))?;
+    Ok(entity_id)
+}
+
+#[test]
+fn engine_runs_over_generic_storage() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_id = rename_equipment(&mut peer.engine, "Fresnel")?;
+
+    assert_eq!(
+        peer.engine.get_field(entity_id, "name")?,
+        Some(FieldValue::Text("Fresnel".into()))
+    );
+    assert!(matches!(peer.engine.undo()?, UndoResult::Applied(_)));
+    assert_eq!(
+        peer.engine.get_field(entity_id, "name")?,
+        Some(FieldValue::Text("Spotlight".into()))
+    );
+    Ok(())
+}
+
+/// Every stored bundle of `engine` (with ops), in HLC order.
+fn all_bundles<S: EngineStorage>(engine: &Engine<S>) -> Result<Vec<(Bundle, Vec<Operation>)>, EngineError> {
+    let mut bundles = Vec::new();
+    for (bundle, _) in engine.storage().list_bundles_with_labels()? {
+        let operations = engine.get_ops_by_bundle(bundle.bundle_id)?;
+        bundles.push((bundle, operations));
+    }
+    Ok(bundles)
+}
+
+#[test]
+fn engine_runs_over_memory_storage() -> Result<(), Box<dyn std::error::Error>> {
+    let mut engine = Engine::new(ActorIdentity::generate(), MemoryStorage::new())?;
+    let light = rename_equipment(&mut engine, "Fresnel")?;
+    let (stage, _) = engine.create_entity_with_fields("Location", vec![("name", FieldValue::Text("Stage".into()))])?;
+    let (edge, _) = engine.create_edge("located_at", light, stage)?;
+    engine.clear_field(light, "name")?;
+
+    assert_eq!(engine.get_field(light, "name")?, None);
+    assert_eq!(engine.get_edges_from(light, &EdgeFilter::all())?[0].edge_id, edge);
+    assert!(matches!(engine.undo()?, UndoResult::Applied(_)));
+    assert_eq!(engine.get_field(light, "name")?, Some(FieldValue::Text("Fresnel".into())));
+
+    // Overlay writes stay out of canonical state until committed
+    let overlay = engine.create_overlay("draft")?;
+    engine.set_field(light, "name", FieldValue::Text("Par".into()))?;
+    assert_eq!(engine.storage().get_field(light, "name")?, Some(FieldValue::Text("Fresnel".into())));
+    engine.commit_overlay(overlay)?;
+    assert_eq!(engine.get_field(light, "name")?, Some(FieldValue::Text("Par".into())));
+
+    let replayed = engine.rebuild_state()?;
+    assert_eq!(replayed, engine.op_count()?);
+    assert_eq!(engine.get_field(light, "name")?, Some(FieldValue::Text("Par".into())));
+    Ok(())
+}
+
+#[test]
+fn oplog_pages_follow_canonical_order() -> Result<(), Box<dyn std::error::Error>> {
+    fn check<S: EngineStorage>(mut engine: Engine<S>) -> Result<(), Box<dyn std::error::Error>> {
+        for i in 0..7 {
+            engine.create_entity_with_fields("Cue", vec![("label", FieldValue::Integer(i))])?;
+        }
+        let canonical: Vec<_> = engine.get_ops_canonical()?.iter().map(|op| op.op_id).collect();
+
+        let first = engine.get_ops_page(None, 5)?;
+        assert_eq!(first.len(), 5);
+        let last = first.last().unwrap();
+        let rest = engine.get_ops_page(Some((last.hlc, last.op_id)), 1000)?;
+        assert_eq!(first.len() + rest.len(), canonical.len());
+
+        // Page sizes that do and don't divide the oplog evenly
+        for page_size in [1, 3, canonical.len(), 1000] {
+            let paged = engine.ops_canonical_paged(page_size).map(|op| op.map(|op| op.op_id)).collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(paged, canonical, "page size {page_size}");
+        }
+        Ok(())
+    }
+    check(Engine::new(ActorIdentity::generate(), MemoryStorage::new())?)?;
+    check(TestPeer::new()?.engine)
+}
+
+#[test]
+fn memory_storage_materializes_like_sqlite() -> Result<(), Box<dyn std::error::Error>> {
+    let mut alice = TestPeer::new()?;
+    let mut bob = TestPeer::new()?;
+    let mut memory = Engine::new(ActorIdentity::generate(), MemoryStorage::new())?;
+
+    let cue = alice.create_record("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    alice.engine.set_field(cue, "duration", FieldValue::Integer(5))?;
+    bob.engine.ingest_bundles(&all_bundles(&alice.engine)?)?;
+    alice.engine.set_field(cue, "label", FieldValue::Text("Alice".into()))?;
+    bob.engine.set_field(cue, "label", FieldValue::Text("Bob".into()))?;
+
+    memory.ingest_bundles(&all_bundles(&alice.engine)?)?;
+    let conflicts = memory.ingest_bundles(&all_bundles(&bob.engine)?)?.conflicts;
+    bob.engine.ingest_bundles(&all_bundles(&alice.engine)?)?;
+
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(memory.op_count()?, bob.engine.op_count()?);
+    assert_eq!(memory.get_vector_clock()?, bob.engine.get_vector_clock()?);
+    assert_eq!(format!("{:?}", memory.get_fields(cue)?), format!("{:?}", bob.engine.get_fields(cue)?));
+    assert_eq!(memory.get_field_metadata(cue, "label")?, bob.engine.get_field_metadata(cue, "label")?);
+    assert_eq!(memory.get_open_conflicts_for_entity(cue)?[0].values.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn memory_storage_restores_from_saved_state() -> Result<(), Box<dyn std::error::Error>> {
+    let identity = ActorIdentity::generate();
+    let secret = identity.secret_bytes();
+    let mut engine = Engine::new(identity, MemoryStorage::new())?;
+    let mut other = TestPeer::new()?;
+
+    let cue = rename_equipment(&mut engine, "Q1")?;
+    other.engine.ingest_bundles(&all_bundles(&engine)?)?;
+    let saved = engine.storage().bundle_count();
+    engine.set_field(cue, "name", FieldValue::Text("Mine".into()))?;
+    other.engine.set_field(cue, "name", FieldValue::Text("Theirs".into()))?;
+    engine.ingest_bundles(&all_bundles(&other.engine)?)?;
+    let overlay = engine.create_overlay("draft")?;
+
+    // Save incrementally: the first bundles, then the rest
+    let mut bundles = engine.storage().bundles_from(0);
+    bundles.truncate(saved);
+    bundles.extend(engine.storage().bundles_from(saved));
+    let local_state = engine.storage().local_state()?;
+
+    let restored = MemoryStorage::restore(bundles, Some(&local_state))?;
+    let reopened = Engine::new(ActorIdentity::from_secret_bytes(&secret), restored)?;
+    assert_eq!(reopened.op_count()?, engine.op_count()?);
+    assert_eq!(reopened.get_field(cue, "name")?, engine.get_field(cue, "name")?);
+    assert_eq!(reopened.get_open_conflicts_for_entity(cue)?.len(), 1);
+    assert_eq!(reopened.active_overlay(), Some(overlay));
+    Ok(())
+}
+
+// ============================================================================
+// Facet Recovery (2 tests)
+// ============================================================================
+
+fn check_preserved_value_recovery<S: EngineStorage>(engine: &mut Engine<S>) -> Result<(), Box<dyn std::error::Error>> {
+    let (lamp, _) = engine.create_entity_with_fields(
+        "Equipment",
+        vec![("name", FieldValue::Text("Spotlight".into())), ("wattage", FieldValue::Integer(750))],
+    )?;
+    assert_eq!(engine.get_preserved_facet_values(lamp, "Equipment")?, None);
+
+    engine.detach_facet(lamp, "Equipment", true)?;
+    let preserved = vec![
+        ("name".to_string(), FieldValue::Text("Spotlight".into())),
+        ("wattage".to_string(), FieldValue::Integer(750)),
+    ];
+    assert_eq!(engine.get_preserved_facet_values(lamp, "Equipment")?, Some(preserved.clone()));
+
+    // Cleared fields come back; a field edited since keeps its new value
+    engine.clear_field(lamp, "name")?;
+    engine.clear_field(lamp, "wattage")?;
+    engine.set_field(lamp, "wattage", FieldValue::Integer(500))?;
+    let restored = engine.restore_facet(lamp, "Equipment", true)?;
+    assert_eq!(engine.get_ops_by_bundle(restored)?.len(), 2);
+    assert_eq!(engine.get_field(lamp, "name")?, Some(FieldValue::Text("Spotlight".into())));
+    assert_eq!(engine.get_field(lamp, "wattage")?, Some(FieldValue::Integer(500)));
+    assert!(!engine.get_facets(lamp)?[0].detached);
+    assert_eq!(engine.get_preserved_facet_values(lamp, "Equipment")?, None);
+    assert!(matches!(engine.restore_facet(lamp, "Equipment", true), Err(EngineError::Rejected(_))));
+
+    // Undo detaches again with the snapshot intact, and clears the restored field
+    assert!(matches!(engine.undo()?, UndoResult::Applied(_)));
+    assert!(engine.get_facets(lamp)?[0].detached);
+    assert_eq!(engine.get_field(lamp, "name")?, None);
+    assert_eq!(
+        engine.get_preserved_facet_values(lamp, "Equipment")?,
+        Some(vec![preserved[0].clone(), ("wattage".to_string(), FieldValue::Integer(500))]),
+    );
+
+    // Without restore_values only the facet comes back
+    engine.restore_facet(lamp, "Equipment", false)?;
+    assert_eq!(engine.get_field(lamp, "name")?, None);
+    Ok(())
+}
+
+#[test]
+fn restore_facet_recovers_preserved_values() -> Result<(), Box<dyn std::error::Error>> {
+    check_preserved_value_recovery(&mut TestPeer::new()?.engine)
+}
+
+#[test]
+fn preserved_value_recovery_on_memory_storage() -> Result<(), Box<dyn std::error::Error>> {
+    check_preserved_value_recovery(&mut Engine::new(ActorIdentity::generate(), MemoryStorage::new())?)
+}
+
+// ============================================================================
+// Facet Fields (3 tests)
+// ============================================================================
+
+fn check_facet_field_scoping<S: EngineStorage>(engine: &mut Engine<S>) -> Result<(), Box<dyn std::error::Error>> {
+    engine.set_facet_fields("Billable", &["rate", "invoice"])?;
+    assert_eq!(engine.facet_field_keys("Billable")?, vec!["invoice".to_string(), "rate".to_string()]);
+    let (task, _) = engine.create_entity_with_fields(
+        "Task",
+        vec![("title", FieldValue::Text("Hang lights".into())), ("rate", FieldValue::Integer(80))],
+    )?;
+    engine.attach_facet(task, "Billable")?;
+    engine.attach_facet(task, "Scheduled")?;
+    assert_eq!(engine.get_facet_fields(task, "Billable")?, vec![("rate".to_string(), FieldValue::Integer(80))]);
+    assert!(engine.get_facet_fields(task, "Task")?.is_empty());
+
+    // A preserving detach snapshots only the facet's own fields
+    engine.detach_facet(task, "Billable", true)?;
+    assert_eq!(
+        engine.get_preserved_facet_values(task, "Billable")?,
+        Some(vec![("rate".to_string(), FieldValue::Integer(80))]),
+    );
+    engine.restore_facet(task, "Billable", false)?;
+
+    // A plain detach clears them, leaving other fields alone; undo brings them back
+    engine.detach_facet(task, "Billable", false)?;
+    assert_eq!(engine.get_field(task, "rate")?, None);
+    assert_eq!(engine.get_field(task, "title")?, Some(FieldValue::Text("Hang lights".into())));
+    assert!(matches!(engine.undo()?, UndoResult::Applied(_)));
+    assert_eq!(engine.get_field(task, "rate")?, Some(FieldValue::Integer(80)));
+
+    // Undeclared facets clear nothing
+    engine.detach_facet(task, "Scheduled", false)?;
+    assert_eq!(engine.get_fields(task)?.len(), 2);
+
+    engine.set_facet_fields("Billable", &[])?;
+    assert!(engine.facet_field_keys("Billable")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn detach_scopes_to_declared_facet_fields() -> Result<(), Box<dyn std::error::Error>> {
+    check_facet_field_scoping(&mut TestPeer::new()?.engine)
+}
+
+#[test]
+fn facet_field_scoping_on_memory_storage() -> Result<(), Box<dyn std::error::Error>> {
+    check_facet_field_scoping(&mut Engine::new(ActorIdentity::generate(), MemoryStorage::new())?)
+}
+
+#[test]
+fn facet_field_declarations_sync_and_narrow_snapshots_alike() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let task = net.peer_mut(a).create_record(
+        "Task",
+        vec![("title", FieldValue::Text("Hang lights".into())), ("rate", FieldValue::Integer(80))],
+    )?;
+    net.sync_to(a, b)?;
+
+    // b detaches before it learns of the declaration a makes meanwhile
+    net.peer_mut(b).engine.detach_facet(task, "Task", true)?;
+    net.peer_mut(a).engine.set_facet_fields("Task", &["rate"])?;
+    net.sync_to(a, b)?;
+    net.sync_to(b, a)?;
+    for peer in [a, b] {
+        let engine = &net.peer(peer).engine;
+        assert_eq!(engine.facet_field_keys("Task")?, vec!["rate".to_string()]);
+        assert_eq!(
+            engine.get_preserved_facet_values(task, "Task")?,
+            Some(vec![("rate".to_string(), FieldValue::Integer(80))]),
+        );
+    }
+
+    // Dropping the declaration anywhere gives every field back everywhere
+    net.peer_mut(b).engine.set_facet_fields("Task", &[])?;
+    net.sync_to(b, a)?;
+    assert!(net.peer(a).engine.facet_field_keys("Task")?.is_empty());
+    assert_eq!(net.peer(a).engine.get_preserved_facet_values(task, "Task")?.map(|values| values.len()), Some(2));
+    Ok(())
+}
+
+// ============================================================================
+// System Fields (2 tests)
+// ============================================================================
+
+#[test]
+fn system_fields_are_reserved_for_engine_helpers() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let task = peer.create_record("Task", vec![])?;
+    let reserved = |result: Result<BundleId, EngineError>| matches!(result, Err(EngineError::ReservedField(_)));
+    assert!(reserved(peer.engine.set_field(task, TITLE_FIELD, FieldValue::Text("Hang lights".into()))));
+    assert!(reserved(peer.engine.clear_field(task, ARCHIVED_FIELD)));
+    assert!(matches!(
+        peer.engine.create_entity_with_fields("Task", vec![("sys:color", FieldValue::Text("red".into()))]),
+        Err(EngineError::ReservedField(_))
+    ));
+    let Err(EngineError::BulkValidation(errors)) =
+        peer.engine.set_field_bulk(vec![(task, "title", FieldValue::Null), (task, ICON_FIELD, FieldValue::Null)])
+    else {
+        panic!("expected bulk validation error");
+    };
+    assert_eq!(errors[0].index, 1);
+    assert!(peer.engine.get_fields(task)?.is_empty());
+
+    peer.engine.set_title(task, "Hang lights")?;
+    peer.engine.set_icon(task, "bulb")?;
+    peer.engine.set_archived(task, true)?;
+    assert_eq!(peer.engine.title(task)?.as_deref(), Some("Hang lights"));
+    assert_eq!(peer.engine.icon(task)?.as_deref(), Some("bulb"));
+    assert!(peer.engine.is_archived(task)?);
+    peer.engine.set_archived(task, false)?;
+    assert!(!peer.engine.is_archived(task)?);
+    assert_eq!(peer.engine.get_field(task, ARCHIVED_FIELD)?, None);
+
+    peer.engine.set_system_field(task, "sys:color", FieldValue::Text("red".into()))?;
+    assert!(matches!(peer.engine.set_system_field(task, "color", FieldValue::Null), Err(EngineError::Rejected(_))));
+    assert!(is_system_field("sys:color") && !is_system_field("color"));
+    Ok(())
+}
+
+#[test]
+fn system_fields_sync_between_peers() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let task = net.peer_mut(a).create_record("Task", vec![])?;
+    net.peer_mut(a).engine.set_title(task, "Focus")?;
+    net.sync_all()?;
+    assert_eq!(net.peer(b).engine.title(task)?.as_deref(), Some("Focus"));
+
+    // Undo of a helper write goes through like any other edit
+    assert!(matches!(net.peer_mut(a).engine.undo()?, UndoResult::Applied(_)));
+    net.sync_all()?;
+    assert_eq!(net.peer(b).engine.title(task)?, None);
+    Ok(())
+}
+
 // ============================================================================
 // BONUS Tests (2 tests)
 // ============================================================================
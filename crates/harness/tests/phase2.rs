@@ -600,10 +600,10 @@ fn inject_foreign_set_field(
     field_key: &str,
     value: FieldValue,
 ) -> Result<ActorId, Box<dyn std::error::Error>> {
-    let identity_b = ActorIdentity::generate();
+    let identity_b = ActorIdentity::from_seed(1000);
     let actor_b = identity_b.actor_id();
     let storage_b = SqliteStorage::open_in_memory()?;
-    let mut engine_b = Engine::new(identity_b, storage_b);
+    let mut engine_b = Engine::new(identity_b, storage_b)?;
 
     // Create the entity in engine_b so it can set fields on it
     engine_b.execute(
@@ -9,7 +9,7 @@ use openprod_core::{
     vector_clock::VectorClock,
 };
 use openprod_harness::{TestNetwork, TestPeer};
-use openprod_storage::{ConflictRecord, ConflictStatus, ConflictValue, SqliteStorage, Storage};
+use openprod_storage::{ConflictRecord, ConflictStatus, ConflictValue, MaterializationStore, SqliteStorage, Storage};
 
 /// Helper: create a shared entity on peer_a, replicate its creation bundle to peer_b.
 /// Returns the entity_id.
@@ -57,7 +57,7 @@ fn sync_latest_bundle(
         &bundle_ops,
         vc,
     )?;
-    let conflicts = to.engine.ingest_bundle(&bundle, &bundle_ops)?;
+    let conflicts = to.engine.ingest_bundle(&bundle, &bundle_ops)?.conflicts;
     Ok(conflicts)
 }
 
@@ -213,7 +213,7 @@ fn bundle_stores_and_retrieves_creator_vector_clock() -> Result<(), Box<dyn std:
 #[test]
 fn lww_later_set_field_wins_over_earlier() -> Result<(), Box<dyn std::error::Error>> {
     // Test: when ops arrive out of order, LWW picks the latest
-    let identity = ActorIdentity::generate();
+    let identity = ActorIdentity::from_seed(1000);
     let mut storage = SqliteStorage::open_in_memory()?;
 
     let entity_id = EntityId::new();
@@ -259,7 +259,7 @@ fn lww_later_set_field_wins_over_earlier() -> Result<(), Box<dyn std::error::Err
 
 #[test]
 fn lww_clear_field_older_does_not_delete_newer_set() -> Result<(), Box<dyn std::error::Error>> {
-    let identity = ActorIdentity::generate();
+    let identity = ActorIdentity::from_seed(1000);
     let mut storage = SqliteStorage::open_in_memory()?;
 
     let entity_id = EntityId::new();
@@ -305,7 +305,7 @@ fn lww_clear_field_older_does_not_delete_newer_set() -> Result<(), Box<dyn std::
 
 #[test]
 fn lww_set_field_older_does_not_overwrite_newer_clear() -> Result<(), Box<dyn std::error::Error>> {
-    let identity = ActorIdentity::generate();
+    let identity = ActorIdentity::from_seed(1000);
     let mut storage = SqliteStorage::open_in_memory()?;
 
     let entity_id = EntityId::new();
@@ -643,7 +643,7 @@ fn same_actor_no_conflict() -> Result<(), Box<dyn std::error::Error>> {
 fn deterministic_lww_tiebreak() -> Result<(), Box<dyn std::error::Error>> {
     // When two ops have the same HLC, LWW should deterministically pick
     // the one with the larger op_id (byte comparison)
-    let identity = ActorIdentity::generate();
+    let identity = ActorIdentity::from_seed(1000);
     let mut storage = SqliteStorage::open_in_memory()?;
 
     let entity_id = EntityId::new();
@@ -1386,7 +1386,7 @@ fn acknowledge_drift_does_not_corrupt_other_fields() -> Result<(), Box<dyn std::
 fn lww_tiebreak_by_op_id_larger_wins() -> Result<(), Box<dyn std::error::Error>> {
     // When two ops have the exact same HLC, the one with the larger op_id wins.
     // We control this by creating ops and checking which op_id is larger.
-    let identity = ActorIdentity::generate();
+    let identity = ActorIdentity::from_seed(1000);
     let mut storage = SqliteStorage::open_in_memory()?;
 
     let entity_id = EntityId::new();
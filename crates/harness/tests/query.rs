@@ -0,0 +1,234 @@
+use openprod_core::{field_value::FieldValue, identity::ActorIdentity, ids::EntityId};
+use openprod_engine::Engine;
+use openprod_harness::TestPeer;
+use openprod_storage::{Aggregate, EngineStorage, EntityOrder, MemoryStorage};
+
+// ============================================================================
+// Aggregates
+// ============================================================================
+
+/// Three budgets on live Tasks plus values aggregates must skip.
+fn seed_budgets<S: EngineStorage>(engine: &mut Engine<S>) -> Result<(), Box<dyn std::error::Error>> {
+    engine.create_entity_with_fields("Task", vec![("budget", FieldValue::Integer(100))])?;
+    engine.create_entity_with_fields("Task", vec![("budget", FieldValue::Integer(250))])?;
+    engine.create_entity_with_fields("Task", vec![("budget", FieldValue::Integer(-50))])?;
+    engine.create_entity_with_fields("Task", vec![("budget", FieldValue::Text("tbd".into()))])?;
+    engine.create_entity_with_fields("Task", vec![])?;
+    let (deleted, _) = engine.create_entity_with_fields("Task", vec![("budget", FieldValue::Integer(1_000))])?;
+    engine.delete_entity(deleted)?;
+    let (detached, _) = engine.create_entity_with_fields("Task", vec![("budget", FieldValue::Integer(1_000))])?;
+    engine.detach_facet(detached, "Task", false)?;
+    engine.create_entity_with_fields("Project", vec![("budget", FieldValue::Integer(1_000))])?;
+    Ok(())
+}
+
+#[test]
+fn aggregates_skip_non_numbers_and_dead_entities() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    seed_budgets(&mut peer.engine)?;
+    let engine = &peer.engine;
+
+    assert_eq!(engine.count_entities_by_facet()?, vec![("Project".into(), 1), ("Task".into(), 5)]);
+    assert_eq!(engine.aggregate_field("Task", "budget", Aggregate::Sum)?, Some(FieldValue::Integer(300)));
+    assert_eq!(engine.aggregate_field("Task", "budget", Aggregate::Avg)?, Some(FieldValue::Float(100.0)));
+    assert_eq!(engine.aggregate_field("Task", "budget", Aggregate::Min)?, Some(FieldValue::Integer(-50)));
+    assert_eq!(engine.aggregate_field("Task", "budget", Aggregate::Max)?, Some(FieldValue::Integer(250)));
+    assert_eq!(engine.aggregate_field("Task", "missing", Aggregate::Sum)?, None);
+    assert_eq!(engine.aggregate_field("Cue", "budget", Aggregate::Max)?, None);
+
+    // Any float makes the sum a float
+    let (task, _) = peer.engine.create_entity_with_fields("Task", vec![("budget", FieldValue::Float(0.5))])?;
+    assert_eq!(peer.engine.aggregate_field("Task", "budget", Aggregate::Sum)?, Some(FieldValue::Float(300.5)));
+    assert_eq!(peer.engine.aggregate_field("Task", "budget", Aggregate::Min)?, Some(FieldValue::Integer(-50)));
+    peer.engine.set_field(task, "budget", FieldValue::Float(-75.5))?;
+    assert_eq!(peer.engine.aggregate_field("Task", "budget", Aggregate::Min)?, Some(FieldValue::Float(-75.5)));
+    Ok(())
+}
+
+#[test]
+fn memory_storage_aggregates_match_sqlite() -> Result<(), Box<dyn std::error::Error>> {
+    let mut sqlite = TestPeer::new()?;
+    let mut memory = Engine::new(ActorIdentity::generate(), MemoryStorage::new())?;
+    seed_budgets(&mut sqlite.engine)?;
+    seed_budgets(&mut memory)?;
+
+    assert_eq!(memory.count_entities_by_facet()?, sqlite.engine.count_entities_by_facet()?);
+    for aggregate in [Aggregate::Sum, Aggregate::Avg, Aggregate::Min, Aggregate::Max] {
+        assert_eq!(
+            memory.aggregate_field("Task", "budget", aggregate)?,
+            sqlite.engine.aggregate_field("Task", "budget", aggregate)?,
+            "{aggregate:?}"
+        );
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Pages
+// ============================================================================
+
+/// Every entity of `facet_type` in `order`, `page_size` at a time.
+fn all_pages<S: EngineStorage>(
+    engine: &Engine<S>,
+    facet_type: &str,
+    order: &EntityOrder,
+    page_size: usize,
+) -> Result<Vec<EntityId>, Box<dyn std::error::Error>> {
+    let mut entities = Vec::new();
+    let mut after = None;
+    loop {
+        let page = engine.get_entities_page(facet_type, order, after.as_ref(), page_size)?;
+        assert!(page.entities.len() <= page_size);
+        entities.extend(page.entities);
+        match page.next {
+            Some(next) => after = Some(next),
+            None => return Ok(entities),
+        }
+    }
+}
+
+/// Tasks with mixed ranks, returned in creation order.
+fn seed_ranks<S: EngineStorage>(engine: &mut Engine<S>) -> Result<Vec<EntityId>, Box<dyn std::error::Error>> {
+    let mut tasks = Vec::new();
+    for rank in [
+        Some(FieldValue::Integer(3)),
+        Some(FieldValue::Text("b".into())),
+        None,
+        Some(FieldValue::Float(-1.5)),
+        Some(FieldValue::Integer(10)),
+        Some(FieldValue::Text("a".into())),
+        Some(FieldValue::Integer(3)),
+    ] {
+        let (task, _) = engine.create_entity_with_fields("Task", rank.map(|r| ("rank", r)).into_iter().collect())?;
+        tasks.push(task);
+    }
+    let (deleted, _) = engine.create_entity_with_fields("Task", vec![("rank", FieldValue::Integer(0))])?;
+    engine.delete_entity(deleted)?;
+    engine.create_entity_with_fields("Project", vec![("rank", FieldValue::Integer(0))])?;
+    Ok(tasks)
+}
+
+#[test]
+fn pages_walk_a_facet_in_creation_order() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let tasks = seed_ranks(&mut peer.engine)?;
+
+    for page_size in [1, 2, 3, 7, 100] {
+        assert_eq!(all_pages(&peer.engine, "Task", &EntityOrder::created_at(), page_size)?, tasks);
+    }
+    let newest_first: Vec<_> = tasks.iter().rev().copied().collect();
+    assert_eq!(all_pages(&peer.engine, "Task", &EntityOrder::created_at().descending(), 3)?, newest_first);
+
+    let page = peer.engine.get_entities_page("Task", &EntityOrder::created_at(), None, 7)?;
+    assert_eq!(page.entities, tasks);
+    assert_eq!(page.next, None);
+    assert!(peer.engine.get_entities_page("Cue", &EntityOrder::created_at(), None, 10)?.entities.is_empty());
+    Ok(())
+}
+
+#[test]
+fn pages_order_by_field_value() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let t = seed_ranks(&mut peer.engine)?;
+
+    // Missing first, then numbers, then text; equal values by entity id
+    let tied = if t[0] < t[6] { [t[0], t[6]] } else { [t[6], t[0]] };
+    let expected = vec![t[2], t[3], tied[0], tied[1], t[4], t[5], t[1]];
+    assert_eq!(all_pages(&peer.engine, "Task", &EntityOrder::field("rank"), 2)?, expected);
+    let reversed: Vec<_> = expected.iter().rev().copied().collect();
+    assert_eq!(all_pages(&peer.engine, "Task", &EntityOrder::field("rank").descending(), 2)?, reversed);
+
+    // An edit between pages moves the entity; the cursor keeps its place
+    let first = peer.engine.get_entities_page("Task", &EntityOrder::field("rank"), None, 3)?;
+    assert_eq!(first.entities, expected[..3]);
+    peer.engine.set_field(t[1], "rank", FieldValue::Integer(-100))?;
+    let rest = peer.engine.get_entities_page("Task", &EntityOrder::field("rank"), first.next.as_ref(), 10)?;
+    assert_eq!(rest.entities, vec![tied[1], t[4], t[5]]);
+    Ok(())
+}
+
+#[test]
+fn memory_storage_pages_match_sqlite() -> Result<(), Box<dyn std::error::Error>> {
+    let mut sqlite = TestPeer::new()?;
+    let mut memory = Engine::new(ActorIdentity::generate(), MemoryStorage::new())?;
+    seed_ranks(&mut sqlite.engine)?;
+    seed_ranks(&mut memory)?;
+
+    for order in [EntityOrder::field("rank"), EntityOrder::field("rank").descending()] {
+        let sqlite_ranks: Vec<_> = all_pages(&sqlite.engine, "Task", &order, 2)?
+            .into_iter()
+            .map(|id| sqlite.engine.get_field(id, "rank"))
+            .collect::<Result<_, _>>()?;
+        let memory_ranks: Vec<_> = all_pages(&memory, "Task", &order, 2)?
+            .into_iter()
+            .map(|id| memory.get_field(id, "rank"))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(memory_ranks, sqlite_ranks, "{order:?}");
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Projections
+// ============================================================================
+
+#[test]
+fn projections_return_selected_fields_in_key_order() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let (a, _) = peer.engine.create_entity_with_fields(
+        "Task",
+        vec![("title", FieldValue::Text("Hang".into())), ("budget", FieldValue::Integer(5)), ("notes", FieldValue::Text("x".into()))],
+    )?;
+    let (b, _) = peer.engine.create_entity_with_fields("Task", vec![("budget", FieldValue::Integer(7))])?;
+    let (c, _) = peer.engine.create_entity_with_fields("Task", vec![])?;
+    let (deleted, _) = peer.engine.create_entity_with_fields("Task", vec![("budget", FieldValue::Integer(1))])?;
+    peer.engine.delete_entity(deleted)?;
+    peer.engine.create_entity_with_fields("Project", vec![("budget", FieldValue::Integer(1))])?;
+
+    let mut expected = vec![
+        (a, vec![("budget".to_string(), FieldValue::Integer(5)), ("title".to_string(), FieldValue::Text("Hang".into()))]),
+        (b, vec![("budget".to_string(), FieldValue::Integer(7))]),
+        (c, vec![]),
+    ];
+    expected.sort_by_key(|(id, _)| *id);
+    assert_eq!(peer.engine.get_entities_with_fields("Task", &["budget", "title"])?, expected);
+    for (entity_id, fields) in peer.engine.get_entities_with_fields("Task", &["title", "budget", "notes"])? {
+        let mut all = peer.engine.get_fields(entity_id)?;
+        all.sort_by_key(|(key, _)| ["title", "budget", "notes"].iter().position(|k| k == key));
+        assert_eq!(fields, all);
+    }
+    assert!(peer.engine.get_entities_with_fields("Task", &[])?.iter().all(|(_, fields)| fields.is_empty()));
+
+    let memory = {
+        let mut engine = Engine::new(ActorIdentity::generate(), MemoryStorage::new())?;
+        let (a, _) = engine.create_entity_with_fields("Task", vec![("budget", FieldValue::Integer(5)), ("title", FieldValue::Text("Hang".into()))])?;
+        engine.get_entities_with_fields("Task", &["title", "budget"])?.into_iter().find(|(id, _)| *id == a).map(|(_, f)| f)
+    };
+    assert_eq!(memory, Some(vec![("title".into(), FieldValue::Text("Hang".into())), ("budget".into(), FieldValue::Integer(5))]));
+    Ok(())
+}
+
+#[test]
+fn projections_read_through_the_active_overlay() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let (task, _) = peer.engine.create_entity_with_fields(
+        "Task",
+        vec![("title", FieldValue::Text("Hang".into())), ("budget", FieldValue::Integer(5))],
+    )?;
+
+    let draft = peer.create_overlay("draft")?;
+    peer.engine.set_field(task, "budget", FieldValue::Integer(9))?;
+    peer.engine.clear_field(task, "title")?;
+    peer.engine.set_field(task, "notes", FieldValue::Text("x".into()))?;
+    assert_eq!(
+        peer.engine.get_entities_with_fields("Task", &["title", "budget"])?,
+        vec![(task, vec![("budget".into(), FieldValue::Integer(9))])]
+    );
+
+    peer.engine.stash_overlay(draft)?;
+    assert_eq!(
+        peer.engine.get_entities_with_fields("Task", &["title", "budget"])?,
+        vec![(task, vec![("title".into(), FieldValue::Text("Hang".into())), ("budget".into(), FieldValue::Integer(5))])]
+    );
+    Ok(())
+}
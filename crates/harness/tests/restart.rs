@@ -0,0 +1,113 @@
+use openprod_core::field_value::FieldValue;
+use openprod_engine::UndoResult;
+use openprod_harness::{TestNetwork, TestPeer};
+use openprod_storage::{LabelStore, Storage};
+
+fn text(s: &str) -> FieldValue {
+    FieldValue::Text(s.into())
+}
+
+#[test]
+fn reopening_from_a_save_loses_later_writes() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let saved = dir.path().join("saved.db");
+    let mut peer = TestPeer::new()?;
+    let actor_id = peer.actor_id();
+    let cue = peer.create_record("Cue", vec![("label", text("Q1"))])?;
+    peer.save_to(&saved)?;
+    peer.set_field(cue, "label", text("unsaved"))?;
+
+    let peer = peer.reopen(&saved)?;
+    assert_eq!(peer.actor_id(), actor_id);
+    assert_eq!(peer.engine.get_field(cue, "label")?, Some(text("Q1")));
+    Ok(())
+}
+
+#[test]
+fn disk_backed_peers_keep_every_committed_write() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("peer.db");
+    let mut peer = TestPeer::on_disk(&path)?;
+    let cue = peer.create_record("Cue", vec![("label", text("Q1"))])?;
+
+    let peer = peer.reopen(&path)?;
+    assert_eq!(peer.engine.get_field(cue, "label")?, Some(text("Q1")));
+    Ok(())
+}
+
+#[test]
+fn undo_history_is_per_session() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let cue = net.peer_mut(a).create_record("Cue", vec![("label", text("Q1"))])?;
+    net.peer_mut(a).set_field(cue, "label", text("Q2"))?;
+    assert_eq!(net.peer(a).engine.undo_stack()?.len(), 2);
+
+    // The undo stack lives in memory; the edits it covered are durable
+    net.restart_peer(a)?;
+    assert!(net.peer(a).engine.undo_stack()?.is_empty());
+    assert!(matches!(net.peer_mut(a).engine.undo()?, UndoResult::Empty));
+    assert_eq!(net.peer(a).engine.get_field(cue, "label")?, Some(text("Q2")));
+
+    // Edits after a restart are undoable, and a second restart reopens the first one's database
+    net.peer_mut(a).set_field(cue, "label", text("Q3"))?;
+    assert!(matches!(net.peer_mut(a).engine.undo()?, UndoResult::Applied(_)));
+    net.restart_peer(a)?;
+    assert_eq!(net.peer(a).engine.get_field(cue, "label")?, Some(text("Q2")));
+    Ok(())
+}
+
+#[test]
+fn active_overlay_survives_restart() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let cue = net.peer_mut(a).create_record("Cue", vec![("label", text("Q1"))])?;
+    let overlay_id = net.peer_mut(a).create_overlay("draft")?;
+    net.peer_mut(a).set_field(cue, "label", text("draft"))?;
+
+    net.restart_peer(a)?;
+    assert_eq!(net.peer(a).engine.active_overlay(), Some(overlay_id));
+    assert_eq!(net.peer(a).engine.get_field(cue, "label")?, Some(text("draft")));
+    net.peer_mut(a).commit_overlay(overlay_id)?;
+    assert_eq!(net.peer(a).engine.get_field(cue, "label")?, Some(text("draft")));
+    Ok(())
+}
+
+#[test]
+fn pending_bundles_survive_restart() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let cue = net.peer_mut(a).create_record("Cue", vec![])?;
+    net.peer_mut(a).set_field(cue, "label", text("Q1"))?;
+
+    // Deliver only the edit; it waits for the create it depends on
+    let ops = net.peer(a).engine.get_ops_canonical()?;
+    let edit = ops.last().ok_or("no ops")?.bundle_id;
+    let bundle = net.peer(a).engine.storage().get_bundle(edit)?.ok_or("missing bundle")?;
+    let batch = vec![(bundle, net.peer(a).engine.get_ops_by_bundle(edit)?)];
+    net.peer_mut(b).engine.ingest_bundles(&batch)?;
+    assert_eq!(net.peer(b).engine.pending_count()?, 1);
+
+    net.restart_peer(b)?;
+    assert_eq!(net.peer(b).engine.pending_count()?, 1);
+    net.sync_to(a, b)?;
+    assert_eq!(net.peer(b).engine.pending_count()?, 0);
+    assert_eq!(net.peer(b).engine.get_field(cue, "label")?, Some(text("Q1")));
+    Ok(())
+}
+
+#[test]
+fn retried_commands_return_the_original_bundle_after_a_crash() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("peer.db");
+    let mut peer = TestPeer::on_disk(&path)?;
+    let (cue, bundle_id) = peer.engine.idempotent("req-1").create_entity_with_fields("Cue", vec![("label", text("Q1"))])?;
+
+    // The app died before it saw the result, and retries
+    let mut peer = peer.reopen(&path)?;
+    let bundles = peer.engine.storage().list_bundles_with_labels()?.len();
+    assert_eq!(peer.engine.idempotent("req-1").create_entity_with_fields("Cue", vec![("label", text("Q1"))])?, (cue, bundle_id));
+    assert_eq!(peer.engine.storage().list_bundles_with_labels()?.len(), bundles);
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use std::path::Path;
+
+use openprod_core::field_value::FieldValue;
+use openprod_harness::script::{Script, ScriptValue, Step};
+
+#[test]
+fn scripted_scenarios_pass() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let mut ran = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            let script = Script::from_toml(&std::fs::read_to_string(&path)?)?;
+            script.run().map_err(|e| format!("{}: {e}", path.display()))?;
+            ran += 1;
+        }
+    }
+    assert!(ran >= 3);
+    Ok(())
+}
+
+fn set(peer: &str, value: &str) -> Step {
+    Step::Set { peer: peer.into(), entity: "q1".into(), field: "label".into(), value: ScriptValue::Text(value.into()) }
+}
+
+#[test]
+fn built_scripts_round_trip_through_toml() -> Result<(), Box<dyn std::error::Error>> {
+    let script = Script::new(&["alice", "bob"])
+        .step(Step::Create { peer: "alice".into(), facet: "Cue".into(), name: "q1".into(), fields: Default::default() })
+        .step(set("alice", "Q1"))
+        .step(Step::SyncAll)
+        .step(Step::Undo { peer: "alice".into() })
+        .step(Step::SyncAll)
+        .step(Step::ExpectConverged);
+
+    let parsed = Script::from_toml(&script.to_toml()?)?;
+    assert_eq!(parsed.steps.len(), script.steps.len());
+    let run = parsed.run()?;
+    let bob = run.peer("bob")?;
+    assert_eq!(run.network.peer(bob).engine.get_field(run.entity("q1")?, "label")?, None);
+    Ok(())
+}
+
+#[test]
+fn failed_expectations_name_the_step() -> Result<(), Box<dyn std::error::Error>> {
+    let script = Script::new(&["alice", "bob"])
+        .step(Step::Create { peer: "alice".into(), facet: "Cue".into(), name: "q1".into(), fields: Default::default() })
+        .step(set("alice", "Q1"))
+        .step(Step::Sync { from: "alice".into(), to: "bob".into() })
+        .step(Step::ExpectField {
+            peer: "bob".into(),
+            entity: "q1".into(),
+            field: "label".into(),
+            value: Some(ScriptValue::Text("Q2".into())),
+        });
+    let error = script.run().err().ok_or("expectation should fail")?;
+    assert_eq!(error.step, Some(4));
+    assert!(error.to_string().contains(&format!("{:?}", Some(FieldValue::Text("Q1".into())))), "{error}");
+
+    let unknown = Script::new(&["alice"]).step(set("alice", "Q1")).run().err().ok_or("unknown entity")?;
+    assert_eq!(unknown.step, Some(1));
+    assert!(unknown.message.contains("no entity named \"q1\""));
+
+    assert!(Script::from_toml("peers = [\"a\"]\n[[step]]\ndo = \"teleport\"\n").is_err());
+    Ok(())
+}
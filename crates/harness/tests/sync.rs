@@ -0,0 +1,682 @@
+use openprod_core::{
+    capabilities::{Capabilities, FORMAT_VERSION},
+    field_value::FieldValue,
+    identity::ActorIdentity,
+    ids::EntityId,
+    operations::{BundleType, CrdtType, OperationPayload},
+    presence::{CursorPosition, OnlineStatus, PresenceState},
+    sync_scope::SyncScope,
+};
+use openprod_engine::{
+    EngineBuilder, EngineError, PresenceTracker, Role, TrustPolicy, UndoResult, DEFAULT_PRESENCE_TIMEOUT,
+    KEY_ROTATION_FACET,
+};
+use openprod_harness::{TestNetwork, TestPeer};
+use openprod_storage::{EdgeFilter, TrustState};
+
+// ============================================================================
+// Capability Negotiation
+// ============================================================================
+
+#[test]
+fn same_version_peers_are_fully_compatible() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+
+    let caps = net.peer(a).engine.capabilities();
+    assert_eq!(caps.format_version, FORMAT_VERSION);
+    assert!(caps.payload_types.contains("SetField"));
+    // CRDT ops are accepted only for types this build can merge
+    assert!(caps.payload_types.contains("ApplyCrdt"));
+    assert_eq!(caps.crdt_types.into_iter().collect::<Vec<_>>(), vec![CrdtType::List, CrdtType::RichText]);
+
+    let report = net.peer(a).engine.negotiate(&net.peer(b).engine.capabilities())?;
+    assert!(report.is_fully_compatible());
+    Ok(())
+}
+
+#[test]
+fn unsupported_ops_are_parked_until_peer_upgrades() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+
+    // b runs an older build that doesn't know about edges
+    let full = net.peer(b).engine.capabilities();
+    let mut old = full.clone();
+    old.payload_types.remove("CreateEdge");
+    net.set_capabilities(b, old);
+
+    let x = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("x".into()))])?;
+    let y = net.peer_mut(a).create_record("Task", vec![])?;
+    let edge_id = net.peer_mut(a).create_edge("blocks", x, y)?;
+    net.peer_mut(a).set_field(y, "title", FieldValue::Text("after".into()))?;
+
+    let report = net.compatibility(a, b);
+    assert!(report.can_sync());
+    assert_eq!(report.payload_types_missing_remotely, vec!["CreateEdge".to_string()]);
+
+    net.sync_to(a, b)?;
+    assert_eq!(net.parked(a, b).len(), 1);
+    assert!(net.peer(b).engine.get_edge(edge_id)?.is_none());
+    // Bundles after the parked one causally depend on it, so they wait in b's pending area
+    assert_eq!(net.peer(b).engine.get_field(y, "title")?, None);
+    assert_eq!(net.peer(b).engine.pending_count()?, 1);
+
+    // Still parked while b is old
+    net.sync_to(a, b)?;
+    assert_eq!(net.parked(a, b).len(), 1);
+
+    // After upgrading, the parked bundle is delivered
+    net.set_capabilities(b, full);
+    net.sync_to(a, b)?;
+    assert!(net.parked(a, b).is_empty());
+    assert!(net.peer(b).engine.get_edge(edge_id)?.is_some());
+    assert_eq!(net.peer(b).engine.get_field(y, "title")?, Some(FieldValue::Text("after".into())));
+    assert_eq!(net.peer(b).engine.pending_count()?, 0);
+    Ok(())
+}
+
+#[test]
+fn format_version_mismatch_refuses_sync() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+
+    let mut future = net.peer(b).engine.capabilities();
+    future.format_version = FORMAT_VERSION + 1;
+    net.set_capabilities(b, future.clone());
+
+    let result = net.peer(a).engine.negotiate(&future);
+    assert!(matches!(result, Err(EngineError::IncompatiblePeer(_))));
+
+    net.peer_mut(a).create_record("Task", vec![])?;
+    assert!(net.sync_to(a, b).is_err());
+    assert_eq!(net.peer(b).engine.op_count()?, 0);
+
+    let current = Capabilities::new(net.peer(a).engine.capabilities().payload_types, []);
+    net.set_capabilities(b, current);
+    net.sync_to(a, b)?;
+    assert_eq!(net.peer(b).engine.op_count()?, net.peer(a).engine.op_count()?);
+    Ok(())
+}
+
+// ============================================================================
+// Range-Digest Reconciliation
+// ============================================================================
+
+#[test]
+fn identical_peers_reconcile_in_one_round() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    for i in 0..40 {
+        net.peer_mut(a).create_record("Task", vec![("n", FieldValue::Integer(i))])?;
+    }
+    net.sync_pair(a, b)?;
+
+    let (rounds, conflicts) = net.sync_by_digest(a, b)?;
+    assert_eq!(rounds, 1);
+    assert!(conflicts.is_empty());
+    Ok(())
+}
+
+#[test]
+fn digest_sync_finds_bundles_missing_on_both_sides() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    for i in 0..200 {
+        net.peer_mut(a).create_record("Task", vec![("n", FieldValue::Integer(i))])?;
+    }
+    net.sync_pair(a, b)?;
+
+    // A few divergent bundles on each side, buried in a large shared history
+    let only_a = net.peer_mut(a).create_record("Task", vec![])?;
+    let only_b = net.peer_mut(b).create_record("Note", vec![])?;
+    net.peer_mut(b).set_field(only_b, "title", FieldValue::Text("b".into()))?;
+
+    let (rounds, _) = net.sync_by_digest(a, b)?;
+    assert!(rounds <= 6, "expected O(log n) rounds, got {rounds}");
+    assert!(net.peer(b).engine.get_entity(only_a)?.is_some());
+    assert_eq!(net.peer(a).engine.get_field(only_b, "title")?, Some(FieldValue::Text("b".into())));
+    assert_eq!(net.peer(a).engine.get_vector_clock()?, net.peer(b).engine.get_vector_clock()?);
+    Ok(())
+}
+
+#[test]
+fn digest_sync_into_empty_peer_transfers_everything() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    for i in 0..50 {
+        net.peer_mut(a).create_record("Task", vec![("n", FieldValue::Integer(i))])?;
+    }
+
+    net.sync_by_digest(a, b)?;
+    assert_eq!(net.peer(b).engine.op_count()?, net.peer(a).engine.op_count()?);
+    Ok(())
+}
+
+// ============================================================================
+// Peer Outbox
+// ============================================================================
+
+#[test]
+fn outbox_shrinks_as_peer_acknowledges() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let b_id = net.peer(b).actor_id();
+
+    net.peer_mut(a).engine.add_peer(b_id, Some("laptop"))?;
+    let first = net.peer_mut(a).create_record("Task", vec![])?;
+    net.peer_mut(a).set_field(first, "title", FieldValue::Text("x".into()))?;
+    assert_eq!(net.peer(a).engine.pending_bundles_for(b_id, None)?.len(), 2);
+
+    // b receives everything and acks its vector clock
+    net.sync_to(a, b)?;
+    let acked = net.peer(b).engine.get_vector_clock()?;
+    net.peer_mut(a).engine.mark_peer_synced(b_id, &acked)?;
+    assert!(net.peer(a).engine.pending_bundles_for(b_id, None)?.is_empty());
+
+    // New local edits show up again; an older ack never rewinds progress
+    net.peer_mut(a).set_field(first, "title", FieldValue::Text("y".into()))?;
+    net.peer_mut(a).engine.mark_peer_synced(b_id, &Default::default())?;
+    assert_eq!(net.peer(a).engine.pending_bundles_for(b_id, None)?.len(), 1);
+
+    let peer = net.peer(a).engine.get_peer(b_id)?.unwrap();
+    assert_eq!(peer.display_name.as_deref(), Some("laptop"));
+    assert!(peer.last_synced_at.is_some());
+    Ok(())
+}
+
+#[test]
+fn outbox_excludes_peer_own_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let b_id = net.peer(b).actor_id();
+
+    net.peer_mut(b).create_record("Task", vec![])?;
+    net.sync_to(b, a)?;
+    net.peer_mut(a).create_record("Task", vec![])?;
+
+    // Unknown peer: everything it didn't author is unsent
+    assert_eq!(net.peer(a).engine.pending_bundles_for(b_id, None)?.len(), 1);
+    assert!(net.peer(a).engine.peers()?.is_empty());
+
+    net.peer_mut(a).engine.mark_peer_synced(b_id, &Default::default())?;
+    assert_eq!(net.peer(a).engine.peers()?.len(), 1);
+    assert!(net.peer_mut(a).engine.remove_peer(b_id)?);
+    assert!(net.peer(a).engine.get_peer(b_id)?.is_none());
+    Ok(())
+}
+
+// ============================================================================
+// Partial Sync
+// ============================================================================
+
+#[test]
+fn scoped_peer_gets_only_its_facets_and_defers_edges() -> Result<(), Box<dyn std::error::Error>> {
+    let mut desktop = TestPeer::new()?;
+    let task = desktop.create_record("Task", vec![("title", FieldValue::Text("Hang lights".into()))])?;
+    let note = desktop.create_record("Note", vec![("body", FieldValue::Text("Bring a ladder".into()))])?;
+    let project = desktop.create_record("Project", vec![("name", FieldValue::Text("Gala".into()))])?;
+    desktop.set_field(project, "name", FieldValue::Text("Spring Gala".into()))?;
+    let (edge_id, _) = desktop.engine.create_edge("belongs_to", task, project)?;
+
+    let mut phone = EngineBuilder::new()
+        .sync_scope(SyncScope::facets(["Task", "Note"]))
+        .verify_signatures(true)
+        .open_in_memory(ActorIdentity::generate())?;
+    let batch = desktop.engine.bundles_since(&phone.get_vector_clock()?, phone.sync_scope(), None)?;
+    // The project's creation and rename carry nothing in scope
+    assert_eq!(batch.len(), 3);
+    phone.ingest_bundles(&batch)?;
+
+    assert_eq!(phone.get_field(task, "title")?, Some(FieldValue::Text("Hang lights".into())));
+    assert_eq!(phone.get_field(note, "body")?, Some(FieldValue::Text("Bring a ladder".into())));
+    assert!(phone.get_fields(project)?.is_empty());
+    assert!(phone.get_entity(project)?.ok_or("no placeholder")?.placeholder);
+    assert!(!phone.get_entity(task)?.ok_or("task missing")?.placeholder);
+
+    // The edge is stored but deferred until its target is synced
+    assert!(phone.get_edge(edge_id)?.is_some());
+    assert!(phone.get_edges_from(task, &EdgeFilter::all())?.is_empty());
+
+    // Once the project gains a facet in scope, its later ops follow and the edge shows
+    desktop.engine.attach_facet(project, "Note")?;
+    desktop.set_field(project, "body", FieldValue::Text("Venue booked".into()))?;
+    let batch = desktop.engine.bundles_since(&phone.get_vector_clock()?, phone.sync_scope(), None)?;
+    assert_eq!(batch.len(), 2);
+    phone.ingest_bundles(&batch)?;
+    assert_eq!(phone.get_field(project, "body")?, Some(FieldValue::Text("Venue booked".into())));
+    assert_eq!(phone.get_field(project, "name")?, None);
+    let edges = phone.get_edges_from(task, &EdgeFilter::all())?;
+    assert_eq!(edges.iter().map(|e| e.edge_id).collect::<Vec<_>>(), vec![edge_id]);
+    Ok(())
+}
+
+#[test]
+fn trimmed_bundles_verify_only_on_scoped_peers() -> Result<(), Box<dyn std::error::Error>> {
+    let mut desktop = TestPeer::new()?;
+    let task = desktop.create_record("Task", vec![])?;
+    let project = desktop.create_record("Project", vec![])?;
+    // One bundle touching both: the project's op is left out for the phone
+    desktop.execute_bundle(
+        BundleType::UserEdit,
+        vec![
+            OperationPayload::SetField { entity_id: task, field_key: "title".into(), value: FieldValue::Text("a".into()) },
+            OperationPayload::SetField { entity_id: project, field_key: "name".into(), value: FieldValue::Text("b".into()) },
+        ],
+    )?;
+    let scope = SyncScope::facets(["Task"]);
+    let batch = desktop.engine.bundles_since(&Default::default(), &scope, None)?;
+    let (bundle, ops) = batch.last().ok_or("no bundles")?;
+    assert_eq!((bundle.op_count, ops.len()), (2, 1));
+
+    let mut full = EngineBuilder::new().verify_signatures(true).open_in_memory(ActorIdentity::generate())?;
+    let Err(EngineError::InvalidBundle(reason)) = full.ingest_bundles(&batch) else {
+        panic!("full peer took a trimmed bundle");
+    };
+    assert!(reason.contains("ops, found"), "{reason}");
+
+    let mut phone = EngineBuilder::new().sync_scope(scope).verify_signatures(true).open_in_memory(ActorIdentity::generate())?;
+    phone.ingest_bundles(&batch)?;
+    assert_eq!(phone.get_field(task, "title")?, Some(FieldValue::Text("a".into())));
+    assert!(phone.get_entity(project)?.is_none());
+    assert!(phone.verify_integrity()?.bundle_errors.is_empty());
+
+    // Trimming doesn't make tampering any easier
+    let mut forged = batch.clone();
+    forged.last_mut().unwrap().1[0].payload =
+        OperationPayload::SetField { entity_id: task, field_key: "title".into(), value: FieldValue::Text("forged".into()) };
+    let mut other = EngineBuilder::new()
+        .sync_scope(SyncScope::facets(["Task"]))
+        .verify_signatures(true)
+        .open_in_memory(ActorIdentity::generate())?;
+    assert!(matches!(other.ingest_bundles(&forged), Err(EngineError::InvalidBundle(_))));
+    Ok(())
+}
+
+#[test]
+fn scoped_peer_serves_full_peers_only_its_own_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut desktop = TestPeer::new()?;
+    let task = desktop.create_record("Task", vec![])?;
+    let project = desktop.create_record("Project", vec![])?;
+    desktop.execute_bundle(
+        BundleType::UserEdit,
+        vec![
+            OperationPayload::SetField { entity_id: task, field_key: "title".into(), value: FieldValue::Text("a".into()) },
+            OperationPayload::SetField { entity_id: project, field_key: "name".into(), value: FieldValue::Text("b".into()) },
+        ],
+    )?;
+    let mut phone = EngineBuilder::new().sync_scope(SyncScope::facets(["Task"])).open_in_memory(ActorIdentity::generate())?;
+    phone.ingest_bundles(&desktop.engine.bundles_since(&phone.get_vector_clock()?, phone.sync_scope(), None)?)?;
+    phone.set_field(task, "notes", FieldValue::Text("from the phone".into()))?;
+
+    // A full peer pulling from the phone gets the phone's edit, not the trimmed copies
+    let mut laptop = EngineBuilder::new().open_in_memory(ActorIdentity::generate())?;
+    let batch = phone.bundles_since(&laptop.get_vector_clock()?, laptop.sync_scope(), None)?;
+    assert_eq!(batch.len(), 1);
+    assert_eq!(batch[0].0.actor_id, phone.actor_id());
+    laptop.ingest_bundles(&batch)?;
+
+    // The desktop's bundles then arrive whole
+    laptop.ingest_bundles(&desktop.engine.bundles_since(&laptop.get_vector_clock()?, laptop.sync_scope(), None)?)?;
+    assert_eq!(laptop.get_field(project, "name")?, Some(FieldValue::Text("b".into())));
+    assert_eq!(laptop.get_field(task, "notes")?, Some(FieldValue::Text("from the phone".into())));
+    assert!(laptop.verify_integrity()?.is_ok());
+    Ok(())
+}
+
+#[test]
+fn scoped_peer_applies_bundles_out_of_order_over_placeholders() -> Result<(), Box<dyn std::error::Error>> {
+    let mut desktop = TestPeer::new()?;
+    let task = desktop.create_record("Task", vec![])?;
+    desktop.set_field(task, "title", FieldValue::Text("late".into()))?;
+    let scope = SyncScope::facets(["Task"]);
+    let batch = desktop.engine.bundles_since(&Default::default(), &scope, None)?;
+    assert_eq!(batch.len(), 2);
+
+    // The edit arrives first: no waiting on its dependencies, just a placeholder
+    let mut phone = EngineBuilder::new().sync_scope(scope).open_in_memory(ActorIdentity::generate())?;
+    let report = phone.ingest_bundles(&batch[1..])?;
+    assert_eq!(report.bundles_buffered, 0);
+    assert!(phone.get_entity(task)?.ok_or("no placeholder")?.placeholder);
+    assert_eq!(phone.get_field(task, "title")?, Some(FieldValue::Text("late".into())));
+
+    // The creation then fills in the placeholder instead of colliding
+    phone.ingest_bundles(&batch[..1])?;
+    let entity = phone.get_entity(task)?.ok_or("task missing")?;
+    assert!(!entity.placeholder);
+    assert_eq!(entity.created_by, desktop.actor_id());
+    assert_eq!(phone.get_entities_by_facet("Task")?, vec![task]);
+    Ok(())
+}
+
+// ============================================================================
+// Oplog Archives
+// ============================================================================
+
+#[test]
+fn archive_carries_bundles_since_clock() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("oplog.opar");
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+
+    let task = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+    net.sync_to(a, b)?;
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("v2".into()))?;
+
+    // Only what b hasn't seen goes in the archive
+    let b_vc = net.peer(b).engine.get_vector_clock()?;
+    assert_eq!(net.peer(a).engine.export_archive(&path, &b_vc)?, 1);
+
+    let report = net.peer_mut(b).engine.import_archive(&path)?;
+    assert_eq!(report.imported, 1);
+    assert_eq!(net.peer(b).engine.get_field(task, "title")?, Some(FieldValue::Text("v2".into())));
+
+    // Importing twice is a no-op
+    let again = net.peer_mut(b).engine.import_archive(&path)?;
+    assert_eq!((again.imported, again.already_present), (0, 1));
+    assert_eq!(net.peer(b).engine.op_count()?, net.peer(a).engine.op_count()?);
+    Ok(())
+}
+
+#[test]
+fn corrupted_archive_is_rejected_without_ingesting() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("oplog.opar");
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+
+    net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("x".into()))])?;
+    net.peer(a).engine.export_archive(&path, &Default::default())?;
+
+    let mut bytes = std::fs::read(&path)?;
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    std::fs::write(&path, bytes)?;
+
+    let result = net.peer_mut(b).engine.import_archive(&path);
+    assert!(matches!(result, Err(EngineError::InvalidArchive(_))));
+    assert_eq!(net.peer(b).engine.op_count()?, 0);
+    Ok(())
+}
+
+// ============================================================================
+// Actor Trust
+// ============================================================================
+
+#[test]
+fn unknown_actor_is_quarantined_until_approved() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let a_id = net.peer(a).actor_id();
+    net.peer_mut(b).engine.set_trust_policy(TrustPolicy::QuarantineUnknown);
+
+    let task = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("x".into()))])?;
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("y".into()))?;
+    net.sync_to(a, b)?;
+
+    assert_eq!(net.peer(b).engine.actor_trust(a_id)?, Some(TrustState::Pending));
+    assert_eq!(net.peer(b).engine.quarantined_count()?, 2);
+    assert!(net.peer(b).engine.get_entity(task)?.is_none());
+
+    net.peer_mut(b).engine.approve_actor(a_id)?;
+    assert_eq!(net.peer(b).engine.quarantined_count()?, 0);
+    assert_eq!(net.peer(b).engine.get_field(task, "title")?, Some(FieldValue::Text("y".into())));
+
+    // Trusted from now on: new bundles go straight in
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("z".into()))?;
+    net.sync_to(a, b)?;
+    assert_eq!(net.peer(b).engine.get_field(task, "title")?, Some(FieldValue::Text("z".into())));
+    Ok(())
+}
+
+#[test]
+fn revoked_and_unknown_actors_are_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let a_id = net.peer(a).actor_id();
+
+    let task = net.peer_mut(a).create_record("Task", vec![])?;
+    net.sync_to(a, b)?;
+
+    // Revocation isn't retroactive, but blocks anything new
+    net.peer_mut(b).engine.revoke_actor(a_id)?;
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("after".into()))?;
+    let err = net.sync_to(a, b).unwrap_err();
+    assert!(matches!(err.downcast_ref::<EngineError>(), Some(EngineError::UntrustedActor(_))));
+    assert!(net.peer(b).engine.get_entity(task)?.is_some());
+    assert_eq!(net.peer(b).engine.get_field(task, "title")?, None);
+
+    let c = net.add_peer()?;
+    net.peer_mut(c).engine.set_trust_policy(TrustPolicy::RejectUnknown);
+    assert!(net.sync_to(a, c).is_err());
+    assert_eq!(net.peer(c).engine.op_count()?, 0);
+    Ok(())
+}
+
+// ============================================================================
+// Key Rotation
+// ============================================================================
+
+#[test]
+fn rotated_key_stays_the_same_logical_actor() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let c = net.add_peer()?;
+    let old_id = net.peer(a).actor_id();
+    let c_id = net.peer(c).actor_id();
+
+    // b only accepts verified keys, and a is its admin
+    net.peer_mut(b).engine.set_trust_policy(TrustPolicy::QuarantineUnknown);
+    net.peer_mut(b).engine.approve_actor(old_id)?;
+    net.peer_mut(a).engine.grant_role(old_id, Role::Admin)?;
+    let task = net.peer_mut(a).create_record("Task", vec![])?;
+    net.sync_to(a, b)?;
+
+    let rotation = net.peer_mut(a).engine.rotate_identity()?;
+    let new_id = net.peer(a).actor_id();
+    assert_eq!((rotation.old_actor, rotation.new_actor), (old_id, new_id));
+
+    // The new key inherits trust and role without a fresh approval
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("new key".into()))?;
+    net.peer_mut(a).engine.grant_role(c_id, Role::Writer)?;
+    net.sync_to(a, b)?;
+    assert_eq!(net.peer(b).engine.quarantined_count()?, 0);
+    assert_eq!(net.peer(b).engine.logical_actor(new_id)?, old_id);
+    assert_eq!(net.peer(b).engine.get_field(task, "title")?, Some(FieldValue::Text("new key".into())));
+    assert_eq!(net.peer(b).engine.role_of(c_id)?, Some(Role::Writer));
+    assert_eq!(net.peer(b).engine.actor_trust(new_id)?, None, "inherited, not copied");
+
+    // Undoing an edit made before rotation isn't blocked by our own later writes
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("again".into()))?;
+    assert!(matches!(net.peer_mut(a).engine.undo()?, UndoResult::Applied(_)));
+
+    // Revoking the old key revokes its successor too
+    net.peer_mut(b).engine.revoke_actor(old_id)?;
+    net.peer_mut(a).set_field(task, "title", FieldValue::Text("revoked".into()))?;
+    let err = net.sync_to(a, b).unwrap_err();
+    assert!(matches!(err.downcast_ref::<EngineError>(), Some(EngineError::UntrustedActor(_))));
+    assert_ne!(net.peer(b).engine.get_field(task, "title")?, Some(FieldValue::Text("revoked".into())));
+    Ok(())
+}
+
+#[test]
+fn forged_rotation_records_are_ignored() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let c = net.add_peer()?;
+    let a_id = net.peer(a).actor_id();
+    let c_id = net.peer(c).actor_id();
+
+    // c claims to be a's successor, signing the proof with its own key
+    let (_, mut forged) = net.peer(c).identity().rotate();
+    forged.old_actor = a_id;
+    forged.new_actor = c_id;
+    let entity_id = EntityId::new();
+    net.peer_mut(c).execute_bundle(BundleType::System, vec![
+        OperationPayload::CreateEntity { entity_id, initial_table: Some(KEY_ROTATION_FACET.to_string()) },
+        OperationPayload::SetField {
+            entity_id,
+            field_key: "proof".into(),
+            value: FieldValue::Bytes(forged.to_msgpack()?),
+        },
+    ])?;
+    net.sync_to(c, a)?;
+
+    assert!(net.peer(a).engine.key_rotations()?.is_empty());
+    assert_eq!(net.peer(a).engine.logical_actor(c_id)?, c_id);
+    Ok(())
+}
+
+// ============================================================================
+// Multi-Device Users
+// ============================================================================
+
+#[test]
+fn devices_of_one_user_do_not_conflict() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let laptop = net.add_peer()?;
+    let phone = net.add_peer()?;
+    let observer = net.add_peer()?;
+    let phone_id = net.peer(phone).actor_id();
+
+    net.peer_mut(laptop).engine.join_user("alice")?;
+    net.peer_mut(phone).engine.join_user("alice")?;
+    net.sync_all()?;
+    net.peer_mut(laptop).engine.add_user_device("alice", phone_id)?;
+    let task = net.peer_mut(laptop).create_record("Task", vec![])?;
+    net.sync_all()?;
+    assert_eq!(net.peer(observer).engine.user_of(phone_id)?.as_deref(), Some("alice"));
+    assert_eq!(net.peer(observer).engine.user_devices("alice")?.len(), 2);
+
+    // Concurrent edits from both devices
+    net.peer_mut(laptop).set_field(task, "title", FieldValue::Text("laptop".into()))?;
+    net.peer_mut(phone).set_field(task, "title", FieldValue::Text("phone".into()))?;
+    net.sync_to(phone, observer)?;
+    assert!(net.sync_to(laptop, phone)?.is_empty());
+
+    // With grouping disabled they are ordinary concurrent writers
+    net.peer_mut(observer).engine.set_group_user_devices(false);
+    assert_eq!(net.sync_to(laptop, observer)?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn device_claims_need_a_member_to_vouch() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let laptop = net.add_peer()?;
+    let mallory = net.add_peer()?;
+    let mallory_id = net.peer(mallory).actor_id();
+
+    net.peer_mut(laptop).engine.join_user("alice")?;
+    net.sync_all()?;
+    net.peer_mut(mallory).engine.join_user("alice")?;
+    let denied = net.peer_mut(mallory).engine.add_user_device("alice", mallory_id);
+    assert!(matches!(denied, Err(EngineError::PermissionDenied(_))));
+    net.sync_all()?;
+
+    assert_eq!(net.peer(laptop).engine.user_of(mallory_id)?, None);
+    assert_eq!(net.peer(laptop).engine.user_devices("alice")?, vec![net.peer(laptop).actor_id()]);
+    Ok(())
+}
+
+// ============================================================================
+// Actor Directory
+// ============================================================================
+
+#[test]
+fn display_names_sync_to_every_peer() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let a_id = net.peer(a).actor_id();
+    let b_id = net.peer(b).actor_id();
+
+    net.peer_mut(a).engine.set_actor_display_name("Ada")?;
+    net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("x".into()))])?;
+    net.peer_mut(b).create_record("Task", vec![])?;
+    net.sync_all()?;
+    net.peer_mut(a).engine.set_actor_display_name("Ada L.")?;
+    net.sync_all()?;
+
+    let actors = net.peer(b).engine.list_actors()?;
+    assert_eq!(actors.len(), 2);
+    let ada = actors.iter().find(|r| r.actor_id == a_id).unwrap();
+    assert_eq!(ada.display_name.as_deref(), Some("Ada L."));
+    assert_eq!(ada.op_count, net.peer(a).engine.list_actors()?.iter().find(|r| r.actor_id == a_id).unwrap().op_count);
+    assert!(ada.first_seen_at < ada.last_seen_at);
+    let other = actors.iter().find(|r| r.actor_id == b_id).unwrap();
+    assert_eq!(other.display_name, None);
+
+    // Names survive a rebuild of materialized state
+    net.peer_mut(b).engine.rebuild_state()?;
+    let ada = net.peer(b).engine.list_actors()?.into_iter().find(|r| r.actor_id == a_id).unwrap();
+    assert_eq!(ada.display_name.as_deref(), Some("Ada L."));
+    Ok(())
+}
+
+// ============================================================================
+// Presence
+// ============================================================================
+
+#[test]
+fn presence_reaches_reachable_peers_and_times_out() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let (a, b, c) = (net.add_peer()?, net.add_peer()?, net.add_peer()?);
+    let a_id = net.peer(a).actor_id();
+    let cue = net.peer_mut(a).create_record("Cue", vec![("notes", FieldValue::Text("fade up".into()))])?;
+    let cursor = CursorPosition { entity_id: cue, field_key: "notes".into(), offset: 5, anchor: None };
+
+    net.partition(&[c]);
+    net.set_presence(a, PresenceState { selected_entity: Some(cue), cursor: Some(cursor.clone()), ..Default::default() });
+    assert_eq!(net.presence(b).online(net.now()), vec![a_id]);
+    assert_eq!(net.presence(b).selecting(cue, net.now()), vec![a_id]);
+    assert_eq!(net.presence(b).cursors_in(cue, net.now()), vec![(a_id, &cursor)]);
+    assert!(net.presence(a).online(net.now()).is_empty());
+    assert!(net.presence(c).online(net.now()).is_empty());
+
+    // Away still counts as online; silence past the timeout doesn't
+    net.heal();
+    net.set_presence(a, PresenceState { status: OnlineStatus::Away, ..Default::default() });
+    assert_eq!(net.presence(c).online(net.now()), vec![a_id]);
+    assert!(net.presence(b).selecting(cue, net.now()).is_empty());
+    net.advance(DEFAULT_PRESENCE_TIMEOUT.as_millis() as u64)?;
+    assert!(net.presence(c).online(net.now()).is_empty());
+    Ok(())
+}
+
+#[test]
+fn presence_trackers_drop_stale_and_offline_messages() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let (a, b) = (net.add_peer()?, net.add_peer()?);
+    let (a_id, b_id) = (net.peer(a).actor_id(), net.peer(b).actor_id());
+    let mut relay = PresenceTracker::new(b_id, DEFAULT_PRESENCE_TIMEOUT);
+
+    let first = net.set_presence(a, PresenceState::default());
+    let offline = net.set_presence(a, PresenceState { status: OnlineStatus::Offline, ..Default::default() });
+    assert!(net.presence(b).online(0).is_empty());
+
+    // A late copy of the earlier message can't bring the actor back
+    assert!(relay.apply(&offline, 0));
+    assert!(!relay.apply(&first, 0));
+    assert!(relay.state(a_id, 0).is_none());
+    // Nor does an actor track itself
+    let own = relay.update(PresenceState::default(), 0);
+    assert!(!relay.apply(&own, 0));
+    relay.prune(0);
+    assert!(relay.online(0).is_empty());
+    Ok(())
+}
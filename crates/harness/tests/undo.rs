@@ -0,0 +1,504 @@
+use openprod_core::{field_value::FieldValue, identity::ActorIdentity, ids::*, operations::*};
+use openprod_engine::{EngineBuilder, EngineError, UndoResult, UndoScope};
+use openprod_harness::{TestNetwork, TestPeer};
+use openprod_storage::EdgeFilter;
+
+// ============================================================================
+// Revert (Compensating Bundles)
+// ============================================================================
+
+#[test]
+fn revert_historical_bundle_restores_previous_value() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_id = peer.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+
+    let target = peer.engine.set_field(entity_id, "title", FieldValue::Text("v2".into()))?;
+    // An unrelated later edit buries the target bundle below the top of the undo stack
+    peer.set_field(entity_id, "status", FieldValue::Text("open".into()))?;
+
+    let result = peer.engine.revert_bundle(target)?;
+    assert!(result.bundle_id.is_some());
+    assert!(result.skipped.is_empty());
+    assert_eq!(peer.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v1".into())));
+    assert_eq!(peer.engine.get_field(entity_id, "status")?, Some(FieldValue::Text("open".into())));
+
+    // The compensating bundle is itself undoable
+    peer.engine.undo()?;
+    assert_eq!(peer.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v2".into())));
+    Ok(())
+}
+
+#[test]
+fn revert_peer_bundle_emits_signed_bundle_that_syncs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+
+    let entity_id = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("mine".into()))])?;
+    net.sync_to(a, b)?;
+    let peer_bundle = net.peer_mut(a).engine.set_field(entity_id, "title", FieldValue::Text("theirs".into()))?;
+    net.sync_to(a, b)?;
+
+    let result = net.peer_mut(b).engine.revert_bundle(peer_bundle)?;
+    let revert_id = result.bundle_id.unwrap();
+    let ops = net.peer(b).engine.get_ops_by_bundle(revert_id)?;
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].actor_id, net.peer(b).actor_id());
+
+    net.sync_to(b, a)?;
+    for idx in [a, b] {
+        assert_eq!(net.peer(idx).engine.get_field(entity_id, "title")?, Some(FieldValue::Text("mine".into())));
+    }
+    Ok(())
+}
+
+#[test]
+fn revert_skips_fields_written_later() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_id = peer.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+
+    let target = peer.engine.set_field(entity_id, "title", FieldValue::Text("v2".into()))?;
+    peer.set_field(entity_id, "title", FieldValue::Text("v3".into()))?;
+
+    let result = peer.engine.revert_bundle(target)?;
+    assert!(result.bundle_id.is_none());
+    assert_eq!(result.skipped.len(), 1);
+    assert_eq!(result.skipped[0].field_key, "title");
+    assert_eq!(result.skipped[0].modified_by, peer.actor_id());
+    assert_eq!(peer.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v3".into())));
+    Ok(())
+}
+
+#[test]
+fn revert_restores_and_skips_edge_properties() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let source = peer.create_record("Task", vec![])?;
+    let target = peer.create_record("Task", vec![])?;
+    let edge_id = peer.create_edge_with_properties("blocks", source, target, vec![("weight", FieldValue::Integer(1))])?;
+
+    let set = peer.engine.set_edge_property(edge_id, "weight", FieldValue::Integer(2))?;
+    assert!(peer.engine.revert_bundle(set)?.bundle_id.is_some());
+    assert_eq!(peer.engine.get_edge_property(edge_id, "weight")?, Some(FieldValue::Integer(1)));
+
+    let set = peer.engine.set_edge_property(edge_id, "weight", FieldValue::Integer(2))?;
+    peer.set_edge_property(edge_id, "weight", FieldValue::Integer(3))?;
+    let result = peer.engine.revert_bundle(set)?;
+    assert!(result.bundle_id.is_none());
+    assert_eq!(result.skipped_edge_properties.len(), 1);
+    assert_eq!(result.skipped_edge_properties[0].property_key, "weight");
+    assert_eq!(result.skipped_edge_properties[0].modified_by, peer.actor_id());
+    assert_eq!(peer.engine.get_edge_property(edge_id, "weight")?, Some(FieldValue::Integer(3)));
+    Ok(())
+}
+
+#[test]
+fn revert_create_deletes_entity() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let (entity_id, create_bundle) = peer.engine.create_entity_with_fields(
+        "Task",
+        vec![("title", FieldValue::Text("temp".into()))],
+    )?;
+    let other = peer.create_record("Task", vec![])?;
+    peer.create_edge("blocks", entity_id, other)?;
+
+    let result = peer.engine.revert_bundle(create_bundle)?;
+    assert!(result.bundle_id.is_some());
+    assert!(peer.engine.get_entity(entity_id)?.unwrap().deleted);
+    assert!(peer.engine.get_edges_to(other, &EdgeFilter::all())?.iter().all(|e| e.deleted));
+    Ok(())
+}
+
+#[test]
+fn revert_unknown_bundle_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let result = peer.engine.revert_bundle(BundleId::new());
+    assert!(matches!(result, Err(EngineError::BundleNotFound(_))));
+    Ok(())
+}
+
+// ============================================================================
+// Selective Undo
+// ============================================================================
+
+#[test]
+fn undo_bundle_from_middle_of_stack() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_id = peer.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+
+    let target = peer.engine.set_field(entity_id, "title", FieldValue::Text("v2".into()))?;
+    peer.set_field(entity_id, "status", FieldValue::Text("open".into()))?;
+
+    let result = peer.engine.undo_bundle(target)?;
+    assert!(matches!(result, UndoResult::Applied(_)));
+    assert_eq!(peer.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v1".into())));
+    assert_eq!(peer.engine.get_field(entity_id, "status")?, Some(FieldValue::Text("open".into())));
+
+    // The later edit is still the top of the stack
+    peer.engine.undo()?;
+    assert_eq!(peer.engine.get_field(entity_id, "status")?, None);
+    assert_eq!(peer.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v1".into())));
+    Ok(())
+}
+
+#[test]
+fn undo_bundle_skips_on_intermediate_own_edit() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_id = peer.create_record("Task", vec![("title", FieldValue::Text("v1".into()))])?;
+
+    let target = peer.engine.set_field(entity_id, "title", FieldValue::Text("v2".into()))?;
+    peer.set_field(entity_id, "title", FieldValue::Text("v3".into()))?;
+
+    match peer.engine.undo_bundle(target)? {
+        UndoResult::Skipped { conflicts } => {
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].field_key, "title");
+            assert_eq!(conflicts[0].modified_by, peer.actor_id());
+        }
+        other => panic!("expected Skipped, got {other:?}"),
+    }
+    assert_eq!(peer.engine.get_field(entity_id, "title")?, Some(FieldValue::Text("v3".into())));
+
+    // Entry stays on the stack (a consumed entry would report Empty)
+    assert!(matches!(peer.engine.undo_bundle(target)?, UndoResult::Skipped { .. }));
+    Ok(())
+}
+
+#[test]
+fn undo_bundle_not_on_stack_is_empty() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_id = peer.create_record("Task", vec![])?;
+    let bundle_id = peer.engine.set_field(entity_id, "title", FieldValue::Text("x".into()))?;
+    peer.engine.undo()?;
+
+    assert!(matches!(peer.engine.undo_bundle(bundle_id)?, UndoResult::Empty));
+    assert!(matches!(peer.engine.undo_bundle(BundleId::new())?, UndoResult::Empty));
+    Ok(())
+}
+
+// ============================================================================
+// Undo History Introspection
+// ============================================================================
+
+#[test]
+fn undo_stack_summaries_most_recent_first() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let (entity_id, create_bundle) = peer.engine.create_entity_with_fields(
+        "Task",
+        vec![("title", FieldValue::Text("a".into()))],
+    )?;
+    let set_bundle = peer.engine.set_field(entity_id, "title", FieldValue::Text("b".into()))?;
+
+    let stack = peer.engine.undo_stack()?;
+    assert_eq!(stack.len(), 2);
+    assert_eq!(stack[0].bundle_id, set_bundle);
+    assert_eq!(stack[0].op_types, vec!["SetField".to_string()]);
+    assert_eq!(stack[0].entities, vec![entity_id]);
+    assert_eq!(stack[1].bundle_id, create_bundle);
+    assert_eq!(stack[1].op_types, vec!["CreateEntity".to_string(), "SetField".to_string()]);
+    assert_eq!(stack[1].entities, vec![entity_id]);
+    assert!(stack[1].hlc < stack[0].hlc);
+    assert!(peer.engine.redo_stack()?.is_empty());
+
+    peer.engine.undo()?;
+    assert_eq!(peer.engine.undo_stack()?.len(), 1);
+    let redo = peer.engine.redo_stack()?;
+    assert_eq!(redo.len(), 1);
+    assert_eq!(redo[0].bundle_id, set_bundle);
+    Ok(())
+}
+
+#[test]
+fn undo_stack_summaries_carry_labels() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_id = EntityId::new();
+    let bundle_id = peer.engine.execute_with_meta(
+        BundleType::UserEdit,
+        vec![OperationPayload::CreateEntity { entity_id, initial_table: None }],
+        BundleMeta::with_label("New task"),
+    )?;
+    assert_eq!(peer.engine.undo_stack()?[0].label.as_deref(), Some("New task"));
+
+    peer.engine.relabel_bundle(bundle_id, "Renamed")?;
+    assert_eq!(peer.engine.undo_stack()?[0].label.as_deref(), Some("Renamed"));
+    Ok(())
+}
+
+// ============================================================================
+// Undo Grouping
+// ============================================================================
+
+#[test]
+fn undo_group_reverts_burst_in_one_step() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_id = peer.create_record("Note", vec![("body", FieldValue::Text("".into()))])?;
+
+    peer.engine.begin_undo_group();
+    for text in ["h", "he", "hel", "hell", "hello"] {
+        peer.set_field(entity_id, "body", FieldValue::Text(text.into()))?;
+    }
+    peer.set_field(entity_id, "edited", FieldValue::Boolean(true))?;
+    peer.engine.end_undo_group();
+
+    let stack = peer.engine.undo_stack()?;
+    assert_eq!(stack.len(), 2);
+    assert_eq!(stack[0].op_types, vec!["SetField".to_string(), "SetField".to_string()]);
+
+    assert!(matches!(peer.engine.undo()?, UndoResult::Applied(_)));
+    assert_eq!(peer.engine.get_field(entity_id, "body")?, Some(FieldValue::Text("".into())));
+    assert_eq!(peer.engine.get_field(entity_id, "edited")?, None);
+
+    // Redo replays the final state of the burst
+    assert!(matches!(peer.engine.redo()?, UndoResult::Applied(_)));
+    assert_eq!(peer.engine.get_field(entity_id, "body")?, Some(FieldValue::Text("hello".into())));
+    assert_eq!(peer.engine.get_field(entity_id, "edited")?, Some(FieldValue::Boolean(true)));
+    Ok(())
+}
+
+#[test]
+fn nested_undo_groups_merge_at_outermost_close() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+
+    peer.engine.begin_undo_group();
+    let entity_id = peer.create_record("Task", vec![])?;
+    peer.engine.begin_undo_group();
+    peer.set_field(entity_id, "title", FieldValue::Text("a".into()))?;
+    peer.set_field(entity_id, "title", FieldValue::Text("ab".into()))?;
+    peer.engine.end_undo_group();
+    assert_eq!(peer.engine.undo_stack()?.len(), 3);
+    peer.engine.end_undo_group();
+    assert_eq!(peer.engine.undo_stack()?.len(), 1);
+
+    peer.engine.undo()?;
+    assert!(peer.engine.get_entity(entity_id)?.unwrap().deleted);
+    Ok(())
+}
+
+#[test]
+fn grouped_entry_found_by_any_member_bundle() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_id = peer.create_record("Task", vec![])?;
+
+    peer.engine.begin_undo_group();
+    let first = peer.engine.set_field(entity_id, "a", FieldValue::Integer(1))?;
+    peer.engine.set_field(entity_id, "b", FieldValue::Integer(2))?;
+    peer.engine.end_undo_group();
+    peer.set_field(entity_id, "c", FieldValue::Integer(3))?;
+
+    assert!(matches!(peer.engine.undo_bundle(first)?, UndoResult::Applied(_)));
+    assert_eq!(peer.engine.get_field(entity_id, "a")?, None);
+    assert_eq!(peer.engine.get_field(entity_id, "b")?, None);
+    assert_eq!(peer.engine.get_field(entity_id, "c")?, Some(FieldValue::Integer(3)));
+    Ok(())
+}
+
+#[test]
+fn group_undo_sees_remote_write_between_its_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let entity_id = net.peer_mut(a).create_record("Task", vec![])?;
+
+    net.peer_mut(a).engine.begin_undo_group();
+    net.peer_mut(a).set_field(entity_id, "title", FieldValue::Text("mine".into()))?;
+    net.sync_to(a, b)?;
+    net.peer_mut(b).set_field(entity_id, "title", FieldValue::Text("theirs".into()))?;
+    net.sync_to(b, a)?;
+    // Written after b's edit, so the group's last HLC is past it
+    net.peer_mut(a).set_field(entity_id, "status", FieldValue::Text("open".into()))?;
+    net.peer_mut(a).engine.end_undo_group();
+
+    match net.peer_mut(a).engine.undo()? {
+        UndoResult::Skipped { conflicts } => {
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].field_key, "title");
+            assert_eq!(conflicts[0].modified_by, net.peer(b).actor_id());
+        }
+        other => panic!("expected Skipped, got {other:?}"),
+    }
+    assert_eq!(net.peer(a).engine.get_field(entity_id, "title")?, Some(FieldValue::Text("theirs".into())));
+    Ok(())
+}
+
+#[test]
+fn undo_mid_group_closes_it() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let entity_id = peer.create_record("Task", vec![])?;
+
+    peer.engine.begin_undo_group();
+    peer.set_field(entity_id, "title", FieldValue::Text("a".into()))?;
+    peer.set_field(entity_id, "title", FieldValue::Text("ab".into()))?;
+    peer.engine.undo()?;
+    assert_eq!(peer.engine.get_field(entity_id, "title")?, None);
+
+    // A stray end after the implicit close is harmless
+    peer.engine.end_undo_group();
+    peer.set_field(entity_id, "title", FieldValue::Text("x".into()))?;
+    assert_eq!(peer.engine.undo_stack()?.len(), 2);
+    Ok(())
+}
+
+// ============================================================================
+// Undo Scopes
+// ============================================================================
+
+#[test]
+fn undo_scope_leaves_other_entities_alone() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let doc_a = peer.create_record("Task", vec![])?;
+    let doc_b = peer.create_record("Note", vec![])?;
+
+    peer.set_field(doc_a, "title", FieldValue::Text("a1".into()))?;
+    peer.set_field(doc_b, "title", FieldValue::Text("b1".into()))?;
+
+    let result = peer.engine.undo_scope(&UndoScope::Entity(doc_a))?;
+    assert!(matches!(result, UndoResult::Applied(_)));
+    assert_eq!(peer.engine.get_field(doc_a, "title")?, None);
+    assert_eq!(peer.engine.get_field(doc_b, "title")?, Some(FieldValue::Text("b1".into())));
+
+    // Global undo still sees doc B's edit on top
+    peer.engine.undo()?;
+    assert_eq!(peer.engine.get_field(doc_b, "title")?, None);
+    Ok(())
+}
+
+#[test]
+fn undo_scope_by_facet_and_redo_scope() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let task = peer.create_record("Task", vec![])?;
+    let note = peer.create_record("Note", vec![])?;
+
+    peer.set_field(task, "status", FieldValue::Text("done".into()))?;
+    peer.set_field(note, "body", FieldValue::Text("hello".into()))?;
+
+    let scope = UndoScope::Facet("Task".into());
+    assert!(matches!(peer.engine.undo_scope(&scope)?, UndoResult::Applied(_)));
+    assert_eq!(peer.engine.get_field(task, "status")?, None);
+    assert_eq!(peer.engine.get_field(note, "body")?, Some(FieldValue::Text("hello".into())));
+
+    // Undo in another scope, then redo only the Task scope
+    peer.engine.undo_scope(&UndoScope::Facet("Note".into()))?;
+    assert!(matches!(peer.engine.redo_scope(&scope)?, UndoResult::Applied(_)));
+    assert_eq!(peer.engine.get_field(task, "status")?, Some(FieldValue::Text("done".into())));
+    assert_eq!(peer.engine.get_field(note, "body")?, None);
+    assert_eq!(peer.engine.redo_stack()?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn edit_in_another_scope_keeps_redo() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let task = peer.create_record("Task", vec![])?;
+    let note = peer.create_record("Note", vec![])?;
+    peer.set_field(task, "status", FieldValue::Text("done".into()))?;
+
+    let scope = UndoScope::Entity(task);
+    assert!(matches!(peer.engine.undo_scope(&scope)?, UndoResult::Applied(_)));
+    peer.set_field(note, "body", FieldValue::Text("hello".into()))?;
+    assert!(matches!(peer.engine.redo_scope(&scope)?, UndoResult::Applied(_)));
+    assert_eq!(peer.engine.get_field(task, "status")?, Some(FieldValue::Text("done".into())));
+
+    // An edit in the same scope still invalidates its redo
+    peer.engine.undo_scope(&scope)?;
+    peer.set_field(task, "title", FieldValue::Text("t".into()))?;
+    assert!(matches!(peer.engine.redo_scope(&scope)?, UndoResult::Empty));
+    Ok(())
+}
+
+#[test]
+fn undo_depth_applies_per_scope() -> Result<(), Box<dyn std::error::Error>> {
+    let mut engine = EngineBuilder::new().undo_depth(2).open_in_memory(ActorIdentity::generate())?;
+    let (task, _) = engine.create_entity(Some("Task"))?;
+    let (note, _) = engine.create_entity(Some("Note"))?;
+    engine.set_field(task, "status", FieldValue::Text("done".into()))?;
+    for n in 0..3 {
+        engine.set_field(note, "n", FieldValue::Integer(n))?;
+    }
+
+    // Edits to the note don't push the task's history off the stack
+    assert!(matches!(engine.undo_scope(&UndoScope::Entity(task))?, UndoResult::Applied(_)));
+    assert_eq!(engine.get_field(task, "status")?, None);
+    Ok(())
+}
+
+#[test]
+fn edge_edits_belong_to_both_endpoints() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let source = peer.create_record("Task", vec![])?;
+    let target = peer.create_record("Task", vec![])?;
+    let other = peer.create_record("Note", vec![])?;
+
+    let edge_id = peer.create_edge("blocks", source, target)?;
+    peer.set_field(other, "body", FieldValue::Text("x".into()))?;
+
+    assert!(matches!(peer.engine.undo_scope(&UndoScope::Entity(target))?, UndoResult::Applied(_)));
+    assert!(peer.engine.get_edge(edge_id)?.unwrap().deleted);
+    assert_eq!(peer.engine.get_field(other, "body")?, Some(FieldValue::Text("x".into())));
+    Ok(())
+}
+
+#[test]
+fn undo_scope_with_nothing_in_scope_is_empty() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    peer.create_record("Task", vec![("title", FieldValue::Text("x".into()))])?;
+
+    let result = peer.engine.undo_scope(&UndoScope::Facet("Invoice".into()))?;
+    assert!(matches!(result, UndoResult::Empty));
+    assert!(matches!(peer.engine.redo_scope(&UndoScope::Facet("Invoice".into()))?, UndoResult::Empty));
+    Ok(())
+}
+
+// ============================================================================
+// Restore Commands
+// ============================================================================
+
+#[test]
+fn restore_edge_requires_a_deleted_edge_with_live_ends() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let task = peer.create_record("Task", vec![])?;
+    let person = peer.create_record("Person", vec![])?;
+    let edge = peer.create_edge("assigned_to", task, person)?;
+
+    assert!(matches!(peer.engine.restore_edge(edge), Err(EngineError::EdgeNotDeleted(_))));
+    assert!(matches!(peer.engine.restore_edge(EdgeId::new()), Err(EngineError::EdgeNotFound(_))));
+
+    peer.delete_edge(edge)?;
+    peer.engine.restore_edge(edge)?;
+    assert!(!peer.engine.get_edge(edge)?.unwrap().deleted);
+    assert!(matches!(peer.engine.undo()?, UndoResult::Applied(_)));
+    assert!(peer.engine.get_edge(edge)?.unwrap().deleted);
+
+    // Not while an end is deleted
+    peer.delete_entity(person)?;
+    assert!(matches!(peer.engine.restore_edge(edge), Err(EngineError::EntityAlreadyDeleted(_))));
+
+    // A unique type only brings back the edge duplicates merge into
+    peer.engine.restore_entity(person)?;
+    let later = peer.create_edge("assigned_to", task, person)?;
+    peer.engine.declare_unique_edge_type("assigned_to")?;
+    assert!(peer.engine.get_edge(later)?.unwrap().deleted);
+    assert!(matches!(peer.engine.restore_edge(later), Err(EngineError::Rejected(_))));
+    peer.engine.restore_edge(edge)?;
+    assert!(!peer.engine.get_edge(edge)?.unwrap().deleted);
+    Ok(())
+}
+
+#[test]
+fn undoing_restore_skips_when_others_edited_since() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+
+    let task = net.peer_mut(a).create_record("Task", vec![("title", FieldValue::Text("draft".into()))])?;
+    net.peer_mut(a).delete_entity(task)?;
+    net.peer_mut(a).engine.restore_entity(task)?;
+    net.sync_to(a, b)?;
+    net.peer_mut(b).engine.set_field(task, "title", FieldValue::Text("theirs".into()))?;
+    net.sync_to(b, a)?;
+
+    match net.peer_mut(a).engine.undo()? {
+        UndoResult::Skipped { conflicts } => assert_eq!(conflicts[0].field_key, "title"),
+        other => panic!("expected Skipped, got {other:?}"),
+    }
+    assert!(!net.peer(a).engine.get_entity(task)?.unwrap().deleted);
+    Ok(())
+}
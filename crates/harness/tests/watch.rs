@@ -0,0 +1,572 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::ManualClock,
+    identity::ActorIdentity,
+    ids::EntityId,
+};
+use openprod_engine::{
+    CdcBatch, CdcChange, Engine, EngineBuilder, EngineError, EntityChange, EntityQuery, EntityWatch, QueryUpdate,
+    QueryWatch, WebhookFilter, WebhookReport, WebhookTransport,
+};
+use openprod_harness::{TestNetwork, TestPeer};
+use openprod_storage::{ConflictStatus, MemoryStorage, Storage};
+
+fn drain(watch: &EntityWatch) -> Vec<EntityChange> {
+    watch.updates.try_iter().collect()
+}
+
+fn field_set(field_key: &str, value: &str) -> EntityChange {
+    EntityChange::FieldSet { field_key: field_key.into(), value: FieldValue::Text(value.into()) }
+}
+
+// ============================================================================
+// Snapshot and Local Writes
+// ============================================================================
+
+#[test]
+fn watch_returns_snapshot_then_local_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let cue = peer.create_record("Cue", vec![("label", FieldValue::Text("Q1".into())), ("notes", FieldValue::Text("n".into()))])?;
+    let stage = peer.create_record("Location", vec![])?;
+
+    let watch = peer.engine.watch_entity(cue)?;
+    assert_eq!(watch.view.fields.get("label"), Some(&FieldValue::Text("Q1".into())));
+    assert_eq!(watch.view.fields.len(), 2);
+    assert!(watch.view.edges.is_empty());
+    assert!(drain(&watch).is_empty());
+
+    peer.set_field(cue, "label", FieldValue::Text("Q2".into()))?;
+    peer.engine.clear_field(cue, "notes")?;
+    peer.engine.attach_facet(cue, "Lighting")?;
+    assert_eq!(
+        drain(&watch),
+        vec![
+            field_set("label", "Q2"),
+            EntityChange::FieldCleared { field_key: "notes".into() },
+            EntityChange::FacetAttached { facet_type: "Lighting".into() },
+        ]
+    );
+
+    // Edges in either direction
+    let (edge_id, _) = peer.engine.create_edge("located_at", stage, cue)?;
+    peer.engine.delete_edge(edge_id)?;
+    assert_eq!(
+        drain(&watch),
+        vec![
+            EntityChange::EdgeAdded { edge_id, edge_type: "located_at".into(), source_id: stage, target_id: cue },
+            EntityChange::EdgeRemoved { edge_id },
+        ]
+    );
+
+    // Writes to other entities don't produce updates
+    peer.set_field(stage, "name", FieldValue::Text("Main".into()))?;
+    assert!(drain(&watch).is_empty());
+
+    peer.engine.delete_entity(cue)?;
+    assert_eq!(drain(&watch), vec![EntityChange::Deleted]);
+    peer.engine.undo()?;
+    assert_eq!(drain(&watch), vec![EntityChange::Restored]);
+    Ok(())
+}
+
+#[test]
+fn watching_a_missing_entity_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let err = peer.engine.watch_entity(EntityId::new()).unwrap_err();
+    assert!(matches!(err, EngineError::EntityNotFound(_)));
+    Ok(())
+}
+
+#[test]
+fn dropped_watches_stop_receiving() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let cue = peer.create_record("Cue", vec![])?;
+    let kept = peer.engine.watch_entity(cue)?;
+    drop(peer.engine.watch_entity(cue)?);
+
+    peer.set_field(cue, "label", FieldValue::Text("Q1".into()))?;
+    assert_eq!(drain(&kept), vec![field_set("label", "Q1")]);
+    Ok(())
+}
+
+// ============================================================================
+// Ingest and Overlays
+// ============================================================================
+
+#[test]
+fn watch_sees_ingested_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let cue = net.peer_mut(a).create_record("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    net.sync_to(a, b)?;
+
+    let watch = net.peer_mut(b).engine.watch_entity(cue)?;
+    net.peer_mut(a).set_field(cue, "label", FieldValue::Text("Q1 remote".into()))?;
+    net.sync_to(a, b)?;
+    assert_eq!(drain(&watch), vec![field_set("label", "Q1 remote")]);
+    Ok(())
+}
+
+#[test]
+fn watch_follows_overlay_activation() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let cue = peer.create_record("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    let watch = peer.engine.watch_entity(cue)?;
+
+    let draft = peer.create_overlay("draft")?;
+    assert!(drain(&watch).is_empty());
+    peer.set_field(cue, "label", FieldValue::Text("Q1 draft".into()))?;
+    assert_eq!(drain(&watch), vec![field_set("label", "Q1 draft")]);
+
+    peer.engine.stash_overlay(draft)?;
+    assert_eq!(drain(&watch), vec![field_set("label", "Q1")]);
+
+    // Switching straight from one overlay to another reports only the net change
+    peer.create_overlay("other")?;
+    peer.set_field(cue, "label", FieldValue::Text("Q1 other".into()))?;
+    drain(&watch);
+    peer.engine.activate_overlay(draft)?;
+    assert_eq!(drain(&watch), vec![field_set("label", "Q1 draft")]);
+
+    peer.engine.discard_overlay(draft)?;
+    assert_eq!(drain(&watch), vec![field_set("label", "Q1")]);
+    Ok(())
+}
+
+// ============================================================================
+// Live Queries
+// ============================================================================
+
+fn open_tasks() -> EntityQuery {
+    EntityQuery::facet("Task").field_eq("status", FieldValue::Text("open".into()))
+}
+
+fn next_update(watch: &QueryWatch) -> QueryUpdate {
+    let updates: Vec<QueryUpdate> = watch.updates.try_iter().collect();
+    assert!(updates.len() <= 1, "{updates:?}");
+    updates.into_iter().next().unwrap_or_default()
+}
+
+fn sorted<T: Ord>(mut ids: Vec<T>) -> Vec<T> {
+    ids.sort();
+    ids
+}
+
+#[test]
+fn query_returns_matching_entities() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let open = FieldValue::Text("open".into());
+    let a = peer.create_record("Task", vec![("status", open.clone())])?;
+    let b = peer.create_record("Task", vec![("status", open.clone()), ("due", FieldValue::Integer(1))])?;
+    peer.create_record("Task", vec![("status", FieldValue::Text("done".into()))])?;
+    peer.create_record("Note", vec![("status", open)])?;
+    let deleted = peer.create_record("Task", vec![("status", FieldValue::Text("open".into()))])?;
+    peer.engine.delete_entity(deleted)?;
+
+    assert_eq!(peer.engine.query_entities(&open_tasks())?, sorted(vec![a, b]));
+    assert_eq!(peer.engine.query_entities(&open_tasks().has_field("due"))?, vec![b]);
+    assert_eq!(peer.engine.query_entities(&open_tasks().lacks_field("due"))?, vec![a]);
+    Ok(())
+}
+
+#[test]
+fn live_query_reports_added_removed_and_changed() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let a = peer.create_record("Task", vec![("status", FieldValue::Text("open".into()))])?;
+    let watch = peer.engine.watch_query(open_tasks())?;
+    assert_eq!(watch.results, vec![a]);
+
+    let b = peer.create_record("Task", vec![("status", FieldValue::Text("open".into()))])?;
+    assert_eq!(next_update(&watch), QueryUpdate { added: vec![b], ..Default::default() });
+
+    peer.set_field(a, "title", FieldValue::Text("Hang lights".into()))?;
+    assert_eq!(next_update(&watch), QueryUpdate { changed: vec![a], ..Default::default() });
+
+    peer.set_field(a, "status", FieldValue::Text("done".into()))?;
+    assert_eq!(next_update(&watch), QueryUpdate { removed: vec![a], ..Default::default() });
+
+    peer.engine.detach_facet(b, "Task", false)?;
+    assert_eq!(next_update(&watch), QueryUpdate { removed: vec![b], ..Default::default() });
+
+    // Writes to non-matching entities send nothing
+    peer.set_field(a, "title", FieldValue::Text("Focus lights".into()))?;
+    peer.create_record("Note", vec![("status", FieldValue::Text("open".into()))])?;
+    assert_eq!(next_update(&watch), QueryUpdate::default());
+    Ok(())
+}
+
+#[test]
+fn live_query_follows_ingest_and_overlays() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let task = net.peer_mut(a).create_record("Task", vec![("status", FieldValue::Text("open".into()))])?;
+    let watch = net.peer_mut(b).engine.watch_query(open_tasks())?;
+    assert!(watch.results.is_empty());
+
+    net.sync_to(a, b)?;
+    assert_eq!(next_update(&watch), QueryUpdate { added: vec![task], ..Default::default() });
+
+    let peer = net.peer_mut(b);
+    let draft = peer.create_overlay("draft")?;
+    peer.set_field(task, "status", FieldValue::Text("done".into()))?;
+    assert_eq!(next_update(&watch), QueryUpdate { removed: vec![task], ..Default::default() });
+    peer.engine.stash_overlay(draft)?;
+    assert_eq!(next_update(&watch), QueryUpdate { added: vec![task], ..Default::default() });
+    Ok(())
+}
+
+// ============================================================================
+// Changes Since
+// ============================================================================
+
+#[test]
+fn changes_since_lists_touched_entities_and_edges() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let cue = peer.create_record("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    let stage = peer.create_record("Location", vec![])?;
+    let spare = peer.create_record("Cue", vec![])?;
+    let edge = peer.create_edge("at", spare, stage)?;
+    let since = peer.engine.changes_watermark()?;
+    assert!(peer.engine.entities_changed_since(since)?.is_empty());
+
+    peer.set_field(cue, "label", FieldValue::Text("Q2".into()))?;
+    let link = peer.create_edge("at", cue, stage)?;
+    peer.delete_entity(spare)?;
+
+    // Creating an edge doesn't count as changing its endpoints; the delete
+    // cascades to the spare's edge
+    assert_eq!(peer.engine.entities_changed_since(since)?, sorted(vec![cue, spare]));
+    assert_eq!(peer.engine.edges_changed_since(since)?, sorted(vec![edge, link]));
+
+    let since = peer.engine.changes_watermark()?;
+    peer.engine.set_edge_property(link, "cue_point", FieldValue::Integer(3))?;
+    assert!(peer.engine.entities_changed_since(since)?.is_empty());
+    assert_eq!(peer.engine.edges_changed_since(since)?, vec![link]);
+    Ok(())
+}
+
+#[test]
+fn changes_since_includes_ingested_ops_on_memory_storage() -> Result<(), Box<dyn std::error::Error>> {
+    let mut a = TestPeer::new()?;
+    let mut b = Engine::new(ActorIdentity::generate(), MemoryStorage::new())?;
+    assert_eq!(b.changes_watermark()?, 0);
+    let (local, local_bundle) = b.create_entity(Some("Cue"))?;
+    let since = b.changes_watermark()?;
+
+    // Having seen b's write, a stamps its own after it even within the same millisecond
+    a.engine.ingest_bundle(&b.storage().get_bundle(local_bundle)?.unwrap(), &b.get_ops_by_bundle(local_bundle)?)?;
+    let remote = a.create_record("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    for op in a.engine.get_ops_canonical()? {
+        let bundle = a.engine.storage().get_bundle(op.bundle_id)?.unwrap();
+        b.ingest_bundle(&bundle, &a.engine.get_ops_by_bundle(op.bundle_id)?)?;
+    }
+    assert_eq!(b.entities_changed_since(since)?, vec![remote]);
+    assert_eq!(b.entities_changed_since(0)?, sorted(vec![local, remote]));
+    Ok(())
+}
+
+#[test]
+fn changes_since_includes_older_ops_synced_in_later() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let cue = net.peer_mut(a).create_record("Cue", vec![])?;
+    net.sync_all()?;
+
+    // b's offline edit is stamped before a polls but only arrives afterwards
+    net.peer_mut(b).set_field(cue, "label", FieldValue::Text("Q1".into()))?;
+    std::thread::sleep(Duration::from_millis(2));
+    net.peer_mut(a).create_record("Location", vec![])?;
+    let since = net.peer(a).engine.changes_watermark()?;
+    assert!(net.peer(a).engine.entities_changed_since(since)?.is_empty());
+
+    net.sync_to(b, a)?;
+    assert_eq!(net.peer(a).engine.entities_changed_since(since)?, vec![cue]);
+    Ok(())
+}
+
+// ============================================================================
+// Change Data Capture
+// ============================================================================
+
+fn cdc_changes(peer: &TestPeer, after: u64) -> Result<(Vec<CdcChange>, u64), EngineError> {
+    let batch = peer.engine.cdc_events(after, 100)?;
+    Ok((batch.events.into_iter().map(|e| e.change).collect(), batch.cursor))
+}
+
+#[test]
+fn cdc_streams_normalized_changes_in_pages() -> Result<(), Box<dyn std::error::Error>> {
+    let mut peer = TestPeer::new()?;
+    let cue = peer.create_record("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    let stage = peer.create_record("Location", vec![])?;
+    let edge = peer.create_edge("at", cue, stage)?;
+    peer.clear_field(cue, "label")?;
+    peer.delete_entity(stage)?;
+
+    // Events carry the current state: the label is cleared and the stage gone
+    let (changes, cursor) = cdc_changes(&peer, 0)?;
+    let at = |deleted| CdcChange::EdgeChanged {
+        edge_id: edge,
+        edge_type: "at".into(),
+        source_id: cue,
+        target_id: stage,
+        deleted,
+    };
+    assert_eq!(
+        changes,
+        vec![
+            CdcChange::EntityUpserted { entity_id: cue, facets: vec!["Cue".into()] },
+            CdcChange::FieldChanged { entity_id: cue, field_key: "label".into(), value: None },
+            CdcChange::EntityDeleted { entity_id: stage },
+            at(true),
+            CdcChange::FieldChanged { entity_id: cue, field_key: "label".into(), value: None },
+            CdcChange::EntityDeleted { entity_id: stage },
+        ]
+    );
+    assert_eq!(peer.engine.cdc_events(cursor, 100)?, CdcBatch { events: vec![], cursor });
+
+    // Small pages resume where the last one stopped and cover the same events
+    let (mut paged, mut after) = (Vec::new(), 0);
+    loop {
+        let batch = peer.engine.cdc_events(after, 2)?;
+        if batch.events.is_empty() {
+            break;
+        }
+        assert!(batch.events.len() <= 2 && batch.events.iter().all(|e| e.cursor > after && e.cursor <= batch.cursor));
+        paged.extend(batch.events.into_iter().map(|e| e.change));
+        after = batch.cursor;
+    }
+    assert_eq!((paged, after), (changes, cursor));
+
+    peer.set_field(cue, "label", FieldValue::Text("Q2".into()))?;
+    let (changes, _) = cdc_changes(&peer, cursor)?;
+    assert_eq!(
+        changes,
+        vec![CdcChange::FieldChanged { entity_id: cue, field_key: "label".into(), value: Some(FieldValue::Text("Q2".into())) }]
+    );
+    Ok(())
+}
+
+#[test]
+fn cdc_cursor_covers_synced_ops_and_conflicts_across_restarts() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let cue = net.peer_mut(a).create_record("Cue", vec![])?;
+    net.sync_to(a, b)?;
+    let (_, cursor) = cdc_changes(net.peer(b), 0)?;
+
+    // a's older write reaches b after b's own, and conflicts with it
+    net.peer_mut(a).set_field(cue, "label", FieldValue::Text("from a".into()))?;
+    net.peer_mut(b).set_field(cue, "label", FieldValue::Text("from b".into()))?;
+    net.restart_peer(b)?;
+    net.sync_to(a, b)?;
+    let conflict = net.peer(b).engine.get_open_conflicts()?.pop().expect("concurrent writes conflict");
+
+    let (changes, _) = cdc_changes(net.peer(b), cursor)?;
+    let label = net.peer(b).engine.get_field(cue, "label")?;
+    let field = CdcChange::FieldChanged { entity_id: cue, field_key: "label".into(), value: label };
+    let open = CdcChange::Conflict {
+        conflict_id: conflict.conflict_id,
+        entity_id: cue,
+        field_key: "label".into(),
+        status: ConflictStatus::Open,
+    };
+    // Both competing writes report the conflict
+    assert_eq!(changes, vec![field.clone(), open.clone(), field, open]);
+    Ok(())
+}
+
+// ============================================================================
+// Webhooks
+// ============================================================================
+
+/// Records each request, failing while `down` is set.
+#[derive(Clone, Default)]
+struct RecordingTransport {
+    requests: Arc<Mutex<Vec<(String, serde_json::Value)>>>,
+    down: Arc<Mutex<bool>>,
+}
+
+impl WebhookTransport for RecordingTransport {
+    fn post(&mut self, url: &str, body: &str) -> Result<(), String> {
+        if *self.down.lock().unwrap() {
+            return Err("connection refused".into());
+        }
+        self.requests.lock().unwrap().push((url.to_string(), serde_json::from_str(body).unwrap()));
+        Ok(())
+    }
+}
+
+impl RecordingTransport {
+    /// Event types received so far, and forget them.
+    fn take_types(&self) -> Vec<String> {
+        let requests = std::mem::take(&mut *self.requests.lock().unwrap());
+        requests
+            .iter()
+            .flat_map(|(_, body)| body["events"].as_array().unwrap().clone())
+            .map(|event| event["type"].as_str().unwrap().to_string())
+            .collect()
+    }
+}
+
+#[test]
+fn webhooks_receive_filtered_events_once_dispatched() -> Result<(), Box<dyn std::error::Error>> {
+    let mut net = TestNetwork::new();
+    let a = net.add_peer()?;
+    let b = net.add_peer()?;
+    let transport = RecordingTransport::default();
+    net.peer_mut(b).engine.set_webhook_transport(transport.clone());
+    net.peer_mut(b).create_record("Cue", vec![])?;
+    let all = net.peer_mut(b).engine.register_webhook("http://hooks.example/all", &WebhookFilter::new())?;
+    let cues = net.peer_mut(b).engine.register_webhook(
+        "http://hooks.example/cues",
+        &WebhookFilter::new().facets(["Cue"]).changes(["field_changed"]),
+    )?;
+    let ftp = net.peer_mut(b).engine.register_webhook("ftp://hooks.example", &WebhookFilter::new());
+    assert!(matches!(ftp, Err(EngineError::Rejected(_))));
+    assert!(transport.take_types().is_empty(), "registering starts after existing history");
+
+    // A local write
+    let cue = net.peer_mut(b).create_record("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    assert!(transport.requests.lock().unwrap().is_empty(), "writes don't block on endpoints");
+    assert!(net.peer(b).engine.webhooks_due());
+    net.peer_mut(b).engine.dispatch_webhooks()?;
+    assert!(!net.peer(b).engine.webhooks_due());
+    let requests = transport.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 2);
+    let (url, body) = &requests[1];
+    assert_eq!(url, "http://hooks.example/cues");
+    assert_eq!(body["webhook_id"], serde_json::json!(cues));
+    assert_eq!(body["events"][0]["entity_id"], serde_json::json!(cue));
+    assert_eq!(body["events"][0]["value"], serde_json::json!({ "type": "text", "value": "Q1" }));
+    assert_eq!(transport.take_types(), vec!["entity_upserted", "field_changed", "field_changed"]);
+
+    // An ingested bundle; the page isn't a cue, so only `all` hears of it
+    let page = net.peer_mut(a).create_record("Page", vec![("number", FieldValue::Integer(4))])?;
+    net.sync_to(a, b)?;
+    net.peer_mut(b).engine.dispatch_webhooks()?;
+    let requests = std::mem::take(&mut *transport.requests.lock().unwrap());
+    assert_eq!(requests.iter().map(|(url, _)| url.as_str()).collect::<Vec<_>>(), vec!["http://hooks.example/all"]);
+
+    assert!(net.peer_mut(b).engine.unregister_webhook(all)?);
+    assert!(!net.peer_mut(b).engine.unregister_webhook(all)?);
+    net.peer_mut(b).set_field(page, "number", FieldValue::Integer(5))?;
+    net.peer_mut(b).engine.dispatch_webhooks()?;
+    assert!(transport.take_types().is_empty());
+    assert_eq!(net.peer(b).engine.list_webhooks()?.iter().map(|w| w.webhook_id).collect::<Vec<_>>(), vec![cues]);
+    Ok(())
+}
+
+#[test]
+fn failed_webhook_deliveries_back_off_and_resume_after_restart() -> Result<(), Box<dyn std::error::Error>> {
+    const T0: u64 = 1_700_000_000_000;
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("hooks.db");
+    let path = path.to_str().unwrap();
+    let clock = ManualClock::new(T0);
+    let open = || EngineBuilder::new().clock_source(clock.clone()).open(ActorIdentity::generate(), path);
+    let mut engine = open()?;
+    let transport = RecordingTransport::default();
+    engine.set_webhook_transport(transport.clone());
+    let webhook_id = engine.register_webhook("http://hooks.example/", &WebhookFilter::new())?;
+
+    *transport.down.lock().unwrap() = true;
+    let (cue, _) = engine.create_entity(Some("Cue"))?;
+    engine.dispatch_webhooks()?;
+    let record = engine.list_webhooks()?.remove(0);
+    assert_eq!((record.attempts, record.retry_at), (1, Some(T0 as i64 + 1_000)));
+    assert_eq!(record.last_error.as_deref(), Some("connection refused"));
+
+    // Not due yet; then due, but still failing, so the delay doubles
+    assert_eq!(engine.dispatch_webhooks()?, WebhookReport::default());
+    clock.advance(1_000);
+    let report = engine.dispatch_webhooks()?;
+    assert_eq!((report.failed[0].attempts, report.failed[0].retry_at), (2, T0 as i64 + 3_000));
+
+    // Delivery state is stored: a restarted engine delivers the backlog once due
+    drop(engine);
+    let mut engine = open()?;
+    engine.set_webhook_transport(transport.clone());
+    *transport.down.lock().unwrap() = false;
+    clock.advance(2_000);
+    engine.set_field(cue, "label", FieldValue::Text("Q1".into()))?;
+    engine.dispatch_webhooks()?;
+    assert_eq!(transport.take_types(), vec!["entity_upserted", "field_changed"]);
+    let record = engine.list_webhooks()?.remove(0);
+    assert_eq!((record.webhook_id, record.attempts, record.retry_at, record.last_error), (webhook_id, 0, None, None));
+    assert_eq!(record.cursor, engine.cdc_tail()?);
+    Ok(())
+}
+
+// ============================================================================
+// Editing Presence
+// ============================================================================
+
+#[test]
+fn editing_hints_reach_peers_and_lapse() -> Result<(), Box<dyn std::error::Error>> {
+    const T0: u64 = 1_700_000_000_000;
+    let clock = ManualClock::new(T0);
+    let open = || EngineBuilder::new().clock_source(clock.clone()).open_in_memory(ActorIdentity::generate());
+    let (mut alice, mut bob) = (open()?, open()?);
+    let outgoing = alice.subscribe_presence();
+    let (cue, _) = alice.create_entity(Some("Cue"))?;
+
+    alice.announce_editing(cue, Some("label"), Duration::from_secs(5))?;
+    alice.announce_editing(cue, None, Duration::from_secs(30))?;
+    for hint in outgoing.try_iter() {
+        assert!(bob.receive_presence(hint)?);
+    }
+    let editors = bob.current_editors(cue)?;
+    assert_eq!(editors.iter().map(|e| (e.actor_id, e.field_key.as_deref())).collect::<Vec<_>>(), vec![
+        (alice.actor_id(), None),
+        (alice.actor_id(), Some("label")),
+    ]);
+    assert_eq!(editors[1].expires_at, T0 + 5_000);
+    // An actor isn't told about itself, and nothing reached the oplog
+    assert!(alice.current_editors(cue)?.is_empty());
+    assert_eq!(bob.get_vector_clock()?, Default::default());
+
+    // The field hint lapses on its own; the entity hint is withdrawn
+    clock.advance(5_000);
+    assert_eq!(bob.current_editors(cue)?.len(), 1);
+    alice.stop_editing(cue, None)?;
+    bob.receive_presence(outgoing.try_recv()?)?;
+    assert!(bob.current_editors(cue)?.is_empty());
+
+    // Hints only go out for live entities
+    alice.delete_entity(cue)?;
+    assert!(alice.announce_editing(cue, None, Duration::from_secs(5)).is_err());
+    Ok(())
+}
+
+#[test]
+fn relayed_hints_are_applied_once() -> Result<(), Box<dyn std::error::Error>> {
+    const T0: u64 = 1_700_000_000_000;
+    let clock = ManualClock::new(T0);
+    let open = || EngineBuilder::new().clock_source(clock.clone()).open_in_memory(ActorIdentity::generate());
+    let (mut alice, mut hub, mut carol) = (open()?, open()?, open()?);
+    let (from_alice, from_hub, from_carol) = (alice.subscribe_presence(), hub.subscribe_presence(), carol.subscribe_presence());
+    let (cue, _) = alice.create_entity(Some("Cue"))?;
+
+    // The hub passes what it receives on; carol's copy coming back is stale
+    alice.announce_editing(cue, None, Duration::from_secs(5))?;
+    let first = from_alice.try_recv()?;
+    assert!(hub.receive_presence(first.clone())?);
+    assert!(carol.receive_presence(from_hub.try_recv()?)?);
+    assert!(!hub.receive_presence(from_carol.try_recv()?)?);
+    assert!(from_hub.try_recv().is_err());
+    assert_eq!(carol.current_editors(cue)?[0].actor_id, alice.actor_id());
+
+    // A withdrawal can't be undone by an older hint arriving late
+    alice.stop_editing(cue, None)?;
+    assert!(carol.receive_presence(from_alice.try_recv()?)?);
+    assert!(!carol.receive_presence(first)?);
+    assert!(carol.current_editors(cue)?.is_empty());
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::session::run_session;
+use crate::{SharedEngine, SyncConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// Waiting to retry after a failed or dropped connection.
+    Disconnected,
+    Stopped,
+}
+
+/// A background connection to a sync server that reconnects with exponential
+/// backoff until stopped. Each (re)connect resumes from what the server has
+/// acknowledged, so nothing is lost across drops.
+pub struct SyncClient {
+    shutdown: watch::Sender<bool>,
+    state: watch::Receiver<ConnectionState>,
+    task: JoinHandle<()>,
+}
+
+impl SyncClient {
+    /// Start syncing `engine` with the server at `url` (e.g. `ws://host:port`).
+    pub fn spawn(engine: SharedEngine, url: impl Into<String>, config: SyncConfig) -> Self {
+        let url = url.into();
+        let (shutdown, mut shutdown_rx) = watch::channel(false);
+        let (state_tx, state) = watch::channel(ConnectionState::Connecting);
+
+        let task = tokio::spawn(async move {
+            let mut delay = config.reconnect_initial_delay;
+            while !*shutdown_rx.borrow() {
+                let _ = state_tx.send(ConnectionState::Connecting);
+                if let Ok((ws, _)) = tokio_tungstenite::connect_async(url.as_str()).await {
+                    let _ = state_tx.send(ConnectionState::Connected);
+                    delay = config.reconnect_initial_delay;
+                    let _ = run_session(engine.clone(), ws, &config, shutdown_rx.clone()).await;
+                }
+                if *shutdown_rx.borrow() {
+                    break;
+                }
+                let _ = state_tx.send(ConnectionState::Disconnected);
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = shutdown_rx.changed() => break,
+                }
+                delay = (delay * 2).min(config.reconnect_max_delay);
+            }
+            let _ = state_tx.send(ConnectionState::Stopped);
+        });
+
+        Self { shutdown, state, task }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+
+    /// Wait until the connection reaches `state`.
+    pub async fn wait_for(&mut self, state: ConnectionState) {
+        let _ = self.state.wait_for(|s| *s == state).await;
+    }
+
+    /// Close the connection and stop reconnecting.
+    pub async fn stop(self) {
+        let _ = self.shutdown.send(true);
+        let _ = self.task.await;
+    }
+}
@@ -0,0 +1,43 @@
+use openprod_core::CoreError;
+use openprod_engine::EngineError;
+use openprod_storage::StorageError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum NetError {
+    #[error("engine error: {0}")]
+    Engine(#[from] EngineError),
+
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("core error: {0}")]
+    Core(#[from] CoreError),
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("websocket error: {0}")]
+    WebSocket(String),
+
+    #[error("protocol error: {0}")]
+    Protocol(String),
+
+    #[error("invalid bundle: {0}")]
+    InvalidBundle(String),
+
+    #[error("connection closed")]
+    Closed,
+
+    #[error("engine lock poisoned")]
+    Poisoned,
+
+    #[error("engine task failed: {0}")]
+    Task(String),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for NetError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        NetError::WebSocket(e.to_string())
+    }
+}
@@ -0,0 +1,182 @@
+//! Stateless HTTP sync endpoints, enough to stand up a simple relay server.
+//!
+//! - `POST /sync`: body is a msgpack `SyncRequest` (the caller's vector clock,
+//!   sync scope and capabilities); responds with a `SyncResponse` holding the
+//!   bundles the caller is missing, trimmed to its scope, less those it can't
+//!   materialize. At most `limit` bundles come back at once; `next` then holds
+//!   the clock to ask with for the rest.
+//! - `POST /bundles`: body is a msgpack `PushRequest`; every bundle is
+//!   signature-checked before any is ingested, then all are ingested in one
+//!   transaction. Sealed bundles are opened with the server's workspace key,
+//!   or quarantined without one. Responds with an `IngestResponse`.
+//!
+//! Engine calls run on the blocking thread pool so slow storage doesn't stall
+//! the async runtime.
+
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use openprod_core::{
+    capabilities::Capabilities,
+    operations::{Bundle, Operation, RawBundle},
+    sealed::SealedBundle,
+    sync_scope::SyncScope,
+    vector_clock::VectorClock,
+};
+use openprod_engine::EngineError;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+use crate::error::NetError;
+use crate::session::lock;
+use crate::SharedEngine;
+
+const MSGPACK: &str = "application/msgpack";
+
+/// Most bundles one `/sync` response carries, whatever the caller asks for.
+pub const MAX_SYNC_PAGE: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncRequest {
+    pub vector_clock: VectorClock,
+    /// What the caller replicates; everything if left out.
+    #[serde(default)]
+    pub scope: SyncScope,
+    /// What the caller materializes; bundles beyond it are held back. Callers
+    /// that leave it out get everything.
+    #[serde(default)]
+    pub capabilities: Option<Capabilities>,
+    /// Most bundles to return; capped at (and defaulting to) `MAX_SYNC_PAGE`.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleBody {
+    pub bundle: Bundle,
+    pub operations: Vec<Operation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncResponse {
+    /// Missing bundles in HLC order, ready to pass to `ingest_bundles`.
+    pub bundles: Vec<BundleBody>,
+    pub vector_clock: VectorClock,
+    /// Set when more bundles remain: send it as the next request's
+    /// `vector_clock`. The caller's own clock won't do, as it doesn't cover
+    /// bundles held back from this page.
+    #[serde(default)]
+    pub next: Option<VectorClock>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushRequest {
+    pub bundles: Vec<BundleBody>,
+    /// Bundles encrypted to the workspace key, ingested after `bundles`.
+    #[serde(default)]
+    pub sealed: Vec<SealedBundle>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestResponse {
+    /// Conflicts the ingest opened on the server.
+    pub conflicts: usize,
+    /// Bundles set aside in quarantine, e.g. sealed ones the server has no key for.
+    #[serde(default)]
+    pub quarantined: usize,
+    pub vector_clock: VectorClock,
+}
+
+/// Router serving `POST /sync` and `POST /bundles` for `engine`.
+pub fn router(engine: SharedEngine) -> Router {
+    Router::new()
+        .route("/sync", post(sync))
+        .route("/bundles", post(push_bundles))
+        .with_state(engine)
+}
+
+async fn sync(State(engine): State<SharedEngine>, body: Bytes) -> Result<Response, NetError> {
+    let request: SyncRequest = decode(&body)?;
+    let response = blocking(move || {
+        let engine = lock(&engine)?;
+        if let Some(capabilities) = &request.capabilities {
+            engine.negotiate(capabilities)?;
+        }
+        let limit = request.limit.unwrap_or(MAX_SYNC_PAGE).min(MAX_SYNC_PAGE);
+        let page = engine.bundles_since_page(
+            &request.vector_clock,
+            &request.scope,
+            request.capabilities.as_ref(),
+            limit,
+        )?;
+        let bundles = page.bundles.into_iter().map(|(bundle, operations)| BundleBody { bundle, operations }).collect();
+        Ok(SyncResponse { bundles, vector_clock: engine.get_vector_clock()?, next: page.next })
+    })
+    .await?;
+    msgpack(&response)
+}
+
+async fn push_bundles(State(engine): State<SharedEngine>, body: Bytes) -> Result<Response, NetError> {
+    let request: PushRequest = decode(&body)?;
+    for body in &request.bundles {
+        verify_bundle(&body.bundle, &body.operations)?;
+    }
+    for sealed in &request.sealed {
+        sealed.bundle.verify_signature().map_err(|e| NetError::InvalidBundle(e.to_string()))?;
+    }
+    let batch = request
+        .bundles
+        .iter()
+        .map(|body| RawBundle::encode(&body.bundle, &body.operations))
+        .chain(request.sealed.iter().map(SealedBundle::to_raw))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let response = blocking(move || {
+        let mut engine = lock(&engine)?;
+        let report = engine.ingest_raw_bundles(&batch)?;
+        let vector_clock = engine.get_vector_clock()?;
+        Ok(IngestResponse { conflicts: report.conflicts.len(), quarantined: report.bundles_quarantined, vector_clock })
+    })
+    .await?;
+    msgpack(&response)
+}
+
+/// Run engine work on the blocking pool: storage calls block, and the engine
+/// lock may be held by a sync session for a while.
+async fn blocking<T, F>(work: F) -> Result<T, NetError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, NetError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work).await.map_err(|e| NetError::Task(e.to_string()))?
+}
+
+/// Check that the bundle header is signed by its actor and that every operation
+/// is signed, belongs to the bundle, and matches the signed count and checksum.
+pub fn verify_bundle(bundle: &Bundle, operations: &[Operation]) -> Result<(), NetError> {
+    bundle.verify(operations).map_err(|e| NetError::InvalidBundle(e.to_string()))
+}
+
+fn decode<T: DeserializeOwned>(body: &[u8]) -> Result<T, NetError> {
+    rmp_serde::from_slice(body).map_err(|e| NetError::Protocol(e.to_string()))
+}
+
+fn msgpack<T: Serialize>(value: &T) -> Result<Response, NetError> {
+    let bytes = rmp_serde::to_vec_named(value).map_err(|e| NetError::Protocol(e.to_string()))?;
+    Ok(([(header::CONTENT_TYPE, MSGPACK)], bytes).into_response())
+}
+
+impl IntoResponse for NetError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            NetError::Protocol(_) => StatusCode::BAD_REQUEST,
+            NetError::InvalidBundle(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            NetError::Engine(EngineError::IncompatiblePeer(_)) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
@@ -0,0 +1,48 @@
+//! Sync transports: a WebSocket connection that wires Engines together over the
+//! bundle sync protocol, and stateless HTTP pull/push endpoints for relays.
+//! Also a plain-HTTP transport for outbound webhooks.
+
+pub mod client;
+pub mod error;
+pub mod http;
+pub mod protocol;
+pub mod server;
+mod session;
+pub mod webhook;
+
+pub use client::{ConnectionState, SyncClient};
+pub use error::NetError;
+pub use http::router;
+pub use protocol::WireMessage;
+pub use server::serve;
+pub use webhook::HttpWebhookTransport;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use openprod_engine::Engine;
+
+/// An engine shared between the application and its sync connections.
+/// The lock is only held for individual engine calls, never across awaits.
+pub type SharedEngine = Arc<Mutex<Engine>>;
+
+/// Tuning for sync connections.
+#[derive(Debug, Clone)]
+pub struct SyncConfig {
+    /// Outgoing messages buffered per connection before senders wait (backpressure).
+    pub outbound_capacity: usize,
+    /// Delay before the first reconnect attempt; doubles on each failure.
+    pub reconnect_initial_delay: Duration,
+    /// Upper bound for the reconnect delay.
+    pub reconnect_max_delay: Duration,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            outbound_capacity: 64,
+            reconnect_initial_delay: Duration::from_millis(100),
+            reconnect_max_delay: Duration::from_secs(30),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use openprod_core::{capabilities::Capabilities, ids::ActorId, operations::RawBundle, vector_clock::VectorClock};
+use openprod_engine::PresenceHint;
+use serde::{Deserialize, Serialize};
+
+use crate::error::NetError;
+
+/// Messages exchanged over a sync connection, one per binary WebSocket frame.
+///
+/// Both sides open with `Hello`; each then streams the bundles the other has not
+/// acknowledged, pushes newly created bundles as they happen, and acknowledges
+/// what it has ingested. Editing-presence hints ride alongside, never acked
+/// or resent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WireMessage {
+    /// Sender's identity, everything it already holds, and what it can
+    /// materialize. Bundles it can't are parked instead of sent; peers that
+    /// predate capabilities leave them out and get everything.
+    Hello {
+        actor_id: ActorId,
+        vector_clock: VectorClock,
+        #[serde(default)]
+        capabilities: Option<Capabilities>,
+    },
+    /// A signed bundle and its operations, each encoded separately so the
+    /// receiver can quarantine one it can't decode and carry on. Sealed
+    /// (`SealedBundle::to_raw`) when the sender has a workspace key.
+    Bundle(RawBundle),
+    /// Sender's vector clock after ingesting; the receiver stops resending what it covers.
+    Ack { vector_clock: VectorClock },
+    /// Someone is editing (or stopped editing) an entity; see `Engine::announce_editing`.
+    Presence(PresenceHint),
+}
+
+impl WireMessage {
+    pub fn encode(&self) -> Result<Vec<u8>, NetError> {
+        rmp_serde::to_vec_named(self).map_err(|e| NetError::Protocol(e.to_string()))
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, NetError> {
+        rmp_serde::from_slice(bytes).map_err(|e| NetError::Protocol(e.to_string()))
+    }
+}
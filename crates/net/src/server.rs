@@ -0,0 +1,25 @@
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+
+use crate::error::NetError;
+use crate::session::run_session;
+use crate::{SharedEngine, SyncConfig};
+
+/// Accept sync connections on `listener` forever, one session task per client.
+/// Bundles created locally or received from any client are pushed live to
+/// every other connected client.
+pub async fn serve(engine: SharedEngine, listener: TcpListener, config: SyncConfig) -> Result<(), NetError> {
+    // Sessions end when their client disconnects; the server never asks them to stop
+    let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let engine = engine.clone();
+        let config = config.clone();
+        let shutdown = shutdown_rx.clone();
+        tokio::spawn(async move {
+            if let Ok(ws) = tokio_tungstenite::accept_async(stream).await {
+                let _ = run_session(engine, ws, &config, shutdown).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::sync::MutexGuard;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use openprod_core::capabilities::Capabilities;
+use openprod_core::ids::{ActorId, BundleId};
+use openprod_core::operations::{Bundle, Operation, RawBundle};
+use openprod_engine::Engine;
+use openprod_storage::Storage;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, watch};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::error::NetError;
+use crate::protocol::WireMessage;
+use crate::{SharedEngine, SyncConfig};
+
+/// How often the notification bridge checks whether its session has ended.
+const NOTIFY_POLL: Duration = Duration::from_millis(100);
+
+pub(crate) fn lock(engine: &SharedEngine) -> Result<MutexGuard<'_, Engine>, NetError> {
+    engine.lock().map_err(|_| NetError::Poisoned)
+}
+
+/// Run one sync connection until the peer disconnects or `shutdown` flips to true.
+pub(crate) async fn run_session<S>(
+    engine: SharedEngine,
+    ws: WebSocketStream<S>,
+    config: &SyncConfig,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), NetError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sink, mut stream) = ws.split();
+
+    // Writer: a bounded queue so producers wait when the socket is slow
+    let (out_tx, mut out_rx) = mpsc::channel::<WireMessage>(config.outbound_capacity);
+    let writer = tokio::spawn(async move {
+        while let Some(message) = out_rx.recv().await {
+            sink.send(Message::Binary(message.encode()?)).await?;
+        }
+        let _ = sink.close().await;
+        Ok::<(), NetError>(())
+    });
+
+    let (hello, bundle_rx, presence_rx) = {
+        let mut engine = lock(&engine)?;
+        let hello = WireMessage::Hello {
+            actor_id: engine.actor_id(),
+            vector_clock: engine.get_vector_clock()?,
+            capabilities: Some(engine.capabilities()),
+        };
+        (hello, engine.subscribe_bundles(), engine.subscribe_presence())
+    };
+    out_tx.send(hello).await.map_err(|_| NetError::Closed)?;
+
+    let mut notify_rx = bridge(bundle_rx, config.outbound_capacity);
+    let mut presence_rx = bridge(presence_rx, config.outbound_capacity);
+
+    let mut remote: Option<Remote> = None;
+    // Bundles received on this connection, so live push doesn't echo them back
+    let mut received: HashSet<BundleId> = HashSet::new();
+
+    let result = loop {
+        tokio::select! {
+            frame = stream.next() => {
+                let bytes = match frame {
+                    Some(Ok(Message::Binary(bytes))) => bytes,
+                    Some(Ok(Message::Close(_))) | None => break Ok(()),
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => break Err(e.into()),
+                };
+                let handled = handle_message(&engine, &out_tx, &mut remote, &mut received, WireMessage::decode(&bytes)?).await;
+                if let Err(e) = handled {
+                    break Err(e);
+                }
+            }
+            Some(bundle_id) = notify_rx.recv(), if remote.is_some() => {
+                if received.contains(&bundle_id) {
+                    continue;
+                }
+                if let Some(message) = load_bundle_message(&engine, bundle_id, remote.as_ref())?
+                    && out_tx.send(message).await.is_err()
+                {
+                    break Err(NetError::Closed);
+                }
+            }
+            Some(hint) = presence_rx.recv(), if remote.is_some() => {
+                // Don't hand a peer back its own hints
+                if remote.as_ref().is_some_and(|remote| remote.actor_id == hint.actor_id) {
+                    continue;
+                }
+                if out_tx.send(WireMessage::Presence(hint)).await.is_err() {
+                    break Err(NetError::Closed);
+                }
+            }
+            _ = shutdown.changed() => break Ok(()),
+        }
+    };
+
+    drop(out_tx);
+    let _ = writer.await;
+    result
+}
+
+/// The peer on the other end, once its `Hello` arrives.
+struct Remote {
+    actor_id: ActorId,
+    /// None for peers that predate capability exchange: they get everything.
+    capabilities: Option<Capabilities>,
+}
+
+/// Bridge one of the engine's notification hooks into the async world. The
+/// thread exits once the returned receiver is dropped.
+fn bridge<T: Send + 'static>(hook: std::sync::mpsc::Receiver<T>, capacity: usize) -> mpsc::Receiver<T> {
+    let (tx, rx) = mpsc::channel::<T>(capacity);
+    tokio::task::spawn_blocking(move || loop {
+        match hook.recv_timeout(NOTIFY_POLL) {
+            Ok(item) => {
+                if tx.blocking_send(item).is_err() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) if !tx.is_closed() => {}
+            Err(_) => break,
+        }
+    });
+    rx
+}
+
+async fn handle_message(
+    engine: &SharedEngine,
+    out_tx: &mpsc::Sender<WireMessage>,
+    remote: &mut Option<Remote>,
+    received: &mut HashSet<BundleId>,
+    message: WireMessage,
+) -> Result<(), NetError> {
+    match message {
+        WireMessage::Hello { actor_id, vector_clock, capabilities } => {
+            let outbox = {
+                let mut engine = lock(engine)?;
+                // Refuse peers on another wire format before touching anything
+                if let Some(capabilities) = &capabilities {
+                    engine.negotiate(capabilities)?;
+                }
+                engine.mark_peer_synced(actor_id, &vector_clock)?;
+                engine.pending_bundles_for(actor_id, capabilities.as_ref())?
+            };
+            let remote = remote.insert(Remote { actor_id, capabilities });
+            // Load one bundle at a time so the lock is never held while waiting on the socket
+            for bundle_id in outbox {
+                if let Some(message) = load_bundle_message(engine, bundle_id, Some(remote))? {
+                    out_tx.send(message).await.map_err(|_| NetError::Closed)?;
+                }
+            }
+        }
+        WireMessage::Bundle(raw) => {
+            if let Ok(bundle) = raw.decode_header() {
+                received.insert(bundle.bundle_id);
+            }
+            let ack = {
+                let mut engine = lock(engine)?;
+                engine.ingest_raw_bundles(std::slice::from_ref(&raw))?;
+                WireMessage::Ack { vector_clock: engine.get_vector_clock()? }
+            };
+            out_tx.send(ack).await.map_err(|_| NetError::Closed)?;
+        }
+        WireMessage::Ack { vector_clock } => {
+            let peer = remote.as_ref().ok_or_else(|| NetError::Protocol("ack before hello".into()))?;
+            lock(engine)?.mark_peer_synced(peer.actor_id, &vector_clock)?;
+        }
+        WireMessage::Presence(hint) => {
+            lock(engine)?.receive_presence(hint)?;
+        }
+    }
+    Ok(())
+}
+
+/// Load a stored bundle for sending, skipping bundles authored by the remote
+/// and parking (skipping, unacknowledged) ones it can't materialize. Sealed
+/// with the workspace key when the engine has one.
+fn load_bundle_message(
+    engine: &SharedEngine,
+    bundle_id: BundleId,
+    remote: Option<&Remote>,
+) -> Result<Option<WireMessage>, NetError> {
+    let engine = lock(engine)?;
+    let Some((bundle, operations)) = load_bundle(&engine, bundle_id)? else {
+        return Ok(None);
+    };
+    if let Some(remote) = remote {
+        let parked = remote
+            .capabilities
+            .as_ref()
+            .is_some_and(|capabilities| !operations.iter().all(|op| capabilities.supports(&op.payload)));
+        if bundle.actor_id == remote.actor_id || parked {
+            return Ok(None);
+        }
+    }
+    let raw = if engine.has_workspace_key() {
+        engine.seal_bundle(bundle_id)?.to_raw()?
+    } else {
+        RawBundle::encode(&bundle, &operations)?
+    };
+    Ok(Some(WireMessage::Bundle(raw)))
+}
+
+/// Load a stored bundle with its operations. `None` if the creating transaction
+/// rolled back after notifying.
+pub(crate) fn load_bundle(
+    engine: &Engine,
+    bundle_id: BundleId,
+) -> Result<Option<(Bundle, Vec<Operation>)>, NetError> {
+    let Some(bundle) = engine.storage().get_bundle(bundle_id)? else {
+        return Ok(None);
+    };
+    let operations = engine.get_ops_by_bundle(bundle_id)?;
+    Ok(Some((bundle, operations)))
+}
@@ -0,0 +1,67 @@
+//! A `WebhookTransport` that POSTs over plain HTTP/1.1 with blocking std
+//! sockets. HTTPS endpoints need a transport built on a TLS client.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use openprod_engine::WebhookTransport;
+
+/// Posts each request on a fresh connection and treats any 2xx status as
+/// delivered.
+#[derive(Debug, Clone)]
+pub struct HttpWebhookTransport {
+    timeout: Duration,
+}
+
+impl HttpWebhookTransport {
+    pub fn new() -> Self {
+        Self { timeout: Duration::from_secs(10) }
+    }
+
+    /// Bound on connecting, and on each read or write (default 10 s).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Default for HttpWebhookTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WebhookTransport for HttpWebhookTransport {
+    fn post(&mut self, url: &str, body: &str) -> Result<(), String> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| format!("unsupported webhook url {url:?}"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let address = if authority.contains(':') { authority.to_string() } else { format!("{authority}:80") };
+        let socket_addr = address
+            .to_socket_addrs()
+            .map_err(|e| format!("{authority}: {e}"))?
+            .next()
+            .ok_or_else(|| format!("{authority}: no address"))?;
+
+        let mut stream = TcpStream::connect_timeout(&socket_addr, self.timeout).map_err(|e| e.to_string())?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(|e| e.to_string())?;
+        write!(
+            stream,
+            "POST {path} HTTP/1.1\r\nHost: {authority}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line).map_err(|e| e.to_string())?;
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            Some(status) => Err(format!("HTTP {status}")),
+            None => Err(format!("malformed response {:?}", status_line.trim_end())),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::sync::{Arc, Mutex};
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::http::{Request, StatusCode};
+use openprod_core::{capabilities::FORMAT_VERSION, field_value::FieldValue, identity::ActorIdentity, operations::OperationPayload, sealed::WorkspaceKey, sync_scope::SyncScope};
+use openprod_engine::Engine;
+use openprod_net::http::{BundleBody, IngestResponse, PushRequest, SyncRequest, SyncResponse};
+use openprod_net::{SharedEngine, router};
+use openprod_storage::{SqliteStorage, Storage};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tower::ServiceExt;
+
+fn shared_engine() -> Result<SharedEngine, Box<dyn std::error::Error>> {
+    let storage = SqliteStorage::open_in_memory()?;
+    Ok(Arc::new(Mutex::new(Engine::new(ActorIdentity::generate(), storage)?)))
+}
+
+async fn post<B: Serialize, R: DeserializeOwned>(
+    app: &Router,
+    path: &str,
+    body: &B,
+) -> Result<(StatusCode, Option<R>), Box<dyn std::error::Error>> {
+    let request = Request::post(path).body(Body::from(rmp_serde::to_vec_named(body)?))?;
+    let response = app.clone().oneshot(request).await?;
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await?;
+    let decoded = if status.is_success() { Some(rmp_serde::from_slice(&bytes)?) } else { None };
+    Ok((status, decoded))
+}
+
+/// Everything `engine` holds that a peer with an empty clock is missing.
+fn all_bundles(engine: &SharedEngine) -> Result<Vec<BundleBody>, Box<dyn std::error::Error>> {
+    let engine = engine.lock().unwrap();
+    let mut bundles = Vec::new();
+    for bundle_id in engine.bundles_missing_from(&Default::default())? {
+        let bundle = engine.storage().get_bundle(bundle_id)?.ok_or("missing bundle")?;
+        let operations = engine.get_ops_by_bundle(bundle_id)?;
+        bundles.push(BundleBody { bundle, operations });
+    }
+    Ok(bundles)
+}
+
+#[tokio::test]
+async fn pull_returns_only_missing_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let client = shared_engine()?;
+    let app = router(server.clone());
+    let (entity, _) = server
+        .lock()
+        .unwrap()
+        .create_entity_with_fields("Task", vec![("title", FieldValue::Text("first".into()))])?;
+
+    let request = SyncRequest { vector_clock: client.lock().unwrap().get_vector_clock()?, scope: SyncScope::All, capabilities: None, limit: None };
+    let (status, response) = post::<_, SyncResponse>(&app, "/sync", &request).await?;
+    assert_eq!(status, StatusCode::OK);
+    let response = response.unwrap();
+    assert_eq!(response.bundles.len(), 1);
+
+    let batch: Vec<_> = response.bundles.into_iter().map(|b| (b.bundle, b.operations)).collect();
+    client.lock().unwrap().ingest_bundles(&batch)?;
+    assert_eq!(client.lock().unwrap().get_field(entity, "title")?, Some(FieldValue::Text("first".into())));
+
+    // A caught-up client gets nothing back
+    let request = SyncRequest { vector_clock: client.lock().unwrap().get_vector_clock()?, scope: SyncScope::All, capabilities: None, limit: None };
+    let (_, response) = post::<_, SyncResponse>(&app, "/sync", &request).await?;
+    assert!(response.unwrap().bundles.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn pull_pages_through_missing_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let client = shared_engine()?;
+    let app = router(server.clone());
+    let mut tasks = Vec::new();
+    for _ in 0..5 {
+        tasks.push(server.lock().unwrap().create_entity(Some("Task"))?.0);
+    }
+
+    let mut request = SyncRequest { vector_clock: Default::default(), scope: SyncScope::All, capabilities: None, limit: Some(2) };
+    let mut pages = Vec::new();
+    loop {
+        let (status, response) = post::<_, SyncResponse>(&app, "/sync", &request).await?;
+        assert_eq!(status, StatusCode::OK);
+        let response = response.unwrap();
+        pages.push(response.bundles.len());
+        let batch: Vec<_> = response.bundles.into_iter().map(|b| (b.bundle, b.operations)).collect();
+        client.lock().unwrap().ingest_bundles(&batch)?;
+        match response.next {
+            Some(next) => request.vector_clock = next,
+            None => break,
+        }
+    }
+    assert_eq!(pages, vec![2, 2, 1]);
+    for task in tasks {
+        assert!(client.lock().unwrap().get_entity(task)?.is_some());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn pull_trims_bundles_to_the_callers_scope() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let app = router(server.clone());
+    let (task, _) = server.lock().unwrap().create_entity(Some("Task"))?;
+    server.lock().unwrap().create_entity(Some("Project"))?;
+
+    let request = SyncRequest { vector_clock: Default::default(), scope: SyncScope::facets(["Task"]), capabilities: None, limit: None };
+    let (status, response) = post::<_, SyncResponse>(&app, "/sync", &request).await?;
+    assert_eq!(status, StatusCode::OK);
+    let bundles = response.unwrap().bundles;
+    assert_eq!(bundles.len(), 1);
+    assert_eq!(bundles[0].operations[0].payload.entity_id(), Some(task));
+    Ok(())
+}
+
+#[tokio::test]
+async fn pull_holds_back_bundles_the_caller_cannot_materialize() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let app = router(server.clone());
+    let (x, _) = server.lock().unwrap().create_entity(Some("Task"))?;
+    let (y, _) = server.lock().unwrap().create_entity(Some("Task"))?;
+    server.lock().unwrap().create_edge("blocks", x, y)?;
+
+    let mut old = server.lock().unwrap().capabilities();
+    old.payload_types.remove("CreateEdge");
+    let request = SyncRequest { vector_clock: Default::default(), scope: SyncScope::All, capabilities: Some(old.clone()), limit: None };
+    let (status, response) = post::<_, SyncResponse>(&app, "/sync", &request).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.unwrap().bundles.len(), 2);
+
+    // Another wire format can't sync at all
+    old.format_version = FORMAT_VERSION + 1;
+    let request = SyncRequest { vector_clock: Default::default(), scope: SyncScope::All, capabilities: Some(old), limit: None };
+    let (status, _) = post::<_, SyncResponse>(&app, "/sync", &request).await?;
+    assert_eq!(status, StatusCode::CONFLICT);
+    Ok(())
+}
+
+#[tokio::test]
+async fn push_ingests_signed_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let client = shared_engine()?;
+    let app = router(server.clone());
+    let (entity, _) = client.lock().unwrap().create_entity(Some("Task"))?;
+    client.lock().unwrap().set_field(entity, "title", FieldValue::Text("pushed".into()))?;
+
+    let request = PushRequest { bundles: all_bundles(&client)?, sealed: Vec::new() };
+    let (status, response) = post::<_, IngestResponse>(&app, "/bundles", &request).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.unwrap().vector_clock, client.lock().unwrap().get_vector_clock()?);
+    assert_eq!(server.lock().unwrap().get_field(entity, "title")?, Some(FieldValue::Text("pushed".into())));
+    Ok(())
+}
+
+#[tokio::test]
+async fn push_quarantines_sealed_bundles_until_the_key_is_set() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let client = shared_engine()?;
+    let app = router(server.clone());
+    let key = WorkspaceKey::generate();
+    client.lock().unwrap().set_workspace_key(Some(key.clone()));
+    let (entity, _) = client
+        .lock()
+        .unwrap()
+        .create_entity_with_fields("Task", vec![("title", FieldValue::Text("secret".into()))])?;
+    let sealed = all_bundles(&client)?
+        .iter()
+        .map(|body| client.lock().unwrap().seal_bundle(body.bundle.bundle_id))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let request = PushRequest { bundles: Vec::new(), sealed };
+    let (status, response) = post::<_, IngestResponse>(&app, "/bundles", &request).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.unwrap().quarantined, 1);
+    assert!(server.lock().unwrap().get_entity(entity)?.is_none());
+
+    let mut server = server.lock().unwrap();
+    let quarantined = server.list_quarantined()?;
+    server.set_workspace_key(Some(key));
+    server.retry_quarantined(quarantined[0].quarantine_id)?;
+    assert_eq!(server.get_field(entity, "title")?, Some(FieldValue::Text("secret".into())));
+    Ok(())
+}
+
+#[tokio::test]
+async fn push_rejects_tampered_bundles() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let client = shared_engine()?;
+    let app = router(server.clone());
+    client
+        .lock()
+        .unwrap()
+        .create_entity_with_fields("Task", vec![("title", FieldValue::Text("honest".into()))])?;
+
+    let mut bundles = all_bundles(&client)?;
+    for op in &mut bundles[0].operations {
+        if let OperationPayload::SetField { value, .. } = &mut op.payload {
+            *value = FieldValue::Text("forged".into());
+        }
+    }
+    let (status, _) = post::<_, IngestResponse>(&app, "/bundles", &PushRequest { bundles, sealed: Vec::new() }).await?;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(server.lock().unwrap().get_vector_clock()?.entries().is_empty());
+
+    // Garbage bodies are a client error, not a server failure
+    let (status, _) = post::<_, IngestResponse>(&app, "/bundles", &"not a push request").await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    Ok(())
+}
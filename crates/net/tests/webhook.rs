@@ -0,0 +1,59 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use openprod_engine::WebhookTransport;
+use openprod_net::HttpWebhookTransport;
+
+/// Path and body of each request the server received.
+type Received = thread::JoinHandle<Vec<(String, String)>>;
+
+/// Answer one request per status, returning each request's path and body.
+fn serve(statuses: &'static [u16]) -> Result<(String, Received), std::io::Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/hooks/cues", listener.local_addr()?);
+    let handle = thread::spawn(move || {
+        statuses
+            .iter()
+            .map(|status| {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                write!(stream, "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\n\r\n").unwrap();
+                (request_line.split_whitespace().nth(1).unwrap().to_string(), String::from_utf8(body).unwrap())
+            })
+            .collect()
+    });
+    Ok((url, handle))
+}
+
+#[test]
+fn http_transport_posts_json_and_reports_status() -> Result<(), Box<dyn std::error::Error>> {
+    let (url, server) = serve(&[204, 503])?;
+    let mut transport = HttpWebhookTransport::new();
+    transport.post(&url, r#"{"events":[]}"#)?;
+    assert_eq!(transport.post(&url, "{}"), Err("HTTP 503".to_string()));
+    assert_eq!(
+        server.join().unwrap(),
+        vec![("/hooks/cues".to_string(), r#"{"events":[]}"#.to_string()), ("/hooks/cues".to_string(), "{}".to_string())]
+    );
+
+    assert!(transport.post("https://hooks.example/", "{}").is_err());
+    let closed = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    assert!(transport.post(&format!("http://{closed}/"), "{}").is_err());
+    Ok(())
+}
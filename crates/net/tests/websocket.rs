@@ -0,0 +1,279 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use openprod_core::capabilities::{Capabilities, FORMAT_VERSION};
+use openprod_core::{field_value::FieldValue, identity::ActorIdentity, ids::{ActorId, BundleId}, sealed::WorkspaceKey};
+use openprod_engine::Engine;
+use openprod_net::{ConnectionState, SharedEngine, SyncClient, SyncConfig, WireMessage, serve};
+use openprod_storage::SqliteStorage;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+fn shared_engine() -> Result<SharedEngine, Box<dyn std::error::Error>> {
+    let storage = SqliteStorage::open_in_memory()?;
+    Ok(Arc::new(Mutex::new(Engine::new(ActorIdentity::generate(), storage)?)))
+}
+
+fn fast_reconnect() -> SyncConfig {
+    SyncConfig {
+        reconnect_initial_delay: Duration::from_millis(20),
+        reconnect_max_delay: Duration::from_millis(200),
+        ..SyncConfig::default()
+    }
+}
+
+/// Poll until both engines hold the same vector clock.
+async fn wait_converged(a: &SharedEngine, b: &SharedEngine) -> Result<(), Box<dyn std::error::Error>> {
+    for _ in 0..200 {
+        let va = a.lock().unwrap().get_vector_clock()?;
+        let vb = b.lock().unwrap().get_vector_clock()?;
+        if va == vb {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    Err("engines did not converge".into())
+}
+
+/// Wait until `client` has no bundles left to resend to `peer`. Acks follow
+/// ingest, so they can land after the vector clocks already match.
+async fn wait_acked(client: &SharedEngine, peer: ActorId) -> Result<(), Box<dyn std::error::Error>> {
+    for _ in 0..200 {
+        if client.lock().unwrap().pending_bundles_for(peer, None)?.is_empty() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    Err("bundles were never acked".into())
+}
+
+async fn start_server(engine: &SharedEngine) -> Result<String, Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("ws://{}", listener.local_addr()?);
+    tokio::spawn(serve(engine.clone(), listener, SyncConfig::default()));
+    Ok(url)
+}
+
+/// Speak the protocol directly: open with a `Hello` carrying `capabilities`,
+/// then collect the bundles the server sends until it goes quiet. Also
+/// reports whether the server closed the connection.
+async fn bundles_offered_to(
+    url: &str,
+    capabilities: Capabilities,
+) -> Result<(Vec<BundleId>, bool), Box<dyn std::error::Error>> {
+    let (mut ws, _) = tokio_tungstenite::connect_async(url).await?;
+    let hello = WireMessage::Hello {
+        actor_id: ActorIdentity::generate().actor_id(),
+        vector_clock: Default::default(),
+        capabilities: Some(capabilities),
+    };
+    ws.send(Message::Binary(hello.encode()?)).await?;
+    let mut bundles = Vec::new();
+    loop {
+        match tokio::time::timeout(Duration::from_millis(300), ws.next()).await {
+            Ok(Some(Ok(Message::Binary(bytes)))) => {
+                if let WireMessage::Bundle(raw) = WireMessage::decode(&bytes)? {
+                    bundles.push(raw.decode_header()?.bundle_id);
+                }
+            }
+            Ok(Some(Ok(Message::Close(_)) | Err(_)) | None) => return Ok((bundles, true)),
+            Ok(Some(Ok(_))) => {}
+            Err(_) => return Ok((bundles, false)),
+        }
+    }
+}
+
+// ============================================================================
+// Initial Sync
+// ============================================================================
+
+#[tokio::test(flavor = "multi_thread")]
+async fn existing_data_converges_in_both_directions() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let client = shared_engine()?;
+    let (on_server, _) = server
+        .lock()
+        .unwrap()
+        .create_entity_with_fields("Task", vec![("title", FieldValue::Text("server".into()))])?;
+    let (on_client, _) = client
+        .lock()
+        .unwrap()
+        .create_entity_with_fields("Task", vec![("title", FieldValue::Text("client".into()))])?;
+
+    let url = start_server(&server).await?;
+    let sync = SyncClient::spawn(client.clone(), url, fast_reconnect());
+    wait_converged(&server, &client).await?;
+
+    assert_eq!(client.lock().unwrap().get_field(on_server, "title")?, Some(FieldValue::Text("server".into())));
+    assert_eq!(server.lock().unwrap().get_field(on_client, "title")?, Some(FieldValue::Text("client".into())));
+    sync.stop().await;
+    Ok(())
+}
+
+// ============================================================================
+// Live Push
+// ============================================================================
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_bundles_are_pushed_while_connected() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let client = shared_engine()?;
+    let url = start_server(&server).await?;
+    let mut sync = SyncClient::spawn(client.clone(), url, fast_reconnect());
+    sync.wait_for(ConnectionState::Connected).await;
+
+    let (entity, _) = server.lock().unwrap().create_entity(Some("Task"))?;
+    server.lock().unwrap().set_field(entity, "title", FieldValue::Text("live".into()))?;
+    wait_converged(&server, &client).await?;
+    assert_eq!(client.lock().unwrap().get_field(entity, "title")?, Some(FieldValue::Text("live".into())));
+
+    // And back the other way
+    client.lock().unwrap().set_field(entity, "title", FieldValue::Text("reply".into()))?;
+    wait_converged(&server, &client).await?;
+    assert_eq!(server.lock().unwrap().get_field(entity, "title")?, Some(FieldValue::Text("reply".into())));
+
+    // Acks record what the server has seen, so nothing is left to resend
+    let server_id = server.lock().unwrap().actor_id();
+    wait_acked(&client, server_id).await?;
+    sync.stop().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sealed_bundles_wait_in_quarantine_for_the_key() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let client = shared_engine()?;
+    let key = WorkspaceKey::generate();
+    client.lock().unwrap().set_workspace_key(Some(key.clone()));
+    let (entity, _) = client
+        .lock()
+        .unwrap()
+        .create_entity_with_fields("Task", vec![("title", FieldValue::Text("secret".into()))])?;
+
+    let url = start_server(&server).await?;
+    let mut sync = SyncClient::spawn(client.clone(), url, fast_reconnect());
+    sync.wait_for(ConnectionState::Connected).await;
+
+    // Without the key the server sets the bundle aside instead of failing the session
+    let mut quarantined = Vec::new();
+    for _ in 0..200 {
+        quarantined = server.lock().unwrap().list_quarantined()?;
+        if !quarantined.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].reason, "no workspace key configured");
+    assert!(server.lock().unwrap().get_entity(entity)?.is_none());
+    assert_eq!(sync.state(), ConnectionState::Connected);
+
+    server.lock().unwrap().set_workspace_key(Some(key));
+    server.lock().unwrap().retry_quarantined(quarantined[0].quarantine_id)?;
+    assert_eq!(server.lock().unwrap().get_field(entity, "title")?, Some(FieldValue::Text("secret".into())));
+    sync.stop().await;
+    Ok(())
+}
+
+// ============================================================================
+// Capabilities
+// ============================================================================
+
+#[tokio::test(flavor = "multi_thread")]
+async fn bundles_the_remote_cannot_materialize_are_parked() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let (x, x_bundle) = server.lock().unwrap().create_entity(Some("Task"))?;
+    let (y, y_bundle) = server.lock().unwrap().create_entity(Some("Task"))?;
+    server.lock().unwrap().create_edge("blocks", x, y)?;
+    let url = start_server(&server).await?;
+
+    // An older build without edges gets everything but the edge
+    let full = server.lock().unwrap().capabilities();
+    let mut old = full.clone();
+    old.payload_types.remove("CreateEdge");
+    let (offered, closed) = bundles_offered_to(&url, old).await?;
+    assert_eq!(offered, vec![x_bundle, y_bundle]);
+    assert!(!closed);
+
+    // Once it upgrades, the parked bundle goes out too
+    let (offered, _) = bundles_offered_to(&url, full).await?;
+    assert_eq!(offered.len(), 3);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn peers_on_another_wire_format_are_refused() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    server.lock().unwrap().create_entity(Some("Task"))?;
+    let url = start_server(&server).await?;
+
+    let mut future = server.lock().unwrap().capabilities();
+    future.format_version = FORMAT_VERSION + 1;
+    let (offered, closed) = bundles_offered_to(&url, future).await?;
+    assert!(offered.is_empty());
+    assert!(closed);
+    Ok(())
+}
+
+// ============================================================================
+// Editing Presence
+// ============================================================================
+
+#[tokio::test(flavor = "multi_thread")]
+async fn editing_hints_are_relayed_between_clients() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let (alice, bob) = (shared_engine()?, shared_engine()?);
+    let url = start_server(&server).await?;
+    let mut alice_sync = SyncClient::spawn(alice.clone(), url.clone(), fast_reconnect());
+    let mut bob_sync = SyncClient::spawn(bob.clone(), url, fast_reconnect());
+    alice_sync.wait_for(ConnectionState::Connected).await;
+    bob_sync.wait_for(ConnectionState::Connected).await;
+
+    let (entity, _) = alice.lock().unwrap().create_entity(Some("Task"))?;
+    let alice_id = alice.lock().unwrap().actor_id();
+    alice.lock().unwrap().announce_editing(entity, Some("title"), Duration::from_secs(30))?;
+    let mut editors = Vec::new();
+    for _ in 0..200 {
+        editors = bob.lock().unwrap().current_editors(entity)?;
+        if !editors.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    assert_eq!(editors.len(), 1);
+    assert_eq!((editors[0].actor_id, editors[0].field_key.as_deref()), (alice_id, Some("title")));
+    assert_eq!(server.lock().unwrap().current_editors(entity)?.len(), 1);
+
+    alice_sync.stop().await;
+    bob_sync.stop().await;
+    Ok(())
+}
+
+// ============================================================================
+// Reconnect
+// ============================================================================
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_reconnects_once_server_is_reachable() -> Result<(), Box<dyn std::error::Error>> {
+    let server = shared_engine()?;
+    let client = shared_engine()?;
+    let (entity, _) = client
+        .lock()
+        .unwrap()
+        .create_entity_with_fields("Task", vec![("title", FieldValue::Text("offline".into()))])?;
+
+    // Reserve a port, then free it so the first attempts fail
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let mut sync = SyncClient::spawn(client.clone(), format!("ws://{addr}"), fast_reconnect());
+    sync.wait_for(ConnectionState::Disconnected).await;
+
+    let listener = TcpListener::bind(addr).await?;
+    tokio::spawn(serve(server.clone(), listener, SyncConfig::default()));
+    sync.wait_for(ConnectionState::Connected).await;
+    wait_converged(&server, &client).await?;
+
+    assert_eq!(server.lock().unwrap().get_field(entity, "title")?, Some(FieldValue::Text("offline".into())));
+    sync.stop().await;
+    Ok(())
+}
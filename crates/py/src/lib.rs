@@ -0,0 +1,548 @@
+//! Python bindings (`import openprod`) for scripting against a workspace file.
+//!
+//! ```python
+//! ws = openprod.Workspace("show.db")
+//! with ws.transaction():
+//!     cue = ws.create_entity("Cue", {"label": "Q1", "duration": 5})
+//!     cue["label"] = "Q1 go"
+//! for cue in ws.entities("Cue"):
+//!     print(cue.id, dict(cue.fields()))
+//! ```
+//!
+//! Field values map to Python as `None`, `bool`, `int`, `float`, `str`,
+//! `bytes` and `Entity` (for entity references).
+
+use openprod_core::{
+    field_value::FieldValue,
+    identity::ActorIdentity,
+    ids::{ConflictId, EntityId, OverlayId},
+};
+use openprod_engine::{Engine, EngineError, UndoResult};
+use openprod_storage::{ConflictRecord, SqliteStorage};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyKeyError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes, PyDict, PyFloat, PyInt, PyString, PyType};
+use pyo3::{IntoPyObjectExt, PyTraverseError, PyVisit};
+
+create_exception!(openprod, OpenprodError, PyException);
+
+fn py_err(error: EngineError) -> PyErr {
+    match error {
+        EngineError::EntityNotFound(_)
+        | EngineError::EdgeNotFound(_)
+        | EngineError::ConflictNotFound(_)
+        | EngineError::OverlayNotFound(_)
+        | EngineError::BundleNotFound(_) => PyKeyError::new_err(error.to_string()),
+        _ => OpenprodError::new_err(error.to_string()),
+    }
+}
+
+fn parse_uuid(id: &str) -> PyResult<uuid::Uuid> {
+    uuid::Uuid::parse_str(id).map_err(|e| PyValueError::new_err(format!("invalid id {id:?}: {e}")))
+}
+
+// ============================================================================
+// Value conversion
+// ============================================================================
+
+fn to_field_value(value: &Bound<'_, PyAny>) -> PyResult<FieldValue> {
+    if value.is_none() {
+        Ok(FieldValue::Null)
+    } else if let Ok(b) = value.downcast::<PyBool>() {
+        // Checked before int: bool is an int subclass
+        Ok(FieldValue::Boolean(b.is_true()))
+    } else if value.is_instance_of::<PyInt>() {
+        Ok(FieldValue::Integer(value.extract()?))
+    } else if value.is_instance_of::<PyFloat>() {
+        Ok(FieldValue::Float(value.extract()?))
+    } else if value.is_instance_of::<PyString>() {
+        Ok(FieldValue::Text(value.extract()?))
+    } else if let Ok(bytes) = value.downcast::<PyBytes>() {
+        Ok(FieldValue::Bytes(bytes.as_bytes().to_vec()))
+    } else if let Ok(entity) = value.downcast::<Entity>() {
+        Ok(FieldValue::EntityRef(entity.borrow().entity_id))
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "unsupported field value type {}",
+            value.get_type().name()?
+        )))
+    }
+}
+
+fn from_field_value(py: Python<'_>, workspace: &Py<Workspace>, value: FieldValue) -> PyResult<PyObject> {
+    match value {
+        FieldValue::Null => Ok(py.None()),
+        FieldValue::Text(s) => s.into_py_any(py),
+        FieldValue::Integer(i) | FieldValue::Timestamp(i) => i.into_py_any(py),
+        FieldValue::Float(f) => f.into_py_any(py),
+        FieldValue::Boolean(b) => b.into_py_any(py),
+        FieldValue::EntityRef(entity_id) => Entity::new(py, workspace, entity_id).into_py_any(py),
+        FieldValue::BlobRef(hash) => PyBytes::new(py, hash.as_bytes()).into_py_any(py),
+        FieldValue::Bytes(bytes) => PyBytes::new(py, &bytes).into_py_any(py),
+    }
+}
+
+// ============================================================================
+// Workspace
+// ============================================================================
+
+/// An engine over a workspace file (or an in-memory database if `path` is
+/// omitted). Pass the `secret_key` from a previous session to keep editing
+/// as the same actor.
+#[pyclass(unsendable, module = "openprod")]
+pub struct Workspace {
+    engine: Engine<SqliteStorage>,
+}
+
+#[pymethods]
+impl Workspace {
+    #[new]
+    #[pyo3(signature = (path=None, secret_key=None))]
+    fn new(path: Option<&str>, secret_key: Option<[u8; 32]>) -> PyResult<Self> {
+        let storage = match path {
+            Some(path) => SqliteStorage::open(path),
+            None => SqliteStorage::open_in_memory(),
+        }
+        .map_err(|e| py_err(e.into()))?;
+        let identity = match secret_key {
+            Some(secret) => ActorIdentity::from_secret_bytes(&secret),
+            None => ActorIdentity::generate(),
+        };
+        let engine = Engine::new(identity, storage).map_err(py_err)?;
+        Ok(Self { engine })
+    }
+
+    /// This replica's actor id, hex-encoded.
+    #[getter]
+    fn actor_id(&self) -> String {
+        self.engine.actor_id().as_bytes().iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// The 32-byte signing key to pass back as `secret_key` on reopen.
+    #[getter]
+    fn secret_key<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.engine.identity().secret_bytes())
+    }
+
+    /// Create an entity with facet `facet_type` and optional initial fields.
+    #[pyo3(signature = (facet_type, fields=None))]
+    fn create_entity(
+        slf: &Bound<'_, Self>,
+        facet_type: &str,
+        fields: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Entity> {
+        let mut values = Vec::new();
+        for (key, value) in fields.into_iter().flatten() {
+            values.push((key.extract::<String>()?, to_field_value(&value)?));
+        }
+        let fields = values.iter().map(|(key, value)| (key.as_str(), value.clone())).collect();
+        let (entity_id, _) = slf
+            .borrow_mut()
+            .engine
+            .create_entity_with_fields(facet_type, fields)
+            .map_err(py_err)?;
+        Ok(Entity::new(slf.py(), &slf.clone().unbind(), entity_id))
+    }
+
+    /// Look up an entity by id. Raises `KeyError` if it does not exist.
+    fn entity(slf: &Bound<'_, Self>, entity_id: &str) -> PyResult<Entity> {
+        let entity_id = EntityId::from_uuid(parse_uuid(entity_id)?);
+        match slf.borrow().engine.get_entity(entity_id).map_err(py_err)? {
+            Some(_) => Ok(Entity::new(slf.py(), &slf.clone().unbind(), entity_id)),
+            None => Err(PyKeyError::new_err(entity_id.to_string())),
+        }
+    }
+
+    /// Iterate over live entities carrying facet `facet_type`.
+    fn entities(slf: &Bound<'_, Self>, facet_type: &str) -> PyResult<EntityIter> {
+        let this = slf.borrow();
+        let mut entity_ids = Vec::new();
+        for entity_id in this.engine.get_entities_by_facet(facet_type).map_err(py_err)? {
+            if this.engine.get_entity(entity_id).map_err(py_err)?.is_some_and(|e| !e.deleted) {
+                entity_ids.push(entity_id);
+            }
+        }
+        Ok(EntityIter { workspace: slf.clone().unbind(), entity_ids: entity_ids.into_iter() })
+    }
+
+    /// Group edits into one undo step: `with ws.transaction(): ...`. If the
+    /// block raises, everything it wrote is undone before the error propagates.
+    fn transaction(slf: &Bound<'_, Self>) -> Transaction {
+        Transaction { workspace: slf.clone().unbind(), top_before: None }
+    }
+
+    /// Undo this replica's last edit. Returns whether anything was undone.
+    fn undo(&mut self) -> PyResult<bool> {
+        Ok(matches!(self.engine.undo().map_err(py_err)?, UndoResult::Applied(_)))
+    }
+
+    /// Redo the last undone edit. Returns whether anything was redone.
+    fn redo(&mut self) -> PyResult<bool> {
+        Ok(matches!(self.engine.redo().map_err(py_err)?, UndoResult::Applied(_)))
+    }
+
+    /// Create an overlay and make it active: edits go to it until it is
+    /// stashed, committed or discarded.
+    fn create_overlay(slf: &Bound<'_, Self>, name: &str) -> PyResult<Overlay> {
+        let overlay_id = slf.borrow_mut().engine.create_overlay(name).map_err(py_err)?;
+        Ok(Overlay { workspace: slf.clone().unbind(), overlay_id, name: name.to_string() })
+    }
+
+    /// The active overlay, if any.
+    #[getter]
+    fn active_overlay(slf: &Bound<'_, Self>) -> PyResult<Option<Overlay>> {
+        let this = slf.borrow();
+        let Some(overlay_id) = this.engine.active_overlay() else { return Ok(None) };
+        let name = overlay_name(&this.engine, overlay_id)?;
+        Ok(Some(Overlay { workspace: slf.clone().unbind(), overlay_id, name }))
+    }
+
+    /// Stashed (inactive, uncommitted) overlays.
+    fn stashed_overlays(slf: &Bound<'_, Self>) -> PyResult<Vec<Overlay>> {
+        let stashed = slf.borrow().engine.stashed_overlays().map_err(py_err)?;
+        Ok(stashed
+            .into_iter()
+            .map(|(overlay_id, name)| Overlay { workspace: slf.clone().unbind(), overlay_id, name })
+            .collect())
+    }
+
+    /// Look up a conflict by id. Raises `KeyError` if it does not exist.
+    fn conflict(slf: &Bound<'_, Self>, conflict_id: &str) -> PyResult<Conflict> {
+        let conflict_id = ConflictId::from_uuid(parse_uuid(conflict_id)?);
+        match slf.borrow().engine.get_conflict(conflict_id).map_err(py_err)? {
+            Some(record) => Ok(Conflict::new(slf.py(), &slf.clone().unbind(), record)),
+            None => Err(PyKeyError::new_err(conflict_id.to_string())),
+        }
+    }
+
+    /// Rebuild materialized state from the oplog. Returns the ops replayed.
+    fn rebuild(&mut self) -> PyResult<u64> {
+        self.engine.rebuild_state().map_err(py_err)
+    }
+}
+
+fn overlay_name(engine: &Engine<SqliteStorage>, overlay_id: OverlayId) -> PyResult<String> {
+    use openprod_storage::OverlayStore;
+    let overlay = engine.storage().get_overlay(overlay_id).map_err(|e| py_err(e.into()))?;
+    Ok(overlay.map(|(_, name, ..)| name).unwrap_or_default())
+}
+
+// ============================================================================
+// Entity
+// ============================================================================
+
+/// A handle on one entity. Fields read and write through to the workspace
+/// like a dict: `entity["name"]`, `entity["name"] = "Fresnel"`, `del entity["name"]`.
+#[pyclass(module = "openprod")]
+pub struct Entity {
+    workspace: Py<Workspace>,
+    entity_id: EntityId,
+}
+
+impl Entity {
+    fn new(py: Python<'_>, workspace: &Py<Workspace>, entity_id: EntityId) -> Self {
+        Self { workspace: workspace.clone_ref(py), entity_id }
+    }
+}
+
+#[pymethods]
+impl Entity {
+    #[getter]
+    fn id(&self) -> String {
+        self.entity_id.to_string()
+    }
+
+    /// Facet types currently attached.
+    #[getter]
+    fn facets(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        let facets = self.workspace.borrow(py).engine.get_facets(self.entity_id).map_err(py_err)?;
+        Ok(facets.into_iter().filter(|f| !f.detached).map(|f| f.facet_type).collect())
+    }
+
+    /// Whether the entity has been deleted.
+    #[getter]
+    fn deleted(&self, py: Python<'_>) -> PyResult<bool> {
+        let record = self.workspace.borrow(py).engine.get_entity(self.entity_id).map_err(py_err)?;
+        Ok(record.is_none_or(|r| r.deleted))
+    }
+
+    /// All set fields as a dict.
+    fn fields<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let fields = self.workspace.borrow(py).engine.get_fields(self.entity_id).map_err(py_err)?;
+        let dict = PyDict::new(py);
+        for (key, value) in fields {
+            dict.set_item(key, from_field_value(py, &self.workspace, value)?)?;
+        }
+        Ok(dict)
+    }
+
+    /// `entity.get(key, default=None)`, like `dict.get`.
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, py: Python<'_>, key: &str, default: Option<PyObject>) -> PyResult<PyObject> {
+        let value = self.workspace.borrow(py).engine.get_field(self.entity_id, key).map_err(py_err)?;
+        match value {
+            Some(value) => from_field_value(py, &self.workspace, value),
+            None => Ok(default.unwrap_or_else(|| py.None())),
+        }
+    }
+
+    fn __getitem__(&self, py: Python<'_>, key: &str) -> PyResult<PyObject> {
+        let value = self.workspace.borrow(py).engine.get_field(self.entity_id, key).map_err(py_err)?;
+        match value {
+            Some(value) => from_field_value(py, &self.workspace, value),
+            None => Err(PyKeyError::new_err(key.to_string())),
+        }
+    }
+
+    fn __setitem__(&self, py: Python<'_>, key: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let value = to_field_value(value)?;
+        self.workspace.borrow_mut(py).engine.set_field(self.entity_id, key, value).map_err(py_err)?;
+        Ok(())
+    }
+
+    fn __delitem__(&self, py: Python<'_>, key: &str) -> PyResult<()> {
+        self.workspace.borrow_mut(py).engine.clear_field(self.entity_id, key).map_err(py_err)?;
+        Ok(())
+    }
+
+    fn __contains__(&self, py: Python<'_>, key: &str) -> PyResult<bool> {
+        let value = self.workspace.borrow(py).engine.get_field(self.entity_id, key).map_err(py_err)?;
+        Ok(value.is_some())
+    }
+
+    /// Delete the entity (cascading to its edges).
+    fn delete(&self, py: Python<'_>) -> PyResult<()> {
+        self.workspace.borrow_mut(py).engine.delete_entity(self.entity_id).map_err(py_err)?;
+        Ok(())
+    }
+
+    /// Open conflicts on this entity's fields.
+    fn conflicts(&self, py: Python<'_>) -> PyResult<Vec<Conflict>> {
+        let records =
+            self.workspace.borrow(py).engine.get_open_conflicts_for_entity(self.entity_id).map_err(py_err)?;
+        Ok(records.into_iter().map(|record| Conflict::new(py, &self.workspace, record)).collect())
+    }
+
+    fn __eq__(&self, other: &Bound<'_, PyAny>) -> bool {
+        other.downcast::<Entity>().is_ok_and(|other| other.borrow().entity_id == self.entity_id)
+    }
+
+    fn __hash__(&self) -> u64 {
+        let bytes = self.entity_id.as_bytes();
+        u64::from_le_bytes(bytes[8..].try_into().expect("8 bytes"))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Entity('{}')", self.entity_id)
+    }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.workspace)
+    }
+}
+
+/// Iterator over query results, yielding `Entity` handles.
+#[pyclass(module = "openprod")]
+pub struct EntityIter {
+    workspace: Py<Workspace>,
+    entity_ids: std::vec::IntoIter<EntityId>,
+}
+
+#[pymethods]
+impl EntityIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> Option<Entity> {
+        self.entity_ids.next().map(|entity_id| Entity::new(py, &self.workspace, entity_id))
+    }
+
+    fn __len__(&self) -> usize {
+        self.entity_ids.len()
+    }
+}
+
+// ============================================================================
+// Transactions
+// ============================================================================
+
+/// Context manager from `Workspace.transaction()`.
+#[pyclass(module = "openprod")]
+pub struct Transaction {
+    workspace: Py<Workspace>,
+    /// Top of the undo stack on entry, to tell whether the block wrote anything.
+    top_before: Option<openprod_core::ids::BundleId>,
+}
+
+#[pymethods]
+impl Transaction {
+    fn __enter__(&mut self, py: Python<'_>) -> PyResult<Py<Workspace>> {
+        let mut workspace = self.workspace.borrow_mut(py);
+        self.top_before = undo_top(&workspace.engine)?;
+        workspace.engine.begin_undo_group();
+        Ok(self.workspace.clone_ref(py))
+    }
+
+    #[pyo3(signature = (exc_type, _exc_value, _traceback))]
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        exc_type: Option<&Bound<'_, PyType>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        let mut workspace = self.workspace.borrow_mut(py);
+        workspace.engine.end_undo_group();
+        if exc_type.is_some() && undo_top(&workspace.engine)? != self.top_before {
+            workspace.engine.undo().map_err(py_err)?;
+        }
+        // Never swallow the exception
+        Ok(false)
+    }
+}
+
+fn undo_top(engine: &Engine<SqliteStorage>) -> PyResult<Option<openprod_core::ids::BundleId>> {
+    Ok(engine.undo_stack().map_err(py_err)?.first().map(|entry| entry.bundle_id))
+}
+
+// ============================================================================
+// Overlays and conflicts
+// ============================================================================
+
+/// A named draft layer. Edits made while it is active stay out of canonical
+/// state until `commit()`.
+#[pyclass(module = "openprod")]
+pub struct Overlay {
+    workspace: Py<Workspace>,
+    overlay_id: OverlayId,
+    #[pyo3(get)]
+    name: String,
+}
+
+#[pymethods]
+impl Overlay {
+    #[getter]
+    fn id(&self) -> String {
+        self.overlay_id.to_string()
+    }
+
+    #[getter]
+    fn active(&self, py: Python<'_>) -> bool {
+        self.workspace.borrow(py).engine.active_overlay() == Some(self.overlay_id)
+    }
+
+    /// Make this the active overlay (stashing any other).
+    fn activate(&self, py: Python<'_>) -> PyResult<()> {
+        self.workspace.borrow_mut(py).engine.activate_overlay(self.overlay_id).map_err(py_err)
+    }
+
+    /// Deactivate without discarding; later edits go to canonical state.
+    fn stash(&self, py: Python<'_>) -> PyResult<()> {
+        self.workspace.borrow_mut(py).engine.stash_overlay(self.overlay_id).map_err(py_err)
+    }
+
+    /// Apply the overlay's edits to canonical state as one bundle.
+    fn commit(&self, py: Python<'_>) -> PyResult<()> {
+        self.workspace.borrow_mut(py).engine.commit_overlay(self.overlay_id).map_err(py_err)?;
+        Ok(())
+    }
+
+    /// Throw the overlay and its edits away.
+    fn discard(&self, py: Python<'_>) -> PyResult<()> {
+        self.workspace.borrow_mut(py).engine.discard_overlay(self.overlay_id).map_err(py_err)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Overlay({:?})", self.name)
+    }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.workspace)
+    }
+}
+
+/// Concurrent writes to one field that need a human decision.
+#[pyclass(module = "openprod")]
+pub struct Conflict {
+    workspace: Py<Workspace>,
+    record: ConflictRecord,
+}
+
+impl Conflict {
+    fn new(py: Python<'_>, workspace: &Py<Workspace>, record: ConflictRecord) -> Self {
+        Self { workspace: workspace.clone_ref(py), record }
+    }
+}
+
+#[pymethods]
+impl Conflict {
+    #[getter]
+    fn id(&self) -> String {
+        self.record.conflict_id.to_string()
+    }
+
+    #[getter]
+    fn entity(&self, py: Python<'_>) -> Entity {
+        Entity::new(py, &self.workspace, self.record.entity_id)
+    }
+
+    #[getter]
+    fn field(&self) -> &str {
+        &self.record.field_key
+    }
+
+    #[getter]
+    fn open(&self) -> bool {
+        self.record.status == openprod_storage::ConflictStatus::Open
+    }
+
+    /// The competing values (`None` for a competing clear).
+    #[getter]
+    fn values(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.record
+            .values
+            .iter()
+            .map(|candidate| match &candidate.value {
+                Some(bytes) => {
+                    let value = FieldValue::from_msgpack(bytes).map_err(|e| OpenprodError::new_err(e.to_string()))?;
+                    from_field_value(py, &self.workspace, value)
+                }
+                None => Ok(py.None()),
+            })
+            .collect()
+    }
+
+    /// Settle the conflict on `value` (`None` clears the field).
+    #[pyo3(signature = (value=None))]
+    fn resolve(&mut self, py: Python<'_>, value: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+        let chosen = value.map(to_field_value).transpose()?.filter(|v| !v.is_null());
+        let mut workspace = self.workspace.borrow_mut(py);
+        workspace.engine.resolve_conflict(self.record.conflict_id, chosen).map_err(py_err)?;
+        if let Some(record) = workspace.engine.get_conflict(self.record.conflict_id).map_err(py_err)? {
+            self.record = record;
+        }
+        Ok(())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Conflict({:?}, {} values)", self.record.field_key, self.record.values.len())
+    }
+
+    fn __traverse__(&self, visit: PyVisit<'_>) -> Result<(), PyTraverseError> {
+        visit.call(&self.workspace)
+    }
+}
+
+#[pymodule]
+pub fn openprod(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("OpenprodError", m.py().get_type::<OpenprodError>())?;
+    m.add_class::<Workspace>()?;
+    m.add_class::<Entity>()?;
+    m.add_class::<EntityIter>()?;
+    m.add_class::<Transaction>()?;
+    m.add_class::<Overlay>()?;
+    m.add_class::<Conflict>()?;
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Runs Python snippets against the module in an embedded interpreter.
+
+use std::ffi::CString;
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Execute `code` with the module bound to `openprod`; panics with the
+/// Python traceback on failure.
+fn run(code: &str) {
+    Python::with_gil(|py| {
+        let globals = PyDict::new(py);
+        let module = pyo3::wrap_pymodule!(openprod::openprod)(py);
+        globals.set_item("openprod", module).unwrap();
+        let code = CString::new(code).unwrap();
+        if let Err(e) = py.run(&code, Some(&globals), None) {
+            e.display(py);
+            panic!("python snippet failed: {e}");
+        }
+    });
+}
+
+#[test]
+fn entities_read_and_write_like_dicts() {
+    run(r#"
+ws = openprod.Workspace()
+light = ws.create_entity("Equipment", {"name": "Spotlight", "wattage": 750, "hung": True})
+stage = ws.create_entity("Location", {"name": "Stage"})
+light["located_at"] = stage
+light["dimmer"] = 1.5
+
+assert light["name"] == "Spotlight"
+assert light["hung"] is True
+assert light["located_at"] == stage and light["located_at"]["name"] == "Stage"
+assert light.fields() == {"name": "Spotlight", "wattage": 750, "hung": True, "located_at": stage, "dimmer": 1.5}
+assert "name" in light and "color" not in light
+assert light.get("color", "white") == "white"
+try:
+    light["color"]
+    raise AssertionError("expected KeyError")
+except KeyError:
+    pass
+
+del light["dimmer"]
+assert "dimmer" not in light
+assert ws.entity(light.id) == light and light.facets == ["Equipment"]
+
+names = sorted(e["name"] for e in ws.entities("Equipment"))
+assert names == ["Spotlight"]
+assert len(ws.entities("Location")) == 1
+
+light.delete()
+assert light.deleted
+assert list(ws.entities("Equipment")) == []
+try:
+    light["name"] = "Fresnel"
+    raise AssertionError("expected OpenprodError")
+except openprod.OpenprodError:
+    pass
+"#);
+}
+
+#[test]
+fn transactions_undo_as_one_step_and_roll_back_on_error() {
+    run(r#"
+ws = openprod.Workspace()
+cue = ws.create_entity("Cue", {"label": "Q1"})
+with ws.transaction():
+    cue["label"] = "Q2"
+    cue["duration"] = 5
+assert ws.undo()
+assert cue["label"] == "Q1" and "duration" not in cue
+
+try:
+    with ws.transaction():
+        cue["label"] = "Q3"
+        raise ValueError("abort")
+except ValueError:
+    pass
+assert cue["label"] == "Q1"
+
+# An empty failed block leaves earlier history alone
+try:
+    with ws.transaction():
+        raise ValueError("abort")
+except ValueError:
+    pass
+assert cue["label"] == "Q1" and ws.entity(cue.id)
+"#);
+}
+
+#[test]
+fn overlays_and_reopening_a_workspace_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("show.db");
+    run(&format!(
+        r#"
+ws = openprod.Workspace({path:?})
+cue = ws.create_entity("Cue", {{"label": "Q1"}})
+draft = ws.create_overlay("draft")
+assert ws.active_overlay.name == "draft"
+cue["label"] = "Draft"
+draft.stash()
+assert cue["label"] == "Q1" and [o.name for o in ws.stashed_overlays()] == ["draft"]
+draft.activate()
+draft.commit()
+assert cue["label"] == "Draft"
+
+key = ws.secret_key
+actor = ws.actor_id
+del ws
+reopened = openprod.Workspace({path:?}, key)
+assert reopened.actor_id == actor
+assert reopened.entity(cue.id)["label"] == "Draft"
+"#,
+        path = path.to_str().unwrap()
+    ));
+}
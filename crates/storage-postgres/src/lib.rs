@@ -0,0 +1,4 @@
+pub mod schema;
+mod pg;
+
+pub use pg::PostgresStorage;
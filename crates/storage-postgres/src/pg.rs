@@ -0,0 +1,2316 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use postgres::types::ToSql;
+use postgres::{Client, NoTls, Row};
+
+use openprod_core::{
+    digest::HlcRange,
+    field_value::FieldValue,
+    hlc::Hlc,
+    ids::*,
+    operations::{Bundle, BundleType, Operation, OperationPayload, RawBundle},
+    vector_clock::VectorClock,
+};
+use openprod_storage::{
+    aggregate_numbers, Aggregate, ActorRecord, ActorStore, ConflictRecord, ConflictStatus, ConflictTriage, ConflictValue, Deletion, EdgeFilter, EdgeRecord,
+    EntityFields, EntityOrder, EntityPage, EntityPageCursor, EntityRecord, FacetRecord, FieldMappingRecord, LabelStore, MaterializationStore, MaterializeProgress, OverlayOrigin, OverlayStore, PeerRecord,
+    PeerStore, PendingBundleRecord, PendingStore, QuarantineStore, QuarantinedBundle, Storage, StorageError,
+    Transactional, TrustState, TrustStore, WebhookRecord, WebhookStore, EDGE_OP_TYPES, decode_preserved_values,
+    check_bundle_ops, field_sort_key, indexed_meta,
+};
+
+pub(crate) fn pg_error(e: postgres::Error) -> StorageError {
+    StorageError::Backend(e.to_string())
+}
+
+fn serialization_error(e: impl ToString) -> StorageError {
+    StorageError::Serialization(e.to_string())
+}
+
+/// Convert Vec<u8> to fixed-size array with proper error handling.
+fn to_array<const N: usize>(v: Vec<u8>, label: &str) -> Result<[u8; N], StorageError> {
+    v.try_into()
+        .map_err(|_| StorageError::Serialization(format!("invalid {label} length")))
+}
+
+fn hlc_at(row: &Row, idx: usize, label: &str) -> Result<Hlc, StorageError> {
+    Ok(Hlc::from_bytes(&to_array::<12>(row.get(idx), label)?))
+}
+
+fn actor_at(row: &Row, idx: usize, label: &str) -> Result<ActorId, StorageError> {
+    Ok(ActorId::from_bytes(to_array::<32>(row.get(idx), label)?))
+}
+
+type Params<'a> = [&'a (dyn ToSql + Sync)];
+
+/// Rows of `overlay_ops` as returned by `OverlayStore::get_overlay_ops`.
+type OverlayOpRow = (i64, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, String, Option<Vec<u8>>, bool, Option<String>);
+
+const OP_COLUMNS: &str = "op_id, actor_id, hlc, bundle_id, payload, module_versions, signature";
+
+const BUNDLE_COLUMNS: &str =
+    "bundle_id, actor_id, hlc, bundle_type, op_count, checksum, creates, deletes, meta, signature, creator_vector_clock";
+
+const EDGE_COLUMNS: &str =
+    "edge_id, edge_type, source_id, target_id, created_at, created_by, (deleted_at IS NOT NULL), created_in_bundle";
+
+const CONFLICT_COLUMNS: &str = "conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle, resolved_at, resolved_by, resolved_op_id, resolved_value, reopened_at, reopened_by_op";
+
+const OVERLAY_OP_COLUMNS: &str =
+    "rowid, op_id, hlc, payload, entity_id, op_type, canonical_value_at_creation, canonical_drifted, field_key";
+
+/// Last-writer-wins upsert shared by SetField, ClearField (NULL value) and
+/// ResolveConflict: a write only lands if its (hlc, op_id) beats the stored one.
+const UPSERT_FIELD: &str = "INSERT INTO fields (entity_id, field_key, value, source_op, source_actor, updated_at) VALUES ($1, $2, $3, $4, $5, $6)
+     ON CONFLICT (entity_id, field_key) DO UPDATE SET value = excluded.value, source_op = excluded.source_op, source_actor = excluded.source_actor, updated_at = excluded.updated_at
+     WHERE excluded.updated_at > fields.updated_at OR (excluded.updated_at = fields.updated_at AND excluded.source_op > fields.source_op)";
+
+/// Edge-property counterpart of `UPSERT_FIELD`.
+const UPSERT_EDGE_PROPERTY: &str = "INSERT INTO edge_properties (edge_id, property_key, value, source_op, source_actor, updated_at) VALUES ($1, $2, $3, $4, $5, $6)
+     ON CONFLICT (edge_id, property_key) DO UPDATE SET value = excluded.value, source_op = excluded.source_op, source_actor = excluded.source_actor, updated_at = excluded.updated_at
+     WHERE excluded.updated_at > edge_properties.updated_at OR (excluded.updated_at = edge_properties.updated_at AND excluded.source_op > edge_properties.source_op)";
+
+/// Latest confirmation wins, as for fields.
+const UPSERT_FIELD_MAPPING: &str = "INSERT INTO field_mappings (source_table, target_table, source_field, target_field, source_op, confirmed_by, confirmed_at)
+     VALUES ($1, $2, $3, $4, $5, $6, $7)
+     ON CONFLICT (source_table, target_table, source_field) DO UPDATE SET target_field = excluded.target_field, source_op = excluded.source_op, confirmed_by = excluded.confirmed_by, confirmed_at = excluded.confirmed_at
+     WHERE excluded.confirmed_at > field_mappings.confirmed_at OR (excluded.confirmed_at = field_mappings.confirmed_at AND excluded.source_op > field_mappings.source_op)";
+
+/// Tombstones every live edge sharing a unique edge type and endpoints with
+/// `$1` except the earliest created, each as of its own creation (see the
+/// SQLite backend's `merge_duplicate_edges`).
+const MERGE_DUPLICATE_EDGES: &str = "UPDATE edges SET deleted_at = created_at, deleted_by = created_by, deleted_in_bundle = created_in_bundle
+     WHERE deleted_at IS NULL
+       AND edge_type IN (SELECT edge_type FROM unique_edge_types)
+       AND (source_id, target_id, edge_type) = (SELECT source_id, target_id, edge_type FROM edges WHERE edge_id = $1)
+       AND EXISTS (
+           SELECT 1 FROM edges AS earlier
+           WHERE earlier.source_id = edges.source_id AND earlier.target_id = edges.target_id
+             AND earlier.edge_type = edges.edge_type
+             AND (earlier.created_at, earlier.edge_id) < (edges.created_at, edges.edge_id))";
+
+/// Ops between `materialize_from` progress reports.
+const PROGRESS_INTERVAL: u64 = 1000;
+
+/// Storage backed by a PostgreSQL database, for server deployments where
+/// many clients sync against one central engine. Holds the same tables as
+/// `SqliteStorage` and applies the same materialization rules, so both
+/// backends derive identical state from the same oplog.
+pub struct PostgresStorage {
+    client: RefCell<Client>,
+    /// Set while a transaction is open, so atomic steps nest as savepoints.
+    in_transaction: Cell<bool>,
+}
+
+impl PostgresStorage {
+    /// Connect with a libpq-style string (`host=... user=... dbname=...`) and
+    /// create the schema if needed.
+    pub fn connect(params: &str) -> Result<Self, StorageError> {
+        Self::from_client(Client::connect(params, NoTls).map_err(pg_error)?)
+    }
+
+    /// Use an already configured client (TLS, `search_path`, ...), creating
+    /// the schema if needed.
+    pub fn from_client(mut client: Client) -> Result<Self, StorageError> {
+        crate::schema::init_schema(&mut client)?;
+        Ok(Self { client: RefCell::new(client), in_transaction: Cell::new(false) })
+    }
+
+    fn execute(&self, sql: &str, params: &Params) -> Result<u64, StorageError> {
+        self.client.borrow_mut().execute(sql, params).map_err(pg_error)
+    }
+
+    fn query(&self, sql: &str, params: &Params) -> Result<Vec<Row>, StorageError> {
+        self.client.borrow_mut().query(sql, params).map_err(pg_error)
+    }
+
+    fn query_opt(&self, sql: &str, params: &Params) -> Result<Option<Row>, StorageError> {
+        self.client.borrow_mut().query_opt(sql, params).map_err(pg_error)
+    }
+
+    fn count(&self, sql: &str, params: &Params) -> Result<u64, StorageError> {
+        let count: i64 = self.client.borrow_mut().query_one(sql, params).map_err(pg_error)?.get(0);
+        Ok(count as u64)
+    }
+
+    fn batch(&self, sql: &str) -> Result<(), StorageError> {
+        self.client.borrow_mut().batch_execute(sql).map_err(pg_error)
+    }
+
+    /// Run `f` all-or-nothing: in a savepoint inside an open transaction,
+    /// otherwise in a transaction of its own. Postgres aborts the whole
+    /// transaction on any failed statement, so every multi-statement write
+    /// goes through here.
+    fn atomic<T>(&self, f: impl FnOnce() -> Result<T, StorageError>) -> Result<T, StorageError> {
+        if self.in_transaction.get() {
+            self.batch("SAVEPOINT sp_atomic")?;
+            match f() {
+                Ok(value) => {
+                    self.batch("RELEASE SAVEPOINT sp_atomic")?;
+                    Ok(value)
+                }
+                Err(e) => {
+                    let _ = self.batch("ROLLBACK TO SAVEPOINT sp_atomic; RELEASE SAVEPOINT sp_atomic");
+                    Err(e)
+                }
+            }
+        } else {
+            self.begin_transaction()?;
+            match f() {
+                Ok(value) => {
+                    self.commit_transaction()?;
+                    Ok(value)
+                }
+                Err(e) => {
+                    let _ = self.rollback_transaction();
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    fn query_ops(&self, filter: &str, params: &Params) -> Result<Vec<Operation>, StorageError> {
+        self.query(&format!("SELECT {OP_COLUMNS} FROM oplog {filter}"), params)?
+            .iter()
+            .map(read_op)
+            .collect()
+    }
+
+    fn read_bundle(&self, bundle_id: BundleId) -> Result<Option<Bundle>, StorageError> {
+        self.query_opt(
+            &format!("SELECT {BUNDLE_COLUMNS} FROM bundles WHERE bundle_id = $1"),
+            &[&bundle_id.as_bytes().as_slice()],
+        )?
+        .map(|row| read_bundle(&row))
+        .transpose()
+    }
+
+    fn query_edges(&self, filter: &str, params: &Params) -> Result<Vec<EdgeRecord>, StorageError> {
+        self.query(&format!("SELECT {EDGE_COLUMNS} FROM edges {filter}"), params)?
+            .iter()
+            .map(read_edge)
+            .collect()
+    }
+
+    /// `query_edges` narrowed by an `EdgeFilter`, with the deleted check as
+    /// literal SQL so the planner can use the partial indexes.
+    fn query_filtered_edges(
+        &self,
+        condition: &str,
+        params: &Params,
+        filter: &EdgeFilter,
+    ) -> Result<Vec<EdgeRecord>, StorageError> {
+        let mut sql = condition.to_string();
+        if !filter.include_deleted {
+            sql.push_str(" AND deleted_at IS NULL");
+        }
+        match &filter.edge_type {
+            Some(edge_type) => {
+                sql.push_str(&format!(" AND edge_type = ${}", params.len() + 1));
+                let mut params = params.to_vec();
+                params.push(edge_type);
+                self.query_edges(&sql, &params)
+            }
+            None => self.query_edges(&sql, params),
+        }
+    }
+
+    fn query_conflict(&self, filter: &str, params: &Params) -> Result<Option<ConflictRecord>, StorageError> {
+        Ok(self.query_conflicts(filter, params)?.into_iter().next())
+    }
+
+    fn query_conflicts(&self, filter: &str, params: &Params) -> Result<Vec<ConflictRecord>, StorageError> {
+        let rows = self.query(&format!("SELECT {CONFLICT_COLUMNS} FROM conflicts {filter}"), params)?;
+        let mut result = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let mut record = read_conflict(row)?;
+            record.values = self.load_conflict_values(record.conflict_id)?;
+            result.push(record);
+        }
+        Ok(result)
+    }
+
+    fn load_conflict_values(&self, conflict_id: ConflictId) -> Result<Vec<ConflictValue>, StorageError> {
+        self.query(
+            "SELECT actor_id, hlc, op_id, value FROM conflict_values WHERE conflict_id = $1",
+            &[&conflict_id.as_bytes().as_slice()],
+        )?
+        .iter()
+        .map(|row| {
+            Ok(ConflictValue {
+                actor_id: actor_at(row, 0, "actor_id")?,
+                hlc: hlc_at(row, 1, "hlc")?,
+                op_id: OpId::from_bytes(to_array::<16>(row.get(2), "op_id")?),
+                value: row.get(3),
+            })
+        })
+        .collect()
+    }
+
+    fn insert_conflict_value(&self, conflict_id: ConflictId, value: &ConflictValue) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO conflict_values (conflict_id, actor_id, hlc, op_id, value) VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (conflict_id, actor_id) DO UPDATE SET hlc = excluded.hlc, op_id = excluded.op_id, value = excluded.value",
+            &[
+                &conflict_id.as_bytes().as_slice(),
+                &value.actor_id.as_bytes().as_slice(),
+                &value.hlc.to_bytes().as_slice(),
+                &value.op_id.as_bytes().as_slice(),
+                &value.value.as_deref(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn query_overlay_ops(&self, filter: &str, params: &Params) -> Result<Vec<OverlayOpRow>, StorageError> {
+        Ok(self
+            .query(&format!("SELECT {OVERLAY_OP_COLUMNS} FROM overlay_ops {filter} ORDER BY rowid"), params)?
+            .iter()
+            .map(|row| {
+                (
+                    row.get(0),
+                    row.get(1),
+                    row.get(2),
+                    row.get(3),
+                    row.get(4),
+                    row.get(5),
+                    row.get(6),
+                    row.get(7),
+                    row.get(8),
+                )
+            })
+            .collect())
+    }
+
+    fn query_held_bundles(&self, sql: &str, params: &Params) -> Result<Vec<PendingBundleRecord>, StorageError> {
+        self.query(sql, params)?
+            .iter()
+            .map(|row| {
+                Ok(PendingBundleRecord {
+                    bundle: rmp_serde::from_slice(row.get(0)).map_err(serialization_error)?,
+                    operations: rmp_serde::from_slice(row.get(1)).map_err(serialization_error)?,
+                    received_at: row.get(2),
+                })
+            })
+            .collect()
+    }
+
+    fn query_peers(&self, peer_id: Option<ActorId>) -> Result<Vec<PeerRecord>, StorageError> {
+        self.query(
+            "SELECT p.peer_id, p.display_name, s.acked_vector_clock, s.last_synced_at
+             FROM peers p LEFT JOIN peer_sync_state s ON s.peer_id = p.peer_id
+             WHERE $1::BYTEA IS NULL OR p.peer_id = $1
+             ORDER BY p.added_at, p.peer_id",
+            &[&peer_id.as_ref().map(|id| id.as_bytes().as_slice())],
+        )?
+        .iter()
+        .map(|row| {
+            let acked_vc = match row.get::<_, Option<&[u8]>>(2) {
+                Some(bytes) => VectorClock::from_msgpack(bytes).map_err(serialization_error)?,
+                None => VectorClock::new(),
+            };
+            Ok(PeerRecord {
+                peer_id: actor_at(row, 0, "peer_id")?,
+                display_name: row.get(1),
+                acked_vc,
+                last_synced_at: row.get(3),
+            })
+        })
+        .collect()
+    }
+
+    fn query_webhooks(&self, webhook_id: Option<WebhookId>) -> Result<Vec<WebhookRecord>, StorageError> {
+        self.query(
+            "SELECT webhook_id, url, filter, cursor, attempts, retry_at, last_error FROM webhooks
+             WHERE $1::BYTEA IS NULL OR webhook_id = $1
+             ORDER BY position",
+            &[&webhook_id.as_ref().map(|id| id.as_bytes().as_slice())],
+        )?
+        .iter()
+        .map(|row| {
+            Ok(WebhookRecord {
+                webhook_id: WebhookId::from_bytes(to_array::<16>(row.get(0), "webhook_id")?),
+                url: row.get(1),
+                filter: row.get(2),
+                cursor: row.get::<_, i64>(3) as u64,
+                attempts: row.get::<_, i32>(4) as u32,
+                retry_at: row.get(5),
+                last_error: row.get(6),
+            })
+        })
+        .collect()
+    }
+
+    /// Record an op's actor in the directory and advance the vector clock.
+    fn track_actor(&self, op: &Operation) -> Result<(), StorageError> {
+        let actor = op.actor_id.as_bytes().as_slice();
+        let hlc = op.hlc.to_bytes();
+        self.execute(
+            "INSERT INTO actors (actor_id, display_name, first_seen_at) VALUES ($1, NULL, $2) ON CONFLICT DO NOTHING",
+            &[&actor, &hlc.as_slice()],
+        )?;
+        self.execute(
+            "INSERT INTO vector_clock (actor_id, max_hlc) VALUES ($1, $2)
+             ON CONFLICT (actor_id) DO UPDATE SET max_hlc = excluded.max_hlc
+             WHERE excluded.max_hlc > vector_clock.max_hlc",
+            &[&actor, &hlc.as_slice()],
+        )?;
+        Ok(())
+    }
+
+    /// Replay ops at or after `from` in canonical order, then advance the
+    /// watermark to the end of the oplog. Must run inside `atomic`.
+    fn replay_ops_from(
+        &self,
+        from: Hlc,
+        progress: &mut dyn FnMut(MaterializeProgress),
+    ) -> Result<u64, StorageError> {
+        let ops = self.query_ops("WHERE hlc >= $1 ORDER BY hlc, op_id", &[&from.to_bytes().as_slice()])?;
+        let total = ops.len() as u64;
+
+        let mut bundle_cache: HashMap<BundleId, Bundle> = HashMap::new();
+        for (i, op) in ops.iter().enumerate() {
+            if let std::collections::hash_map::Entry::Vacant(e) = bundle_cache.entry(op.bundle_id) {
+                let bundle = self
+                    .read_bundle(op.bundle_id)?
+                    .ok_or_else(|| StorageError::NotFound(format!("bundle {}", op.bundle_id)))?;
+                e.insert(bundle);
+            }
+            self.materialize_op(op, &bundle_cache[&op.bundle_id])?;
+            self.track_actor(op)?;
+
+            let replayed = i as u64 + 1;
+            if replayed.is_multiple_of(PROGRESS_INTERVAL) || replayed == total {
+                progress(MaterializeProgress { replayed, total });
+            }
+        }
+
+        self.checkpoint()?;
+        Ok(total)
+    }
+
+    fn checkpoint(&self) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO materialization_state (id, seq)
+             SELECT 1, MAX(rowid) FROM oplog HAVING COUNT(*) > 0
+             ON CONFLICT (id) DO UPDATE SET seq = excluded.seq",
+            &[],
+        )?;
+        Ok(())
+    }
+
+    /// Give an entity an op refers to a placeholder row if this store hasn't
+    /// seen its creation, so its facets, fields and edges have a row to hang off.
+    fn insert_placeholder(&self, entity_id: EntityId, op: &Operation, bundle: &Bundle) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO entities (entity_id, created_at, created_by, created_in_bundle, placeholder) VALUES ($1, $2, $3, $4, TRUE)
+             ON CONFLICT (entity_id) DO NOTHING",
+            &[
+                &entity_id.as_bytes().as_slice(),
+                &op.hlc.to_bytes().as_slice(),
+                &op.actor_id.as_bytes().as_slice(),
+                &bundle.bundle_id.as_bytes().as_slice(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn edge_exists(&self, edge_id: EdgeId) -> Result<bool, StorageError> {
+        Ok(self.query_opt("SELECT 1 FROM edges WHERE edge_id = $1", &[&edge_id.as_bytes().as_slice()])?.is_some())
+    }
+
+    /// Apply one op to the materialized tables (same rules as the SQLite backend).
+    fn materialize_op(&self, op: &Operation, bundle: &Bundle) -> Result<(), StorageError> {
+        let hlc_bytes = op.hlc.to_bytes();
+        let hlc = hlc_bytes.as_slice();
+        let actor = op.actor_id.as_bytes().as_slice();
+        let op_id = op.op_id.as_bytes().as_slice();
+        let bundle_id = bundle.bundle_id.as_bytes().as_slice();
+
+        match &op.payload {
+            OperationPayload::CreateEntity { entity_id, initial_table } => {
+                let entity = entity_id.as_bytes().as_slice();
+                // A placeholder takes on the creation; any other existing row collides
+                let created = self.execute(
+                    "INSERT INTO entities (entity_id, created_at, created_by, created_in_bundle) VALUES ($1, $2, $3, $4)
+                     ON CONFLICT (entity_id) DO UPDATE SET created_at = excluded.created_at, created_by = excluded.created_by, created_in_bundle = excluded.created_in_bundle, placeholder = FALSE
+                     WHERE entities.placeholder",
+                    &[&entity, &hlc, &actor, &bundle_id],
+                )?;
+                if created == 0 {
+                    return Err(StorageError::EntityCollision { entity_id: entity_id.to_string() });
+                }
+                if let Some(facet_type) = initial_table {
+                    self.execute(
+                        "INSERT INTO facets (entity_id, facet_type, attached_at, attached_by, attached_in_bundle) VALUES ($1, $2, $3, $4, $5)",
+                        &[&entity, facet_type, &hlc, &actor, &bundle_id],
+                    )?;
+                }
+            }
+
+            OperationPayload::DeleteEntity { entity_id, cascade_edges } => {
+                self.execute(
+                    "UPDATE entities SET deleted_at = $1, deleted_by = $2, deleted_in_bundle = $3 WHERE entity_id = $4",
+                    &[&hlc, &actor, &bundle_id, &entity_id.as_bytes().as_slice()],
+                )?;
+                for edge_id in cascade_edges {
+                    self.execute(
+                        "UPDATE edges SET deleted_at = $1, deleted_by = $2, deleted_in_bundle = $3 WHERE edge_id = $4",
+                        &[&hlc, &actor, &bundle_id, &edge_id.as_bytes().as_slice()],
+                    )?;
+                }
+            }
+
+            OperationPayload::AttachFacet { entity_id, facet_type } => {
+                self.insert_placeholder(*entity_id, op, bundle)?;
+                self.execute(
+                    "INSERT INTO facets (entity_id, facet_type, attached_at, attached_by, attached_in_bundle) VALUES ($1, $2, $3, $4, $5)
+                     ON CONFLICT (entity_id, facet_type) DO UPDATE SET attached_at = excluded.attached_at, attached_by = excluded.attached_by, attached_in_bundle = excluded.attached_in_bundle, detached_at = NULL, detached_by = NULL, detached_in_bundle = NULL, preserve_values = NULL",
+                    &[&entity_id.as_bytes().as_slice(), facet_type, &hlc, &actor, &bundle_id],
+                )?;
+            }
+
+            OperationPayload::DetachFacet { entity_id, facet_type, preserve_values } => {
+                let entity = entity_id.as_bytes().as_slice();
+                let preserved = if *preserve_values {
+                    let fields: Vec<(String, Vec<u8>)> = self
+                        .query("SELECT field_key, value FROM fields WHERE entity_id = $1 AND value IS NOT NULL", &[&entity])?
+                        .iter()
+                        .map(|row| (row.get(0), row.get(1)))
+                        .collect();
+                    Some(rmp_serde::to_vec(&fields).map_err(serialization_error)?)
+                } else {
+                    None
+                };
+                // Without preservation any earlier snapshot is left as it was
+                self.execute(
+                    "UPDATE facets SET detached_at = $1, detached_by = $2, detached_in_bundle = $3,
+                         preserve_values = CASE WHEN $4::BOOLEAN THEN $5 ELSE preserve_values END
+                     WHERE entity_id = $6 AND facet_type = $7",
+                    &[&hlc, &actor, &bundle_id, preserve_values, &preserved, &entity, facet_type],
+                )?;
+            }
+
+            OperationPayload::SetField { entity_id, field_key, value } => {
+                let value_bytes = value.to_msgpack().map_err(serialization_error)?;
+                self.insert_placeholder(*entity_id, op, bundle)?;
+                self.execute(
+                    UPSERT_FIELD,
+                    &[&entity_id.as_bytes().as_slice(), field_key, &Some(value_bytes), &op_id, &actor, &hlc],
+                )?;
+            }
+
+            OperationPayload::ClearField { entity_id, field_key } => {
+                // Tombstone (value = NULL) under the same LWW guard
+                self.insert_placeholder(*entity_id, op, bundle)?;
+                self.execute(
+                    UPSERT_FIELD,
+                    &[&entity_id.as_bytes().as_slice(), field_key, &None::<Vec<u8>>, &op_id, &actor, &hlc],
+                )?;
+            }
+
+            OperationPayload::ResolveConflict { entity_id, field_key, chosen_value, .. } => {
+                let value_bytes = chosen_value
+                    .as_ref()
+                    .map(|value| value.to_msgpack().map_err(serialization_error))
+                    .transpose()?;
+                self.insert_placeholder(*entity_id, op, bundle)?;
+                self.execute(
+                    UPSERT_FIELD,
+                    &[&entity_id.as_bytes().as_slice(), field_key, &value_bytes, &op_id, &actor, &hlc],
+                )?;
+            }
+
+            OperationPayload::CreateEdge { edge_id, edge_type, source_id, target_id, properties } => {
+                let edge = edge_id.as_bytes().as_slice();
+                self.insert_placeholder(*source_id, op, bundle)?;
+                self.insert_placeholder(*target_id, op, bundle)?;
+                self.execute(
+                    "INSERT INTO edges (edge_id, edge_type, source_id, target_id, created_at, created_by, created_in_bundle) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                    &[
+                        &edge,
+                        edge_type,
+                        &source_id.as_bytes().as_slice(),
+                        &target_id.as_bytes().as_slice(),
+                        &hlc,
+                        &actor,
+                        &bundle_id,
+                    ],
+                )?;
+                for (key, value) in properties {
+                    let value_bytes = value.to_msgpack().map_err(serialization_error)?;
+                    self.execute(
+                        "INSERT INTO edge_properties (edge_id, property_key, value, source_op, source_actor, updated_at) VALUES ($1, $2, $3, $4, $5, $6)",
+                        &[&edge, key, &value_bytes, &op_id, &actor, &hlc],
+                    )?;
+                }
+                self.execute(MERGE_DUPLICATE_EDGES, &[&edge])?;
+            }
+
+            // Skipped for an edge whose creation this store never got (left
+            // out of a scoped sync)
+            OperationPayload::SetEdgeProperty { edge_id, property_key, value } if self.edge_exists(*edge_id)? => {
+                let value_bytes = value.to_msgpack().map_err(serialization_error)?;
+                self.execute(
+                    UPSERT_EDGE_PROPERTY,
+                    &[&edge_id.as_bytes().as_slice(), property_key, &Some(value_bytes), &op_id, &actor, &hlc],
+                )?;
+            }
+
+            OperationPayload::ClearEdgeProperty { edge_id, property_key } if self.edge_exists(*edge_id)? => {
+                self.execute(
+                    UPSERT_EDGE_PROPERTY,
+                    &[&edge_id.as_bytes().as_slice(), property_key, &None::<Vec<u8>>, &op_id, &actor, &hlc],
+                )?;
+            }
+
+            OperationPayload::SetEdgeProperty { .. } | OperationPayload::ClearEdgeProperty { .. } => {}
+
+            OperationPayload::DeleteEdge { edge_id } => {
+                self.execute(
+                    "UPDATE edges SET deleted_at = $1, deleted_by = $2, deleted_in_bundle = $3 WHERE edge_id = $4",
+                    &[&hlc, &actor, &bundle_id, &edge_id.as_bytes().as_slice()],
+                )?;
+            }
+
+            OperationPayload::RestoreEntity { entity_id } => {
+                self.execute(
+                    "UPDATE entities SET deleted_at = NULL, deleted_by = NULL, deleted_in_bundle = NULL WHERE entity_id = $1",
+                    &[&entity_id.as_bytes().as_slice()],
+                )?;
+            }
+
+            OperationPayload::RestoreEdge { edge_id } => {
+                self.execute(
+                    "UPDATE edges SET deleted_at = NULL, deleted_by = NULL, deleted_in_bundle = NULL WHERE edge_id = $1",
+                    &[&edge_id.as_bytes().as_slice()],
+                )?;
+                self.execute(MERGE_DUPLICATE_EDGES, &[&edge_id.as_bytes().as_slice()])?;
+            }
+
+            OperationPayload::RestoreFacet { entity_id, facet_type } => {
+                self.execute(
+                    "UPDATE facets SET detached_at = NULL, detached_by = NULL, detached_in_bundle = NULL, preserve_values = NULL WHERE entity_id = $1 AND facet_type = $2",
+                    &[&entity_id.as_bytes().as_slice(), facet_type],
+                )?;
+            }
+
+            OperationPayload::ConfirmFieldMapping { source_table, target_table, source_field, target_field } => {
+                self.execute(
+                    UPSERT_FIELD_MAPPING,
+                    &[
+                        &source_table.as_bytes().as_slice(),
+                        &target_table.as_bytes().as_slice(),
+                        source_field,
+                        target_field,
+                        &op_id,
+                        &actor,
+                        &hlc,
+                    ],
+                )?;
+            }
+
+            // Operations not yet materialized -- stored in oplog only
+            OperationPayload::ApplyCrdt { .. }
+            | OperationPayload::ClearAndAdd { .. }
+            | OperationPayload::CreateOrderedEdge { .. }
+            | OperationPayload::MoveOrderedEdge { .. }
+            | OperationPayload::LinkTables { .. }
+            | OperationPayload::UnlinkTables { .. }
+            | OperationPayload::AddToTable { .. }
+            | OperationPayload::RemoveFromTable { .. }
+            | OperationPayload::MergeEntities { .. }
+            | OperationPayload::SplitEntity { .. }
+            | OperationPayload::CreateRule { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+fn read_op(row: &Row) -> Result<Operation, StorageError> {
+    let module_versions: BTreeMap<String, String> =
+        rmp_serde::from_slice(row.get(5)).map_err(serialization_error)?;
+    Ok(Operation {
+        op_id: OpId::from_bytes(to_array::<16>(row.get(0), "op_id")?),
+        actor_id: actor_at(row, 1, "actor_id")?,
+        hlc: hlc_at(row, 2, "hlc")?,
+        bundle_id: BundleId::from_bytes(to_array::<16>(row.get(3), "bundle_id")?),
+        module_versions,
+        payload: OperationPayload::from_msgpack(row.get(4))?,
+        signature: Signature::from_bytes(to_array::<64>(row.get(6), "signature")?),
+    })
+}
+
+fn read_bundle(row: &Row) -> Result<Bundle, StorageError> {
+    let bundle_type = match row.get::<_, i32>(3) {
+        1 => BundleType::UserEdit,
+        2 => BundleType::ScriptOutput,
+        3 => BundleType::Import,
+        4 => BundleType::System,
+        other => return Err(StorageError::Serialization(format!("unknown bundle_type: {other}"))),
+    };
+    let creator_vc = row
+        .get::<_, Option<&[u8]>>(10)
+        .map(|bytes| VectorClock::from_msgpack(bytes).map_err(serialization_error))
+        .transpose()?;
+    Ok(Bundle {
+        bundle_id: BundleId::from_bytes(to_array::<16>(row.get(0), "bundle_id")?),
+        actor_id: actor_at(row, 1, "actor_id")?,
+        hlc: hlc_at(row, 2, "hlc")?,
+        bundle_type,
+        op_count: row.get::<_, i64>(4) as u32,
+        checksum: to_array::<32>(row.get(5), "checksum")?,
+        creates: rmp_serde::from_slice(row.get(6)).map_err(serialization_error)?,
+        deletes: rmp_serde::from_slice(row.get(7)).map_err(serialization_error)?,
+        meta: row.get(8),
+        signature: Signature::from_bytes(to_array::<64>(row.get(9), "signature")?),
+        creator_vc,
+    })
+}
+
+fn read_edge(row: &Row) -> Result<EdgeRecord, StorageError> {
+    Ok(EdgeRecord {
+        edge_id: EdgeId::from_bytes(to_array::<16>(row.get(0), "edge_id")?),
+        edge_type: row.get(1),
+        source_id: EntityId::from_bytes(to_array::<16>(row.get(2), "source_id")?),
+        target_id: EntityId::from_bytes(to_array::<16>(row.get(3), "target_id")?),
+        created_at: hlc_at(row, 4, "created_at")?,
+        created_by: actor_at(row, 5, "created_by")?,
+        created_in_bundle: BundleId::from_bytes(to_array::<16>(row.get(7), "created_in_bundle")?),
+        deleted: row.get(6),
+    })
+}
+
+/// The `deleted_at, deleted_by, deleted_in_bundle` columns starting at `first`.
+fn read_deletion(row: &Row, first: usize) -> Result<Deletion, StorageError> {
+    Ok(Deletion {
+        deleted_at: hlc_at(row, first, "deleted_at")?,
+        deleted_by: actor_at(row, first + 1, "deleted_by")?,
+        deleted_in_bundle: BundleId::from_bytes(to_array::<16>(row.get(first + 2), "deleted_in_bundle")?),
+    })
+}
+
+/// Conflict columns in `CONFLICT_COLUMNS` order; values are loaded separately.
+fn read_conflict(row: &Row) -> Result<ConflictRecord, StorageError> {
+    let optional_hlc = |idx: usize, label: &str| -> Result<Option<Hlc>, StorageError> {
+        row.get::<_, Option<Vec<u8>>>(idx)
+            .map(|b| Ok(Hlc::from_bytes(&to_array::<12>(b, label)?)))
+            .transpose()
+    };
+    let optional_op = |idx: usize, label: &str| -> Result<Option<OpId>, StorageError> {
+        row.get::<_, Option<Vec<u8>>>(idx)
+            .map(|b| Ok(OpId::from_bytes(to_array::<16>(b, label)?)))
+            .transpose()
+    };
+    Ok(ConflictRecord {
+        conflict_id: ConflictId::from_bytes(to_array::<16>(row.get(0), "conflict_id")?),
+        entity_id: EntityId::from_bytes(to_array::<16>(row.get(1), "entity_id")?),
+        field_key: row.get(2),
+        status: ConflictStatus::parse(row.get(3))?,
+        values: Vec::new(),
+        detected_at: hlc_at(row, 4, "detected_at")?,
+        detected_in_bundle: BundleId::from_bytes(to_array::<16>(row.get(5), "detected_in_bundle")?),
+        resolved_at: optional_hlc(6, "resolved_at")?,
+        resolved_by: row
+            .get::<_, Option<Vec<u8>>>(7)
+            .map(|b| Ok::<_, StorageError>(ActorId::from_bytes(to_array::<32>(b, "resolved_by")?)))
+            .transpose()?,
+        resolved_op_id: optional_op(8, "resolved_op_id")?,
+        resolved_value: row.get(9),
+        reopened_at: optional_hlc(10, "reopened_at")?,
+        reopened_by_op: optional_op(11, "reopened_by_op")?,
+    })
+}
+
+impl Transactional for PostgresStorage {
+    fn begin_transaction(&self) -> Result<(), StorageError> {
+        self.batch("BEGIN")?;
+        self.in_transaction.set(true);
+        Ok(())
+    }
+
+    fn commit_transaction(&self) -> Result<(), StorageError> {
+        self.in_transaction.set(false);
+        self.batch("COMMIT")
+    }
+
+    fn rollback_transaction(&self) -> Result<(), StorageError> {
+        self.in_transaction.set(false);
+        self.batch("ROLLBACK")
+    }
+}
+
+impl MaterializationStore for PostgresStorage {
+    fn rebuild_from_oplog(&mut self) -> Result<u64, StorageError> {
+        self.atomic(|| {
+            // Children before parents to respect FK constraints
+            self.batch(
+                "DELETE FROM conflict_values;
+                 DELETE FROM conflicts;
+                 DELETE FROM edge_properties;
+                 DELETE FROM fields;
+                 DELETE FROM facets;
+                 DELETE FROM edges;
+                 DELETE FROM entities;
+                 DELETE FROM field_mappings;
+                 DELETE FROM actors;
+                 DELETE FROM vector_clock;
+                 DELETE FROM materialization_state;",
+            )?;
+            self.replay_ops_from(Hlc::new(0, 0), &mut |_| {})
+        })
+    }
+
+    fn materialize_from(
+        &mut self,
+        from: Hlc,
+        progress: &mut dyn FnMut(MaterializeProgress),
+    ) -> Result<u64, StorageError> {
+        self.atomic(|| {
+            let from_bytes = from.to_bytes();
+            let from_param: &Params = &[&from_bytes.as_slice()];
+            // Rows created at or after `from` go entirely (children first)
+            self.execute(
+                "DELETE FROM edge_properties WHERE updated_at >= $1 OR edge_id IN (
+                     SELECT edge_id FROM edges WHERE created_at >= $1
+                         OR source_id IN (SELECT entity_id FROM entities WHERE created_at >= $1)
+                         OR target_id IN (SELECT entity_id FROM entities WHERE created_at >= $1))",
+                from_param,
+            )?;
+            self.execute(
+                "DELETE FROM edges WHERE created_at >= $1
+                     OR source_id IN (SELECT entity_id FROM entities WHERE created_at >= $1)
+                     OR target_id IN (SELECT entity_id FROM entities WHERE created_at >= $1)",
+                from_param,
+            )?;
+            self.execute(
+                "DELETE FROM fields WHERE updated_at >= $1
+                     OR entity_id IN (SELECT entity_id FROM entities WHERE created_at >= $1)",
+                from_param,
+            )?;
+            self.execute(
+                "DELETE FROM facets WHERE attached_at >= $1
+                     OR entity_id IN (SELECT entity_id FROM entities WHERE created_at >= $1)",
+                from_param,
+            )?;
+            self.execute("DELETE FROM entities WHERE created_at >= $1", from_param)?;
+            self.execute("DELETE FROM field_mappings WHERE confirmed_at >= $1", from_param)?;
+            // Later deletions and detaches on older rows are undone
+            self.execute(
+                "UPDATE entities SET deleted_at = NULL, deleted_by = NULL, deleted_in_bundle = NULL WHERE deleted_at >= $1",
+                from_param,
+            )?;
+            self.execute(
+                "UPDATE edges SET deleted_at = NULL, deleted_by = NULL, deleted_in_bundle = NULL WHERE deleted_at >= $1",
+                from_param,
+            )?;
+            self.execute(
+                "UPDATE facets SET detached_at = NULL, detached_by = NULL, detached_in_bundle = NULL, preserve_values = NULL
+                 WHERE detached_at >= $1",
+                from_param,
+            )?;
+            self.replay_ops_from(from, progress)
+        })
+    }
+
+    fn materialization_watermark(&self) -> Result<Option<u64>, StorageError> {
+        Ok(self
+            .query_opt("SELECT seq FROM materialization_state WHERE id = 1", &[])?
+            .map(|row| row.get::<_, i64>(0) as u64))
+    }
+
+    fn checkpoint_materialization(&mut self) -> Result<(), StorageError> {
+        self.checkpoint()
+    }
+
+    fn add_unique_edge_type(&mut self, edge_type: &str) -> Result<(), StorageError> {
+        let added = self.execute("INSERT INTO unique_edge_types (edge_type) VALUES ($1) ON CONFLICT DO NOTHING", &[&edge_type])?;
+        if added > 0 {
+            self.execute(
+                "UPDATE edges SET deleted_at = created_at, deleted_by = created_by, deleted_in_bundle = created_in_bundle
+                 WHERE deleted_at IS NULL AND edge_type = $1
+                   AND EXISTS (
+                       SELECT 1 FROM edges AS earlier
+                       WHERE earlier.source_id = edges.source_id AND earlier.target_id = edges.target_id
+                         AND earlier.edge_type = edges.edge_type
+                         AND (earlier.created_at, earlier.edge_id) < (edges.created_at, edges.edge_id))",
+                &[&edge_type],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn list_unique_edge_types(&self) -> Result<Vec<String>, StorageError> {
+        Ok(self
+            .query("SELECT edge_type FROM unique_edge_types ORDER BY edge_type", &[])?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    fn set_facet_fields(&mut self, facet_type: &str, field_keys: &[&str]) -> Result<(), StorageError> {
+        self.atomic(|| {
+            self.execute("DELETE FROM facet_fields WHERE facet_type = $1", &[&facet_type])?;
+            for field_key in field_keys {
+                self.execute(
+                    "INSERT INTO facet_fields (facet_type, field_key) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                    &[&facet_type, field_key],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    fn get_facet_fields(&self, facet_type: &str) -> Result<Vec<String>, StorageError> {
+        Ok(self
+            .query("SELECT field_key FROM facet_fields WHERE facet_type = $1 ORDER BY field_key", &[&facet_type])?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+}
+
+impl Storage for PostgresStorage {
+    fn append_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<Vec<OpId>, StorageError> {
+        check_bundle_ops(bundle, operations)?;
+        // Idempotent: skip if bundle already ingested
+        let bundle_id = bundle.bundle_id.as_bytes().as_slice();
+        if self.count("SELECT COUNT(*) FROM bundles WHERE bundle_id = $1", &[&bundle_id])? > 0 {
+            return Ok(operations.iter().map(|op| op.op_id).collect());
+        }
+
+        self.atomic(|| {
+            let creator_vc_bytes = bundle
+                .creator_vc
+                .as_ref()
+                .map(|vc| vc.to_msgpack().map_err(serialization_error))
+                .transpose()?;
+            self.execute(
+                &format!("INSERT INTO bundles ({BUNDLE_COLUMNS}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"),
+                &[
+                    &bundle_id,
+                    &bundle.actor_id.as_bytes().as_slice(),
+                    &bundle.hlc.to_bytes().as_slice(),
+                    &(bundle.bundle_type as i32),
+                    &(bundle.op_count as i64),
+                    &bundle.checksum.as_slice(),
+                    &rmp_serde::to_vec(&bundle.creates).map_err(serialization_error)?,
+                    &rmp_serde::to_vec(&bundle.deletes).map_err(serialization_error)?,
+                    &bundle.meta.as_deref(),
+                    &bundle.signature.as_bytes().as_slice(),
+                    &creator_vc_bytes,
+                ],
+            )?;
+            let meta = indexed_meta(bundle);
+            for tag in &meta.tags {
+                self.execute(
+                    "INSERT INTO bundle_tags (tag, bundle_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                    &[tag, &bundle_id],
+                )?;
+            }
+            if let Some(session_id) = meta.session {
+                self.execute(
+                    "INSERT INTO bundle_sessions (bundle_id, session_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                    &[&bundle_id, &session_id.as_bytes().as_slice()],
+                )?;
+            }
+            if let Some(key) = &meta.idempotency_key {
+                self.execute(
+                    "INSERT INTO bundle_idempotency_keys (actor_id, idempotency_key, bundle_id) VALUES ($1, $2, $3)
+                     ON CONFLICT DO NOTHING",
+                    &[&bundle.actor_id.as_bytes().as_slice(), key, &bundle_id],
+                )?;
+            }
+
+            for op in operations {
+                let payload_bytes = op.payload.to_msgpack()?;
+                let mv_bytes = rmp_serde::to_vec(&op.module_versions).map_err(serialization_error)?;
+                let entity_id = op.payload.entity_id();
+                let edge_id = op.payload.property_edge_id();
+                self.execute(
+                    "INSERT INTO oplog (op_id, actor_id, hlc, bundle_id, payload, module_versions, signature, op_type, entity_id, edge_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+",
+                    &[
+                        &op.op_id.as_bytes().as_slice(),
+                        &op.actor_id.as_bytes().as_slice(),
+                        &op.hlc.to_bytes().as_slice(),
+                        &op.bundle_id.as_bytes().as_slice(),
+                        &payload_bytes,
+                        &mv_bytes,
+                        &op.signature.as_bytes().as_slice(),
+                        &op.payload.op_type_name(),
+                        &entity_id.as_ref().map(|eid| eid.as_bytes().as_slice()),
+                        &edge_id.as_ref().map(|eid| eid.as_bytes().as_slice()),
+                    ],
+                )?;
+                self.materialize_op(op, bundle)?;
+                self.track_actor(op)?;
+            }
+            Ok(Vec::new())
+        })
+    }
+
+    fn get_ops_canonical(&self) -> Result<Vec<Operation>, StorageError> {
+        self.query_ops("ORDER BY hlc, op_id", &[])
+    }
+
+    fn get_ops_page(
+        &self,
+        after: Option<(Hlc, OpId)>,
+        limit: usize,
+    ) -> Result<Vec<Operation>, StorageError> {
+        let limit = limit as i64;
+        match after {
+            Some((hlc, op_id)) => self.query_ops(
+                "WHERE (hlc, op_id) > ($1, $2) ORDER BY hlc, op_id LIMIT $3",
+                &[&hlc.to_bytes().as_slice(), &op_id.as_bytes().as_slice(), &limit],
+            ),
+            None => self.query_ops("ORDER BY hlc, op_id LIMIT $1", &[&limit]),
+        }
+    }
+
+    fn get_ops_after_seq(&self, after: u64, limit: usize) -> Result<Vec<(u64, Operation)>, StorageError> {
+        self.query(
+            &format!("SELECT {OP_COLUMNS}, rowid FROM oplog WHERE rowid > $1 ORDER BY rowid LIMIT $2"),
+            &[&(after as i64), &(limit as i64)],
+        )?
+        .iter()
+        .map(|row| Ok((row.get::<_, i64>(7) as u64, read_op(row)?)))
+        .collect()
+    }
+
+    fn get_ops_by_bundle(&self, bundle_id: BundleId) -> Result<Vec<Operation>, StorageError> {
+        self.query_ops("WHERE bundle_id = $1 ORDER BY rowid", &[&bundle_id.as_bytes().as_slice()])
+    }
+
+    fn get_ops_by_actor_after(
+        &self,
+        actor_id: ActorId,
+        after: Hlc,
+    ) -> Result<Vec<Operation>, StorageError> {
+        self.query_ops(
+            "WHERE actor_id = $1 AND hlc > $2 ORDER BY hlc, op_id",
+            &[&actor_id.as_bytes().as_slice(), &after.to_bytes().as_slice()],
+        )
+    }
+
+    fn get_ops_by_entity(&self, entity_id: EntityId) -> Result<Vec<Operation>, StorageError> {
+        self.query_ops("WHERE entity_id = $1 ORDER BY hlc, op_id", &[&entity_id.as_bytes().as_slice()])
+    }
+
+    fn get_edge_property_ops(&self, edge_id: EdgeId) -> Result<Vec<Operation>, StorageError> {
+        self.query_ops("WHERE edge_id = $1 ORDER BY hlc, op_id", &[&edge_id.as_bytes().as_slice()])
+    }
+
+    fn get_ops_by_type(&self, op_types: &[&str]) -> Result<Vec<Operation>, StorageError> {
+        self.query_ops("WHERE op_type = ANY($1) ORDER BY hlc, op_id", &[&op_types])
+    }
+
+    fn get_entities_changed_since(&self, after: u64) -> Result<Vec<EntityId>, StorageError> {
+        self.query(
+            "SELECT DISTINCT entity_id FROM oplog
+             WHERE rowid > $1 AND entity_id IS NOT NULL AND op_type NOT IN ('CreateEdge', 'CreateOrderedEdge')
+             ORDER BY entity_id",
+            &[&(after as i64)],
+        )?
+        .iter()
+        .map(|row| Ok(EntityId::from_bytes(to_array::<16>(row.get(0), "entity_id")?)))
+        .collect()
+    }
+
+    fn get_edges_changed_since(&self, after: u64) -> Result<Vec<EdgeId>, StorageError> {
+        let mut edges = BTreeSet::new();
+        for row in self.query(
+            "SELECT payload FROM oplog WHERE rowid > $1 AND op_type = ANY($2)",
+            &[&(after as i64), &EDGE_OP_TYPES],
+        )? {
+            edges.extend(OperationPayload::from_msgpack(row.get(0))?.edge_ids());
+        }
+        Ok(edges.into_iter().collect())
+    }
+
+    fn op_count(&self) -> Result<u64, StorageError> {
+        self.count("SELECT COUNT(*) FROM oplog", &[])
+    }
+
+    fn analyze(&mut self) -> Result<(), StorageError> {
+        self.client.borrow_mut().batch_execute("ANALYZE").map_err(pg_error)
+    }
+
+    fn get_entity(&self, entity_id: EntityId) -> Result<Option<EntityRecord>, StorageError> {
+        self.query_opt(
+            "SELECT entity_id, created_at, created_by, (deleted_at IS NOT NULL), placeholder FROM entities WHERE entity_id = $1",
+            &[&entity_id.as_bytes().as_slice()],
+        )?
+        .map(|row| {
+            Ok(EntityRecord {
+                entity_id: EntityId::from_bytes(to_array::<16>(row.get(0), "entity_id")?),
+                created_at: hlc_at(&row, 1, "created_at")?,
+                created_by: actor_at(&row, 2, "created_by")?,
+                deleted: row.get(3),
+                placeholder: row.get(4),
+            })
+        })
+        .transpose()
+    }
+
+    fn get_fields(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<Vec<(String, FieldValue)>, StorageError> {
+        self.query(
+            "SELECT field_key, value FROM fields WHERE entity_id = $1 AND value IS NOT NULL",
+            &[&entity_id.as_bytes().as_slice()],
+        )?
+        .iter()
+        .map(|row| Ok((row.get(0), FieldValue::from_msgpack(row.get(1)).map_err(serialization_error)?)))
+        .collect()
+    }
+
+    fn get_field(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<FieldValue>, StorageError> {
+        self.query_opt(
+            "SELECT value FROM fields WHERE entity_id = $1 AND field_key = $2 AND value IS NOT NULL",
+            &[&entity_id.as_bytes().as_slice(), &field_key],
+        )?
+        .map(|row| FieldValue::from_msgpack(row.get(0)).map_err(serialization_error))
+        .transpose()
+    }
+
+    fn get_facets(&self, entity_id: EntityId) -> Result<Vec<FacetRecord>, StorageError> {
+        self.query(
+            "SELECT entity_id, facet_type, attached_at, attached_by, (detached_at IS NOT NULL) FROM facets WHERE entity_id = $1",
+            &[&entity_id.as_bytes().as_slice()],
+        )?
+        .iter()
+        .map(|row| {
+            Ok(FacetRecord {
+                entity_id: EntityId::from_bytes(to_array::<16>(row.get(0), "entity_id")?),
+                facet_type: row.get(1),
+                attached_at: hlc_at(row, 2, "attached_at")?,
+                attached_by: actor_at(row, 3, "attached_by")?,
+                detached: row.get(4),
+            })
+        })
+        .collect()
+    }
+
+    fn get_preserved_values(
+        &self,
+        entity_id: EntityId,
+        facet_type: &str,
+    ) -> Result<Option<Vec<(String, FieldValue)>>, StorageError> {
+        self.query_opt(
+            "SELECT preserve_values FROM facets
+             WHERE entity_id = $1 AND facet_type = $2 AND detached_at IS NOT NULL AND preserve_values IS NOT NULL",
+            &[&entity_id.as_bytes().as_slice(), &facet_type],
+        )?
+        .map(|row| decode_preserved_values(row.get(0)))
+        .transpose()
+    }
+
+    fn get_entities_by_facet(&self, facet_type: &str) -> Result<Vec<EntityId>, StorageError> {
+        self.query(
+            "SELECT entity_id FROM facets WHERE facet_type = $1 AND detached_at IS NULL",
+            &[&facet_type],
+        )?
+        .iter()
+        .map(|row| Ok(EntityId::from_bytes(to_array::<16>(row.get(0), "entity_id")?)))
+        .collect()
+    }
+
+    /// Creation-time pages are cut in SQL. Field values are msgpack, which
+    /// Postgres cannot order, so field pages sort the facet's values here.
+    fn get_entities_page(
+        &self,
+        facet_type: &str,
+        order: &EntityOrder,
+        after: Option<&EntityPageCursor>,
+        limit: usize,
+    ) -> Result<EntityPage, StorageError> {
+        let fetch = limit.saturating_add(1).min(i64::MAX as usize) as i64;
+        let Some(field_key) = &order.field_key else {
+            let (comparison, direction) = if order.descending { ("<", "DESC") } else { (">", "ASC") };
+            let live = "SELECT e.created_at, e.entity_id FROM facets f JOIN entities e ON e.entity_id = f.entity_id
+                        WHERE f.facet_type = $1 AND f.detached_at IS NULL AND e.deleted_at IS NULL";
+            let rows = match after {
+                Some(after) => self.query(
+                    &format!(
+                        "{live} AND (e.created_at, e.entity_id) {comparison} ($2, $3)
+                         ORDER BY e.created_at {direction}, e.entity_id {direction} LIMIT $4"
+                    ),
+                    &[&facet_type, &after.sort_key, &after.entity_id.as_bytes().as_slice(), &fetch],
+                )?,
+                None => self.query(
+                    &format!("{live} ORDER BY e.created_at {direction}, e.entity_id {direction} LIMIT $2"),
+                    &[&facet_type, &fetch],
+                )?,
+            };
+            return rows
+                .iter()
+                .map(|row| Ok((row.get(0), EntityId::from_bytes(to_array::<16>(row.get(1), "entity_id")?))))
+                .collect::<Result<Vec<_>, StorageError>>()
+                .map(|rows| EntityPage::from_rows(rows, limit));
+        };
+        let mut rows = self
+            .query(
+                "SELECT fl.value, e.entity_id FROM facets f JOIN entities e ON e.entity_id = f.entity_id
+                 LEFT JOIN fields fl ON fl.entity_id = e.entity_id AND fl.field_key = $2
+                 WHERE f.facet_type = $1 AND f.detached_at IS NULL AND e.deleted_at IS NULL",
+                &[&facet_type, field_key],
+            )?
+            .iter()
+            .map(|row| {
+                let value = row
+                    .get::<_, Option<&[u8]>>(0)
+                    .map(FieldValue::from_msgpack)
+                    .transpose()
+                    .map_err(serialization_error)?;
+                Ok((field_sort_key(value.as_ref()), EntityId::from_bytes(to_array::<16>(row.get(1), "entity_id")?)))
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        if let Some(after) = after {
+            let after = (after.sort_key.clone(), after.entity_id);
+            rows.retain(|row| if order.descending { *row < after } else { *row > after });
+        }
+        rows.sort();
+        if order.descending {
+            rows.reverse();
+        }
+        rows.truncate(fetch as usize);
+        Ok(EntityPage::from_rows(rows, limit))
+    }
+
+    fn get_entities_with_fields(
+        &self,
+        facet_type: &str,
+        field_keys: &[&str],
+    ) -> Result<Vec<EntityFields>, StorageError> {
+        let rows = self.query(
+            "SELECT e.entity_id, fl.field_key, fl.value FROM facets f JOIN entities e ON e.entity_id = f.entity_id
+             LEFT JOIN fields fl ON fl.entity_id = e.entity_id AND fl.value IS NOT NULL AND fl.field_key = ANY($2)
+             WHERE f.facet_type = $1 AND f.detached_at IS NULL AND e.deleted_at IS NULL
+             ORDER BY e.entity_id",
+            &[&facet_type, &field_keys],
+        )?;
+        let mut result: Vec<EntityFields> = Vec::new();
+        for row in &rows {
+            let entity_id = EntityId::from_bytes(to_array::<16>(row.get(0), "entity_id")?);
+            if result.last().is_none_or(|(last, _)| *last != entity_id) {
+                result.push((entity_id, Vec::new()));
+            }
+            if let (Some(field_key), Some(value)) = (row.get::<_, Option<String>>(1), row.get::<_, Option<&[u8]>>(2)) {
+                let value = FieldValue::from_msgpack(value).map_err(serialization_error)?;
+                result.last_mut().expect("pushed above").1.push((field_key, value));
+            }
+        }
+        for (_, fields) in &mut result {
+            fields.sort_by_key(|(key, _)| field_keys.iter().position(|k| k == key));
+        }
+        Ok(result)
+    }
+
+    fn count_entities_by_facet(&self) -> Result<Vec<(String, u64)>, StorageError> {
+        Ok(self
+            .query(
+                "SELECT f.facet_type, COUNT(*) FROM facets f JOIN entities e ON e.entity_id = f.entity_id
+                 WHERE f.detached_at IS NULL AND e.deleted_at IS NULL
+                 GROUP BY f.facet_type ORDER BY f.facet_type",
+                &[],
+            )?
+            .iter()
+            .map(|row| (row.get(0), row.get::<_, i64>(1) as u64))
+            .collect())
+    }
+
+    /// Values are msgpack, which Postgres cannot decode, so the matching
+    /// values are selected in SQL and combined here.
+    fn aggregate_field(
+        &self,
+        facet_type: &str,
+        field_key: &str,
+        aggregate: Aggregate,
+    ) -> Result<Option<FieldValue>, StorageError> {
+        let values = self
+            .query(
+                "SELECT fl.value FROM fields fl
+                 JOIN facets f ON f.entity_id = fl.entity_id AND f.facet_type = $1 AND f.detached_at IS NULL
+                 JOIN entities e ON e.entity_id = fl.entity_id AND e.deleted_at IS NULL
+                 WHERE fl.field_key = $2 AND fl.value IS NOT NULL",
+                &[&facet_type, &field_key],
+            )?
+            .iter()
+            .map(|row| FieldValue::from_msgpack(row.get(0)).map_err(serialization_error))
+            .collect::<Result<Vec<_>, _>>()?;
+        aggregate_numbers(values, aggregate)
+    }
+
+    fn get_field_mappings(&self, source_table: TableId) -> Result<Vec<FieldMappingRecord>, StorageError> {
+        self.query(
+            "SELECT target_table, source_field, target_field, confirmed_by, confirmed_at FROM field_mappings
+             WHERE source_table = $1 ORDER BY target_table, source_field",
+            &[&source_table.as_bytes().as_slice()],
+        )?
+        .iter()
+        .map(|row| {
+            Ok(FieldMappingRecord {
+                source_table,
+                target_table: TableId::from_bytes(to_array::<16>(row.get(0), "target_table")?),
+                source_field: row.get(1),
+                target_field: row.get(2),
+                confirmed_by: actor_at(row, 3, "confirmed_by")?,
+                confirmed_at: hlc_at(row, 4, "confirmed_at")?,
+            })
+        })
+        .collect()
+    }
+
+    fn get_edges_from(&self, entity_id: EntityId, filter: &EdgeFilter) -> Result<Vec<EdgeRecord>, StorageError> {
+        self.query_filtered_edges("WHERE source_id = $1", &[&entity_id.as_bytes().as_slice()], filter)
+    }
+
+    fn get_edges_to(&self, entity_id: EntityId, filter: &EdgeFilter) -> Result<Vec<EdgeRecord>, StorageError> {
+        self.query_filtered_edges("WHERE target_id = $1", &[&entity_id.as_bytes().as_slice()], filter)
+    }
+
+    fn get_edges_between(
+        &self,
+        source_id: EntityId,
+        target_id: EntityId,
+        filter: &EdgeFilter,
+    ) -> Result<Vec<EdgeRecord>, StorageError> {
+        self.query_filtered_edges(
+            "WHERE source_id = $1 AND target_id = $2",
+            &[&source_id.as_bytes().as_slice(), &target_id.as_bytes().as_slice()],
+            filter,
+        )
+    }
+
+    fn get_edges_by_type(&self, edge_type: &str) -> Result<Vec<EdgeRecord>, StorageError> {
+        self.query_edges("WHERE edge_type = $1 AND deleted_at IS NULL", &[&edge_type])
+    }
+
+    fn get_deleted_entities(
+        &self,
+        facet_type: Option<&str>,
+        since: Option<Hlc>,
+    ) -> Result<Vec<(EntityRecord, Deletion)>, StorageError> {
+        let since_bytes = since.map(|hlc| hlc.to_bytes().to_vec());
+        let mut sql = String::from(
+            "SELECT entity_id, created_at, created_by, deleted_at, deleted_by, deleted_in_bundle, placeholder FROM entities WHERE deleted_at IS NOT NULL",
+        );
+        let mut params: Vec<&(dyn ToSql + Sync)> = Vec::new();
+        if let Some(since) = &since_bytes {
+            params.push(since);
+            sql.push_str(&format!(" AND deleted_at > ${}", params.len()));
+        }
+        if let Some(facet_type) = &facet_type {
+            params.push(facet_type);
+            sql.push_str(&format!(
+                " AND entity_id IN (SELECT entity_id FROM facets WHERE facet_type = ${} AND detached_at IS NULL)",
+                params.len()
+            ));
+        }
+        sql.push_str(" ORDER BY deleted_at DESC, entity_id");
+        self.query(&sql, &params)?
+            .iter()
+            .map(|row| {
+                let entity = EntityRecord {
+                    entity_id: EntityId::from_bytes(to_array::<16>(row.get(0), "entity_id")?),
+                    created_at: hlc_at(row, 1, "created_at")?,
+                    created_by: actor_at(row, 2, "created_by")?,
+                    deleted: true,
+                    placeholder: row.get(6),
+                };
+                Ok((entity, read_deletion(row, 3)?))
+            })
+            .collect()
+    }
+
+    fn get_deleted_edges(&self) -> Result<Vec<(EdgeRecord, Deletion)>, StorageError> {
+        self.query(
+            &format!(
+                "SELECT {EDGE_COLUMNS}, deleted_at, deleted_by, deleted_in_bundle FROM edges
+                 WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, edge_id"
+            ),
+            &[],
+        )?
+        .iter()
+        .map(|row| Ok((read_edge(row)?, read_deletion(row, 8)?)))
+        .collect()
+    }
+
+    fn get_vector_clock(&self) -> Result<VectorClock, StorageError> {
+        let mut vc = VectorClock::new();
+        for row in self.query("SELECT actor_id, max_hlc FROM vector_clock", &[])? {
+            vc.update(actor_at(&row, 0, "actor_id")?, hlc_at(&row, 1, "max_hlc")?);
+        }
+        Ok(vc)
+    }
+
+    fn get_last_hlc(&self) -> Result<Option<Hlc>, StorageError> {
+        self.query_opt("SELECT last_hlc FROM clock_state WHERE id = 1", &[])?
+            .map(|row| hlc_at(&row, 0, "last_hlc"))
+            .transpose()
+    }
+
+    fn set_last_hlc(&mut self, hlc: Hlc) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO clock_state (id, last_hlc) VALUES (1, $1)
+             ON CONFLICT (id) DO UPDATE SET last_hlc = excluded.last_hlc
+             WHERE excluded.last_hlc > clock_state.last_hlc",
+            &[&hlc.to_bytes().as_slice()],
+        )?;
+        Ok(())
+    }
+
+    fn get_field_metadata(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<(ActorId, Hlc)>, StorageError> {
+        self.query_opt(
+            "SELECT source_actor, updated_at FROM fields WHERE entity_id = $1 AND field_key = $2",
+            &[&entity_id.as_bytes().as_slice(), &field_key],
+        )?
+        .map(|row| Ok((actor_at(&row, 0, "source_actor")?, hlc_at(&row, 1, "updated_at")?)))
+        .transpose()
+    }
+
+    fn get_edge(&self, edge_id: EdgeId) -> Result<Option<EdgeRecord>, StorageError> {
+        Ok(self.query_edges("WHERE edge_id = $1", &[&edge_id.as_bytes().as_slice()])?.pop())
+    }
+
+    fn get_edge_properties(
+        &self,
+        edge_id: EdgeId,
+    ) -> Result<Vec<(String, FieldValue)>, StorageError> {
+        self.query(
+            "SELECT property_key, value FROM edge_properties WHERE edge_id = $1 AND value IS NOT NULL",
+            &[&edge_id.as_bytes().as_slice()],
+        )?
+        .iter()
+        .map(|row| Ok((row.get(0), FieldValue::from_msgpack(row.get(1)).map_err(serialization_error)?)))
+        .collect()
+    }
+
+    fn get_edge_property(
+        &self,
+        edge_id: EdgeId,
+        key: &str,
+    ) -> Result<Option<FieldValue>, StorageError> {
+        self.query_opt(
+            "SELECT value FROM edge_properties WHERE edge_id = $1 AND property_key = $2 AND value IS NOT NULL",
+            &[&edge_id.as_bytes().as_slice(), &key],
+        )?
+        .map(|row| FieldValue::from_msgpack(row.get(0)).map_err(serialization_error))
+        .transpose()
+    }
+
+    fn get_edge_property_metadata(
+        &self,
+        edge_id: EdgeId,
+        key: &str,
+    ) -> Result<Option<(ActorId, Hlc)>, StorageError> {
+        self.query_opt(
+            "SELECT source_actor, updated_at FROM edge_properties WHERE edge_id = $1 AND property_key = $2",
+            &[&edge_id.as_bytes().as_slice(), &key],
+        )?
+        .map(|row| Ok((actor_at(&row, 0, "source_actor")?, hlc_at(&row, 1, "updated_at")?)))
+        .transpose()
+    }
+
+    fn insert_conflict(&mut self, record: &ConflictRecord) -> Result<(), StorageError> {
+        self.atomic(|| {
+            self.execute(
+                "INSERT INTO conflicts (conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle) VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &record.conflict_id.as_bytes().as_slice(),
+                    &record.entity_id.as_bytes().as_slice(),
+                    &record.field_key,
+                    &record.status.as_str(),
+                    &record.detected_at.to_bytes().as_slice(),
+                    &record.detected_in_bundle.as_bytes().as_slice(),
+                ],
+            )?;
+            for value in &record.values {
+                self.insert_conflict_value(record.conflict_id, value)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn update_conflict_resolved(
+        &mut self,
+        conflict_id: ConflictId,
+        resolved_at: Hlc,
+        resolved_by: ActorId,
+        resolved_op: OpId,
+        resolved_value: Option<Vec<u8>>,
+    ) -> Result<(), StorageError> {
+        self.execute(
+            "UPDATE conflicts SET status = 'resolved', resolved_at = $1, resolved_by = $2, resolved_op_id = $3, resolved_value = $4 WHERE conflict_id = $5",
+            &[
+                &resolved_at.to_bytes().as_slice(),
+                &resolved_by.as_bytes().as_slice(),
+                &resolved_op.as_bytes().as_slice(),
+                &resolved_value,
+                &conflict_id.as_bytes().as_slice(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get_open_conflicts_for_entity(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<Vec<ConflictRecord>, StorageError> {
+        self.query_conflicts("WHERE entity_id = $1 AND status = 'open'", &[&entity_id.as_bytes().as_slice()])
+    }
+
+    fn get_open_conflicts(&self) -> Result<Vec<ConflictRecord>, StorageError> {
+        self.query_conflicts("WHERE status = 'open' ORDER BY detected_at", &[])
+    }
+
+    fn get_conflict(
+        &self,
+        conflict_id: ConflictId,
+    ) -> Result<Option<ConflictRecord>, StorageError> {
+        self.query_conflict("WHERE conflict_id = $1", &[&conflict_id.as_bytes().as_slice()])
+    }
+
+    fn get_open_conflict_for_field(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<ConflictRecord>, StorageError> {
+        self.query_conflict(
+            "WHERE entity_id = $1 AND field_key = $2 AND status = 'open'",
+            &[&entity_id.as_bytes().as_slice(), &field_key],
+        )
+    }
+
+    fn get_latest_conflict_for_field(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<ConflictRecord>, StorageError> {
+        self.query_conflict(
+            "WHERE entity_id = $1 AND field_key = $2 ORDER BY detected_at DESC LIMIT 1",
+            &[&entity_id.as_bytes().as_slice(), &field_key],
+        )
+    }
+
+    fn set_conflict_triage(
+        &mut self,
+        conflict_id: ConflictId,
+        triage: ConflictTriage,
+    ) -> Result<(), StorageError> {
+        let updated = self.execute(
+            "UPDATE conflicts SET seen_at = $1, snoozed_until = $2 WHERE conflict_id = $3",
+            &[&triage.seen_at, &triage.snoozed_until, &conflict_id.as_bytes().as_slice()],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::NotFound(format!("conflict {conflict_id}")));
+        }
+        Ok(())
+    }
+
+    fn get_conflict_triage(
+        &self,
+        conflict_id: ConflictId,
+    ) -> Result<Option<ConflictTriage>, StorageError> {
+        Ok(self
+            .query_opt(
+                "SELECT seen_at, snoozed_until FROM conflicts WHERE conflict_id = $1",
+                &[&conflict_id.as_bytes().as_slice()],
+            )?
+            .map(|row| ConflictTriage { seen_at: row.get(0), snoozed_until: row.get(1) }))
+    }
+
+    fn count_unseen_conflicts(&self, now: i64) -> Result<u64, StorageError> {
+        self.count(
+            "SELECT COUNT(*) FROM conflicts
+             WHERE status = 'open' AND seen_at IS NULL AND (snoozed_until IS NULL OR snoozed_until <= $1)",
+            &[&now],
+        )
+    }
+
+    fn reopen_conflict(
+        &mut self,
+        conflict_id: ConflictId,
+        reopened_at: Hlc,
+        reopened_by_op: OpId,
+        new_values: &[ConflictValue],
+    ) -> Result<(), StorageError> {
+        self.atomic(|| {
+            let conflict = conflict_id.as_bytes().as_slice();
+            self.execute(
+                "UPDATE conflicts SET status = 'open', reopened_at = $1, reopened_by_op = $2, seen_at = NULL, snoozed_until = NULL
+                 WHERE conflict_id = $3",
+                &[&reopened_at.to_bytes().as_slice(), &reopened_by_op.as_bytes().as_slice(), &conflict],
+            )?;
+            // Replace all branch tips with the new values
+            self.execute("DELETE FROM conflict_values WHERE conflict_id = $1", &[&conflict])?;
+            for value in new_values {
+                self.insert_conflict_value(conflict_id, value)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn add_conflict_value(
+        &mut self,
+        conflict_id: ConflictId,
+        value: &ConflictValue,
+    ) -> Result<(), StorageError> {
+        self.atomic(|| {
+            self.insert_conflict_value(conflict_id, value)?;
+            self.execute(
+                "UPDATE conflicts SET seen_at = NULL, snoozed_until = NULL WHERE conflict_id = $1",
+                &[&conflict_id.as_bytes().as_slice()],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn get_bundle_vector_clock(
+        &self,
+        bundle_id: BundleId,
+    ) -> Result<Option<VectorClock>, StorageError> {
+        let row = self.query_opt(
+            "SELECT creator_vector_clock FROM bundles WHERE bundle_id = $1",
+            &[&bundle_id.as_bytes().as_slice()],
+        )?;
+        row.and_then(|row| row.get::<_, Option<Vec<u8>>>(0))
+            .map(|bytes| VectorClock::from_msgpack(&bytes).map_err(serialization_error))
+            .transpose()
+    }
+
+    fn get_bundle(&self, bundle_id: BundleId) -> Result<Option<Bundle>, StorageError> {
+        self.read_bundle(bundle_id)
+    }
+
+    fn get_bundle_ids_in_range(&self, range: &HlcRange) -> Result<Vec<(Hlc, BundleId)>, StorageError> {
+        let end = range.end.map(|end| end.to_bytes().to_vec());
+        self.query(
+            "SELECT hlc, bundle_id FROM bundles
+             WHERE hlc >= $1 AND ($2::BYTEA IS NULL OR hlc < $2)
+             ORDER BY hlc, bundle_id",
+            &[&range.start.to_bytes().as_slice(), &end],
+        )?
+        .iter()
+        .map(|row| Ok((hlc_at(row, 0, "hlc")?, BundleId::from_bytes(to_array::<16>(row.get(1), "bundle_id")?))))
+        .collect()
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_field_source_bundle_vc(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<(ActorId, Hlc, OpId, Option<VectorClock>)>, StorageError> {
+        self.query_opt(
+            "SELECT f.source_actor, f.updated_at, f.source_op, b.creator_vector_clock
+             FROM fields f
+             JOIN oplog o ON o.op_id = f.source_op
+             JOIN bundles b ON b.bundle_id = o.bundle_id
+             WHERE f.entity_id = $1 AND f.field_key = $2",
+            &[&entity_id.as_bytes().as_slice(), &field_key],
+        )?
+        .map(|row| {
+            let vc = row
+                .get::<_, Option<&[u8]>>(3)
+                .map(|bytes| VectorClock::from_msgpack(bytes).map_err(serialization_error))
+                .transpose()?;
+            Ok((
+                actor_at(&row, 0, "source_actor")?,
+                hlc_at(&row, 1, "updated_at")?,
+                OpId::from_bytes(to_array::<16>(row.get(2), "source_op")?),
+                vc,
+            ))
+        })
+        .transpose()
+    }
+
+    fn get_op_field_value(&self, op_id: OpId) -> Result<Option<Vec<u8>>, StorageError> {
+        let Some(row) = self.query_opt("SELECT payload FROM oplog WHERE op_id = $1", &[&op_id.as_bytes().as_slice()])?
+        else {
+            return Ok(None);
+        };
+        match OperationPayload::from_msgpack(row.get(0))? {
+            OperationPayload::SetField { value, .. }
+            | OperationPayload::ResolveConflict { chosen_value: Some(value), .. } => {
+                Ok(Some(value.to_msgpack().map_err(serialization_error)?))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// ============================================================================
+// Overlay CRUD
+// ============================================================================
+
+impl OverlayStore for PostgresStorage {
+    fn insert_overlay(
+        &mut self,
+        overlay_id: OverlayId,
+        display_name: &str,
+        source: &str,
+        status: &str,
+        created_at: &Hlc,
+    ) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO overlays (overlay_id, display_name, source, status, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $5)",
+            &[&overlay_id.as_bytes().as_slice(), &display_name, &source, &status, &created_at.to_bytes().as_slice()],
+        )?;
+        Ok(())
+    }
+
+    fn update_overlay_status(
+        &mut self,
+        overlay_id: OverlayId,
+        status: &str,
+        updated_at: &Hlc,
+    ) -> Result<(), StorageError> {
+        self.execute(
+            "UPDATE overlays SET status = $1, updated_at = $2 WHERE overlay_id = $3",
+            &[&status, &updated_at.to_bytes().as_slice(), &overlay_id.as_bytes().as_slice()],
+        )?;
+        Ok(())
+    }
+
+    fn delete_overlay(&mut self, overlay_id: OverlayId) -> Result<(), StorageError> {
+        // overlay_ops go with it (ON DELETE CASCADE)
+        self.execute("DELETE FROM overlays WHERE overlay_id = $1", &[&overlay_id.as_bytes().as_slice()])?;
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_overlay(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<Option<(OverlayId, String, String, String, Hlc, Hlc)>, StorageError> {
+        self.query_opt(
+            "SELECT overlay_id, display_name, source, status, created_at, updated_at FROM overlays WHERE overlay_id = $1",
+            &[&overlay_id.as_bytes().as_slice()],
+        )?
+        .map(|row| {
+            Ok((
+                OverlayId::from_bytes(to_array::<16>(row.get(0), "overlay_id")?),
+                row.get(1),
+                row.get(2),
+                row.get(3),
+                hlc_at(&row, 4, "created_at")?,
+                hlc_at(&row, 5, "updated_at")?,
+            ))
+        })
+        .transpose()
+    }
+
+    fn list_overlays_by_status(
+        &self,
+        status: &str,
+    ) -> Result<Vec<(OverlayId, String, String, Hlc)>, StorageError> {
+        self.query(
+            "SELECT overlay_id, display_name, source, created_at FROM overlays WHERE status = $1 ORDER BY created_at",
+            &[&status],
+        )?
+        .iter()
+        .map(|row| {
+            Ok((
+                OverlayId::from_bytes(to_array::<16>(row.get(0), "overlay_id")?),
+                row.get(1),
+                row.get(2),
+                hlc_at(row, 3, "created_at")?,
+            ))
+        })
+        .collect()
+    }
+
+    fn set_overlay_drift_policy(&mut self, overlay_id: OverlayId, policy: &str) -> Result<(), StorageError> {
+        let updated = self.execute(
+            "UPDATE overlays SET drift_policy = $1 WHERE overlay_id = $2",
+            &[&policy, &overlay_id.as_bytes().as_slice()],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::NotFound(format!("overlay {overlay_id}")));
+        }
+        Ok(())
+    }
+
+    fn get_overlay_drift_policy(&self, overlay_id: OverlayId) -> Result<Option<String>, StorageError> {
+        Ok(self
+            .query_opt("SELECT drift_policy FROM overlays WHERE overlay_id = $1", &[&overlay_id.as_bytes().as_slice()])?
+            .map(|row| row.get(0)))
+    }
+
+    fn set_overlay_origin(&mut self, overlay_id: OverlayId, origin: &OverlayOrigin) -> Result<(), StorageError> {
+        let updated = self.execute(
+            "UPDATE overlays SET script_id = $1, script_execution_id = $2, auto_commit = $3 WHERE overlay_id = $4",
+            &[&origin.script_id, &origin.run_id, &origin.auto_commit, &overlay_id.as_bytes().as_slice()],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::NotFound(format!("overlay {overlay_id}")));
+        }
+        Ok(())
+    }
+
+    fn get_overlay_origin(&self, overlay_id: OverlayId) -> Result<Option<OverlayOrigin>, StorageError> {
+        Ok(self
+            .query_opt(
+                "SELECT script_id, script_execution_id, auto_commit FROM overlays WHERE overlay_id = $1",
+                &[&overlay_id.as_bytes().as_slice()],
+            )?
+            .map(|row| OverlayOrigin { script_id: row.get(0), run_id: row.get(1), auto_commit: row.get(2) }))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert_overlay_op(
+        &mut self,
+        overlay_id: OverlayId,
+        op_id: OpId,
+        hlc: &Hlc,
+        payload_bytes: &[u8],
+        entity_id: Option<EntityId>,
+        field_key: Option<&str>,
+        op_type: &str,
+        canonical_value_at_creation: Option<&[u8]>,
+    ) -> Result<i64, StorageError> {
+        let row = self.client.borrow_mut().query_one(
+            "INSERT INTO overlay_ops (overlay_id, op_id, hlc, payload, entity_id, field_key, op_type, canonical_value_at_creation)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING rowid",
+            &[
+                &overlay_id.as_bytes().as_slice(),
+                &op_id.as_bytes().as_slice(),
+                &hlc.to_bytes().as_slice(),
+                &payload_bytes,
+                &entity_id.as_ref().map(|eid| eid.as_bytes().as_slice()),
+                &field_key,
+                &op_type,
+                &canonical_value_at_creation,
+            ],
+        );
+        Ok(row.map_err(pg_error)?.get(0))
+    }
+
+    fn delete_overlay_op(&mut self, rowid: i64) -> Result<(), StorageError> {
+        self.execute("DELETE FROM overlay_ops WHERE rowid = $1", &[&rowid])?;
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_overlay_ops(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<Vec<(i64, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, String, Option<Vec<u8>>, bool, Option<String>)>, StorageError> {
+        self.query_overlay_ops("WHERE overlay_id = $1", &[&overlay_id.as_bytes().as_slice()])
+    }
+
+    fn get_latest_overlay_field_op(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<(i64, Vec<u8>)>, StorageError> {
+        Ok(self
+            .query_opt(
+                "SELECT rowid, payload FROM overlay_ops WHERE overlay_id = $1 AND entity_id = $2 AND field_key = $3 ORDER BY rowid DESC LIMIT 1",
+                &[&overlay_id.as_bytes().as_slice(), &entity_id.as_bytes().as_slice(), &field_key],
+            )?
+            .map(|row| (row.get(0), row.get(1))))
+    }
+
+    fn count_overlay_ops(&self, overlay_id: OverlayId) -> Result<u64, StorageError> {
+        self.count("SELECT COUNT(*) FROM overlay_ops WHERE overlay_id = $1", &[&overlay_id.as_bytes().as_slice()])
+    }
+
+    fn mark_overlay_ops_drifted(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<u64, StorageError> {
+        self.execute(
+            "UPDATE overlay_ops SET canonical_drifted = true WHERE entity_id = $1 AND field_key = $2 AND NOT canonical_drifted",
+            &[&entity_id.as_bytes().as_slice(), &field_key],
+        )
+    }
+
+    fn clear_drift_flag(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<(), StorageError> {
+        self.execute(
+            "UPDATE overlay_ops SET canonical_drifted = false WHERE overlay_id = $1 AND entity_id = $2 AND field_key = $3 AND canonical_drifted",
+            &[&overlay_id.as_bytes().as_slice(), &entity_id.as_bytes().as_slice(), &field_key],
+        )?;
+        Ok(())
+    }
+
+    fn update_canonical_value_at_creation(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+        new_value: Option<&[u8]>,
+    ) -> Result<(), StorageError> {
+        self.execute(
+            "UPDATE overlay_ops SET canonical_value_at_creation = $4 WHERE overlay_id = $1 AND entity_id = $2 AND field_key = $3",
+            &[&overlay_id.as_bytes().as_slice(), &entity_id.as_bytes().as_slice(), &field_key, &new_value],
+        )?;
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_drifted_overlay_ops(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<Vec<(i64, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, String, Option<Vec<u8>>, bool, Option<String>)>, StorageError> {
+        self.query_overlay_ops("WHERE overlay_id = $1 AND canonical_drifted", &[&overlay_id.as_bytes().as_slice()])
+    }
+
+    fn count_unresolved_drift(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<u64, StorageError> {
+        self.count(
+            "SELECT COUNT(*) FROM overlay_ops WHERE overlay_id = $1 AND canonical_drifted",
+            &[&overlay_id.as_bytes().as_slice()],
+        )
+    }
+
+    fn delete_overlay_ops_for_field(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<u64, StorageError> {
+        self.execute(
+            "DELETE FROM overlay_ops WHERE overlay_id = $1 AND entity_id = $2 AND field_key = $3",
+            &[&overlay_id.as_bytes().as_slice(), &entity_id.as_bytes().as_slice(), &field_key],
+        )
+    }
+}
+
+// ============================================================================
+// Bundle Labels
+// ============================================================================
+
+impl LabelStore for PostgresStorage {
+    fn set_bundle_label(
+        &mut self,
+        bundle_id: BundleId,
+        label: &str,
+    ) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO bundle_labels (bundle_id, label) VALUES ($1, $2)
+             ON CONFLICT (bundle_id) DO UPDATE SET label = excluded.label",
+            &[&bundle_id.as_bytes().as_slice(), &label],
+        )?;
+        Ok(())
+    }
+
+    fn get_bundle_label(&self, bundle_id: BundleId) -> Result<Option<String>, StorageError> {
+        Ok(self
+            .query_opt("SELECT label FROM bundle_labels WHERE bundle_id = $1", &[&bundle_id.as_bytes().as_slice()])?
+            .map(|row| row.get(0)))
+    }
+
+    fn list_bundles_with_labels(&self) -> Result<Vec<(Bundle, Option<String>)>, StorageError> {
+        let columns: Vec<String> = BUNDLE_COLUMNS.split(", ").map(|c| format!("b.{c}")).collect();
+        self.query(
+            &format!(
+                "SELECT {}, l.label FROM bundles b
+                 LEFT JOIN bundle_labels l ON l.bundle_id = b.bundle_id
+                 ORDER BY b.hlc, b.bundle_id",
+                columns.join(", ")
+            ),
+            &[],
+        )?
+        .iter()
+        .map(|row| Ok((read_bundle(row)?, row.get(11))))
+        .collect()
+    }
+
+    fn get_bundles_by_tag(&self, tag: &str) -> Result<Vec<Bundle>, StorageError> {
+        let columns: Vec<String> = BUNDLE_COLUMNS.split(", ").map(|c| format!("b.{c}")).collect();
+        self.query(
+            &format!(
+                "SELECT {} FROM bundle_tags t JOIN bundles b ON b.bundle_id = t.bundle_id
+                 WHERE t.tag = $1 ORDER BY b.hlc, b.bundle_id",
+                columns.join(", ")
+            ),
+            &[&tag],
+        )?
+        .iter()
+        .map(read_bundle)
+        .collect()
+    }
+
+    fn get_bundles_by_session(&self, session_id: SessionId) -> Result<Vec<Bundle>, StorageError> {
+        let columns: Vec<String> = BUNDLE_COLUMNS.split(", ").map(|c| format!("b.{c}")).collect();
+        self.query(
+            &format!(
+                "SELECT {} FROM bundle_sessions s JOIN bundles b ON b.bundle_id = s.bundle_id
+                 WHERE s.session_id = $1 ORDER BY b.hlc, b.bundle_id",
+                columns.join(", ")
+            ),
+            &[&session_id.as_bytes().as_slice()],
+        )?
+        .iter()
+        .map(read_bundle)
+        .collect()
+    }
+
+    fn get_bundle_by_idempotency_key(&self, actor_id: ActorId, key: &str) -> Result<Option<Bundle>, StorageError> {
+        let columns: Vec<String> = BUNDLE_COLUMNS.split(", ").map(|c| format!("b.{c}")).collect();
+        self.query_opt(
+            &format!(
+                "SELECT {} FROM bundle_idempotency_keys k JOIN bundles b ON b.bundle_id = k.bundle_id
+                 WHERE k.actor_id = $1 AND k.idempotency_key = $2",
+                columns.join(", ")
+            ),
+            &[&actor_id.as_bytes().as_slice(), &key],
+        )?
+        .as_ref()
+        .map(read_bundle)
+        .transpose()
+    }
+}
+
+// ============================================================================
+// Pending Bundles
+// ============================================================================
+
+impl PendingStore for PostgresStorage {
+    fn insert_pending_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO pending_bundles (bundle_id, actor_id, hlc, bundle, operations)
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+            &[
+                &bundle.bundle_id.as_bytes().as_slice(),
+                &bundle.actor_id.as_bytes().as_slice(),
+                &bundle.hlc.to_bytes().as_slice(),
+                &rmp_serde::to_vec(bundle).map_err(serialization_error)?,
+                &rmp_serde::to_vec(operations).map_err(serialization_error)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn delete_pending_bundle(&mut self, bundle_id: BundleId) -> Result<bool, StorageError> {
+        let rows = self.execute("DELETE FROM pending_bundles WHERE bundle_id = $1", &[&bundle_id.as_bytes().as_slice()])?;
+        Ok(rows > 0)
+    }
+
+    fn list_pending_bundles(&self) -> Result<Vec<PendingBundleRecord>, StorageError> {
+        self.query_held_bundles(
+            "SELECT bundle, operations, received_at FROM pending_bundles ORDER BY hlc, bundle_id",
+            &[],
+        )
+    }
+
+    fn count_pending_bundles(&self) -> Result<u64, StorageError> {
+        self.count("SELECT COUNT(*) FROM pending_bundles", &[])
+    }
+}
+
+// ============================================================================
+// Quarantine
+// ============================================================================
+
+const QUARANTINE_COLUMNS: &str = "quarantine_id, bundle_id, actor_id, raw, reason, received_at";
+
+fn quarantine_at(row: &Row) -> Result<QuarantinedBundle, StorageError> {
+    Ok(QuarantinedBundle {
+        quarantine_id: row.get(0),
+        bundle_id: row
+            .get::<_, Option<Vec<u8>>>(1)
+            .map(|b| to_array::<16>(b, "bundle_id").map(BundleId::from_bytes))
+            .transpose()?,
+        actor_id: row
+            .get::<_, Option<Vec<u8>>>(2)
+            .map(|a| to_array::<32>(a, "actor_id").map(ActorId::from_bytes))
+            .transpose()?,
+        raw: RawBundle::from_msgpack(row.get(3)).map_err(serialization_error)?,
+        reason: row.get(4),
+        received_at: row.get(5),
+    })
+}
+
+impl QuarantineStore for PostgresStorage {
+    fn insert_quarantined_bundle(
+        &mut self,
+        raw: &RawBundle,
+        bundle_id: Option<BundleId>,
+        actor_id: Option<ActorId>,
+        reason: &str,
+    ) -> Result<i64, StorageError> {
+        let raw_bytes = raw.to_msgpack().map_err(serialization_error)?;
+        let digest = blake3::hash(&raw_bytes);
+        let row = self
+            .client
+            .borrow_mut()
+            .query_one(
+                "INSERT INTO quarantined_bundles (digest, bundle_id, actor_id, raw, reason)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (digest) DO UPDATE SET reason = EXCLUDED.reason
+                 RETURNING quarantine_id",
+                &[
+                    &digest.as_bytes().as_slice(),
+                    &bundle_id.as_ref().map(|b| b.as_bytes().as_slice()),
+                    &actor_id.as_ref().map(|a| a.as_bytes().as_slice()),
+                    &raw_bytes,
+                    &reason,
+                ],
+            )
+            .map_err(pg_error)?;
+        Ok(row.get(0))
+    }
+
+    fn get_quarantined_bundle(&self, quarantine_id: i64) -> Result<Option<QuarantinedBundle>, StorageError> {
+        self.query_opt(
+            &format!("SELECT {QUARANTINE_COLUMNS} FROM quarantined_bundles WHERE quarantine_id = $1"),
+            &[&quarantine_id],
+        )?
+        .as_ref()
+        .map(quarantine_at)
+        .transpose()
+    }
+
+    fn list_quarantined_bundles(&self) -> Result<Vec<QuarantinedBundle>, StorageError> {
+        self.query(&format!("SELECT {QUARANTINE_COLUMNS} FROM quarantined_bundles ORDER BY quarantine_id"), &[])?
+            .iter()
+            .map(quarantine_at)
+            .collect()
+    }
+
+    fn update_quarantine_reason(&mut self, quarantine_id: i64, reason: &str) -> Result<(), StorageError> {
+        self.execute(
+            "UPDATE quarantined_bundles SET reason = $2 WHERE quarantine_id = $1",
+            &[&quarantine_id, &reason],
+        )?;
+        Ok(())
+    }
+
+    fn delete_quarantined_bundle(&mut self, quarantine_id: i64) -> Result<bool, StorageError> {
+        Ok(self.execute("DELETE FROM quarantined_bundles WHERE quarantine_id = $1", &[&quarantine_id])? > 0)
+    }
+}
+
+// ============================================================================
+// Actor Directory
+// ============================================================================
+
+impl ActorStore for PostgresStorage {
+    fn set_actor_display_name(&mut self, actor_id: ActorId, display_name: Option<&str>) -> Result<(), StorageError> {
+        self.execute(
+            "UPDATE actors SET display_name = $2 WHERE actor_id = $1",
+            &[&actor_id.as_bytes().as_slice(), &display_name],
+        )?;
+        Ok(())
+    }
+
+    fn list_actors(&self) -> Result<Vec<ActorRecord>, StorageError> {
+        self.query(
+            "SELECT a.actor_id, a.display_name, a.first_seen_at, v.max_hlc,
+                    (SELECT COUNT(*) FROM oplog o WHERE o.actor_id = a.actor_id)
+             FROM actors a JOIN vector_clock v ON v.actor_id = a.actor_id
+             ORDER BY a.first_seen_at, a.actor_id",
+            &[],
+        )?
+        .iter()
+        .map(|row| {
+            Ok(ActorRecord {
+                actor_id: actor_at(row, 0, "actor_id")?,
+                display_name: row.get(1),
+                first_seen_at: hlc_at(row, 2, "first_seen_at")?,
+                last_seen_at: hlc_at(row, 3, "max_hlc")?,
+                op_count: row.get::<_, i64>(4) as u64,
+            })
+        })
+        .collect()
+    }
+}
+
+// ============================================================================
+// Actor Trust
+// ============================================================================
+
+impl TrustStore for PostgresStorage {
+    fn set_actor_trust(&mut self, actor_id: ActorId, state: TrustState) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO actor_trust (actor_id, state) VALUES ($1, $2)
+             ON CONFLICT (actor_id) DO UPDATE SET state = excluded.state,
+                 updated_at = (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT",
+            &[&actor_id.as_bytes().as_slice(), &state.as_str()],
+        )?;
+        Ok(())
+    }
+
+    fn get_actor_trust(&self, actor_id: ActorId) -> Result<Option<TrustState>, StorageError> {
+        self.query_opt("SELECT state FROM actor_trust WHERE actor_id = $1", &[&actor_id.as_bytes().as_slice()])?
+            .map(|row| TrustState::parse(row.get(0)))
+            .transpose()
+    }
+
+    fn list_actor_trust(&self) -> Result<Vec<(ActorId, TrustState)>, StorageError> {
+        self.query("SELECT actor_id, state FROM actor_trust ORDER BY actor_id", &[])?
+            .iter()
+            .map(|row| Ok((actor_at(row, 0, "actor_id")?, TrustState::parse(row.get(1))?)))
+            .collect()
+    }
+
+    fn insert_untrusted_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO untrusted_bundles (bundle_id, actor_id, hlc, bundle, operations)
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+            &[
+                &bundle.bundle_id.as_bytes().as_slice(),
+                &bundle.actor_id.as_bytes().as_slice(),
+                &bundle.hlc.to_bytes().as_slice(),
+                &rmp_serde::to_vec(bundle).map_err(serialization_error)?,
+                &rmp_serde::to_vec(operations).map_err(serialization_error)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn list_untrusted_bundles(&self, actor_id: ActorId) -> Result<Vec<PendingBundleRecord>, StorageError> {
+        self.query_held_bundles(
+            "SELECT bundle, operations, received_at FROM untrusted_bundles
+             WHERE actor_id = $1 ORDER BY hlc, bundle_id",
+            &[&actor_id.as_bytes().as_slice()],
+        )
+    }
+
+    fn delete_untrusted_bundles(&mut self, actor_id: ActorId) -> Result<usize, StorageError> {
+        let rows = self.execute("DELETE FROM untrusted_bundles WHERE actor_id = $1", &[&actor_id.as_bytes().as_slice()])?;
+        Ok(rows as usize)
+    }
+
+    fn count_untrusted_bundles(&self) -> Result<u64, StorageError> {
+        self.count("SELECT COUNT(*) FROM untrusted_bundles", &[])
+    }
+}
+
+// ============================================================================
+// Peers
+// ============================================================================
+
+impl PeerStore for PostgresStorage {
+    fn upsert_peer(
+        &mut self,
+        peer_id: ActorId,
+        display_name: Option<&str>,
+    ) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO peers (peer_id, display_name) VALUES ($1, $2)
+             ON CONFLICT (peer_id) DO UPDATE SET display_name = COALESCE(excluded.display_name, peers.display_name)",
+            &[&peer_id.as_bytes().as_slice(), &display_name],
+        )?;
+        Ok(())
+    }
+
+    fn delete_peer(&mut self, peer_id: ActorId) -> Result<bool, StorageError> {
+        let rows = self.execute("DELETE FROM peers WHERE peer_id = $1", &[&peer_id.as_bytes().as_slice()])?;
+        Ok(rows > 0)
+    }
+
+    fn set_peer_acked_vc(
+        &mut self,
+        peer_id: ActorId,
+        acked_vc: &VectorClock,
+    ) -> Result<(), StorageError> {
+        let vc_bytes = acked_vc.to_msgpack().map_err(serialization_error)?;
+        self.execute(
+            "INSERT INTO peer_sync_state (peer_id, acked_vector_clock, last_synced_at)
+             VALUES ($1, $2, (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT)
+             ON CONFLICT (peer_id) DO UPDATE SET acked_vector_clock = excluded.acked_vector_clock,
+                 last_synced_at = excluded.last_synced_at",
+            &[&peer_id.as_bytes().as_slice(), &vc_bytes],
+        )?;
+        Ok(())
+    }
+
+    fn get_peer(&self, peer_id: ActorId) -> Result<Option<PeerRecord>, StorageError> {
+        Ok(self.query_peers(Some(peer_id))?.pop())
+    }
+
+    fn list_peers(&self) -> Result<Vec<PeerRecord>, StorageError> {
+        self.query_peers(None)
+    }
+}
+
+// ============================================================================
+// Webhooks
+// ============================================================================
+
+impl WebhookStore for PostgresStorage {
+    fn insert_webhook(&mut self, webhook: &WebhookRecord) -> Result<(), StorageError> {
+        self.execute(
+            "INSERT INTO webhooks (webhook_id, url, filter, cursor, attempts, retry_at, last_error)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            &[
+                &webhook.webhook_id.as_bytes().as_slice(),
+                &webhook.url,
+                &webhook.filter,
+                &(webhook.cursor as i64),
+                &(webhook.attempts as i32),
+                &webhook.retry_at,
+                &webhook.last_error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn delete_webhook(&mut self, webhook_id: WebhookId) -> Result<bool, StorageError> {
+        let rows = self.execute("DELETE FROM webhooks WHERE webhook_id = $1", &[&webhook_id.as_bytes().as_slice()])?;
+        Ok(rows > 0)
+    }
+
+    fn get_webhook(&self, webhook_id: WebhookId) -> Result<Option<WebhookRecord>, StorageError> {
+        Ok(self.query_webhooks(Some(webhook_id))?.pop())
+    }
+
+    fn list_webhooks(&self) -> Result<Vec<WebhookRecord>, StorageError> {
+        self.query_webhooks(None)
+    }
+
+    fn update_webhook_delivery(
+        &mut self,
+        webhook_id: WebhookId,
+        cursor: u64,
+        attempts: u32,
+        retry_at: Option<i64>,
+        last_error: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let rows = self.execute(
+            "UPDATE webhooks SET cursor = $2, attempts = $3, retry_at = $4, last_error = $5 WHERE webhook_id = $1",
+            &[&webhook_id.as_bytes().as_slice(), &(cursor as i64), &(attempts as i32), &retry_at, &last_error],
+        )?;
+        if rows == 0 {
+            return Err(StorageError::NotFound(format!("webhook {webhook_id}")));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,371 @@
+use postgres::Client;
+
+use openprod_core::operations::OperationPayload;
+use openprod_storage::{schema::SCHEMA_VERSION, StorageError};
+
+use crate::pg::pg_error;
+
+/// Create the tables if needed and record the schema version. Columns added
+/// since are created in place (`ADD COLUMN IF NOT EXISTS`) and filled from
+/// existing rows where needed; a database from a newer build is rejected.
+pub fn init_schema(client: &mut Client) -> Result<(), StorageError> {
+    client.batch_execute(SCHEMA_SQL).map_err(pg_error)?;
+    let found: i32 = client
+        .query_one("SELECT COALESCE(MAX(version), 0) FROM schema_version", &[])
+        .map_err(pg_error)?
+        .get(0);
+    if found > SCHEMA_VERSION {
+        return Err(StorageError::SchemaTooNew { found, supported: SCHEMA_VERSION });
+    }
+    if found > 0 && found < 25 {
+        backfill_edge_ids(client)?;
+    }
+    client
+        .execute(
+            "INSERT INTO schema_version (version, applied_at)
+             VALUES ($1, EXTRACT(EPOCH FROM now())::BIGINT) ON CONFLICT DO NOTHING",
+            &[&SCHEMA_VERSION],
+        )
+        .map_err(pg_error)?;
+    Ok(())
+}
+
+/// Index the ops a database from before version 25 stored by the edge whose
+/// properties they write, as the SQLite migration does.
+fn backfill_edge_ids(client: &mut Client) -> Result<(), StorageError> {
+    let rows = client
+        .query(
+            "SELECT rowid, payload FROM oplog WHERE op_type IN ('CreateEdge', 'CreateOrderedEdge', 'SetEdgeProperty', 'ClearEdgeProperty')",
+            &[],
+        )
+        .map_err(pg_error)?;
+    for row in rows {
+        let (rowid, payload): (i64, Vec<u8>) = (row.get(0), row.get(1));
+        let Some(edge_id) = OperationPayload::from_msgpack(&payload).ok().and_then(|p| p.property_edge_id()) else {
+            continue;
+        };
+        client
+            .execute("UPDATE oplog SET edge_id = $1 WHERE rowid = $2", &[&edge_id.as_bytes().as_slice(), &rowid])
+            .map_err(pg_error)?;
+    }
+    Ok(())
+}
+
+/// Same tables as the SQLite schema: BLOB columns become BYTEA (compared
+/// bytewise, so HLC ordering is unchanged), INTEGER flags become BOOLEAN and
+/// rowids become identity columns.
+const SCHEMA_SQL: &str = "
+CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER PRIMARY KEY,
+    applied_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS oplog (
+    rowid BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    op_id BYTEA NOT NULL UNIQUE CHECK (length(op_id) = 16),
+    actor_id BYTEA NOT NULL CHECK (length(actor_id) = 32),
+    hlc BYTEA NOT NULL CHECK (length(hlc) = 12),
+    bundle_id BYTEA NOT NULL CHECK (length(bundle_id) = 16),
+    payload BYTEA NOT NULL,
+    module_versions BYTEA NOT NULL,
+    signature BYTEA NOT NULL CHECK (length(signature) = 64),
+    op_type TEXT NOT NULL,
+    entity_id BYTEA,
+    received_at BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT
+);
+CREATE INDEX IF NOT EXISTS idx_oplog_canonical_order ON oplog (hlc, op_id);
+CREATE INDEX IF NOT EXISTS idx_oplog_actor_hlc ON oplog (actor_id, hlc);
+CREATE INDEX IF NOT EXISTS idx_oplog_entity ON oplog (entity_id, hlc) WHERE entity_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_oplog_bundle ON oplog (bundle_id);
+CREATE INDEX IF NOT EXISTS idx_oplog_type ON oplog (op_type, hlc);
+ALTER TABLE oplog ADD COLUMN IF NOT EXISTS edge_id BYTEA;
+CREATE INDEX IF NOT EXISTS idx_oplog_edge ON oplog (edge_id, hlc) WHERE edge_id IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS bundles (
+    bundle_id BYTEA PRIMARY KEY CHECK (length(bundle_id) = 16),
+    actor_id BYTEA NOT NULL CHECK (length(actor_id) = 32),
+    hlc BYTEA NOT NULL CHECK (length(hlc) = 12),
+    bundle_type INTEGER NOT NULL,
+    op_count BIGINT NOT NULL,
+    checksum BYTEA NOT NULL CHECK (length(checksum) = 32),
+    creates BYTEA,
+    deletes BYTEA,
+    meta BYTEA,
+    signature BYTEA NOT NULL CHECK (length(signature) = 64),
+    creator_vector_clock BYTEA,
+    received_at BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT
+);
+CREATE INDEX IF NOT EXISTS idx_bundles_hlc ON bundles (hlc);
+CREATE INDEX IF NOT EXISTS idx_bundles_actor ON bundles (actor_id, hlc);
+CREATE INDEX IF NOT EXISTS idx_bundles_type ON bundles (bundle_type, hlc);
+
+CREATE TABLE IF NOT EXISTS entities (
+    entity_id BYTEA PRIMARY KEY CHECK (length(entity_id) = 16),
+    created_at BYTEA NOT NULL CHECK (length(created_at) = 12),
+    created_by BYTEA NOT NULL CHECK (length(created_by) = 32),
+    created_in_bundle BYTEA NOT NULL REFERENCES bundles(bundle_id),
+    deleted_at BYTEA CHECK (deleted_at IS NULL OR length(deleted_at) = 12),
+    deleted_by BYTEA CHECK (deleted_by IS NULL OR length(deleted_by) = 32),
+    deleted_in_bundle BYTEA REFERENCES bundles(bundle_id),
+    redirect_to BYTEA REFERENCES entities(entity_id),
+    redirect_at BYTEA CHECK (redirect_at IS NULL OR length(redirect_at) = 12)
+);
+-- Added in place so existing databases pick it up
+ALTER TABLE entities ADD COLUMN IF NOT EXISTS placeholder BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX IF NOT EXISTS idx_entities_active ON entities (created_at) WHERE deleted_at IS NULL AND redirect_to IS NULL;
+CREATE INDEX IF NOT EXISTS idx_entities_deleted ON entities (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_entities_redirects ON entities (redirect_to) WHERE redirect_to IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS fields (
+    entity_id BYTEA NOT NULL REFERENCES entities(entity_id),
+    field_key TEXT NOT NULL,
+    value BYTEA,
+    source_op BYTEA NOT NULL CHECK (length(source_op) = 16),
+    source_actor BYTEA NOT NULL CHECK (length(source_actor) = 32),
+    updated_at BYTEA NOT NULL CHECK (length(updated_at) = 12),
+    PRIMARY KEY (entity_id, field_key)
+);
+CREATE INDEX IF NOT EXISTS idx_fields_key_value ON fields (field_key, value);
+CREATE INDEX IF NOT EXISTS idx_fields_source_op ON fields (source_op);
+
+CREATE TABLE IF NOT EXISTS facets (
+    entity_id BYTEA NOT NULL REFERENCES entities(entity_id),
+    facet_type TEXT NOT NULL,
+    attached_at BYTEA NOT NULL CHECK (length(attached_at) = 12),
+    attached_by BYTEA NOT NULL CHECK (length(attached_by) = 32),
+    attached_in_bundle BYTEA NOT NULL REFERENCES bundles(bundle_id),
+    source_type TEXT NOT NULL DEFAULT 'user',
+    detached_at BYTEA CHECK (detached_at IS NULL OR length(detached_at) = 12),
+    detached_by BYTEA CHECK (detached_by IS NULL OR length(detached_by) = 32),
+    detached_in_bundle BYTEA REFERENCES bundles(bundle_id),
+    preserve_values BYTEA,
+    PRIMARY KEY (entity_id, facet_type)
+);
+CREATE INDEX IF NOT EXISTS idx_facets_type ON facets (facet_type) WHERE detached_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_facets_type_detached ON facets (facet_type, detached_at);
+
+CREATE TABLE IF NOT EXISTS edges (
+    edge_id BYTEA PRIMARY KEY CHECK (length(edge_id) = 16),
+    edge_type TEXT NOT NULL,
+    source_id BYTEA NOT NULL REFERENCES entities(entity_id),
+    target_id BYTEA NOT NULL REFERENCES entities(entity_id),
+    created_at BYTEA NOT NULL CHECK (length(created_at) = 12),
+    created_by BYTEA NOT NULL CHECK (length(created_by) = 32),
+    created_in_bundle BYTEA NOT NULL REFERENCES bundles(bundle_id),
+    deleted_at BYTEA CHECK (deleted_at IS NULL OR length(deleted_at) = 12),
+    deleted_by BYTEA CHECK (deleted_by IS NULL OR length(deleted_by) = 32),
+    deleted_in_bundle BYTEA REFERENCES bundles(bundle_id)
+);
+CREATE INDEX IF NOT EXISTS idx_edges_source ON edges (source_id, edge_type) WHERE deleted_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_edges_target ON edges (target_id, edge_type) WHERE deleted_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_edges_type ON edges (edge_type) WHERE deleted_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_edges_deleted ON edges (deleted_in_bundle) WHERE deleted_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_edges_source_all ON edges (source_id);
+CREATE INDEX IF NOT EXISTS idx_edges_target_all ON edges (target_id);
+CREATE INDEX IF NOT EXISTS idx_edges_between ON edges (source_id, target_id, edge_type);
+
+CREATE TABLE IF NOT EXISTS unique_edge_types (
+    edge_type TEXT PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS facet_fields (
+    facet_type TEXT NOT NULL,
+    field_key TEXT NOT NULL,
+    PRIMARY KEY (facet_type, field_key)
+);
+
+CREATE TABLE IF NOT EXISTS edge_properties (
+    edge_id BYTEA NOT NULL REFERENCES edges(edge_id),
+    property_key TEXT NOT NULL,
+    value BYTEA,
+    source_op BYTEA NOT NULL CHECK (length(source_op) = 16),
+    source_actor BYTEA NOT NULL CHECK (length(source_actor) = 32),
+    updated_at BYTEA NOT NULL CHECK (length(updated_at) = 12),
+    PRIMARY KEY (edge_id, property_key)
+);
+CREATE INDEX IF NOT EXISTS idx_edge_properties_source_op ON edge_properties (source_op);
+
+CREATE TABLE IF NOT EXISTS actors (
+    actor_id BYTEA PRIMARY KEY CHECK (length(actor_id) = 32),
+    display_name TEXT,
+    first_seen_at BYTEA NOT NULL CHECK (length(first_seen_at) = 12)
+);
+
+CREATE TABLE IF NOT EXISTS vector_clock (
+    actor_id BYTEA PRIMARY KEY CHECK (length(actor_id) = 32),
+    max_hlc BYTEA NOT NULL CHECK (length(max_hlc) = 12)
+);
+
+CREATE TABLE IF NOT EXISTS conflicts (
+    conflict_id BYTEA PRIMARY KEY CHECK (length(conflict_id) = 16),
+    entity_id BYTEA NOT NULL REFERENCES entities(entity_id),
+    field_key TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'resolved')),
+    detected_at BYTEA NOT NULL CHECK (length(detected_at) = 12),
+    detected_in_bundle BYTEA NOT NULL REFERENCES bundles(bundle_id),
+    resolved_at BYTEA CHECK (resolved_at IS NULL OR length(resolved_at) = 12),
+    resolved_by BYTEA CHECK (resolved_by IS NULL OR length(resolved_by) = 32),
+    resolved_op_id BYTEA CHECK (resolved_op_id IS NULL OR length(resolved_op_id) = 16),
+    resolved_value BYTEA,
+    reopened_at BYTEA CHECK (reopened_at IS NULL OR length(reopened_at) = 12),
+    reopened_by_op BYTEA CHECK (reopened_by_op IS NULL OR length(reopened_by_op) = 16)
+);
+CREATE INDEX IF NOT EXISTS idx_conflicts_entity ON conflicts (entity_id, field_key) WHERE status = 'open';
+CREATE INDEX IF NOT EXISTS idx_conflicts_status ON conflicts (status);
+-- Local triage state, added in place so existing databases pick it up
+ALTER TABLE conflicts ADD COLUMN IF NOT EXISTS seen_at BIGINT;
+ALTER TABLE conflicts ADD COLUMN IF NOT EXISTS snoozed_until BIGINT;
+
+CREATE TABLE IF NOT EXISTS conflict_values (
+    conflict_id BYTEA NOT NULL REFERENCES conflicts(conflict_id),
+    actor_id BYTEA NOT NULL CHECK (length(actor_id) = 32),
+    hlc BYTEA NOT NULL CHECK (length(hlc) = 12),
+    op_id BYTEA NOT NULL CHECK (length(op_id) = 16),
+    value BYTEA,
+    PRIMARY KEY (conflict_id, actor_id)
+);
+
+CREATE TABLE IF NOT EXISTS overlays (
+    overlay_id BYTEA PRIMARY KEY CHECK (length(overlay_id) = 16),
+    display_name TEXT NOT NULL,
+    source TEXT NOT NULL DEFAULT 'user' CHECK (source IN ('user', 'script', 'import')),
+    source_id TEXT,
+    status TEXT NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'stashed', 'committed', 'discarded')),
+    created_at BYTEA NOT NULL CHECK (length(created_at) = 12),
+    updated_at BYTEA NOT NULL CHECK (length(updated_at) = 12),
+    script_id TEXT,
+    script_execution_id TEXT,
+    meta BYTEA
+);
+CREATE INDEX IF NOT EXISTS idx_overlays_status ON overlays (status);
+-- Added in place so existing databases pick them up
+ALTER TABLE overlays ADD COLUMN IF NOT EXISTS drift_policy TEXT NOT NULL DEFAULT 'block'
+    CHECK (drift_policy IN ('block', 'keep_mine', 'use_canonical', 'rebase_if_unchanged'));
+ALTER TABLE overlays ADD COLUMN IF NOT EXISTS auto_commit BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE overlays DROP CONSTRAINT IF EXISTS overlays_source_check;
+ALTER TABLE overlays ADD CONSTRAINT overlays_source_check CHECK (source IN ('user', 'script', 'import'));
+
+CREATE TABLE IF NOT EXISTS overlay_ops (
+    rowid BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    overlay_id BYTEA NOT NULL REFERENCES overlays(overlay_id) ON DELETE CASCADE,
+    op_id BYTEA NOT NULL CHECK (length(op_id) = 16),
+    hlc BYTEA NOT NULL CHECK (length(hlc) = 12),
+    payload BYTEA NOT NULL,
+    entity_id BYTEA CHECK (entity_id IS NULL OR length(entity_id) = 16),
+    field_key TEXT,
+    op_type TEXT NOT NULL,
+    canonical_value_at_creation BYTEA,
+    canonical_drifted BOOLEAN NOT NULL DEFAULT false
+);
+CREATE INDEX IF NOT EXISTS idx_overlay_ops_overlay ON overlay_ops (overlay_id);
+CREATE INDEX IF NOT EXISTS idx_overlay_ops_entity ON overlay_ops (overlay_id, entity_id, field_key);
+
+CREATE TABLE IF NOT EXISTS bundle_labels (
+    bundle_id BYTEA PRIMARY KEY REFERENCES bundles(bundle_id),
+    label TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS bundle_tags (
+    tag TEXT NOT NULL,
+    bundle_id BYTEA NOT NULL REFERENCES bundles(bundle_id),
+    PRIMARY KEY (tag, bundle_id)
+);
+
+CREATE TABLE IF NOT EXISTS field_mappings (
+    source_table BYTEA NOT NULL CHECK (length(source_table) = 16),
+    target_table BYTEA NOT NULL CHECK (length(target_table) = 16),
+    source_field TEXT NOT NULL,
+    target_field TEXT NOT NULL,
+    source_op BYTEA NOT NULL CHECK (length(source_op) = 16),
+    confirmed_by BYTEA NOT NULL CHECK (length(confirmed_by) = 32),
+    confirmed_at BYTEA NOT NULL CHECK (length(confirmed_at) = 12),
+    PRIMARY KEY (source_table, target_table, source_field)
+);
+
+CREATE TABLE IF NOT EXISTS bundle_sessions (
+    bundle_id BYTEA PRIMARY KEY REFERENCES bundles(bundle_id),
+    session_id BYTEA NOT NULL CHECK (length(session_id) = 16)
+);
+CREATE INDEX IF NOT EXISTS idx_bundle_sessions_session ON bundle_sessions(session_id);
+
+CREATE TABLE IF NOT EXISTS bundle_idempotency_keys (
+    actor_id BYTEA NOT NULL CHECK (length(actor_id) = 32),
+    idempotency_key TEXT NOT NULL,
+    bundle_id BYTEA NOT NULL REFERENCES bundles(bundle_id),
+    PRIMARY KEY (actor_id, idempotency_key)
+);
+
+CREATE TABLE IF NOT EXISTS pending_bundles (
+    bundle_id BYTEA PRIMARY KEY CHECK (length(bundle_id) = 16),
+    actor_id BYTEA NOT NULL CHECK (length(actor_id) = 32),
+    hlc BYTEA NOT NULL CHECK (length(hlc) = 12),
+    bundle BYTEA NOT NULL,
+    operations BYTEA NOT NULL,
+    received_at BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT
+);
+CREATE INDEX IF NOT EXISTS idx_pending_bundles_hlc ON pending_bundles (hlc);
+
+CREATE TABLE IF NOT EXISTS peers (
+    peer_id BYTEA PRIMARY KEY CHECK (length(peer_id) = 32),
+    display_name TEXT,
+    added_at BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS peer_sync_state (
+    peer_id BYTEA PRIMARY KEY REFERENCES peers(peer_id) ON DELETE CASCADE,
+    acked_vector_clock BYTEA NOT NULL,
+    last_synced_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS webhooks (
+    position BIGINT GENERATED ALWAYS AS IDENTITY,
+    webhook_id BYTEA PRIMARY KEY CHECK (length(webhook_id) = 16),
+    url TEXT NOT NULL,
+    filter TEXT NOT NULL,
+    cursor BIGINT NOT NULL DEFAULT 0,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    retry_at BIGINT,
+    last_error TEXT
+);
+
+CREATE TABLE IF NOT EXISTS actor_trust (
+    actor_id BYTEA PRIMARY KEY CHECK (length(actor_id) = 32),
+    state TEXT NOT NULL CHECK (state IN ('trusted', 'pending', 'revoked')),
+    updated_at BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS untrusted_bundles (
+    bundle_id BYTEA PRIMARY KEY CHECK (length(bundle_id) = 16),
+    actor_id BYTEA NOT NULL CHECK (length(actor_id) = 32),
+    hlc BYTEA NOT NULL CHECK (length(hlc) = 12),
+    bundle BYTEA NOT NULL,
+    operations BYTEA NOT NULL,
+    received_at BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT
+);
+CREATE INDEX IF NOT EXISTS idx_untrusted_bundles_actor ON untrusted_bundles (actor_id, hlc);
+
+CREATE TABLE IF NOT EXISTS quarantined_bundles (
+    quarantine_id BIGSERIAL PRIMARY KEY,
+    digest BYTEA NOT NULL UNIQUE CHECK (length(digest) = 32),
+    bundle_id BYTEA CHECK (length(bundle_id) = 16),
+    actor_id BYTEA CHECK (length(actor_id) = 32),
+    raw BYTEA NOT NULL,
+    reason TEXT NOT NULL,
+    received_at BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS materialization_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    seq BIGINT NOT NULL
+);
+-- HLC watermarks predate oplog positions and can't be converted; drop them
+ALTER TABLE materialization_state ADD COLUMN IF NOT EXISTS seq BIGINT;
+DELETE FROM materialization_state WHERE seq IS NULL;
+ALTER TABLE materialization_state DROP COLUMN IF EXISTS watermark;
+ALTER TABLE materialization_state ALTER COLUMN seq SET NOT NULL;
+
+CREATE TABLE IF NOT EXISTS clock_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    last_hlc BYTEA NOT NULL CHECK (length(last_hlc) = 12)
+);
+";
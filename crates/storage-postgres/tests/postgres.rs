@@ -0,0 +1,473 @@
+//! Runs against a live server named by `OPENPROD_TEST_POSTGRES` (a libpq
+//! connection string); each test works in its own throwaway schema. The tests
+//! are ignored by default: run them with `cargo test -p
+//! openprod-storage-postgres -- --ignored`, which fails if the variable is
+//! missing rather than passing without touching a server.
+
+use openprod_core::{
+    digest::HlcRange,
+    field_value::FieldValue,
+    identity::ActorIdentity,
+    ids::{BundleId, TableId},
+    operations::{Bundle, BundleMeta, Operation, RawBundle},
+};
+use openprod_engine::{CdcChange, DriftPolicy, Engine, ImportOptions, WebhookFilter};
+use openprod_storage::{
+    Aggregate, EdgeFilter, EngineStorage, EntityOrder, MaterializationStore, QuarantineStore, SqliteStorage, Storage, StorageError, WebhookStore,
+};
+use openprod_storage_postgres::PostgresStorage;
+use postgres::{Client, NoTls};
+
+const URL_VAR: &str = "OPENPROD_TEST_POSTGRES";
+
+type BundleWithOps = (Bundle, Vec<Operation>);
+
+/// A fresh schema that is dropped when the test ends.
+struct TestSchema {
+    url: String,
+    name: String,
+}
+
+impl TestSchema {
+    fn create() -> Self {
+        let url = std::env::var(URL_VAR).unwrap_or_else(|_| panic!("{URL_VAR} must name a test server"));
+        let name = format!("openprod_test_{}", uuid::Uuid::now_v7().simple());
+        let mut admin = Client::connect(&url, NoTls).expect("connect to test server");
+        admin.batch_execute(&format!("CREATE SCHEMA {name}")).expect("create schema");
+        Self { url, name }
+    }
+
+    fn storage(&self) -> PostgresStorage {
+        let mut client = Client::connect(&self.url, NoTls).expect("connect to test server");
+        client.batch_execute(&format!("SET search_path TO {}", self.name)).expect("set search_path");
+        PostgresStorage::from_client(client).expect("init schema")
+    }
+}
+
+impl Drop for TestSchema {
+    fn drop(&mut self) {
+        if let Ok(mut admin) = Client::connect(&self.url, NoTls) {
+            let _ = admin.batch_execute(&format!("DROP SCHEMA {} CASCADE", self.name));
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn engine_runs_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+
+    let (light, _) = engine.create_entity_with_fields(
+        "Equipment",
+        vec![("name", FieldValue::Text("Spotlight".into())), ("wattage", FieldValue::Integer(750))],
+    )?;
+    let (stage, _) = engine.create_entity_with_fields("Location", vec![("name", FieldValue::Text("Stage".into()))])?;
+    let (edge, _) = engine.create_edge("located_at", light, stage)?;
+    engine.set_field(light, "name", FieldValue::Text("Fresnel".into()))?;
+    engine.clear_field(light, "wattage")?;
+
+    assert_eq!(engine.get_field(light, "name")?, Some(FieldValue::Text("Fresnel".into())));
+    assert_eq!(engine.get_field(light, "wattage")?, None);
+    assert_eq!(engine.storage().get_edges_from(light, &EdgeFilter::all())?[0].edge_id, edge);
+
+    engine.undo()?;
+    engine.undo()?;
+    assert_eq!(engine.get_field(light, "name")?, Some(FieldValue::Text("Spotlight".into())));
+    assert_eq!(engine.get_field(light, "wattage")?, Some(FieldValue::Integer(750)));
+
+    // Overlay writes stay out of canonical state until committed
+    let overlay = engine.create_overlay("draft")?;
+    engine.set_field(light, "name", FieldValue::Text("Par".into()))?;
+    assert_eq!(engine.storage().get_field(light, "name")?, Some(FieldValue::Text("Spotlight".into())));
+    engine.commit_overlay(overlay)?;
+    assert_eq!(engine.get_field(light, "name")?, Some(FieldValue::Text("Par".into())));
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn postgres_materializes_like_sqlite() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut server = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let mut client = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+
+    let (cue, _) = client.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    client.set_field(cue, "label", FieldValue::Text("Q1 go".into()))?;
+    client.set_field(cue, "duration", FieldValue::Integer(5))?;
+
+    let bundles = all_bundles(&client)?;
+    server.ingest_bundles(&bundles)?;
+    // Re-ingesting is a no-op
+    server.ingest_bundles(&bundles)?;
+
+    assert_eq!(server.storage().op_count()?, client.storage().op_count()?);
+    assert_eq!(server.get_vector_clock()?, client.get_vector_clock()?);
+    let paged = server.ops_canonical_paged(2).map(|op| op.map(|op| op.op_id)).collect::<Result<Vec<_>, _>>()?;
+    let canonical: Vec<_> = client.get_ops_canonical()?.iter().map(|op| op.op_id).collect();
+    assert_eq!(paged, canonical);
+    let mut server_fields = server.storage().get_fields(cue)?;
+    let mut client_fields = client.storage().get_fields(cue)?;
+    server_fields.sort_by(|a, b| a.0.cmp(&b.0));
+    client_fields.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(server_fields, client_fields);
+    assert_eq!(
+        server.storage().get_field_metadata(cue, "label")?,
+        client.storage().get_field_metadata(cue, "label")?
+    );
+    Ok(())
+}
+
+/// Stored bundles of `engine` (with ops) in HLC order.
+fn all_bundles<S: EngineStorage>(engine: &Engine<S>) -> Result<Vec<BundleWithOps>, Box<dyn std::error::Error>> {
+    let mut bundles = Vec::new();
+    for (_, bundle_id) in engine.storage().get_bundle_ids_in_range(&HlcRange::full())? {
+        let bundle = engine.storage().get_bundle(bundle_id)?.expect("stored bundle");
+        bundles.push((bundle, engine.get_ops_by_bundle(bundle_id)?));
+    }
+    Ok(bundles)
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn concurrent_edits_conflict_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut server = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let mut alice = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+    let mut bob = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+
+    let (cue, _) = alice.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    bob.ingest_bundles(&all_bundles(&alice)?)?;
+    alice.set_field(cue, "label", FieldValue::Text("Alice".into()))?;
+    bob.set_field(cue, "label", FieldValue::Text("Bob".into()))?;
+
+    server.ingest_bundles(&all_bundles(&alice)?)?;
+    let conflicts = server.ingest_bundles(&all_bundles(&bob)?)?.conflicts;
+    assert_eq!(conflicts.len(), 1);
+    let conflict = server.storage().get_open_conflict_for_field(cue, "label")?.expect("open conflict");
+    assert_eq!(conflict.values.len(), 2);
+
+    server.resolve_conflict(conflict.conflict_id, Some(FieldValue::Text("Both".into())))?;
+    assert!(server.storage().get_open_conflicts_for_entity(cue)?.is_empty());
+    assert_eq!(server.get_field(cue, "label")?, Some(FieldValue::Text("Both".into())));
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn rebuild_from_oplog_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let (cue, _) = engine.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    engine.set_field(cue, "label", FieldValue::Text("Q2".into()))?;
+
+    let replayed = engine.storage_mut().rebuild_from_oplog()?;
+    assert_eq!(replayed, engine.storage().op_count()?);
+    assert_eq!(engine.get_field(cue, "label")?, Some(FieldValue::Text("Q2".into())));
+    assert!(engine.storage().materialization_watermark()?.is_some());
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn quarantine_round_trips_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut author = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+    let (cue, _) = author.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    let (bundle, mut operations) = all_bundles(&author)?.remove(0);
+    let raw = RawBundle::encode(&bundle, &operations)?;
+    operations.pop();
+
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    engine.ingest_raw_bundles(&[RawBundle::encode(&bundle, &operations)?])?;
+    let entry = engine.list_quarantined()?.remove(0);
+    assert_eq!(entry.bundle_id, Some(bundle.bundle_id));
+    assert!(entry.reason.contains("expected"), "{}", entry.reason);
+
+    let id = engine.storage_mut().insert_quarantined_bundle(&raw, None, None, "first")?;
+    assert_eq!(engine.storage_mut().insert_quarantined_bundle(&raw, None, None, "again")?, id);
+    assert_eq!(engine.storage().get_quarantined_bundle(id)?.map(|q| q.reason), Some("again".into()));
+    engine.retry_quarantined(id)?;
+    assert_eq!(engine.get_field(cue, "label")?, Some(FieldValue::Text("Q1".into())));
+    assert!(engine.discard_quarantined(entry.quarantine_id)?);
+    assert!(engine.list_quarantined()?.is_empty());
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn resent_bundles_are_skipped_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut author = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+    let (cue, _) = author.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    let (bundle, operations) = all_bundles(&author)?.remove(0);
+
+    let mut storage = schema.storage();
+    assert!(storage.append_bundle(&bundle, &operations)?.is_empty());
+    let duplicates = storage.append_bundle(&bundle, &operations)?;
+    assert_eq!(duplicates, operations.iter().map(|op| op.op_id).collect::<Vec<_>>());
+    assert_eq!(storage.op_count()?, operations.len() as u64);
+    assert_eq!(storage.get_field(cue, "label")?, Some(FieldValue::Text("Q1".into())));
+    let relayed = Bundle::new_signed(BundleId::new(), author.identity(), bundle.hlc, bundle.bundle_type, &operations, None)?;
+    assert!(matches!(storage.append_bundle(&relayed, &operations), Err(StorageError::ConstraintViolation(_))));
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn changes_since_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let (cue, _) = engine.create_entity(Some("Cue"))?;
+    let (stage, _) = engine.create_entity(Some("Location"))?;
+    let since = engine.changes_watermark()?;
+
+    engine.set_field(cue, "label", FieldValue::Text("Q1".into()))?;
+    let (edge, _) = engine.create_edge("at", cue, stage)?;
+    assert_eq!(engine.entities_changed_since(since)?, vec![cue]);
+    assert_eq!(engine.edges_changed_since(since)?, vec![edge]);
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn unique_edges_merge_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut server = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let mut alice = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+    let mut bob = Engine::new(ActorIdentity::generate(), SqliteStorage::open_in_memory()?)?;
+    server.declare_unique_edge_type("at")?;
+    assert_eq!(server.unique_edge_types()?, vec!["at".to_string()]);
+
+    let (cue, _) = alice.create_entity(Some("Cue"))?;
+    let (stage, _) = alice.create_entity(Some("Location"))?;
+    bob.ingest_bundles(&all_bundles(&alice)?)?;
+    let (first, _) = alice.create_edge("at", cue, stage)?;
+    let (second, _) = bob.create_edge("at", cue, stage)?;
+
+    server.ingest_bundles(&all_bundles(&bob)?)?;
+    server.ingest_bundles(&all_bundles(&alice)?)?;
+    let live = server.get_edges_between(cue, stage, &EdgeFilter::live())?;
+    assert_eq!(live.len(), 1);
+    let winner = [first, second]
+        .into_iter()
+        .filter_map(|id| server.get_edge(id).transpose())
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .min_by_key(|edge| (edge.created_at, edge.edge_id))
+        .unwrap();
+    assert_eq!(live[0].edge_id, winner.edge_id);
+    assert_eq!(server.create_edge("at", cue, stage)?.0, winner.edge_id);
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn trash_listing_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let (older, _) = engine.create_entity(Some("Cue"))?;
+    let (cue, _) = engine.create_entity(Some("Cue"))?;
+    let (stage, _) = engine.create_entity(Some("Location"))?;
+    let (edge, _) = engine.create_edge("at", cue, stage)?;
+    engine.delete_entity(older)?;
+    let since = engine.get_vector_clock()?.entries().values().max().copied();
+    let deleted_in = engine.delete_entity(cue)?;
+    engine.delete_entity(stage)?;
+
+    let ids = |facet_type, since| -> Result<Vec<_>, Box<dyn std::error::Error>> {
+        Ok(engine.list_deleted_entities(facet_type, since)?.into_iter().map(|(e, _)| e.entity_id).collect())
+    };
+    assert_eq!(ids(None, None)?, vec![stage, cue, older]);
+    assert_eq!(ids(Some("Cue"), since)?, vec![cue]);
+    let edges = engine.list_deleted_edges()?;
+    assert_eq!((edges[0].0.edge_id, edges[0].1.deleted_in_bundle), (edge, deleted_in));
+    assert_eq!(edges[0].1.deleted_by, engine.actor_id());
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn preserved_values_round_trip_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let (cue, _) = engine.create_entity_with_fields("Cue", vec![("number", FieldValue::Integer(12))])?;
+    engine.detach_facet(cue, "Cue", true)?;
+    engine.clear_field(cue, "number")?;
+    assert_eq!(
+        engine.get_preserved_facet_values(cue, "Cue")?,
+        Some(vec![("number".to_string(), FieldValue::Integer(12))]),
+    );
+
+    engine.restore_facet(cue, "Cue", true)?;
+    assert_eq!(engine.get_field(cue, "number")?, Some(FieldValue::Integer(12)));
+    assert_eq!(engine.get_preserved_facet_values(cue, "Cue")?, None);
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn facet_fields_scope_detach_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    engine.set_facet_fields("Cue", &["number", "label"])?;
+    engine.set_facet_fields("Cue", &["number"])?;
+    assert_eq!(engine.facet_field_keys("Cue")?, vec!["number".to_string()]);
+    let (cue, _) = engine.create_entity_with_fields(
+        "Cue",
+        vec![("number", FieldValue::Integer(12)), ("notes", FieldValue::Text("fade".into()))],
+    )?;
+    engine.detach_facet(cue, "Cue", true)?;
+    assert_eq!(
+        engine.get_preserved_facet_values(cue, "Cue")?,
+        Some(vec![("number".to_string(), FieldValue::Integer(12))]),
+    );
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn bundles_found_by_tag_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    engine.create_entity(Some("Cue"))?;
+    let tagged = engine.with_bundle_meta(BundleMeta::with_label("Renumber").tag("bulk-rename"), |engine| {
+        engine.create_entity(Some("Cue")).map(|(_, bundle_id)| bundle_id)
+    })?;
+    let found = engine.find_bundles_by_tag("bulk-rename")?;
+    assert_eq!(found.iter().map(|e| e.bundle_id).collect::<Vec<_>>(), vec![tagged]);
+    assert_eq!(found[0].label.as_deref(), Some("Renumber"));
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn session_bundles_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    engine.create_entity(Some("Cue"))?;
+    let session = engine.begin_session("Import")?;
+    let (_, first) = engine.create_entity(Some("Cue"))?;
+    let (_, second) = engine.create_entity(Some("Cue"))?;
+    engine.end_session();
+    engine.create_entity(Some("Cue"))?;
+    let found = engine.get_session_bundles(session)?;
+    assert_eq!(found.iter().map(|e| e.bundle_id).collect::<Vec<_>>(), vec![first, second]);
+    assert_eq!(found[0].label.as_deref(), Some("Import"));
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn field_mappings_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let (source, target) = (TableId::new(), TableId::new());
+    let options = ImportOptions::new("Cue", source, target).map("Cue", "number").map("Page", "page");
+    engine.import_csv("Cue,Page\n1,4\n", &options)?;
+    engine.import_csv("Cue\n2\n", &ImportOptions::new("Cue", source, target).map("Cue", "cue_number"))?;
+    engine.storage_mut().rebuild_from_oplog()?;
+    let mappings = engine.get_field_mappings(source)?;
+    let pairs: Vec<(&str, &str)> = mappings.iter().map(|m| (m.source_field.as_str(), m.target_field.as_str())).collect();
+    assert_eq!(pairs, vec![("Cue", "cue_number"), ("Page", "page")]);
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn cdc_events_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let (cue, _) = engine.create_entity_with_fields("Cue", vec![("label", FieldValue::Text("Q1".into()))])?;
+    let first = engine.cdc_events(0, 1)?;
+    assert_eq!(first.events[0].change, CdcChange::EntityUpserted { entity_id: cue, facets: vec!["Cue".into()] });
+    let rest = engine.cdc_events(first.cursor, 10)?;
+    assert!(rest.cursor > first.cursor);
+    assert!(rest.events.iter().any(|e| matches!(&e.change, CdcChange::FieldChanged { field_key, .. } if field_key == "label")));
+    assert!(engine.cdc_events(rest.cursor, 10)?.events.is_empty());
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn webhooks_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    engine.create_entity(Some("Cue"))?;
+    let first = engine.register_webhook("http://hooks.example/a", &WebhookFilter::new())?;
+    let second = engine.register_webhook("http://hooks.example/b", &WebhookFilter::new().facets(["Cue"]))?;
+    engine.storage_mut().update_webhook_delivery(first, 7, 2, Some(1_000), Some("HTTP 503"))?;
+    let webhooks = engine.list_webhooks()?;
+    assert_eq!(webhooks.iter().map(|w| w.webhook_id).collect::<Vec<_>>(), vec![first, second]);
+    assert_eq!((webhooks[0].cursor, webhooks[0].attempts, webhooks[0].retry_at), (7, 2, Some(1_000)));
+    assert_eq!(webhooks[0].last_error.as_deref(), Some("HTTP 503"));
+    assert_eq!(webhooks[1].cursor, engine.cdc_tail()?);
+    assert_eq!(WebhookFilter::from_json(&webhooks[1].filter)?, WebhookFilter::new().facets(["Cue"]));
+    assert!(engine.unregister_webhook(first)?);
+    assert!(engine.storage().get_webhook(first)?.is_none());
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn aggregates_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    engine.create_entity_with_fields("Cue", vec![("duration", FieldValue::Integer(4))])?;
+    engine.create_entity_with_fields("Cue", vec![("duration", FieldValue::Float(2.5))])?;
+    let (gone, _) = engine.create_entity_with_fields("Cue", vec![("duration", FieldValue::Integer(100))])?;
+    engine.delete_entity(gone)?;
+    assert_eq!(engine.count_entities_by_facet()?, vec![("Cue".into(), 2)]);
+    assert_eq!(engine.aggregate_field("Cue", "duration", Aggregate::Sum)?, Some(FieldValue::Float(6.5)));
+    assert_eq!(engine.aggregate_field("Cue", "duration", Aggregate::Max)?, Some(FieldValue::Integer(4)));
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn entity_pages_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let (a, _) = engine.create_entity_with_fields("Cue", vec![("number", FieldValue::Integer(2))])?;
+    let (b, _) = engine.create_entity_with_fields("Cue", vec![("number", FieldValue::Float(1.5))])?;
+    let (c, _) = engine.create_entity_with_fields("Cue", vec![])?;
+
+    let first = engine.get_entities_page("Cue", &EntityOrder::created_at(), None, 2)?;
+    assert_eq!(first.entities, vec![a, b]);
+    let rest = engine.get_entities_page("Cue", &EntityOrder::created_at(), first.next.as_ref(), 2)?;
+    assert_eq!((rest.entities, rest.next), (vec![c], None));
+
+    let first = engine.get_entities_page("Cue", &EntityOrder::field("number").descending(), None, 2)?;
+    assert_eq!(first.entities, vec![a, b]);
+    let rest = engine.get_entities_page("Cue", &EntityOrder::field("number").descending(), first.next.as_ref(), 2)?;
+    assert_eq!(rest.entities, vec![c]);
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn projections_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let (cue, _) = engine.create_entity_with_fields(
+        "Cue",
+        vec![("label", FieldValue::Text("Q1".into())), ("number", FieldValue::Integer(1))],
+    )?;
+    assert_eq!(
+        engine.get_entities_with_fields("Cue", &["number", "label", "missing"])?,
+        vec![(cue, vec![("number".into(), FieldValue::Integer(1)), ("label".into(), FieldValue::Text("Q1".into()))])]
+    );
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs OPENPROD_TEST_POSTGRES"]
+fn drift_policies_on_postgres() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = TestSchema::create();
+    let mut engine = Engine::new(ActorIdentity::generate(), schema.storage())?;
+    let overlay = engine.create_overlay("script")?;
+    assert_eq!(engine.drift_policy(overlay)?, DriftPolicy::Block);
+    engine.set_drift_policy(overlay, DriftPolicy::AutoRebaseIfUnchanged)?;
+    assert_eq!(engine.drift_policy(overlay)?, DriftPolicy::AutoRebaseIfUnchanged);
+    Ok(())
+}
@@ -0,0 +1,68 @@
+//! Bounded-memory iteration over the oplog, one `get_ops_page` at a time.
+
+use openprod_core::{hlc::Hlc, ids::OpId, operations::Operation};
+
+use crate::{Storage, StorageError};
+
+/// Iterates the oplog in canonical (hlc, op_id) order, holding at most one
+/// page of ops in memory. Ops appended while iterating are picked up if they
+/// sort after the current position.
+///
+/// ```ignore
+/// for op in OpCursor::new(&storage, 1000) {
+///     let op = op?;
+///     // ...
+/// }
+/// ```
+pub struct OpCursor<'a, S: Storage + ?Sized> {
+    storage: &'a S,
+    page_size: usize,
+    after: Option<(Hlc, OpId)>,
+    page: std::vec::IntoIter<Operation>,
+    exhausted: bool,
+}
+
+impl<'a, S: Storage + ?Sized> OpCursor<'a, S> {
+    pub fn new(storage: &'a S, page_size: usize) -> Self {
+        Self {
+            storage,
+            page_size: page_size.max(1),
+            after: None,
+            page: Vec::new().into_iter(),
+            exhausted: false,
+        }
+    }
+
+    /// Start just after `(hlc, op_id)`, e.g. a position saved by an earlier pass.
+    pub fn after(mut self, hlc: Hlc, op_id: OpId) -> Self {
+        self.after = Some((hlc, op_id));
+        self
+    }
+}
+
+impl<S: Storage + ?Sized> Iterator for OpCursor<'_, S> {
+    type Item = Result<Operation, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(op) = self.page.next() {
+            return Some(Ok(op));
+        }
+        if self.exhausted {
+            return None;
+        }
+        match self.storage.get_ops_page(self.after, self.page_size) {
+            Ok(page) => {
+                self.exhausted = page.len() < self.page_size;
+                if let Some(last) = page.last() {
+                    self.after = Some((last.hlc, last.op_id));
+                }
+                self.page = page.into_iter();
+                self.page.next().map(Ok)
+            }
+            Err(e) => {
+                self.exhausted = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -2,9 +2,14 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum StorageError {
+    #[cfg(feature = "sqlite")]
     #[error("sqlite error: {0}")]
     Sqlite(#[from] rusqlite::Error),
 
+    /// Error from a non-SQLite backend, carried as its message.
+    #[error("backend error: {0}")]
+    Backend(String),
+
     #[error("serialization error: {0}")]
     Serialization(String),
 
@@ -17,6 +22,18 @@ pub enum StorageError {
     #[error("entity collision: {entity_id}")]
     EntityCollision { entity_id: String },
 
+    #[error("wrong encryption key")]
+    WrongKey,
+
+    #[error("database corrupt: {0}")]
+    Corrupt(String),
+
+    #[error("database schema version {found} is newer than supported version {supported}")]
+    SchemaTooNew { found: i32, supported: i32 },
+
+    #[error("migration to schema version {version} failed: {reason}")]
+    Migration { version: i32, reason: String },
+
     #[error("core error: {0}")]
     Core(#[from] openprod_core::CoreError),
 }
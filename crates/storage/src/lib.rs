@@ -1,8 +1,14 @@
+pub mod cursor;
 pub mod error;
+pub mod memory;
 pub mod schema;
+#[cfg(feature = "sqlite")]
 pub mod sqlite;
 pub mod traits;
 
+pub use cursor::OpCursor;
 pub use error::StorageError;
-pub use sqlite::SqliteStorage;
+pub use memory::MemoryStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite::{DivergentRow, Divergence, OrphanedRows, SqliteStorage};
 pub use traits::*;
@@ -0,0 +1,2059 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use openprod_core::{
+    digest::HlcRange,
+    field_value::FieldValue,
+    hlc::{physical_now, Hlc},
+    ids::*,
+    operations::{Bundle, Operation, OperationPayload, RawBundle},
+    vector_clock::VectorClock,
+};
+
+use crate::error::StorageError;
+use crate::traits::{
+    aggregate_numbers, Aggregate, ActorRecord, ActorStore, ConflictRecord, ConflictStatus, ConflictTriage, ConflictValue, Deletion, EdgeFilter, EdgeRecord,
+    EntityFields, EntityOrder, EntityPage, EntityPageCursor, EntityRecord, FacetRecord, FieldMappingRecord, LabelStore, MaterializationStore, MaterializeProgress, OverlayOrigin, OverlayStore, PeerRecord,
+    PeerStore, PendingBundleRecord, PendingStore, QuarantineStore, QuarantinedBundle, Storage, Transactional,
+    TrustState, TrustStore, WebhookRecord, WebhookStore, check_bundle_ops, field_sort_key, indexed_meta,
+};
+
+fn serialization_error(e: impl ToString) -> StorageError {
+    StorageError::Serialization(e.to_string())
+}
+
+/// Rows of overlay ops as returned by `OverlayStore::get_overlay_ops`.
+type OverlayOpRow = (i64, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, String, Option<Vec<u8>>, bool, Option<String>);
+
+/// Ops between `materialize_from` progress reports.
+const PROGRESS_INTERVAL: u64 = 1000;
+
+/// Append-only oplog: bundles and their ops in the order they were stored.
+#[derive(Default)]
+struct Log {
+    bundles: Vec<Bundle>,
+    ops: Vec<Operation>,
+    bundle_index: HashMap<BundleId, usize>,
+    op_index: HashMap<OpId, usize>,
+    /// Each bundle's ops are stored contiguously.
+    bundle_ops: HashMap<BundleId, Range<usize>>,
+}
+
+impl Log {
+    fn push(&mut self, bundle: &Bundle, operations: &[Operation]) {
+        let start = self.ops.len();
+        for op in operations {
+            self.op_index.insert(op.op_id, self.ops.len());
+            self.ops.push(op.clone());
+        }
+        self.bundle_index.insert(bundle.bundle_id, self.bundles.len());
+        self.bundle_ops.insert(bundle.bundle_id, start..self.ops.len());
+        self.bundles.push(bundle.clone());
+    }
+
+    fn mark(&self) -> (usize, usize) {
+        (self.bundles.len(), self.ops.len())
+    }
+
+    /// Drop everything stored after `mark`.
+    fn truncate(&mut self, (bundles, ops): (usize, usize)) {
+        for bundle in self.bundles.drain(bundles..) {
+            self.bundle_index.remove(&bundle.bundle_id);
+            self.bundle_ops.remove(&bundle.bundle_id);
+        }
+        for op in self.ops.drain(ops..) {
+            self.op_index.remove(&op.op_id);
+        }
+    }
+
+    fn bundle(&self, bundle_id: BundleId) -> Option<&Bundle> {
+        self.bundle_index.get(&bundle_id).map(|&i| &self.bundles[i])
+    }
+
+    fn op(&self, op_id: OpId) -> Option<&Operation> {
+        self.op_index.get(&op_id).map(|&i| &self.ops[i])
+    }
+
+    /// Ops matching `filter`, in canonical (hlc, op_id) order.
+    fn canonical(&self, filter: impl Fn(&Operation) -> bool) -> Vec<Operation> {
+        let mut ops: Vec<Operation> = self.ops.iter().filter(|op| filter(op)).cloned().collect();
+        ops.sort_by_key(|op| (op.hlc, op.op_id));
+        ops
+    }
+}
+
+#[derive(Clone)]
+struct EntityRow {
+    created_at: Hlc,
+    created_by: ActorId,
+    deleted: Option<Deletion>,
+    placeholder: bool,
+}
+
+/// A field or edge property; `value` is None for a tombstone.
+#[derive(Clone)]
+struct ValueRow {
+    value: Option<Vec<u8>>,
+    source_op: OpId,
+    source_actor: ActorId,
+    updated_at: Hlc,
+}
+
+impl ValueRow {
+    /// Last-writer-wins: a write only lands if its (hlc, op_id) beats the stored one.
+    fn upsert<K: Ord>(rows: &mut BTreeMap<K, ValueRow>, key: K, row: ValueRow) {
+        match rows.get(&key) {
+            Some(current) if (row.updated_at, row.source_op) <= (current.updated_at, current.source_op) => {}
+            _ => {
+                rows.insert(key, row);
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct FacetRow {
+    attached_at: Hlc,
+    attached_by: ActorId,
+    detached_at: Option<Hlc>,
+    /// `(field_key, msgpack value)` pairs snapshotted by a preserving detach.
+    preserved: Option<Vec<(String, Vec<u8>)>>,
+}
+
+/// Target of a confirmed field mapping, under the same LWW guard as `ValueRow`.
+#[derive(Clone)]
+struct MappingRow {
+    target_field: String,
+    source_op: OpId,
+    confirmed_by: ActorId,
+    confirmed_at: Hlc,
+}
+
+#[derive(Clone)]
+struct EdgeRow {
+    edge_type: String,
+    source_id: EntityId,
+    target_id: EntityId,
+    created_at: Hlc,
+    created_by: ActorId,
+    created_in_bundle: BundleId,
+    deleted: Option<Deletion>,
+}
+
+#[derive(Clone)]
+struct ActorRow {
+    first_seen_at: Hlc,
+}
+
+/// State derived from the oplog; `rebuild_from_oplog` recreates it.
+#[derive(Clone, Default)]
+struct Materialized {
+    entities: BTreeMap<EntityId, EntityRow>,
+    fields: BTreeMap<(EntityId, String), ValueRow>,
+    facets: BTreeMap<(EntityId, String), FacetRow>,
+    edges: BTreeMap<EdgeId, EdgeRow>,
+    edge_properties: BTreeMap<(EdgeId, String), ValueRow>,
+    /// Keyed by (source table, target table, source field).
+    field_mappings: BTreeMap<(TableId, TableId, String), MappingRow>,
+    actors: BTreeMap<ActorId, ActorRow>,
+    vector_clock: VectorClock,
+    watermark: Option<u64>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct OverlayRow {
+    display_name: String,
+    source: String,
+    status: String,
+    created_at: Hlc,
+    updated_at: Hlc,
+    drift_policy: String,
+    script_id: Option<String>,
+    run_id: Option<String>,
+    auto_commit: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct OverlayOp {
+    rowid: i64,
+    overlay_id: OverlayId,
+    op_id: OpId,
+    hlc: Hlc,
+    payload: Vec<u8>,
+    entity_id: Option<EntityId>,
+    field_key: Option<String>,
+    op_type: String,
+    canonical_value_at_creation: Option<Vec<u8>>,
+    canonical_drifted: bool,
+}
+
+impl OverlayOp {
+    fn matches_field(&self, entity_id: EntityId, field_key: &str) -> bool {
+        self.entity_id == Some(entity_id) && self.field_key.as_deref() == Some(field_key)
+    }
+
+    fn to_row(&self) -> OverlayOpRow {
+        (
+            self.rowid,
+            self.op_id.as_bytes().to_vec(),
+            self.hlc.to_bytes().to_vec(),
+            self.payload.clone(),
+            self.entity_id.map(|id| id.as_bytes().to_vec()),
+            self.op_type.clone(),
+            self.canonical_value_at_creation.clone(),
+            self.canonical_drifted,
+            self.field_key.clone(),
+        )
+    }
+}
+
+/// Local state that can't be re-derived from the oplog. Serialized as a whole
+/// by `MemoryStorage::local_state`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Local {
+    /// In detection order.
+    conflicts: Vec<ConflictRecord>,
+    conflict_triage: BTreeMap<ConflictId, ConflictTriage>,
+    overlays: BTreeMap<OverlayId, OverlayRow>,
+    overlay_ops: Vec<OverlayOp>,
+    next_overlay_rowid: i64,
+    labels: HashMap<BundleId, String>,
+    pending: Vec<PendingBundleRecord>,
+    trust: BTreeMap<ActorId, TrustState>,
+    untrusted: Vec<PendingBundleRecord>,
+    /// In the order they were quarantined.
+    quarantined: Vec<QuarantinedBundle>,
+    next_quarantine_id: i64,
+    /// In the order they were added.
+    peers: Vec<PeerRecord>,
+    actor_names: BTreeMap<ActorId, String>,
+    unique_edge_types: BTreeSet<String>,
+    facet_fields: BTreeMap<String, BTreeSet<String>>,
+    /// In the order they were registered.
+    webhooks: Vec<WebhookRecord>,
+    last_hlc: Option<Hlc>,
+}
+
+impl Local {
+    fn conflict_mut(&mut self, conflict_id: ConflictId) -> Result<&mut ConflictRecord, StorageError> {
+        self.conflicts
+            .iter_mut()
+            .find(|c| c.conflict_id == conflict_id)
+            .ok_or_else(|| StorageError::NotFound(format!("conflict {conflict_id}")))
+    }
+}
+
+/// Snapshot taken by `begin_transaction` and restored on rollback. The oplog
+/// is append-only, so a mark is enough to undo it.
+struct Savepoint {
+    log_mark: (usize, usize),
+    state: Materialized,
+    local: Local,
+}
+
+/// Storage held entirely in memory, applying the same materialization rules
+/// as `SqliteStorage`. Needs no native code, so it also runs on
+/// wasm32-unknown-unknown; callers that want durability save `bundles_from`
+/// and `local_state` elsewhere and reload them with `restore`.
+#[derive(Default)]
+pub struct MemoryStorage {
+    log: RefCell<Log>,
+    state: RefCell<Materialized>,
+    local: RefCell<Local>,
+    savepoint: RefCell<Option<Savepoint>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuild storage from bundles in the order `bundles_from` returned them,
+    /// plus a `local_state` blob (if one was saved).
+    pub fn restore(
+        bundles: impl IntoIterator<Item = (Bundle, Vec<Operation>)>,
+        local_state: Option<&[u8]>,
+    ) -> Result<Self, StorageError> {
+        let mut storage = Self::new();
+        for (bundle, operations) in bundles {
+            storage.append_bundle(&bundle, &operations)?;
+        }
+        if let Some(bytes) = local_state {
+            *storage.local.get_mut() = rmp_serde::from_slice(bytes).map_err(serialization_error)?;
+        }
+        Ok(storage)
+    }
+
+    /// Number of bundles stored so far; a cursor for `bundles_from`.
+    pub fn bundle_count(&self) -> usize {
+        self.log.borrow().bundles.len()
+    }
+
+    /// Stored bundles (with ops) in the order they were appended, starting at
+    /// the `start`th. Appending them to a fresh storage in this order
+    /// reproduces the oplog.
+    pub fn bundles_from(&self, start: usize) -> Vec<(Bundle, Vec<Operation>)> {
+        let log = self.log.borrow();
+        log.bundles
+            .iter()
+            .skip(start)
+            .map(|bundle| (bundle.clone(), log.ops[log.bundle_ops[&bundle.bundle_id].clone()].to_vec()))
+            .collect()
+    }
+
+    /// Conflicts, overlays, labels, held bundles, trust, peers and actor
+    /// names, msgpack-encoded for `restore`.
+    pub fn local_state(&self) -> Result<Vec<u8>, StorageError> {
+        rmp_serde::to_vec(&*self.local.borrow()).map_err(serialization_error)
+    }
+
+    /// Reject a bundle whose creates would collide with existing rows, before
+    /// anything is written. This is what keeps `append_bundle` all-or-nothing.
+    fn check_collisions(&self, operations: &[Operation]) -> Result<(), StorageError> {
+        let log = self.log.borrow();
+        let state = self.state.borrow();
+        let mut entities = HashSet::new();
+        let mut edges = HashSet::new();
+        let mut op_ids = HashSet::new();
+        for op in operations {
+            if log.op_index.contains_key(&op.op_id) || !op_ids.insert(op.op_id) {
+                return Err(StorageError::ConstraintViolation(format!("duplicate op {}", op.op_id)));
+            }
+            match &op.payload {
+                OperationPayload::CreateEntity { entity_id, .. }
+                    if state.entities.get(entity_id).is_some_and(|row| !row.placeholder) || !entities.insert(*entity_id) =>
+                {
+                    return Err(StorageError::EntityCollision { entity_id: entity_id.to_string() });
+                }
+                OperationPayload::CreateEdge { edge_id, .. } if state.edges.contains_key(edge_id) || !edges.insert(*edge_id) => {
+                    return Err(StorageError::ConstraintViolation(format!("duplicate edge {edge_id}")));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Record an op's actor in the directory and advance the vector clock.
+    fn track_actor(state: &mut Materialized, op: &Operation) {
+        state.actors.entry(op.actor_id).or_insert(ActorRow { first_seen_at: op.hlc });
+        state.vector_clock.update(op.actor_id, op.hlc);
+    }
+
+    /// Replay ops at or after `from` in canonical order, then advance the
+    /// watermark to the end of the oplog.
+    fn replay_ops_from(
+        &self,
+        from: Hlc,
+        progress: &mut dyn FnMut(MaterializeProgress),
+    ) -> Result<u64, StorageError> {
+        let log = self.log.borrow();
+        let mut state = self.state.borrow_mut();
+        let local = self.local.borrow();
+        let ops = log.canonical(|op| op.hlc >= from);
+        let total = ops.len() as u64;
+        for (i, op) in ops.iter().enumerate() {
+            materialize_op(&mut state, &local, op)?;
+            Self::track_actor(&mut state, op);
+
+            let replayed = i as u64 + 1;
+            if replayed.is_multiple_of(PROGRESS_INTERVAL) || replayed == total {
+                progress(MaterializeProgress { replayed, total });
+            }
+        }
+        state.watermark = (!log.ops.is_empty()).then_some(log.ops.len() as u64);
+        Ok(total)
+    }
+
+    fn read_held(records: &[PendingBundleRecord], filter: impl Fn(&Bundle) -> bool) -> Vec<PendingBundleRecord> {
+        let mut held: Vec<PendingBundleRecord> = records.iter().filter(|r| filter(&r.bundle)).cloned().collect();
+        held.sort_by_key(|r| (r.bundle.hlc, r.bundle.bundle_id));
+        held
+    }
+
+    fn edges(&self, select: impl Fn(&EdgeRow) -> bool, filter: &EdgeFilter) -> Vec<EdgeRecord> {
+        self.state
+            .borrow()
+            .edges
+            .iter()
+            .filter(|(_, row)| select(row))
+            .map(|(edge_id, row)| edge_record(*edge_id, row))
+            .filter(|edge| filter.matches(edge))
+            .collect()
+    }
+
+    fn overlay_ops(&self, filter: impl Fn(&OverlayOp) -> bool) -> Vec<OverlayOpRow> {
+        self.local.borrow().overlay_ops.iter().filter(|op| filter(op)).map(OverlayOp::to_row).collect()
+    }
+}
+
+/// Apply one op to the materialized tables (same rules as the SQLite backend).
+/// `local` supplies the declared unique edge types.
+fn materialize_op(
+    state: &mut Materialized,
+    local: &Local,
+    op: &Operation,
+) -> Result<(), StorageError> {
+    let field_row = |value: Option<&FieldValue>| -> Result<ValueRow, StorageError> {
+        Ok(ValueRow {
+            value: value.map(|v| v.to_msgpack().map_err(serialization_error)).transpose()?,
+            source_op: op.op_id,
+            source_actor: op.actor_id,
+            updated_at: op.hlc,
+        })
+    };
+    let deletion = Deletion { deleted_at: op.hlc, deleted_by: op.actor_id, deleted_in_bundle: op.bundle_id };
+
+    match &op.payload {
+        OperationPayload::CreateEntity { entity_id, initial_table } => {
+            // A placeholder takes on the creation; any other existing row collides
+            let deleted = match state.entities.get(entity_id) {
+                Some(row) if row.placeholder => row.deleted,
+                Some(_) => return Err(StorageError::EntityCollision { entity_id: entity_id.to_string() }),
+                None => None,
+            };
+            state.entities.insert(
+                *entity_id,
+                EntityRow { created_at: op.hlc, created_by: op.actor_id, deleted, placeholder: false },
+            );
+            if let Some(facet_type) = initial_table {
+                state.facets.insert(
+                    (*entity_id, facet_type.clone()),
+                    FacetRow { attached_at: op.hlc, attached_by: op.actor_id, detached_at: None, preserved: None },
+                );
+            }
+        }
+
+        OperationPayload::DeleteEntity { entity_id, cascade_edges } => {
+            if let Some(entity) = state.entities.get_mut(entity_id) {
+                entity.deleted = Some(deletion);
+            }
+            for edge_id in cascade_edges {
+                if let Some(edge) = state.edges.get_mut(edge_id) {
+                    edge.deleted = Some(deletion);
+                }
+            }
+        }
+
+        OperationPayload::AttachFacet { entity_id, facet_type } => {
+            insert_placeholder(state, *entity_id, op);
+            state.facets.insert(
+                (*entity_id, facet_type.clone()),
+                FacetRow { attached_at: op.hlc, attached_by: op.actor_id, detached_at: None, preserved: None },
+            );
+        }
+
+        OperationPayload::DetachFacet { entity_id, facet_type, preserve_values } => {
+            let preserved: Vec<(String, Vec<u8>)> = state
+                .fields
+                .range((*entity_id, String::new())..)
+                .take_while(|((id, _), _)| id == entity_id)
+                .filter_map(|((_, key), row)| Some((key.clone(), row.value.clone()?)))
+                .collect();
+            if let Some(facet) = state.facets.get_mut(&(*entity_id, facet_type.clone())) {
+                facet.detached_at = Some(op.hlc);
+                // Without preservation any earlier snapshot is left as it was
+                if *preserve_values {
+                    facet.preserved = Some(preserved);
+                }
+            }
+        }
+
+        OperationPayload::SetField { entity_id, field_key, value } => {
+            insert_placeholder(state, *entity_id, op);
+            ValueRow::upsert(&mut state.fields, (*entity_id, field_key.clone()), field_row(Some(value))?);
+        }
+
+        OperationPayload::ClearField { entity_id, field_key } => {
+            // Tombstone under the same LWW guard
+            insert_placeholder(state, *entity_id, op);
+            ValueRow::upsert(&mut state.fields, (*entity_id, field_key.clone()), field_row(None)?);
+        }
+
+        OperationPayload::ResolveConflict { entity_id, field_key, chosen_value, .. } => {
+            insert_placeholder(state, *entity_id, op);
+            ValueRow::upsert(&mut state.fields, (*entity_id, field_key.clone()), field_row(chosen_value.as_ref())?);
+        }
+
+        OperationPayload::CreateEdge { edge_id, edge_type, source_id, target_id, properties } => {
+            insert_placeholder(state, *source_id, op);
+            insert_placeholder(state, *target_id, op);
+            state.edges.insert(
+                *edge_id,
+                EdgeRow {
+                    edge_type: edge_type.clone(),
+                    source_id: *source_id,
+                    target_id: *target_id,
+                    created_at: op.hlc,
+                    created_by: op.actor_id,
+                    created_in_bundle: op.bundle_id,
+                    deleted: None,
+                },
+            );
+            for (key, value) in properties {
+                state.edge_properties.insert((*edge_id, key.clone()), field_row(Some(value))?);
+            }
+            merge_duplicate_edges(state, &local.unique_edge_types, *edge_id);
+        }
+
+        // Skipped for an edge whose creation this store never got (left out
+        // of a scoped sync)
+        OperationPayload::SetEdgeProperty { edge_id, property_key, value } if state.edges.contains_key(edge_id) => {
+            ValueRow::upsert(&mut state.edge_properties, (*edge_id, property_key.clone()), field_row(Some(value))?);
+        }
+
+        OperationPayload::ClearEdgeProperty { edge_id, property_key } if state.edges.contains_key(edge_id) => {
+            ValueRow::upsert(&mut state.edge_properties, (*edge_id, property_key.clone()), field_row(None)?);
+        }
+
+        OperationPayload::DeleteEdge { edge_id } => {
+            if let Some(edge) = state.edges.get_mut(edge_id) {
+                edge.deleted = Some(deletion);
+            }
+        }
+
+        OperationPayload::RestoreEntity { entity_id } => {
+            if let Some(entity) = state.entities.get_mut(entity_id) {
+                entity.deleted = None;
+            }
+        }
+
+        OperationPayload::RestoreEdge { edge_id } => {
+            if let Some(edge) = state.edges.get_mut(edge_id) {
+                edge.deleted = None;
+            }
+            merge_duplicate_edges(state, &local.unique_edge_types, *edge_id);
+        }
+
+        OperationPayload::RestoreFacet { entity_id, facet_type } => {
+            if let Some(facet) = state.facets.get_mut(&(*entity_id, facet_type.clone())) {
+                facet.detached_at = None;
+                facet.preserved = None;
+            }
+        }
+
+        OperationPayload::ConfirmFieldMapping { source_table, target_table, source_field, target_field } => {
+            let key = (*source_table, *target_table, source_field.clone());
+            let superseded = state
+                .field_mappings
+                .get(&key)
+                .is_some_and(|current| (op.hlc, op.op_id) <= (current.confirmed_at, current.source_op));
+            if !superseded {
+                let row = MappingRow {
+                    target_field: target_field.clone(),
+                    source_op: op.op_id,
+                    confirmed_by: op.actor_id,
+                    confirmed_at: op.hlc,
+                };
+                state.field_mappings.insert(key, row);
+            }
+        }
+
+        OperationPayload::SetEdgeProperty { .. } | OperationPayload::ClearEdgeProperty { .. } => {}
+
+        // Operations not yet materialized -- stored in oplog only
+        OperationPayload::ApplyCrdt { .. }
+        | OperationPayload::ClearAndAdd { .. }
+        | OperationPayload::CreateOrderedEdge { .. }
+        | OperationPayload::MoveOrderedEdge { .. }
+        | OperationPayload::LinkTables { .. }
+        | OperationPayload::UnlinkTables { .. }
+        | OperationPayload::AddToTable { .. }
+        | OperationPayload::RemoveFromTable { .. }
+        | OperationPayload::MergeEntities { .. }
+        | OperationPayload::SplitEntity { .. }
+        | OperationPayload::CreateRule { .. } => {}
+    }
+    Ok(())
+}
+
+/// Give an entity an op refers to a placeholder row if this store hasn't
+/// seen its creation, as the SQLite backend must for its foreign keys.
+fn insert_placeholder(state: &mut Materialized, entity_id: EntityId, op: &Operation) {
+    state.entities.entry(entity_id).or_insert(EntityRow {
+        created_at: op.hlc,
+        created_by: op.actor_id,
+        deleted: None,
+        placeholder: true,
+    });
+}
+
+/// If `edge_id` has a unique edge type, tombstone every live edge of that type
+/// between the same pair except the earliest created, each as of its own creation.
+fn merge_duplicate_edges(state: &mut Materialized, unique_edge_types: &BTreeSet<String>, edge_id: EdgeId) {
+    let Some(edge) = state.edges.get(&edge_id) else { return };
+    if !unique_edge_types.contains(&edge.edge_type) {
+        return;
+    }
+    let pair = (edge.source_id, edge.target_id, edge.edge_type.clone());
+    let mut duplicates: Vec<(Hlc, EdgeId)> = state
+        .edges
+        .iter()
+        .filter(|(_, row)| (row.source_id, row.target_id, &row.edge_type) == (pair.0, pair.1, &pair.2))
+        .map(|(id, row)| (row.created_at, *id))
+        .collect();
+    duplicates.sort();
+    for (_, id) in duplicates.into_iter().skip(1) {
+        if let Some(row) = state.edges.get_mut(&id)
+            && row.deleted.is_none()
+        {
+            row.deleted = Some(Deletion {
+                deleted_at: row.created_at,
+                deleted_by: row.created_by,
+                deleted_in_bundle: row.created_in_bundle,
+            });
+        }
+    }
+}
+
+fn edge_record(edge_id: EdgeId, row: &EdgeRow) -> EdgeRecord {
+    EdgeRecord {
+        edge_id,
+        edge_type: row.edge_type.clone(),
+        source_id: row.source_id,
+        target_id: row.target_id,
+        created_at: row.created_at,
+        created_by: row.created_by,
+        created_in_bundle: row.created_in_bundle,
+        deleted: row.deleted.is_some(),
+    }
+}
+
+fn decode_values<K>(rows: impl Iterator<Item = (K, Vec<u8>)>) -> Result<Vec<(K, FieldValue)>, StorageError> {
+    rows.map(|(key, bytes)| Ok((key, FieldValue::from_msgpack(&bytes).map_err(serialization_error)?)))
+        .collect()
+}
+
+fn now_ms() -> Result<i64, StorageError> {
+    Ok(physical_now()? as i64)
+}
+
+impl Transactional for MemoryStorage {
+    fn begin_transaction(&self) -> Result<(), StorageError> {
+        let mut savepoint = self.savepoint.borrow_mut();
+        if savepoint.is_some() {
+            return Err(StorageError::Backend("a transaction is already open".into()));
+        }
+        *savepoint = Some(Savepoint {
+            log_mark: self.log.borrow().mark(),
+            state: self.state.borrow().clone(),
+            local: self.local.borrow().clone(),
+        });
+        Ok(())
+    }
+
+    fn commit_transaction(&self) -> Result<(), StorageError> {
+        self.savepoint
+            .borrow_mut()
+            .take()
+            .map(|_| ())
+            .ok_or_else(|| StorageError::Backend("no transaction is open".into()))
+    }
+
+    fn rollback_transaction(&self) -> Result<(), StorageError> {
+        let savepoint = self
+            .savepoint
+            .borrow_mut()
+            .take()
+            .ok_or_else(|| StorageError::Backend("no transaction is open".into()))?;
+        self.log.borrow_mut().truncate(savepoint.log_mark);
+        *self.state.borrow_mut() = savepoint.state;
+        *self.local.borrow_mut() = savepoint.local;
+        Ok(())
+    }
+}
+
+impl MaterializationStore for MemoryStorage {
+    fn rebuild_from_oplog(&mut self) -> Result<u64, StorageError> {
+        *self.state.get_mut() = Materialized::default();
+        let local = self.local.get_mut();
+        local.conflicts.clear();
+        local.conflict_triage.clear();
+        local.actor_names.clear();
+        self.replay_ops_from(Hlc::new(0, 0), &mut |_| {})
+    }
+
+    fn materialize_from(
+        &mut self,
+        from: Hlc,
+        progress: &mut dyn FnMut(MaterializeProgress),
+    ) -> Result<u64, StorageError> {
+        let state = self.state.get_mut();
+        // Rows created at or after `from` go entirely
+        let new_entities: HashSet<EntityId> =
+            state.entities.iter().filter(|(_, e)| e.created_at >= from).map(|(id, _)| *id).collect();
+        let dropped_edges: HashSet<EdgeId> = state
+            .edges
+            .iter()
+            .filter(|(_, e)| {
+                e.created_at >= from || new_entities.contains(&e.source_id) || new_entities.contains(&e.target_id)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        state
+            .edge_properties
+            .retain(|(edge_id, _), row| row.updated_at < from && !dropped_edges.contains(edge_id));
+        state.edges.retain(|edge_id, _| !dropped_edges.contains(edge_id));
+        state
+            .fields
+            .retain(|(entity_id, _), row| row.updated_at < from && !new_entities.contains(entity_id));
+        state
+            .facets
+            .retain(|(entity_id, _), row| row.attached_at < from && !new_entities.contains(entity_id));
+        state.entities.retain(|entity_id, _| !new_entities.contains(entity_id));
+        state.field_mappings.retain(|_, row| row.confirmed_at < from);
+
+        // Later deletions and detaches on older rows are undone
+        for entity in state.entities.values_mut().filter(|e| e.deleted.is_some_and(|d| d.deleted_at >= from)) {
+            entity.deleted = None;
+        }
+        for edge in state.edges.values_mut().filter(|e| e.deleted.is_some_and(|d| d.deleted_at >= from)) {
+            edge.deleted = None;
+        }
+        for facet in state.facets.values_mut().filter(|f| f.detached_at.is_some_and(|at| at >= from)) {
+            facet.detached_at = None;
+            facet.preserved = None;
+        }
+        self.replay_ops_from(from, progress)
+    }
+
+    fn materialization_watermark(&self) -> Result<Option<u64>, StorageError> {
+        Ok(self.state.borrow().watermark)
+    }
+
+    fn checkpoint_materialization(&mut self) -> Result<(), StorageError> {
+        let stored = self.log.get_mut().ops.len() as u64;
+        if stored > 0 {
+            self.state.get_mut().watermark = Some(stored);
+        }
+        Ok(())
+    }
+
+    fn add_unique_edge_type(&mut self, edge_type: &str) -> Result<(), StorageError> {
+        let unique_edge_types = &mut self.local.get_mut().unique_edge_types;
+        if !unique_edge_types.insert(edge_type.to_string()) {
+            return Ok(());
+        }
+        let state = self.state.get_mut();
+        let edges: Vec<EdgeId> =
+            state.edges.iter().filter(|(_, row)| row.edge_type == edge_type).map(|(edge_id, _)| *edge_id).collect();
+        for edge_id in edges {
+            merge_duplicate_edges(state, unique_edge_types, edge_id);
+        }
+        Ok(())
+    }
+
+    fn list_unique_edge_types(&self) -> Result<Vec<String>, StorageError> {
+        Ok(self.local.borrow().unique_edge_types.iter().cloned().collect())
+    }
+
+    fn set_facet_fields(&mut self, facet_type: &str, field_keys: &[&str]) -> Result<(), StorageError> {
+        let facet_fields = &mut self.local.get_mut().facet_fields;
+        if field_keys.is_empty() {
+            facet_fields.remove(facet_type);
+        } else {
+            facet_fields.insert(facet_type.to_string(), field_keys.iter().map(|k| k.to_string()).collect());
+        }
+        Ok(())
+    }
+
+    fn get_facet_fields(&self, facet_type: &str) -> Result<Vec<String>, StorageError> {
+        Ok(self.local.borrow().facet_fields.get(facet_type).into_iter().flatten().cloned().collect())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn append_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<Vec<OpId>, StorageError> {
+        check_bundle_ops(bundle, operations)?;
+        // Idempotent: skip if bundle already ingested
+        if self.log.get_mut().bundle_index.contains_key(&bundle.bundle_id) {
+            return Ok(operations.iter().map(|op| op.op_id).collect());
+        }
+        self.check_collisions(operations)?;
+
+        let state = self.state.get_mut();
+        let local = self.local.get_mut();
+        for op in operations {
+            materialize_op(state, local, op)?;
+            Self::track_actor(state, op);
+        }
+        self.log.get_mut().push(bundle, operations);
+        Ok(Vec::new())
+    }
+
+    fn get_ops_canonical(&self) -> Result<Vec<Operation>, StorageError> {
+        Ok(self.log.borrow().canonical(|_| true))
+    }
+
+    fn get_ops_page(
+        &self,
+        after: Option<(Hlc, OpId)>,
+        limit: usize,
+    ) -> Result<Vec<Operation>, StorageError> {
+        let log = self.log.borrow();
+        let mut ops: Vec<&Operation> =
+            log.ops.iter().filter(|op| after.is_none_or(|after| (op.hlc, op.op_id) > after)).collect();
+        ops.sort_by_key(|op| (op.hlc, op.op_id));
+        Ok(ops.into_iter().take(limit).cloned().collect())
+    }
+
+    fn get_ops_after_seq(&self, after: u64, limit: usize) -> Result<Vec<(u64, Operation)>, StorageError> {
+        let log = self.log.borrow();
+        let start = usize::try_from(after).unwrap_or(usize::MAX).min(log.ops.len());
+        Ok(log.ops[start..].iter().take(limit).zip(after + 1..).map(|(op, seq)| (seq, op.clone())).collect())
+    }
+
+    fn get_ops_by_bundle(&self, bundle_id: BundleId) -> Result<Vec<Operation>, StorageError> {
+        let log = self.log.borrow();
+        Ok(log.bundle_ops.get(&bundle_id).map(|range| log.ops[range.clone()].to_vec()).unwrap_or_default())
+    }
+
+    fn get_ops_by_actor_after(
+        &self,
+        actor_id: ActorId,
+        after: Hlc,
+    ) -> Result<Vec<Operation>, StorageError> {
+        Ok(self.log.borrow().canonical(|op| op.actor_id == actor_id && op.hlc > after))
+    }
+
+    fn get_ops_by_entity(&self, entity_id: EntityId) -> Result<Vec<Operation>, StorageError> {
+        Ok(self.log.borrow().canonical(|op| op.payload.entity_id() == Some(entity_id)))
+    }
+
+    fn get_edge_property_ops(&self, edge_id: EdgeId) -> Result<Vec<Operation>, StorageError> {
+        Ok(self.log.borrow().canonical(|op| op.payload.property_edge_id() == Some(edge_id)))
+    }
+
+    fn get_ops_by_type(&self, op_types: &[&str]) -> Result<Vec<Operation>, StorageError> {
+        Ok(self.log.borrow().canonical(|op| op_types.contains(&op.payload.op_type_name())))
+    }
+
+    fn get_entities_changed_since(&self, after: u64) -> Result<Vec<EntityId>, StorageError> {
+        let entities: BTreeSet<EntityId> = self
+            .log
+            .borrow()
+            .ops
+            .iter()
+            .skip(usize::try_from(after).unwrap_or(usize::MAX))
+            .filter(|op| {
+                !matches!(op.payload, OperationPayload::CreateEdge { .. } | OperationPayload::CreateOrderedEdge { .. })
+            })
+            .filter_map(|op| op.payload.entity_id())
+            .collect();
+        Ok(entities.into_iter().collect())
+    }
+
+    fn get_edges_changed_since(&self, after: u64) -> Result<Vec<EdgeId>, StorageError> {
+        let edges: BTreeSet<EdgeId> = self
+            .log
+            .borrow()
+            .ops
+            .iter()
+            .skip(usize::try_from(after).unwrap_or(usize::MAX))
+            .flat_map(|op| op.payload.edge_ids())
+            .collect();
+        Ok(edges.into_iter().collect())
+    }
+
+    fn op_count(&self) -> Result<u64, StorageError> {
+        Ok(self.log.borrow().ops.len() as u64)
+    }
+
+    fn analyze(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn get_entity(&self, entity_id: EntityId) -> Result<Option<EntityRecord>, StorageError> {
+        Ok(self.state.borrow().entities.get(&entity_id).map(|row| EntityRecord {
+            entity_id,
+            created_at: row.created_at,
+            created_by: row.created_by,
+            deleted: row.deleted.is_some(),
+            placeholder: row.placeholder,
+        }))
+    }
+
+    fn get_fields(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<Vec<(String, FieldValue)>, StorageError> {
+        let state = self.state.borrow();
+        decode_values(
+            state
+                .fields
+                .range((entity_id, String::new())..)
+                .take_while(|((id, _), _)| *id == entity_id)
+                .filter_map(|((_, key), row)| Some((key.clone(), row.value.clone()?))),
+        )
+    }
+
+    fn get_field(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<FieldValue>, StorageError> {
+        self.state
+            .borrow()
+            .fields
+            .get(&(entity_id, field_key.to_string()))
+            .and_then(|row| row.value.as_deref())
+            .map(|bytes| FieldValue::from_msgpack(bytes).map_err(serialization_error))
+            .transpose()
+    }
+
+    fn get_facets(&self, entity_id: EntityId) -> Result<Vec<FacetRecord>, StorageError> {
+        Ok(self
+            .state
+            .borrow()
+            .facets
+            .range((entity_id, String::new())..)
+            .take_while(|((id, _), _)| *id == entity_id)
+            .map(|((_, facet_type), row)| FacetRecord {
+                entity_id,
+                facet_type: facet_type.clone(),
+                attached_at: row.attached_at,
+                attached_by: row.attached_by,
+                detached: row.detached_at.is_some(),
+            })
+            .collect())
+    }
+
+    fn get_preserved_values(
+        &self,
+        entity_id: EntityId,
+        facet_type: &str,
+    ) -> Result<Option<Vec<(String, FieldValue)>>, StorageError> {
+        let state = self.state.borrow();
+        let Some(preserved) = state
+            .facets
+            .get(&(entity_id, facet_type.to_string()))
+            .filter(|f| f.detached_at.is_some())
+            .and_then(|f| f.preserved.as_ref())
+        else {
+            return Ok(None);
+        };
+        let mut values = preserved
+            .iter()
+            .map(|(key, bytes)| Ok((key.clone(), FieldValue::from_msgpack(bytes).map_err(serialization_error)?)))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Some(values))
+    }
+
+    fn get_entities_by_facet(&self, facet_type: &str) -> Result<Vec<EntityId>, StorageError> {
+        Ok(self
+            .state
+            .borrow()
+            .facets
+            .iter()
+            .filter(|((_, facet), row)| facet == facet_type && row.detached_at.is_none())
+            .map(|((entity_id, _), _)| *entity_id)
+            .collect())
+    }
+
+    fn get_entities_page(
+        &self,
+        facet_type: &str,
+        order: &EntityOrder,
+        after: Option<&EntityPageCursor>,
+        limit: usize,
+    ) -> Result<EntityPage, StorageError> {
+        let state = self.state.borrow();
+        let mut rows = Vec::new();
+        for ((entity_id, facet), row) in &state.facets {
+            let Some(entity) = state.entities.get(entity_id) else { continue };
+            if facet != facet_type || row.detached_at.is_some() || entity.deleted.is_some() {
+                continue;
+            }
+            let sort_key = match &order.field_key {
+                Some(field_key) => {
+                    let value = match state.fields.get(&(*entity_id, field_key.clone())).and_then(|f| f.value.as_deref()) {
+                        Some(bytes) => Some(FieldValue::from_msgpack(bytes).map_err(serialization_error)?),
+                        None => None,
+                    };
+                    field_sort_key(value.as_ref())
+                }
+                None => entity.created_at.to_bytes().to_vec(),
+            };
+            rows.push((sort_key, *entity_id));
+        }
+        if let Some(after) = after {
+            let after = (after.sort_key.clone(), after.entity_id);
+            rows.retain(|row| if order.descending { *row < after } else { *row > after });
+        }
+        rows.sort();
+        if order.descending {
+            rows.reverse();
+        }
+        rows.truncate(limit.saturating_add(1));
+        Ok(EntityPage::from_rows(rows, limit))
+    }
+
+    fn get_entities_with_fields(
+        &self,
+        facet_type: &str,
+        field_keys: &[&str],
+    ) -> Result<Vec<EntityFields>, StorageError> {
+        let state = self.state.borrow();
+        let mut result = Vec::new();
+        for ((entity_id, facet), row) in &state.facets {
+            if facet != facet_type
+                || row.detached_at.is_some()
+                || state.entities.get(entity_id).is_none_or(|e| e.deleted.is_some())
+            {
+                continue;
+            }
+            let mut fields = Vec::new();
+            for field_key in field_keys {
+                if fields.iter().any(|(key, _)| key == field_key) {
+                    continue;
+                }
+                if let Some(bytes) = state.fields.get(&(*entity_id, field_key.to_string())).and_then(|f| f.value.as_deref()) {
+                    fields.push((field_key.to_string(), FieldValue::from_msgpack(bytes).map_err(serialization_error)?));
+                }
+            }
+            result.push((*entity_id, fields));
+        }
+        result.sort_by_key(|(entity_id, _)| *entity_id);
+        Ok(result)
+    }
+
+    fn count_entities_by_facet(&self) -> Result<Vec<(String, u64)>, StorageError> {
+        let state = self.state.borrow();
+        let mut counts: BTreeMap<String, u64> = BTreeMap::new();
+        for ((entity_id, facet_type), row) in &state.facets {
+            if row.detached_at.is_none() && state.entities.get(entity_id).is_some_and(|e| e.deleted.is_none()) {
+                *counts.entry(facet_type.clone()).or_default() += 1;
+            }
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    fn aggregate_field(
+        &self,
+        facet_type: &str,
+        field_key: &str,
+        aggregate: Aggregate,
+    ) -> Result<Option<FieldValue>, StorageError> {
+        let state = self.state.borrow();
+        let mut values = Vec::new();
+        for ((entity_id, facet), row) in &state.facets {
+            if facet != facet_type
+                || row.detached_at.is_some()
+                || state.entities.get(entity_id).is_none_or(|e| e.deleted.is_some())
+            {
+                continue;
+            }
+            if let Some(bytes) = state.fields.get(&(*entity_id, field_key.to_string())).and_then(|f| f.value.as_deref()) {
+                values.push(FieldValue::from_msgpack(bytes).map_err(serialization_error)?);
+            }
+        }
+        aggregate_numbers(values, aggregate)
+    }
+
+    fn get_field_mappings(&self, source_table: TableId) -> Result<Vec<FieldMappingRecord>, StorageError> {
+        Ok(self
+            .state
+            .borrow()
+            .field_mappings
+            .iter()
+            .filter(|((source, _, _), _)| *source == source_table)
+            .map(|((_, target_table, source_field), row)| FieldMappingRecord {
+                source_table,
+                target_table: *target_table,
+                source_field: source_field.clone(),
+                target_field: row.target_field.clone(),
+                confirmed_by: row.confirmed_by,
+                confirmed_at: row.confirmed_at,
+            })
+            .collect())
+    }
+
+    fn get_edges_from(&self, entity_id: EntityId, filter: &EdgeFilter) -> Result<Vec<EdgeRecord>, StorageError> {
+        Ok(self.edges(|row| row.source_id == entity_id, filter))
+    }
+
+    fn get_edges_to(&self, entity_id: EntityId, filter: &EdgeFilter) -> Result<Vec<EdgeRecord>, StorageError> {
+        Ok(self.edges(|row| row.target_id == entity_id, filter))
+    }
+
+    fn get_edges_between(
+        &self,
+        source_id: EntityId,
+        target_id: EntityId,
+        filter: &EdgeFilter,
+    ) -> Result<Vec<EdgeRecord>, StorageError> {
+        Ok(self.edges(|row| row.source_id == source_id && row.target_id == target_id, filter))
+    }
+
+    fn get_edges_by_type(&self, edge_type: &str) -> Result<Vec<EdgeRecord>, StorageError> {
+        Ok(self.edges(|row| row.edge_type == edge_type, &EdgeFilter::live()))
+    }
+
+    fn get_deleted_entities(
+        &self,
+        facet_type: Option<&str>,
+        since: Option<Hlc>,
+    ) -> Result<Vec<(EntityRecord, Deletion)>, StorageError> {
+        let state = self.state.borrow();
+        let has_facet = |entity_id: EntityId, facet_type: &str| {
+            state.facets.get(&(entity_id, facet_type.to_string())).is_some_and(|f| f.detached_at.is_none())
+        };
+        let mut deleted: Vec<(EntityRecord, Deletion)> = state
+            .entities
+            .iter()
+            .filter_map(|(entity_id, row)| Some((*entity_id, row, row.deleted?)))
+            .filter(|(entity_id, _, deletion)| {
+                since.is_none_or(|since| deletion.deleted_at > since)
+                    && facet_type.is_none_or(|facet_type| has_facet(*entity_id, facet_type))
+            })
+            .map(|(entity_id, row, deletion)| {
+                let entity = EntityRecord {
+                    entity_id,
+                    created_at: row.created_at,
+                    created_by: row.created_by,
+                    deleted: true,
+                    placeholder: row.placeholder,
+                };
+                (entity, deletion)
+            })
+            .collect();
+        deleted.sort_by_key(|(entity, deletion)| (Reverse(deletion.deleted_at), entity.entity_id));
+        Ok(deleted)
+    }
+
+    fn get_deleted_edges(&self) -> Result<Vec<(EdgeRecord, Deletion)>, StorageError> {
+        let state = self.state.borrow();
+        let mut deleted: Vec<(EdgeRecord, Deletion)> = state
+            .edges
+            .iter()
+            .filter_map(|(edge_id, row)| Some((edge_record(*edge_id, row), row.deleted?)))
+            .collect();
+        deleted.sort_by_key(|(edge, deletion)| (Reverse(deletion.deleted_at), edge.edge_id));
+        Ok(deleted)
+    }
+
+    fn get_vector_clock(&self) -> Result<VectorClock, StorageError> {
+        Ok(self.state.borrow().vector_clock.clone())
+    }
+
+    fn get_last_hlc(&self) -> Result<Option<Hlc>, StorageError> {
+        Ok(self.local.borrow().last_hlc)
+    }
+
+    fn set_last_hlc(&mut self, hlc: Hlc) -> Result<(), StorageError> {
+        let last = &mut self.local.get_mut().last_hlc;
+        *last = (*last).max(Some(hlc));
+        Ok(())
+    }
+
+    fn get_field_metadata(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<(ActorId, Hlc)>, StorageError> {
+        Ok(self
+            .state
+            .borrow()
+            .fields
+            .get(&(entity_id, field_key.to_string()))
+            .map(|row| (row.source_actor, row.updated_at)))
+    }
+
+    fn get_edge(&self, edge_id: EdgeId) -> Result<Option<EdgeRecord>, StorageError> {
+        Ok(self.state.borrow().edges.get(&edge_id).map(|row| edge_record(edge_id, row)))
+    }
+
+    fn get_edge_properties(
+        &self,
+        edge_id: EdgeId,
+    ) -> Result<Vec<(String, FieldValue)>, StorageError> {
+        let state = self.state.borrow();
+        decode_values(
+            state
+                .edge_properties
+                .range((edge_id, String::new())..)
+                .take_while(|((id, _), _)| *id == edge_id)
+                .filter_map(|((_, key), row)| Some((key.clone(), row.value.clone()?))),
+        )
+    }
+
+    fn get_edge_property(
+        &self,
+        edge_id: EdgeId,
+        key: &str,
+    ) -> Result<Option<FieldValue>, StorageError> {
+        self.state
+            .borrow()
+            .edge_properties
+            .get(&(edge_id, key.to_string()))
+            .and_then(|row| row.value.as_deref())
+            .map(|bytes| FieldValue::from_msgpack(bytes).map_err(serialization_error))
+            .transpose()
+    }
+
+    fn get_edge_property_metadata(
+        &self,
+        edge_id: EdgeId,
+        key: &str,
+    ) -> Result<Option<(ActorId, Hlc)>, StorageError> {
+        Ok(self
+            .state
+            .borrow()
+            .edge_properties
+            .get(&(edge_id, key.to_string()))
+            .map(|row| (row.source_actor, row.updated_at)))
+    }
+
+    fn insert_conflict(&mut self, record: &ConflictRecord) -> Result<(), StorageError> {
+        let local = self.local.get_mut();
+        if local.conflicts.iter().any(|c| c.conflict_id == record.conflict_id) {
+            return Err(StorageError::ConstraintViolation(format!("duplicate conflict {}", record.conflict_id)));
+        }
+        // Mirrors the table layout: only the detection columns are taken from
+        // the record, and one value per actor is kept
+        let mut conflict = ConflictRecord {
+            values: Vec::new(),
+            resolved_at: None,
+            resolved_by: None,
+            resolved_op_id: None,
+            resolved_value: None,
+            reopened_at: None,
+            reopened_by_op: None,
+            ..record.clone()
+        };
+        for value in &record.values {
+            upsert_conflict_value(&mut conflict, value);
+        }
+        local.conflicts.push(conflict);
+        Ok(())
+    }
+
+    fn update_conflict_resolved(
+        &mut self,
+        conflict_id: ConflictId,
+        resolved_at: Hlc,
+        resolved_by: ActorId,
+        resolved_op: OpId,
+        resolved_value: Option<Vec<u8>>,
+    ) -> Result<(), StorageError> {
+        if let Some(conflict) = self.local.get_mut().conflicts.iter_mut().find(|c| c.conflict_id == conflict_id) {
+            conflict.status = ConflictStatus::Resolved;
+            conflict.resolved_at = Some(resolved_at);
+            conflict.resolved_by = Some(resolved_by);
+            conflict.resolved_op_id = Some(resolved_op);
+            conflict.resolved_value = resolved_value;
+        }
+        Ok(())
+    }
+
+    fn get_open_conflicts_for_entity(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<Vec<ConflictRecord>, StorageError> {
+        Ok(self
+            .local
+            .borrow()
+            .conflicts
+            .iter()
+            .filter(|c| c.entity_id == entity_id && c.status == ConflictStatus::Open)
+            .cloned()
+            .collect())
+    }
+
+    fn get_open_conflicts(&self) -> Result<Vec<ConflictRecord>, StorageError> {
+        let mut open: Vec<ConflictRecord> =
+            self.local.borrow().conflicts.iter().filter(|c| c.status == ConflictStatus::Open).cloned().collect();
+        open.sort_by_key(|c| c.detected_at);
+        Ok(open)
+    }
+
+    fn get_conflict(
+        &self,
+        conflict_id: ConflictId,
+    ) -> Result<Option<ConflictRecord>, StorageError> {
+        Ok(self.local.borrow().conflicts.iter().find(|c| c.conflict_id == conflict_id).cloned())
+    }
+
+    fn get_open_conflict_for_field(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<ConflictRecord>, StorageError> {
+        Ok(self
+            .local
+            .borrow()
+            .conflicts
+            .iter()
+            .find(|c| c.entity_id == entity_id && c.field_key == field_key && c.status == ConflictStatus::Open)
+            .cloned())
+    }
+
+    fn get_latest_conflict_for_field(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<ConflictRecord>, StorageError> {
+        Ok(self
+            .local
+            .borrow()
+            .conflicts
+            .iter()
+            .filter(|c| c.entity_id == entity_id && c.field_key == field_key)
+            .max_by_key(|c| c.detected_at)
+            .cloned())
+    }
+
+    fn set_conflict_triage(
+        &mut self,
+        conflict_id: ConflictId,
+        triage: ConflictTriage,
+    ) -> Result<(), StorageError> {
+        let local = self.local.get_mut();
+        local.conflict_mut(conflict_id)?;
+        local.conflict_triage.insert(conflict_id, triage);
+        Ok(())
+    }
+
+    fn get_conflict_triage(
+        &self,
+        conflict_id: ConflictId,
+    ) -> Result<Option<ConflictTriage>, StorageError> {
+        let local = self.local.borrow();
+        if !local.conflicts.iter().any(|c| c.conflict_id == conflict_id) {
+            return Ok(None);
+        }
+        Ok(Some(local.conflict_triage.get(&conflict_id).copied().unwrap_or_default()))
+    }
+
+    fn count_unseen_conflicts(&self, now: i64) -> Result<u64, StorageError> {
+        let local = self.local.borrow();
+        Ok(local
+            .conflicts
+            .iter()
+            .filter(|c| c.status == ConflictStatus::Open)
+            .filter(|c| {
+                let triage = local.conflict_triage.get(&c.conflict_id).copied().unwrap_or_default();
+                triage.seen_at.is_none() && triage.snoozed_until.is_none_or(|until| until <= now)
+            })
+            .count() as u64)
+    }
+
+    fn reopen_conflict(
+        &mut self,
+        conflict_id: ConflictId,
+        reopened_at: Hlc,
+        reopened_by_op: OpId,
+        new_values: &[ConflictValue],
+    ) -> Result<(), StorageError> {
+        let local = self.local.get_mut();
+        local.conflict_triage.remove(&conflict_id);
+        let Ok(conflict) = local.conflict_mut(conflict_id) else {
+            return Ok(());
+        };
+        conflict.status = ConflictStatus::Open;
+        conflict.reopened_at = Some(reopened_at);
+        conflict.reopened_by_op = Some(reopened_by_op);
+        // Replace all branch tips with the new values
+        conflict.values.clear();
+        for value in new_values {
+            upsert_conflict_value(conflict, value);
+        }
+        Ok(())
+    }
+
+    fn add_conflict_value(
+        &mut self,
+        conflict_id: ConflictId,
+        value: &ConflictValue,
+    ) -> Result<(), StorageError> {
+        let local = self.local.get_mut();
+        upsert_conflict_value(local.conflict_mut(conflict_id)?, value);
+        local.conflict_triage.remove(&conflict_id);
+        Ok(())
+    }
+
+    fn get_bundle_vector_clock(
+        &self,
+        bundle_id: BundleId,
+    ) -> Result<Option<VectorClock>, StorageError> {
+        Ok(self.log.borrow().bundle(bundle_id).and_then(|b| b.creator_vc.clone()))
+    }
+
+    fn get_bundle(&self, bundle_id: BundleId) -> Result<Option<Bundle>, StorageError> {
+        Ok(self.log.borrow().bundle(bundle_id).cloned())
+    }
+
+    fn get_bundle_ids_in_range(&self, range: &HlcRange) -> Result<Vec<(Hlc, BundleId)>, StorageError> {
+        let mut ids: Vec<(Hlc, BundleId)> = self
+            .log
+            .borrow()
+            .bundles
+            .iter()
+            .filter(|b| b.hlc >= range.start && range.end.is_none_or(|end| b.hlc < end))
+            .map(|b| (b.hlc, b.bundle_id))
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_field_source_bundle_vc(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<(ActorId, Hlc, OpId, Option<VectorClock>)>, StorageError> {
+        let state = self.state.borrow();
+        let Some(row) = state.fields.get(&(entity_id, field_key.to_string())) else {
+            return Ok(None);
+        };
+        let log = self.log.borrow();
+        let Some(bundle) = log.op(row.source_op).and_then(|op| log.bundle(op.bundle_id)) else {
+            return Ok(None);
+        };
+        Ok(Some((row.source_actor, row.updated_at, row.source_op, bundle.creator_vc.clone())))
+    }
+
+    fn get_op_field_value(&self, op_id: OpId) -> Result<Option<Vec<u8>>, StorageError> {
+        match self.log.borrow().op(op_id).map(|op| &op.payload) {
+            Some(OperationPayload::SetField { value, .. })
+            | Some(OperationPayload::ResolveConflict { chosen_value: Some(value), .. }) => {
+                Ok(Some(value.to_msgpack().map_err(serialization_error)?))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// One value per actor: a later value from the same actor replaces the earlier one.
+fn upsert_conflict_value(conflict: &mut ConflictRecord, value: &ConflictValue) {
+    match conflict.values.iter_mut().find(|v| v.actor_id == value.actor_id) {
+        Some(existing) => *existing = value.clone(),
+        None => conflict.values.push(value.clone()),
+    }
+}
+
+// ============================================================================
+// Overlay CRUD
+// ============================================================================
+
+impl OverlayStore for MemoryStorage {
+    fn insert_overlay(
+        &mut self,
+        overlay_id: OverlayId,
+        display_name: &str,
+        source: &str,
+        status: &str,
+        created_at: &Hlc,
+    ) -> Result<(), StorageError> {
+        let overlays = &mut self.local.get_mut().overlays;
+        if overlays.contains_key(&overlay_id) {
+            return Err(StorageError::ConstraintViolation(format!("duplicate overlay {overlay_id}")));
+        }
+        overlays.insert(
+            overlay_id,
+            OverlayRow {
+                display_name: display_name.to_string(),
+                source: source.to_string(),
+                status: status.to_string(),
+                created_at: *created_at,
+                updated_at: *created_at,
+                drift_policy: "block".to_string(),
+                script_id: None,
+                run_id: None,
+                auto_commit: false,
+            },
+        );
+        Ok(())
+    }
+
+    fn update_overlay_status(
+        &mut self,
+        overlay_id: OverlayId,
+        status: &str,
+        updated_at: &Hlc,
+    ) -> Result<(), StorageError> {
+        if let Some(overlay) = self.local.get_mut().overlays.get_mut(&overlay_id) {
+            overlay.status = status.to_string();
+            overlay.updated_at = *updated_at;
+        }
+        Ok(())
+    }
+
+    fn delete_overlay(&mut self, overlay_id: OverlayId) -> Result<(), StorageError> {
+        let local = self.local.get_mut();
+        local.overlays.remove(&overlay_id);
+        local.overlay_ops.retain(|op| op.overlay_id != overlay_id);
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_overlay(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<Option<(OverlayId, String, String, String, Hlc, Hlc)>, StorageError> {
+        Ok(self.local.borrow().overlays.get(&overlay_id).map(|row| {
+            (
+                overlay_id,
+                row.display_name.clone(),
+                row.source.clone(),
+                row.status.clone(),
+                row.created_at,
+                row.updated_at,
+            )
+        }))
+    }
+
+    fn list_overlays_by_status(
+        &self,
+        status: &str,
+    ) -> Result<Vec<(OverlayId, String, String, Hlc)>, StorageError> {
+        let mut overlays: Vec<_> = self
+            .local
+            .borrow()
+            .overlays
+            .iter()
+            .filter(|(_, row)| row.status == status)
+            .map(|(id, row)| (*id, row.display_name.clone(), row.source.clone(), row.created_at))
+            .collect();
+        overlays.sort_by_key(|overlay| overlay.3);
+        Ok(overlays)
+    }
+
+    fn set_overlay_drift_policy(&mut self, overlay_id: OverlayId, policy: &str) -> Result<(), StorageError> {
+        let overlay = self.local.get_mut().overlays.get_mut(&overlay_id)
+            .ok_or_else(|| StorageError::NotFound(format!("overlay {overlay_id}")))?;
+        overlay.drift_policy = policy.to_string();
+        Ok(())
+    }
+
+    fn get_overlay_drift_policy(&self, overlay_id: OverlayId) -> Result<Option<String>, StorageError> {
+        Ok(self.local.borrow().overlays.get(&overlay_id).map(|row| row.drift_policy.clone()))
+    }
+
+    fn set_overlay_origin(&mut self, overlay_id: OverlayId, origin: &OverlayOrigin) -> Result<(), StorageError> {
+        let overlay = self.local.get_mut().overlays.get_mut(&overlay_id)
+            .ok_or_else(|| StorageError::NotFound(format!("overlay {overlay_id}")))?;
+        overlay.script_id = origin.script_id.clone();
+        overlay.run_id = origin.run_id.clone();
+        overlay.auto_commit = origin.auto_commit;
+        Ok(())
+    }
+
+    fn get_overlay_origin(&self, overlay_id: OverlayId) -> Result<Option<OverlayOrigin>, StorageError> {
+        Ok(self.local.borrow().overlays.get(&overlay_id).map(|row| OverlayOrigin {
+            script_id: row.script_id.clone(),
+            run_id: row.run_id.clone(),
+            auto_commit: row.auto_commit,
+        }))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert_overlay_op(
+        &mut self,
+        overlay_id: OverlayId,
+        op_id: OpId,
+        hlc: &Hlc,
+        payload_bytes: &[u8],
+        entity_id: Option<EntityId>,
+        field_key: Option<&str>,
+        op_type: &str,
+        canonical_value_at_creation: Option<&[u8]>,
+    ) -> Result<i64, StorageError> {
+        let local = self.local.get_mut();
+        if !local.overlays.contains_key(&overlay_id) {
+            return Err(StorageError::ConstraintViolation(format!("unknown overlay {overlay_id}")));
+        }
+        local.next_overlay_rowid += 1;
+        let rowid = local.next_overlay_rowid;
+        local.overlay_ops.push(OverlayOp {
+            rowid,
+            overlay_id,
+            op_id,
+            hlc: *hlc,
+            payload: payload_bytes.to_vec(),
+            entity_id,
+            field_key: field_key.map(str::to_string),
+            op_type: op_type.to_string(),
+            canonical_value_at_creation: canonical_value_at_creation.map(<[u8]>::to_vec),
+            canonical_drifted: false,
+        });
+        Ok(rowid)
+    }
+
+    fn delete_overlay_op(&mut self, rowid: i64) -> Result<(), StorageError> {
+        self.local.get_mut().overlay_ops.retain(|op| op.rowid != rowid);
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_overlay_ops(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<Vec<(i64, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, String, Option<Vec<u8>>, bool, Option<String>)>, StorageError> {
+        Ok(self.overlay_ops(|op| op.overlay_id == overlay_id))
+    }
+
+    fn get_latest_overlay_field_op(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<(i64, Vec<u8>)>, StorageError> {
+        Ok(self
+            .local
+            .borrow()
+            .overlay_ops
+            .iter()
+            .rev()
+            .find(|op| op.overlay_id == overlay_id && op.matches_field(entity_id, field_key))
+            .map(|op| (op.rowid, op.payload.clone())))
+    }
+
+    fn count_overlay_ops(&self, overlay_id: OverlayId) -> Result<u64, StorageError> {
+        Ok(self.local.borrow().overlay_ops.iter().filter(|op| op.overlay_id == overlay_id).count() as u64)
+    }
+
+    fn mark_overlay_ops_drifted(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<u64, StorageError> {
+        let mut marked = 0;
+        for op in self.local.borrow_mut().overlay_ops.iter_mut() {
+            if op.matches_field(entity_id, field_key) && !op.canonical_drifted {
+                op.canonical_drifted = true;
+                marked += 1;
+            }
+        }
+        Ok(marked)
+    }
+
+    fn clear_drift_flag(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<(), StorageError> {
+        for op in self.local.borrow_mut().overlay_ops.iter_mut() {
+            if op.overlay_id == overlay_id && op.matches_field(entity_id, field_key) {
+                op.canonical_drifted = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn update_canonical_value_at_creation(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+        new_value: Option<&[u8]>,
+    ) -> Result<(), StorageError> {
+        for op in self.local.borrow_mut().overlay_ops.iter_mut() {
+            if op.overlay_id == overlay_id && op.matches_field(entity_id, field_key) {
+                op.canonical_value_at_creation = new_value.map(<[u8]>::to_vec);
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_drifted_overlay_ops(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<Vec<(i64, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, String, Option<Vec<u8>>, bool, Option<String>)>, StorageError> {
+        Ok(self.overlay_ops(|op| op.overlay_id == overlay_id && op.canonical_drifted))
+    }
+
+    fn count_unresolved_drift(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<u64, StorageError> {
+        Ok(self
+            .local
+            .borrow()
+            .overlay_ops
+            .iter()
+            .filter(|op| op.overlay_id == overlay_id && op.canonical_drifted)
+            .count() as u64)
+    }
+
+    fn delete_overlay_ops_for_field(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<u64, StorageError> {
+        let overlay_ops = &mut self.local.borrow_mut().overlay_ops;
+        let before = overlay_ops.len();
+        overlay_ops.retain(|op| !(op.overlay_id == overlay_id && op.matches_field(entity_id, field_key)));
+        Ok((before - overlay_ops.len()) as u64)
+    }
+}
+
+// ============================================================================
+// Bundle Labels
+// ============================================================================
+
+impl LabelStore for MemoryStorage {
+    fn set_bundle_label(
+        &mut self,
+        bundle_id: BundleId,
+        label: &str,
+    ) -> Result<(), StorageError> {
+        if self.log.get_mut().bundle(bundle_id).is_none() {
+            return Err(StorageError::ConstraintViolation(format!("unknown bundle {bundle_id}")));
+        }
+        self.local.get_mut().labels.insert(bundle_id, label.to_string());
+        Ok(())
+    }
+
+    fn get_bundle_label(&self, bundle_id: BundleId) -> Result<Option<String>, StorageError> {
+        Ok(self.local.borrow().labels.get(&bundle_id).cloned())
+    }
+
+    fn list_bundles_with_labels(&self) -> Result<Vec<(Bundle, Option<String>)>, StorageError> {
+        let labels = &self.local.borrow().labels;
+        let mut bundles: Vec<_> = self
+            .log
+            .borrow()
+            .bundles
+            .iter()
+            .map(|b| (b.clone(), labels.get(&b.bundle_id).cloned()))
+            .collect();
+        bundles.sort_by_key(|(b, _)| (b.hlc, b.bundle_id));
+        Ok(bundles)
+    }
+
+    fn get_bundles_by_tag(&self, tag: &str) -> Result<Vec<Bundle>, StorageError> {
+        let mut bundles: Vec<Bundle> =
+            self.log.borrow().bundles.iter().filter(|b| indexed_meta(b).tags.contains(tag)).cloned().collect();
+        bundles.sort_by_key(|b| (b.hlc, b.bundle_id));
+        Ok(bundles)
+    }
+
+    fn get_bundles_by_session(&self, session_id: SessionId) -> Result<Vec<Bundle>, StorageError> {
+        let mut bundles: Vec<Bundle> = self
+            .log
+            .borrow()
+            .bundles
+            .iter()
+            .filter(|b| indexed_meta(b).session == Some(session_id))
+            .cloned()
+            .collect();
+        bundles.sort_by_key(|b| (b.hlc, b.bundle_id));
+        Ok(bundles)
+    }
+
+    fn get_bundle_by_idempotency_key(&self, actor_id: ActorId, key: &str) -> Result<Option<Bundle>, StorageError> {
+        Ok(self
+            .log
+            .borrow()
+            .bundles
+            .iter()
+            .find(|b| b.actor_id == actor_id && indexed_meta(b).idempotency_key.as_deref() == Some(key))
+            .cloned())
+    }
+}
+
+// ============================================================================
+// Pending Bundles
+// ============================================================================
+
+impl PendingStore for MemoryStorage {
+    fn insert_pending_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<(), StorageError> {
+        let pending = &mut self.local.get_mut().pending;
+        if !pending.iter().any(|r| r.bundle.bundle_id == bundle.bundle_id) {
+            pending.push(PendingBundleRecord {
+                bundle: bundle.clone(),
+                operations: operations.to_vec(),
+                received_at: now_ms()?,
+            });
+        }
+        Ok(())
+    }
+
+    fn delete_pending_bundle(&mut self, bundle_id: BundleId) -> Result<bool, StorageError> {
+        let pending = &mut self.local.get_mut().pending;
+        let before = pending.len();
+        pending.retain(|r| r.bundle.bundle_id != bundle_id);
+        Ok(pending.len() < before)
+    }
+
+    fn list_pending_bundles(&self) -> Result<Vec<PendingBundleRecord>, StorageError> {
+        Ok(Self::read_held(&self.local.borrow().pending, |_| true))
+    }
+
+    fn count_pending_bundles(&self) -> Result<u64, StorageError> {
+        Ok(self.local.borrow().pending.len() as u64)
+    }
+}
+
+// ============================================================================
+// Quarantine
+// ============================================================================
+
+impl QuarantineStore for MemoryStorage {
+    fn insert_quarantined_bundle(
+        &mut self,
+        raw: &RawBundle,
+        bundle_id: Option<BundleId>,
+        actor_id: Option<ActorId>,
+        reason: &str,
+    ) -> Result<i64, StorageError> {
+        let received_at = now_ms()?;
+        let local = self.local.get_mut();
+        if let Some(existing) = local.quarantined.iter_mut().find(|q| q.raw == *raw) {
+            existing.reason = reason.to_string();
+            return Ok(existing.quarantine_id);
+        }
+        local.next_quarantine_id += 1;
+        local.quarantined.push(QuarantinedBundle {
+            quarantine_id: local.next_quarantine_id,
+            bundle_id,
+            actor_id,
+            raw: raw.clone(),
+            reason: reason.to_string(),
+            received_at,
+        });
+        Ok(local.next_quarantine_id)
+    }
+
+    fn get_quarantined_bundle(&self, quarantine_id: i64) -> Result<Option<QuarantinedBundle>, StorageError> {
+        Ok(self.local.borrow().quarantined.iter().find(|q| q.quarantine_id == quarantine_id).cloned())
+    }
+
+    fn list_quarantined_bundles(&self) -> Result<Vec<QuarantinedBundle>, StorageError> {
+        Ok(self.local.borrow().quarantined.clone())
+    }
+
+    fn update_quarantine_reason(&mut self, quarantine_id: i64, reason: &str) -> Result<(), StorageError> {
+        if let Some(entry) = self.local.get_mut().quarantined.iter_mut().find(|q| q.quarantine_id == quarantine_id) {
+            entry.reason = reason.to_string();
+        }
+        Ok(())
+    }
+
+    fn delete_quarantined_bundle(&mut self, quarantine_id: i64) -> Result<bool, StorageError> {
+        let quarantined = &mut self.local.get_mut().quarantined;
+        let before = quarantined.len();
+        quarantined.retain(|q| q.quarantine_id != quarantine_id);
+        Ok(quarantined.len() < before)
+    }
+}
+
+// ============================================================================
+// Actor Directory
+// ============================================================================
+
+impl ActorStore for MemoryStorage {
+    fn set_actor_display_name(&mut self, actor_id: ActorId, display_name: Option<&str>) -> Result<(), StorageError> {
+        if !self.state.get_mut().actors.contains_key(&actor_id) {
+            return Ok(());
+        }
+        let names = &mut self.local.get_mut().actor_names;
+        match display_name {
+            Some(name) => names.insert(actor_id, name.to_string()),
+            None => names.remove(&actor_id),
+        };
+        Ok(())
+    }
+
+    fn list_actors(&self) -> Result<Vec<ActorRecord>, StorageError> {
+        let state = self.state.borrow();
+        let names = &self.local.borrow().actor_names;
+        let mut op_counts: HashMap<ActorId, u64> = HashMap::new();
+        for op in &self.log.borrow().ops {
+            *op_counts.entry(op.actor_id).or_default() += 1;
+        }
+        let mut actors: Vec<ActorRecord> = state
+            .actors
+            .iter()
+            .map(|(actor_id, row)| ActorRecord {
+                actor_id: *actor_id,
+                display_name: names.get(actor_id).cloned(),
+                first_seen_at: row.first_seen_at,
+                last_seen_at: state.vector_clock.get(actor_id).copied().unwrap_or(row.first_seen_at),
+                op_count: op_counts.get(actor_id).copied().unwrap_or(0),
+            })
+            .collect();
+        actors.sort_by_key(|a| (a.first_seen_at, a.actor_id));
+        Ok(actors)
+    }
+}
+
+// ============================================================================
+// Actor Trust
+// ============================================================================
+
+impl TrustStore for MemoryStorage {
+    fn set_actor_trust(&mut self, actor_id: ActorId, state: TrustState) -> Result<(), StorageError> {
+        self.local.get_mut().trust.insert(actor_id, state);
+        Ok(())
+    }
+
+    fn get_actor_trust(&self, actor_id: ActorId) -> Result<Option<TrustState>, StorageError> {
+        Ok(self.local.borrow().trust.get(&actor_id).copied())
+    }
+
+    fn list_actor_trust(&self) -> Result<Vec<(ActorId, TrustState)>, StorageError> {
+        Ok(self.local.borrow().trust.iter().map(|(id, state)| (*id, *state)).collect())
+    }
+
+    fn insert_untrusted_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<(), StorageError> {
+        let untrusted = &mut self.local.get_mut().untrusted;
+        if !untrusted.iter().any(|r| r.bundle.bundle_id == bundle.bundle_id) {
+            untrusted.push(PendingBundleRecord {
+                bundle: bundle.clone(),
+                operations: operations.to_vec(),
+                received_at: now_ms()?,
+            });
+        }
+        Ok(())
+    }
+
+    fn list_untrusted_bundles(&self, actor_id: ActorId) -> Result<Vec<PendingBundleRecord>, StorageError> {
+        Ok(Self::read_held(&self.local.borrow().untrusted, |b| b.actor_id == actor_id))
+    }
+
+    fn delete_untrusted_bundles(&mut self, actor_id: ActorId) -> Result<usize, StorageError> {
+        let untrusted = &mut self.local.get_mut().untrusted;
+        let before = untrusted.len();
+        untrusted.retain(|r| r.bundle.actor_id != actor_id);
+        Ok(before - untrusted.len())
+    }
+
+    fn count_untrusted_bundles(&self) -> Result<u64, StorageError> {
+        Ok(self.local.borrow().untrusted.len() as u64)
+    }
+}
+
+// ============================================================================
+// Peers
+// ============================================================================
+
+impl PeerStore for MemoryStorage {
+    fn upsert_peer(
+        &mut self,
+        peer_id: ActorId,
+        display_name: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let peers = &mut self.local.get_mut().peers;
+        match peers.iter_mut().find(|p| p.peer_id == peer_id) {
+            Some(peer) => {
+                if let Some(name) = display_name {
+                    peer.display_name = Some(name.to_string());
+                }
+            }
+            None => peers.push(PeerRecord {
+                peer_id,
+                display_name: display_name.map(str::to_string),
+                acked_vc: VectorClock::new(),
+                last_synced_at: None,
+            }),
+        }
+        Ok(())
+    }
+
+    fn delete_peer(&mut self, peer_id: ActorId) -> Result<bool, StorageError> {
+        let peers = &mut self.local.get_mut().peers;
+        let before = peers.len();
+        peers.retain(|p| p.peer_id != peer_id);
+        Ok(peers.len() < before)
+    }
+
+    fn set_peer_acked_vc(
+        &mut self,
+        peer_id: ActorId,
+        acked_vc: &VectorClock,
+    ) -> Result<(), StorageError> {
+        let synced_at = now_ms()?;
+        let peer = self
+            .local
+            .get_mut()
+            .peers
+            .iter_mut()
+            .find(|p| p.peer_id == peer_id)
+            .ok_or_else(|| StorageError::ConstraintViolation(format!("unknown peer {peer_id}")))?;
+        peer.acked_vc = acked_vc.clone();
+        peer.last_synced_at = Some(synced_at);
+        Ok(())
+    }
+
+    fn get_peer(&self, peer_id: ActorId) -> Result<Option<PeerRecord>, StorageError> {
+        Ok(self.local.borrow().peers.iter().find(|p| p.peer_id == peer_id).cloned())
+    }
+
+    fn list_peers(&self) -> Result<Vec<PeerRecord>, StorageError> {
+        Ok(self.local.borrow().peers.clone())
+    }
+}
+
+// ============================================================================
+// Webhooks
+// ============================================================================
+
+impl WebhookStore for MemoryStorage {
+    fn insert_webhook(&mut self, webhook: &WebhookRecord) -> Result<(), StorageError> {
+        let webhooks = &mut self.local.get_mut().webhooks;
+        if webhooks.iter().any(|w| w.webhook_id == webhook.webhook_id) {
+            return Err(StorageError::ConstraintViolation(format!("webhook {} already registered", webhook.webhook_id)));
+        }
+        webhooks.push(webhook.clone());
+        Ok(())
+    }
+
+    fn delete_webhook(&mut self, webhook_id: WebhookId) -> Result<bool, StorageError> {
+        let webhooks = &mut self.local.get_mut().webhooks;
+        let before = webhooks.len();
+        webhooks.retain(|w| w.webhook_id != webhook_id);
+        Ok(webhooks.len() < before)
+    }
+
+    fn get_webhook(&self, webhook_id: WebhookId) -> Result<Option<WebhookRecord>, StorageError> {
+        Ok(self.local.borrow().webhooks.iter().find(|w| w.webhook_id == webhook_id).cloned())
+    }
+
+    fn list_webhooks(&self) -> Result<Vec<WebhookRecord>, StorageError> {
+        Ok(self.local.borrow().webhooks.clone())
+    }
+
+    fn update_webhook_delivery(
+        &mut self,
+        webhook_id: WebhookId,
+        cursor: u64,
+        attempts: u32,
+        retry_at: Option<i64>,
+        last_error: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let webhook = self
+            .local
+            .get_mut()
+            .webhooks
+            .iter_mut()
+            .find(|w| w.webhook_id == webhook_id)
+            .ok_or_else(|| StorageError::NotFound(format!("webhook {webhook_id}")))?;
+        webhook.cursor = cursor;
+        webhook.attempts = attempts;
+        webhook.retry_at = retry_at;
+        webhook.last_error = last_error.map(str::to_string);
+        Ok(())
+    }
+}
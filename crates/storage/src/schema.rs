@@ -1,9 +1,30 @@
+#[cfg(feature = "sqlite")]
 use rusqlite::Connection;
 
+#[cfg(feature = "sqlite")]
 use crate::error::StorageError;
 
-pub const SCHEMA_VERSION: i32 = 2;
+/// Version of the schema this build creates and understands: the baseline
+/// plus every migration in `MIGRATIONS`.
+pub const SCHEMA_VERSION: i32 = 25;
 
+/// Workspace of databases opened without naming one, and of every row
+/// written before workspaces existed.
+pub const DEFAULT_WORKSPACE: &str = "default";
+
+/// Version created by `BASELINE_SQL`; later changes are migrations.
+#[cfg(feature = "sqlite")]
+const BASELINE_VERSION: i32 = 2;
+
+/// One in-place schema upgrade. Applied in order, each in its own savepoint,
+/// to databases whose recorded version is below `version`.
+pub struct Migration {
+    pub version: i32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+#[cfg(feature = "sqlite")]
 pub fn init_schema(conn: &Connection) -> Result<(), StorageError> {
     conn.execute_batch(
         "
@@ -15,11 +36,603 @@ pub fn init_schema(conn: &Connection) -> Result<(), StorageError> {
         PRAGMA busy_timeout = 5000;
     ",
     )?;
-    conn.execute_batch(SCHEMA_SQL)?;
+    migrate(conn)
+}
+
+/// Bring the database up to `SCHEMA_VERSION`, applying pending migrations in
+/// order. Fails with `SchemaTooNew` (before touching anything) if the database
+/// was written by a newer build.
+#[cfg(feature = "sqlite")]
+pub fn migrate(conn: &Connection) -> Result<(), StorageError> {
+    let found = current_version(conn)?;
+    if found > SCHEMA_VERSION {
+        return Err(StorageError::SchemaTooNew { found, supported: SCHEMA_VERSION });
+    }
+    conn.execute_batch(BASELINE_SQL)?;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > found.max(BASELINE_VERSION)) {
+        conn.execute_batch("SAVEPOINT sp_migrate")?;
+        let applied = conn.execute_batch(migration.sql).and_then(|_| backfill(conn, migration.version)).and_then(|_| {
+            conn.execute(
+                "INSERT INTO schema_version (version, applied_at) VALUES (?1, unixepoch())",
+                [migration.version],
+            )
+        });
+        match applied {
+            Ok(_) => conn.execute_batch("RELEASE sp_migrate")?,
+            Err(e) => {
+                let _ = conn.execute_batch("ROLLBACK TO sp_migrate; RELEASE sp_migrate");
+                return Err(StorageError::Migration {
+                    version: migration.version,
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
     Ok(())
 }
 
-const SCHEMA_SQL: &str = "
+/// Data a migration derives from existing rows in Rust rather than SQL,
+/// run in the migration's savepoint right after its `sql`.
+#[cfg(feature = "sqlite")]
+fn backfill(conn: &Connection, version: i32) -> rusqlite::Result<()> {
+    match version {
+        13 => {
+            let mut stmt = conn.prepare("SELECT bundle_id, meta FROM bundles WHERE meta IS NOT NULL")?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            for (bundle_id, meta) in rows {
+                let Ok(meta) = openprod_core::operations::BundleMeta::from_msgpack(&meta) else { continue };
+                for tag in meta.tags {
+                    conn.execute(
+                        "INSERT OR IGNORE INTO bundle_tags (tag, bundle_id) VALUES (?1, ?2)",
+                        rusqlite::params![tag, bundle_id],
+                    )?;
+                }
+            }
+            Ok(())
+        }
+        15 => {
+            use openprod_core::operations::OperationPayload;
+            // Mappings confirmed before they were materialized
+            let mut stmt = conn.prepare(
+                "SELECT op_id, actor_id, hlc, payload FROM oplog WHERE op_type = 'ConfirmFieldMapping' ORDER BY hlc, op_id",
+            )?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?, row.get::<_, Vec<u8>>(2)?, row.get::<_, Vec<u8>>(3)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            for (op_id, actor_id, hlc, payload) in rows {
+                let Ok(OperationPayload::ConfirmFieldMapping { source_table, target_table, source_field, target_field }) =
+                    OperationPayload::from_msgpack(&payload)
+                else {
+                    continue;
+                };
+                // As `UPSERT_FIELD_MAPPING` stood at this version, before workspaces
+                conn.execute(
+                    "INSERT INTO field_mappings (source_table, target_table, source_field, target_field, source_op, confirmed_by, confirmed_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                     ON CONFLICT(source_table, target_table, source_field) DO UPDATE SET target_field = excluded.target_field, source_op = excluded.source_op, confirmed_by = excluded.confirmed_by, confirmed_at = excluded.confirmed_at
+                     WHERE excluded.confirmed_at > field_mappings.confirmed_at OR (excluded.confirmed_at = field_mappings.confirmed_at AND excluded.source_op > field_mappings.source_op)",
+                    rusqlite::params![
+                        source_table.as_bytes().as_slice(),
+                        target_table.as_bytes().as_slice(),
+                        source_field,
+                        target_field,
+                        op_id,
+                        actor_id,
+                        hlc,
+                    ],
+                )?;
+            }
+            Ok(())
+        }
+        25 => {
+            use openprod_core::operations::OperationPayload;
+            let mut stmt = conn.prepare(
+                "SELECT rowid, payload FROM oplog WHERE op_type IN ('CreateEdge', 'CreateOrderedEdge', 'SetEdgeProperty', 'ClearEdgeProperty')",
+            )?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            for (rowid, payload) in rows {
+                let Some(edge_id) = OperationPayload::from_msgpack(&payload).ok().and_then(|p| p.property_edge_id()) else {
+                    continue;
+                };
+                conn.execute(
+                    "UPDATE oplog SET edge_id = ?1 WHERE rowid = ?2",
+                    rusqlite::params![edge_id.as_bytes().as_slice(), rowid],
+                )?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Highest recorded schema version (0 for a fresh database).
+#[cfg(feature = "sqlite")]
+fn current_version(conn: &Connection) -> Result<i32, StorageError> {
+    let has_table: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Ok(0);
+    }
+    Ok(conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))?)
+}
+
+/// Schema changes since the baseline, oldest first. Append only: never edit
+/// a migration that has shipped.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 3,
+        description: "bundle labels",
+        sql: "
+CREATE TABLE IF NOT EXISTS bundle_labels (
+    bundle_id BLOB PRIMARY KEY CHECK (length(bundle_id) = 16),
+    label TEXT NOT NULL,
+    FOREIGN KEY (bundle_id) REFERENCES bundles(bundle_id)
+);
+",
+    },
+    Migration {
+        version: 4,
+        description: "pending bundle buffer",
+        sql: "
+CREATE TABLE IF NOT EXISTS pending_bundles (
+    bundle_id BLOB PRIMARY KEY CHECK (length(bundle_id) = 16),
+    actor_id BLOB NOT NULL CHECK (length(actor_id) = 32),
+    hlc BLOB NOT NULL CHECK (length(hlc) = 12),
+    bundle BLOB NOT NULL,
+    operations BLOB NOT NULL,
+    received_at INTEGER NOT NULL DEFAULT (CAST(unixepoch('now','subsec') * 1000 AS INTEGER))
+);
+CREATE INDEX IF NOT EXISTS idx_pending_bundles_hlc ON pending_bundles (hlc);
+",
+    },
+    Migration {
+        version: 5,
+        description: "sync peers",
+        sql: "
+CREATE TABLE IF NOT EXISTS peers (
+    peer_id BLOB PRIMARY KEY CHECK (length(peer_id) = 32),
+    display_name TEXT,
+    added_at INTEGER NOT NULL DEFAULT (CAST(unixepoch('now','subsec') * 1000 AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS peer_sync_state (
+    peer_id BLOB PRIMARY KEY CHECK (length(peer_id) = 32),
+    acked_vector_clock BLOB NOT NULL,
+    last_synced_at INTEGER NOT NULL,
+    FOREIGN KEY (peer_id) REFERENCES peers(peer_id) ON DELETE CASCADE
+);
+",
+    },
+    Migration {
+        version: 6,
+        description: "actor trust",
+        sql: "
+CREATE TABLE IF NOT EXISTS actor_trust (
+    actor_id BLOB PRIMARY KEY CHECK (length(actor_id) = 32),
+    state TEXT NOT NULL CHECK (state IN ('trusted', 'pending', 'revoked')),
+    updated_at INTEGER NOT NULL DEFAULT (CAST(unixepoch('now','subsec') * 1000 AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS untrusted_bundles (
+    bundle_id BLOB PRIMARY KEY CHECK (length(bundle_id) = 16),
+    actor_id BLOB NOT NULL CHECK (length(actor_id) = 32),
+    hlc BLOB NOT NULL CHECK (length(hlc) = 12),
+    bundle BLOB NOT NULL,
+    operations BLOB NOT NULL,
+    received_at INTEGER NOT NULL DEFAULT (CAST(unixepoch('now','subsec') * 1000 AS INTEGER))
+);
+CREATE INDEX IF NOT EXISTS idx_untrusted_bundles_actor ON untrusted_bundles (actor_id, hlc);
+",
+    },
+    Migration {
+        version: 7,
+        description: "materialization watermark",
+        sql: "
+CREATE TABLE IF NOT EXISTS materialization_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    watermark BLOB NOT NULL CHECK (length(watermark) = 12)
+);
+",
+    },
+    Migration {
+        version: 8,
+        description: "unfiltered edge and facet indexes",
+        // The partial indexes only serve live-row queries; edge lookups that
+        // include deleted edges, and detached-facet lookups, were table scans
+        sql: "
+CREATE INDEX IF NOT EXISTS idx_edges_source_all ON edges (source_id);
+CREATE INDEX IF NOT EXISTS idx_edges_target_all ON edges (target_id);
+CREATE INDEX IF NOT EXISTS idx_facets_type_detached ON facets (facet_type, detached_at);
+",
+    },
+    Migration {
+        version: 9,
+        description: "quarantined bundles",
+        sql: "
+CREATE TABLE IF NOT EXISTS quarantined_bundles (
+    quarantine_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    digest BLOB NOT NULL UNIQUE CHECK (length(digest) = 32),
+    bundle_id BLOB CHECK (length(bundle_id) = 16),
+    actor_id BLOB CHECK (length(actor_id) = 32),
+    raw BLOB NOT NULL,
+    reason TEXT NOT NULL,
+    received_at INTEGER NOT NULL DEFAULT (CAST(unixepoch('now','subsec') * 1000 AS INTEGER))
+);
+",
+    },
+    Migration {
+        version: 10,
+        description: "edge endpoint pair index",
+        sql: "
+CREATE INDEX IF NOT EXISTS idx_edges_between ON edges (source_id, target_id, edge_type);
+",
+    },
+    Migration {
+        version: 11,
+        description: "unique edge types",
+        sql: "
+CREATE TABLE IF NOT EXISTS unique_edge_types (
+    edge_type TEXT PRIMARY KEY NOT NULL
+);
+",
+    },
+    Migration {
+        version: 12,
+        description: "facet fields",
+        sql: "
+CREATE TABLE IF NOT EXISTS facet_fields (
+    facet_type TEXT NOT NULL,
+    field_key TEXT NOT NULL,
+    PRIMARY KEY (facet_type, field_key)
+);
+",
+    },
+    Migration {
+        version: 13,
+        description: "bundle tags",
+        sql: "
+CREATE TABLE IF NOT EXISTS bundle_tags (
+    tag TEXT NOT NULL,
+    bundle_id BLOB NOT NULL REFERENCES bundles(bundle_id),
+    PRIMARY KEY (tag, bundle_id)
+);
+",
+    },
+    Migration {
+        version: 14,
+        description: "bundle sessions",
+        sql: "
+CREATE TABLE IF NOT EXISTS bundle_sessions (
+    bundle_id BLOB PRIMARY KEY NOT NULL REFERENCES bundles(bundle_id),
+    session_id BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_bundle_sessions_session ON bundle_sessions(session_id);
+",
+    },
+    Migration {
+        version: 15,
+        description: "field mappings",
+        sql: "
+CREATE TABLE IF NOT EXISTS field_mappings (
+    source_table BLOB NOT NULL,
+    target_table BLOB NOT NULL,
+    source_field TEXT NOT NULL,
+    target_field TEXT NOT NULL,
+    source_op BLOB NOT NULL,
+    confirmed_by BLOB NOT NULL,
+    confirmed_at BLOB NOT NULL,
+    PRIMARY KEY (source_table, target_table, source_field)
+);
+",
+    },
+    Migration {
+        version: 16,
+        description: "webhooks",
+        sql: "
+CREATE TABLE IF NOT EXISTS webhooks (
+    webhook_id BLOB PRIMARY KEY NOT NULL CHECK (length(webhook_id) = 16),
+    url TEXT NOT NULL,
+    filter TEXT NOT NULL,
+    cursor INTEGER NOT NULL DEFAULT 0,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    retry_at INTEGER,
+    last_error TEXT
+);
+",
+    },
+    Migration {
+        version: 17,
+        description: "conflict triage",
+        sql: "
+ALTER TABLE conflicts ADD COLUMN seen_at INTEGER;
+ALTER TABLE conflicts ADD COLUMN snoozed_until INTEGER;
+",
+    },
+    Migration {
+        version: 18,
+        description: "overlay drift policy",
+        sql: "
+ALTER TABLE overlays ADD COLUMN drift_policy TEXT NOT NULL DEFAULT 'block'
+    CHECK (drift_policy IN ('block', 'keep_mine', 'use_canonical', 'rebase_if_unchanged'));
+",
+    },
+    Migration {
+        version: 19,
+        description: "import overlays and auto-commit",
+        // SQLite can't alter a CHECK, so the table is rebuilt. overlay_ops
+        // reference it, so they are set aside meanwhile and put back.
+        sql: "
+CREATE TABLE overlays_new (
+    overlay_id BLOB PRIMARY KEY CHECK (length(overlay_id) = 16),
+    display_name TEXT NOT NULL,
+    source TEXT NOT NULL DEFAULT 'user' CHECK (source IN ('user', 'script', 'import')),
+    source_id TEXT,
+    status TEXT NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'stashed', 'committed', 'discarded')),
+    created_at BLOB NOT NULL CHECK (length(created_at) = 12),
+    updated_at BLOB NOT NULL CHECK (length(updated_at) = 12),
+    script_id TEXT,
+    script_execution_id TEXT,
+    meta BLOB,
+    drift_policy TEXT NOT NULL DEFAULT 'block'
+        CHECK (drift_policy IN ('block', 'keep_mine', 'use_canonical', 'rebase_if_unchanged')),
+    auto_commit INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO overlays_new (overlay_id, display_name, source, source_id, status, created_at, updated_at, script_id,
+                          script_execution_id, meta, drift_policy)
+    SELECT overlay_id, display_name, source, source_id, status, created_at, updated_at, script_id,
+           script_execution_id, meta, drift_policy
+    FROM overlays;
+CREATE TEMP TABLE overlay_ops_saved AS SELECT * FROM overlay_ops;
+DELETE FROM overlay_ops;
+DROP TABLE overlays;
+ALTER TABLE overlays_new RENAME TO overlays;
+CREATE INDEX IF NOT EXISTS idx_overlays_status ON overlays (status);
+INSERT INTO overlay_ops SELECT * FROM overlay_ops_saved;
+DROP TABLE overlay_ops_saved;
+",
+    },
+    Migration {
+        version: 20,
+        description: "bundle idempotency keys",
+        sql: "
+CREATE TABLE IF NOT EXISTS bundle_idempotency_keys (
+    actor_id BLOB NOT NULL CHECK (length(actor_id) = 32),
+    idempotency_key TEXT NOT NULL,
+    bundle_id BLOB NOT NULL REFERENCES bundles(bundle_id),
+    PRIMARY KEY (actor_id, idempotency_key)
+);
+",
+    },
+    Migration {
+        version: 21,
+        description: "persisted clock state",
+        sql: "
+CREATE TABLE IF NOT EXISTS clock_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    last_hlc BLOB NOT NULL CHECK (length(last_hlc) = 12)
+);
+",
+    },
+    Migration {
+        version: 22,
+        description: "placeholder entities",
+        sql: "
+ALTER TABLE entities ADD COLUMN placeholder INTEGER NOT NULL DEFAULT 0;
+",
+    },
+    Migration {
+        version: 23,
+        description: "workspaces",
+        // Tables keyed by random ids keep their keys (an id belongs to one
+        // workspace); tables keyed by actor, type name or a fixed row are
+        // rebuilt keyed per workspace. Indexes that enumerate lead with the
+        // workspace so one project's queries don't scan the others.
+        sql: "
+ALTER TABLE oplog ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE bundles ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE entities ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE fields ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE facets ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE edges ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE edge_properties ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE conflicts ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE conflict_values ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE overlays ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE overlay_ops ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE bundle_labels ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE pending_bundles ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE untrusted_bundles ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE bundle_tags ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE bundle_sessions ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE field_mappings ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE webhooks ADD COLUMN workspace_id TEXT NOT NULL DEFAULT 'default';
+
+DROP INDEX IF EXISTS idx_oplog_canonical_order;
+DROP INDEX IF EXISTS idx_oplog_actor_hlc;
+DROP INDEX IF EXISTS idx_bundles_hlc;
+DROP INDEX IF EXISTS idx_bundles_actor;
+DROP INDEX IF EXISTS idx_bundles_type;
+DROP INDEX IF EXISTS idx_entities_active;
+DROP INDEX IF EXISTS idx_entities_deleted;
+DROP INDEX IF EXISTS idx_fields_key_value;
+DROP INDEX IF EXISTS idx_facets_type;
+DROP INDEX IF EXISTS idx_facets_type_detached;
+DROP INDEX IF EXISTS idx_edges_type;
+DROP INDEX IF EXISTS idx_conflicts_status;
+DROP INDEX IF EXISTS idx_overlays_status;
+DROP INDEX IF EXISTS idx_pending_bundles_hlc;
+DROP INDEX IF EXISTS idx_untrusted_bundles_actor;
+CREATE INDEX idx_oplog_canonical_order ON oplog (workspace_id, hlc, op_id);
+CREATE INDEX idx_oplog_actor_hlc ON oplog (workspace_id, actor_id, hlc);
+CREATE INDEX idx_bundles_hlc ON bundles (workspace_id, hlc);
+CREATE INDEX idx_bundles_actor ON bundles (workspace_id, actor_id, hlc);
+CREATE INDEX idx_bundles_type ON bundles (workspace_id, bundle_type, hlc);
+CREATE INDEX idx_entities_active ON entities (workspace_id, created_at) WHERE deleted_at IS NULL AND redirect_to IS NULL;
+CREATE INDEX idx_entities_deleted ON entities (workspace_id, deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX idx_fields_key_value ON fields (workspace_id, field_key, value);
+CREATE INDEX idx_facets_type ON facets (workspace_id, facet_type) WHERE detached_at IS NULL;
+CREATE INDEX idx_facets_type_detached ON facets (workspace_id, facet_type, detached_at);
+CREATE INDEX idx_edges_type ON edges (workspace_id, edge_type) WHERE deleted_at IS NULL;
+CREATE INDEX idx_conflicts_status ON conflicts (workspace_id, status);
+CREATE INDEX idx_overlays_status ON overlays (workspace_id, status);
+CREATE INDEX idx_pending_bundles_hlc ON pending_bundles (workspace_id, hlc);
+CREATE INDEX idx_untrusted_bundles_actor ON untrusted_bundles (workspace_id, actor_id, hlc);
+CREATE INDEX idx_bundle_tags_workspace ON bundle_tags (workspace_id, tag);
+
+CREATE TABLE actors_new (
+    workspace_id TEXT NOT NULL DEFAULT 'default',
+    actor_id BLOB NOT NULL CHECK (length(actor_id) = 32),
+    display_name TEXT,
+    first_seen_at BLOB NOT NULL CHECK (length(first_seen_at) = 12),
+    PRIMARY KEY (workspace_id, actor_id)
+);
+INSERT INTO actors_new (actor_id, display_name, first_seen_at) SELECT actor_id, display_name, first_seen_at FROM actors;
+DROP TABLE actors;
+ALTER TABLE actors_new RENAME TO actors;
+
+CREATE TABLE vector_clock_new (
+    workspace_id TEXT NOT NULL DEFAULT 'default',
+    actor_id BLOB NOT NULL CHECK (length(actor_id) = 32),
+    max_hlc BLOB NOT NULL CHECK (length(max_hlc) = 12),
+    PRIMARY KEY (workspace_id, actor_id)
+);
+INSERT INTO vector_clock_new (actor_id, max_hlc) SELECT actor_id, max_hlc FROM vector_clock;
+DROP TABLE vector_clock;
+ALTER TABLE vector_clock_new RENAME TO vector_clock;
+
+CREATE TABLE peers_new (
+    workspace_id TEXT NOT NULL DEFAULT 'default',
+    peer_id BLOB NOT NULL CHECK (length(peer_id) = 32),
+    display_name TEXT,
+    added_at INTEGER NOT NULL DEFAULT (CAST(unixepoch('now','subsec') * 1000 AS INTEGER)),
+    PRIMARY KEY (workspace_id, peer_id)
+);
+CREATE TABLE peer_sync_state_new (
+    workspace_id TEXT NOT NULL DEFAULT 'default',
+    peer_id BLOB NOT NULL CHECK (length(peer_id) = 32),
+    acked_vector_clock BLOB NOT NULL,
+    last_synced_at INTEGER NOT NULL,
+    PRIMARY KEY (workspace_id, peer_id),
+    FOREIGN KEY (workspace_id, peer_id) REFERENCES peers_new(workspace_id, peer_id) ON DELETE CASCADE
+);
+INSERT INTO peers_new (peer_id, display_name, added_at) SELECT peer_id, display_name, added_at FROM peers;
+INSERT INTO peer_sync_state_new (peer_id, acked_vector_clock, last_synced_at)
+    SELECT peer_id, acked_vector_clock, last_synced_at FROM peer_sync_state;
+DROP TABLE peer_sync_state;
+DROP TABLE peers;
+ALTER TABLE peers_new RENAME TO peers;
+ALTER TABLE peer_sync_state_new RENAME TO peer_sync_state;
+
+CREATE TABLE actor_trust_new (
+    workspace_id TEXT NOT NULL DEFAULT 'default',
+    actor_id BLOB NOT NULL CHECK (length(actor_id) = 32),
+    state TEXT NOT NULL CHECK (state IN ('trusted', 'pending', 'revoked')),
+    updated_at INTEGER NOT NULL DEFAULT (CAST(unixepoch('now','subsec') * 1000 AS INTEGER)),
+    PRIMARY KEY (workspace_id, actor_id)
+);
+INSERT INTO actor_trust_new (actor_id, state, updated_at) SELECT actor_id, state, updated_at FROM actor_trust;
+DROP TABLE actor_trust;
+ALTER TABLE actor_trust_new RENAME TO actor_trust;
+
+CREATE TABLE materialization_state_new (
+    workspace_id TEXT PRIMARY KEY NOT NULL,
+    watermark BLOB NOT NULL CHECK (length(watermark) = 12)
+);
+INSERT INTO materialization_state_new (workspace_id, watermark) SELECT 'default', watermark FROM materialization_state;
+DROP TABLE materialization_state;
+ALTER TABLE materialization_state_new RENAME TO materialization_state;
+
+CREATE TABLE quarantined_bundles_new (
+    quarantine_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workspace_id TEXT NOT NULL DEFAULT 'default',
+    digest BLOB NOT NULL CHECK (length(digest) = 32),
+    bundle_id BLOB CHECK (length(bundle_id) = 16),
+    actor_id BLOB CHECK (length(actor_id) = 32),
+    raw BLOB NOT NULL,
+    reason TEXT NOT NULL,
+    received_at INTEGER NOT NULL DEFAULT (CAST(unixepoch('now','subsec') * 1000 AS INTEGER)),
+    UNIQUE (workspace_id, digest)
+);
+INSERT INTO quarantined_bundles_new (quarantine_id, digest, bundle_id, actor_id, raw, reason, received_at)
+    SELECT quarantine_id, digest, bundle_id, actor_id, raw, reason, received_at FROM quarantined_bundles;
+DROP TABLE quarantined_bundles;
+ALTER TABLE quarantined_bundles_new RENAME TO quarantined_bundles;
+
+CREATE TABLE unique_edge_types_new (
+    workspace_id TEXT NOT NULL DEFAULT 'default',
+    edge_type TEXT NOT NULL,
+    PRIMARY KEY (workspace_id, edge_type)
+);
+INSERT INTO unique_edge_types_new (edge_type) SELECT edge_type FROM unique_edge_types;
+DROP TABLE unique_edge_types;
+ALTER TABLE unique_edge_types_new RENAME TO unique_edge_types;
+
+CREATE TABLE facet_fields_new (
+    workspace_id TEXT NOT NULL DEFAULT 'default',
+    facet_type TEXT NOT NULL,
+    field_key TEXT NOT NULL,
+    PRIMARY KEY (workspace_id, facet_type, field_key)
+);
+INSERT INTO facet_fields_new (facet_type, field_key) SELECT facet_type, field_key FROM facet_fields;
+DROP TABLE facet_fields;
+ALTER TABLE facet_fields_new RENAME TO facet_fields;
+
+CREATE TABLE bundle_idempotency_keys_new (
+    workspace_id TEXT NOT NULL DEFAULT 'default',
+    actor_id BLOB NOT NULL CHECK (length(actor_id) = 32),
+    idempotency_key TEXT NOT NULL,
+    bundle_id BLOB NOT NULL REFERENCES bundles(bundle_id),
+    PRIMARY KEY (workspace_id, actor_id, idempotency_key)
+);
+INSERT INTO bundle_idempotency_keys_new (actor_id, idempotency_key, bundle_id)
+    SELECT actor_id, idempotency_key, bundle_id FROM bundle_idempotency_keys;
+DROP TABLE bundle_idempotency_keys;
+ALTER TABLE bundle_idempotency_keys_new RENAME TO bundle_idempotency_keys;
+
+CREATE TABLE clock_state_new (
+    workspace_id TEXT PRIMARY KEY NOT NULL,
+    last_hlc BLOB NOT NULL CHECK (length(last_hlc) = 12)
+);
+INSERT INTO clock_state_new (workspace_id, last_hlc) SELECT 'default', last_hlc FROM clock_state;
+DROP TABLE clock_state;
+ALTER TABLE clock_state_new RENAME TO clock_state;
+",
+    },
+    Migration {
+        version: 24,
+        description: "materialization watermark as oplog position",
+        // An HLC watermark can't say which ops arrived after it (synced ops
+        // may be older), so existing checkpoints are dropped and the next
+        // recovery replays everything.
+        sql: "
+DROP TABLE materialization_state;
+CREATE TABLE materialization_state (
+    workspace_id TEXT PRIMARY KEY NOT NULL,
+    seq INTEGER NOT NULL
+);
+",
+    },
+    Migration {
+        version: 25,
+        description: "oplog indexed by the edge whose properties an op writes",
+        sql: "
+ALTER TABLE oplog ADD COLUMN edge_id BLOB;
+CREATE INDEX idx_oplog_edge ON oplog (workspace_id, edge_id, hlc) WHERE edge_id IS NOT NULL;
+",
+    },
+];
+
+#[cfg(feature = "sqlite")]
+const BASELINE_SQL: &str = "
 CREATE TABLE IF NOT EXISTS schema_version (
     version INTEGER PRIMARY KEY,
     applied_at INTEGER NOT NULL
@@ -1,17 +1,28 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
 
-use rusqlite::Connection;
+use rusqlite::backup::Progress;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Connection, DatabaseName, OpenFlags};
 
 use openprod_core::{
+    digest::HlcRange,
     field_value::FieldValue,
     hlc::Hlc,
     ids::*,
-    operations::{Bundle, BundleType, Operation, OperationPayload},
+    operations::{Bundle, BundleType, Operation, OperationPayload, RawBundle},
     vector_clock::VectorClock,
 };
 
 use crate::error::StorageError;
-use crate::traits::{ConflictRecord, ConflictStatus, ConflictValue, EdgeRecord, EntityRecord, FacetRecord, Storage};
+use crate::schema::DEFAULT_WORKSPACE;
+use crate::traits::{
+    Aggregate, ActorRecord, ActorStore, ConflictRecord, ConflictStatus, ConflictTriage, ConflictValue, Deletion, EdgeFilter, EdgeRecord,
+    EntityFields, EntityOrder, EntityPage, EntityPageCursor, EntityRecord, FacetRecord, FieldMappingRecord, LabelStore, MaterializationStore, MaterializeProgress, OverlayOrigin, OverlayStore, PeerRecord,
+    PeerStore, PendingBundleRecord, PendingStore, QuarantineStore, QuarantinedBundle, Storage, Transactional,
+    TrustState, TrustStore, WebhookRecord, WebhookStore, EDGE_OP_TYPES, check_bundle_ops, decode_preserved_values, field_sort_key, indexed_meta,
+};
 
 /// Convert Vec<u8> to fixed-size array with proper error handling.
 fn to_array<const N: usize>(v: Vec<u8>, label: &str) -> Result<[u8; N], StorageError> {
@@ -19,7 +30,9 @@ fn to_array<const N: usize>(v: Vec<u8>, label: &str) -> Result<[u8; N], StorageE
         .map_err(|_| StorageError::Serialization(format!("invalid {label} length")))
 }
 
-type RawEdgeRow = (Vec<u8>, String, Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>, bool);
+type RawQuarantineRow = (i64, Option<Vec<u8>>, Option<Vec<u8>>, Vec<u8>, String, i64);
+
+type RawEdgeRow = (Vec<u8>, String, Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>, bool, Vec<u8>);
 
 fn extract_edge_row(row: &rusqlite::Row) -> rusqlite::Result<RawEdgeRow> {
     Ok((
@@ -30,11 +43,21 @@ fn extract_edge_row(row: &rusqlite::Row) -> rusqlite::Result<RawEdgeRow> {
         row.get(4)?,
         row.get(5)?,
         row.get(6)?,
+        row.get(7)?,
     ))
 }
 
 fn parse_edge_row(raw: RawEdgeRow) -> Result<EdgeRecord, StorageError> {
-    let (edge_id_bytes, edge_type, source_id_bytes, target_id_bytes, created_at_bytes, created_by_bytes, deleted) = raw;
+    let (
+        edge_id_bytes,
+        edge_type,
+        source_id_bytes,
+        target_id_bytes,
+        created_at_bytes,
+        created_by_bytes,
+        deleted,
+        created_in_bundle_bytes,
+    ) = raw;
     Ok(EdgeRecord {
         edge_id: EdgeId::from_bytes(to_array::<16>(edge_id_bytes, "edge_id")?),
         edge_type,
@@ -42,196 +65,455 @@ fn parse_edge_row(raw: RawEdgeRow) -> Result<EdgeRecord, StorageError> {
         target_id: EntityId::from_bytes(to_array::<16>(target_id_bytes, "target_id")?),
         created_at: Hlc::from_bytes(&to_array::<12>(created_at_bytes, "created_at")?),
         created_by: ActorId::from_bytes(to_array::<32>(created_by_bytes, "created_by")?),
+        created_in_bundle: BundleId::from_bytes(to_array::<16>(created_in_bundle_bytes, "created_in_bundle")?),
         deleted,
     })
 }
 
+type RawDeletion = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+fn extract_deletion(row: &rusqlite::Row, first: usize) -> rusqlite::Result<RawDeletion> {
+    Ok((row.get(first)?, row.get(first + 1)?, row.get(first + 2)?))
+}
+
+fn parse_deletion(raw: RawDeletion) -> Result<Deletion, StorageError> {
+    let (deleted_at_bytes, deleted_by_bytes, deleted_in_bundle_bytes) = raw;
+    Ok(Deletion {
+        deleted_at: Hlc::from_bytes(&to_array::<12>(deleted_at_bytes, "deleted_at")?),
+        deleted_by: ActorId::from_bytes(to_array::<32>(deleted_by_bytes, "deleted_by")?),
+        deleted_in_bundle: BundleId::from_bytes(to_array::<16>(deleted_in_bundle_bytes, "deleted_in_bundle")?),
+    })
+}
+
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// Oplog rows per multi-row INSERT in `append_bundle` (9 parameters each,
+/// well under SQLite's bound-parameter limit).
+const OPLOG_INSERT_BATCH: usize = 100;
+
+/// One workspace of a SQLite database. Every table carries a `workspace_id`
+/// and every statement is scoped to this storage's, so several independent
+/// workspaces (oplogs, clocks, peers, ...) can share one file.
 pub struct SqliteStorage {
     conn: Connection,
+    workspace: String,
 }
 
 impl SqliteStorage {
     pub fn open(path: &str) -> Result<Self, StorageError> {
-        let conn = Connection::open(path)?;
-        crate::schema::init_schema(&conn)?;
-        Ok(Self { conn })
+        Self::open_workspace(path, DEFAULT_WORKSPACE)
+    }
+
+    /// Open (or create) the database at `path`, scoped to `workspace`. A
+    /// workspace exists once something is written to it.
+    pub fn open_workspace(path: &str, workspace: &str) -> Result<Self, StorageError> {
+        let storage = Self::with_connection(Connection::open(path)?, workspace)?;
+        crate::schema::init_schema(&storage.conn)?;
+        Ok(storage)
     }
 
     pub fn open_in_memory() -> Result<Self, StorageError> {
-        let conn = Connection::open_in_memory()?;
-        crate::schema::init_schema(&conn)?;
-        Ok(Self { conn })
+        let storage = Self::with_connection(Connection::open_in_memory()?, DEFAULT_WORKSPACE)?;
+        crate::schema::init_schema(&storage.conn)?;
+        Ok(storage)
     }
 
-    /// Get the source actor, HLC, op_id, and the creator vector clock of the bundle
-    /// that last wrote a particular field. Used for conflict detection.
-    #[allow(clippy::type_complexity)]
-    pub fn get_field_source_bundle_vc(
-        &self,
-        entity_id: EntityId,
-        field_key: &str,
-    ) -> Result<Option<(ActorId, Hlc, OpId, Option<VectorClock>)>, StorageError> {
-        let result = self.conn.query_row(
-            "SELECT f.source_actor, f.updated_at, f.source_op, b.creator_vector_clock
-             FROM fields f
-             JOIN oplog o ON o.op_id = f.source_op
-             JOIN bundles b ON b.bundle_id = o.bundle_id
-             WHERE f.entity_id = ?1 AND f.field_key = ?2",
-            rusqlite::params![entity_id.as_bytes().as_slice(), field_key],
-            |row| {
-                let actor_bytes: Vec<u8> = row.get(0)?;
-                let hlc_bytes: Vec<u8> = row.get(1)?;
-                let op_id_bytes: Vec<u8> = row.get(2)?;
-                let vc_bytes: Option<Vec<u8>> = row.get(3)?;
-                Ok((actor_bytes, hlc_bytes, op_id_bytes, vc_bytes))
+    /// The workspace this storage reads and writes.
+    pub fn workspace(&self) -> &str {
+        &self.workspace
+    }
+
+    /// Workspaces in the database that hold at least one bundle, sorted.
+    pub fn workspaces(&self) -> Result<Vec<String>, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT DISTINCT workspace_id FROM bundles ORDER BY workspace_id")?;
+        let workspaces = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<_>, _>>()?;
+        Ok(workspaces)
+    }
+
+    /// Path of the database file, or `None` for an in-memory database.
+    pub fn path(&self) -> Option<&str> {
+        self.conn.path().filter(|path| !path.is_empty())
+    }
+
+    /// Set a `PRAGMA` on the connection, e.g. `set_pragma("synchronous", "OFF")`.
+    pub fn set_pragma(&self, name: &str, value: &str) -> Result<(), StorageError> {
+        self.conn.pragma_update(None, name, value)?;
+        Ok(())
+    }
+
+    /// Current value of a `PRAGMA`, as text.
+    pub fn pragma(&self, name: &str) -> Result<String, StorageError> {
+        Ok(self.conn.pragma_query_value(None, name, |row| {
+            Ok(match row.get_ref(0)? {
+                rusqlite::types::ValueRef::Integer(i) => i.to_string(),
+                rusqlite::types::ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned(),
+                other => format!("{other:?}"),
+            })
+        })?)
+    }
+
+    fn with_connection(conn: Connection, workspace: &str) -> Result<Self, StorageError> {
+        // Room for every statement materialization reuses, plus the common queries
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        // workspace(): the workspace this connection is scoped to, which every
+        // statement filters on and writes
+        let name = workspace.to_string();
+        conn.create_scalar_function(
+            "workspace",
+            0,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            move |_| Ok(name.clone()),
+        )?;
+        // field_number(value): a stored field value as an INTEGER or REAL, NULL
+        // for anything else, so aggregates run over decoded values in SQL
+        conn.create_scalar_function(
+            "field_number",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| {
+                let number = match ctx.get_raw(0) {
+                    rusqlite::types::ValueRef::Blob(bytes) => match FieldValue::from_msgpack(bytes) {
+                        Ok(FieldValue::Integer(n)) => SqlValue::Integer(n),
+                        Ok(FieldValue::Float(x)) => SqlValue::Real(x),
+                        _ => SqlValue::Null,
+                    },
+                    _ => SqlValue::Null,
+                };
+                Ok(number)
             },
-        );
-        match result {
-            Ok((actor_bytes, hlc_bytes, op_id_bytes, vc_bytes)) => {
-                let actor = ActorId::from_bytes(to_array::<32>(actor_bytes, "source_actor")?);
-                let hlc = Hlc::from_bytes(&to_array::<12>(hlc_bytes, "updated_at")?);
-                let op_id = OpId::from_bytes(to_array::<16>(op_id_bytes, "source_op")?);
-                let vc = match vc_bytes {
-                    Some(bytes) => Some(VectorClock::from_msgpack(&bytes)
-                        .map_err(|e| StorageError::Serialization(e.to_string()))?),
-                    None => None,
+        )?;
+        // field_sort_key(value): the `field_sort_key` of a stored field value,
+        // so pages can be ordered by a field in SQL
+        conn.create_scalar_function(
+            "field_sort_key",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| {
+                let value = match ctx.get_raw(0) {
+                    rusqlite::types::ValueRef::Blob(bytes) => FieldValue::from_msgpack(bytes).ok(),
+                    _ => None,
                 };
-                Ok(Some((actor, hlc, op_id, vc)))
+                Ok(field_sort_key(value.as_ref()))
+            },
+        )?;
+        Ok(Self { conn, workspace: workspace.to_string() })
+    }
+
+    /// Open (or create) a SQLCipher-encrypted database. `key` is a passphrase, or a
+    /// raw key written as `x'<64 hex digits>'`. Fails with `WrongKey` if the key
+    /// doesn't decrypt the file and `Corrupt` if pages fail authentication.
+    #[cfg(feature = "sqlcipher")]
+    pub fn open_encrypted(path: &str, key: &str) -> Result<Self, StorageError> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "key", key)?;
+        // The key is only checked on first read; a wrong key makes the file look
+        // like garbage rather than failing the pragma
+        let readable = conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0));
+        match readable {
+            Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::NotADatabase => {
+                return Err(StorageError::WrongKey);
             }
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(StorageError::Sqlite(e)),
+            Err(e) => return Err(e.into()),
+            Ok(_) => {}
         }
+        let storage = Self::with_connection(conn, DEFAULT_WORKSPACE)?;
+        storage.cipher_integrity_check()?;
+        crate::schema::init_schema(&storage.conn)?;
+        Ok(storage)
     }
 
-    /// Expose the connection for use in transactions from Engine.
+    /// Re-encrypt the database under `new_key`. The old key stops working.
+    #[cfg(feature = "sqlcipher")]
+    pub fn rekey(&mut self, new_key: &str) -> Result<(), StorageError> {
+        self.conn.pragma_update(None, "rekey", new_key)?;
+        Ok(())
+    }
+
+    /// Verify the HMAC of every page; any failure means the file was corrupted
+    /// or tampered with (as opposed to opened with the wrong key).
+    #[cfg(feature = "sqlcipher")]
+    fn cipher_integrity_check(&self) -> Result<(), StorageError> {
+        let mut stmt = self.conn.prepare("PRAGMA cipher_integrity_check")?;
+        let problems = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(StorageError::Corrupt(problems.join("; ")))
+        }
+    }
+
+    /// Expose the underlying connection for SQLite-specific work.
     pub fn conn(&self) -> &Connection {
         &self.conn
     }
+}
 
-    /// Get the field value bytes from an oplog operation by op_id.
-    /// Returns Some(bytes) for SetField/ResolveConflict with value, None for ClearField/tombstone.
-    pub fn get_op_field_value(&self, op_id: OpId) -> Result<Option<Vec<u8>>, StorageError> {
-        let result = self.conn.query_row(
-            "SELECT payload FROM oplog WHERE op_id = ?1",
-            rusqlite::params![op_id.as_bytes().as_slice()],
-            |row| {
-                let payload_bytes: Vec<u8> = row.get(0)?;
-                Ok(payload_bytes)
-            },
-        );
-        match result {
-            Ok(payload_bytes) => {
-                let payload = OperationPayload::from_msgpack(&payload_bytes)?;
-                match payload {
-                    OperationPayload::SetField { value, .. } => {
-                        let bytes = value.to_msgpack()
-                            .map_err(|e| StorageError::Serialization(e.to_string()))?;
-                        Ok(Some(bytes))
-                    }
-                    OperationPayload::ClearField { .. } => Ok(None),
-                    OperationPayload::ResolveConflict { chosen_value: Some(v), .. } => {
-                        let bytes = v.to_msgpack()
-                            .map_err(|e| StorageError::Serialization(e.to_string()))?;
-                        Ok(Some(bytes))
-                    }
-                    OperationPayload::ResolveConflict { chosen_value: None, .. } => Ok(None),
-                    _ => Ok(None),
-                }
-            }
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(StorageError::Sqlite(e)),
-        }
+impl Transactional for SqliteStorage {
+    fn begin_transaction(&self) -> Result<(), StorageError> {
+        Ok(self.conn.execute_batch("BEGIN IMMEDIATE")?)
+    }
+
+    fn commit_transaction(&self) -> Result<(), StorageError> {
+        Ok(self.conn.execute_batch("COMMIT")?)
+    }
+
+    fn rollback_transaction(&self) -> Result<(), StorageError> {
+        Ok(self.conn.execute_batch("ROLLBACK")?)
     }
 }
 
-impl SqliteStorage {
-    pub fn rebuild_from_oplog(&mut self) -> Result<u64, StorageError> {
+/// Ops between `materialize_from` progress reports.
+const PROGRESS_INTERVAL: u64 = 1000;
+
+impl MaterializationStore for SqliteStorage {
+    fn rebuild_from_oplog(&mut self) -> Result<u64, StorageError> {
         self.conn.execute_batch("SAVEPOINT sp_rebuild")?;
 
         let result = (|| -> Result<u64, StorageError> {
             // Clear all materialized tables (children before parents to respect FK constraints)
             self.conn.execute_batch(
-                "DELETE FROM conflict_values;
-                 DELETE FROM conflicts;
-                 DELETE FROM edge_properties;
-                 DELETE FROM fields;
-                 DELETE FROM facets;
-                 DELETE FROM edges;
-                 DELETE FROM entities;
-                 DELETE FROM actors;
-                 DELETE FROM vector_clock;",
+                "DELETE FROM conflict_values WHERE workspace_id = workspace();
+                 DELETE FROM conflicts WHERE workspace_id = workspace();
+                 DELETE FROM edge_properties WHERE workspace_id = workspace();
+                 DELETE FROM fields WHERE workspace_id = workspace();
+                 DELETE FROM facets WHERE workspace_id = workspace();
+                 DELETE FROM edges WHERE workspace_id = workspace();
+                 DELETE FROM entities WHERE workspace_id = workspace();
+                 DELETE FROM field_mappings WHERE workspace_id = workspace();
+                 DELETE FROM actors WHERE workspace_id = workspace();
+                 DELETE FROM vector_clock WHERE workspace_id = workspace();
+                 DELETE FROM materialization_state WHERE workspace_id = workspace();",
             )?;
+            self.replay_ops_from(Hlc::new(0, 0), &mut |_| {})
+        })();
+
+        match result {
+            Ok(count) => {
+                self.conn.execute_batch("RELEASE sp_rebuild")?;
+                Ok(count)
+            }
+            Err(e) => {
+                let _ = self.conn.execute_batch("ROLLBACK TO sp_rebuild; RELEASE sp_rebuild");
+                Err(e)
+            }
+        }
+    }
+
+    fn materialize_from(
+        &mut self,
+        from: Hlc,
+        progress: &mut dyn FnMut(MaterializeProgress),
+    ) -> Result<u64, StorageError> {
+        self.conn.execute_batch("SAVEPOINT sp_materialize")?;
 
-            // Read all ops in canonical order
-            let mut op_stmt = self.conn.prepare(
-                "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog ORDER BY hlc, op_id",
+        let result = (|| -> Result<u64, StorageError> {
+            let from_bytes = from.to_bytes();
+            let from_param = &from_bytes[..];
+            // Rows created at or after `from` go entirely (children first)
+            self.conn.execute(
+                "DELETE FROM edge_properties WHERE workspace_id = workspace() AND (updated_at >= ?1 OR edge_id IN (
+                     SELECT edge_id FROM edges WHERE workspace_id = workspace() AND (created_at >= ?1
+                         OR source_id IN (SELECT entity_id FROM entities WHERE workspace_id = workspace() AND created_at >= ?1)
+                         OR target_id IN (SELECT entity_id FROM entities WHERE workspace_id = workspace() AND created_at >= ?1))))",
+                [from_param],
             )?;
-            let ops: Vec<Operation> = op_stmt
-                .query_map([], |row| {
-                    read_op(row).map_err(|e| match e {
-                        StorageError::Sqlite(sq) => sq,
-                        other => rusqlite::Error::FromSqlConversionFailure(
-                            0,
-                            rusqlite::types::Type::Blob,
-                            Box::new(OpaqueStorageError(other.to_string())),
-                        ),
-                    })
-                })?
-                .collect::<Result<Vec<_>, _>>()?;
-            drop(op_stmt);
+            self.conn.execute(
+                "DELETE FROM edges WHERE workspace_id = workspace() AND (created_at >= ?1
+                     OR source_id IN (SELECT entity_id FROM entities WHERE workspace_id = workspace() AND created_at >= ?1)
+                     OR target_id IN (SELECT entity_id FROM entities WHERE workspace_id = workspace() AND created_at >= ?1))",
+                [from_param],
+            )?;
+            self.conn.execute(
+                "DELETE FROM fields WHERE workspace_id = workspace() AND (updated_at >= ?1
+                     OR entity_id IN (SELECT entity_id FROM entities WHERE workspace_id = workspace() AND created_at >= ?1))",
+                [from_param],
+            )?;
+            self.conn.execute(
+                "DELETE FROM facets WHERE workspace_id = workspace() AND (attached_at >= ?1
+                     OR entity_id IN (SELECT entity_id FROM entities WHERE workspace_id = workspace() AND created_at >= ?1))",
+                [from_param],
+            )?;
+            self.conn.execute("DELETE FROM entities WHERE workspace_id = workspace() AND created_at >= ?1", [from_param])?;
+            self.conn.execute("DELETE FROM field_mappings WHERE workspace_id = workspace() AND confirmed_at >= ?1", [from_param])?;
+            // Later deletions and detaches on older rows are undone
+            self.conn.execute(
+                "UPDATE entities SET deleted_at = NULL, deleted_by = NULL, deleted_in_bundle = NULL WHERE workspace_id = workspace() AND deleted_at >= ?1",
+                [from_param],
+            )?;
+            self.conn.execute(
+                "UPDATE edges SET deleted_at = NULL, deleted_by = NULL, deleted_in_bundle = NULL WHERE workspace_id = workspace() AND deleted_at >= ?1",
+                [from_param],
+            )?;
+            self.conn.execute(
+                "UPDATE facets SET detached_at = NULL, detached_by = NULL, detached_in_bundle = NULL, preserve_values = NULL
+                 WHERE workspace_id = workspace() AND detached_at >= ?1",
+                [from_param],
+            )?;
+            self.replay_ops_from(from, progress)
+        })();
 
-            let op_count = ops.len() as u64;
+        match result {
+            Ok(count) => {
+                self.conn.execute_batch("RELEASE sp_materialize")?;
+                Ok(count)
+            }
+            Err(e) => {
+                let _ = self.conn.execute_batch("ROLLBACK TO sp_materialize; RELEASE sp_materialize");
+                Err(e)
+            }
+        }
+    }
 
-            // Group ops by bundle_id and replay
-            // We need bundle info for materialization, so read bundles
-            let mut bundle_cache: std::collections::HashMap<[u8; 16], Bundle> =
-                std::collections::HashMap::new();
+    fn materialization_watermark(&self) -> Result<Option<u64>, StorageError> {
+        let result = self.conn.query_row(
+            "SELECT seq FROM materialization_state WHERE workspace_id = workspace()",
+            [],
+            |row| row.get::<_, i64>(0),
+        );
+        match result {
+            Ok(seq) => Ok(Some(seq as u64)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
 
-            for op in &ops {
-                let bundle_key = *op.bundle_id.as_bytes();
-                if let std::collections::hash_map::Entry::Vacant(e) = bundle_cache.entry(bundle_key) {
-                    let bundle = read_bundle(&self.conn, op.bundle_id)?;
-                    e.insert(bundle);
-                }
-                let bundle = &bundle_cache[&bundle_key];
+    fn checkpoint_materialization(&mut self) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT INTO materialization_state (workspace_id, seq)
+             SELECT workspace(), MAX(rowid) FROM oplog WHERE workspace_id = workspace() HAVING COUNT(*) > 0
+             ON CONFLICT(workspace_id) DO UPDATE SET seq = excluded.seq",
+            [],
+        )?;
+        Ok(())
+    }
 
-                materialize_op(&self.conn, op, bundle)?;
+    fn add_unique_edge_type(&mut self, edge_type: &str) -> Result<(), StorageError> {
+        let added = self.conn.execute(
+            "INSERT INTO unique_edge_types (workspace_id, edge_type) VALUES (workspace(), ?1) ON CONFLICT(workspace_id, edge_type) DO NOTHING",
+            [edge_type],
+        )?;
+        if added > 0 {
+            self.conn.execute(
+                "UPDATE edges SET deleted_at = created_at, deleted_by = created_by, deleted_in_bundle = created_in_bundle
+                 WHERE workspace_id = workspace() AND deleted_at IS NULL AND edge_type = ?1
+                   AND EXISTS (
+                       SELECT 1 FROM edges AS earlier
+                       WHERE earlier.source_id = edges.source_id AND earlier.target_id = edges.target_id
+                         AND earlier.edge_type = edges.edge_type
+                         AND (earlier.created_at, earlier.edge_id) < (edges.created_at, edges.edge_id))",
+                [edge_type],
+            )?;
+        }
+        Ok(())
+    }
 
-                // Track actor
-                self.conn.execute(
-                    "INSERT OR IGNORE INTO actors (actor_id, display_name, first_seen_at) VALUES (?1, NULL, ?2)",
-                    rusqlite::params![
-                        op.actor_id.as_bytes().as_slice(),
-                        &op.hlc.to_bytes()[..],
-                    ],
-                )?;
+    fn list_unique_edge_types(&self) -> Result<Vec<String>, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT edge_type FROM unique_edge_types WHERE workspace_id = workspace() ORDER BY edge_type")?;
+        let types = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<String>, _>>()?;
+        Ok(types)
+    }
 
-                // Update vector clock
+    fn set_facet_fields(&mut self, facet_type: &str, field_keys: &[&str]) -> Result<(), StorageError> {
+        self.conn.execute_batch("SAVEPOINT sp_facet_fields")?;
+        let result = (|| -> Result<(), StorageError> {
+            self.conn.execute("DELETE FROM facet_fields WHERE workspace_id = workspace() AND facet_type = ?1", [facet_type])?;
+            for field_key in field_keys {
                 self.conn.execute(
-                    "INSERT INTO vector_clock (actor_id, max_hlc) VALUES (?1, ?2)
-                     ON CONFLICT(actor_id) DO UPDATE SET max_hlc = excluded.max_hlc
-                     WHERE excluded.max_hlc > vector_clock.max_hlc",
-                    rusqlite::params![
-                        op.actor_id.as_bytes().as_slice(),
-                        &op.hlc.to_bytes()[..],
-                    ],
+                    "INSERT INTO facet_fields (workspace_id, facet_type, field_key) VALUES (workspace(), ?1, ?2) ON CONFLICT DO NOTHING",
+                    [facet_type, field_key],
                 )?;
             }
-
-            Ok(op_count)
+            Ok(())
         })();
-
         match result {
-            Ok(count) => {
-                self.conn.execute_batch("RELEASE sp_rebuild")?;
-                Ok(count)
-            }
+            Ok(()) => Ok(self.conn.execute_batch("RELEASE sp_facet_fields")?),
             Err(e) => {
-                let _ = self.conn.execute_batch("ROLLBACK TO sp_rebuild; RELEASE sp_rebuild");
+                let _ = self.conn.execute_batch("ROLLBACK TO sp_facet_fields; RELEASE sp_facet_fields");
                 Err(e)
             }
         }
     }
+
+    fn get_facet_fields(&self, facet_type: &str) -> Result<Vec<String>, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT field_key FROM facet_fields WHERE workspace_id = workspace() AND facet_type = ?1 ORDER BY field_key")?;
+        let keys = stmt.query_map([facet_type], |row| row.get(0))?.collect::<Result<Vec<String>, _>>()?;
+        Ok(keys)
+    }
+}
+
+impl SqliteStorage {
+    /// Replay ops at or after `from` in canonical order, then advance the
+    /// watermark to the end of the oplog. Must run inside a savepoint.
+    fn replay_ops_from(
+        &mut self,
+        from: Hlc,
+        progress: &mut dyn FnMut(MaterializeProgress),
+    ) -> Result<u64, StorageError> {
+        let mut op_stmt = self.conn.prepare(
+            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog WHERE workspace_id = workspace() AND hlc >= ?1 ORDER BY hlc, op_id",
+        )?;
+        let ops: Vec<Operation> = op_stmt
+            .query_map([&from.to_bytes()[..]], |row| {
+                read_op(row).map_err(|e| match e {
+                    StorageError::Sqlite(sq) => sq,
+                    other => rusqlite::Error::FromSqlConversionFailure(
+                        0,
+                        rusqlite::types::Type::Blob,
+                        Box::new(OpaqueStorageError(other.to_string())),
+                    ),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(op_stmt);
+
+        let total = ops.len() as u64;
+
+        // Group ops by bundle_id and replay
+        // We need bundle info for materialization, so read bundles
+        let mut bundle_cache: std::collections::HashMap<[u8; 16], Bundle> =
+            std::collections::HashMap::new();
+
+        for (i, op) in ops.iter().enumerate() {
+            let bundle_key = *op.bundle_id.as_bytes();
+            if let std::collections::hash_map::Entry::Vacant(e) = bundle_cache.entry(bundle_key) {
+                let bundle = read_bundle(&self.conn, op.bundle_id)?;
+                e.insert(bundle);
+            }
+            let bundle = &bundle_cache[&bundle_key];
+
+            materialize_op(&self.conn, op, bundle)?;
+
+            // Track actor
+            self.conn.execute(
+                "INSERT OR IGNORE INTO actors (workspace_id, actor_id, display_name, first_seen_at) VALUES (workspace(), ?1, NULL, ?2)",
+                rusqlite::params![
+                    op.actor_id.as_bytes().as_slice(),
+                    &op.hlc.to_bytes()[..],
+                ],
+            )?;
+
+            // Update vector clock
+            self.conn.execute(
+                "INSERT INTO vector_clock (workspace_id, actor_id, max_hlc) VALUES (workspace(), ?1, ?2)
+                 ON CONFLICT(workspace_id, actor_id) DO UPDATE SET max_hlc = excluded.max_hlc
+                 WHERE excluded.max_hlc > vector_clock.max_hlc",
+                rusqlite::params![
+                    op.actor_id.as_bytes().as_slice(),
+                    &op.hlc.to_bytes()[..],
+                ],
+            )?;
+
+            let replayed = i as u64 + 1;
+            if replayed.is_multiple_of(PROGRESS_INTERVAL) || replayed == total {
+                progress(MaterializeProgress { replayed, total });
+            }
+        }
+
+        self.checkpoint_materialization()?;
+        Ok(total)
+    }
 }
 
 fn read_op(row: &rusqlite::Row) -> Result<Operation, StorageError> {
@@ -265,7 +547,7 @@ fn read_op(row: &rusqlite::Row) -> Result<Operation, StorageError> {
 
 fn read_bundle(conn: &Connection, bundle_id: BundleId) -> Result<Bundle, StorageError> {
     conn.query_row(
-        "SELECT bundle_id, actor_id, hlc, bundle_type, op_count, checksum, creates, deletes, meta, signature, creator_vector_clock FROM bundles WHERE bundle_id = ?1",
+        "SELECT bundle_id, actor_id, hlc, bundle_type, op_count, checksum, creates, deletes, meta, signature, creator_vector_clock FROM bundles WHERE bundle_id = ?1 AND workspace_id = workspace()",
         rusqlite::params![bundle_id.as_bytes().as_slice()],
         |row| {
             let bundle_id_bytes: Vec<u8> = row.get(0)?;
@@ -323,6 +605,13 @@ fn read_bundle(conn: &Connection, bundle_id: BundleId) -> Result<Bundle, Storage
     })
 }
 
+/// `Connection::execute` through the statement cache; materialization runs
+/// the same few statements for every op, so re-preparing them dominates
+/// appends of large bundles.
+fn execute_cached(conn: &Connection, sql: &str, params: impl rusqlite::Params) -> rusqlite::Result<usize> {
+    conn.prepare_cached(sql)?.execute(params)
+}
+
 fn materialize_op(
     conn: &Connection,
     op: &Operation,
@@ -333,30 +622,29 @@ fn materialize_op(
             entity_id,
             initial_table,
         } => {
-            let result = conn.execute(
-                "INSERT INTO entities (entity_id, created_at, created_by, created_in_bundle) VALUES (?1, ?2, ?3, ?4)",
+            // A placeholder takes on the creation; any other existing row collides
+            let created = execute_cached(
+                conn,
+                "INSERT INTO entities (workspace_id, entity_id, created_at, created_by, created_in_bundle) VALUES (workspace(), ?1, ?2, ?3, ?4)
+                 ON CONFLICT(entity_id) DO UPDATE SET created_at = excluded.created_at, created_by = excluded.created_by, created_in_bundle = excluded.created_in_bundle, placeholder = 0
+                 WHERE entities.placeholder",
                 rusqlite::params![
                     entity_id.as_bytes().as_slice(),
                     &op.hlc.to_bytes()[..],
                     op.actor_id.as_bytes().as_slice(),
                     bundle.bundle_id.as_bytes().as_slice(),
                 ],
-            );
-            match result {
-                Ok(_) => {}
-                Err(rusqlite::Error::SqliteFailure(err, _))
-                    if err.code == rusqlite::ErrorCode::ConstraintViolation =>
-                {
-                    return Err(StorageError::EntityCollision {
-                        entity_id: entity_id.to_string(),
-                    });
-                }
-                Err(e) => return Err(StorageError::Sqlite(e)),
+            )?;
+            if created == 0 {
+                return Err(StorageError::EntityCollision {
+                    entity_id: entity_id.to_string(),
+                });
             }
 
             if let Some(facet_type) = initial_table {
-                conn.execute(
-                    "INSERT INTO facets (entity_id, facet_type, attached_at, attached_by, attached_in_bundle) VALUES (?1, ?2, ?3, ?4, ?5)",
+                execute_cached(
+                    conn,
+                    "INSERT INTO facets (workspace_id, entity_id, facet_type, attached_at, attached_by, attached_in_bundle) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5)",
                     rusqlite::params![
                         entity_id.as_bytes().as_slice(),
                         facet_type,
@@ -372,8 +660,9 @@ fn materialize_op(
             entity_id,
             cascade_edges,
         } => {
-            conn.execute(
-                "UPDATE entities SET deleted_at = ?1, deleted_by = ?2, deleted_in_bundle = ?3 WHERE entity_id = ?4",
+            execute_cached(
+                conn,
+                "UPDATE entities SET deleted_at = ?1, deleted_by = ?2, deleted_in_bundle = ?3 WHERE entity_id = ?4 AND workspace_id = workspace()",
                 rusqlite::params![
                     &op.hlc.to_bytes()[..],
                     op.actor_id.as_bytes().as_slice(),
@@ -382,8 +671,9 @@ fn materialize_op(
                 ],
             )?;
             for edge_id in cascade_edges {
-                conn.execute(
-                    "UPDATE edges SET deleted_at = ?1, deleted_by = ?2, deleted_in_bundle = ?3 WHERE edge_id = ?4",
+                execute_cached(
+                    conn,
+                    "UPDATE edges SET deleted_at = ?1, deleted_by = ?2, deleted_in_bundle = ?3 WHERE edge_id = ?4 AND workspace_id = workspace()",
                     rusqlite::params![
                         &op.hlc.to_bytes()[..],
                         op.actor_id.as_bytes().as_slice(),
@@ -398,8 +688,10 @@ fn materialize_op(
             entity_id,
             facet_type,
         } => {
-            conn.execute(
-                "INSERT INTO facets (entity_id, facet_type, attached_at, attached_by, attached_in_bundle) VALUES (?1, ?2, ?3, ?4, ?5)
+            insert_placeholder(conn, *entity_id, op, bundle)?;
+            execute_cached(
+                conn,
+                "INSERT INTO facets (workspace_id, entity_id, facet_type, attached_at, attached_by, attached_in_bundle) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5)
                  ON CONFLICT(entity_id, facet_type) DO UPDATE SET attached_at = excluded.attached_at, attached_by = excluded.attached_by, attached_in_bundle = excluded.attached_in_bundle, detached_at = NULL, detached_by = NULL, detached_in_bundle = NULL, preserve_values = NULL",
                 rusqlite::params![
                     entity_id.as_bytes().as_slice(),
@@ -417,8 +709,9 @@ fn materialize_op(
             preserve_values,
         } => {
             if *preserve_values {
-                let mut stmt =
-                    conn.prepare("SELECT field_key, value FROM fields WHERE entity_id = ?1 AND value IS NOT NULL")?;
+                let mut stmt = conn.prepare_cached(
+                    "SELECT field_key, value FROM fields WHERE entity_id = ?1 AND workspace_id = workspace() AND value IS NOT NULL",
+                )?;
                 let fields: Vec<(String, Vec<u8>)> = stmt
                     .query_map(
                         rusqlite::params![entity_id.as_bytes().as_slice()],
@@ -427,8 +720,9 @@ fn materialize_op(
                     .collect::<Result<Vec<_>, _>>()?;
                 let preserved = rmp_serde::to_vec(&fields)
                     .map_err(|e| StorageError::Serialization(e.to_string()))?;
-                conn.execute(
-                    "UPDATE facets SET detached_at = ?1, detached_by = ?2, detached_in_bundle = ?3, preserve_values = ?4 WHERE entity_id = ?5 AND facet_type = ?6",
+                execute_cached(
+                    conn,
+                    "UPDATE facets SET detached_at = ?1, detached_by = ?2, detached_in_bundle = ?3, preserve_values = ?4 WHERE entity_id = ?5 AND facet_type = ?6 AND workspace_id = workspace()",
                     rusqlite::params![
                         &op.hlc.to_bytes()[..],
                         op.actor_id.as_bytes().as_slice(),
@@ -439,8 +733,9 @@ fn materialize_op(
                     ],
                 )?;
             } else {
-                conn.execute(
-                    "UPDATE facets SET detached_at = ?1, detached_by = ?2, detached_in_bundle = ?3 WHERE entity_id = ?4 AND facet_type = ?5",
+                execute_cached(
+                    conn,
+                    "UPDATE facets SET detached_at = ?1, detached_by = ?2, detached_in_bundle = ?3 WHERE entity_id = ?4 AND facet_type = ?5 AND workspace_id = workspace()",
                     rusqlite::params![
                         &op.hlc.to_bytes()[..],
                         op.actor_id.as_bytes().as_slice(),
@@ -460,8 +755,10 @@ fn materialize_op(
             let value_bytes = value
                 .to_msgpack()
                 .map_err(|e| StorageError::Serialization(e.to_string()))?;
-            conn.execute(
-                "INSERT INTO fields (entity_id, field_key, value, source_op, source_actor, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            insert_placeholder(conn, *entity_id, op, bundle)?;
+            execute_cached(
+                conn,
+                "INSERT INTO fields (workspace_id, entity_id, field_key, value, source_op, source_actor, updated_at) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?6)
                  ON CONFLICT(entity_id, field_key) DO UPDATE SET value = excluded.value, source_op = excluded.source_op, source_actor = excluded.source_actor, updated_at = excluded.updated_at
                  WHERE excluded.updated_at > fields.updated_at OR (excluded.updated_at = fields.updated_at AND excluded.source_op > fields.source_op)",
                 rusqlite::params![
@@ -480,8 +777,10 @@ fn materialize_op(
             field_key,
         } => {
             // ClearField writes a tombstone (value = NULL) with LWW guard
-            conn.execute(
-                "INSERT INTO fields (entity_id, field_key, value, source_op, source_actor, updated_at) VALUES (?1, ?2, NULL, ?3, ?4, ?5)
+            insert_placeholder(conn, *entity_id, op, bundle)?;
+            execute_cached(
+                conn,
+                "INSERT INTO fields (workspace_id, entity_id, field_key, value, source_op, source_actor, updated_at) VALUES (workspace(), ?1, ?2, NULL, ?3, ?4, ?5)
                  ON CONFLICT(entity_id, field_key) DO UPDATE SET value = NULL, source_op = excluded.source_op, source_actor = excluded.source_actor, updated_at = excluded.updated_at
                  WHERE excluded.updated_at > fields.updated_at OR (excluded.updated_at = fields.updated_at AND excluded.source_op > fields.source_op)",
                 rusqlite::params![
@@ -501,13 +800,15 @@ fn materialize_op(
             ..
         } => {
             // ResolveConflict materializes like SetField (with value) or ClearField (without)
+            insert_placeholder(conn, *entity_id, op, bundle)?;
             match chosen_value {
                 Some(value) => {
                     let value_bytes = value
                         .to_msgpack()
                         .map_err(|e| StorageError::Serialization(e.to_string()))?;
-                    conn.execute(
-                        "INSERT INTO fields (entity_id, field_key, value, source_op, source_actor, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                    execute_cached(
+                        conn,
+                        "INSERT INTO fields (workspace_id, entity_id, field_key, value, source_op, source_actor, updated_at) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?6)
                          ON CONFLICT(entity_id, field_key) DO UPDATE SET value = excluded.value, source_op = excluded.source_op, source_actor = excluded.source_actor, updated_at = excluded.updated_at
                          WHERE excluded.updated_at > fields.updated_at OR (excluded.updated_at = fields.updated_at AND excluded.source_op > fields.source_op)",
                         rusqlite::params![
@@ -521,8 +822,9 @@ fn materialize_op(
                     )?;
                 }
                 None => {
-                    conn.execute(
-                        "INSERT INTO fields (entity_id, field_key, value, source_op, source_actor, updated_at) VALUES (?1, ?2, NULL, ?3, ?4, ?5)
+                    execute_cached(
+                        conn,
+                        "INSERT INTO fields (workspace_id, entity_id, field_key, value, source_op, source_actor, updated_at) VALUES (workspace(), ?1, ?2, NULL, ?3, ?4, ?5)
                          ON CONFLICT(entity_id, field_key) DO UPDATE SET value = NULL, source_op = excluded.source_op, source_actor = excluded.source_actor, updated_at = excluded.updated_at
                          WHERE excluded.updated_at > fields.updated_at OR (excluded.updated_at = fields.updated_at AND excluded.source_op > fields.source_op)",
                         rusqlite::params![
@@ -544,8 +846,11 @@ fn materialize_op(
             target_id,
             properties,
         } => {
-            conn.execute(
-                "INSERT INTO edges (edge_id, edge_type, source_id, target_id, created_at, created_by, created_in_bundle) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            insert_placeholder(conn, *source_id, op, bundle)?;
+            insert_placeholder(conn, *target_id, op, bundle)?;
+            execute_cached(
+                conn,
+                "INSERT INTO edges (workspace_id, edge_id, edge_type, source_id, target_id, created_at, created_by, created_in_bundle) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?6, ?7)",
                 rusqlite::params![
                     edge_id.as_bytes().as_slice(),
                     edge_type,
@@ -560,8 +865,9 @@ fn materialize_op(
                 let value_bytes = value
                     .to_msgpack()
                     .map_err(|e| StorageError::Serialization(e.to_string()))?;
-                conn.execute(
-                    "INSERT INTO edge_properties (edge_id, property_key, value, source_op, source_actor, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                execute_cached(
+                    conn,
+                    "INSERT INTO edge_properties (workspace_id, edge_id, property_key, value, source_op, source_actor, updated_at) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?6)",
                     rusqlite::params![
                         edge_id.as_bytes().as_slice(),
                         key,
@@ -572,6 +878,7 @@ fn materialize_op(
                     ],
                 )?;
             }
+            merge_duplicate_edges(conn, *edge_id)?;
         }
 
         OperationPayload::SetEdgeProperty {
@@ -579,11 +886,17 @@ fn materialize_op(
             property_key,
             value,
         } => {
+            // Skipped for an edge whose creation this store never got (left
+            // out of a scoped sync)
+            if !edge_exists(conn, *edge_id)? {
+                return Ok(());
+            }
             let value_bytes = value
                 .to_msgpack()
                 .map_err(|e| StorageError::Serialization(e.to_string()))?;
-            conn.execute(
-                "INSERT INTO edge_properties (edge_id, property_key, value, source_op, source_actor, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            execute_cached(
+                conn,
+                "INSERT INTO edge_properties (workspace_id, edge_id, property_key, value, source_op, source_actor, updated_at) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?6)
                  ON CONFLICT(edge_id, property_key) DO UPDATE SET value = excluded.value, source_op = excluded.source_op, source_actor = excluded.source_actor, updated_at = excluded.updated_at
                  WHERE excluded.updated_at > edge_properties.updated_at OR (excluded.updated_at = edge_properties.updated_at AND excluded.source_op > edge_properties.source_op)",
                 rusqlite::params![
@@ -603,8 +916,12 @@ fn materialize_op(
         } => {
             // ClearEdgeProperty writes a tombstone (value = NULL) with LWW guard
             // (mirrors ClearField pattern for correct out-of-order sync)
-            conn.execute(
-                "INSERT INTO edge_properties (edge_id, property_key, value, source_op, source_actor, updated_at) VALUES (?1, ?2, NULL, ?3, ?4, ?5)
+            if !edge_exists(conn, *edge_id)? {
+                return Ok(());
+            }
+            execute_cached(
+                conn,
+                "INSERT INTO edge_properties (workspace_id, edge_id, property_key, value, source_op, source_actor, updated_at) VALUES (workspace(), ?1, ?2, NULL, ?3, ?4, ?5)
                  ON CONFLICT(edge_id, property_key) DO UPDATE SET value = NULL, source_op = excluded.source_op, source_actor = excluded.source_actor, updated_at = excluded.updated_at
                  WHERE excluded.updated_at > edge_properties.updated_at OR (excluded.updated_at = edge_properties.updated_at AND excluded.source_op > edge_properties.source_op)",
                 rusqlite::params![
@@ -618,8 +935,9 @@ fn materialize_op(
         }
 
         OperationPayload::DeleteEdge { edge_id } => {
-            conn.execute(
-                "UPDATE edges SET deleted_at = ?1, deleted_by = ?2, deleted_in_bundle = ?3 WHERE edge_id = ?4",
+            execute_cached(
+                conn,
+                "UPDATE edges SET deleted_at = ?1, deleted_by = ?2, deleted_in_bundle = ?3 WHERE edge_id = ?4 AND workspace_id = workspace()",
                 rusqlite::params![
                     &op.hlc.to_bytes()[..],
                     op.actor_id.as_bytes().as_slice(),
@@ -630,29 +948,49 @@ fn materialize_op(
         }
 
         OperationPayload::RestoreEntity { entity_id } => {
-            conn.execute(
-                "UPDATE entities SET deleted_at = NULL, deleted_by = NULL, deleted_in_bundle = NULL WHERE entity_id = ?1",
+            execute_cached(
+                conn,
+                "UPDATE entities SET deleted_at = NULL, deleted_by = NULL, deleted_in_bundle = NULL WHERE entity_id = ?1 AND workspace_id = workspace()",
                 rusqlite::params![entity_id.as_bytes().as_slice()],
             )?;
         }
 
         OperationPayload::RestoreEdge { edge_id } => {
-            conn.execute(
-                "UPDATE edges SET deleted_at = NULL, deleted_by = NULL, deleted_in_bundle = NULL WHERE edge_id = ?1",
+            execute_cached(
+                conn,
+                "UPDATE edges SET deleted_at = NULL, deleted_by = NULL, deleted_in_bundle = NULL WHERE edge_id = ?1 AND workspace_id = workspace()",
                 rusqlite::params![edge_id.as_bytes().as_slice()],
             )?;
+            merge_duplicate_edges(conn, *edge_id)?;
         }
 
         OperationPayload::RestoreFacet {
             entity_id,
             facet_type,
         } => {
-            conn.execute(
-                "UPDATE facets SET detached_at = NULL, detached_by = NULL, detached_in_bundle = NULL, preserve_values = NULL WHERE entity_id = ?1 AND facet_type = ?2",
+            execute_cached(
+                conn,
+                "UPDATE facets SET detached_at = NULL, detached_by = NULL, detached_in_bundle = NULL, preserve_values = NULL WHERE entity_id = ?1 AND facet_type = ?2 AND workspace_id = workspace()",
                 rusqlite::params![entity_id.as_bytes().as_slice(), facet_type],
             )?;
         }
 
+        OperationPayload::ConfirmFieldMapping { source_table, target_table, source_field, target_field } => {
+            execute_cached(
+                conn,
+                UPSERT_FIELD_MAPPING,
+                rusqlite::params![
+                    source_table.as_bytes().as_slice(),
+                    target_table.as_bytes().as_slice(),
+                    source_field,
+                    target_field,
+                    op.op_id.as_bytes().as_slice(),
+                    op.actor_id.as_bytes().as_slice(),
+                    &op.hlc.to_bytes()[..],
+                ],
+            )?;
+        }
+
         // Operations not yet materialized -- stored in oplog only
         OperationPayload::ApplyCrdt { .. }
         | OperationPayload::ClearAndAdd { .. }
@@ -662,7 +1000,6 @@ fn materialize_op(
         | OperationPayload::UnlinkTables { .. }
         | OperationPayload::AddToTable { .. }
         | OperationPayload::RemoveFromTable { .. }
-        | OperationPayload::ConfirmFieldMapping { .. }
         | OperationPayload::MergeEntities { .. }
         | OperationPayload::SplitEntity { .. }
         | OperationPayload::CreateRule { .. } => {}
@@ -670,21 +1007,111 @@ fn materialize_op(
     Ok(())
 }
 
-impl Storage for SqliteStorage {
-    fn append_bundle(
-        &mut self,
-        bundle: &Bundle,
+/// Give an entity an op refers to a placeholder row if this store hasn't
+/// seen its creation, so its facets, fields and edges have a row to hang off.
+fn insert_placeholder(conn: &Connection, entity_id: EntityId, op: &Operation, bundle: &Bundle) -> Result<(), StorageError> {
+    execute_cached(
+        conn,
+        "INSERT INTO entities (workspace_id, entity_id, created_at, created_by, created_in_bundle, placeholder) VALUES (workspace(), ?1, ?2, ?3, ?4, 1)
+         ON CONFLICT(entity_id) DO NOTHING",
+        rusqlite::params![
+            entity_id.as_bytes().as_slice(),
+            &op.hlc.to_bytes()[..],
+            op.actor_id.as_bytes().as_slice(),
+            bundle.bundle_id.as_bytes().as_slice(),
+        ],
+    )?;
+    Ok(())
+}
+
+fn edge_exists(conn: &Connection, edge_id: EdgeId) -> Result<bool, StorageError> {
+    Ok(conn.prepare_cached("SELECT EXISTS(SELECT 1 FROM edges WHERE edge_id = ?1 AND workspace_id = workspace())")?.query_row(
+        rusqlite::params![edge_id.as_bytes().as_slice()],
+        |row| row.get(0),
+    )?)
+}
+
+/// Ids are unique across the file, not per workspace: refuse a bundle whose
+/// id, ops, or the entities and edges they touch belong to another workspace,
+/// rather than skip or write through to its rows.
+fn check_workspace_ids(conn: &Connection, bundle: &Bundle, operations: &[Operation]) -> Result<(), StorageError> {
+    let elsewhere = |sql: &str, id: &[u8]| -> Result<bool, StorageError> {
+        Ok(conn.prepare_cached(sql)?.query_row([id], |row| row.get(0))?)
+    };
+    if elsewhere(
+        "SELECT EXISTS(SELECT 1 FROM bundles WHERE bundle_id = ?1 AND workspace_id <> workspace())",
+        bundle.bundle_id.as_bytes(),
+    )? {
+        return Err(StorageError::ConstraintViolation(format!("bundle {} belongs to another workspace", bundle.bundle_id)));
+    }
+    for op in operations {
+        if elsewhere("SELECT EXISTS(SELECT 1 FROM oplog WHERE op_id = ?1 AND workspace_id <> workspace())", op.op_id.as_bytes())? {
+            return Err(StorageError::ConstraintViolation(format!("op {} belongs to another workspace", op.op_id)));
+        }
+        let target = match &op.payload {
+            OperationPayload::CreateEdge { target_id, .. } | OperationPayload::CreateOrderedEdge { target_id, .. } => Some(*target_id),
+            _ => None,
+        };
+        for entity_id in op.payload.entity_id().into_iter().chain(target) {
+            if elsewhere(
+                "SELECT EXISTS(SELECT 1 FROM entities WHERE entity_id = ?1 AND workspace_id <> workspace())",
+                entity_id.as_bytes(),
+            )? {
+                return Err(StorageError::ConstraintViolation(format!("entity {entity_id} belongs to another workspace")));
+            }
+        }
+        for edge_id in op.payload.edge_ids() {
+            if elsewhere("SELECT EXISTS(SELECT 1 FROM edges WHERE edge_id = ?1 AND workspace_id <> workspace())", edge_id.as_bytes())? {
+                return Err(StorageError::ConstraintViolation(format!("edge {edge_id} belongs to another workspace")));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Latest confirmation wins, as for fields.
+pub(crate) const UPSERT_FIELD_MAPPING: &str = "INSERT INTO field_mappings (workspace_id, source_table, target_table, source_field, target_field, source_op, confirmed_by, confirmed_at)
+     VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?6, ?7)
+     ON CONFLICT(source_table, target_table, source_field) DO UPDATE SET target_field = excluded.target_field, source_op = excluded.source_op, confirmed_by = excluded.confirmed_by, confirmed_at = excluded.confirmed_at
+     WHERE excluded.confirmed_at > field_mappings.confirmed_at OR (excluded.confirmed_at = field_mappings.confirmed_at AND excluded.source_op > field_mappings.source_op)";
+
+/// If `edge_id` has a unique edge type, tombstone every live edge of that type
+/// between the same pair except the earliest created. Each loser is deleted as
+/// of its own creation, so peers converge whatever order duplicates arrive in.
+fn merge_duplicate_edges(conn: &Connection, edge_id: EdgeId) -> Result<(), StorageError> {
+    execute_cached(
+        conn,
+        "UPDATE edges SET deleted_at = created_at, deleted_by = created_by, deleted_in_bundle = created_in_bundle
+         WHERE workspace_id = workspace() AND deleted_at IS NULL
+           AND edge_type IN (SELECT edge_type FROM unique_edge_types WHERE workspace_id = workspace())
+           AND (source_id, target_id, edge_type) = (SELECT source_id, target_id, edge_type FROM edges WHERE edge_id = ?1)
+           AND EXISTS (
+               SELECT 1 FROM edges AS earlier
+               WHERE earlier.source_id = edges.source_id AND earlier.target_id = edges.target_id
+                 AND earlier.edge_type = edges.edge_type
+                 AND (earlier.created_at, earlier.edge_id) < (edges.created_at, edges.edge_id))",
+        rusqlite::params![edge_id.as_bytes().as_slice()],
+    )?;
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn append_bundle(
+        &mut self,
+        bundle: &Bundle,
         operations: &[Operation],
-    ) -> Result<(), StorageError> {
+    ) -> Result<Vec<OpId>, StorageError> {
+        check_bundle_ops(bundle, operations)?;
         // Idempotent: skip if bundle already ingested
         let exists: bool = self.conn.query_row(
-            "SELECT EXISTS(SELECT 1 FROM bundles WHERE bundle_id = ?1)",
+            "SELECT EXISTS(SELECT 1 FROM bundles WHERE bundle_id = ?1 AND workspace_id = workspace())",
             rusqlite::params![bundle.bundle_id.as_bytes().as_slice()],
             |row| row.get(0),
         )?;
         if exists {
-            return Ok(());
+            return Ok(operations.iter().map(|op| op.op_id).collect());
         }
+        check_workspace_ids(&self.conn, bundle, operations)?;
 
         self.conn.execute_batch("SAVEPOINT sp_append")?;
 
@@ -695,7 +1122,7 @@ impl Storage for SqliteStorage {
             }).transpose()?;
 
             self.conn.execute(
-                "INSERT INTO bundles (bundle_id, actor_id, hlc, bundle_type, op_count, checksum, creates, deletes, meta, signature, creator_vector_clock) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                "INSERT INTO bundles (workspace_id, bundle_id, actor_id, hlc, bundle_type, op_count, checksum, creates, deletes, meta, signature, creator_vector_clock) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
                 rusqlite::params![
                     bundle.bundle_id.as_bytes().as_slice(),
                     bundle.actor_id.as_bytes().as_slice(),
@@ -712,48 +1139,85 @@ impl Storage for SqliteStorage {
                     creator_vc_bytes.as_deref(),
                 ],
             )?;
-
-            for op in operations {
-                let payload_bytes = op.payload.to_msgpack()?;
-                let mv_bytes = rmp_serde::to_vec(&op.module_versions)
-                    .map_err(|e| StorageError::Serialization(e.to_string()))?;
-                let entity_id_blob = op
-                    .payload
-                    .entity_id()
-                    .map(|eid| eid.as_bytes().to_vec());
-
-                self.conn.execute(
-                    "INSERT INTO oplog (op_id, actor_id, hlc, bundle_id, payload, module_versions, signature, op_type, entity_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-                    rusqlite::params![
-                        op.op_id.as_bytes().as_slice(),
-                        op.actor_id.as_bytes().as_slice(),
-                        &op.hlc.to_bytes()[..],
-                        op.bundle_id.as_bytes().as_slice(),
-                        payload_bytes,
-                        mv_bytes,
-                        op.signature.as_bytes().as_slice(),
-                        op.payload.op_type_name(),
-                        entity_id_blob,
-                    ],
+            let meta = indexed_meta(bundle);
+            for tag in &meta.tags {
+                execute_cached(
+                    &self.conn,
+                    "INSERT OR IGNORE INTO bundle_tags (workspace_id, tag, bundle_id) VALUES (workspace(), ?1, ?2)",
+                    rusqlite::params![tag, bundle.bundle_id.as_bytes().as_slice()],
+                )?;
+            }
+            if let Some(session_id) = meta.session {
+                execute_cached(
+                    &self.conn,
+                    "INSERT OR IGNORE INTO bundle_sessions (workspace_id, bundle_id, session_id) VALUES (workspace(), ?1, ?2)",
+                    rusqlite::params![bundle.bundle_id.as_bytes().as_slice(), session_id.as_bytes().as_slice()],
+                )?;
+            }
+            if let Some(key) = &meta.idempotency_key {
+                execute_cached(
+                    &self.conn,
+                    "INSERT OR IGNORE INTO bundle_idempotency_keys (workspace_id, actor_id, idempotency_key, bundle_id) VALUES (workspace(), ?1, ?2, ?3)",
+                    rusqlite::params![bundle.actor_id.as_bytes().as_slice(), key, bundle.bundle_id.as_bytes().as_slice()],
                 )?;
+            }
 
-                materialize_op(&self.conn, op, bundle)?;
+            // Oplog rows go in OPLOG_INSERT_BATCH at a time, and actor and vector
+            // clock rows once per actor after the ops rather than once per op:
+            // (actor, first hlc seen, max hlc)
+            let mut actors: Vec<(ActorId, Hlc, Hlc)> = Vec::new();
+            for chunk in operations.chunks(OPLOG_INSERT_BATCH) {
+                let mut values = Vec::with_capacity(chunk.len() * 10);
+                for op in chunk {
+                    let payload_bytes = op.payload.to_msgpack()?;
+                    let mv_bytes = rmp_serde::to_vec(&op.module_versions)
+                        .map_err(|e| StorageError::Serialization(e.to_string()))?;
+                    values.extend([
+                        SqlValue::Blob(op.op_id.as_bytes().to_vec()),
+                        SqlValue::Blob(op.actor_id.as_bytes().to_vec()),
+                        SqlValue::Blob(op.hlc.to_bytes().to_vec()),
+                        SqlValue::Blob(op.bundle_id.as_bytes().to_vec()),
+                        SqlValue::Blob(payload_bytes),
+                        SqlValue::Blob(mv_bytes),
+                        SqlValue::Blob(op.signature.as_bytes().to_vec()),
+                        SqlValue::Text(op.payload.op_type_name().to_string()),
+                        op.payload.entity_id().map_or(SqlValue::Null, |eid| SqlValue::Blob(eid.as_bytes().to_vec())),
+                        op.payload.property_edge_id().map_or(SqlValue::Null, |eid| SqlValue::Blob(eid.as_bytes().to_vec())),
+                    ]);
+                }
+                let rows = vec!["(workspace(), ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"; chunk.len()].join(", ");
+                self.conn
+                    .prepare_cached(&format!(
+                        "INSERT INTO oplog (workspace_id, op_id, actor_id, hlc, bundle_id, payload, module_versions, signature, op_type, entity_id, edge_id) VALUES {rows}"
+                    ))?
+                    .execute(rusqlite::params_from_iter(values))?;
+
+                for op in chunk {
+                    materialize_op(&self.conn, op, bundle)?;
+
+                    match actors.iter_mut().find(|(actor_id, _, _)| *actor_id == op.actor_id) {
+                        Some((_, _, max_hlc)) => *max_hlc = (*max_hlc).max(op.hlc),
+                        None => actors.push((op.actor_id, op.hlc, op.hlc)),
+                    }
+                }
+            }
 
+            for (actor_id, first_hlc, max_hlc) in actors {
                 self.conn.execute(
-                    "INSERT OR IGNORE INTO actors (actor_id, display_name, first_seen_at) VALUES (?1, NULL, ?2)",
+                    "INSERT OR IGNORE INTO actors (workspace_id, actor_id, display_name, first_seen_at) VALUES (workspace(), ?1, NULL, ?2)",
                     rusqlite::params![
-                        op.actor_id.as_bytes().as_slice(),
-                        &op.hlc.to_bytes()[..],
+                        actor_id.as_bytes().as_slice(),
+                        &first_hlc.to_bytes()[..],
                     ],
                 )?;
 
                 self.conn.execute(
-                    "INSERT INTO vector_clock (actor_id, max_hlc) VALUES (?1, ?2)
-                     ON CONFLICT(actor_id) DO UPDATE SET max_hlc = excluded.max_hlc
+                    "INSERT INTO vector_clock (workspace_id, actor_id, max_hlc) VALUES (workspace(), ?1, ?2)
+                     ON CONFLICT(workspace_id, actor_id) DO UPDATE SET max_hlc = excluded.max_hlc
                      WHERE excluded.max_hlc > vector_clock.max_hlc",
                     rusqlite::params![
-                        op.actor_id.as_bytes().as_slice(),
-                        &op.hlc.to_bytes()[..],
+                        actor_id.as_bytes().as_slice(),
+                        &max_hlc.to_bytes()[..],
                     ],
                 )?;
             }
@@ -764,7 +1228,7 @@ impl Storage for SqliteStorage {
         match result {
             Ok(()) => {
                 self.conn.execute_batch("RELEASE sp_append")?;
-                Ok(())
+                Ok(Vec::new())
             }
             Err(e) => {
                 let _ = self.conn.execute_batch("ROLLBACK TO sp_append; RELEASE sp_append");
@@ -775,7 +1239,7 @@ impl Storage for SqliteStorage {
 
     fn get_ops_canonical(&self) -> Result<Vec<Operation>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog ORDER BY hlc, op_id",
+            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog WHERE workspace_id = workspace() ORDER BY hlc, op_id",
         )?;
         let ops = stmt
             .query_map([], |row| {
@@ -792,9 +1256,64 @@ impl Storage for SqliteStorage {
         Ok(ops)
     }
 
+    fn get_ops_page(
+        &self,
+        after: Option<(Hlc, OpId)>,
+        limit: usize,
+    ) -> Result<Vec<Operation>, StorageError> {
+        // Hlc bytes sort like Hlc, so the row-value comparison follows canonical order
+        let (filter, mut params) = match after {
+            Some((hlc, op_id)) => (
+                "AND (hlc, op_id) > (?, ?)",
+                vec![SqlValue::Blob(hlc.to_bytes().to_vec()), SqlValue::Blob(op_id.as_bytes().to_vec())],
+            ),
+            None => ("", Vec::new()),
+        };
+        params.push(SqlValue::Integer(limit as i64));
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog WHERE workspace_id = workspace() {filter} ORDER BY hlc, op_id LIMIT ?"
+        ))?;
+        let ops = stmt
+            .query_map(
+                rusqlite::params_from_iter(params),
+                |row| {
+                    read_op(row).map_err(|e| match e {
+                        StorageError::Sqlite(sq) => sq,
+                        other => rusqlite::Error::FromSqlConversionFailure(
+                            0,
+                            rusqlite::types::Type::Blob,
+                            Box::new(OpaqueStorageError(other.to_string())),
+                        ),
+                    })
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ops)
+    }
+
+    fn get_ops_after_seq(&self, after: u64, limit: usize) -> Result<Vec<(u64, Operation)>, StorageError> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature, rowid FROM oplog WHERE workspace_id = workspace() AND rowid > ?1 ORDER BY rowid LIMIT ?2",
+        )?;
+        let ops = stmt
+            .query_map(rusqlite::params![after as i64, limit as i64], |row| {
+                let op = read_op(row).map_err(|e| match e {
+                    StorageError::Sqlite(sq) => sq,
+                    other => rusqlite::Error::FromSqlConversionFailure(
+                        0,
+                        rusqlite::types::Type::Blob,
+                        Box::new(OpaqueStorageError(other.to_string())),
+                    ),
+                })?;
+                Ok((row.get::<_, i64>(7)? as u64, op))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ops)
+    }
+
     fn get_ops_by_bundle(&self, bundle_id: BundleId) -> Result<Vec<Operation>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog WHERE bundle_id = ?1",
+            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog WHERE workspace_id = workspace() AND bundle_id = ?1",
         )?;
         let ops = stmt
             .query_map(rusqlite::params![bundle_id.as_bytes().as_slice()], |row| {
@@ -811,13 +1330,103 @@ impl Storage for SqliteStorage {
         Ok(ops)
     }
 
+    fn get_ops_by_entity(&self, entity_id: EntityId) -> Result<Vec<Operation>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog WHERE workspace_id = workspace() AND entity_id = ?1 ORDER BY hlc, op_id",
+        )?;
+        let ops = stmt
+            .query_map(rusqlite::params![entity_id.as_bytes().as_slice()], |row| {
+                read_op(row).map_err(|e| match e {
+                    StorageError::Sqlite(sq) => sq,
+                    other => rusqlite::Error::FromSqlConversionFailure(
+                        0,
+                        rusqlite::types::Type::Blob,
+                        Box::new(OpaqueStorageError(other.to_string())),
+                    ),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ops)
+    }
+
+    fn get_edge_property_ops(&self, edge_id: EdgeId) -> Result<Vec<Operation>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog WHERE workspace_id = workspace() AND edge_id = ?1 ORDER BY hlc, op_id",
+        )?;
+        let ops = stmt
+            .query_map(rusqlite::params![edge_id.as_bytes().as_slice()], |row| {
+                read_op(row).map_err(|e| match e {
+                    StorageError::Sqlite(sq) => sq,
+                    other => rusqlite::Error::FromSqlConversionFailure(
+                        0,
+                        rusqlite::types::Type::Blob,
+                        Box::new(OpaqueStorageError(other.to_string())),
+                    ),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ops)
+    }
+
+    fn get_ops_by_type(&self, op_types: &[&str]) -> Result<Vec<Operation>, StorageError> {
+        let placeholders = vec!["?"; op_types.len()].join(", ");
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog WHERE workspace_id = workspace() AND op_type IN ({placeholders}) ORDER BY hlc, op_id",
+        ))?;
+        let ops = stmt
+            .query_map(rusqlite::params_from_iter(op_types), |row| {
+                read_op(row).map_err(|e| match e {
+                    StorageError::Sqlite(sq) => sq,
+                    other => rusqlite::Error::FromSqlConversionFailure(
+                        0,
+                        rusqlite::types::Type::Blob,
+                        Box::new(OpaqueStorageError(other.to_string())),
+                    ),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ops)
+    }
+
+    fn get_entities_changed_since(&self, after: u64) -> Result<Vec<EntityId>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT entity_id FROM oplog
+             WHERE workspace_id = workspace() AND rowid > ?1 AND entity_id IS NOT NULL AND op_type NOT IN ('CreateEdge', 'CreateOrderedEdge')
+             ORDER BY entity_id",
+        )?;
+        let rows = stmt
+            .query_map([after as i64], |row| row.get::<_, Vec<u8>>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|bytes| Ok(EntityId::from_bytes(to_array::<16>(bytes, "entity_id")?)))
+            .collect()
+    }
+
+    fn get_edges_changed_since(&self, after: u64) -> Result<Vec<EdgeId>, StorageError> {
+        let placeholders = vec!["?"; EDGE_OP_TYPES.len()].join(", ");
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT payload FROM oplog WHERE workspace_id = workspace() AND rowid > ? AND op_type IN ({placeholders})"))?;
+        let params = std::iter::once(SqlValue::Integer(after as i64))
+            .chain(EDGE_OP_TYPES.iter().map(|t| SqlValue::Text(t.to_string())));
+        let payloads = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| row.get::<_, Vec<u8>>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut edges = BTreeSet::new();
+        for bytes in payloads {
+            edges.extend(OperationPayload::from_msgpack(&bytes)?.edge_ids());
+        }
+        Ok(edges.into_iter().collect())
+    }
+
     fn get_ops_by_actor_after(
         &self,
         actor_id: ActorId,
         after: Hlc,
     ) -> Result<Vec<Operation>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog WHERE actor_id = ?1 AND hlc > ?2 ORDER BY hlc, op_id",
+            "SELECT op_id, actor_id, hlc, bundle_id, payload, module_versions, signature FROM oplog WHERE workspace_id = workspace() AND actor_id = ?1 AND hlc > ?2 ORDER BY hlc, op_id",
         )?;
         let ops = stmt
             .query_map(
@@ -840,13 +1449,18 @@ impl Storage for SqliteStorage {
     fn op_count(&self) -> Result<u64, StorageError> {
         let count: i64 = self
             .conn
-            .query_row("SELECT COUNT(*) FROM oplog", [], |row| row.get(0))?;
+            .query_row("SELECT COUNT(*) FROM oplog WHERE workspace_id = workspace()", [], |row| row.get(0))?;
         Ok(count as u64)
     }
 
+    fn analyze(&mut self) -> Result<(), StorageError> {
+        self.conn.execute_batch("ANALYZE")?;
+        Ok(())
+    }
+
     fn get_entity(&self, entity_id: EntityId) -> Result<Option<EntityRecord>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT entity_id, created_at, created_by, (deleted_at IS NOT NULL) FROM entities WHERE entity_id = ?1",
+            "SELECT entity_id, created_at, created_by, (deleted_at IS NOT NULL), placeholder FROM entities WHERE workspace_id = workspace() AND entity_id = ?1",
         )?;
         let mut rows = stmt.query_map(
             rusqlite::params![entity_id.as_bytes().as_slice()],
@@ -855,12 +1469,13 @@ impl Storage for SqliteStorage {
                 let created_at_bytes: Vec<u8> = row.get(1)?;
                 let created_by_bytes: Vec<u8> = row.get(2)?;
                 let deleted: bool = row.get(3)?;
-                Ok((eid_bytes, created_at_bytes, created_by_bytes, deleted))
+                let placeholder: bool = row.get(4)?;
+                Ok((eid_bytes, created_at_bytes, created_by_bytes, deleted, placeholder))
             },
         )?;
 
         match rows.next() {
-            Some(Ok((eid_bytes, created_at_bytes, created_by_bytes, deleted))) => {
+            Some(Ok((eid_bytes, created_at_bytes, created_by_bytes, deleted, placeholder))) => {
                 let entity_id =
                     EntityId::from_bytes(to_array::<16>(eid_bytes, "entity_id")?);
                 let created_at =
@@ -872,6 +1487,7 @@ impl Storage for SqliteStorage {
                     created_at,
                     created_by,
                     deleted,
+                    placeholder,
                 }))
             }
             Some(Err(e)) => Err(StorageError::Sqlite(e)),
@@ -885,7 +1501,7 @@ impl Storage for SqliteStorage {
     ) -> Result<Vec<(String, FieldValue)>, StorageError> {
         let mut stmt = self
             .conn
-            .prepare("SELECT field_key, value FROM fields WHERE entity_id = ?1 AND value IS NOT NULL")?;
+            .prepare("SELECT field_key, value FROM fields WHERE workspace_id = workspace() AND entity_id = ?1 AND value IS NOT NULL")?;
         let rows = stmt.query_map(
             rusqlite::params![entity_id.as_bytes().as_slice()],
             |row| {
@@ -912,7 +1528,7 @@ impl Storage for SqliteStorage {
     ) -> Result<Option<FieldValue>, StorageError> {
         let mut stmt = self
             .conn
-            .prepare("SELECT value FROM fields WHERE entity_id = ?1 AND field_key = ?2 AND value IS NOT NULL")?;
+            .prepare("SELECT value FROM fields WHERE workspace_id = workspace() AND entity_id = ?1 AND field_key = ?2 AND value IS NOT NULL")?;
         let mut rows = stmt.query_map(
             rusqlite::params![entity_id.as_bytes().as_slice(), field_key],
             |row| {
@@ -934,7 +1550,7 @@ impl Storage for SqliteStorage {
 
     fn get_facets(&self, entity_id: EntityId) -> Result<Vec<FacetRecord>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT entity_id, facet_type, attached_at, attached_by, (detached_at IS NOT NULL) FROM facets WHERE entity_id = ?1",
+            "SELECT entity_id, facet_type, attached_at, attached_by, (detached_at IS NOT NULL) FROM facets WHERE workspace_id = workspace() AND entity_id = ?1",
         )?;
         let rows = stmt.query_map(
             rusqlite::params![entity_id.as_bytes().as_slice()],
@@ -976,7 +1592,7 @@ impl Storage for SqliteStorage {
     fn get_entities_by_facet(&self, facet_type: &str) -> Result<Vec<EntityId>, StorageError> {
         let mut stmt = self
             .conn
-            .prepare("SELECT entity_id FROM facets WHERE facet_type = ?1 AND detached_at IS NULL")?;
+            .prepare("SELECT entity_id FROM facets WHERE workspace_id = workspace() AND facet_type = ?1 AND detached_at IS NULL")?;
         let rows = stmt.query_map(rusqlite::params![facet_type], |row| {
             let eid_bytes: Vec<u8> = row.get(0)?;
             Ok(eid_bytes)
@@ -991,40 +1607,256 @@ impl Storage for SqliteStorage {
         Ok(result)
     }
 
-    fn get_edges_from(&self, entity_id: EntityId) -> Result<Vec<EdgeRecord>, StorageError> {
-        let mut stmt = self.conn.prepare(
-            "SELECT edge_id, edge_type, source_id, target_id, created_at, created_by, (deleted_at IS NOT NULL) FROM edges WHERE source_id = ?1",
-        )?;
-        let rows = stmt.query_map(
-            rusqlite::params![entity_id.as_bytes().as_slice()],
-            extract_edge_row,
-        )?;
-        let mut result = Vec::new();
-        for row in rows {
-            result.push(parse_edge_row(row?)?);
+    fn get_entities_page(
+        &self,
+        facet_type: &str,
+        order: &EntityOrder,
+        after: Option<&EntityPageCursor>,
+        limit: usize,
+    ) -> Result<EntityPage, StorageError> {
+        let mut params = Vec::new();
+        let (join, sort_key) = match &order.field_key {
+            Some(field_key) => {
+                params.push(SqlValue::Text(field_key.clone()));
+                (
+                    "LEFT JOIN fields fl ON fl.entity_id = e.entity_id AND fl.field_key = ?",
+                    "field_sort_key(fl.value)",
+                )
+            }
+            None => ("", "e.created_at"),
+        };
+        params.push(SqlValue::Text(facet_type.to_string()));
+        let (comparison, direction) = if order.descending { ("<", "DESC") } else { (">", "ASC") };
+        let mut sql = format!(
+            "SELECT {sort_key}, e.entity_id FROM facets f JOIN entities e ON e.entity_id = f.entity_id {join}
+             WHERE f.workspace_id = workspace() AND f.facet_type = ? AND f.detached_at IS NULL AND e.deleted_at IS NULL"
+        );
+        if let Some(after) = after {
+            sql.push_str(&format!(" AND ({sort_key}, e.entity_id) {comparison} (?, ?)"));
+            params.push(SqlValue::Blob(after.sort_key.clone()));
+            params.push(SqlValue::Blob(after.entity_id.as_bytes().to_vec()));
+        }
+        sql.push_str(&format!(" ORDER BY 1 {direction}, e.entity_id {direction} LIMIT ?"));
+        params.push(SqlValue::Integer(limit.saturating_add(1) as i64));
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let rows = rows
+            .into_iter()
+            .map(|(sort_key, eid)| Ok((sort_key, EntityId::from_bytes(to_array::<16>(eid, "entity_id")?))))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        Ok(EntityPage::from_rows(rows, limit))
+    }
+
+    fn get_entities_with_fields(
+        &self,
+        facet_type: &str,
+        field_keys: &[&str],
+    ) -> Result<Vec<EntityFields>, StorageError> {
+        let placeholders = vec!["?"; field_keys.len()].join(", ");
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT e.entity_id, fl.field_key, fl.value FROM facets f JOIN entities e ON e.entity_id = f.entity_id
+             LEFT JOIN fields fl ON fl.entity_id = e.entity_id AND fl.value IS NOT NULL AND fl.field_key IN ({placeholders})
+             WHERE f.workspace_id = workspace() AND f.facet_type = ? AND f.detached_at IS NULL AND e.deleted_at IS NULL
+             ORDER BY e.entity_id"
+        ))?;
+        let params = field_keys.iter().copied().chain([facet_type]);
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, Option<Vec<u8>>>(2)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut result: Vec<EntityFields> = Vec::new();
+        for (eid, field_key, value) in rows {
+            let entity_id = EntityId::from_bytes(to_array::<16>(eid, "entity_id")?);
+            if result.last().is_none_or(|(last, _)| *last != entity_id) {
+                result.push((entity_id, Vec::new()));
+            }
+            if let (Some(field_key), Some(value)) = (field_key, value) {
+                let value = FieldValue::from_msgpack(&value).map_err(|e| StorageError::Serialization(e.to_string()))?;
+                result.last_mut().expect("pushed above").1.push((field_key, value));
+            }
+        }
+        for (_, fields) in &mut result {
+            fields.sort_by_key(|(key, _)| field_keys.iter().position(|k| k == key));
         }
         Ok(result)
     }
 
-    fn get_edges_to(&self, entity_id: EntityId) -> Result<Vec<EdgeRecord>, StorageError> {
+    fn count_entities_by_facet(&self) -> Result<Vec<(String, u64)>, StorageError> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.facet_type, COUNT(*) FROM facets f JOIN entities e ON e.entity_id = f.entity_id
+             WHERE f.workspace_id = workspace() AND f.detached_at IS NULL AND e.deleted_at IS NULL
+             GROUP BY f.facet_type ORDER BY f.facet_type",
+        )?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    fn aggregate_field(
+        &self,
+        facet_type: &str,
+        field_key: &str,
+        aggregate: Aggregate,
+    ) -> Result<Option<FieldValue>, StorageError> {
+        let function = match aggregate {
+            Aggregate::Sum => "SUM",
+            Aggregate::Avg => "AVG",
+            Aggregate::Min => "MIN",
+            Aggregate::Max => "MAX",
+        };
+        let result: SqlValue = self.conn.query_row(
+            &format!(
+                "SELECT {function}(field_number(fl.value)) FROM fields fl
+                 JOIN facets f ON f.entity_id = fl.entity_id AND f.facet_type = ?1 AND f.detached_at IS NULL
+                 JOIN entities e ON e.entity_id = fl.entity_id AND e.deleted_at IS NULL
+                 WHERE fl.workspace_id = workspace() AND fl.field_key = ?2"
+            ),
+            rusqlite::params![facet_type, field_key],
+            |row| row.get(0),
+        )?;
+        Ok(match result {
+            SqlValue::Integer(n) => Some(FieldValue::Integer(n)),
+            SqlValue::Real(x) => Some(FieldValue::Float(x)),
+            _ => None,
+        })
+    }
+
+    fn get_field_mappings(&self, source_table: TableId) -> Result<Vec<FieldMappingRecord>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT edge_id, edge_type, source_id, target_id, created_at, created_by, (deleted_at IS NOT NULL) FROM edges WHERE target_id = ?1",
+            "SELECT target_table, source_field, target_field, confirmed_by, confirmed_at FROM field_mappings
+             WHERE workspace_id = workspace() AND source_table = ?1 ORDER BY target_table, source_field",
         )?;
-        let rows = stmt.query_map(
-            rusqlite::params![entity_id.as_bytes().as_slice()],
-            extract_edge_row,
+        let rows = stmt
+            .query_map([source_table.as_bytes().as_slice()], |row| {
+                Ok((
+                    row.get::<_, Vec<u8>>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Vec<u8>>(3)?,
+                    row.get::<_, Vec<u8>>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(target_table, source_field, target_field, confirmed_by, confirmed_at)| {
+                Ok(FieldMappingRecord {
+                    source_table,
+                    target_table: TableId::from_bytes(to_array::<16>(target_table, "target_table")?),
+                    source_field,
+                    target_field,
+                    confirmed_by: ActorId::from_bytes(to_array::<32>(confirmed_by, "confirmed_by")?),
+                    confirmed_at: Hlc::from_bytes(&to_array::<12>(confirmed_at, "confirmed_at")?),
+                })
+            })
+            .collect()
+    }
+
+    fn get_preserved_values(
+        &self,
+        entity_id: EntityId,
+        facet_type: &str,
+    ) -> Result<Option<Vec<(String, FieldValue)>>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT preserve_values FROM facets
+             WHERE workspace_id = workspace() AND entity_id = ?1 AND facet_type = ?2 AND detached_at IS NOT NULL AND preserve_values IS NOT NULL",
         )?;
-        let mut result = Vec::new();
-        for row in rows {
-            result.push(parse_edge_row(row?)?);
+        let mut rows = stmt.query_map(rusqlite::params![entity_id.as_bytes().as_slice(), facet_type], |row| {
+            row.get::<_, Vec<u8>>(0)
+        })?;
+        rows.next().transpose()?.map(|bytes| decode_preserved_values(&bytes)).transpose()
+    }
+
+    fn get_edges_from(&self, entity_id: EntityId, filter: &EdgeFilter) -> Result<Vec<EdgeRecord>, StorageError> {
+        self.query_edges("source_id = ?", vec![SqlValue::Blob(entity_id.as_bytes().to_vec())], filter)
+    }
+
+    fn get_edges_to(&self, entity_id: EntityId, filter: &EdgeFilter) -> Result<Vec<EdgeRecord>, StorageError> {
+        self.query_edges("target_id = ?", vec![SqlValue::Blob(entity_id.as_bytes().to_vec())], filter)
+    }
+
+    fn get_edges_between(
+        &self,
+        source_id: EntityId,
+        target_id: EntityId,
+        filter: &EdgeFilter,
+    ) -> Result<Vec<EdgeRecord>, StorageError> {
+        self.query_edges(
+            "source_id = ? AND target_id = ?",
+            vec![SqlValue::Blob(source_id.as_bytes().to_vec()), SqlValue::Blob(target_id.as_bytes().to_vec())],
+            filter,
+        )
+    }
+
+    fn get_edges_by_type(&self, edge_type: &str) -> Result<Vec<EdgeRecord>, StorageError> {
+        self.query_edges("edge_type = ?", vec![SqlValue::Text(edge_type.to_string())], &EdgeFilter::live())
+    }
+
+    fn get_deleted_entities(
+        &self,
+        facet_type: Option<&str>,
+        since: Option<Hlc>,
+    ) -> Result<Vec<(EntityRecord, Deletion)>, StorageError> {
+        let mut sql = String::from(
+            "SELECT entity_id, created_at, created_by, deleted_at, deleted_by, deleted_in_bundle, placeholder FROM entities WHERE workspace_id = workspace() AND deleted_at IS NOT NULL",
+        );
+        let mut params = Vec::new();
+        if let Some(since) = since {
+            sql.push_str(" AND deleted_at > ?");
+            params.push(SqlValue::Blob(since.to_bytes().to_vec()));
         }
-        Ok(result)
+        if let Some(facet_type) = facet_type {
+            sql.push_str(" AND entity_id IN (SELECT entity_id FROM facets WHERE workspace_id = workspace() AND facet_type = ? AND detached_at IS NULL)");
+            params.push(SqlValue::Text(facet_type.to_string()));
+        }
+        sql.push_str(" ORDER BY deleted_at DESC, entity_id");
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                Ok((
+                    row.get::<_, Vec<u8>>(0)?,
+                    row.get::<_, Vec<u8>>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                    extract_deletion(row, 3)?,
+                    row.get::<_, bool>(6)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(entity_id, created_at, created_by, deletion, placeholder)| {
+                let entity = EntityRecord {
+                    entity_id: EntityId::from_bytes(to_array::<16>(entity_id, "entity_id")?),
+                    created_at: Hlc::from_bytes(&to_array::<12>(created_at, "created_at")?),
+                    created_by: ActorId::from_bytes(to_array::<32>(created_by, "created_by")?),
+                    deleted: true,
+                    placeholder,
+                };
+                Ok((entity, parse_deletion(deletion)?))
+            })
+            .collect()
+    }
+
+    fn get_deleted_edges(&self) -> Result<Vec<(EdgeRecord, Deletion)>, StorageError> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT edge_id, edge_type, source_id, target_id, created_at, created_by, (deleted_at IS NOT NULL), created_in_bundle,
+                    deleted_at, deleted_by, deleted_in_bundle
+             FROM edges WHERE workspace_id = workspace() AND deleted_at IS NOT NULL ORDER BY deleted_at DESC, edge_id",
+        )?;
+        let rows = stmt
+            .query_map([], |row| Ok((extract_edge_row(row)?, extract_deletion(row, 8)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter().map(|(edge, deletion)| Ok((parse_edge_row(edge)?, parse_deletion(deletion)?))).collect()
     }
 
     fn get_vector_clock(&self) -> Result<VectorClock, StorageError> {
         let mut stmt = self
             .conn
-            .prepare("SELECT actor_id, max_hlc FROM vector_clock")?;
+            .prepare("SELECT actor_id, max_hlc FROM vector_clock WHERE workspace_id = workspace()")?;
         let rows = stmt.query_map([], |row| {
             let actor_id_bytes: Vec<u8> = row.get(0)?;
             let hlc_bytes: Vec<u8> = row.get(1)?;
@@ -1041,13 +1873,36 @@ impl Storage for SqliteStorage {
         Ok(vc)
     }
 
+    fn get_last_hlc(&self) -> Result<Option<Hlc>, StorageError> {
+        let result = self.conn.query_row(
+            "SELECT last_hlc FROM clock_state WHERE workspace_id = workspace()",
+            [],
+            |row| row.get::<_, Vec<u8>>(0),
+        );
+        match result {
+            Ok(bytes) => Ok(Some(Hlc::from_bytes(&to_array::<12>(bytes, "last_hlc")?))),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_last_hlc(&mut self, hlc: Hlc) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT INTO clock_state (workspace_id, last_hlc) VALUES (workspace(), ?1)
+             ON CONFLICT(workspace_id) DO UPDATE SET last_hlc = excluded.last_hlc
+             WHERE excluded.last_hlc > clock_state.last_hlc",
+            [hlc.to_bytes().as_slice()],
+        )?;
+        Ok(())
+    }
+
     fn get_field_metadata(
         &self,
         entity_id: EntityId,
         field_key: &str,
     ) -> Result<Option<(ActorId, Hlc)>, StorageError> {
         let result = self.conn.query_row(
-            "SELECT source_actor, updated_at FROM fields WHERE entity_id = ?1 AND field_key = ?2",
+            "SELECT source_actor, updated_at FROM fields WHERE workspace_id = workspace() AND entity_id = ?1 AND field_key = ?2",
             rusqlite::params![entity_id.as_bytes().as_slice(), field_key],
             |row| {
                 let actor_bytes: Vec<u8> = row.get(0)?;
@@ -1068,7 +1923,7 @@ impl Storage for SqliteStorage {
 
     fn get_edge(&self, edge_id: EdgeId) -> Result<Option<EdgeRecord>, StorageError> {
         let result = self.conn.query_row(
-            "SELECT edge_id, edge_type, source_id, target_id, created_at, created_by, (deleted_at IS NOT NULL) FROM edges WHERE edge_id = ?1",
+            "SELECT edge_id, edge_type, source_id, target_id, created_at, created_by, (deleted_at IS NOT NULL), created_in_bundle FROM edges WHERE workspace_id = workspace() AND edge_id = ?1",
             rusqlite::params![edge_id.as_bytes().as_slice()],
             extract_edge_row,
         );
@@ -1084,7 +1939,7 @@ impl Storage for SqliteStorage {
         edge_id: EdgeId,
     ) -> Result<Vec<(String, FieldValue)>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT property_key, value FROM edge_properties WHERE edge_id = ?1 AND value IS NOT NULL",
+            "SELECT property_key, value FROM edge_properties WHERE workspace_id = workspace() AND edge_id = ?1 AND value IS NOT NULL",
         )?;
         let rows = stmt.query_map(
             rusqlite::params![edge_id.as_bytes().as_slice()],
@@ -1110,7 +1965,7 @@ impl Storage for SqliteStorage {
         key: &str,
     ) -> Result<Option<FieldValue>, StorageError> {
         let result = self.conn.query_row(
-            "SELECT value FROM edge_properties WHERE edge_id = ?1 AND property_key = ?2 AND value IS NOT NULL",
+            "SELECT value FROM edge_properties WHERE workspace_id = workspace() AND edge_id = ?1 AND property_key = ?2 AND value IS NOT NULL",
             rusqlite::params![edge_id.as_bytes().as_slice(), key],
             |row| {
                 let val_bytes: Vec<u8> = row.get(0)?;
@@ -1134,7 +1989,7 @@ impl Storage for SqliteStorage {
         key: &str,
     ) -> Result<Option<(ActorId, Hlc)>, StorageError> {
         let result = self.conn.query_row(
-            "SELECT source_actor, updated_at FROM edge_properties WHERE edge_id = ?1 AND property_key = ?2",
+            "SELECT source_actor, updated_at FROM edge_properties WHERE workspace_id = workspace() AND edge_id = ?1 AND property_key = ?2",
             rusqlite::params![edge_id.as_bytes().as_slice(), key],
             |row| {
                 let actor_bytes: Vec<u8> = row.get(0)?;
@@ -1155,7 +2010,7 @@ impl Storage for SqliteStorage {
 
     fn insert_conflict(&mut self, record: &ConflictRecord) -> Result<(), StorageError> {
         self.conn.execute(
-            "INSERT INTO conflicts (conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO conflicts (workspace_id, conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?6)",
             rusqlite::params![
                 record.conflict_id.as_bytes().as_slice(),
                 record.entity_id.as_bytes().as_slice(),
@@ -1167,7 +2022,7 @@ impl Storage for SqliteStorage {
         )?;
         for val in &record.values {
             self.conn.execute(
-                "INSERT INTO conflict_values (conflict_id, actor_id, hlc, op_id, value) VALUES (?1, ?2, ?3, ?4, ?5)",
+                "INSERT INTO conflict_values (workspace_id, conflict_id, actor_id, hlc, op_id, value) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5)",
                 rusqlite::params![
                     record.conflict_id.as_bytes().as_slice(),
                     val.actor_id.as_bytes().as_slice(),
@@ -1189,7 +2044,7 @@ impl Storage for SqliteStorage {
         resolved_value: Option<Vec<u8>>,
     ) -> Result<(), StorageError> {
         self.conn.execute(
-            "UPDATE conflicts SET status = 'resolved', resolved_at = ?1, resolved_by = ?2, resolved_op_id = ?3, resolved_value = ?4 WHERE conflict_id = ?5",
+            "UPDATE conflicts SET status = 'resolved', resolved_at = ?1, resolved_by = ?2, resolved_op_id = ?3, resolved_value = ?4 WHERE conflict_id = ?5 AND workspace_id = workspace()",
             rusqlite::params![
                 &resolved_at.to_bytes()[..],
                 resolved_by.as_bytes().as_slice(),
@@ -1206,7 +2061,7 @@ impl Storage for SqliteStorage {
         entity_id: EntityId,
     ) -> Result<Vec<ConflictRecord>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle, resolved_at, resolved_by, resolved_op_id, resolved_value, reopened_at, reopened_by_op FROM conflicts WHERE entity_id = ?1 AND status = 'open'",
+            "SELECT conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle, resolved_at, resolved_by, resolved_op_id, resolved_value, reopened_at, reopened_by_op FROM conflicts WHERE workspace_id = workspace() AND entity_id = ?1 AND status = 'open'",
         )?;
         let rows = stmt.query_map(
             rusqlite::params![entity_id.as_bytes().as_slice()],
@@ -1221,12 +2076,26 @@ impl Storage for SqliteStorage {
         Ok(result)
     }
 
+    fn get_open_conflicts(&self) -> Result<Vec<ConflictRecord>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle, resolved_at, resolved_by, resolved_op_id, resolved_value, reopened_at, reopened_by_op FROM conflicts WHERE workspace_id = workspace() AND status = 'open' ORDER BY detected_at",
+        )?;
+        let rows = stmt.query_map([], parse_conflict_row)?;
+        let mut result = Vec::new();
+        for row in rows {
+            let mut record = row.map_err(StorageError::Sqlite).and_then(|r| r)?;
+            record.values = load_conflict_values(&self.conn, record.conflict_id)?;
+            result.push(record);
+        }
+        Ok(result)
+    }
+
     fn get_conflict(
         &self,
         conflict_id: ConflictId,
     ) -> Result<Option<ConflictRecord>, StorageError> {
         let result = self.conn.query_row(
-            "SELECT conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle, resolved_at, resolved_by, resolved_op_id, resolved_value, reopened_at, reopened_by_op FROM conflicts WHERE conflict_id = ?1",
+            "SELECT conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle, resolved_at, resolved_by, resolved_op_id, resolved_value, reopened_at, reopened_by_op FROM conflicts WHERE workspace_id = workspace() AND conflict_id = ?1",
             rusqlite::params![conflict_id.as_bytes().as_slice()],
             parse_conflict_row,
         );
@@ -1247,7 +2116,7 @@ impl Storage for SqliteStorage {
         field_key: &str,
     ) -> Result<Option<ConflictRecord>, StorageError> {
         let result = self.conn.query_row(
-            "SELECT conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle, resolved_at, resolved_by, resolved_op_id, resolved_value, reopened_at, reopened_by_op FROM conflicts WHERE entity_id = ?1 AND field_key = ?2 AND status = 'open'",
+            "SELECT conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle, resolved_at, resolved_by, resolved_op_id, resolved_value, reopened_at, reopened_by_op FROM conflicts WHERE workspace_id = workspace() AND entity_id = ?1 AND field_key = ?2 AND status = 'open'",
             rusqlite::params![entity_id.as_bytes().as_slice(), field_key],
             parse_conflict_row,
         );
@@ -1268,7 +2137,7 @@ impl Storage for SqliteStorage {
         field_key: &str,
     ) -> Result<Option<ConflictRecord>, StorageError> {
         let result = self.conn.query_row(
-            "SELECT conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle, resolved_at, resolved_by, resolved_op_id, resolved_value, reopened_at, reopened_by_op FROM conflicts WHERE entity_id = ?1 AND field_key = ?2 ORDER BY detected_at DESC LIMIT 1",
+            "SELECT conflict_id, entity_id, field_key, status, detected_at, detected_in_bundle, resolved_at, resolved_by, resolved_op_id, resolved_value, reopened_at, reopened_by_op FROM conflicts WHERE workspace_id = workspace() AND entity_id = ?1 AND field_key = ?2 ORDER BY detected_at DESC LIMIT 1",
             rusqlite::params![entity_id.as_bytes().as_slice(), field_key],
             parse_conflict_row,
         );
@@ -1283,29 +2152,67 @@ impl Storage for SqliteStorage {
         }
     }
 
-    fn reopen_conflict(
+    fn set_conflict_triage(
         &mut self,
         conflict_id: ConflictId,
-        reopened_at: Hlc,
-        reopened_by_op: OpId,
-        new_values: &[ConflictValue],
+        triage: ConflictTriage,
     ) -> Result<(), StorageError> {
-        self.conn.execute(
-            "UPDATE conflicts SET status = 'open', reopened_at = ?1, reopened_by_op = ?2 WHERE conflict_id = ?3",
-            rusqlite::params![
-                &reopened_at.to_bytes()[..],
-                reopened_by_op.as_bytes().as_slice(),
-                conflict_id.as_bytes().as_slice(),
-            ],
+        let updated = self.conn.execute(
+            "UPDATE conflicts SET seen_at = ?1, snoozed_until = ?2 WHERE conflict_id = ?3 AND workspace_id = workspace()",
+            rusqlite::params![triage.seen_at, triage.snoozed_until, conflict_id.as_bytes().as_slice()],
         )?;
-        // Replace all branch tips with the new values
+        if updated == 0 {
+            return Err(StorageError::NotFound(format!("conflict {conflict_id}")));
+        }
+        Ok(())
+    }
+
+    fn get_conflict_triage(
+        &self,
+        conflict_id: ConflictId,
+    ) -> Result<Option<ConflictTriage>, StorageError> {
+        let mut stmt = self.conn.prepare_cached("SELECT seen_at, snoozed_until FROM conflicts WHERE workspace_id = workspace() AND conflict_id = ?1")?;
+        let mut rows = stmt.query(rusqlite::params![conflict_id.as_bytes().as_slice()])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(ConflictTriage { seen_at: row.get(0)?, snoozed_until: row.get(1)? })),
+            None => Ok(None),
+        }
+    }
+
+    fn count_unseen_conflicts(&self, now: i64) -> Result<u64, StorageError> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM conflicts
+             WHERE workspace_id = workspace() AND status = 'open' AND seen_at IS NULL AND (snoozed_until IS NULL OR snoozed_until <= ?1)",
+            [now],
+            |row| row.get(0),
+        )?;
+        Ok(count as u64)
+    }
+
+    fn reopen_conflict(
+        &mut self,
+        conflict_id: ConflictId,
+        reopened_at: Hlc,
+        reopened_by_op: OpId,
+        new_values: &[ConflictValue],
+    ) -> Result<(), StorageError> {
+        self.conn.execute(
+            "UPDATE conflicts SET status = 'open', reopened_at = ?1, reopened_by_op = ?2, seen_at = NULL, snoozed_until = NULL
+             WHERE conflict_id = ?3 AND workspace_id = workspace()",
+            rusqlite::params![
+                &reopened_at.to_bytes()[..],
+                reopened_by_op.as_bytes().as_slice(),
+                conflict_id.as_bytes().as_slice(),
+            ],
+        )?;
+        // Replace all branch tips with the new values
         self.conn.execute(
-            "DELETE FROM conflict_values WHERE conflict_id = ?1",
+            "DELETE FROM conflict_values WHERE workspace_id = workspace() AND conflict_id = ?1",
             rusqlite::params![conflict_id.as_bytes().as_slice()],
         )?;
         for val in new_values {
             self.conn.execute(
-                "INSERT INTO conflict_values (conflict_id, actor_id, hlc, op_id, value) VALUES (?1, ?2, ?3, ?4, ?5)",
+                "INSERT INTO conflict_values (workspace_id, conflict_id, actor_id, hlc, op_id, value) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5)",
                 rusqlite::params![
                     conflict_id.as_bytes().as_slice(),
                     val.actor_id.as_bytes().as_slice(),
@@ -1324,7 +2231,7 @@ impl Storage for SqliteStorage {
         value: &ConflictValue,
     ) -> Result<(), StorageError> {
         self.conn.execute(
-            "INSERT INTO conflict_values (conflict_id, actor_id, hlc, op_id, value) VALUES (?1, ?2, ?3, ?4, ?5)
+            "INSERT INTO conflict_values (workspace_id, conflict_id, actor_id, hlc, op_id, value) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5)
              ON CONFLICT(conflict_id, actor_id) DO UPDATE SET hlc = excluded.hlc, op_id = excluded.op_id, value = excluded.value",
             rusqlite::params![
                 conflict_id.as_bytes().as_slice(),
@@ -1334,6 +2241,10 @@ impl Storage for SqliteStorage {
                 value.value.as_deref(),
             ],
         )?;
+        self.conn.execute(
+            "UPDATE conflicts SET seen_at = NULL, snoozed_until = NULL WHERE conflict_id = ?1 AND workspace_id = workspace()",
+            rusqlite::params![conflict_id.as_bytes().as_slice()],
+        )?;
         Ok(())
     }
 
@@ -1342,7 +2253,7 @@ impl Storage for SqliteStorage {
         bundle_id: BundleId,
     ) -> Result<Option<VectorClock>, StorageError> {
         let result = self.conn.query_row(
-            "SELECT creator_vector_clock FROM bundles WHERE bundle_id = ?1",
+            "SELECT creator_vector_clock FROM bundles WHERE workspace_id = workspace() AND bundle_id = ?1",
             rusqlite::params![bundle_id.as_bytes().as_slice()],
             |row| {
                 let bytes: Option<Vec<u8>> = row.get(0)?;
@@ -1360,6 +2271,113 @@ impl Storage for SqliteStorage {
             Err(e) => Err(StorageError::Sqlite(e)),
         }
     }
+
+    fn get_bundle(&self, bundle_id: BundleId) -> Result<Option<Bundle>, StorageError> {
+        match read_bundle(&self.conn, bundle_id) {
+            Ok(bundle) => Ok(Some(bundle)),
+            Err(StorageError::Sqlite(rusqlite::Error::QueryReturnedNoRows)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn get_bundle_ids_in_range(&self, range: &HlcRange) -> Result<Vec<(Hlc, BundleId)>, StorageError> {
+        let end_bytes = range.end.map(|end| end.to_bytes().to_vec());
+        let mut stmt = self.conn.prepare(
+            "SELECT hlc, bundle_id FROM bundles
+             WHERE workspace_id = workspace() AND hlc >= ?1 AND (?2 IS NULL OR hlc < ?2)
+             ORDER BY hlc, bundle_id",
+        )?;
+        let rows = stmt
+            .query_map(
+                rusqlite::params![range.start.to_bytes().as_slice(), end_bytes],
+                |row| {
+                    let hlc_bytes: Vec<u8> = row.get(0)?;
+                    let bundle_id_bytes: Vec<u8> = row.get(1)?;
+                    Ok((hlc_bytes, bundle_id_bytes))
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(hlc_bytes, bundle_id_bytes)| {
+                Ok((
+                    Hlc::from_bytes(&to_array::<12>(hlc_bytes, "hlc")?),
+                    BundleId::from_bytes(to_array::<16>(bundle_id_bytes, "bundle_id")?),
+                ))
+            })
+            .collect()
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_field_source_bundle_vc(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<(ActorId, Hlc, OpId, Option<VectorClock>)>, StorageError> {
+        let result = self.conn.query_row(
+            "SELECT f.source_actor, f.updated_at, f.source_op, b.creator_vector_clock
+             FROM fields f
+             JOIN oplog o ON o.op_id = f.source_op
+             JOIN bundles b ON b.bundle_id = o.bundle_id
+             WHERE f.workspace_id = workspace() AND f.entity_id = ?1 AND f.field_key = ?2",
+            rusqlite::params![entity_id.as_bytes().as_slice(), field_key],
+            |row| {
+                let actor_bytes: Vec<u8> = row.get(0)?;
+                let hlc_bytes: Vec<u8> = row.get(1)?;
+                let op_id_bytes: Vec<u8> = row.get(2)?;
+                let vc_bytes: Option<Vec<u8>> = row.get(3)?;
+                Ok((actor_bytes, hlc_bytes, op_id_bytes, vc_bytes))
+            },
+        );
+        match result {
+            Ok((actor_bytes, hlc_bytes, op_id_bytes, vc_bytes)) => {
+                let actor = ActorId::from_bytes(to_array::<32>(actor_bytes, "source_actor")?);
+                let hlc = Hlc::from_bytes(&to_array::<12>(hlc_bytes, "updated_at")?);
+                let op_id = OpId::from_bytes(to_array::<16>(op_id_bytes, "source_op")?);
+                let vc = match vc_bytes {
+                    Some(bytes) => Some(VectorClock::from_msgpack(&bytes)
+                        .map_err(|e| StorageError::Serialization(e.to_string()))?),
+                    None => None,
+                };
+                Ok(Some((actor, hlc, op_id, vc)))
+            }
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(StorageError::Sqlite(e)),
+        }
+    }
+
+    fn get_op_field_value(&self, op_id: OpId) -> Result<Option<Vec<u8>>, StorageError> {
+        let result = self.conn.query_row(
+            "SELECT payload FROM oplog WHERE workspace_id = workspace() AND op_id = ?1",
+            rusqlite::params![op_id.as_bytes().as_slice()],
+            |row| {
+                let payload_bytes: Vec<u8> = row.get(0)?;
+                Ok(payload_bytes)
+            },
+        );
+        match result {
+            Ok(payload_bytes) => {
+                let payload = OperationPayload::from_msgpack(&payload_bytes)?;
+                match payload {
+                    OperationPayload::SetField { value, .. } => {
+                        let bytes = value.to_msgpack()
+                            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+                        Ok(Some(bytes))
+                    }
+                    OperationPayload::ClearField { .. } => Ok(None),
+                    OperationPayload::ResolveConflict { chosen_value: Some(v), .. } => {
+                        let bytes = v.to_msgpack()
+                            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+                        Ok(Some(bytes))
+                    }
+                    OperationPayload::ResolveConflict { chosen_value: None, .. } => Ok(None),
+                    _ => Ok(None),
+                }
+            }
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(StorageError::Sqlite(e)),
+        }
+    }
 }
 
 /// Parse a conflict row from the conflicts table (no value columns — values loaded separately).
@@ -1411,7 +2429,7 @@ fn parse_conflict_row(row: &rusqlite::Row) -> rusqlite::Result<Result<ConflictRe
 /// Load all competing values for a conflict from the conflict_values table.
 fn load_conflict_values(conn: &Connection, conflict_id: ConflictId) -> Result<Vec<ConflictValue>, StorageError> {
     let mut stmt = conn.prepare(
-        "SELECT actor_id, hlc, op_id, value FROM conflict_values WHERE conflict_id = ?1",
+        "SELECT actor_id, hlc, op_id, value FROM conflict_values WHERE workspace_id = workspace() AND conflict_id = ?1",
     )?;
     let rows = stmt.query_map(
         rusqlite::params![conflict_id.as_bytes().as_slice()],
@@ -1453,8 +2471,8 @@ impl std::error::Error for OpaqueStorageError {}
 // Overlay CRUD (local-only, not on Storage trait)
 // ============================================================================
 
-impl SqliteStorage {
-    pub fn insert_overlay(
+impl OverlayStore for SqliteStorage {
+    fn insert_overlay(
         &mut self,
         overlay_id: OverlayId,
         display_name: &str,
@@ -1463,7 +2481,7 @@ impl SqliteStorage {
         created_at: &Hlc,
     ) -> Result<(), StorageError> {
         self.conn.execute(
-            "INSERT INTO overlays (overlay_id, display_name, source, status, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+            "INSERT INTO overlays (workspace_id, overlay_id, display_name, source, status, created_at, updated_at) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?5)",
             rusqlite::params![
                 overlay_id.as_bytes().as_slice(),
                 display_name,
@@ -1475,14 +2493,14 @@ impl SqliteStorage {
         Ok(())
     }
 
-    pub fn update_overlay_status(
+    fn update_overlay_status(
         &mut self,
         overlay_id: OverlayId,
         status: &str,
         updated_at: &Hlc,
     ) -> Result<(), StorageError> {
         self.conn.execute(
-            "UPDATE overlays SET status = ?1, updated_at = ?2 WHERE overlay_id = ?3",
+            "UPDATE overlays SET status = ?1, updated_at = ?2 WHERE overlay_id = ?3 AND workspace_id = workspace()",
             rusqlite::params![
                 status,
                 &updated_at.to_bytes()[..],
@@ -1492,26 +2510,26 @@ impl SqliteStorage {
         Ok(())
     }
 
-    pub fn delete_overlay(&mut self, overlay_id: OverlayId) -> Result<(), StorageError> {
+    fn delete_overlay(&mut self, overlay_id: OverlayId) -> Result<(), StorageError> {
         // Delete overlay ops first (FK constraint)
         self.conn.execute(
-            "DELETE FROM overlay_ops WHERE overlay_id = ?1",
+            "DELETE FROM overlay_ops WHERE workspace_id = workspace() AND overlay_id = ?1",
             rusqlite::params![overlay_id.as_bytes().as_slice()],
         )?;
         self.conn.execute(
-            "DELETE FROM overlays WHERE overlay_id = ?1",
+            "DELETE FROM overlays WHERE workspace_id = workspace() AND overlay_id = ?1",
             rusqlite::params![overlay_id.as_bytes().as_slice()],
         )?;
         Ok(())
     }
 
     #[allow(clippy::type_complexity)]
-    pub fn get_overlay(
+    fn get_overlay(
         &self,
         overlay_id: OverlayId,
     ) -> Result<Option<(OverlayId, String, String, String, Hlc, Hlc)>, StorageError> {
         let result = self.conn.query_row(
-            "SELECT overlay_id, display_name, source, status, created_at, updated_at FROM overlays WHERE overlay_id = ?1",
+            "SELECT overlay_id, display_name, source, status, created_at, updated_at FROM overlays WHERE workspace_id = workspace() AND overlay_id = ?1",
             rusqlite::params![overlay_id.as_bytes().as_slice()],
             |row| {
                 let id_bytes: Vec<u8> = row.get(0)?;
@@ -1535,12 +2553,12 @@ impl SqliteStorage {
         }
     }
 
-    pub fn list_overlays_by_status(
+    fn list_overlays_by_status(
         &self,
         status: &str,
     ) -> Result<Vec<(OverlayId, String, String, Hlc)>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT overlay_id, display_name, source, created_at FROM overlays WHERE status = ?1 ORDER BY created_at",
+            "SELECT overlay_id, display_name, source, created_at FROM overlays WHERE workspace_id = workspace() AND status = ?1 ORDER BY created_at",
         )?;
         let rows = stmt.query_map(rusqlite::params![status], |row| {
             let id_bytes: Vec<u8> = row.get(0)?;
@@ -1559,8 +2577,50 @@ impl SqliteStorage {
         Ok(result)
     }
 
+    fn set_overlay_drift_policy(&mut self, overlay_id: OverlayId, policy: &str) -> Result<(), StorageError> {
+        let updated = self.conn.execute(
+            "UPDATE overlays SET drift_policy = ?1 WHERE overlay_id = ?2 AND workspace_id = workspace()",
+            rusqlite::params![policy, overlay_id.as_bytes().as_slice()],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::NotFound(format!("overlay {overlay_id}")));
+        }
+        Ok(())
+    }
+
+    fn get_overlay_drift_policy(&self, overlay_id: OverlayId) -> Result<Option<String>, StorageError> {
+        let mut stmt = self.conn.prepare_cached("SELECT drift_policy FROM overlays WHERE workspace_id = workspace() AND overlay_id = ?1")?;
+        let mut rows = stmt.query(rusqlite::params![overlay_id.as_bytes().as_slice()])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_overlay_origin(&mut self, overlay_id: OverlayId, origin: &OverlayOrigin) -> Result<(), StorageError> {
+        let updated = self.conn.execute(
+            "UPDATE overlays SET script_id = ?1, script_execution_id = ?2, auto_commit = ?3 WHERE overlay_id = ?4 AND workspace_id = workspace()",
+            rusqlite::params![origin.script_id, origin.run_id, origin.auto_commit, overlay_id.as_bytes().as_slice()],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::NotFound(format!("overlay {overlay_id}")));
+        }
+        Ok(())
+    }
+
+    fn get_overlay_origin(&self, overlay_id: OverlayId) -> Result<Option<OverlayOrigin>, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT script_id, script_execution_id, auto_commit FROM overlays WHERE workspace_id = workspace() AND overlay_id = ?1")?;
+        let mut rows = stmt.query(rusqlite::params![overlay_id.as_bytes().as_slice()])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(OverlayOrigin { script_id: row.get(0)?, run_id: row.get(1)?, auto_commit: row.get(2)? })),
+            None => Ok(None),
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
-    pub fn insert_overlay_op(
+    fn insert_overlay_op(
         &mut self,
         overlay_id: OverlayId,
         op_id: OpId,
@@ -1573,7 +2633,7 @@ impl SqliteStorage {
     ) -> Result<i64, StorageError> {
         let entity_id_blob = entity_id.map(|eid| eid.as_bytes().to_vec());
         self.conn.execute(
-            "INSERT INTO overlay_ops (overlay_id, op_id, hlc, payload, entity_id, field_key, op_type, canonical_value_at_creation) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            "INSERT INTO overlay_ops (workspace_id, overlay_id, op_id, hlc, payload, entity_id, field_key, op_type, canonical_value_at_creation) VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             rusqlite::params![
                 overlay_id.as_bytes().as_slice(),
                 op_id.as_bytes().as_slice(),
@@ -1588,21 +2648,21 @@ impl SqliteStorage {
         Ok(self.conn.last_insert_rowid())
     }
 
-    pub fn delete_overlay_op(&mut self, rowid: i64) -> Result<(), StorageError> {
+    fn delete_overlay_op(&mut self, rowid: i64) -> Result<(), StorageError> {
         self.conn.execute(
-            "DELETE FROM overlay_ops WHERE rowid = ?1",
+            "DELETE FROM overlay_ops WHERE workspace_id = workspace() AND rowid = ?1",
             rusqlite::params![rowid],
         )?;
         Ok(())
     }
 
     #[allow(clippy::type_complexity)]
-    pub fn get_overlay_ops(
+    fn get_overlay_ops(
         &self,
         overlay_id: OverlayId,
     ) -> Result<Vec<(i64, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, String, Option<Vec<u8>>, bool, Option<String>)>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT rowid, op_id, hlc, payload, entity_id, op_type, canonical_value_at_creation, canonical_drifted, field_key FROM overlay_ops WHERE overlay_id = ?1 ORDER BY rowid",
+            "SELECT rowid, op_id, hlc, payload, entity_id, op_type, canonical_value_at_creation, canonical_drifted, field_key FROM overlay_ops WHERE workspace_id = workspace() AND overlay_id = ?1 ORDER BY rowid",
         )?;
         let rows = stmt.query_map(
             rusqlite::params![overlay_id.as_bytes().as_slice()],
@@ -1627,16 +2687,14 @@ impl SqliteStorage {
         Ok(result)
     }
 
-    /// Get the latest overlay op for a specific field on a specific entity.
-    /// Returns (rowid, payload_bytes) or None.
-    pub fn get_latest_overlay_field_op(
+    fn get_latest_overlay_field_op(
         &self,
         overlay_id: OverlayId,
         entity_id: EntityId,
         field_key: &str,
     ) -> Result<Option<(i64, Vec<u8>)>, StorageError> {
         let result = self.conn.query_row(
-            "SELECT rowid, payload FROM overlay_ops WHERE overlay_id = ?1 AND entity_id = ?2 AND field_key = ?3 ORDER BY rowid DESC LIMIT 1",
+            "SELECT rowid, payload FROM overlay_ops WHERE workspace_id = workspace() AND overlay_id = ?1 AND entity_id = ?2 AND field_key = ?3 ORDER BY rowid DESC LIMIT 1",
             rusqlite::params![
                 overlay_id.as_bytes().as_slice(),
                 entity_id.as_bytes().as_slice(),
@@ -1655,40 +2713,35 @@ impl SqliteStorage {
         }
     }
 
-    /// Count overlay ops for an overlay.
-    pub fn count_overlay_ops(&self, overlay_id: OverlayId) -> Result<u64, StorageError> {
+    fn count_overlay_ops(&self, overlay_id: OverlayId) -> Result<u64, StorageError> {
         let count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM overlay_ops WHERE overlay_id = ?1",
+            "SELECT COUNT(*) FROM overlay_ops WHERE workspace_id = workspace() AND overlay_id = ?1",
             rusqlite::params![overlay_id.as_bytes().as_slice()],
             |row| row.get(0),
         )?;
         Ok(count as u64)
     }
 
-    /// Mark SetField/ClearField overlay ops for an entity+field as drifted (across all overlays).
-    /// Returns the number of rows updated.
-    pub fn mark_overlay_ops_drifted(
+    fn mark_overlay_ops_drifted(
         &self,
         entity_id: EntityId,
         field_key: &str,
     ) -> Result<u64, StorageError> {
         let rows_affected = self.conn.execute(
-            "UPDATE overlay_ops SET canonical_drifted = 1 WHERE entity_id = ?1 AND field_key = ?2 AND canonical_drifted = 0",
+            "UPDATE overlay_ops SET canonical_drifted = 1 WHERE entity_id = ?1 AND field_key = ?2 AND canonical_drifted = 0 AND workspace_id = workspace()",
             rusqlite::params![entity_id.as_bytes().as_slice(), field_key],
         )?;
         Ok(rows_affected as u64)
     }
 
-    /// Clear the canonical_drifted flag for overlay ops matching a specific field
-    /// in a specific overlay+entity.
-    pub fn clear_drift_flag(
+    fn clear_drift_flag(
         &self,
         overlay_id: OverlayId,
         entity_id: EntityId,
         field_key: &str,
     ) -> Result<(), StorageError> {
         self.conn.execute(
-            "UPDATE overlay_ops SET canonical_drifted = 0 WHERE overlay_id = ?1 AND entity_id = ?2 AND field_key = ?3 AND canonical_drifted = 1",
+            "UPDATE overlay_ops SET canonical_drifted = 0 WHERE overlay_id = ?1 AND entity_id = ?2 AND field_key = ?3 AND canonical_drifted = 1 AND workspace_id = workspace()",
             rusqlite::params![
                 overlay_id.as_bytes().as_slice(),
                 entity_id.as_bytes().as_slice(),
@@ -1698,9 +2751,7 @@ impl SqliteStorage {
         Ok(())
     }
 
-    /// Update canonical_value_at_creation for overlay ops matching a specific field
-    /// in a specific overlay+entity.
-    pub fn update_canonical_value_at_creation(
+    fn update_canonical_value_at_creation(
         &self,
         overlay_id: OverlayId,
         entity_id: EntityId,
@@ -1708,7 +2759,7 @@ impl SqliteStorage {
         new_value: Option<&[u8]>,
     ) -> Result<(), StorageError> {
         self.conn.execute(
-            "UPDATE overlay_ops SET canonical_value_at_creation = ?4 WHERE overlay_id = ?1 AND entity_id = ?2 AND field_key = ?3",
+            "UPDATE overlay_ops SET canonical_value_at_creation = ?4 WHERE overlay_id = ?1 AND entity_id = ?2 AND field_key = ?3 AND workspace_id = workspace()",
             rusqlite::params![
                 overlay_id.as_bytes().as_slice(),
                 entity_id.as_bytes().as_slice(),
@@ -1719,15 +2770,13 @@ impl SqliteStorage {
         Ok(())
     }
 
-    /// Get overlay ops where canonical_drifted = 1 for a specific overlay.
-    /// Returns the same tuple type as `get_overlay_ops`.
     #[allow(clippy::type_complexity)]
-    pub fn get_drifted_overlay_ops(
+    fn get_drifted_overlay_ops(
         &self,
         overlay_id: OverlayId,
     ) -> Result<Vec<(i64, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, String, Option<Vec<u8>>, bool, Option<String>)>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT rowid, op_id, hlc, payload, entity_id, op_type, canonical_value_at_creation, canonical_drifted, field_key FROM overlay_ops WHERE overlay_id = ?1 AND canonical_drifted = 1 ORDER BY rowid",
+            "SELECT rowid, op_id, hlc, payload, entity_id, op_type, canonical_value_at_creation, canonical_drifted, field_key FROM overlay_ops WHERE workspace_id = workspace() AND overlay_id = ?1 AND canonical_drifted = 1 ORDER BY rowid",
         )?;
         let rows = stmt.query_map(
             rusqlite::params![overlay_id.as_bytes().as_slice()],
@@ -1752,29 +2801,26 @@ impl SqliteStorage {
         Ok(result)
     }
 
-    /// Count overlay ops with canonical_drifted = 1 for a specific overlay.
-    pub fn count_unresolved_drift(
+    fn count_unresolved_drift(
         &self,
         overlay_id: OverlayId,
     ) -> Result<u64, StorageError> {
         let count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM overlay_ops WHERE overlay_id = ?1 AND canonical_drifted = 1",
+            "SELECT COUNT(*) FROM overlay_ops WHERE workspace_id = workspace() AND overlay_id = ?1 AND canonical_drifted = 1",
             rusqlite::params![overlay_id.as_bytes().as_slice()],
             |row| row.get(0),
         )?;
         Ok(count as u64)
     }
 
-    /// Delete overlay ops for a specific field (used for knockout).
-    /// Returns the number of rows deleted.
-    pub fn delete_overlay_ops_for_field(
+    fn delete_overlay_ops_for_field(
         &self,
         overlay_id: OverlayId,
         entity_id: EntityId,
         field_key: &str,
     ) -> Result<u64, StorageError> {
         let rows_affected = self.conn.execute(
-            "DELETE FROM overlay_ops WHERE overlay_id = ?1 AND entity_id = ?2 AND field_key = ?3",
+            "DELETE FROM overlay_ops WHERE workspace_id = workspace() AND overlay_id = ?1 AND entity_id = ?2 AND field_key = ?3",
             rusqlite::params![
                 overlay_id.as_bytes().as_slice(),
                 entity_id.as_bytes().as_slice(),
@@ -1784,3 +2830,979 @@ impl SqliteStorage {
         Ok(rows_affected as u64)
     }
 }
+
+// ============================================================================
+// Bundle Labels (local-only, not on Storage trait)
+// ============================================================================
+
+impl LabelStore for SqliteStorage {
+    fn set_bundle_label(
+        &mut self,
+        bundle_id: BundleId,
+        label: &str,
+    ) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT INTO bundle_labels (workspace_id, bundle_id, label) VALUES (workspace(), ?1, ?2)
+             ON CONFLICT(bundle_id) DO UPDATE SET label = excluded.label WHERE bundle_labels.workspace_id = excluded.workspace_id",
+            rusqlite::params![bundle_id.as_bytes().as_slice(), label],
+        )?;
+        Ok(())
+    }
+
+    fn get_bundle_label(&self, bundle_id: BundleId) -> Result<Option<String>, StorageError> {
+        let result = self.conn.query_row(
+            "SELECT label FROM bundle_labels WHERE workspace_id = workspace() AND bundle_id = ?1",
+            rusqlite::params![bundle_id.as_bytes().as_slice()],
+            |row| row.get(0),
+        );
+        match result {
+            Ok(label) => Ok(Some(label)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(StorageError::Sqlite(e)),
+        }
+    }
+
+    fn list_bundles_with_labels(&self) -> Result<Vec<(Bundle, Option<String>)>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT b.bundle_id, l.label FROM bundles b
+             LEFT JOIN bundle_labels l ON l.bundle_id = b.bundle_id
+             WHERE b.workspace_id = workspace()
+             ORDER BY b.hlc, b.bundle_id",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                let bundle_id_bytes: Vec<u8> = row.get(0)?;
+                let label: Option<String> = row.get(1)?;
+                Ok((bundle_id_bytes, label))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut result = Vec::with_capacity(rows.len());
+        for (bundle_id_bytes, label) in rows {
+            let bundle_id = BundleId::from_bytes(to_array::<16>(bundle_id_bytes, "bundle_id")?);
+            result.push((read_bundle(&self.conn, bundle_id)?, label));
+        }
+        Ok(result)
+    }
+
+    fn get_bundles_by_tag(&self, tag: &str) -> Result<Vec<Bundle>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT b.bundle_id FROM bundle_tags t JOIN bundles b ON b.bundle_id = t.bundle_id
+             WHERE t.workspace_id = workspace() AND t.tag = ?1 ORDER BY b.hlc, b.bundle_id",
+        )?;
+        let ids = stmt.query_map([tag], |row| row.get::<_, Vec<u8>>(0))?.collect::<Result<Vec<_>, _>>()?;
+        ids.into_iter()
+            .map(|bytes| read_bundle(&self.conn, BundleId::from_bytes(to_array::<16>(bytes, "bundle_id")?)))
+            .collect()
+    }
+
+    fn get_bundles_by_session(&self, session_id: SessionId) -> Result<Vec<Bundle>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT b.bundle_id FROM bundle_sessions s JOIN bundles b ON b.bundle_id = s.bundle_id
+             WHERE s.workspace_id = workspace() AND s.session_id = ?1 ORDER BY b.hlc, b.bundle_id",
+        )?;
+        let ids = stmt
+            .query_map([session_id.as_bytes().as_slice()], |row| row.get::<_, Vec<u8>>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        ids.into_iter()
+            .map(|bytes| read_bundle(&self.conn, BundleId::from_bytes(to_array::<16>(bytes, "bundle_id")?)))
+            .collect()
+    }
+
+    fn get_bundle_by_idempotency_key(&self, actor_id: ActorId, key: &str) -> Result<Option<Bundle>, StorageError> {
+        let result = self.conn.query_row(
+            "SELECT bundle_id FROM bundle_idempotency_keys WHERE workspace_id = workspace() AND actor_id = ?1 AND idempotency_key = ?2",
+            rusqlite::params![actor_id.as_bytes().as_slice(), key],
+            |row| row.get::<_, Vec<u8>>(0),
+        );
+        match result {
+            Ok(bytes) => Ok(Some(read_bundle(&self.conn, BundleId::from_bytes(to_array::<16>(bytes, "bundle_id")?))?)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(StorageError::Sqlite(e)),
+        }
+    }
+}
+
+// ============================================================================
+// Pending Bundles (local-only, not on Storage trait)
+// ============================================================================
+
+impl PendingStore for SqliteStorage {
+    fn insert_pending_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<(), StorageError> {
+        let bundle_bytes = rmp_serde::to_vec(bundle)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let ops_bytes = rmp_serde::to_vec(operations)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.conn.execute(
+            "INSERT OR IGNORE INTO pending_bundles (workspace_id, bundle_id, actor_id, hlc, bundle, operations)
+             VALUES (workspace(), ?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                bundle.bundle_id.as_bytes().as_slice(),
+                bundle.actor_id.as_bytes().as_slice(),
+                bundle.hlc.to_bytes().as_slice(),
+                bundle_bytes,
+                ops_bytes,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn delete_pending_bundle(&mut self, bundle_id: BundleId) -> Result<bool, StorageError> {
+        let rows = self.conn.execute(
+            "DELETE FROM pending_bundles WHERE workspace_id = workspace() AND bundle_id = ?1",
+            rusqlite::params![bundle_id.as_bytes().as_slice()],
+        )?;
+        Ok(rows > 0)
+    }
+
+    fn list_pending_bundles(&self) -> Result<Vec<PendingBundleRecord>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT bundle, operations, received_at FROM pending_bundles WHERE workspace_id = workspace() ORDER BY hlc, bundle_id",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                let bundle_bytes: Vec<u8> = row.get(0)?;
+                let ops_bytes: Vec<u8> = row.get(1)?;
+                let received_at: i64 = row.get(2)?;
+                Ok((bundle_bytes, ops_bytes, received_at))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(bundle_bytes, ops_bytes, received_at)| {
+                Ok(PendingBundleRecord {
+                    bundle: rmp_serde::from_slice(&bundle_bytes)
+                        .map_err(|e| StorageError::Serialization(e.to_string()))?,
+                    operations: rmp_serde::from_slice(&ops_bytes)
+                        .map_err(|e| StorageError::Serialization(e.to_string()))?,
+                    received_at,
+                })
+            })
+            .collect()
+    }
+
+    fn count_pending_bundles(&self) -> Result<u64, StorageError> {
+        let count: i64 = self.conn.query_row("SELECT COUNT(*) FROM pending_bundles WHERE workspace_id = workspace()", [], |row| row.get(0))?;
+        Ok(count as u64)
+    }
+}
+
+// ============================================================================
+// Actor Directory (local-only, not on Storage trait)
+// ============================================================================
+
+impl ActorStore for SqliteStorage {
+    fn set_actor_display_name(&mut self, actor_id: ActorId, display_name: Option<&str>) -> Result<(), StorageError> {
+        self.conn.execute(
+            "UPDATE actors SET display_name = ?2 WHERE actor_id = ?1 AND workspace_id = workspace()",
+            rusqlite::params![actor_id.as_bytes().as_slice(), display_name],
+        )?;
+        Ok(())
+    }
+
+    fn list_actors(&self) -> Result<Vec<ActorRecord>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT a.actor_id, a.display_name, a.first_seen_at, v.max_hlc,
+                    (SELECT COUNT(*) FROM oplog o WHERE o.workspace_id = a.workspace_id AND o.actor_id = a.actor_id)
+             FROM actors a JOIN vector_clock v ON v.workspace_id = a.workspace_id AND v.actor_id = a.actor_id
+             WHERE a.workspace_id = workspace()
+             ORDER BY a.first_seen_at, a.actor_id",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, Vec<u8>>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                    row.get::<_, Vec<u8>>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(actor_bytes, display_name, first_bytes, last_bytes, op_count)| {
+                Ok(ActorRecord {
+                    actor_id: ActorId::from_bytes(to_array::<32>(actor_bytes, "actor_id")?),
+                    display_name,
+                    first_seen_at: Hlc::from_bytes(&to_array::<12>(first_bytes, "first_seen_at")?),
+                    last_seen_at: Hlc::from_bytes(&to_array::<12>(last_bytes, "max_hlc")?),
+                    op_count: op_count as u64,
+                })
+            })
+            .collect()
+    }
+}
+
+// ============================================================================
+// Actor Trust (local-only, not on Storage trait)
+// ============================================================================
+
+impl TrustStore for SqliteStorage {
+    fn set_actor_trust(&mut self, actor_id: ActorId, state: TrustState) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT INTO actor_trust (workspace_id, actor_id, state) VALUES (workspace(), ?1, ?2)
+             ON CONFLICT(workspace_id, actor_id) DO UPDATE SET state = excluded.state,
+                 updated_at = CAST(unixepoch('now','subsec') * 1000 AS INTEGER)",
+            rusqlite::params![actor_id.as_bytes().as_slice(), state.as_str()],
+        )?;
+        Ok(())
+    }
+
+    fn get_actor_trust(&self, actor_id: ActorId) -> Result<Option<TrustState>, StorageError> {
+        let result = self.conn.query_row(
+            "SELECT state FROM actor_trust WHERE workspace_id = workspace() AND actor_id = ?1",
+            rusqlite::params![actor_id.as_bytes().as_slice()],
+            |row| row.get::<_, String>(0),
+        );
+        match result {
+            Ok(state) => Ok(Some(TrustState::parse(&state)?)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn list_actor_trust(&self) -> Result<Vec<(ActorId, TrustState)>, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT actor_id, state FROM actor_trust WHERE workspace_id = workspace() ORDER BY actor_id")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(actor_bytes, state)| {
+                Ok((ActorId::from_bytes(to_array::<32>(actor_bytes, "actor_id")?), TrustState::parse(&state)?))
+            })
+            .collect()
+    }
+
+    fn insert_untrusted_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<(), StorageError> {
+        let bundle_bytes = rmp_serde::to_vec(bundle)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let ops_bytes = rmp_serde::to_vec(operations)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.conn.execute(
+            "INSERT OR IGNORE INTO untrusted_bundles (workspace_id, bundle_id, actor_id, hlc, bundle, operations)
+             VALUES (workspace(), ?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                bundle.bundle_id.as_bytes().as_slice(),
+                bundle.actor_id.as_bytes().as_slice(),
+                bundle.hlc.to_bytes().as_slice(),
+                bundle_bytes,
+                ops_bytes,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn list_untrusted_bundles(&self, actor_id: ActorId) -> Result<Vec<PendingBundleRecord>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT bundle, operations, received_at FROM untrusted_bundles
+             WHERE workspace_id = workspace() AND actor_id = ?1 ORDER BY hlc, bundle_id",
+        )?;
+        let rows = stmt
+            .query_map(rusqlite::params![actor_id.as_bytes().as_slice()], |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?, row.get::<_, i64>(2)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(bundle_bytes, ops_bytes, received_at)| {
+                Ok(PendingBundleRecord {
+                    bundle: rmp_serde::from_slice(&bundle_bytes)
+                        .map_err(|e| StorageError::Serialization(e.to_string()))?,
+                    operations: rmp_serde::from_slice(&ops_bytes)
+                        .map_err(|e| StorageError::Serialization(e.to_string()))?,
+                    received_at,
+                })
+            })
+            .collect()
+    }
+
+    fn delete_untrusted_bundles(&mut self, actor_id: ActorId) -> Result<usize, StorageError> {
+        Ok(self.conn.execute(
+            "DELETE FROM untrusted_bundles WHERE workspace_id = workspace() AND actor_id = ?1",
+            rusqlite::params![actor_id.as_bytes().as_slice()],
+        )?)
+    }
+
+    fn count_untrusted_bundles(&self) -> Result<u64, StorageError> {
+        let count: i64 = self.conn.query_row("SELECT COUNT(*) FROM untrusted_bundles WHERE workspace_id = workspace()", [], |row| row.get(0))?;
+        Ok(count as u64)
+    }
+}
+
+// ============================================================================
+// Quarantine (local-only, not on Storage trait)
+// ============================================================================
+
+const QUARANTINE_COLUMNS: &str = "quarantine_id, bundle_id, actor_id, raw, reason, received_at";
+
+fn quarantine_from_row(row: RawQuarantineRow) -> Result<QuarantinedBundle, StorageError> {
+    let (quarantine_id, bundle_id, actor_id, raw, reason, received_at) = row;
+    Ok(QuarantinedBundle {
+        quarantine_id,
+        bundle_id: bundle_id.map(|b| to_array::<16>(b, "bundle_id").map(BundleId::from_bytes)).transpose()?,
+        actor_id: actor_id.map(|a| to_array::<32>(a, "actor_id").map(ActorId::from_bytes)).transpose()?,
+        raw: RawBundle::from_msgpack(&raw).map_err(|e| StorageError::Serialization(e.to_string()))?,
+        reason,
+        received_at,
+    })
+}
+
+impl QuarantineStore for SqliteStorage {
+    fn insert_quarantined_bundle(
+        &mut self,
+        raw: &RawBundle,
+        bundle_id: Option<BundleId>,
+        actor_id: Option<ActorId>,
+        reason: &str,
+    ) -> Result<i64, StorageError> {
+        let raw_bytes = raw.to_msgpack().map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let digest = blake3::hash(&raw_bytes);
+        Ok(self.conn.query_row(
+            "INSERT INTO quarantined_bundles (workspace_id, digest, bundle_id, actor_id, raw, reason)
+             VALUES (workspace(), ?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(workspace_id, digest) DO UPDATE SET reason = excluded.reason
+             RETURNING quarantine_id",
+            rusqlite::params![
+                digest.as_bytes().as_slice(),
+                bundle_id.map(|b| b.as_bytes().to_vec()),
+                actor_id.map(|a| a.as_bytes().to_vec()),
+                raw_bytes,
+                reason,
+            ],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn get_quarantined_bundle(&self, quarantine_id: i64) -> Result<Option<QuarantinedBundle>, StorageError> {
+        let row = self.conn.query_row(
+            &format!("SELECT {QUARANTINE_COLUMNS} FROM quarantined_bundles WHERE workspace_id = workspace() AND quarantine_id = ?1"),
+            [quarantine_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+        );
+        match row {
+            Ok(row) => Ok(Some(quarantine_from_row(row)?)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn list_quarantined_bundles(&self) -> Result<Vec<QuarantinedBundle>, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {QUARANTINE_COLUMNS} FROM quarantined_bundles WHERE workspace_id = workspace() ORDER BY quarantine_id"))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)))?
+            .collect::<Result<Vec<RawQuarantineRow>, _>>()?;
+        rows.into_iter().map(quarantine_from_row).collect()
+    }
+
+    fn update_quarantine_reason(&mut self, quarantine_id: i64, reason: &str) -> Result<(), StorageError> {
+        self.conn.execute(
+            "UPDATE quarantined_bundles SET reason = ?2 WHERE quarantine_id = ?1 AND workspace_id = workspace()",
+            rusqlite::params![quarantine_id, reason],
+        )?;
+        Ok(())
+    }
+
+    fn delete_quarantined_bundle(&mut self, quarantine_id: i64) -> Result<bool, StorageError> {
+        Ok(self.conn.execute("DELETE FROM quarantined_bundles WHERE workspace_id = workspace() AND quarantine_id = ?1", [quarantine_id])? > 0)
+    }
+}
+
+// ============================================================================
+// Peers (local-only, not on Storage trait)
+// ============================================================================
+
+impl PeerStore for SqliteStorage {
+    fn upsert_peer(
+        &mut self,
+        peer_id: ActorId,
+        display_name: Option<&str>,
+    ) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT INTO peers (workspace_id, peer_id, display_name) VALUES (workspace(), ?1, ?2)
+             ON CONFLICT(workspace_id, peer_id) DO UPDATE SET display_name = COALESCE(excluded.display_name, peers.display_name)",
+            rusqlite::params![peer_id.as_bytes().as_slice(), display_name],
+        )?;
+        Ok(())
+    }
+
+    fn delete_peer(&mut self, peer_id: ActorId) -> Result<bool, StorageError> {
+        let rows = self.conn.execute(
+            "DELETE FROM peers WHERE workspace_id = workspace() AND peer_id = ?1",
+            rusqlite::params![peer_id.as_bytes().as_slice()],
+        )?;
+        Ok(rows > 0)
+    }
+
+    fn set_peer_acked_vc(
+        &mut self,
+        peer_id: ActorId,
+        acked_vc: &VectorClock,
+    ) -> Result<(), StorageError> {
+        let vc_bytes = acked_vc
+            .to_msgpack()
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO peer_sync_state (workspace_id, peer_id, acked_vector_clock, last_synced_at)
+             VALUES (workspace(), ?1, ?2, CAST(unixepoch('now','subsec') * 1000 AS INTEGER))",
+            rusqlite::params![peer_id.as_bytes().as_slice(), vc_bytes],
+        )?;
+        Ok(())
+    }
+
+    fn get_peer(&self, peer_id: ActorId) -> Result<Option<PeerRecord>, StorageError> {
+        Ok(self.query_peers(Some(peer_id))?.pop())
+    }
+
+    fn list_peers(&self) -> Result<Vec<PeerRecord>, StorageError> {
+        self.query_peers(None)
+    }
+}
+
+impl SqliteStorage {
+    fn query_peers(&self, peer_id: Option<ActorId>) -> Result<Vec<PeerRecord>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT p.peer_id, p.display_name, s.acked_vector_clock, s.last_synced_at
+             FROM peers p LEFT JOIN peer_sync_state s ON s.workspace_id = p.workspace_id AND s.peer_id = p.peer_id
+             WHERE p.workspace_id = workspace() AND (?1 IS NULL OR p.peer_id = ?1)
+             ORDER BY p.added_at, p.peer_id",
+        )?;
+        let rows = stmt
+            .query_map(
+                rusqlite::params![peer_id.map(|id| id.as_bytes().to_vec())],
+                |row| {
+                    let peer_id_bytes: Vec<u8> = row.get(0)?;
+                    let display_name: Option<String> = row.get(1)?;
+                    let vc_bytes: Option<Vec<u8>> = row.get(2)?;
+                    let last_synced_at: Option<i64> = row.get(3)?;
+                    Ok((peer_id_bytes, display_name, vc_bytes, last_synced_at))
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(peer_id_bytes, display_name, vc_bytes, last_synced_at)| {
+                let acked_vc = match vc_bytes {
+                    Some(bytes) => VectorClock::from_msgpack(&bytes)
+                        .map_err(|e| StorageError::Serialization(e.to_string()))?,
+                    None => VectorClock::new(),
+                };
+                Ok(PeerRecord {
+                    peer_id: ActorId::from_bytes(to_array::<32>(peer_id_bytes, "peer_id")?),
+                    display_name,
+                    acked_vc,
+                    last_synced_at,
+                })
+            })
+            .collect()
+    }
+}
+
+// ============================================================================
+// Webhooks (local-only, not on Storage trait)
+// ============================================================================
+
+impl WebhookStore for SqliteStorage {
+    fn insert_webhook(&mut self, webhook: &WebhookRecord) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT INTO webhooks (workspace_id, webhook_id, url, filter, cursor, attempts, retry_at, last_error)
+             VALUES (workspace(), ?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                webhook.webhook_id.as_bytes().as_slice(),
+                webhook.url,
+                webhook.filter,
+                webhook.cursor as i64,
+                webhook.attempts,
+                webhook.retry_at,
+                webhook.last_error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn delete_webhook(&mut self, webhook_id: WebhookId) -> Result<bool, StorageError> {
+        let rows = self.conn.execute(
+            "DELETE FROM webhooks WHERE workspace_id = workspace() AND webhook_id = ?1",
+            rusqlite::params![webhook_id.as_bytes().as_slice()],
+        )?;
+        Ok(rows > 0)
+    }
+
+    fn get_webhook(&self, webhook_id: WebhookId) -> Result<Option<WebhookRecord>, StorageError> {
+        Ok(self.query_webhooks(Some(webhook_id))?.pop())
+    }
+
+    fn list_webhooks(&self) -> Result<Vec<WebhookRecord>, StorageError> {
+        self.query_webhooks(None)
+    }
+
+    fn update_webhook_delivery(
+        &mut self,
+        webhook_id: WebhookId,
+        cursor: u64,
+        attempts: u32,
+        retry_at: Option<i64>,
+        last_error: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let rows = self.conn.execute(
+            "UPDATE webhooks SET cursor = ?2, attempts = ?3, retry_at = ?4, last_error = ?5 WHERE webhook_id = ?1 AND workspace_id = workspace()",
+            rusqlite::params![webhook_id.as_bytes().as_slice(), cursor as i64, attempts, retry_at, last_error],
+        )?;
+        if rows == 0 {
+            return Err(StorageError::NotFound(format!("webhook {webhook_id}")));
+        }
+        Ok(())
+    }
+}
+
+impl SqliteStorage {
+    fn query_webhooks(&self, webhook_id: Option<WebhookId>) -> Result<Vec<WebhookRecord>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT webhook_id, url, filter, cursor, attempts, retry_at, last_error FROM webhooks
+             WHERE workspace_id = workspace() AND (?1 IS NULL OR webhook_id = ?1)
+             ORDER BY rowid",
+        )?;
+        let rows = stmt
+            .query_map(rusqlite::params![webhook_id.map(|id| id.as_bytes().to_vec())], |row| {
+                Ok((
+                    row.get::<_, Vec<u8>>(0)?,
+                    WebhookRecord {
+                        webhook_id: WebhookId::from_bytes([0; 16]),
+                        url: row.get(1)?,
+                        filter: row.get(2)?,
+                        cursor: row.get::<_, i64>(3)? as u64,
+                        attempts: row.get(4)?,
+                        retry_at: row.get(5)?,
+                        last_error: row.get(6)?,
+                    },
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(id_bytes, webhook)| {
+                Ok(WebhookRecord { webhook_id: WebhookId::from_bytes(to_array::<16>(id_bytes, "webhook_id")?), ..webhook })
+            })
+            .collect()
+    }
+}
+
+// ============================================================================
+// Backup / Restore (local-only, not on Storage trait)
+// ============================================================================
+
+impl SqliteStorage {
+    /// Open an existing database without creating or migrating tables, for
+    /// inspecting a backup before restoring it.
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::open_read_only_workspace(path, DEFAULT_WORKSPACE)
+    }
+
+    /// `open_read_only` scoped to `workspace`.
+    pub fn open_read_only_workspace(path: impl AsRef<Path>, workspace: &str) -> Result<Self, StorageError> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Self::with_connection(conn, workspace)
+    }
+
+    /// Write a consistent copy of the database to `path` using SQLite's online
+    /// backup API. Safe to call while the storage is in use. The copy holds
+    /// every workspace in the file, not just this one.
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<(), StorageError> {
+        self.conn.backup(DatabaseName::Main, path, None)?;
+        Ok(())
+    }
+
+    /// Replace the entire database contents with the backup at `path`,
+    /// upgrading it in place if it was taken at an older schema version.
+    /// Every workspace in the file is replaced.
+    pub fn restore_from(&mut self, path: impl AsRef<Path>) -> Result<(), StorageError> {
+        self.conn.restore(DatabaseName::Main, path, None::<fn(Progress)>)?;
+        crate::schema::migrate(&self.conn)
+    }
+
+    /// Highest schema version recorded in the database (0 if none).
+    pub fn schema_version(&self) -> Result<i32, StorageError> {
+        let version = self.conn.query_row(
+            "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(version)
+    }
+
+    /// Run SQLite's `quick_check`. Returns the reported problems (empty if sound).
+    pub fn quick_check(&self) -> Result<Vec<String>, StorageError> {
+        let mut stmt = self.conn.prepare("PRAGMA quick_check")?;
+        let rows = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows.into_iter().filter(|row| row != "ok").collect())
+    }
+}
+
+// ============================================================================
+// Integrity Checks (local-only, not on Storage trait)
+// ============================================================================
+
+/// Materialized tables covered by `materialized_digest`, with their key order.
+const MATERIALIZED_TABLES: &[(&str, &str)] = &[
+    ("entities", "entity_id"),
+    ("fields", "entity_id, field_key"),
+    ("facets", "entity_id, facet_type"),
+    ("edges", "edge_id"),
+    ("edge_properties", "edge_id, property_key"),
+    ("field_mappings", "source_table, target_table, source_field"),
+];
+
+impl SqliteStorage {
+    /// Edges matching `condition` and `filter`. The deleted and type checks
+    /// are added as literal SQL so the planner can use the partial indexes.
+    fn query_edges(
+        &self,
+        condition: &str,
+        mut params: Vec<SqlValue>,
+        filter: &EdgeFilter,
+    ) -> Result<Vec<EdgeRecord>, StorageError> {
+        let mut sql = format!(
+            "SELECT edge_id, edge_type, source_id, target_id, created_at, created_by, (deleted_at IS NOT NULL), created_in_bundle FROM edges WHERE workspace_id = workspace() AND {condition}"
+        );
+        if !filter.include_deleted {
+            sql.push_str(" AND deleted_at IS NULL");
+        }
+        if let Some(edge_type) = &filter.edge_type {
+            sql.push_str(" AND edge_type = ?");
+            params.push(SqlValue::Text(edge_type.clone()));
+        }
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params), extract_edge_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter().map(parse_edge_row).collect()
+    }
+}
+
+/// Child rows whose parent row is gone, as (table, column, parent table,
+/// parent column). `repair_materialized_rows` handles the materialized ones;
+/// the rest are deleted by `delete_orphaned_rows`.
+const ORPHAN_CHECKS: &[(&str, &str, &str, &str)] = &[
+    ("fields", "entity_id", "entities", "entity_id"),
+    ("facets", "entity_id", "entities", "entity_id"),
+    ("edge_properties", "edge_id", "edges", "edge_id"),
+    ("conflict_values", "conflict_id", "conflicts", "conflict_id"),
+    ("overlay_ops", "overlay_id", "overlays", "overlay_id"),
+];
+
+/// Rows of `table` that reference a missing `parent` row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrphanedRows {
+    pub table: &'static str,
+    pub parent: &'static str,
+    pub count: u64,
+}
+
+/// A materialized row that differs from what replaying the oplog produces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DivergentRow {
+    pub table: &'static str,
+    /// Primary key values, blobs in hex, e.g. `"0192…, title"`.
+    pub key: String,
+    pub divergence: Divergence,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Divergence {
+    /// The replay has the row, storage does not.
+    Missing,
+    /// Storage has a row the replay does not.
+    Extra,
+    /// Both have the row with different contents.
+    Changed,
+}
+
+/// Rows of one materialized table by encoded primary key: (key values, row).
+type TableRows = BTreeMap<Vec<u8>, (Vec<SqlValue>, Vec<SqlValue>)>;
+
+/// The materialized tables as a replay leaves them, or why the replay failed.
+type ReplayedRows = Result<Vec<TableRows>, String>;
+
+impl SqliteStorage {
+    /// Hash of every row in the materialized tables, in key order.
+    pub fn materialized_digest(&self) -> Result<[u8; 32], StorageError> {
+        let mut hasher = blake3::Hasher::new();
+        for (table, key) in MATERIALIZED_TABLES {
+            hasher.update(table.as_bytes());
+            let mut stmt =
+                self.conn.prepare(&format!("SELECT * FROM {table} WHERE workspace_id = workspace() ORDER BY {key}"))?;
+            // Leave the workspace name out so copies in different workspaces compare equal
+            let columns: Vec<usize> =
+                (0..stmt.column_count()).filter(|&i| stmt.column_name(i).is_ok_and(|c| c != "workspace_id")).collect();
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                for &i in &columns {
+                    match row.get_ref(i)? {
+                        rusqlite::types::ValueRef::Null => hasher.update(&[0]),
+                        rusqlite::types::ValueRef::Integer(v) => hasher.update(&[1]).update(&v.to_le_bytes()),
+                        rusqlite::types::ValueRef::Real(v) => hasher.update(&[2]).update(&v.to_le_bytes()),
+                        rusqlite::types::ValueRef::Text(v) | rusqlite::types::ValueRef::Blob(v) => hasher
+                            .update(&[3])
+                            .update(&(v.len() as u64).to_le_bytes())
+                            .update(v),
+                    };
+                }
+            }
+        }
+        Ok(*hasher.finalize().as_bytes())
+    }
+
+    /// Replay the oplog and compare the result with the current materialized
+    /// state. A replay that fails (e.g. an op whose bundle is gone) counts as a
+    /// mismatch. The replay is rolled back, so nothing changes either way.
+    pub fn replay_matches(&mut self) -> Result<bool, StorageError> {
+        let before = self.materialized_digest()?;
+        self.conn.execute_batch("SAVEPOINT sp_verify")?;
+        let replayed = self.rebuild_from_oplog().and_then(|_| self.materialized_digest());
+        self.conn.execute_batch("ROLLBACK TO sp_verify; RELEASE sp_verify")?;
+        Ok(replayed.is_ok_and(|after| after == before))
+    }
+
+    /// Broken references between the oplog, bundles and materialized tables.
+    pub fn dangling_references(&self) -> Result<Vec<String>, StorageError> {
+        let mut problems = Vec::new();
+
+        let mut stmt = self.conn.prepare("PRAGMA foreign_key_check")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?, row.get::<_, String>(2)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (table, rowid, parent) in rows {
+            let rowid = rowid.map_or_else(|| "?".to_string(), |r| r.to_string());
+            problems.push(format!("{table} row {rowid}: missing {parent} reference"));
+        }
+
+        let checks = [
+            ("operations without a bundle", "SELECT COUNT(*) FROM oplog WHERE workspace_id = workspace() AND bundle_id NOT IN (SELECT bundle_id FROM bundles)"),
+            ("bundles without operations", "SELECT COUNT(*) FROM bundles WHERE workspace_id = workspace() AND bundle_id NOT IN (SELECT bundle_id FROM oplog)"),
+            ("fields written by unknown operations", "SELECT COUNT(*) FROM fields WHERE workspace_id = workspace() AND source_op NOT IN (SELECT op_id FROM oplog)"),
+            ("edge properties written by unknown operations", "SELECT COUNT(*) FROM edge_properties WHERE workspace_id = workspace() AND source_op NOT IN (SELECT op_id FROM oplog)"),
+        ];
+        for (label, sql) in checks {
+            let count: i64 = self.conn.query_row(sql, [], |row| row.get(0))?;
+            if count > 0 {
+                problems.push(format!("{count} {label}"));
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Counts of rows whose parent row is missing, e.g. fields of an entity
+    /// that is gone or overlay ops of a deleted overlay.
+    pub fn orphaned_rows(&self) -> Result<Vec<OrphanedRows>, StorageError> {
+        let mut orphans = Vec::new();
+        for (table, column, parent, parent_column) in ORPHAN_CHECKS {
+            let count: i64 = self.conn.query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM {table}
+                     WHERE workspace_id = workspace() AND {column} NOT IN (SELECT {parent_column} FROM {parent})"
+                ),
+                [],
+                |row| row.get(0),
+            )?;
+            if count > 0 {
+                orphans.push(OrphanedRows { table, parent, count: count as u64 });
+            }
+        }
+        Ok(orphans)
+    }
+
+    /// Delete the rows reported by `orphaned_rows`. Returns how many went.
+    pub fn delete_orphaned_rows(&mut self) -> Result<u64, StorageError> {
+        let mut deleted = 0;
+        for (table, column, parent, parent_column) in ORPHAN_CHECKS {
+            deleted += self.conn.execute(
+                &format!(
+                    "DELETE FROM {table} WHERE workspace_id = workspace() AND {column} NOT IN (SELECT {parent_column} FROM {parent})"
+                ),
+                [],
+            )? as u64;
+        }
+        Ok(deleted)
+    }
+
+    /// Foreign key violations other than the orphans `orphaned_rows` counts,
+    /// e.g. an entity created in a bundle that is gone.
+    pub fn dangling_foreign_keys(&self) -> Result<Vec<String>, StorageError> {
+        let mut stmt = self.conn.prepare("PRAGMA foreign_key_check")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?, row.get::<_, String>(2)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows
+            .into_iter()
+            .filter(|(table, _, parent)| !ORPHAN_CHECKS.iter().any(|(t, _, p, _)| t == table && p == parent))
+            .map(|(table, rowid, parent)| {
+                let rowid = rowid.map_or_else(|| "?".to_string(), |r| r.to_string());
+                format!("{table} row {rowid}: missing {parent} reference")
+            })
+            .collect())
+    }
+
+    /// Materialized rows that differ from a replay of the oplog, or why the
+    /// replay failed. Both copies of the materialized tables are held in
+    /// memory; the replay is rolled back.
+    pub fn divergent_rows(&mut self) -> Result<Result<Vec<DivergentRow>, String>, StorageError> {
+        let current = self.materialized_rows()?;
+        let replayed = match self.replayed_rows()? {
+            Ok(replayed) => replayed,
+            Err(reason) => return Ok(Err(reason)),
+        };
+        let mut divergent = Vec::new();
+        for (((table, _), current), replayed) in MATERIALIZED_TABLES.iter().zip(&current).zip(&replayed) {
+            for (encoded, (key, row)) in current {
+                let divergence = match replayed.get(encoded) {
+                    None => Divergence::Extra,
+                    Some((_, replayed_row)) if replayed_row != row => Divergence::Changed,
+                    Some(_) => continue,
+                };
+                divergent.push(DivergentRow { table, key: describe_key(key), divergence });
+            }
+            for (encoded, (key, _)) in replayed {
+                if !current.contains_key(encoded) {
+                    divergent.push(DivergentRow { table, key: describe_key(key), divergence: Divergence::Missing });
+                }
+            }
+        }
+        Ok(Ok(divergent))
+    }
+
+    /// Re-materialize the rows `divergent_rows` reports from a replay of the
+    /// oplog, leaving every other row untouched. Returns the number of rows
+    /// written or deleted; fails without changes if the replay fails.
+    pub fn repair_materialized_rows(&mut self) -> Result<u64, StorageError> {
+        let current = self.materialized_rows()?;
+        let replayed = self.replayed_rows()?.map_err(StorageError::Serialization)?;
+
+        self.conn.execute_batch("SAVEPOINT sp_repair; PRAGMA defer_foreign_keys = ON")?;
+        let result = (|| -> Result<u64, StorageError> {
+            let mut repaired = 0;
+            // Deletes children first, inserts parents first
+            for (((table, key_columns), current), replayed) in
+                MATERIALIZED_TABLES.iter().zip(&current).zip(&replayed).rev()
+            {
+                let condition = key_columns.split(", ").map(|c| format!("{c} = ?")).collect::<Vec<_>>().join(" AND ");
+                let sql = format!("DELETE FROM {table} WHERE workspace_id = workspace() AND {condition}");
+                for (encoded, (key, row)) in current {
+                    if replayed.get(encoded).is_none_or(|(_, replayed_row)| replayed_row != row) {
+                        self.conn.execute(&sql, rusqlite::params_from_iter(key))?;
+                        repaired += 1;
+                    }
+                }
+            }
+            for (((table, _), current), replayed) in MATERIALIZED_TABLES.iter().zip(&current).zip(&replayed) {
+                for (encoded, (_, row)) in replayed {
+                    if current.get(encoded).is_none_or(|(_, current_row)| current_row != row) {
+                        let placeholders = vec!["?"; row.len()].join(", ");
+                        self.conn.execute(
+                            &format!("INSERT INTO {table} VALUES ({placeholders})"),
+                            rusqlite::params_from_iter(row),
+                        )?;
+                        // A changed row was counted when it was deleted
+                        if !current.contains_key(encoded) {
+                            repaired += 1;
+                        }
+                    }
+                }
+            }
+            Ok(repaired)
+        })();
+
+        match result {
+            Ok(repaired) => {
+                self.conn.execute_batch("RELEASE sp_repair")?;
+                Ok(repaired)
+            }
+            Err(e) => {
+                let _ = self.conn.execute_batch("ROLLBACK TO sp_repair; RELEASE sp_repair");
+                Err(e)
+            }
+        }
+    }
+
+    /// Every row of each table in `MATERIALIZED_TABLES`, in that order.
+    fn materialized_rows(&self) -> Result<Vec<TableRows>, StorageError> {
+        let mut tables = Vec::new();
+        for (table, key_columns) in MATERIALIZED_TABLES {
+            let mut stmt = self.conn.prepare(&format!("SELECT * FROM {table} WHERE workspace_id = workspace()"))?;
+            let key_indexes = key_columns
+                .split(", ")
+                .map(|c| stmt.column_index(c))
+                .collect::<Result<Vec<_>, _>>()?;
+            let columns = stmt.column_count();
+            let mut rows = TableRows::new();
+            let mut results = stmt.query([])?;
+            while let Some(result) = results.next()? {
+                let row = (0..columns).map(|i| result.get::<_, SqlValue>(i)).collect::<Result<Vec<_>, _>>()?;
+                let key: Vec<SqlValue> = key_indexes.iter().map(|&i| row[i].clone()).collect();
+                rows.insert(encode_key(&key), (key, row));
+            }
+            tables.push(rows);
+        }
+        Ok(tables)
+    }
+
+    /// `materialized_rows` as a full replay of the oplog leaves them. The
+    /// replay is rolled back.
+    fn replayed_rows(&mut self) -> Result<ReplayedRows, StorageError> {
+        self.conn.execute_batch("SAVEPOINT sp_replay")?;
+        let replayed = self.rebuild_from_oplog().and_then(|_| self.materialized_rows());
+        self.conn.execute_batch("ROLLBACK TO sp_replay; RELEASE sp_replay")?;
+        Ok(replayed.map_err(|e| e.to_string()))
+    }
+}
+
+/// Primary key values as one comparable byte string.
+fn encode_key(key: &[SqlValue]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for value in key {
+        match value {
+            SqlValue::Null => encoded.push(0),
+            SqlValue::Integer(v) => {
+                encoded.push(1);
+                encoded.extend_from_slice(&v.to_be_bytes());
+            }
+            SqlValue::Real(v) => {
+                encoded.push(2);
+                encoded.extend_from_slice(&v.to_be_bytes());
+            }
+            SqlValue::Text(v) => {
+                encoded.push(3);
+                encoded.extend_from_slice(&(v.len() as u64).to_be_bytes());
+                encoded.extend_from_slice(v.as_bytes());
+            }
+            SqlValue::Blob(v) => {
+                encoded.push(4);
+                encoded.extend_from_slice(&(v.len() as u64).to_be_bytes());
+                encoded.extend_from_slice(v);
+            }
+        }
+    }
+    encoded
+}
+
+fn describe_key(key: &[SqlValue]) -> String {
+    key.iter()
+        .map(|value| match value {
+            SqlValue::Null => "NULL".to_string(),
+            SqlValue::Integer(v) => v.to_string(),
+            SqlValue::Real(v) => v.to_string(),
+            SqlValue::Text(v) => v.clone(),
+            SqlValue::Blob(v) => v.iter().map(|b| format!("{b:02x}")).collect(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -1,19 +1,67 @@
 use openprod_core::{
+    digest::HlcRange,
     field_value::FieldValue,
     hlc::Hlc,
     ids::*,
-    operations::{Bundle, Operation},
+    operations::{Bundle, BundleMeta, Operation, RawBundle},
     vector_clock::VectorClock,
 };
 
+use serde::{Deserialize, Serialize};
+
 use crate::error::StorageError;
 
+/// Payload types every backend applies to materialized state (by `op_type_name`).
+/// Other types are stored in the oplog but have no materialized effect yet.
+pub const MATERIALIZED_OP_TYPES: &[&str] = &[
+    "CreateEntity",
+    "DeleteEntity",
+    "AttachFacet",
+    "DetachFacet",
+    "RestoreFacet",
+    "SetField",
+    "ClearField",
+    "ResolveConflict",
+    "CreateEdge",
+    "DeleteEdge",
+    "SetEdgeProperty",
+    "ClearEdgeProperty",
+    "RestoreEntity",
+    "RestoreEdge",
+    "ConfirmFieldMapping",
+];
+
+/// Payload types that can touch edges (`OperationPayload::edge_ids`), by
+/// `op_type_name`.
+pub const EDGE_OP_TYPES: &[&str] = &[
+    "CreateEdge",
+    "DeleteEdge",
+    "SetEdgeProperty",
+    "ClearEdgeProperty",
+    "CreateOrderedEdge",
+    "MoveOrderedEdge",
+    "RestoreEdge",
+    "DeleteEntity",
+];
+
 #[derive(Debug, Clone)]
 pub struct EntityRecord {
     pub entity_id: EntityId,
     pub created_at: Hlc,
     pub created_by: ActorId,
     pub deleted: bool,
+    /// Known only from ops on the entity or edges to it, not from its
+    /// creation (e.g. on a peer whose `SyncScope` left it out), so
+    /// `created_at` and `created_by` are those of the first such op.
+    pub placeholder: bool,
+}
+
+/// When and by whom an entity or edge was soft-deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deletion {
+    pub deleted_at: Hlc,
+    pub deleted_by: ActorId,
+    pub deleted_in_bundle: BundleId,
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +73,54 @@ pub struct FacetRecord {
     pub detached: bool,
 }
 
+/// A confirmed field mapping, materialized from `ConfirmFieldMapping` ops.
+/// The latest confirmation wins per (source table, target table, source field).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldMappingRecord {
+    pub source_table: TableId,
+    pub target_table: TableId,
+    pub source_field: String,
+    pub target_field: String,
+    pub confirmed_by: ActorId,
+    pub confirmed_at: Hlc,
+}
+
+/// A bundle's `BundleMeta` as storage indexes it (tags for
+/// `LabelStore::get_bundles_by_tag`, session for `get_bundles_by_session`).
+/// Meta is informational, so a bundle whose meta doesn't decode has none.
+pub fn indexed_meta(bundle: &Bundle) -> BundleMeta {
+    bundle.decode_meta().ok().flatten().unwrap_or_default()
+}
+
+/// Check that every op of a bundle being appended carries the bundle's id
+/// and none repeats. Anything else would store the bundle with fewer ops than
+/// it was signed over, so it could no longer be served whole.
+pub fn check_bundle_ops(bundle: &Bundle, operations: &[Operation]) -> Result<(), StorageError> {
+    let mut seen = std::collections::HashSet::new();
+    for op in operations {
+        if op.bundle_id != bundle.bundle_id {
+            return Err(StorageError::ConstraintViolation(format!("op {} belongs to bundle {}", op.op_id, op.bundle_id)));
+        }
+        if !seen.insert(op.op_id) {
+            return Err(StorageError::ConstraintViolation(format!("op {} repeated in bundle {}", op.op_id, bundle.bundle_id)));
+        }
+    }
+    Ok(())
+}
+
+/// Decode a `DetachFacet { preserve_values: true }` snapshot: the entity's
+/// `(field_key, msgpack value)` pairs at detach time, returned sorted by key.
+pub fn decode_preserved_values(bytes: &[u8]) -> Result<Vec<(String, FieldValue)>, StorageError> {
+    let raw: Vec<(String, Vec<u8>)> =
+        rmp_serde::from_slice(bytes).map_err(|e| StorageError::Serialization(e.to_string()))?;
+    let mut values = raw
+        .into_iter()
+        .map(|(key, value)| Ok((key, FieldValue::from_msgpack(&value).map_err(|e| StorageError::Serialization(e.to_string()))?)))
+        .collect::<Result<Vec<_>, StorageError>>()?;
+    values.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(values)
+}
+
 #[derive(Debug, Clone)]
 pub struct EdgeRecord {
     pub edge_id: EdgeId,
@@ -33,10 +129,171 @@ pub struct EdgeRecord {
     pub target_id: EntityId,
     pub created_at: Hlc,
     pub created_by: ActorId,
+    pub created_in_bundle: BundleId,
     pub deleted: bool,
 }
 
+/// Narrows an edge lookup. The default matches live edges of any type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EdgeFilter {
+    /// Only edges of this type.
+    pub edge_type: Option<String>,
+    /// Include deleted edges (flagged `deleted`) as well as live ones.
+    pub include_deleted: bool,
+}
+
+impl EdgeFilter {
+    /// Live edges of any type.
+    pub fn live() -> Self {
+        Self::default()
+    }
+
+    /// Every edge, deleted ones included.
+    pub fn all() -> Self {
+        Self { edge_type: None, include_deleted: true }
+    }
+
+    /// Only edges of `edge_type`.
+    pub fn of_type(mut self, edge_type: impl Into<String>) -> Self {
+        self.edge_type = Some(edge_type.into());
+        self
+    }
+
+    pub fn matches(&self, edge: &EdgeRecord) -> bool {
+        (self.include_deleted || !edge.deleted) && self.edge_type.as_ref().is_none_or(|t| *t == edge.edge_type)
+    }
+}
+
+/// How `Storage::aggregate_field` combines a field's numeric values.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+/// `aggregate` over the `Integer` and `Float` values in `values`, skipping
+/// any others, with SQLite's typing: a sum of integers is an `Integer`, an
+/// average is always a `Float`, and min/max return the value as stored.
+/// `None` if there are no numbers; a sum past `i64` fails.
+pub fn aggregate_numbers(
+    values: impl IntoIterator<Item = FieldValue>,
+    aggregate: Aggregate,
+) -> Result<Option<FieldValue>, StorageError> {
+    let numbers: Vec<FieldValue> =
+        values.into_iter().filter(|v| matches!(v, FieldValue::Integer(_) | FieldValue::Float(_))).collect();
+    let as_f64 = |value: &FieldValue| match value {
+        FieldValue::Integer(n) => *n as f64,
+        FieldValue::Float(x) => *x,
+        _ => f64::NAN,
+    };
+    if numbers.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(match aggregate {
+        Aggregate::Sum if numbers.iter().all(|v| matches!(v, FieldValue::Integer(_))) => {
+            let mut sum: i64 = 0;
+            for value in &numbers {
+                if let FieldValue::Integer(n) = value {
+                    sum = sum
+                        .checked_add(*n)
+                        .ok_or_else(|| StorageError::ConstraintViolation("integer overflow".into()))?;
+                }
+            }
+            FieldValue::Integer(sum)
+        }
+        Aggregate::Sum => FieldValue::Float(numbers.iter().map(as_f64).sum()),
+        Aggregate::Avg => FieldValue::Float(numbers.iter().map(as_f64).sum::<f64>() / numbers.len() as f64),
+        Aggregate::Min => numbers.into_iter().reduce(|a, b| if as_f64(&b) < as_f64(&a) { b } else { a }).unwrap(),
+        Aggregate::Max => numbers.into_iter().reduce(|a, b| if as_f64(&b) > as_f64(&a) { b } else { a }).unwrap(),
+    }))
+}
+
+/// An entity with some of its field values, as `Storage::get_entities_with_fields` returns them.
+pub type EntityFields = (EntityId, Vec<(String, FieldValue)>);
+
+/// What `Storage::get_entities_page` orders by. Ties, and entities sharing a
+/// field value, fall back to entity id order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityOrder {
+    /// Order by this field's value rather than creation time.
+    pub field_key: Option<String>,
+    pub descending: bool,
+}
+
+impl EntityOrder {
+    /// Oldest entities first.
+    pub fn created_at() -> Self {
+        Self { field_key: None, descending: false }
+    }
+
+    /// Ascending by `field_key`'s value (see `field_sort_key`), entities
+    /// without one first.
+    pub fn field(field_key: impl Into<String>) -> Self {
+        Self { field_key: Some(field_key.into()), descending: false }
+    }
+
+    /// Reverse the order.
+    pub fn descending(mut self) -> Self {
+        self.descending = true;
+        self
+    }
+}
+
+/// The position after the last entity of a page. Only meaningful for the
+/// facet and `EntityOrder` that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityPageCursor {
+    pub sort_key: Vec<u8>,
+    pub entity_id: EntityId,
+}
+
+/// One page of `Storage::get_entities_page`. `next` is `None` on the last page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityPage {
+    pub entities: Vec<EntityId>,
+    pub next: Option<EntityPageCursor>,
+}
+
+impl EntityPage {
+    /// The page from `rows`, already ordered and past the cursor, of which at
+    /// most `limit + 1` were fetched to learn whether another page follows.
+    pub fn from_rows(mut rows: Vec<(Vec<u8>, EntityId)>, limit: usize) -> Self {
+        let more = rows.len() > limit;
+        rows.truncate(limit);
+        let next = if more {
+            rows.last().map(|(sort_key, entity_id)| EntityPageCursor { sort_key: sort_key.clone(), entity_id: *entity_id })
+        } else {
+            None
+        };
+        Self { entities: rows.into_iter().map(|(_, entity_id)| entity_id).collect(), next }
+    }
+}
+
+/// Bytes that sort like field values: missing and `Null` first, then
+/// numbers (integers and floats compared as `f64`), then text by code point,
+/// then anything else by its msgpack encoding. SQLite computes the same key
+/// in SQL as `field_sort_key(value)`.
+pub fn field_sort_key(value: Option<&FieldValue>) -> Vec<u8> {
+    match value {
+        None | Some(FieldValue::Null) => vec![0],
+        Some(FieldValue::Integer(n)) => number_sort_key(*n as f64),
+        Some(FieldValue::Float(x)) => number_sort_key(*x),
+        Some(FieldValue::Text(s)) => [&[2u8][..], s.as_bytes()].concat(),
+        Some(other) => [vec![3u8], other.to_msgpack().unwrap_or_default()].concat(),
+    }
+}
+
+fn number_sort_key(x: f64) -> Vec<u8> {
+    // Flip the sign bit of positives and every bit of negatives so the
+    // big-endian bytes order like the numbers
+    let bits = x.to_bits();
+    let ordered = if bits >> 63 == 1 { !bits } else { bits | 1 << 63 };
+    [&[1u8][..], &ordered.to_be_bytes()].concat()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ConflictStatus {
     Open,
     Resolved,
@@ -59,7 +316,7 @@ impl ConflictStatus {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConflictValue {
     pub value: Option<Vec<u8>>,
     pub actor_id: ActorId,
@@ -67,7 +324,7 @@ pub struct ConflictValue {
     pub op_id: OpId,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConflictRecord {
     pub conflict_id: ConflictId,
     pub entity_id: EntityId,
@@ -84,15 +341,152 @@ pub struct ConflictRecord {
     pub reopened_by_op: Option<OpId>,
 }
 
+/// This replica's triage of a conflict, for an inbox or badge. Local only:
+/// never synced, and cleared when the conflict reopens or gains a branch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictTriage {
+    /// When it was marked seen, in milliseconds since the Unix epoch.
+    pub seen_at: Option<i64>,
+    /// Left out of the unseen count until this local time, in milliseconds
+    /// since the Unix epoch.
+    pub snoozed_until: Option<i64>,
+}
+
+/// Where a script or import overlay came from, and whether it commits itself
+/// when its run succeeds. Local only.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverlayOrigin {
+    /// The script that generated the overlay.
+    pub script_id: Option<String>,
+    /// The run of that script (or import job) that produced it.
+    pub run_id: Option<String>,
+    pub auto_commit: bool,
+}
+
+/// A registered webhook endpoint and how far delivery to it has got.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookRecord {
+    pub webhook_id: WebhookId,
+    pub url: String,
+    /// The engine's event filter, stored as given.
+    pub filter: String,
+    /// Change-stream position delivered up to (or skipped by the filter).
+    pub cursor: u64,
+    /// Failed attempts since the last successful delivery.
+    pub attempts: u32,
+    /// No retry before this local time, in milliseconds since the Unix epoch.
+    pub retry_at: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+/// A known sync peer and what it has acknowledged receiving.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub peer_id: ActorId,
+    pub display_name: Option<String>,
+    /// Everything the peer has acknowledged; empty if it never synced.
+    pub acked_vc: VectorClock,
+    /// Local time of the last acknowledgement, in milliseconds since the Unix epoch.
+    pub last_synced_at: Option<i64>,
+}
+
+/// Progress of a replay, reported by `SqliteStorage::materialize_from`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaterializeProgress {
+    pub replayed: u64,
+    pub total: u64,
+}
+
+/// An actor that has authored ops, with activity stats.
+#[derive(Debug, Clone)]
+pub struct ActorRecord {
+    pub actor_id: ActorId,
+    pub display_name: Option<String>,
+    pub first_seen_at: Hlc,
+    pub last_seen_at: Hlc,
+    pub op_count: u64,
+}
+
+/// Local trust decision about an actor's public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrustState {
+    Trusted,
+    /// Seen but not yet verified out-of-band; its bundles are quarantined.
+    Pending,
+    /// Key revoked; new bundles from it are rejected.
+    Revoked,
+}
+
+impl TrustState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Trusted => "trusted",
+            Self::Pending => "pending",
+            Self::Revoked => "revoked",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, crate::error::StorageError> {
+        match s {
+            "trusted" => Ok(Self::Trusted),
+            "pending" => Ok(Self::Pending),
+            "revoked" => Ok(Self::Revoked),
+            _ => Err(crate::error::StorageError::Serialization(format!("unknown trust state: {s}"))),
+        }
+    }
+}
+
+/// A bundle held back until its causal dependencies have been ingested.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingBundleRecord {
+    pub bundle: Bundle,
+    pub operations: Vec<Operation>,
+    /// Local receive time in milliseconds since the Unix epoch.
+    pub received_at: i64,
+}
+
+/// A bundle that arrived malformed or failed verification, kept as received.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedBundle {
+    pub quarantine_id: i64,
+    /// Set if the bundle header could be decoded.
+    pub bundle_id: Option<BundleId>,
+    pub actor_id: Option<ActorId>,
+    pub raw: RawBundle,
+    /// Why it was quarantined, updated on each failed retry.
+    pub reason: String,
+    /// Local receive time in milliseconds since the Unix epoch.
+    pub received_at: i64,
+}
+
 pub trait Storage {
+    /// Store a bundle and materialize its operations, which must all be its
+    /// own (`check_bundle_ops`). Idempotent: a bundle already stored (resent,
+    /// or relayed along another path) is skipped whole. Returns the ids of
+    /// operations skipped as duplicates.
     fn append_bundle(
         &mut self,
         bundle: &Bundle,
         operations: &[Operation],
-    ) -> Result<(), StorageError>;
+    ) -> Result<Vec<OpId>, StorageError>;
 
     fn get_ops_canonical(&self) -> Result<Vec<Operation>, StorageError>;
 
+    /// Up to `limit` ops in canonical (hlc, op_id) order, starting just after
+    /// `after` (or at the beginning). Pass the last op's `(hlc, op_id)` to get
+    /// the next page; see `OpCursor` for an iterator over the whole oplog.
+    fn get_ops_page(
+        &self,
+        after: Option<(Hlc, OpId)>,
+        limit: usize,
+    ) -> Result<Vec<Operation>, StorageError>;
+
+    /// Up to `limit` ops in the order this replica stored them, after local
+    /// sequence number `after` (0 for the beginning), each with its sequence
+    /// number. Sequence numbers start at 1 and only grow, so a saved one
+    /// resumes the scan after a restart.
+    fn get_ops_after_seq(&self, after: u64, limit: usize) -> Result<Vec<(u64, Operation)>, StorageError>;
+
     fn get_ops_by_bundle(&self, bundle_id: BundleId) -> Result<Vec<Operation>, StorageError>;
 
     fn get_ops_by_actor_after(
@@ -101,8 +495,33 @@ pub trait Storage {
         after: Hlc,
     ) -> Result<Vec<Operation>, StorageError>;
 
+    /// Ops whose primary entity is `entity_id`, in canonical order.
+    fn get_ops_by_entity(&self, entity_id: EntityId) -> Result<Vec<Operation>, StorageError>;
+
+    /// Ops that write `edge_id`'s properties (`OperationPayload::property_edge_id`),
+    /// in canonical order.
+    fn get_edge_property_ops(&self, edge_id: EdgeId) -> Result<Vec<Operation>, StorageError>;
+
+    /// Ops of the given types (`OperationPayload::op_type_name`), in canonical order.
+    fn get_ops_by_type(&self, op_types: &[&str]) -> Result<Vec<Operation>, StorageError>;
+
+    /// Entities that ops stored after sequence number `after` (as in
+    /// `get_ops_after_seq`) created, deleted, restored or wrote fields or
+    /// facets on, in id order. Creating an edge doesn't count as changing its
+    /// endpoints. Served in storage order, so the cost follows the number of
+    /// ops stored since `after`.
+    fn get_entities_changed_since(&self, after: u64) -> Result<Vec<EntityId>, StorageError>;
+
+    /// Edges that ops stored after sequence number `after` created, changed
+    /// or deleted (`OperationPayload::edge_ids`), in id order.
+    fn get_edges_changed_since(&self, after: u64) -> Result<Vec<EdgeId>, StorageError>;
+
     fn op_count(&self) -> Result<u64, StorageError>;
 
+    /// Refresh the query planner's table statistics. Worth running after a
+    /// large import or initial sync; a no-op for backends without a planner.
+    fn analyze(&mut self) -> Result<(), StorageError>;
+
     fn get_entity(&self, entity_id: EntityId) -> Result<Option<EntityRecord>, StorageError>;
 
     fn get_fields(
@@ -120,12 +539,91 @@ pub trait Storage {
 
     fn get_entities_by_facet(&self, facet_type: &str) -> Result<Vec<EntityId>, StorageError>;
 
-    fn get_edges_from(&self, entity_id: EntityId) -> Result<Vec<EdgeRecord>, StorageError>;
+    /// Up to `limit` live entities with `facet_type` attached, in `order`,
+    /// starting just after `after` (or at the beginning). Pass the page's
+    /// `next` back as `after` for the following page; entities created or
+    /// edited meanwhile land wherever their sort key puts them.
+    fn get_entities_page(
+        &self,
+        facet_type: &str,
+        order: &EntityOrder,
+        after: Option<&EntityPageCursor>,
+        limit: usize,
+    ) -> Result<EntityPage, StorageError>;
 
-    fn get_edges_to(&self, entity_id: EntityId) -> Result<Vec<EdgeRecord>, StorageError>;
+    /// Every live entity with `facet_type` attached, in id order, with its
+    /// values for `field_keys` (in that order; keys without a value are left
+    /// out), read in one query rather than a `get_fields` per entity.
+    fn get_entities_with_fields(
+        &self,
+        facet_type: &str,
+        field_keys: &[&str],
+    ) -> Result<Vec<EntityFields>, StorageError>;
+
+    /// Live entities with each facet attached, by facet type. Facets no live
+    /// entity carries are left out.
+    fn count_entities_by_facet(&self) -> Result<Vec<(String, u64)>, StorageError>;
+
+    /// `aggregate` over `field_key` on live entities with `facet_type`
+    /// attached; see `aggregate_numbers` for how values combine.
+    fn aggregate_field(
+        &self,
+        facet_type: &str,
+        field_key: &str,
+        aggregate: Aggregate,
+    ) -> Result<Option<FieldValue>, StorageError>;
+
+    /// Confirmed mappings out of `source_table`, by target table then source field.
+    fn get_field_mappings(&self, source_table: TableId) -> Result<Vec<FieldMappingRecord>, StorageError>;
+
+    /// Field values snapshotted when `facet_type` was detached from
+    /// `entity_id` with `preserve_values`, sorted by key. `None` unless the
+    /// facet is currently detached with a snapshot.
+    fn get_preserved_values(
+        &self,
+        entity_id: EntityId,
+        facet_type: &str,
+    ) -> Result<Option<Vec<(String, FieldValue)>>, StorageError>;
+
+    /// Edges out of `entity_id` that match `filter`.
+    fn get_edges_from(&self, entity_id: EntityId, filter: &EdgeFilter) -> Result<Vec<EdgeRecord>, StorageError>;
+
+    /// Edges into `entity_id` that match `filter`.
+    fn get_edges_to(&self, entity_id: EntityId, filter: &EdgeFilter) -> Result<Vec<EdgeRecord>, StorageError>;
+
+    /// Edges from `source_id` to `target_id` (that direction only) that
+    /// match `filter`.
+    fn get_edges_between(
+        &self,
+        source_id: EntityId,
+        target_id: EntityId,
+        filter: &EdgeFilter,
+    ) -> Result<Vec<EdgeRecord>, StorageError>;
+
+    /// Live edges of `edge_type`, across all entities.
+    fn get_edges_by_type(&self, edge_type: &str) -> Result<Vec<EdgeRecord>, StorageError>;
+
+    /// Soft-deleted entities, most recently deleted first. `facet_type` keeps
+    /// only entities with that facet attached; `since` only deletions after it.
+    fn get_deleted_entities(
+        &self,
+        facet_type: Option<&str>,
+        since: Option<Hlc>,
+    ) -> Result<Vec<(EntityRecord, Deletion)>, StorageError>;
+
+    /// Soft-deleted edges, most recently deleted first.
+    fn get_deleted_edges(&self) -> Result<Vec<(EdgeRecord, Deletion)>, StorageError>;
 
     fn get_vector_clock(&self) -> Result<VectorClock, StorageError>;
 
+    /// The last HLC this replica's clock issued, as saved by `set_last_hlc`
+    /// (None if never). Restored on open so timestamps stay monotonic across
+    /// restarts even if the system clock was set back in between.
+    fn get_last_hlc(&self) -> Result<Option<Hlc>, StorageError>;
+
+    /// Save the clock's last issued HLC. Never moves it backwards.
+    fn set_last_hlc(&mut self, hlc: Hlc) -> Result<(), StorageError>;
+
     fn get_field_metadata(
         &self,
         entity_id: EntityId,
@@ -167,6 +665,9 @@ pub trait Storage {
         entity_id: EntityId,
     ) -> Result<Vec<ConflictRecord>, StorageError>;
 
+    /// Every open conflict in the workspace, oldest first.
+    fn get_open_conflicts(&self) -> Result<Vec<ConflictRecord>, StorageError>;
+
     fn get_conflict(
         &self,
         conflict_id: ConflictId,
@@ -184,6 +685,23 @@ pub trait Storage {
         field_key: &str,
     ) -> Result<Option<ConflictRecord>, StorageError>;
 
+    /// Replace a conflict's triage state. Fails if there is no such conflict.
+    fn set_conflict_triage(
+        &mut self,
+        conflict_id: ConflictId,
+        triage: ConflictTriage,
+    ) -> Result<(), StorageError>;
+
+    /// `None` if there is no such conflict.
+    fn get_conflict_triage(
+        &self,
+        conflict_id: ConflictId,
+    ) -> Result<Option<ConflictTriage>, StorageError>;
+
+    /// Open conflicts neither seen nor snoozed past `now` (milliseconds since
+    /// the Unix epoch).
+    fn count_unseen_conflicts(&self, now: i64) -> Result<u64, StorageError>;
+
     fn reopen_conflict(
         &mut self,
         conflict_id: ConflictId,
@@ -202,4 +720,391 @@ pub trait Storage {
         &self,
         bundle_id: BundleId,
     ) -> Result<Option<VectorClock>, StorageError>;
+
+    fn get_bundle(&self, bundle_id: BundleId) -> Result<Option<Bundle>, StorageError>;
+
+    /// Bundle ids whose HLC falls in `range`, ordered by (hlc, bundle_id).
+    fn get_bundle_ids_in_range(&self, range: &HlcRange) -> Result<Vec<(Hlc, BundleId)>, StorageError>;
+
+    /// Get the source actor, HLC, op_id, and the creator vector clock of the bundle
+    /// that last wrote a particular field. Used for conflict detection.
+    #[allow(clippy::type_complexity)]
+    fn get_field_source_bundle_vc(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<(ActorId, Hlc, OpId, Option<VectorClock>)>, StorageError>;
+
+    /// Get the field value bytes from an oplog operation by op_id.
+    /// Returns Some(bytes) for SetField/ResolveConflict with value, None for ClearField/tombstone.
+    fn get_op_field_value(&self, op_id: OpId) -> Result<Option<Vec<u8>>, StorageError>;
+}
+
+/// Transaction control the engine uses to make multi-step writes atomic.
+pub trait Transactional {
+    /// Start a write transaction.
+    fn begin_transaction(&self) -> Result<(), StorageError>;
+
+    fn commit_transaction(&self) -> Result<(), StorageError>;
+
+    fn rollback_transaction(&self) -> Result<(), StorageError>;
+}
+
+/// Re-deriving materialized state (entities, fields, facets, edges) from the oplog.
+pub trait MaterializationStore {
+    /// Wipe all materialized state and replay the whole oplog.
+    fn rebuild_from_oplog(&mut self) -> Result<u64, StorageError>;
+
+    /// Re-derive materialized state from `from` onwards: roll back every
+    /// materialized change made by ops at or after `from`, then replay those
+    /// ops in canonical order. State before `from` is kept as-is, so this is
+    /// only as correct as that prefix; use `rebuild_from_oplog` when in doubt.
+    /// Conflict records are left untouched. Returns the number of ops replayed.
+    fn materialize_from(
+        &mut self,
+        from: Hlc,
+        progress: &mut dyn FnMut(MaterializeProgress),
+    ) -> Result<u64, StorageError>;
+
+    /// Oplog position (the sequence number of `get_ops_after_seq`) up to which
+    /// materialized state was last re-derived (None if never). Ops stored
+    /// after it may still sort before ops it covers, so replay from the
+    /// earliest HLC among them.
+    fn materialization_watermark(&self) -> Result<Option<u64>, StorageError>;
+
+    /// Record that materialized state reflects the whole oplog, moving the
+    /// watermark to the last op stored. No-op on an empty oplog.
+    fn checkpoint_materialization(&mut self) -> Result<(), StorageError>;
+
+    /// Declare edges of `edge_type` unique per (source, target) pair. From
+    /// then on, materializing a second live edge of the type between the same
+    /// pair keeps the earliest created (by HLC, then edge id) and tombstones
+    /// the rest as of their own creation; duplicates already materialized are
+    /// merged the same way at once. Declarations are never withdrawn, so the
+    /// outcome doesn't depend on when one arrives. No-op if already declared.
+    fn add_unique_edge_type(&mut self, edge_type: &str) -> Result<(), StorageError>;
+
+    /// Edge types declared unique, in name order.
+    fn list_unique_edge_types(&self) -> Result<Vec<String>, StorageError>;
+
+    /// Record the fields `facet_type` owns, replacing any earlier record (an
+    /// empty list removes it). Materialization ignores it: a preserving
+    /// detach snapshots every field, and readers narrow that to the owned ones.
+    fn set_facet_fields(&mut self, facet_type: &str, field_keys: &[&str]) -> Result<(), StorageError>;
+
+    /// Fields declared for `facet_type`, in key order; empty if undeclared.
+    fn get_facet_fields(&self, facet_type: &str) -> Result<Vec<String>, StorageError>;
+}
+
+/// Local overlays and their staged ops.
+pub trait OverlayStore {
+    fn insert_overlay(
+        &mut self,
+        overlay_id: OverlayId,
+        display_name: &str,
+        source: &str,
+        status: &str,
+        created_at: &Hlc,
+    ) -> Result<(), StorageError>;
+
+    fn update_overlay_status(
+        &mut self,
+        overlay_id: OverlayId,
+        status: &str,
+        updated_at: &Hlc,
+    ) -> Result<(), StorageError>;
+
+    fn delete_overlay(&mut self, overlay_id: OverlayId) -> Result<(), StorageError>;
+
+    #[allow(clippy::type_complexity)]
+    fn get_overlay(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<Option<(OverlayId, String, String, String, Hlc, Hlc)>, StorageError>;
+
+    fn list_overlays_by_status(
+        &self,
+        status: &str,
+    ) -> Result<Vec<(OverlayId, String, String, Hlc)>, StorageError>;
+
+    /// Set how drift on an overlay is handled when it is flagged. Overlays
+    /// start out as `block`.
+    fn set_overlay_drift_policy(&mut self, overlay_id: OverlayId, policy: &str) -> Result<(), StorageError>;
+
+    /// `None` if there is no such overlay.
+    fn get_overlay_drift_policy(&self, overlay_id: OverlayId) -> Result<Option<String>, StorageError>;
+
+    /// Replace an overlay's origin. Fails if there is no such overlay.
+    fn set_overlay_origin(&mut self, overlay_id: OverlayId, origin: &OverlayOrigin) -> Result<(), StorageError>;
+
+    /// `None` if there is no such overlay.
+    fn get_overlay_origin(&self, overlay_id: OverlayId) -> Result<Option<OverlayOrigin>, StorageError>;
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert_overlay_op(
+        &mut self,
+        overlay_id: OverlayId,
+        op_id: OpId,
+        hlc: &Hlc,
+        payload_bytes: &[u8],
+        entity_id: Option<EntityId>,
+        field_key: Option<&str>,
+        op_type: &str,
+        canonical_value_at_creation: Option<&[u8]>,
+    ) -> Result<i64, StorageError>;
+
+    fn delete_overlay_op(&mut self, rowid: i64) -> Result<(), StorageError>;
+
+    #[allow(clippy::type_complexity)]
+    fn get_overlay_ops(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<Vec<(i64, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, String, Option<Vec<u8>>, bool, Option<String>)>, StorageError>;
+
+    /// Get the latest overlay op for a specific field on a specific entity.
+    /// Returns (rowid, payload_bytes) or None.
+    fn get_latest_overlay_field_op(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<Option<(i64, Vec<u8>)>, StorageError>;
+
+    /// Count overlay ops for an overlay.
+    fn count_overlay_ops(&self, overlay_id: OverlayId) -> Result<u64, StorageError>;
+
+    /// Mark SetField/ClearField overlay ops for an entity+field as drifted (across all overlays).
+    /// Returns the number of rows updated.
+    fn mark_overlay_ops_drifted(
+        &self,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<u64, StorageError>;
+
+    /// Clear the canonical_drifted flag for overlay ops matching a specific field
+    /// in a specific overlay+entity.
+    fn clear_drift_flag(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<(), StorageError>;
+
+    /// Update canonical_value_at_creation for overlay ops matching a specific field
+    /// in a specific overlay+entity.
+    fn update_canonical_value_at_creation(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+        new_value: Option<&[u8]>,
+    ) -> Result<(), StorageError>;
+
+    /// Get overlay ops where canonical_drifted = 1 for a specific overlay.
+    /// Returns the same tuple type as `get_overlay_ops`.
+    #[allow(clippy::type_complexity)]
+    fn get_drifted_overlay_ops(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<Vec<(i64, Vec<u8>, Vec<u8>, Vec<u8>, Option<Vec<u8>>, String, Option<Vec<u8>>, bool, Option<String>)>, StorageError>;
+
+    /// Count overlay ops with canonical_drifted = 1 for a specific overlay.
+    fn count_unresolved_drift(
+        &self,
+        overlay_id: OverlayId,
+    ) -> Result<u64, StorageError>;
+
+    /// Delete overlay ops for a specific field (used for knockout).
+    /// Returns the number of rows deleted.
+    fn delete_overlay_ops_for_field(
+        &self,
+        overlay_id: OverlayId,
+        entity_id: EntityId,
+        field_key: &str,
+    ) -> Result<u64, StorageError>;
+}
+
+/// Local bundle relabels.
+pub trait LabelStore {
+    /// Set (or replace) the local label for a bundle. Never synced.
+    fn set_bundle_label(
+        &mut self,
+        bundle_id: BundleId,
+        label: &str,
+    ) -> Result<(), StorageError>;
+
+    /// Get the local label for a bundle, if one was set.
+    fn get_bundle_label(&self, bundle_id: BundleId) -> Result<Option<String>, StorageError>;
+
+    /// All bundles in HLC order, each with its local label (if any).
+    fn list_bundles_with_labels(&self) -> Result<Vec<(Bundle, Option<String>)>, StorageError>;
+
+    /// Bundles whose meta carries `tag`, in HLC order.
+    fn get_bundles_by_tag(&self, tag: &str) -> Result<Vec<Bundle>, StorageError>;
+
+    /// Bundles whose meta names `session_id`, in HLC order.
+    fn get_bundles_by_session(&self, session_id: SessionId) -> Result<Vec<Bundle>, StorageError>;
+
+    /// The first bundle `actor_id` wrote whose meta carries `key`.
+    fn get_bundle_by_idempotency_key(&self, actor_id: ActorId, key: &str) -> Result<Option<Bundle>, StorageError>;
+}
+
+/// Bundles buffered until their causal dependencies arrive.
+pub trait PendingStore {
+    /// Buffer a bundle and its operations. Re-buffering the same bundle is a no-op.
+    fn insert_pending_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<(), StorageError>;
+
+    /// Remove a bundle from the holding area (after ingest or when discarded).
+    /// Returns true if it was pending.
+    fn delete_pending_bundle(&mut self, bundle_id: BundleId) -> Result<bool, StorageError>;
+
+    /// All pending bundles in HLC order.
+    fn list_pending_bundles(&self) -> Result<Vec<PendingBundleRecord>, StorageError>;
+
+    /// Number of bundles waiting in the holding area.
+    fn count_pending_bundles(&self) -> Result<u64, StorageError>;
+}
+
+/// Known actors and their cached display names.
+pub trait ActorStore {
+    /// Set the cached display name of a known actor. Unknown actors are ignored.
+    fn set_actor_display_name(&mut self, actor_id: ActorId, display_name: Option<&str>) -> Result<(), StorageError>;
+
+    /// Every actor that has authored ops, in first-seen order.
+    fn list_actors(&self) -> Result<Vec<ActorRecord>, StorageError>;
+}
+
+/// Local trust decisions about actor keys, and bundles quarantined pending approval.
+pub trait TrustStore {
+    fn set_actor_trust(&mut self, actor_id: ActorId, state: TrustState) -> Result<(), StorageError>;
+
+    fn get_actor_trust(&self, actor_id: ActorId) -> Result<Option<TrustState>, StorageError>;
+
+    /// Every actor with a trust decision, in actor order.
+    fn list_actor_trust(&self) -> Result<Vec<(ActorId, TrustState)>, StorageError>;
+
+    /// Quarantine a bundle from an actor that isn't trusted yet. Re-inserting is a no-op.
+    fn insert_untrusted_bundle(
+        &mut self,
+        bundle: &Bundle,
+        operations: &[Operation],
+    ) -> Result<(), StorageError>;
+
+    /// Quarantined bundles from `actor_id` in HLC order.
+    fn list_untrusted_bundles(&self, actor_id: ActorId) -> Result<Vec<PendingBundleRecord>, StorageError>;
+
+    /// Drop every quarantined bundle from `actor_id`. Returns how many were removed.
+    fn delete_untrusted_bundles(&mut self, actor_id: ActorId) -> Result<usize, StorageError>;
+
+    fn count_untrusted_bundles(&self) -> Result<u64, StorageError>;
+}
+
+/// Bundles that couldn't be decoded or verified on ingest, kept with the
+/// reason so they can be inspected, retried (e.g. after an upgrade) or dropped.
+pub trait QuarantineStore {
+    /// Quarantine a raw bundle. Quarantining the same bytes again only updates
+    /// the reason. Returns the quarantine id.
+    fn insert_quarantined_bundle(
+        &mut self,
+        raw: &RawBundle,
+        bundle_id: Option<BundleId>,
+        actor_id: Option<ActorId>,
+        reason: &str,
+    ) -> Result<i64, StorageError>;
+
+    fn get_quarantined_bundle(&self, quarantine_id: i64) -> Result<Option<QuarantinedBundle>, StorageError>;
+
+    /// Every quarantined bundle, oldest first.
+    fn list_quarantined_bundles(&self) -> Result<Vec<QuarantinedBundle>, StorageError>;
+
+    fn update_quarantine_reason(&mut self, quarantine_id: i64, reason: &str) -> Result<(), StorageError>;
+
+    /// Returns true if the bundle was quarantined.
+    fn delete_quarantined_bundle(&mut self, quarantine_id: i64) -> Result<bool, StorageError>;
+}
+
+/// Known sync peers and their acknowledged vector clocks.
+pub trait PeerStore {
+    /// Register a peer, or update its display name if already known.
+    fn upsert_peer(
+        &mut self,
+        peer_id: ActorId,
+        display_name: Option<&str>,
+    ) -> Result<(), StorageError>;
+
+    /// Forget a peer and its sync state. Returns true if it was known.
+    fn delete_peer(&mut self, peer_id: ActorId) -> Result<bool, StorageError>;
+
+    /// Record the vector clock a peer has acknowledged (replaces the previous one).
+    fn set_peer_acked_vc(
+        &mut self,
+        peer_id: ActorId,
+        acked_vc: &VectorClock,
+    ) -> Result<(), StorageError>;
+
+    fn get_peer(&self, peer_id: ActorId) -> Result<Option<PeerRecord>, StorageError>;
+
+    /// All known peers, in the order they were added.
+    fn list_peers(&self) -> Result<Vec<PeerRecord>, StorageError>;
+}
+
+/// Outbound webhook endpoints and their delivery state.
+pub trait WebhookStore {
+    /// Register an endpoint. Fails if `webhook_id` is already registered.
+    fn insert_webhook(&mut self, webhook: &WebhookRecord) -> Result<(), StorageError>;
+
+    /// Returns true if the endpoint was registered.
+    fn delete_webhook(&mut self, webhook_id: WebhookId) -> Result<bool, StorageError>;
+
+    fn get_webhook(&self, webhook_id: WebhookId) -> Result<Option<WebhookRecord>, StorageError>;
+
+    /// All endpoints, in the order they were registered.
+    fn list_webhooks(&self) -> Result<Vec<WebhookRecord>, StorageError>;
+
+    /// Record a delivery attempt: the new cursor, and the retry state
+    /// (`attempts` 0 and no `retry_at` after a success).
+    fn update_webhook_delivery(
+        &mut self,
+        webhook_id: WebhookId,
+        cursor: u64,
+        attempts: u32,
+        retry_at: Option<i64>,
+        last_error: Option<&str>,
+    ) -> Result<(), StorageError>;
+}
+
+/// Everything `Engine` needs from a backend: the replicated data model plus
+/// the local-only companion stores. Implemented for any type providing them all.
+pub trait EngineStorage:
+    Storage
+    + Transactional
+    + MaterializationStore
+    + OverlayStore
+    + LabelStore
+    + PendingStore
+    + ActorStore
+    + TrustStore
+    + QuarantineStore
+    + PeerStore
+    + WebhookStore
+{
+}
+
+impl<T> EngineStorage for T where
+    T: Storage
+        + Transactional
+        + MaterializationStore
+        + OverlayStore
+        + LabelStore
+        + PendingStore
+        + ActorStore
+        + TrustStore
+        + QuarantineStore
+        + PeerStore
+        + WebhookStore
+{
 }
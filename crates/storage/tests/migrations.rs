@@ -0,0 +1,294 @@
+#![cfg(feature = "sqlite")]
+
+use openprod_storage::schema::{MIGRATIONS, SCHEMA_VERSION};
+use openprod_core::{
+    field_value::FieldValue,
+    hlc::Hlc,
+    identity::ActorIdentity,
+    ids::{BundleId, EdgeId, EntityId, OpId, OverlayId, SessionId, TableId},
+    operations::{Bundle, BundleMeta, BundleType, Operation, OperationPayload},
+};
+use openprod_storage::{
+    LabelStore, MaterializationStore, OverlayOrigin, OverlayStore, QuarantineStore, SqliteStorage, Storage, StorageError,
+    TrustStore,
+};
+
+/// Undo the columns later migrations add to existing tables, which can't be
+/// re-added over themselves when a test replays those migrations.
+const DROP_ADDED_COLUMNS: &str = "
+    ALTER TABLE conflicts DROP COLUMN seen_at;
+    ALTER TABLE conflicts DROP COLUMN snoozed_until;
+    ALTER TABLE overlays DROP COLUMN drift_policy;
+    ALTER TABLE overlays DROP COLUMN auto_commit;
+    ALTER TABLE entities DROP COLUMN placeholder;";
+
+/// Undo the workspace columns (and the indexes over them) migration 23 adds
+/// to existing tables, the materialization_state shape of migration 24 and
+/// the oplog edge column of 25. The other tables 23 rebuilds copy either shape.
+const DROP_WORKSPACE_COLUMNS: &str = "
+    DROP INDEX idx_oplog_edge;
+    ALTER TABLE oplog DROP COLUMN edge_id;
+    DROP INDEX idx_oplog_canonical_order;
+    DROP INDEX idx_oplog_actor_hlc;
+    DROP INDEX idx_bundles_hlc;
+    DROP INDEX idx_bundles_actor;
+    DROP INDEX idx_bundles_type;
+    DROP INDEX idx_entities_active;
+    DROP INDEX idx_entities_deleted;
+    DROP INDEX idx_fields_key_value;
+    DROP INDEX idx_facets_type;
+    DROP INDEX idx_facets_type_detached;
+    DROP INDEX idx_edges_type;
+    DROP INDEX idx_conflicts_status;
+    DROP INDEX idx_overlays_status;
+    DROP INDEX idx_pending_bundles_hlc;
+    DROP INDEX idx_untrusted_bundles_actor;
+    DROP INDEX idx_bundle_tags_workspace;
+    ALTER TABLE oplog DROP COLUMN workspace_id;
+    ALTER TABLE bundles DROP COLUMN workspace_id;
+    ALTER TABLE entities DROP COLUMN workspace_id;
+    ALTER TABLE fields DROP COLUMN workspace_id;
+    ALTER TABLE facets DROP COLUMN workspace_id;
+    ALTER TABLE edges DROP COLUMN workspace_id;
+    ALTER TABLE edge_properties DROP COLUMN workspace_id;
+    ALTER TABLE conflicts DROP COLUMN workspace_id;
+    ALTER TABLE conflict_values DROP COLUMN workspace_id;
+    ALTER TABLE overlays DROP COLUMN workspace_id;
+    ALTER TABLE overlay_ops DROP COLUMN workspace_id;
+    ALTER TABLE bundle_labels DROP COLUMN workspace_id;
+    ALTER TABLE pending_bundles DROP COLUMN workspace_id;
+    ALTER TABLE untrusted_bundles DROP COLUMN workspace_id;
+    ALTER TABLE bundle_tags DROP COLUMN workspace_id;
+    ALTER TABLE bundle_sessions DROP COLUMN workspace_id;
+    ALTER TABLE field_mappings DROP COLUMN workspace_id;
+    ALTER TABLE webhooks DROP COLUMN workspace_id;
+    DROP TABLE materialization_state;
+    CREATE TABLE materialization_state (id INTEGER PRIMARY KEY, watermark BLOB NOT NULL);";
+
+// ============================================================================
+// Schema Migrations
+// ============================================================================
+
+#[test]
+fn migrations_are_ordered_and_end_at_schema_version() {
+    assert!(MIGRATIONS.windows(2).all(|w| w[0].version + 1 == w[1].version));
+    assert_eq!(MIGRATIONS.last().map(|m| m.version), Some(SCHEMA_VERSION));
+}
+
+#[test]
+fn baseline_database_upgrades_in_place() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.db");
+    let path_str = path.to_str().unwrap();
+    drop(SqliteStorage::open(path_str)?);
+
+    // Roll the file back to what a baseline build would have left behind
+    let conn = rusqlite::Connection::open(&path)?;
+    conn.execute_batch(&format!(
+        "{DROP_WORKSPACE_COLUMNS}
+         DROP TABLE bundle_labels;
+         DROP TABLE pending_bundles;
+         DROP TABLE peer_sync_state;
+         DROP TABLE peers;
+         DROP TABLE actor_trust;
+         DROP TABLE untrusted_bundles;
+         DROP TABLE materialization_state;
+         DROP TABLE quarantined_bundles;
+         DROP TABLE unique_edge_types;
+         DROP TABLE facet_fields;
+         DROP TABLE bundle_tags;
+         DROP TABLE bundle_sessions;
+         DROP TABLE field_mappings;
+         DROP TABLE webhooks;
+         DROP TABLE bundle_idempotency_keys;
+         DROP TABLE clock_state;
+         {DROP_ADDED_COLUMNS}
+         DROP INDEX idx_edges_source_all;
+         DROP INDEX idx_edges_target_all;
+         DELETE FROM schema_version WHERE version > 2;"
+    ))?;
+    drop(conn);
+
+    let storage = SqliteStorage::open(path_str)?;
+    assert_eq!(storage.schema_version()?, SCHEMA_VERSION);
+    assert_eq!(storage.count_untrusted_bundles()?, 0);
+    assert!(storage.list_quarantined_bundles()?.is_empty());
+    assert_eq!(storage.materialization_watermark()?, None);
+    assert!(storage.list_unique_edge_types()?.is_empty());
+    assert!(storage.get_facet_fields("Task")?.is_empty());
+    assert!(storage.get_bundles_by_session(SessionId::new())?.is_empty());
+    assert!(storage.get_field_mappings(TableId::new())?.is_empty());
+
+    let conn = rusqlite::Connection::open(&path)?;
+    let recorded: i64 = conn.query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))?;
+    assert_eq!(recorded as usize, 1 + MIGRATIONS.len());
+    Ok(())
+}
+
+#[test]
+fn bundle_tags_are_backfilled_on_upgrade() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.db");
+    let path_str = path.to_str().unwrap();
+    let identity = ActorIdentity::generate();
+    let (bundle_id, hlc) = (BundleId::new(), Hlc::new(1_000, 0));
+    let payload = OperationPayload::CreateEntity { entity_id: EntityId::new(), initial_table: None };
+    let ops = vec![Operation::new_signed(&identity, hlc, bundle_id, Default::default(), payload)?];
+    let mut bundle = Bundle::new_signed(bundle_id, &identity, hlc, BundleType::Import, &ops, None)?;
+    bundle.meta = Some(BundleMeta::with_label("Import").tag("import").to_msgpack()?);
+    let mut storage = SqliteStorage::open(path_str)?;
+    storage.append_bundle(&bundle, &ops)?;
+    drop(storage);
+
+    // As a build from before the tag index left it
+    let conn = rusqlite::Connection::open(&path)?;
+    conn.execute_batch(&format!(
+        "{DROP_WORKSPACE_COLUMNS} DROP TABLE bundle_tags; {DROP_ADDED_COLUMNS} DELETE FROM schema_version WHERE version >= 13;"
+    ))?;
+    drop(conn);
+
+    let storage = SqliteStorage::open(path_str)?;
+    let tagged: Vec<BundleId> = storage.get_bundles_by_tag("import")?.iter().map(|b| b.bundle_id).collect();
+    assert_eq!(tagged, vec![bundle_id]);
+    Ok(())
+}
+
+#[test]
+fn field_mappings_are_backfilled_on_upgrade() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.db");
+    let path_str = path.to_str().unwrap();
+    let identity = ActorIdentity::generate();
+    let (bundle_id, hlc, source) = (BundleId::new(), Hlc::new(1_000, 0), TableId::new());
+    let payload = OperationPayload::ConfirmFieldMapping {
+        source_table: source,
+        target_table: TableId::new(),
+        source_field: "E-mail".into(),
+        target_field: "email".into(),
+    };
+    let ops = vec![Operation::new_signed(&identity, hlc, bundle_id, Default::default(), payload)?];
+    let bundle = Bundle::new_signed(bundle_id, &identity, hlc, BundleType::Import, &ops, None)?;
+    let mut storage = SqliteStorage::open(path_str)?;
+    storage.append_bundle(&bundle, &ops)?;
+    drop(storage);
+
+    // As a build from before mappings were materialized left it
+    let conn = rusqlite::Connection::open(&path)?;
+    conn.execute_batch(&format!(
+        "{DROP_WORKSPACE_COLUMNS} DROP TABLE field_mappings; {DROP_ADDED_COLUMNS} DELETE FROM schema_version WHERE version >= 15;"
+    ))?;
+    drop(conn);
+
+    let storage = SqliteStorage::open(path_str)?;
+    let mappings = storage.get_field_mappings(source)?;
+    assert_eq!(mappings.len(), 1);
+    assert_eq!((mappings[0].target_field.as_str(), mappings[0].confirmed_at), ("email", hlc));
+    Ok(())
+}
+
+#[test]
+fn edge_property_ops_are_backfilled_on_upgrade() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.db");
+    let path_str = path.to_str().unwrap();
+    let identity = ActorIdentity::generate();
+    let (bundle_id, hlc, edge_id) = (BundleId::new(), Hlc::new(1_000, 0), EdgeId::new());
+    let payloads = [
+        OperationPayload::CreateEntity { entity_id: EntityId::new(), initial_table: None },
+        OperationPayload::SetEdgeProperty { edge_id, property_key: "weight".into(), value: FieldValue::Integer(1) },
+    ];
+    let ops = payloads
+        .into_iter()
+        .map(|payload| Operation::new_signed(&identity, hlc, bundle_id, Default::default(), payload))
+        .collect::<Result<Vec<_>, _>>()?;
+    let bundle = Bundle::new_signed(bundle_id, &identity, hlc, BundleType::UserEdit, &ops, None)?;
+    let mut storage = SqliteStorage::open(path_str)?;
+    storage.append_bundle(&bundle, &ops)?;
+    drop(storage);
+
+    // As a build from before the edge index left it
+    let conn = rusqlite::Connection::open(&path)?;
+    conn.execute_batch("DROP INDEX idx_oplog_edge; ALTER TABLE oplog DROP COLUMN edge_id; DELETE FROM schema_version WHERE version >= 25;")?;
+    drop(conn);
+
+    let storage = SqliteStorage::open(path_str)?;
+    let found: Vec<OpId> = storage.get_edge_property_ops(edge_id)?.iter().map(|op| op.op_id).collect();
+    assert_eq!(found, vec![ops[1].op_id]);
+    Ok(())
+}
+
+#[test]
+fn overlays_keep_their_ops_when_the_table_is_rebuilt() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.db");
+    let path_str = path.to_str().unwrap();
+    let (overlay_id, hlc) = (OverlayId::new(), Hlc::new(1_000, 0));
+    let mut storage = SqliteStorage::open(path_str)?;
+    storage.insert_overlay(overlay_id, "run", "script", "stashed", &hlc)?;
+    storage.insert_overlay_op(overlay_id, OpId::new(), &hlc, &[0x90], Some(EntityId::new()), Some("title"), "SetField", None)?;
+    drop(storage);
+
+    // As a build from before import overlays left it
+    let conn = rusqlite::Connection::open(&path)?;
+    conn.execute_batch(&format!(
+        "{DROP_WORKSPACE_COLUMNS} ALTER TABLE overlays DROP COLUMN auto_commit; ALTER TABLE entities DROP COLUMN placeholder;
+         DELETE FROM schema_version WHERE version >= 19;"
+    ))?;
+    drop(conn);
+
+    let mut storage = SqliteStorage::open(path_str)?;
+    assert_eq!(storage.count_overlay_ops(overlay_id)?, 1);
+    assert_eq!(storage.get_overlay_origin(overlay_id)?, Some(OverlayOrigin::default()));
+    storage.insert_overlay(OverlayId::new(), "csv", "import", "active", &hlc)?;
+    Ok(())
+}
+
+#[test]
+fn newer_database_is_refused() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.db");
+    let path_str = path.to_str().unwrap();
+    drop(SqliteStorage::open(path_str)?);
+
+    let conn = rusqlite::Connection::open(&path)?;
+    conn.execute(
+        "INSERT INTO schema_version (version, applied_at) VALUES (?1, unixepoch())",
+        [SCHEMA_VERSION + 1],
+    )?;
+    drop(conn);
+
+    match SqliteStorage::open(path_str) {
+        Err(StorageError::SchemaTooNew { found, supported }) => {
+            assert_eq!((found, supported), (SCHEMA_VERSION + 1, SCHEMA_VERSION));
+        }
+        other => panic!("expected SchemaTooNew, got {:?}", other.err()),
+    }
+    Ok(())
+}
+
+#[test]
+fn hot_lookups_use_indexes() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("store.db");
+    let mut storage = SqliteStorage::open(path.to_str().unwrap())?;
+    Storage::analyze(&mut storage)?;
+    drop(storage);
+
+    let conn = rusqlite::Connection::open(&path)?;
+    for query in [
+        "SELECT edge_id FROM edges WHERE source_id = x'00'",
+        "SELECT edge_id FROM edges WHERE target_id = x'00'",
+        "SELECT entity_id FROM facets WHERE workspace_id = 'default' AND facet_type = 'Cue' AND detached_at IS NOT NULL",
+        "SELECT field_key FROM fields WHERE entity_id = x'00'",
+        "SELECT op_id FROM oplog WHERE bundle_id = x'00'",
+        "SELECT op_id FROM oplog WHERE workspace_id = 'default' AND edge_id = x'00' ORDER BY hlc, op_id",
+        "SELECT op_id FROM oplog WHERE workspace_id = 'default' AND actor_id = x'00' AND hlc > x'00' ORDER BY hlc",
+        "SELECT op_id FROM oplog WHERE workspace_id = 'default' ORDER BY hlc, op_id",
+        "SELECT rowid FROM overlay_ops WHERE overlay_id = x'00' AND entity_id = x'00' AND field_key = 'label'",
+    ] {
+        let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {query}"))?;
+        let plan: Vec<String> = stmt.query_map([], |row| row.get(3))?.collect::<Result<_, _>>()?;
+        assert!(plan.iter().all(|step| !step.starts_with("SCAN")), "{query}: {plan:?}");
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+//! Minimal promise-based access to IndexedDB.
+//!
+//! Layout: object store `bundles` holds one bundle wire envelope per key,
+//! keyed by append position (0, 1, 2, ...), so `getAll` returns them in the
+//! order they must be replayed. Object store `local` holds the actor's secret
+//! key under `identity` and `MemoryStorage::local_state` under `state`.
+
+use js_sys::{Array, Function, Promise, Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue, closure::Closure};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbFactory, IdbRequest, IdbTransaction, IdbTransactionMode};
+
+const DB_VERSION: u32 = 1;
+pub const BUNDLES: &str = "bundles";
+pub const LOCAL: &str = "local";
+pub const IDENTITY_KEY: &str = "identity";
+pub const STATE_KEY: &str = "state";
+
+/// Open (or create) the database `name`. Works in windows and workers alike.
+pub async fn open(name: &str) -> Result<IdbDatabase, JsValue> {
+    let factory: IdbFactory = Reflect::get(&js_sys::global(), &"indexedDB".into())?.dyn_into()?;
+    let request = factory.open_with_u32(name, DB_VERSION)?;
+    let upgrade_request = request.clone();
+    let on_upgrade = Closure::once_into_js(move || {
+        if let Ok(db) = upgrade_request.result().and_then(|db| db.dyn_into::<IdbDatabase>()) {
+            for store in [BUNDLES, LOCAL] {
+                if !db.object_store_names().contains(store) {
+                    let _ = db.create_object_store(store);
+                }
+            }
+        }
+    });
+    request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+    let db = completed(&request).await?;
+    request.set_onupgradeneeded(None);
+    db.dyn_into()
+}
+
+/// Resolve with the request's result once it succeeds.
+pub async fn completed(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let ok_request = request.clone();
+        let err_request = request.clone();
+        request.set_onsuccess(Some(
+            Closure::once_into_js(move || {
+                let _ = resolve.call1(&JsValue::NULL, &ok_request.result().unwrap_or(JsValue::UNDEFINED));
+            })
+            .unchecked_ref(),
+        ));
+        request.set_onerror(Some(
+            Closure::once_into_js(move || {
+                let error = err_request.error().ok().flatten().map(JsValue::from).unwrap_or(JsValue::UNDEFINED);
+                let _ = reject.call1(&JsValue::NULL, &error);
+            })
+            .unchecked_ref(),
+        ));
+    });
+    JsFuture::from(promise).await
+}
+
+/// Resolve once every write in `transaction` has been committed.
+pub async fn committed(transaction: &IdbTransaction) -> Result<(), JsValue> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let err_transaction = transaction.clone();
+        transaction.set_oncomplete(Some(
+            Closure::once_into_js(move || {
+                let _ = resolve.call0(&JsValue::NULL);
+            })
+            .unchecked_ref(),
+        ));
+        let on_error = Closure::once_into_js(move || {
+            let error = err_transaction.error().map(JsValue::from).unwrap_or(JsValue::UNDEFINED);
+            let _ = reject.call1(&JsValue::NULL, &error);
+        });
+        transaction.set_onerror(Some(on_error.unchecked_ref()));
+        transaction.set_onabort(Some(on_error.unchecked_ref()));
+    });
+    JsFuture::from(promise).await.map(|_| ())
+}
+
+/// Every stored bundle envelope, in append order.
+pub async fn load_bundles(db: &IdbDatabase) -> Result<Vec<Vec<u8>>, JsValue> {
+    let store = db.transaction_with_str(BUNDLES)?.object_store(BUNDLES)?;
+    let values: Array = completed(&store.get_all()?).await?.dyn_into()?;
+    Ok(values.iter().map(|value| Uint8Array::new(&value).to_vec()).collect())
+}
+
+/// A value from the `local` store, if present.
+pub async fn load_local(db: &IdbDatabase, key: &str) -> Result<Option<Vec<u8>>, JsValue> {
+    let store = db.transaction_with_str(LOCAL)?.object_store(LOCAL)?;
+    let value = completed(&store.get(&key.into())?).await?;
+    Ok((!value.is_undefined()).then(|| Uint8Array::new(&value).to_vec()))
+}
+
+/// Write bundle envelopes at positions `first..` and the given `local`
+/// entries in one transaction.
+pub async fn save(
+    db: &IdbDatabase,
+    first: usize,
+    bundles: &[Vec<u8>],
+    local: &[(&str, &[u8])],
+) -> Result<(), JsValue> {
+    let stores = Array::of2(&BUNDLES.into(), &LOCAL.into());
+    let transaction = db.transaction_with_str_sequence_and_mode(&stores, IdbTransactionMode::Readwrite)?;
+    let bundle_store = transaction.object_store(BUNDLES)?;
+    for (i, envelope) in bundles.iter().enumerate() {
+        bundle_store.put_with_key(&Uint8Array::from(envelope.as_slice()), &((first + i) as f64).into())?;
+    }
+    let local_store = transaction.object_store(LOCAL)?;
+    for (key, bytes) in local {
+        local_store.put_with_key(&Uint8Array::from(*bytes), &(*key).into())?;
+    }
+    committed(&transaction).await
+}
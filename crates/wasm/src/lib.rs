@@ -0,0 +1,229 @@
+//! Browser bindings: an `Engine` over `MemoryStorage`, persisted to IndexedDB.
+//!
+//! The oplog is the source of truth, so `save` only appends the bundles
+//! written since the last save, plus a snapshot of local-only state
+//! (conflicts, overlays, labels, ...). `open` replays both.
+
+mod idb;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use js_sys::{Array, Promise, Uint8Array};
+use openprod_core::{
+    field_value::FieldValue,
+    identity::ActorIdentity,
+    ids::{EdgeId, EntityId},
+    vector_clock::VectorClock,
+    wire::{Compression, decode_bundle_wire, encode_bundle_wire},
+};
+use openprod_engine::Engine;
+use openprod_storage::{MemoryStorage, Storage};
+use uuid::Uuid;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+use web_sys::IdbDatabase;
+
+#[wasm_bindgen]
+pub struct WasmEngine {
+    engine: Engine<MemoryStorage>,
+    db: Option<IdbDatabase>,
+    /// Bundles already written to IndexedDB (a `MemoryStorage::bundles_from` cursor).
+    saved: Rc<Cell<usize>>,
+}
+
+#[wasm_bindgen]
+impl WasmEngine {
+    /// Open the IndexedDB database `name`, replaying whatever was saved in
+    /// it. A fresh database gets a newly generated identity.
+    pub async fn open(name: String) -> Result<WasmEngine, JsError> {
+        let db = idb::open(&name).await.map_err(js_error)?;
+        let identity = match idb::load_local(&db, idb::IDENTITY_KEY).await.map_err(js_error)? {
+            Some(bytes) => {
+                let secret: [u8; 32] =
+                    bytes.try_into().map_err(|_| JsError::new("stored identity is not a 32-byte key"))?;
+                ActorIdentity::from_secret_bytes(&secret)
+            }
+            None => {
+                let identity = ActorIdentity::generate();
+                idb::save(&db, 0, &[], &[(idb::IDENTITY_KEY, &identity.secret_bytes())])
+                    .await
+                    .map_err(js_error)?;
+                identity
+            }
+        };
+        let bundles = idb::load_bundles(&db)
+            .await
+            .map_err(js_error)?
+            .iter()
+            .map(|envelope| decode_bundle_wire(envelope))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(js_error)?;
+        let saved = bundles.len();
+        let state = idb::load_local(&db, idb::STATE_KEY).await.map_err(js_error)?;
+        let storage = MemoryStorage::restore(bundles, state.as_deref()).map_err(js_error)?;
+        Ok(WasmEngine {
+            engine: Engine::new(identity, storage).map_err(js_error)?,
+            db: Some(db),
+            saved: Rc::new(Cell::new(saved)),
+        })
+    }
+
+    /// An engine with no persistence; `save` is a no-op.
+    #[wasm_bindgen(js_name = inMemory)]
+    pub fn in_memory() -> Result<WasmEngine, JsError> {
+        Ok(WasmEngine {
+            engine: Engine::new(ActorIdentity::generate(), MemoryStorage::new()).map_err(js_error)?,
+            db: None,
+            saved: Rc::new(Cell::new(0)),
+        })
+    }
+
+    /// This replica's actor id, hex-encoded.
+    #[wasm_bindgen(js_name = actorId)]
+    pub fn actor_id(&self) -> String {
+        self.engine.actor_id().as_bytes().iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// Create an entity with facet `facet_type`; returns its id.
+    #[wasm_bindgen(js_name = createEntity)]
+    pub fn create_entity(&mut self, facet_type: &str) -> Result<String, JsError> {
+        let (entity_id, _) = self.engine.create_entity(Some(facet_type)).map_err(js_error)?;
+        Ok(entity_id.to_string())
+    }
+
+    #[wasm_bindgen(js_name = deleteEntity)]
+    pub fn delete_entity(&mut self, entity_id: &str) -> Result<(), JsError> {
+        self.engine.delete_entity(parse_entity(entity_id)?).map_err(js_error)?;
+        Ok(())
+    }
+
+    /// Set a field; `value` is a serialized `FieldValue` (e.g. `{ Text: "Q1" }`).
+    #[wasm_bindgen(js_name = setField)]
+    pub fn set_field(&mut self, entity_id: &str, field_key: &str, value: JsValue) -> Result<(), JsError> {
+        let value: FieldValue = serde_wasm_bindgen::from_value(value).map_err(js_error)?;
+        self.engine.set_field(parse_entity(entity_id)?, field_key, value).map_err(js_error)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = clearField)]
+    pub fn clear_field(&mut self, entity_id: &str, field_key: &str) -> Result<(), JsError> {
+        self.engine.clear_field(parse_entity(entity_id)?, field_key).map_err(js_error)?;
+        Ok(())
+    }
+
+    /// The field's current `FieldValue`, or `undefined` if unset.
+    #[wasm_bindgen(js_name = getField)]
+    pub fn get_field(&self, entity_id: &str, field_key: &str) -> Result<JsValue, JsError> {
+        match self.engine.get_field(parse_entity(entity_id)?, field_key).map_err(js_error)? {
+            Some(value) => serde_wasm_bindgen::to_value(&value).map_err(js_error),
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
+
+    /// All fields of the entity as `[key, FieldValue]` pairs.
+    #[wasm_bindgen(js_name = getFields)]
+    pub fn get_fields(&self, entity_id: &str) -> Result<JsValue, JsError> {
+        let fields = self.engine.get_fields(parse_entity(entity_id)?).map_err(js_error)?;
+        serde_wasm_bindgen::to_value(&fields).map_err(js_error)
+    }
+
+    #[wasm_bindgen(js_name = entitiesWithFacet)]
+    pub fn entities_with_facet(&self, facet_type: &str) -> Result<Vec<String>, JsError> {
+        let entities = self.engine.get_entities_by_facet(facet_type).map_err(js_error)?;
+        Ok(entities.iter().map(ToString::to_string).collect())
+    }
+
+    /// Create an edge; returns its id.
+    #[wasm_bindgen(js_name = createEdge)]
+    pub fn create_edge(&mut self, edge_type: &str, source_id: &str, target_id: &str) -> Result<String, JsError> {
+        let (edge_id, _) = self
+            .engine
+            .create_edge(edge_type, parse_entity(source_id)?, parse_entity(target_id)?)
+            .map_err(js_error)?;
+        Ok(edge_id.to_string())
+    }
+
+    #[wasm_bindgen(js_name = deleteEdge)]
+    pub fn delete_edge(&mut self, edge_id: &str) -> Result<(), JsError> {
+        self.engine.delete_edge(EdgeId::from_uuid(parse_uuid(edge_id)?)).map_err(js_error)?;
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> Result<(), JsError> {
+        self.engine.undo().map_err(js_error)?;
+        Ok(())
+    }
+
+    pub fn redo(&mut self) -> Result<(), JsError> {
+        self.engine.redo().map_err(js_error)?;
+        Ok(())
+    }
+
+    /// The vector clock, msgpack-encoded, to send to a peer.
+    #[wasm_bindgen(js_name = vectorClock)]
+    pub fn vector_clock(&self) -> Result<Vec<u8>, JsError> {
+        self.engine.get_vector_clock().map_err(js_error)?.to_msgpack().map_err(js_error)
+    }
+
+    /// Wire envelopes for every bundle a peer with vector clock `vc` (from
+    /// `vectorClock`) has not seen, in HLC order.
+    #[wasm_bindgen(js_name = bundlesMissingFrom)]
+    pub fn bundles_missing_from(&self, vc: &[u8]) -> Result<Array, JsError> {
+        let vc = VectorClock::from_msgpack(vc).map_err(js_error)?;
+        let envelopes = Array::new();
+        for bundle_id in self.engine.bundles_missing_from(&vc).map_err(js_error)? {
+            let bundle = self.engine.storage().get_bundle(bundle_id).map_err(js_error)?;
+            let bundle = bundle.ok_or_else(|| JsError::new(&format!("bundle {bundle_id} vanished")))?;
+            let operations = self.engine.get_ops_by_bundle(bundle_id).map_err(js_error)?;
+            let envelope = encode_bundle_wire(&bundle, &operations, Compression::Zstd).map_err(js_error)?;
+            envelopes.push(&Uint8Array::from(envelope.as_slice()));
+        }
+        Ok(envelopes)
+    }
+
+    /// Ingest wire envelopes from a peer; returns the number of conflicts raised.
+    pub fn ingest(&mut self, envelopes: Array) -> Result<usize, JsError> {
+        let batch = envelopes
+            .iter()
+            .map(|envelope| decode_bundle_wire(&Uint8Array::new(&envelope).to_vec()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(js_error)?;
+        Ok(self.engine.ingest_bundles(&batch).map_err(js_error)?.conflicts.len())
+    }
+
+    /// Persist bundles written since the last save and the current local
+    /// state. Resolves once IndexedDB has committed them.
+    pub fn save(&self) -> Result<Promise, JsError> {
+        let Some(db) = self.db.clone() else {
+            return Ok(Promise::resolve(&JsValue::UNDEFINED));
+        };
+        let storage = self.engine.storage();
+        let first = self.saved.get();
+        let envelopes = storage
+            .bundles_from(first)
+            .iter()
+            .map(|(bundle, operations)| encode_bundle_wire(bundle, operations, Compression::None))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(js_error)?;
+        let state = storage.local_state().map_err(js_error)?;
+        let saved = Rc::clone(&self.saved);
+        Ok(future_to_promise(async move {
+            idb::save(&db, first, &envelopes, &[(idb::STATE_KEY, &state)]).await?;
+            saved.set(first + envelopes.len());
+            Ok(JsValue::UNDEFINED)
+        }))
+    }
+}
+
+fn js_error(error: impl std::fmt::Debug) -> JsError {
+    JsError::new(&format!("{error:?}"))
+}
+
+fn parse_uuid(id: &str) -> Result<Uuid, JsError> {
+    Uuid::parse_str(id).map_err(|e| JsError::new(&format!("invalid id {id:?}: {e}")))
+}
+
+fn parse_entity(id: &str) -> Result<EntityId, JsError> {
+    Ok(EntityId::from_uuid(parse_uuid(id)?))
+}